@@ -0,0 +1,67 @@
+use std::env;
+/**
+ * Build script for ControlNet Image Generator
+ *
+ * Records the git commit, the build date and the enabled features in
+ * environment variables, so `urasoe --version` can tell exactly which
+ * build is running.
+ */
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn main() {
+    println!("cargo:rustc-env=URASOE_GIT_HASH={}", git_hash());
+    println!("cargo:rustc-env=URASOE_BUILD_DATE={}", build_date());
+    println!("cargo:rustc-env=URASOE_FEATURES={}", features());
+}
+
+/// Short hash of the checked out commit, "unknown" outside a git checkout
+fn git_hash() -> String {
+    Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|hash| hash.trim().to_string())
+        .filter(|hash| !hash.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Build date as YYYY-MM-DD in UTC, honouring SOURCE_DATE_EPOCH for reproducible builds
+fn build_date() -> String {
+    let seconds = env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.parse::<i64>().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_secs() as i64)
+                .unwrap_or_default()
+        });
+    // Civil date from days since the epoch, after Howard Hinnant's algorithm
+    let days = seconds.div_euclid(86_400) + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Enabled cargo features, comma separated, "none" when built without any
+fn features() -> String {
+    let mut features: Vec<String> = env::vars()
+        .filter_map(|(key, _)| key.strip_prefix("CARGO_FEATURE_").map(|name| name.to_lowercase().replace('_', "-")))
+        .filter(|name| name != "default")
+        .collect();
+    features.sort();
+    if features.is_empty() {
+        "none".to_string()
+    } else {
+        features.join(",")
+    }
+}
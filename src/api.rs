@@ -1,5 +1,6 @@
 use anyhow::{Context, Result};
-use colored::*;
+use base64::{Engine, prelude::BASE64_STANDARD};
+use tracing::{debug, error, info, warn};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
@@ -10,24 +11,201 @@ use std::time::Duration;
  * This module handles all communication with the Stable Diffusion API,
  * including image generation with ControlNet and model management.
  */
+use std::io::Write;
 use std::path::Path;
+use std::sync::Arc;
+use tempfile::{NamedTempFile, TempPath};
 
-// We'll use direct serde_json parsing instead of api_types structs for now
+use crate::api_types::{GenerationRequest, ProgressResponse};
 use crate::config::Config;
-use crate::image::image_to_base64;
+use crate::digest::RequestDigest;
+use crate::fixtures::Fixtures;
+use crate::http::{self, HttpStack, Middleware, RequestIdentity};
+use crate::i18n::{Msg, tr, tr_args};
+use crate::image::{ImageProcessor, image_to_base64};
+use crate::list_cache::ListCache;
+#[cfg(feature = "scripting")]
+use crate::plugins::Plugins;
+use crate::prompt_source::{self, PromptContext};
+use crate::server_files::ServerFilesConfig;
+use crate::style::*;
 
 /// Response from the Stable Diffusion API after image generation
 ///
-/// Contains the generated images as base64 strings, along with
-/// optional parameters and information about the generation process.
+/// Contains the generated images, along with optional parameters and
+/// information about the generation process.
 #[derive(Serialize, Deserialize, Debug)]
 pub struct StableDiffusionResponse {
-    /// Array of base64-encoded generated images
-    pub images: Vec<String>,
+    /// Generated images, base64 encoded in the JSON of the API
+    pub images: Vec<GeneratedImage>,
     /// Optional parameters used for generation
     pub parameters: Option<serde_json::Value>,
     /// Optional information about the generation process
     pub info: Option<String>,
+    /// Hashes of the request that generated the images, not part of the API response
+    #[serde(skip)]
+    pub digest: Option<RequestDigest>,
+}
+
+impl StableDiffusionResponse {
+    /// Seed of the first generated image, as reported in the generation information
+    pub fn seed(&self) -> Option<i64> {
+        let info: serde_json::Value = serde_json::from_str(self.info.as_deref()?).ok()?;
+        info["seed"].as_i64()
+    }
+
+    /// Seed of each generated image, as reported in the generation information
+    ///
+    /// Empty unless the server reported a seed for every image.
+    pub fn seeds(&self) -> Vec<i64> {
+        let Some(info) = self.info_value() else {
+            return Vec::new();
+        };
+        let seeds: Vec<i64> = info["all_seeds"]
+            .as_array()
+            .map(|seeds| seeds.iter().filter_map(|seed| seed.as_i64()).collect())
+            .unwrap_or_default();
+        if seeds.len() == self.images.len() { seeds } else { Vec::new() }
+    }
+
+    /// Add the images of another response for the same input, e.g. of a re-roll
+    ///
+    /// The seeds and infotexts of both are kept in the generation information,
+    /// so that every image keeps those that reproduce it. When either response
+    /// lacks them for some of its images, they are left out altogether.
+    pub fn append(&mut self, other: StableDiffusionResponse) {
+        let (own_seeds, other_seeds) = (self.seeds(), other.seeds());
+        let (own_texts, other_texts) = (self.infotexts(), other.infotexts());
+        let keep_seeds = own_seeds.len() == self.images.len() && other_seeds.len() == other.images.len();
+        let keep_texts = own_texts.len() == self.images.len() && other_texts.len() == other.images.len();
+
+        let mut info = self.info_value().unwrap_or_else(|| json!({}));
+        if let Some(info) = info.as_object_mut() {
+            info.remove("all_seeds");
+            info.remove("infotexts");
+            if keep_seeds {
+                info.insert("all_seeds".to_string(), json!([own_seeds, other_seeds].concat()));
+            }
+            if keep_texts {
+                info.insert("infotexts".to_string(), json!([own_texts, other_texts].concat()));
+            }
+        }
+        self.info = Some(info.to_string());
+        self.images.extend(other.images);
+    }
+
+    /// Generation information as JSON
+    fn info_value(&self) -> Option<serde_json::Value> {
+        serde_json::from_str(self.info.as_deref()?).ok()
+    }
+
+    /// Sampler that generated the images, as reported in the generation information
+    ///
+    /// Older servers report the scheduler appended to the sampler name, as it was sent.
+    pub fn sampler_name(&self) -> Option<String> {
+        let info: serde_json::Value = serde_json::from_str(self.info.as_deref()?).ok()?;
+        info["sampler_name"].as_str().map(str::to_string)
+    }
+
+    /// Number of images of the response that decode as base64
+    pub fn decodable_images(&self) -> usize {
+        self.images.iter().filter(|image| image.is_decodable()).count()
+    }
+
+    /// Generation parameters of each image in the infotext format of the Web UI, as reported by the server
+    pub fn infotexts(&self) -> Vec<String> {
+        let Some(info) = self.info_value() else {
+            return Vec::new();
+        };
+        info["infotexts"]
+            .as_array()
+            .map(|texts| texts.iter().filter_map(|text| text.as_str().map(str::to_string)).collect())
+            .unwrap_or_default()
+    }
+}
+
+/// Generated image of a response, decoded and written to a temporary file as soon as it is parsed
+///
+/// A batch of large images is never held in memory at once, neither as
+/// base64 text nor decoded. The file is removed when the last clone is dropped.
+#[derive(Clone, Debug)]
+pub struct GeneratedImage(Spooled);
+
+#[derive(Clone, Debug)]
+enum Spooled {
+    File(Arc<TempPath>),
+    /// The server sent text that is not base64, kept to be reported when the image is saved
+    Undecodable(base64::DecodeError),
+}
+
+impl GeneratedImage {
+    /// Decode a base64 image as sent by the API
+    ///
+    /// Text that does not decode is kept as an undecodable image rather than
+    /// failing, so the other images of a batch can still be saved.
+    pub fn from_base64(text: &str) -> Result<Self> {
+        match BASE64_STANDARD.decode(text) {
+            Ok(bytes) => Self::from_bytes(&bytes),
+            Err(e) => Ok(Self(Spooled::Undecodable(e))),
+        }
+    }
+
+    /// Image of the given encoded bytes
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let mut file = NamedTempFile::new().context("Failed to create a file for a received image")?;
+        file.write_all(bytes).context("Failed to write a received image")?;
+        Ok(Self(Spooled::File(Arc::new(file.into_temp_path()))))
+    }
+
+    /// Whether the server sent valid base64 for the image
+    pub fn is_decodable(&self) -> bool {
+        matches!(self.0, Spooled::File(_))
+    }
+
+    /// Encoded bytes of the image, as the server generated them
+    pub fn bytes(&self) -> Result<Vec<u8>> {
+        match &self.0 {
+            Spooled::File(path) => std::fs::read(path.as_ref() as &Path).context("Failed to read a received image"),
+            Spooled::Undecodable(e) => Err(e.clone()).context("Failed to decode base64 image"),
+        }
+    }
+}
+
+impl Serialize for GeneratedImage {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let bytes = self.bytes().map_err(serde::ser::Error::custom)?;
+        serializer.serialize_str(&BASE64_STANDARD.encode(bytes))
+    }
+}
+
+impl<'de> Deserialize<'de> for GeneratedImage {
+    /// Each image of the array is written out as it is parsed, before the next one is read
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        Self::from_base64(&text).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Body of a txt2img response, which carries an error message instead of images when generation failed
+#[derive(Deserialize)]
+struct Txt2ImgBody {
+    images: Option<Vec<GeneratedImage>>,
+    parameters: Option<serde_json::Value>,
+    info: Option<String>,
+    error: Option<serde_json::Value>,
+}
+
+/// Add settings to the override_settings of a txt2img payload, replacing those of the same name
+fn merge_override_settings(payload: &mut serde_json::Value, settings: &serde_json::Map<String, serde_json::Value>) {
+    if settings.is_empty() {
+        return;
+    }
+    if !payload["override_settings"].is_object() {
+        payload["override_settings"] = json!({});
+    }
+    if let Some(overrides) = payload["override_settings"].as_object_mut() {
+        overrides.extend(settings.clone());
+    }
 }
 
 /// Client for interacting with Stable Diffusion API
@@ -35,10 +213,15 @@ pub struct StableDiffusionResponse {
 /// Handles communication with the Automatic1111 Stable Diffusion Web UI API,
 /// including model loading and image generation with ControlNet.
 pub struct StableDiffusionClient {
-    /// HTTP client for making API requests
-    client: Client,
+    /// HTTP client and middleware for making API requests
+    http: HttpStack,
     /// Base URL for the Stable Diffusion API
     api_url: String,
+    /// Recorded responses to save or play back, if any
+    fixtures: Option<Arc<Fixtures>>,
+    /// Plugins changing the requests, if any
+    #[cfg(feature = "scripting")]
+    plugins: Option<Arc<Plugins>>,
 }
 
 impl StableDiffusionClient {
@@ -51,8 +234,11 @@ impl StableDiffusionClient {
     /// A new StableDiffusionClient instance
     pub fn new(api_url: &str) -> Self {
         Self {
-            client: Client::new(),
+            http: HttpStack::default(),
             api_url: api_url.to_string(),
+            fixtures: None,
+            #[cfg(feature = "scripting")]
+            plugins: None,
         }
     }
 
@@ -65,17 +251,70 @@ impl StableDiffusionClient {
     /// # Returns
     /// A new StableDiffusionClient instance with the specified timeout
     pub fn with_timeout(api_url: &str, timeout_ms: u64) -> Self {
-        let client = reqwest::Client::builder()
+        let client = http::default_client_builder()
             .timeout(Duration::from_millis(timeout_ms))
             .build()
             .unwrap_or_else(|_| Client::new());
         
         Self {
-            client,
+            http: HttpStack::new(client),
             api_url: api_url.to_string(),
+            fixtures: None,
+            #[cfg(feature = "scripting")]
+            plugins: None,
         }
     }
 
+    /// Record API responses to, or replay them from, the given fixtures
+    ///
+    /// While replaying, no requests reach the API at all.
+    pub fn with_fixtures(mut self, fixtures: Option<Arc<Fixtures>>) -> Self {
+        self.fixtures = fixtures;
+        self
+    }
+
+    /// Let the payload plugins change every generation request before it is sent
+    #[cfg(feature = "scripting")]
+    pub fn with_plugins(mut self, plugins: Arc<Plugins>) -> Self {
+        self.plugins = Some(plugins);
+        self
+    }
+
+    /// Send requests with the given HTTP client, e.g. one with proxies or default headers
+    ///
+    /// Replaces the client made by the constructor, including its timeout,
+    /// and keeps the middleware added so far.
+    pub fn with_http_client(mut self, client: Client) -> Self {
+        self.http = self.http.with_client(client);
+        self
+    }
+
+    /// Pass every request through the given middleware, inside those added before
+    pub fn with_middleware(mut self, middleware: Arc<dyn Middleware>) -> Self {
+        self.http = self.http.with(middleware);
+        self
+    }
+
+    /// Send the given user agent and run id with every request
+    pub fn with_identity(self, identity: RequestIdentity) -> Self {
+        self.with_middleware(Arc::new(identity))
+    }
+
+    /// HTTP client the requests are built with
+    fn client(&self) -> &Client {
+        self.http.client()
+    }
+
+    /// Send a request through the middleware
+    async fn send(&self, request: reqwest::RequestBuilder) -> Result<reqwest::Response> {
+        self.http.send(request).await
+    }
+
+    /// Whether responses are played back instead of requested from the API
+    fn is_replay(&self) -> bool {
+        self.fixtures.as_ref().is_some_and(|fixtures| fixtures.is_replay())
+    }
+
     /// Load a specific Stable Diffusion model checkpoint
     ///
     /// Sends a request to the API to load a specific model checkpoint for image generation.
@@ -88,17 +327,18 @@ impl StableDiffusionClient {
     /// # Returns
     /// * `Result<()>` - Ok if successful, Error if the request fails
     pub async fn load_model(&self, model_name: &str) -> Result<()> {
-        println!("{} {}", "Loading model:".blue(), model_name);
+        info!("{}", tr_args(Msg::LoadingModel, &[&model_name]).blue());
+        if self.is_replay() {
+            return Ok(());
+        }
 
         let url = format!("{}options", self.api_url);
 
+        let request = self.client().post(&url).json(&json!({
+            "sd_model_checkpoint": model_name
+        }));
         let response = self
-            .client
-            .post(&url)
-            .json(&json!({
-                "sd_model_checkpoint": model_name
-            }))
-            .send()
+            .send(request)
             .await
             .context("Failed to send request to load model")?;
 
@@ -110,12 +350,50 @@ impl StableDiffusionClient {
 
         Ok(())
     }
+
+    /// Unload the current checkpoint from GPU memory
+    pub async fn unload_checkpoint(&self) -> Result<()> {
+        self.post_action("sdapi/v1/unload-checkpoint").await
+    }
+
+    /// Load the current checkpoint back into GPU memory
+    pub async fn reload_checkpoint(&self) -> Result<()> {
+        self.post_action("sdapi/v1/reload-checkpoint").await
+    }
+
+    /// Interrupt the generation the server is currently running
+    pub async fn interrupt(&self) -> Result<()> {
+        self.post_action("sdapi/v1/interrupt").await
+    }
+
+    /// Send a POST request without a body to an action endpoint
+    async fn post_action(&self, endpoint: &str) -> Result<()> {
+        if self.is_replay() {
+            return Ok(());
+        }
+        let url = format!("{}{}", self.api_url, endpoint);
+        debug!("POST {}", url);
+
+        let response = self
+            .send(self.client().post(&url))
+            .await
+            .context(format!("Failed to send request to {}", endpoint))?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let text = response.text().await.unwrap_or_default();
+            return Err(anyhow::anyhow!("{} failed: {} {}", endpoint, status, text));
+        }
+
+        Ok(())
+    }
     
     /// Generate images using ControlNet with the specified input image
     ///
     /// Sends a request to the API to generate images using ControlNet with the provided
     /// input image and configuration settings. The input image is used as a reference
-    /// for the ControlNet model to guide the image generation.
+    /// for the ControlNet model to guide the image generation. Payload plugins given
+    /// with `with_plugins` may change the request before it is sent.
     ///
     /// # Arguments
     /// * `image_path` - Path to the input image file
@@ -129,85 +407,249 @@ impl StableDiffusionClient {
         image_path: &Path,
         config: &Config,
     ) -> Result<Option<StableDiffusionResponse>> {
-        let image_base64 = image_to_base64(image_path)?;
+        let prompt = prompt_source::resolve(
+            config,
+            PromptContext {
+                image_path,
+                client: self,
+            },
+        )
+        .await?;
+        let mut request = GenerationRequest {
+            prompt: prompt.prompt,
+            negative_prompt: prompt.negative_prompt,
+            ..GenerationRequest::from(config)
+        };
+        // Units without pixel perfect preprocess at the resolution of the control image
+        let units = config.active_controlnet_units();
+        if units.iter().any(|unit| !unit.pixel_perfect && unit.processor_res.is_none()) {
+            let control_size = ImageProcessor::control_image_size(image_path, config)?;
+            for (sent, unit) in request.controlnet_units.iter_mut().zip(&units) {
+                if !unit.pixel_perfect {
+                    sent.processor_res = unit.processor_res_for(control_size, config.max_processor_res);
+                }
+            }
+        }
+        request.controlnet_units.extend(config.style_reference.unit_for(image_path)?);
+        let mut payload = request.to_payload(&ImageProcessor::control_image_base64(image_path, config)?);
+        config.server_files.apply_to_payload(&mut payload);
+        merge_override_settings(&mut payload, &config.override_settings);
+        #[cfg(feature = "scripting")]
+        if let Some(plugins) = &self.plugins {
+            payload = plugins.mutate_payload(image_path, payload)?;
+        }
+        let backend_settings = config.backend_settings(&self.api_url);
+        let mut response = self.send_txt2img(image_path, &request, &payload, &backend_settings).await?;
+        if config.server_files.enabled
+            && let Some(result) = &mut response
+        {
+            self.fetch_server_files(result, &config.server_files).await?;
+        }
+        Ok(response)
+    }
+
+    /// Generate images for an input image with a request built without a `Config`
+    ///
+    /// # Arguments
+    /// * `image_path` - Path to the input image file, the control image of units without their own
+    /// * `request` - Prompt, size, sampler and ControlNet units of the generation
+    ///
+    /// # Returns
+    /// * `Result<Option<StableDiffusionResponse>>` - The API response containing generated images if successful,
+    ///   or an Error if the request failed
+    pub async fn generate(
+        &self,
+        image_path: &Path,
+        request: &GenerationRequest,
+    ) -> Result<Option<StableDiffusionResponse>> {
+        let payload = request.to_payload(&image_to_base64(image_path)?);
+        self.send_txt2img(image_path, request, &payload, &serde_json::Map::new()).await
+    }
 
-        let url = format!("{}sdapi/v1/txt2img", self.api_url);
+    /// Describe an input image with the interrogator of the server
+    ///
+    /// Replayed runs do not reach the API and get an empty caption.
+    ///
+    /// # Arguments
+    /// * `image_path` - Path to the image to describe
+    /// * `model` - Interrogator to use, e.g. "clip" or "deepdanbooru"
+    ///
+    /// # Returns
+    /// * `Result<String>` - Caption of the image
+    pub async fn interrogate(&self, image_path: &Path, model: &str) -> Result<String> {
+        if self.is_replay() {
+            return Ok(String::new());
+        }
+        let url = format!("{}sdapi/v1/interrogate", self.api_url);
+        let payload = json!({ "image": image_to_base64(image_path)?, "model": model });
+        debug!("POST {} (model {})", url, model);
+        let response = self
+            .send(self.client().post(&url).json(&payload))
+            .await
+            .context("Failed to interrogate image")?;
 
-        // Use the new configuration options for ControlNet
-        let controlnet_unit = json!({
-            "input_image": image_base64,
-            "module": config.controlnet_module,
-            "model": format!("control_{}_sd15", config.model),
-            "weight": config.controlnet_weight,
-            "guidance_start": 0.0,
-            "guidance_end": 1.0,
-            "processor_res": 512,
-            "threshold_a": 64,
-            "threshold_b": 64,
-            "control_mode": 0,
-            "resize_mode": 1, // Scale to fit
-            "pixel_perfect": true,
-            "enabled": true
-        });
+        if !response.status().is_success() {
+            let status = response.status();
+            let text = response.text().await.unwrap_or_default();
+            return Err(anyhow::anyhow!("API error: {} - {}", status, text));
+        }
 
-        // Use sampler_name and scheduler configuration options
-        let sampler_name = if config.scheduler.is_empty() {
-            config.sampler_name.clone()
-        } else {
-            format!("{} {}", config.sampler_name, config.scheduler)
-        };
+        let body = response.json::<serde_json::Value>().await?;
+        body["caption"]
+            .as_str()
+            .map(|caption| caption.trim().to_string())
+            .context("Interrogation response has no caption")
+    }
 
+    /// Upscale an image with an upscaler of the extras tab of the server
+    ///
+    /// # Arguments
+    /// * `image_path` - Path to the image to upscale
+    /// * `upscaler` - Upscaler to use, e.g. "R-ESRGAN 4x+"
+    /// * `scale` - Factor the width and height are multiplied by
+    ///
+    /// # Returns
+    /// * `Result<Vec<u8>>` - The upscaled image, PNG encoded
+    pub async fn upscale(&self, image_path: &Path, upscaler: &str, scale: f32) -> Result<Vec<u8>> {
+        let url = format!("{}sdapi/v1/extra-single-image", self.api_url);
         let payload = json!({
-            "prompt": config.prompt,
-            "negative_prompt": config.negative_prompt,
-            "batch_size": config.batch_size,
-            "steps": config.steps,
-            "width": config.width,
-            "height": config.height,
-            "cfg_scale": config.cfg,
-            "sampler_name": sampler_name,
-            "override_settings": {
-                "sd_model_checkpoint": config.checkpoint_model,
-            },
-            "alwayson_scripts": {
-                "controlnet": {
-                    "args": [controlnet_unit]
-                }
-            }
+            "image": image_to_base64(image_path)?,
+            "resize_mode": 0,
+            "upscaling_resize": scale,
+            "upscaler_1": upscaler,
         });
-
+        debug!("POST {} ({} x{})", url, upscaler, scale);
         let response = self
-            .client
-            .post(&url)
-            .json(&payload)
-            .send()
+            .send(self.client().post(&url).json(&payload))
             .await
-            .context("API request failed")?;
+            .context("Failed to upscale image")?;
 
         if !response.status().is_success() {
             let status = response.status();
-            println!("{} {}", "API responded with status:".red(), status);
-            
-            // Try to get error details for better handling
-            let error_text = response.text().await.unwrap_or_default();
-            return Err(anyhow::anyhow!("API error: {} - {}", status, error_text));
+            let text = response.text().await.unwrap_or_default();
+            return Err(anyhow::anyhow!("API error: {} - {}", status, text));
         }
 
-        // Parse the response
-        let response_text = response.text().await.context("Failed to get response text")?;
-        
-        // Check if the response contains error information in JSON
-        if let Ok(error_json) = serde_json::from_str::<serde_json::Value>(&response_text) {
-            if let Some(error) = error_json.get("error").and_then(|e| e.as_str()) {
-                return Err(anyhow::anyhow!("API returned error: {}", error));
+        let body = response.json::<serde_json::Value>().await?;
+        let image = body["image"].as_str().context("Upscale response has no image")?;
+        BASE64_STANDARD.decode(image).context("Failed to decode upscaled image")
+    }
+
+    /// Download a file saved on the server from its file endpoint
+    ///
+    /// # Arguments
+    /// * `path` - Path of the file on the server, as reported by the server
+    ///
+    /// # Returns
+    /// * `Result<Vec<u8>>` - Contents of the file
+    pub async fn download_file(&self, path: &str) -> Result<Vec<u8>> {
+        let url = format!("{}file={}", self.api_url, path);
+        debug!("GET {}", url);
+        let response = self
+            .send(self.client().get(&url))
+            .await
+            .context(format!("Failed to download {}", path))?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let text = response.text().await.unwrap_or_default();
+            return Err(anyhow::anyhow!("Failed to download {}: {} {}", path, status, text));
+        }
+        Ok(response.bytes().await.context(format!("Failed to download {}", path))?.to_vec())
+    }
+
+    /// Fill in the images of a response from the files the server saved
+    ///
+    /// A download that does not match the hash reported by the server is
+    /// downloaded once more before the generation fails. Replayed responses
+    /// are left as they were recorded.
+    ///
+    /// # Arguments
+    /// * `result` - Response of a txt2img request made with server files enabled
+    /// * `config` - Where the generation info lists the saved images
+    async fn fetch_server_files(
+        &self,
+        result: &mut StableDiffusionResponse,
+        config: &ServerFilesConfig,
+    ) -> Result<()> {
+        if self.is_replay() {
+            return Ok(());
+        }
+        for file in config.files(result)? {
+            let mut bytes = self.download_file(&file.path).await?;
+            if let Err(e) = file.verify(&bytes) {
+                warn!("{}", tr_args(Msg::DownloadingAgain, &[&format!("{:#}", e)]).yellow());
+                bytes = self.download_file(&file.path).await?;
+                file.verify(&bytes)?;
             }
+            result.images.push(GeneratedImage::from_bytes(&bytes)?);
         }
+        Ok(())
+    }
 
-        // Try to parse as StableDiffusionResponse
-        match serde_json::from_str::<StableDiffusionResponse>(&response_text) {
-            Ok(result) => Ok(Some(result)),
-            Err(e) => Err(anyhow::anyhow!("Failed to parse API response: {}", e))
+    /// Send a txt2img payload, or play back its recorded response
+    ///
+    /// The response carries the digest of the payload and of the input image.
+    /// Settings of the backend are sent along in override_settings but left
+    /// out of the digest, so the same request on another backend matches.
+    async fn send_txt2img(
+        &self,
+        image_path: &Path,
+        request: &GenerationRequest,
+        payload: &serde_json::Value,
+        backend_settings: &serde_json::Map<String, serde_json::Value>,
+    ) -> Result<Option<StableDiffusionResponse>> {
+        let url = format!("{}sdapi/v1/txt2img", self.api_url);
+        let mut sent = payload.clone();
+        merge_override_settings(&mut sent, backend_settings);
+
+        let body: Txt2ImgBody = match &self.fixtures {
+            Some(fixtures) if fixtures.is_replay() => serde_json::from_str(&fixtures.replay(image_path)?)
+                .map_err(|e| anyhow::anyhow!("Failed to parse API response: {}", e))?,
+            _ => {
+                debug!("POST {} (batch size {}, {}x{})", url, request.batch_size, request.width, request.height);
+                let response = self
+                    .send(self.client().post(&url).json(&sent))
+                    .await
+                    .context("API request failed")?;
+                debug!("API responded with status {}", response.status());
+
+                if !response.status().is_success() {
+                    let status = response.status();
+                    error!("{}", tr_args(Msg::ApiStatus, &[&status]).red());
+
+                    // Try to get error details for better handling
+                    let error_text = response.text().await.unwrap_or_default();
+                    return Err(anyhow::anyhow!("API error: {} - {}", status, error_text));
+                }
+
+                // Large batches of large images are parsed as they arrive, each image written out before the next is read
+                let mut recording = match &self.fixtures {
+                    Some(fixtures) => Some(fixtures.recording(image_path)?),
+                    None => None,
+                };
+                http::read_json_streamed(response, recording.as_mut().map(|file| file as _))
+                    .await
+                    .map_err(|e| anyhow::anyhow!("Failed to parse API response: {:#}", e))?
+            }
+        };
+
+        // Check if the response contains error information in JSON
+        if let Some(error) = body.error.as_ref().and_then(|error| error.as_str()) {
+            return Err(anyhow::anyhow!("API returned error: {}", error));
         }
+        let Some(images) = body.images else {
+            return Err(anyhow::anyhow!("Failed to parse API response: missing field `images`"));
+        };
+        let mut result = StableDiffusionResponse {
+            images,
+            parameters: body.parameters,
+            info: body.info,
+            digest: None,
+        };
+        let image = std::fs::read(image_path)
+            .context(format!("Error reading image: {}", image_path.display()))?;
+        result.digest = Some(RequestDigest::new(payload, &image, result.seed()));
+        Ok(Some(result))
     }
 
     /// Fetch available ControlNet models from the API
@@ -217,8 +659,7 @@ impl StableDiffusionClient {
     pub async fn get_controlnet_models(&self) -> Result<Vec<String>> {
         let url = format!("{}controlnet/model_list", self.api_url);
         
-        let response = self.client.get(&url)
-            .send()
+        let response = self.send(self.client().get(&url))
             .await
             .context("Failed to fetch ControlNet models")?;
             
@@ -274,8 +715,7 @@ impl StableDiffusionClient {
     pub async fn get_controlnet_modules(&self) -> Result<Vec<String>> {
         let url = format!("{}controlnet/module_list", self.api_url);
         
-        let response = self.client.get(&url)
-            .send()
+        let response = self.send(self.client().get(&url))
             .await
             .context("Failed to fetch ControlNet modules")?;
             
@@ -303,8 +743,7 @@ impl StableDiffusionClient {
     pub async fn get_sd_models(&self) -> Result<Vec<String>> {
         let url = format!("{}sdapi/v1/sd-models", self.api_url);
         
-        let response = self.client.get(&url)
-            .send()
+        let response = self.send(self.client().get(&url))
             .await
             .context("Failed to fetch SD models")?;
             
@@ -329,8 +768,7 @@ impl StableDiffusionClient {
     pub async fn get_samplers(&self) -> Result<Vec<String>> {
         let url = format!("{}sdapi/v1/samplers", self.api_url);
         
-        let response = self.client.get(&url)
-            .send()
+        let response = self.send(self.client().get(&url))
             .await
             .context("Failed to fetch samplers")?;
             
@@ -347,9 +785,170 @@ impl StableDiffusionClient {
             
         Ok(sampler_names)
     }
+
+    /// Fetch available scheduler names from the API
+    ///
+    /// # Returns
+    /// * `Result<Vec<String>>` - List of available scheduler names
+    pub async fn get_schedulers(&self) -> Result<Vec<String>> {
+        let url = format!("{}sdapi/v1/schedulers", self.api_url);
+
+        let response = self.send(self.client().get(&url))
+            .await
+            .context("Failed to fetch schedulers")?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let text = response.text().await.unwrap_or_default();
+            return Err(anyhow::anyhow!("Failed to get schedulers: {} {}", status, text));
+        }
+
+        let schedulers = response.json::<Vec<serde_json::Value>>().await?;
+        let scheduler_names: Vec<String> = schedulers.iter()
+            .filter_map(|scheduler| scheduler["label"].as_str().or(scheduler["name"].as_str()).map(String::from))
+            .collect();
+
+        Ok(scheduler_names)
+    }
+
+    /// Check whether the API answers, without generating anything
+    ///
+    /// # Returns
+    /// * `Result<reqwest::StatusCode>` - Status of a progress request, an Error if the server could not be reached
+    pub async fn api_status(&self) -> Result<reqwest::StatusCode> {
+        let url = format!("{}sdapi/v1/progress?skip_current_image=true", self.api_url);
+        debug!("GET {}", url);
+
+        let response = self.send(self.client().get(&url))
+            .await
+            .context("Failed to reach the API")?;
+        Ok(response.status())
+    }
+
+    /// Fetch the progress of the generation running on the server, with its live preview
+    ///
+    /// # Returns
+    /// * `Result<ProgressResponse>` - Sampling step and preview image, empty when replaying fixtures
+    pub async fn get_progress(&self) -> Result<ProgressResponse> {
+        if self.is_replay() {
+            return Ok(ProgressResponse::default());
+        }
+        let url = format!("{}sdapi/v1/progress", self.api_url);
+
+        let response = self.send(self.client().get(&url))
+            .await
+            .context("Failed to fetch progress")?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            return Err(anyhow::anyhow!("Failed to get progress: {}", status));
+        }
+
+        Ok(response.json::<ProgressResponse>().await?)
+    }
+
+    /// Poll the progress of the server while a generation runs
+    ///
+    /// The progress is fetched every interval until the generation is done,
+    /// the first time right away. Failing to fetch it is only logged.
+    ///
+    /// # Arguments
+    /// * `interval` - Time between progress requests
+    /// * `generation` - Generation running on this server
+    /// * `on_progress` - Called with every progress fetched
+    ///
+    /// # Returns
+    /// The result of the generation
+    pub async fn poll_progress<F: std::future::Future>(
+        &self,
+        interval: Duration,
+        generation: F,
+        mut on_progress: impl FnMut(&ProgressResponse),
+    ) -> F::Output {
+        let mut interval = tokio::time::interval(interval.max(Duration::from_millis(1)));
+        tokio::pin!(generation);
+        loop {
+            tokio::select! {
+                output = &mut generation => return output,
+                _ = async {
+                    interval.tick().await;
+                    match self.get_progress().await {
+                        Ok(progress) => on_progress(&progress),
+                        Err(e) => debug!("{} {:#}", "Failed to fetch progress:".yellow(), e),
+                    }
+                } => {}
+            }
+        }
+    }
+
+    /// Fetch the memory statistics of the server
+    ///
+    /// # Returns
+    /// * `Result<serde_json::Value>` - RAM and CUDA memory usage as reported by the server
+    pub async fn get_memory(&self) -> Result<serde_json::Value> {
+        let url = format!("{}sdapi/v1/memory", self.api_url);
+
+        let response = self.send(self.client().get(&url))
+            .await
+            .context("Failed to fetch memory statistics")?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            return Err(anyhow::anyhow!("Failed to get memory statistics: {}", status));
+        }
+
+        Ok(response.json::<serde_json::Value>().await?)
+    }
+
+    /// Fetch the version of the Web UI
+    ///
+    /// # Returns
+    /// * `Result<String>` - Version reported in the system information, e.g. "v1.10.1"
+    pub async fn get_webui_version(&self) -> Result<String> {
+        let url = format!("{}internal/sysinfo", self.api_url);
+
+        let response = self.send(self.client().get(&url))
+            .await
+            .context("Failed to fetch system information")?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            return Err(anyhow::anyhow!("Failed to get system information: {}", status));
+        }
+
+        let sysinfo = response.json::<serde_json::Value>().await?;
+        sysinfo["Version"]
+            .as_str()
+            .map(String::from)
+            .context("System information has no version")
+    }
+
+    /// Fetch the version of the ControlNet extension
+    ///
+    /// # Returns
+    /// * `Result<u64>` - API version of the extension, an Error if it is not installed
+    pub async fn get_controlnet_version(&self) -> Result<u64> {
+        let url = format!("{}controlnet/version", self.api_url);
+
+        let response = self.send(self.client().get(&url))
+            .await
+            .context("Failed to fetch ControlNet version")?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            return Err(anyhow::anyhow!("Failed to get ControlNet version: {}", status));
+        }
+
+        let version = response.json::<serde_json::Value>().await?;
+        version["version"]
+            .as_u64()
+            .context("ControlNet version missing from response")
+    }
     
     /// Validate configuration options against available API options
     ///
+    /// The lists of the server are taken from the list cache while it is fresh.
+    ///
     /// # Arguments
     /// * `config` - Configuration to validate
     ///
@@ -360,14 +959,16 @@ impl StableDiffusionClient {
         
         // Skip validation if disabled in config
         if !config.validate_options {
-            println!("{}", "Option validation disabled in config.".blue());
+            info!("{}", tr(Msg::ValidationDisabled).blue());
             return Ok(issues);
         }
         
-        println!("{}", "Validating configuration options against API...".blue());
+        info!("{}", tr(Msg::ValidatingOptions).blue());
+        // Lists fetched recently, or saved before the server went away, save asking again
+        let mut cache = ListCache::from_config(config);
         
         // Check if model checkpoint exists
-        match self.get_sd_models().await {
+        match cache.list("checkpoints", self.get_sd_models()).await {
             Ok(models) => {
                 if !models.iter().any(|m| m == &config.checkpoint_model) {
                     issues.push(format!(
@@ -377,11 +978,11 @@ impl StableDiffusionClient {
                     ));
                 }
             },
-            Err(e) => println!("{} {}", "Could not validate checkpoint models:".yellow(), e),
+            Err(e) => warn!("{}", tr_args(Msg::CheckpointsUnchecked, &[&e]).yellow()),
         }
         
         // Check if sampler exists
-        match self.get_samplers().await {
+        match cache.list("samplers", self.get_samplers()).await {
             Ok(samplers) => {
                 if !samplers.iter().any(|s| s == &config.sampler_name) {
                     issues.push(format!(
@@ -391,36 +992,42 @@ impl StableDiffusionClient {
                     ));
                 }
             },
-            Err(e) => println!("{} {}", "Could not validate samplers:".yellow(), e),
+            Err(e) => warn!("{}", tr_args(Msg::SamplersUnchecked, &[&e]).yellow()),
         }
         
-        // Check if ControlNet model exists
-        match self.get_controlnet_models().await {
+        // Check if the ControlNet model of every unit exists
+        let units = config.active_controlnet_units();
+        match cache.list("controlnet_models", self.get_controlnet_models()).await {
             Ok(models) => {
-                let model_name = format!("control_{}_sd15", config.model);
-                if !models.iter().any(|m| m == &model_name) {
-                    issues.push(format!(
-                        "ControlNet model '{}' not found. Available ControlNet models: {}", 
-                        model_name,
-                        models.join(", ")
-                    ));
+                for unit in &units {
+                    let model_name = unit.server_model();
+                    // Listed names have the control_ prefix and _sd15 suffix removed
+                    if !models.iter().any(|m| m == &model_name || m == &unit.model) {
+                        issues.push(format!(
+                            "ControlNet model '{}' not found. Available ControlNet models: {}",
+                            model_name,
+                            models.join(", ")
+                        ));
+                    }
                 }
             },
-            Err(e) => println!("{} {}", "Could not validate ControlNet models:".yellow(), e),
+            Err(e) => warn!("{}", tr_args(Msg::ControlNetModelsUnchecked, &[&e]).yellow()),
         }
         
-        // Check if ControlNet module exists
-        match self.get_controlnet_modules().await {
+        // Check if the ControlNet module of every unit exists
+        match cache.list("controlnet_modules", self.get_controlnet_modules()).await {
             Ok(modules) => {
-                if !modules.iter().any(|m| m == &config.controlnet_module) {
-                    issues.push(format!(
-                        "ControlNet module '{}' not found. Available modules: {}", 
-                        config.controlnet_module,
-                        modules.join(", ")
-                    ));
+                for unit in &units {
+                    if !modules.iter().any(|m| m == &unit.module) {
+                        issues.push(format!(
+                            "ControlNet module '{}' not found. Available modules: {}",
+                            unit.module,
+                            modules.join(", ")
+                        ));
+                    }
                 }
             },
-            Err(e) => println!("{} {}", "Could not validate ControlNet modules:".yellow(), e),
+            Err(e) => warn!("{}", tr_args(Msg::ControlNetModulesUnchecked, &[&e]).yellow()),
         }
         
         Ok(issues)
@@ -464,6 +1071,7 @@ pub async fn generate_with_controlnet(
     image_path: &Path,
     config: &Config,
 ) -> Result<Option<StableDiffusionResponse>> {
-    let sd_client = StableDiffusionClient::new(&config.sd_api_url);
+    let sd_client = StableDiffusionClient::new(&config.sd_api_url)
+        .with_identity(config.request_identity(None));
     sd_client.generate_with_controlnet(image_path, config).await
 }
@@ -1,4 +1,7 @@
 use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::config::{Config, DEFAULT_PROCESSOR_RES};
 
 /// Response for API options query
 #[derive(Serialize, Deserialize, Debug)]
@@ -60,3 +63,215 @@ pub struct SamplersResponse {
     /// List of available samplers
     pub names: Vec<String>,
 }
+
+/// Progress of the generation running on the server
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ProgressResponse {
+    /// Share of the job done, from 0 to 1
+    #[serde(default)]
+    pub progress: f64,
+    /// Estimated time left of the job, in seconds
+    #[serde(default)]
+    pub eta_relative: f64,
+    /// Sampling state of the job
+    #[serde(default)]
+    pub state: ProgressState,
+    /// Base64-encoded live preview of the image being sampled, when the server made one
+    #[serde(default)]
+    pub current_image: Option<String>,
+}
+
+/// Sampling state reported with the progress
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ProgressState {
+    /// Sampling step of the image being generated
+    #[serde(default)]
+    pub sampling_step: u32,
+    /// Sampling steps of the image in total
+    #[serde(default)]
+    pub sampling_steps: u32,
+}
+
+/// One ControlNet unit of a generation request
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ControlNetUnit {
+    /// Preprocessor applied to the control image, e.g. "canny"
+    pub module: String,
+    /// ControlNet model name as known to the server, e.g. "control_canny_sd15"
+    pub model: String,
+    /// Strength of the unit
+    pub weight: f32,
+    /// Fraction of the sampling steps at which the unit starts to apply
+    pub guidance_start: f32,
+    /// Fraction of the sampling steps at which the unit stops to apply
+    pub guidance_end: f32,
+    /// Resolution the preprocessor works at
+    pub processor_res: u32,
+    /// First preprocessor parameter, e.g. the low threshold of canny
+    pub threshold_a: f32,
+    /// Second preprocessor parameter, e.g. the high threshold of canny
+    pub threshold_b: f32,
+    /// 0 balanced, 1 prompt is more important, 2 ControlNet is more important
+    pub control_mode: u32,
+    /// 0 just resize, 1 crop and resize, 2 resize and fill
+    pub resize_mode: u32,
+    /// Let the server pick the preprocessor resolution from the image size
+    pub pixel_perfect: bool,
+    /// Base64 control image, the input image of the request when not set
+    pub input_image: Option<String>,
+}
+
+impl ControlNetUnit {
+    /// Unit with the given preprocessor and model, and the defaults used for every input
+    pub fn new(module: &str, model: &str, weight: f32) -> Self {
+        Self {
+            module: module.to_string(),
+            model: model.to_string(),
+            weight,
+            guidance_start: 0.0,
+            guidance_end: 1.0,
+            processor_res: DEFAULT_PROCESSOR_RES,
+            threshold_a: 64.0,
+            threshold_b: 64.0,
+            control_mode: 0,
+            resize_mode: 1,
+            pixel_perfect: true,
+            input_image: None,
+        }
+    }
+
+    /// Arguments of the unit for the ControlNet extension
+    fn to_args(&self, input_image: &str) -> Value {
+        json!({
+            "input_image": self.input_image.as_deref().unwrap_or(input_image),
+            "module": self.module,
+            "model": self.model,
+            "weight": self.weight,
+            "guidance_start": self.guidance_start,
+            "guidance_end": self.guidance_end,
+            "processor_res": self.processor_res,
+            "threshold_a": self.threshold_a,
+            "threshold_b": self.threshold_b,
+            "control_mode": self.control_mode,
+            "resize_mode": self.resize_mode,
+            "pixel_perfect": self.pixel_perfect,
+            "enabled": true
+        })
+    }
+}
+
+/// Everything the server needs to generate images for one input, without
+/// the settings of the run around it
+///
+/// Build one from a `Config` with `GenerationRequest::from(&config)`, or fill
+/// in the fields directly when driving the API from another program.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct GenerationRequest {
+    /// Positive prompt
+    pub prompt: String,
+    /// Negative prompt
+    pub negative_prompt: String,
+    /// Width of the generated images
+    pub width: u32,
+    /// Height of the generated images
+    pub height: u32,
+    /// Number of images generated at once
+    pub batch_size: u32,
+    /// Number of sampling steps
+    pub steps: u32,
+    /// CFG scale
+    pub cfg_scale: f32,
+    /// Seed, -1 for a random one
+    pub seed: i64,
+    /// Variation seed, -1 for a random one
+    #[serde(default = "crate::config::default_seed")]
+    pub subseed: i64,
+    /// How much of the variation seed is mixed in, 0 for none
+    #[serde(default)]
+    pub subseed_strength: f32,
+    /// Sampler name, e.g. "DPM++ 2M"
+    pub sampler_name: String,
+    /// Scheduler appended to the sampler name, e.g. "Karras", empty for none
+    pub scheduler: String,
+    /// Checkpoint to generate with, the loaded one when empty
+    pub checkpoint: String,
+    /// ControlNet units guiding the generation
+    pub controlnet_units: Vec<ControlNetUnit>,
+}
+
+impl GenerationRequest {
+    /// Body of the txt2img request
+    ///
+    /// # Arguments
+    /// * `input_image` - Base64 input image, used by units without an image of their own
+    ///
+    /// # Returns
+    /// * `Value` - JSON payload for `sdapi/v1/txt2img`
+    pub fn to_payload(&self, input_image: &str) -> Value {
+        let sampler_name = if self.scheduler.is_empty() {
+            self.sampler_name.clone()
+        } else {
+            format!("{} {}", self.sampler_name, self.scheduler)
+        };
+        let units: Vec<Value> = self
+            .controlnet_units
+            .iter()
+            .map(|unit| unit.to_args(input_image))
+            .collect();
+
+        let mut payload = json!({
+            "prompt": self.prompt,
+            "negative_prompt": self.negative_prompt,
+            "batch_size": self.batch_size,
+            "steps": self.steps,
+            "width": self.width,
+            "height": self.height,
+            "cfg_scale": self.cfg_scale,
+            "seed": self.seed,
+            "subseed": self.subseed,
+            "subseed_strength": self.subseed_strength,
+            "sampler_name": sampler_name,
+            "alwayson_scripts": {
+                "controlnet": {
+                    "args": units
+                }
+            }
+        });
+        if !self.checkpoint.is_empty() {
+            payload["override_settings"] = json!({ "sd_model_checkpoint": self.checkpoint });
+        }
+        payload
+    }
+}
+
+impl From<&Config> for GenerationRequest {
+    fn from(config: &Config) -> Self {
+        Self {
+            prompt: config.prompt.clone(),
+            negative_prompt: config.negative_prompt.clone(),
+            width: config.width,
+            height: config.height,
+            batch_size: config.batch_size,
+            steps: config.steps,
+            cfg_scale: config.cfg,
+            seed: config.seed,
+            subseed: config.subseed,
+            subseed_strength: config.subseed_strength,
+            sampler_name: config.sampler_name.clone(),
+            scheduler: config.scheduler.clone(),
+            checkpoint: config.checkpoint_model.clone(),
+            controlnet_units: config
+                .active_controlnet_units()
+                .iter()
+                .map(|unit| ControlNetUnit {
+                    guidance_start: unit.guidance_start,
+                    guidance_end: unit.guidance_end,
+                    processor_res: unit.processor_res.unwrap_or(DEFAULT_PROCESSOR_RES),
+                    pixel_perfect: unit.pixel_perfect,
+                    resize_mode: config.resize_mode.api_value(),
+                    ..ControlNetUnit::new(&unit.module, &unit.server_model(), unit.weight)
+                })
+                .collect(),
+        }
+    }
+}
@@ -0,0 +1,251 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+/**
+ * Benchmarking for ControlNet Image Generator
+ *
+ * This module implements `urasoe benchmark`, which generates from one fixed
+ * input with every combination of the given samplers, step counts and
+ * resolutions. Each combination is timed and the peak VRAM reported by the
+ * server after it is recorded, so the fastest settings that fit the GPU can
+ * be picked before starting a long run. The server only keeps the highest
+ * use since it started, so the peak covers the combinations run before too.
+ */
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use crate::api::StableDiffusionClient;
+#[cfg(feature = "cli")]
+use crate::commands::render_table;
+use crate::config::Config;
+use crate::http;
+use crate::i18n::{Msg, tr, tr_args};
+use crate::style::*;
+
+/// Width and height of generated images, written as `768x512`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ImageSize {
+    /// Width in pixels
+    pub width: u32,
+    /// Height in pixels
+    pub height: u32,
+}
+
+impl FromStr for ImageSize {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        let (width, height) = value
+            .split_once(['x', 'X'])
+            .context(format!("Invalid size '{}', expected WIDTHxHEIGHT", value))?;
+        let parse = |side: &str| {
+            side.trim()
+                .parse::<u32>()
+                .context(format!("Invalid size '{}', expected WIDTHxHEIGHT", value))
+        };
+        Ok(Self {
+            width: parse(width)?,
+            height: parse(height)?,
+        })
+    }
+}
+
+impl fmt::Display for ImageSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)
+    }
+}
+
+/// Settings of one benchmarked generation
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Combination {
+    /// Sampler name
+    pub sampler: String,
+    /// Sampling steps
+    pub steps: u32,
+    /// Image size
+    pub size: ImageSize,
+}
+
+impl Combination {
+    /// The configuration with the settings of this combination, generating a single image
+    pub fn apply(&self, config: &Config) -> Config {
+        let mut config = config.clone();
+        config.sampler_name = self.sampler.clone();
+        config.steps = self.steps;
+        config.width = self.size.width;
+        config.height = self.size.height;
+        config.batch_size = 1;
+        config
+    }
+}
+
+/// Measurements of one combination
+#[derive(Debug, Clone, Serialize)]
+pub struct BenchmarkResult {
+    /// Settings that were benchmarked
+    pub combination: Combination,
+    /// Average generation time over the repetitions in milliseconds
+    pub duration_ms: Option<u64>,
+    /// Highest VRAM use reported by the server after generating, in bytes
+    ///
+    /// This is the peak so far: it includes the combinations run before this one.
+    pub vram_peak_bytes: Option<u64>,
+    /// Error message when generation failed
+    pub error: Option<String>,
+}
+
+/// Every combination of the given settings, falling back to the configured
+/// value for each empty list
+pub fn combinations(config: &Config, samplers: &[String], steps: &[u32], sizes: &[ImageSize]) -> Vec<Combination> {
+    let samplers = if samplers.is_empty() {
+        vec![config.sampler_name.clone()]
+    } else {
+        samplers.to_vec()
+    };
+    let steps = if steps.is_empty() { vec![config.steps] } else { steps.to_vec() };
+    let sizes = if sizes.is_empty() {
+        vec![ImageSize {
+            width: config.width,
+            height: config.height,
+        }]
+    } else {
+        sizes.to_vec()
+    };
+
+    let mut combinations = Vec::new();
+    for sampler in &samplers {
+        for &steps in &steps {
+            for &size in &sizes {
+                combinations.push(Combination {
+                    sampler: sampler.clone(),
+                    steps,
+                    size,
+                });
+            }
+        }
+    }
+    combinations
+}
+
+/// Peak VRAM in a memory statistics response of the server, the highest since it started
+pub fn vram_peak(memory: &serde_json::Value) -> Option<u64> {
+    memory["cuda"]["active"]["peak"]
+        .as_u64()
+        .or_else(|| memory["cuda"]["allocated"]["peak"].as_u64())
+}
+
+/// Generate with every combination and measure it
+///
+/// The first combination is generated once before timing starts, so loading
+/// the checkpoint is not counted against it.
+///
+/// # Arguments
+/// * `config` - Configuration the combinations are applied to
+/// * `image` - Input image used for every generation
+/// * `combinations` - Settings to benchmark
+/// * `repeat` - Generations per combination, averaged
+pub async fn run(config: &Config, image: &Path, combinations: &[Combination], repeat: u32) -> Result<Vec<BenchmarkResult>> {
+    if !image.is_file() {
+        return Err(anyhow::anyhow!("Benchmark input not found: {}", image.display()));
+    }
+    let run_id = config.run_id.clone().unwrap_or_else(http::new_run_id);
+    let client = StableDiffusionClient::new(&config.sd_api_url)
+        .with_identity(config.request_identity(Some(&run_id)));
+    client.load_model(&config.checkpoint_model).await?;
+
+    if let Some(first) = combinations.first() {
+        info!("{}", tr(Msg::WarmingUp).blue());
+        if let Err(e) = client.generate_with_controlnet(image, &first.apply(config)).await {
+            warn!("{}", tr_args(Msg::WarmUpFailed, &[&format!("{:#}", e)]).yellow());
+        }
+    }
+
+    let repeat = repeat.max(1);
+    let mut results = Vec::with_capacity(combinations.len());
+    for combination in combinations {
+        info!("{}", tr_args(Msg::Benchmarking, &[&combination.sampler, &combination.steps, &combination.size]).blue());
+        let combination_config = combination.apply(config);
+        let mut total = Duration::ZERO;
+        let mut error = None;
+        for _ in 0..repeat {
+            let started = Instant::now();
+            match client.generate_with_controlnet(image, &combination_config).await {
+                Ok(Some(_)) => total += started.elapsed(),
+                Ok(None) => error = Some("API returned no result".to_string()),
+                Err(e) => error = Some(format!("{:#}", e)),
+            }
+            if error.is_some() {
+                break;
+            }
+        }
+
+        let vram_peak_bytes = match client.get_memory().await {
+            Ok(memory) => vram_peak(&memory),
+            Err(e) => {
+                warn!("{}", tr_args(Msg::MemoryStatsFailed, &[&format!("{:#}", e)]).yellow());
+                None
+            }
+        };
+        results.push(BenchmarkResult {
+            combination: combination.clone(),
+            duration_ms: error.is_none().then(|| (total / repeat).as_millis() as u64),
+            vram_peak_bytes,
+            error,
+        });
+    }
+    Ok(results)
+}
+
+/// Table rows of the results, fastest first and failed combinations last
+pub fn rows(results: &[BenchmarkResult]) -> Vec<Vec<String>> {
+    let mut sorted: Vec<&BenchmarkResult> = results.iter().collect();
+    sorted.sort_by_key(|result| result.duration_ms.unwrap_or(u64::MAX));
+    sorted
+        .into_iter()
+        .map(|result| {
+            let combination = &result.combination;
+            vec![
+                combination.sampler.clone(),
+                combination.steps.to_string(),
+                combination.size.to_string(),
+                match result.duration_ms {
+                    Some(duration_ms) => format!("{:.1}s", duration_ms as f64 / 1000.0),
+                    None => "failed".to_string(),
+                },
+                match result.vram_peak_bytes {
+                    Some(bytes) => format!("{} MB", bytes / (1024 * 1024)),
+                    None => "-".to_string(),
+                },
+            ]
+        })
+        .collect()
+}
+
+/// Print the results as a comparison table
+#[cfg(feature = "cli")]
+pub fn display(results: &[BenchmarkResult]) {
+    print!(
+        "{}",
+        render_table(&["SAMPLER", "STEPS", "SIZE", "TIME", "VRAM PEAK SO FAR"], &rows(results))
+    );
+    for result in results {
+        if let Some(error) = &result.error {
+            warn!(
+                "{}",
+                tr_args(
+                    Msg::BenchmarkFailed,
+                    &[
+                        &result.combination.sampler,
+                        &result.combination.steps,
+                        &result.combination.size,
+                        error
+                    ]
+                )
+                .yellow()
+            );
+        }
+    }
+}
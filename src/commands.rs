@@ -0,0 +1,757 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+/**
+ * Subcommands of ControlNet Image Generator
+ *
+ * This module implements the subcommands of the command line interface
+ * other than the daemon: generating, validating the configuration against
+ * the server, listing what the server offers, generating a single piped
+ * image, regenerating images from their metadata, and creating and cleaning
+ * up the files a run uses.
+ */
+use std::fs;
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::{debug, info, warn};
+
+use crate::benchmark::{self, Combination};
+use crate::compare::{self, Axis};
+use crate::config::{self, Config, OutputFormat};
+use crate::control::RunControl;
+#[cfg(feature = "tui")]
+use crate::dashboard::{self, Dashboard, KeyControls};
+use crate::digest::RequestDigest;
+use crate::exit::{ConfigInvalid, ExitStatus};
+use crate::file_utils::{DEAD_LETTER_DIR, FileManager, ImageMetadata, SavedImages};
+use crate::fixtures::FixtureMode;
+use crate::history::{self, HistoryQuery};
+use crate::http;
+#[cfg(feature = "server")]
+use crate::gallery;
+use crate::i18n::{Msg, tr, tr_args};
+use crate::inspect;
+use crate::metrics::Metrics;
+use crate::processing::ProcessingStats;
+use crate::queue::JobQueue;
+use crate::rollup::{self, Rollup};
+use crate::status_line::StatusLine;
+use crate::runner::PresetRun;
+use crate::style::*;
+use crate::{api, prompt, runner};
+
+/// Validate the configuration if enabled, then process the inputs
+///
+/// # Arguments
+/// * `config` - Effective configuration
+/// * `args` - Command line arguments, applied on top of presets
+/// * `metrics` - Metrics updated after every input
+///
+/// # Returns
+/// The exit status reflecting how many inputs failed
+pub async fn generate(config: &Config, args: &config::Args, metrics: &Metrics) -> Result<ExitStatus> {
+    // Validate configuration options if enabled, replayed runs never reach the API
+    if config.validate_options && config.fixtures.mode != FixtureMode::Replay {
+        let client = api::StableDiffusionClient::with_timeout(&config.sd_api_url, config.validate_timeout_ms)
+            .with_identity(config.request_identity(None));
+        match client.validate_config_options(config).await {
+            Ok(issues) => {
+                if !issues.is_empty() {
+                    warn!("{}", tr(Msg::ValidationIssues).yellow().bold());
+                    for issue in issues {
+                        warn!("{}", format!("  - {}", issue).yellow());
+                    }
+                    if !prompt::confirm(config, tr(Msg::ContinueAnyway))? {
+                        return Ok(ExitStatus::ConfigInvalid);
+                    }
+                } else {
+                    info!("{}", tr(Msg::ConfigValid).green());
+                }
+            }
+            Err(e) => {
+                warn!("{}", tr_args(Msg::ValidateConfigFailed, &[&e]).yellow());
+                if !prompt::confirm(config, tr(Msg::ContinueAnyway))? {
+                    return Ok(ExitStatus::from_error(&e));
+                }
+            }
+        }
+    }
+
+    // Print effective configuration
+    for unit in config.active_controlnet_units() {
+        debug!(
+            "{} {} {} {}",
+            "Using ControlNet model:".blue(),
+            unit.model,
+            "with module".blue(),
+            format!("{}, weight {}", unit.module, unit.weight)
+        );
+    }
+    debug!("{} {}", "Using checkpoint model:".blue(), config.checkpoint_model);
+    debug!("{} {} {}", "Using sampler:".blue(), config.sampler_name, config.scheduler);
+    debug!("{} {}", "Reading images from:".blue(), config.input_dir);
+    debug!("{} {}", "Saving output to:".blue(), config.output_dir);
+    debug!("{} {}", "Batch size:".blue(), config.batch_size);
+    debug!("{} {}x{}", "Image dimensions:".blue(), config.width, config.height);
+    debug!("{} {}", "Sampling steps:".blue(), config.steps);
+    debug!("{} {}", "CFG scale:".blue(), config.cfg);
+    debug!("{} {}", "Max retries:".blue(), config.max_retries);
+    debug!("{} {}ms", "Retry delay:".blue(), config.retry_delay_ms);
+    debug!("{} {}ms", "Batch break:".blue(), config.batch_break_ms);
+
+    let control = RunControl::new();
+    let interrupts = tokio::spawn(stop_on_ctrl_c(control.clone()));
+    #[cfg(feature = "tui")]
+    let dashboard = if config.tui {
+        Dashboard::start(control.clone())
+    } else {
+        None
+    };
+    // Without the dashboard, keys still pause, resume and skip while logs scroll
+    #[cfg(feature = "tui")]
+    let keys = if dashboard.is_none() && !prompt::is_non_interactive(config) {
+        KeyControls::start(control.clone())
+    } else {
+        None
+    };
+    #[cfg(not(feature = "tui"))]
+    if config.tui {
+        warn!("{}", tr(Msg::DashboardUnavailable).yellow());
+    }
+    #[cfg(feature = "tui")]
+    let status_line = if dashboard.is_none() {
+        StatusLine::start(control.clone())
+    } else {
+        None
+    };
+    #[cfg(not(feature = "tui"))]
+    let status_line = StatusLine::start(control.clone());
+    let outcome = if config.presets.is_empty() {
+        runner::run_batch(config, metrics, &control).await.map(RunOutcome::Batch)
+    } else {
+        runner::run_presets(config, args, metrics, &control).await.map(RunOutcome::Presets)
+    };
+    #[cfg(feature = "tui")]
+    {
+        match dashboard {
+            Some(dashboard) => dashboard.stop().await,
+            None => control.end(),
+        }
+        if let Some(keys) = keys {
+            keys.stop();
+        }
+    }
+    #[cfg(not(feature = "tui"))]
+    control.end();
+    if let Some(status_line) = status_line {
+        status_line.stop();
+    }
+    interrupts.abort();
+
+    let outcome = outcome?;
+    if config.output_format == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&result_document(config, &outcome)?)?);
+    }
+    if control.is_interrupted() {
+        return Ok(ExitStatus::Interrupted);
+    }
+    Ok(outcome.exit_status())
+}
+
+/// Stop the run gracefully on Ctrl+C, and at once on a second one
+///
+/// The first Ctrl+C aborts the run: the backends are told to interrupt the
+/// images they are generating, the remaining inputs stay queued, and the
+/// statistics, progress file and run manifest are written as the run ends.
+/// A question waiting for an answer keeps waiting, the second Ctrl+C ends
+/// the program without writing anything more, after putting the terminal
+/// back as it was.
+async fn stop_on_ctrl_c(control: Arc<RunControl>) {
+    while tokio::signal::ctrl_c().await.is_ok() {
+        if !control.is_interrupted() {
+            warn!("{}", tr(Msg::Interrupting).yellow());
+            control.interrupt();
+            continue;
+        }
+        #[cfg(feature = "tui")]
+        dashboard::restore_terminal();
+        std::process::exit(ExitStatus::Interrupted.code().into());
+    }
+}
+
+/// What a run of `generate` produced
+pub enum RunOutcome {
+    /// Statistics of a single run, `None` when there was nothing to process
+    Batch(Option<ProcessingStats>),
+    /// One run per preset
+    Presets(Vec<PresetRun>),
+}
+
+impl RunOutcome {
+    /// Exit status of the run, from the inputs that failed across all presets
+    pub fn exit_status(&self) -> ExitStatus {
+        match self {
+            RunOutcome::Batch(stats) => ExitStatus::combine(stats),
+            RunOutcome::Presets(runs) => ExitStatus::combine(runs.iter().filter_map(|run| run.stats.as_ref())),
+        }
+    }
+}
+
+/// Document printed on standard output with `--output json`
+///
+/// A single run gives its output directory and statistics, which include the
+/// outcome and saved images of every input. Preset runs give a list of those
+/// per preset.
+pub fn result_document(config: &Config, outcome: &RunOutcome) -> Result<serde_json::Value> {
+    let stats_value = |stats: &Option<ProcessingStats>| match stats {
+        Some(stats) => stats.to_json_value(),
+        None => Ok(serde_json::Value::Null),
+    };
+    Ok(match outcome {
+        RunOutcome::Batch(stats) => serde_json::json!({
+            "output_dir": config.output_dir,
+            "stats": stats_value(stats)?,
+        }),
+        RunOutcome::Presets(runs) => {
+            let presets = runs
+                .iter()
+                .map(|run| {
+                    Ok(serde_json::json!({
+                        "name": run.name,
+                        "checkpoint": run.checkpoint,
+                        "stats": stats_value(&run.stats)?,
+                    }))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            serde_json::json!({ "presets": presets })
+        }
+    })
+}
+
+/// Check the configured options against the server
+///
+/// # Returns
+/// An error when the server could not be asked or reported issues
+pub async fn validate(config: &Config) -> Result<()> {
+    let client = api::StableDiffusionClient::with_timeout(&config.sd_api_url, config.validate_timeout_ms)
+        .with_identity(config.request_identity(None));
+    let issues = client
+        .validate_config_options(config)
+        .await
+        .context("Failed to validate configuration")?;
+    if issues.is_empty() {
+        info!("{}", tr(Msg::ConfigValid).green());
+        return Ok(());
+    }
+    for issue in &issues {
+        warn!("{}", format!("  - {}", issue).yellow());
+    }
+    Err(ConfigInvalid(format!("{} configuration issues found", issues.len())).into())
+}
+
+/// Everything the server offers that the configuration refers to by name
+#[derive(Debug, Default, Serialize)]
+pub struct ModelListing {
+    /// Stable Diffusion checkpoints, for `checkpoint_model`
+    pub checkpoints: Vec<String>,
+    /// ControlNet models, for `model`
+    pub controlnet_models: Vec<String>,
+    /// ControlNet preprocessors, for `controlnet_module`
+    pub controlnet_modules: Vec<String>,
+    /// Samplers, for `sampler_name`
+    pub samplers: Vec<String>,
+    /// Schedulers, for `scheduler`
+    pub schedulers: Vec<String>,
+}
+
+impl ModelListing {
+    /// Ask the server for everything it offers
+    ///
+    /// Servers too old to list schedulers get an empty scheduler list.
+    pub async fn fetch(client: &api::StableDiffusionClient) -> Result<Self> {
+        let schedulers = match client.get_schedulers().await {
+            Ok(schedulers) => schedulers,
+            Err(e) => {
+                warn!("{}", tr_args(Msg::ListSchedulersFailed, &[&e]).yellow());
+                Vec::new()
+            }
+        };
+        Ok(Self {
+            checkpoints: client.get_sd_models().await?,
+            controlnet_models: client.get_controlnet_models().await?,
+            controlnet_modules: client.get_controlnet_modules().await?,
+            samplers: client.get_samplers().await?,
+            schedulers,
+        })
+    }
+
+    /// One row per name, with the kind of the name in the first column
+    pub fn rows(&self) -> Vec<Vec<String>> {
+        let kinds = [
+            ("checkpoint", &self.checkpoints),
+            ("controlnet model", &self.controlnet_models),
+            ("controlnet module", &self.controlnet_modules),
+            ("sampler", &self.samplers),
+            ("scheduler", &self.schedulers),
+        ];
+        kinds
+            .into_iter()
+            .flat_map(|(kind, names)| names.iter().map(move |name| vec![kind.to_string(), name.clone()]))
+            .collect()
+    }
+}
+
+/// Format rows as a table with aligned columns and a header line
+///
+/// # Arguments
+/// * `headers` - Column titles
+/// * `rows` - Cells of every row, in the order of the headers
+pub fn render_table(headers: &[&str], rows: &[Vec<String>]) -> String {
+    let mut widths: Vec<usize> = headers.iter().map(|header| header.chars().count()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let format_row = |cells: Vec<&str>| {
+        let line: Vec<String> = cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        line.join("  ").trim_end().to_string()
+    };
+
+    let mut table = format_row(headers.to_vec());
+    table.push('\n');
+    for row in rows {
+        table.push_str(&format_row(row.iter().map(String::as_str).collect()));
+        table.push('\n');
+    }
+    table
+}
+
+/// List what the server offers, to help picking valid names for the configuration
+///
+/// # Arguments
+/// * `config` - Configuration naming the server
+/// * `json` - Print JSON instead of a table
+pub async fn models(config: &Config, json: bool) -> Result<()> {
+    let client = api::StableDiffusionClient::with_timeout(&config.sd_api_url, config.validate_timeout_ms)
+        .with_identity(config.request_identity(None));
+    let listing = ModelListing::fetch(&client).await?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&listing)?);
+    } else {
+        print!("{}", render_table(&["TYPE", "NAME"], &listing.rows()));
+    }
+    Ok(())
+}
+
+/// List the past generations of the output directory matching a query
+///
+/// # Arguments
+/// * `config` - Configuration naming the output directory and job queue
+/// * `query` - Which generations are listed
+/// * `json` - Print JSON instead of a table
+pub fn history(config: &Config, query: &HistoryQuery, json: bool) -> Result<()> {
+    let entries = history::search(Path::new(&config.output_dir), &config.queue_path(), query)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+    } else if entries.is_empty() {
+        info!("{}", tr_args(Msg::NoMatchingGenerations, &[&config.output_dir]).yellow());
+    } else {
+        let rows: Vec<Vec<String>> = entries.iter().map(history::row).collect();
+        print!("{}", render_table(&history::TABLE_HEADERS, &rows));
+    }
+    Ok(())
+}
+
+/// Sum up the generations recorded in an output directory
+///
+/// # Arguments
+/// * `config` - Configuration naming the output directory
+/// * `dir` - Output directory of the runs, the output directory of the configuration when not given
+/// * `json` - Print JSON instead of a table
+/// * `out` - File to also write the summary to, as CSV or JSON depending on the extension
+pub fn rollup(config: &Config, dir: Option<&Path>, json: bool, out: Option<&Path>) -> Result<()> {
+    let dir = dir.map_or_else(|| PathBuf::from(&config.output_dir), Path::to_path_buf);
+    let rollup = Rollup::scan(&dir)?;
+    if let Some(out) = out {
+        rollup.write(out)?;
+        info!("{}", tr_args(Msg::RollupWritten, &[&out.display()]).green());
+    }
+    if json {
+        println!("{}", serde_json::to_string_pretty(&rollup)?);
+    } else {
+        print!("{}", render_table(&rollup::TABLE_HEADERS, &rollup.rows()));
+    }
+    Ok(())
+}
+
+/// Report on the inputs of a directory and flag problems before generating
+///
+/// # Arguments
+/// * `config` - Configuration the inputs would be generated with
+/// * `dir` - Directory of the inputs, the input directory of the configuration when not given
+/// * `json` - Print JSON instead of a table
+pub fn inspect(config: &Config, dir: Option<&Path>, json: bool) -> Result<()> {
+    let dir = dir.map_or_else(|| PathBuf::from(&config.input_dir), Path::to_path_buf);
+    let reports = inspect::inspect_dir(&dir, config)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&reports)?);
+        return Ok(());
+    }
+    if reports.is_empty() {
+        info!("{}", tr_args(Msg::NoInputImages, &[&dir.display()]).yellow());
+        return Ok(());
+    }
+    let rows: Vec<Vec<String>> = reports.iter().map(inspect::row).collect();
+    print!("{}", render_table(&inspect::TABLE_HEADERS, &rows));
+    let flagged = reports.iter().filter(|report| report.has_problems()).count();
+    if flagged > 0 {
+        warn!("{}", tr_args(Msg::InputsWithProblems, &[&flagged, &reports.len()]).yellow());
+    } else {
+        info!("{}", tr_args(Msg::NoProblems, &[&reports.len()]).green());
+    }
+    Ok(())
+}
+
+/// Upscale the generated images of an earlier run
+///
+/// # Arguments
+/// * `config` - Configuration naming the server and the upscaler
+/// * `run_dir` - Output directory of the run
+/// * `force` - Upscale images again that already have an upscaled companion
+pub async fn upscale(config: &Config, run_dir: &Path, force: bool) -> Result<()> {
+    if !run_dir.is_dir() {
+        return Err(anyhow::anyhow!("Run directory not found: {}", run_dir.display()));
+    }
+    let client = api::StableDiffusionClient::new(&config.sd_api_url)
+        .with_identity(config.request_identity(config.run_id.as_deref()));
+    let summary = config.upscale.run(&client, run_dir, force).await?;
+    if config.output_format == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&summary)?);
+    }
+    if !summary.failed.is_empty() {
+        return Err(anyhow::anyhow!("{} images failed to upscale", summary.failed.len()));
+    }
+    Ok(())
+}
+
+/// Benchmark combinations of settings on one input and print the comparison
+///
+/// # Arguments
+/// * `config` - Configuration the combinations are applied to
+/// * `image` - Input image used for every generation
+/// * `combinations` - Settings to compare
+/// * `repeat` - Generations per combination
+pub async fn benchmark(config: &Config, image: &Path, combinations: &[Combination], repeat: u32) -> Result<()> {
+    let results = benchmark::run(config, image, combinations, repeat).await?;
+    if config.output_format == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&results)?);
+    } else {
+        benchmark::display(&results);
+    }
+    Ok(())
+}
+
+/// Generate the comparison grid of one input and print where the sheet was saved
+///
+/// # Arguments
+/// * `config` - Configuration the axis values are applied to
+/// * `image` - Input image used for every generation
+/// * `x` - Values laid out in columns
+/// * `y` - Values laid out in rows, if any
+pub async fn compare(config: &Config, image: &Path, x: &Axis, y: Option<&Axis>) -> Result<()> {
+    let path = compare::run(config, image, x, y).await?;
+    if config.output_format == OutputFormat::Json {
+        println!("{}", serde_json::json!({ "sheet": path }));
+    }
+    Ok(())
+}
+
+/// Generate the images of a metadata file again
+///
+/// The settings recorded in the metadata are used, with the options given
+/// on the command line on top, e.g. `urasoe regenerate kata-metadata.json --steps 60`.
+/// Unless `--output-dir` is given, the images are saved under `regenerated/`
+/// next to the metadata file, so the originals stay in place.
+///
+/// # Arguments
+/// * `config` - Configuration providing the server and anything not recorded
+/// * `args` - Command line arguments overriding the recorded settings
+/// * `metadata_path` - Metadata file of the images to generate again
+/// * `image` - Input image to use instead of the recorded source image
+///
+/// # Returns
+/// What was saved
+pub async fn regenerate(
+    config: &Config,
+    args: &config::Args,
+    metadata_path: &Path,
+    image: Option<&Path>,
+) -> Result<SavedImages> {
+    let metadata = ImageMetadata::read(metadata_path)?;
+    let mut config = metadata.apply_to(config);
+    config.apply_args(args);
+    if args.output_dir.is_none() {
+        let metadata_dir = metadata_path.parent().unwrap_or(Path::new("."));
+        config.output_dir = metadata_dir.join("regenerated").to_string_lossy().to_string();
+    }
+    let image = image.map_or_else(|| PathBuf::from(&metadata.source_image), Path::to_path_buf);
+    if !image.is_file() {
+        return Err(anyhow::anyhow!(
+            "Source image not found: {}, pass another one with --image",
+            image.display()
+        ));
+    }
+    if config.seed < 0 {
+        warn!("{}", tr(Msg::NoSeedRecorded).yellow());
+    }
+
+    info!("{}", tr_args(Msg::Regenerating, &[&image.display(), &config.seed]).blue());
+    let run_id = config.run_id.clone().unwrap_or_else(http::new_run_id);
+    let client = api::StableDiffusionClient::new(&config.sd_api_url)
+        .with_identity(config.request_identity(Some(&run_id)));
+    client.load_model(&config.checkpoint_model).await?;
+    let response = client
+        .generate_with_controlnet(&image, &config)
+        .await?
+        .context("API returned no result")?;
+    if let Some(digest) = &response.digest {
+        verify_digest(&metadata, digest);
+    }
+    let saved = FileManager::save_generated_images(&response, &image, &config)?;
+    if config.output_format == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&saved)?);
+    }
+    Ok(saved)
+}
+
+/// Tell whether a regenerated request is the one recorded in the metadata
+///
+/// Metadata written before the hashes were recorded is not checked.
+fn verify_digest(metadata: &ImageMetadata, digest: &RequestDigest) {
+    if let Some(payload_sha256) = &metadata.payload_sha256 {
+        if *payload_sha256 == digest.payload_sha256 {
+            info!("{}", tr(Msg::RequestMatches).green());
+        } else {
+            warn!("{}", tr(Msg::RequestDiffers).yellow());
+        }
+    }
+    if let Some(image_sha256) = &metadata.image_sha256
+        && *image_sha256 != digest.image_sha256
+    {
+        warn!("{}", tr(Msg::SourceDiffers).yellow());
+    }
+}
+
+/// Serve the gallery of the output directory until Ctrl+C is pressed
+#[cfg(feature = "server")]
+pub async fn serve(config: &Config, address: &str) -> Result<()> {
+    let (_, server) = gallery::serve(address, Path::new(&config.output_dir)).await?;
+    tokio::signal::ctrl_c().await.context("Failed to listen for Ctrl+C")?;
+    server.abort();
+    Ok(())
+}
+
+/// Generate from an image read on standard input and write the first
+/// generated image to standard output
+///
+/// Log lines go to standard error, so `cat in.png | urasoe pipe > out.png`
+/// leaves only the image in `out.png`.
+pub async fn pipe(config: &Config) -> Result<()> {
+    let mut stdin = io::stdin();
+    if stdin.is_terminal() {
+        return Err(anyhow::anyhow!(
+            "No input image, pipe one in: cat in.png | urasoe pipe > out.png"
+        ));
+    }
+    let mut input = Vec::new();
+    stdin
+        .read_to_end(&mut input)
+        .context("Failed to read the input image from standard input")?;
+
+    let image = pipe_image(config, &input).await?;
+    let mut stdout = io::stdout().lock();
+    stdout
+        .write_all(&image)
+        .and_then(|_| stdout.flush())
+        .context("Failed to write the generated image to standard output")
+}
+
+/// Generate a single image for an input image given as bytes
+///
+/// # Arguments
+/// * `config` - Generation settings, the batch size is ignored
+/// * `input` - Encoded input image
+///
+/// # Returns
+/// The first generated image, PNG encoded
+pub async fn pipe_image(config: &Config, input: &[u8]) -> Result<Vec<u8>> {
+    if input.is_empty() {
+        return Err(anyhow::anyhow!("The input image is empty"));
+    }
+    // The client reads inputs from files, so the piped image gets a temporary one
+    let mut input_file = tempfile::Builder::new()
+        .prefix("urasoe-pipe-")
+        .suffix(".png")
+        .tempfile()
+        .context("Failed to create a temporary input file")?;
+    input_file
+        .write_all(input)
+        .context("Failed to write the temporary input file")?;
+
+    let mut config = config.clone();
+    config.batch_size = 1;
+    let client = api::StableDiffusionClient::new(&config.sd_api_url)
+        .with_identity(config.request_identity(None));
+    let response = client
+        .generate_with_controlnet(input_file.path(), &config)
+        .await?
+        .context("API returned no result")?;
+    let first = response.images.first().context("API returned no images")?;
+    first.bytes()
+}
+
+/// Default configuration written by `urasoe init`
+pub fn default_config_file() -> String {
+    format!(
+        r#"# urasoe configuration file
+
+# Path settings
+input_dir: "{input_dir}"
+output_dir: "{output_dir}"
+
+# Image generation settings
+batch_size: {batch_size}
+width: {width}
+height: {height}
+steps: {steps}
+cfg: {cfg}
+seed: {seed}  # -1 for a random seed per request
+
+# ControlNet settings
+model: "{model}"  # Options: canny, depth, pose, etc.
+controlnet_module: "{controlnet_module}"  # Module: canny, depth, openpose, etc.
+controlnet_weight: {controlnet_weight}  # Weight of ControlNet influence (0.0-1.0)
+
+# Sampler settings
+sampler_name: "{sampler_name}"
+scheduler: "{scheduler}"
+
+# Model settings
+checkpoint_model: "{checkpoint_model}"
+
+# API settings
+sd_api_url: "{sd_api_url}"
+
+# Prompt settings
+prompt: "{prompt}"
+negative_prompt: "{negative_prompt}"
+
+# Error handling settings
+max_retries: {max_retries}
+retry_delay_ms: {retry_delay_ms}
+batch_break_ms: {batch_break_ms}
+"#,
+        input_dir = config::default_input_dir(),
+        output_dir = config::default_output_dir(),
+        batch_size = config::default_batch_size(),
+        width = config::default_width(),
+        height = config::default_height(),
+        steps = config::default_steps(),
+        cfg = config::default_cfg(),
+        seed = config::default_seed(),
+        model = config::default_model(),
+        controlnet_module = config::default_controlnet_module(),
+        controlnet_weight = config::default_controlnet_weight(),
+        sampler_name = config::default_sampler_name(),
+        scheduler = config::default_sampler_index(),
+        checkpoint_model = config::default_checkpoint_model(),
+        sd_api_url = config::default_sd_api_url(),
+        prompt = config::default_prompt(),
+        negative_prompt = config::default_negative_prompt(),
+        max_retries = config::default_max_retries(),
+        retry_delay_ms = config::default_retry_delay(),
+        batch_break_ms = config::default_batch_break(),
+    )
+}
+
+/// Write the default configuration file
+///
+/// # Arguments
+/// * `path` - Where to write the configuration
+/// * `force` - Overwrite an existing file
+pub fn init(path: &str, force: bool) -> Result<()> {
+    if Path::new(path).exists() && !force {
+        return Err(anyhow::anyhow!(
+            "Configuration file already exists: {} (use --force to overwrite)",
+            path
+        ));
+    }
+    fs::write(path, default_config_file())
+        .context(format!("Failed to write configuration file: {}", path))?;
+    info!("{}", tr_args(Msg::ConfigWritten, &[&path]).green());
+    Ok(())
+}
+
+/// Append inputs to the job queue of the output directory
+///
+/// A running generation takes them before its next input, highest priority
+/// first; otherwise the next run or `urasoe resume` processes them.
+///
+/// # Arguments
+/// * `config` - Configuration naming the queue file
+/// * `images` - Input images to add
+/// * `priority` - Priority of the inputs
+pub fn enqueue(config: &Config, images: &[PathBuf], priority: i32) -> Result<()> {
+    let queue_path = config.queue_path();
+    if let Some(parent) = queue_path.parent() {
+        fs::create_dir_all(parent).context(format!("Failed to create {}", parent.display()))?;
+    }
+    for image in images {
+        if !image.is_file() {
+            anyhow::bail!("Input image not found: {}", image.display());
+        }
+        JobQueue::append_to(&queue_path, image, priority)?;
+        info!("{}", tr_args(Msg::Queued, &[&image.display(), &priority]).green());
+    }
+    Ok(())
+}
+
+/// Remove the job queue and failed inputs, or the whole output directory
+///
+/// Removing the whole output directory is confirmed first, unless `--yes`
+/// is given.
+///
+/// # Arguments
+/// * `config` - Configuration naming the files
+/// * `all` - Also remove the generated images
+pub fn clean(config: &Config, all: bool) -> Result<()> {
+    let mut targets = vec![config.queue_path()];
+    if all {
+        if !prompt::confirm(config, &tr_args(Msg::RemoveOutputDir, &[&config.output_dir]))? {
+            info!("{}", tr(Msg::NothingRemoved).yellow());
+            return Ok(());
+        }
+        targets.push(Path::new(&config.output_dir).to_path_buf());
+    } else {
+        targets.push(Path::new(&config.output_dir).join(DEAD_LETTER_DIR));
+    }
+
+    for target in targets {
+        if target.is_dir() {
+            fs::remove_dir_all(&target)
+                .context(format!("Failed to remove {}", target.display()))?;
+        } else if target.is_file() {
+            fs::remove_file(&target).context(format!("Failed to remove {}", target.display()))?;
+        } else {
+            continue;
+        }
+        info!("{}", tr_args(Msg::Removed, &[&target.display()]).green());
+    }
+    Ok(())
+}
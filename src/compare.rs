@@ -0,0 +1,238 @@
+use anyhow::{Context, Result};
+/**
+ * X/Y comparison grids for ControlNet Image Generator
+ *
+ * This module implements `urasoe compare`, which generates one input with
+ * every combination of the values given for one or two parameters, keeping
+ * the seed fixed, and assembles the results into a labeled comparison sheet
+ * like the X/Y plot script of the Web UI.
+ */
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use tracing::{info, warn};
+
+use crate::api::StableDiffusionClient;
+use crate::config::Config;
+use crate::http;
+use crate::i18n::{Msg, tr_args};
+use crate::sheet;
+use crate::style::*;
+
+/// A generation parameter that can be varied along an axis
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GridParam {
+    /// CFG scale
+    Cfg,
+    /// Sampling steps
+    Steps,
+    /// ControlNet weight
+    Weight,
+    /// Sampler name
+    Sampler,
+    /// Scheduler name
+    Scheduler,
+    /// Seed
+    Seed,
+    /// ControlNet model
+    Model,
+    /// ControlNet module
+    Module,
+    /// Checkpoint model
+    Checkpoint,
+}
+
+impl GridParam {
+    /// Name of the parameter as written on the command line and the sheet
+    pub fn name(&self) -> &'static str {
+        match self {
+            GridParam::Cfg => "cfg",
+            GridParam::Steps => "steps",
+            GridParam::Weight => "weight",
+            GridParam::Sampler => "sampler",
+            GridParam::Scheduler => "scheduler",
+            GridParam::Seed => "seed",
+            GridParam::Model => "model",
+            GridParam::Module => "module",
+            GridParam::Checkpoint => "checkpoint",
+        }
+    }
+
+    /// Set the parameter in a configuration
+    pub fn apply(&self, config: &mut Config, value: &str) -> Result<()> {
+        let invalid = || format!("Invalid {} value '{}'", self.name(), value);
+        match self {
+            GridParam::Cfg => config.cfg = value.parse().with_context(invalid)?,
+            GridParam::Steps => config.steps = value.parse().with_context(invalid)?,
+            GridParam::Weight => config.controlnet_weight = value.parse().with_context(invalid)?,
+            GridParam::Seed => config.seed = value.parse().with_context(invalid)?,
+            GridParam::Sampler => config.sampler_name = value.to_string(),
+            GridParam::Scheduler => config.scheduler = value.to_string(),
+            GridParam::Model => config.model = value.to_string(),
+            GridParam::Module => config.controlnet_module = value.to_string(),
+            GridParam::Checkpoint => config.checkpoint_model = value.to_string(),
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for GridParam {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        Ok(match value.trim().to_lowercase().as_str() {
+            "cfg" | "cfg_scale" => GridParam::Cfg,
+            "steps" => GridParam::Steps,
+            "weight" | "controlnet_weight" => GridParam::Weight,
+            "sampler" | "sampler_name" => GridParam::Sampler,
+            "scheduler" => GridParam::Scheduler,
+            "seed" => GridParam::Seed,
+            "model" => GridParam::Model,
+            "module" | "controlnet_module" => GridParam::Module,
+            "checkpoint" | "checkpoint_model" => GridParam::Checkpoint,
+            other => {
+                return Err(anyhow::anyhow!(
+                    "Unknown parameter '{}', expected cfg, steps, weight, sampler, scheduler, seed, model, module or checkpoint",
+                    other
+                ));
+            }
+        })
+    }
+}
+
+impl fmt::Display for GridParam {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Values of one parameter along an axis of the grid, written as `cfg=5,7,9`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Axis {
+    /// Parameter that varies
+    pub param: GridParam,
+    /// Values in the order they are laid out
+    pub values: Vec<String>,
+}
+
+impl Axis {
+    /// Label of a value on the sheet, e.g. `cfg=7`
+    pub fn label(&self, value: &str) -> String {
+        format!("{}={}", self.param, value)
+    }
+}
+
+impl FromStr for Axis {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        let (param, values) = value
+            .split_once('=')
+            .context(format!("Invalid axis '{}', expected PARAMETER=VALUE,VALUE", value))?;
+        let param: GridParam = param.parse()?;
+        let values: Vec<String> = values
+            .split(',')
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+            .collect();
+        if values.is_empty() {
+            return Err(anyhow::anyhow!("No values given for {}", param));
+        }
+        Ok(Self { param, values })
+    }
+}
+
+/// Path of the comparison sheet for an input
+pub fn sheet_path(config: &Config, image: &Path, x: &Axis, y: Option<&Axis>) -> PathBuf {
+    let stem = image.file_stem().unwrap_or_default().to_string_lossy();
+    let mut name = format!("{}-compare-{}", stem, x.param);
+    if let Some(y) = y {
+        name.push_str(&format!("-{}", y.param));
+    }
+    Path::new(&config.output_dir).join(format!("{}.png", name))
+}
+
+/// Generate every cell of the grid and save the comparison sheet
+///
+/// A random seed is drawn once when none is configured, so all cells share it.
+///
+/// # Arguments
+/// * `config` - Configuration the axis values are applied to
+/// * `image` - Input image
+/// * `x` - Values laid out in columns
+/// * `y` - Values laid out in rows, if any
+///
+/// # Returns
+/// Path of the saved sheet
+pub async fn run(config: &Config, image: &Path, x: &Axis, y: Option<&Axis>) -> Result<PathBuf> {
+    if !image.is_file() {
+        return Err(anyhow::anyhow!("Input image not found: {}", image.display()));
+    }
+    let mut base = config.clone();
+    base.batch_size = 1;
+    // Reject values that do not fit their parameter before generating anything
+    for axis in std::iter::once(x).chain(y) {
+        for value in &axis.values {
+            axis.param.apply(&mut config.clone(), value)?;
+        }
+    }
+    if base.seed < 0 {
+        base.seed = rand::random_range(0..i64::from(u32::MAX));
+    }
+    info!("{}", tr_args(Msg::ComparingWithSeed, &[&base.seed]).blue());
+
+    let run_id = config.run_id.clone().unwrap_or_else(http::new_run_id);
+    let client = StableDiffusionClient::new(&config.sd_api_url)
+        .with_identity(config.request_identity(Some(&run_id)));
+    let row_values: Vec<Option<&String>> = match y {
+        Some(y) => y.values.iter().map(Some).collect(),
+        None => vec![None],
+    };
+    let mut cells = Vec::with_capacity(x.values.len() * row_values.len());
+    for row_value in &row_values {
+        for column_value in &x.values {
+            let mut cell_config = base.clone();
+            x.param.apply(&mut cell_config, column_value)?;
+            let mut label = x.label(column_value);
+            if let (Some(y), Some(row_value)) = (y, row_value) {
+                y.param.apply(&mut cell_config, row_value)?;
+                label = format!("{}, {}", label, y.label(row_value));
+            }
+            info!("{}", tr_args(Msg::GeneratingCell, &[&label]).blue());
+            cells.push(match generate_cell(&client, image, &cell_config).await {
+                Ok(cell) => Some(cell),
+                Err(e) => {
+                    warn!("{}", tr_args(Msg::CellFailed, &[&label, &format!("{:#}", e)]).yellow());
+                    None
+                }
+            });
+        }
+    }
+
+    let column_labels: Vec<String> = x.values.iter().map(|value| x.label(value)).collect();
+    let row_labels: Vec<String> = match y {
+        Some(y) => y.values.iter().map(|value| y.label(value)).collect(),
+        None => vec![String::new()],
+    };
+    let canvas = sheet::render(&column_labels, &row_labels, &cells)?;
+
+    let path = sheet_path(config, image, x, y);
+    fs::create_dir_all(&config.output_dir).context("Failed to create output directory")?;
+    canvas
+        .save(&path)
+        .context(format!("Failed to save comparison sheet: {}", path.display()))?;
+    info!("{}", tr_args(Msg::Saved, &[&path.display()]).green());
+    Ok(path)
+}
+
+/// Generate the image of one cell
+async fn generate_cell(client: &StableDiffusionClient, image: &Path, config: &Config) -> Result<image::DynamicImage> {
+    let response = client
+        .generate_with_controlnet(image, config)
+        .await?
+        .context("API returned no result")?;
+    let first = response.images.first().context("API returned no images")?;
+    let bytes = first.bytes()?;
+    image::load_from_memory(&bytes).context("Failed to decode generated image")
+}
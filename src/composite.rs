@@ -0,0 +1,100 @@
+use anyhow::{Context, Result};
+/**
+ * Before/after composites for ControlNet Image Generator
+ *
+ * This module puts an input and an image generated from it side by side on
+ * one canvas, with a caption strip of the generation parameters below, ready
+ * for review decks. The input is scaled to the height of the generated image
+ * and the labels use the bitmap font of the labeled sheets.
+ */
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImage, RgbImage};
+use std::path::Path;
+
+use crate::file_utils::ImageMetadata;
+use crate::sheet::{self, BACKGROUND, PADDING};
+
+/// Suffix of the file stem of composites, `kata-1-composite.png` for `kata-1.png`
+pub const COMPOSITE_SUFFIX: &str = "-composite";
+
+/// Caption lines describing how one image of a batch was generated
+///
+/// # Arguments
+/// * `metadata` - Settings the images were generated with
+/// * `index` - Position of the image in the batch, starting at 0
+pub fn caption(metadata: &ImageMetadata, index: usize) -> Vec<String> {
+    let seed = metadata.seed_of(index);
+    vec![
+        metadata.checkpoint_model.clone(),
+        format!(
+            "seed {}, steps {}, cfg {}, {}",
+            seed, metadata.steps, metadata.cfg_scale, metadata.sampler_name
+        ),
+        format!(
+            "{} {}, weight {}",
+            metadata.controlnet_module, metadata.controlnet_model, metadata.controlnet_weight
+        ),
+    ]
+}
+
+/// Lay out an input and a generated image side by side above a caption strip
+///
+/// # Arguments
+/// * `source` - Input the image was generated from
+/// * `generated` - Generated image
+/// * `caption` - Lines of the caption strip, wrapped to the width of the canvas
+pub fn render(source: &DynamicImage, generated: &DynamicImage, caption: &[String]) -> Result<RgbImage> {
+    let height = generated.height().max(1);
+    let width = (u64::from(source.width()) * u64::from(height) / u64::from(source.height().max(1))).max(1) as u32;
+    let source = source.resize_exact(width, height, FilterType::Triangle);
+    let labels = ["source".to_string(), "generated".to_string()];
+    let pair = sheet::render(&labels, &[String::new()], &[Some(source), Some(generated.clone())])?;
+
+    let lines: Vec<String> = caption
+        .iter()
+        .flat_map(|line| wrap(line, pair.width().saturating_sub(2 * PADDING)))
+        .collect();
+    let strip = lines.len() as u32 * (sheet::text_height() + PADDING);
+    let mut canvas = RgbImage::from_pixel(pair.width(), pair.height() + strip + PADDING, BACKGROUND);
+    canvas.copy_from(&pair, 0, 0)?;
+    for (row, line) in lines.iter().enumerate() {
+        let top = pair.height() + PADDING + row as u32 * (sheet::text_height() + PADDING);
+        sheet::draw_text(&mut canvas, PADDING, top, line);
+    }
+    Ok(canvas)
+}
+
+/// Composite of an input file and one generated image, encoded as PNG
+///
+/// # Arguments
+/// * `input_image_path` - Input the image was generated from
+/// * `generated` - Generated PNG image
+/// * `caption` - Lines of the caption strip
+pub fn render_png(input_image_path: &Path, generated: &[u8], caption: &[String]) -> Result<Vec<u8>> {
+    let source = image::open(input_image_path).context(format!("Failed to open {}", input_image_path.display()))?;
+    let generated = image::load_from_memory(generated).context("Failed to decode generated image")?;
+    let canvas = render(&source, &generated, caption)?;
+    let mut encoded = Vec::new();
+    canvas
+        .write_to(&mut std::io::Cursor::new(&mut encoded), image::ImageFormat::Png)
+        .context("Failed to encode composite")?;
+    Ok(encoded)
+}
+
+/// Split a line at spaces into lines no wider than `max_width` canvas pixels
+fn wrap(line: &str, max_width: u32) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut current = String::new();
+    for word in line.split_whitespace() {
+        let candidate = if current.is_empty() { word.to_string() } else { format!("{} {}", current, word) };
+        if sheet::text_width(&candidate) > max_width && !current.is_empty() {
+            lines.push(std::mem::replace(&mut current, word.to_string()));
+        } else {
+            current = candidate;
+        }
+    }
+    if !current.is_empty() {
+        lines.push(current);
+    }
+    lines
+}
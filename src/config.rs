@@ -9,87 +9,935 @@
  * both this file and the YAML file should be updated to maintain consistency.
  */
 use anyhow::{Context, Result};
-use clap::Parser;
-use colored::*;
+#[cfg(feature = "cli")]
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+use tracing::warn;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "cli")]
+use crate::benchmark::ImageSize;
+#[cfg(feature = "cli")]
+use crate::compare::Axis;
+use crate::daemon::DaemonConfig;
+use crate::digest::{sha256, sha256_hex};
+#[cfg(feature = "cli")]
+use crate::file_utils::PngCompression;
+use crate::file_utils::PngConfig;
+use crate::fixtures::FixtureConfig;
+#[cfg(feature = "cli")]
+use crate::fixtures::FixtureMode;
+use crate::hooks::HooksConfig;
+use crate::image::{ImageProcessor, InputFilter};
+use crate::http::{API_PASSWORD_ENV, API_TOKEN_ENV, API_USERNAME_ENV, ApiAuth, DEFAULT_USER_AGENT, RequestIdentity};
+use crate::i18n::{Lang, Msg, tr, tr_args};
+use crate::logging::{ColorMode, LogFormat, LogLevel};
+use crate::mqtt::MqttConfig;
+use crate::notify::NotificationConfig;
+use crate::prompt::PromptPolicy;
+use crate::preview::{PreviewConfig, default_poll_interval};
+use crate::processing::NanFallbackConfig;
+#[cfg(feature = "cli")]
+use crate::prompt_source::Emphasis;
+#[cfg(feature = "cli")]
+use crate::prompt_source::CaptionMode;
+use crate::prompt_source::PromptSourceConfig;
+use crate::queue::DEFAULT_QUEUE_FILE;
+use crate::schedule::ScheduleConfig;
+#[cfg(feature = "cli")]
+use crate::schedule::TimeWindow;
+#[cfg(feature = "cli")]
+use crate::selection::ScorerConfig;
+use crate::selection::SelectionConfig;
+use crate::sequence::SequenceConfig;
+use crate::server_files::ServerFilesConfig;
+use crate::list_cache::ListCacheConfig;
+use crate::ramp::RampConfig;
+use crate::timelapse::TimelapseConfig;
+use crate::style::*;
+use crate::style_reference::StyleReferenceConfig;
+use crate::sweep::SweepConfig;
+use crate::upscale::UpscaleConfig;
 
 /// Default path for the configuration file
 pub const DEFAULT_CONFIG_PATH: &str = "urasoe.config.yml";
 
+/// What to do with inputs that failed processing
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum DeadLetterMode {
+    /// Leave failed inputs where they are
+    #[default]
+    Off,
+    /// Copy failed inputs into the dead-letter folder
+    Copy,
+    /// Move failed inputs into the dead-letter folder
+    Move,
+}
+
+/// How the ControlNet extension fits the control image to the size of the generated images
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "snake_case")]
+pub enum ResizeMode {
+    /// Stretch the control image to the size, distorting other aspect ratios
+    JustResize,
+    /// Scale the control image to cover the size and crop what sticks out
+    #[default]
+    CropAndResize,
+    /// Scale the control image to fit inside the size and fill the rest
+    ResizeAndFill,
+}
+
+impl ResizeMode {
+    /// Value of the `resize_mode` of a ControlNet unit
+    pub fn api_value(self) -> u32 {
+        match self {
+            ResizeMode::JustResize => 0,
+            ResizeMode::CropAndResize => 1,
+            ResizeMode::ResizeAndFill => 2,
+        }
+    }
+}
+
+/// How the seed of each input is chosen
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "snake_case")]
+pub enum SeedStrategy {
+    /// Use the configured seed for every input
+    #[default]
+    Fixed,
+    /// Derive the seed from a hash of the input file and the seed salt
+    FromInputHash,
+}
+
+impl SeedStrategy {
+    /// Seed to generate an input with
+    ///
+    /// # Arguments
+    /// * `seed` - Configured seed
+    /// * `image_path` - Input image
+    /// * `salt` - Text hashed along with the input, to get other seeds for the same inputs
+    ///
+    /// # Returns
+    /// The configured seed, or one from 0 to 2^32 - 1 derived from the contents of the input
+    pub fn seed_for(self, seed: i64, image_path: &Path, salt: &str) -> Result<i64> {
+        match self {
+            SeedStrategy::Fixed => Ok(seed),
+            SeedStrategy::FromInputHash => {
+                let mut data = salt.as_bytes().to_vec();
+                data.extend(fs::read(image_path).context(format!("Failed to read {}", image_path.display()))?);
+                let hash = sha256(&data);
+                Ok(i64::from(u32::from_be_bytes([hash[0], hash[1], hash[2], hash[3]])))
+            }
+        }
+    }
+}
+
+/// How the output folder of each input is named
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "snake_case")]
+pub enum OutputNaming {
+    /// The file name of the input without its extension, `photo`
+    #[default]
+    Stem,
+    /// The path of the input relative to the input directory, `2024_photo` for `2024/photo.png`
+    RelativePath,
+    /// The file stem with a short hash of the relative path, `photo-1a2b3c4d`
+    Hash,
+}
+
+impl OutputNaming {
+    /// Name of the output folder of an input, also prefixing the names of its outputs
+    ///
+    /// The name only depends on the path relative to the input directory, so
+    /// a resumed run uses the same names.
+    ///
+    /// # Arguments
+    /// * `image_path` - Input image
+    /// * `input_dir` - Input directory, inputs outside it are named by their whole path
+    pub fn name_for(self, image_path: &Path, input_dir: &Path) -> String {
+        let stem = image_path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+        let relative = image_path.strip_prefix(input_dir).unwrap_or(image_path);
+        let components: Vec<String> = relative
+            .components()
+            .filter_map(|component| match component {
+                std::path::Component::Normal(part) => Some(part.to_string_lossy().to_string()),
+                _ => None,
+            })
+            .collect();
+        match self {
+            OutputNaming::Stem => stem,
+            OutputNaming::RelativePath => {
+                let mut parts = components;
+                parts.pop();
+                parts.push(stem);
+                parts.join("_")
+            }
+            OutputNaming::Hash => format!("{}-{}", stem, &sha256_hex(components.join("/").as_bytes())[..8]),
+        }
+    }
+}
+
+/// Format of the result printed to standard output when a run ends
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// Only the log lines, no separate result
+    #[default]
+    Text,
+    /// One JSON document with the statistics, while log lines go to standard error
+    Json,
+}
+
+/// Web UI setting given on the command line as `KEY=VALUE`
+///
+/// Values that are valid JSON, such as numbers and booleans, are sent as
+/// such, other values as strings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OverrideSetting {
+    /// Name of the setting, e.g. "sd_vae"
+    pub key: String,
+    /// Value of the setting
+    pub value: serde_json::Value,
+}
+
+impl std::str::FromStr for OverrideSetting {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        let (key, setting) = value
+            .split_once('=')
+            .context(format!("Invalid setting '{}', expected KEY=VALUE", value))?;
+        let key = key.trim();
+        if key.is_empty() {
+            return Err(anyhow::anyhow!("No setting name given in '{}'", value));
+        }
+        Ok(Self {
+            key: key.to_string(),
+            value: serde_json::from_str(setting).unwrap_or_else(|_| serde_json::Value::String(setting.to_string())),
+        })
+    }
+}
+
+/// One ControlNet unit of the configuration
+///
+/// Units listed in `controlnet_units` guide the same generation together,
+/// each preprocessing the input image in its own way, e.g. canny and depth.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ControlNetUnitConfig {
+    /// ControlNet model, e.g. "depth" for control_depth_sd15
+    pub model: String,
+    /// Preprocessor applied to the input image, e.g. "depth_midas"
+    #[serde(default = "default_controlnet_module")]
+    pub module: String,
+    /// Strength of the unit
+    #[serde(default = "default_controlnet_weight")]
+    pub weight: f32,
+    /// Fraction of the sampling steps at which the unit starts to apply
+    #[serde(default)]
+    pub guidance_start: f32,
+    /// Fraction of the sampling steps at which the unit stops to apply
+    #[serde(default = "default_guidance_end")]
+    pub guidance_end: f32,
+    /// Let the server pick the preprocessor resolution from the image size
+    #[serde(default = "default_pixel_perfect")]
+    pub pixel_perfect: bool,
+    /// Resolution the preprocessor works at without pixel perfect, matched to the control image when unset
+    #[serde(default)]
+    pub processor_res: Option<u32>,
+}
+
+/// Lowest preprocessor resolution the ControlNet extension accepts
+pub const MIN_PROCESSOR_RES: u32 = 64;
+
+/// Preprocessor resolution sent with pixel perfect, which the server replaces
+pub const DEFAULT_PROCESSOR_RES: u32 = 512;
+
+impl ControlNetUnitConfig {
+    /// Name of the model as known to the server, e.g. "control_canny_sd15"
+    pub fn server_model(&self) -> String {
+        format!("control_{}_sd15", self.model)
+    }
+
+    /// Preprocessor resolution of the unit for a control image
+    ///
+    /// A configured `processor_res` is used as is. Otherwise the short side of
+    /// the control image is, so the preprocessor sees large inputs in full
+    /// detail, up to `max`.
+    ///
+    /// # Arguments
+    /// * `control_size` - Width and height of the control image sent
+    /// * `max` - Highest resolution matched to the control image
+    pub fn processor_res_for(&self, (width, height): (u32, u32), max: u32) -> u32 {
+        self.processor_res
+            .unwrap_or_else(|| width.min(height).min(max).max(MIN_PROCESSOR_RES))
+    }
+}
+
 /// Command line arguments
-#[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+#[cfg(feature = "cli")]
+#[derive(Parser, Debug, Default)]
+#[command(author, version, about, long_about = None, disable_version_flag = true)]
 pub struct Args {
+    /// Print the version with the git commit, build date and enabled features
+    #[arg(short = 'V', long)]
+    pub version: bool,
+
+    /// With --version, also check the Web UI and ControlNet versions of the server are supported
+    #[arg(long, requires = "version")]
+    pub check: bool,
+
     /// Path to directory containing input images
-    #[arg(long)]
+    #[arg(long, global = true)]
     pub input_dir: Option<String>,
 
+    /// Also process the images in the subdirectories of the input directory, mirroring them in the output directory
+    #[arg(long, global = true)]
+    pub recursive: bool,
+
+    /// Only process inputs matching this glob pattern, relative to the input directory, can be repeated
+    #[arg(long, value_name = "PATTERN", global = true)]
+    pub include: Vec<String>,
+
+    /// Leave out inputs matching this glob pattern, relative to the input directory, can be repeated
+    #[arg(long, value_name = "PATTERN", global = true)]
+    pub exclude: Vec<String>,
+
     /// Base path for output images
-    #[arg(long)]
+    #[arg(long, global = true)]
     pub output_dir: Option<String>,
 
+    /// How the output folder of each input is named, relative-path or hash keep inputs of the same name apart
+    #[arg(long, value_enum, global = true)]
+    pub output_naming: Option<OutputNaming>,
+
     /// Number of images to generate for each input
-    #[arg(long)]
+    #[arg(long, global = true)]
     pub batch_size: Option<u32>,
 
     /// Width of generated images
-    #[arg(long)]
+    #[arg(long, global = true)]
     pub width: Option<u32>,
 
     /// Height of generated images
-    #[arg(long)]
+    #[arg(long, global = true)]
     pub height: Option<u32>,
 
     /// ControlNet model to use
-    #[arg(long)]
+    #[arg(long, global = true)]
     pub model: Option<String>,
 
     /// ControlNet module to use (e.g., canny, depth, pose)
-    #[arg(long)]
+    #[arg(long, global = true)]
     pub controlnet_module: Option<String>,
 
     /// ControlNet weight (0.0-1.0)
-    #[arg(long)]
+    #[arg(long, global = true)]
     pub controlnet_weight: Option<f32>,
 
+    /// How the control image is fitted to the size of the generated images
+    #[arg(long, value_enum, global = true)]
+    pub resize_mode: Option<ResizeMode>,
+
+    /// Pad the control image to the aspect ratio of the generated images before sending it
+    #[arg(long, global = true)]
+    pub letterbox: bool,
+
+    /// Match the preprocessor resolution of the ControlNet units to the control image instead of pixel perfect
+    #[arg(long, global = true)]
+    pub no_pixel_perfect: bool,
+
+    /// Highest preprocessor resolution matched to the control image without pixel perfect
+    #[arg(long, value_name = "PIXELS", global = true)]
+    pub max_processor_res: Option<u32>,
+
     /// Sampler name to use (e.g., DPM++ 2M, Euler a)
-    #[arg(long)]
+    #[arg(long, global = true)]
     pub sampler: Option<String>,
 
     /// Scheduler to use (e.g., Karras)
-    #[arg(long)]
+    #[arg(long, global = true)]
     pub scheduler: Option<String>,
 
     /// Number of sampling steps
-    #[arg(long)]
+    #[arg(long, global = true)]
     pub steps: Option<u32>,
 
     /// CFG scale for generation
-    #[arg(long)]
+    #[arg(long, global = true)]
     pub cfg: Option<f32>,
 
+    /// Seed for generation, -1 for a random seed per request
+    #[arg(long, allow_hyphen_values = true, global = true)]
+    pub seed: Option<i64>,
+
+    /// How the seed of each input is chosen, from_input_hash derives it from the input file
+    #[arg(long, value_enum, global = true)]
+    pub seed_strategy: Option<SeedStrategy>,
+
+    /// Text hashed along with each input by the from_input_hash seed strategy
+    #[arg(long, global = true)]
+    pub seed_salt: Option<String>,
+
+    /// Variation seed mixed into the seed by --subseed-strength, -1 for a random one
+    #[arg(long, allow_hyphen_values = true, global = true)]
+    pub subseed: Option<i64>,
+
+    /// How much of the variation seed is mixed in, from 0 for none to 1 for only the variation seed
+    #[arg(long, global = true)]
+    pub subseed_strength: Option<f32>,
+
+    /// Generate every input this many times with consecutive seeds, starting from --seed
+    #[arg(long, value_name = "N", global = true)]
+    pub seed_sweep: Option<u32>,
+
     /// Maximum number of retry attempts
-    #[arg(long)]
+    #[arg(long, global = true)]
     pub max_retries: Option<u32>,
 
+    /// Sampler to retry with once when an image produces NaN tensors
+    #[arg(long, value_name = "NAME", global = true)]
+    pub nan_fallback_sampler: Option<String>,
+
+    /// How long to wait for a restarted server to come back, in seconds, 0 fails the image at once
+    #[arg(long, value_name = "SECONDS", global = true)]
+    pub server_restart_wait: Option<u64>,
+
     /// Delay between retries in milliseconds
-    #[arg(long)]
+    #[arg(long, global = true)]
     pub retry_delay: Option<u64>,    /// Break duration between batches in milliseconds
-    #[arg(long)]
+    #[arg(long, global = true)]
     pub batch_break: Option<u64>,
+
+    /// Only take breaks when generation times degrade, doubling them while it continues
+    #[arg(long, global = true)]
+    pub adaptive_breaks: bool,
+
+    /// Inputs in flight at the same time on each backend
+    #[arg(long, value_name = "N", global = true)]
+    pub concurrency: Option<usize>,
     
     /// Whether to validate options against the SD webui
-    #[arg(long)]
+    #[arg(long, global = true)]
     pub validate_options: Option<bool>,
     
+    /// Whether to write the parameters of each image as Web UI infotext next to it
+    #[arg(long, global = true)]
+    pub parameters_files: Option<bool>,
+
+    /// Save the input and each generated image side by side with a caption of the parameters
+    #[arg(long, global = true)]
+    pub composites: bool,
+
+    /// Web UI setting sent as override_settings with every generation, e.g. `CLIP_stop_at_last_layers=2`, can be repeated
+    #[arg(long, value_name = "KEY=VALUE", global = true)]
+    pub override_setting: Vec<OverrideSetting>,
+
+    /// Have the server save the images and download them by path instead of receiving them as base64
+    #[arg(long, global = true)]
+    pub server_files: bool,
+
     /// Timeout for validation requests in milliseconds
-    #[arg(long)]
+    #[arg(long, global = true)]
     pub validate_timeout: Option<u64>,
 
+    /// Process inputs in random order, reproducible when a seed is given
+    #[arg(long, num_args = 0..=1, value_name = "SEED", global = true)]
+    pub shuffle: Option<Option<u64>>,
+
+    /// Interleave inputs from different subdirectories
+    #[arg(long, global = true)]
+    pub stratified: bool,
+
+    /// Whether to generate one sample first and print a time and size estimate
+    #[arg(long, global = true)]
+    pub estimate: Option<bool>,
+
+    /// Do not ask for confirmation
+    #[arg(short = 'y', long, global = true)]
+    pub yes: bool,
+
+    /// Continue the job queue of the previous run, skipping finished inputs and retrying failed ones
+    #[arg(long, conflicts_with = "force")]
+    pub resume: bool,
+
+    /// Start over even when the previous run in the output directory was interrupted or had failures
+    #[arg(long)]
+    pub force: bool,
+
+    /// Never wait for answers, let --prompt-policy answer questions instead
+    #[arg(long, global = true)]
+    pub non_interactive: bool,
+
+    /// Answer to questions when running non-interactively: continue or abort
+    #[arg(long, value_enum, global = true)]
+    pub prompt_policy: Option<PromptPolicy>,
+
+    /// Write processing statistics to this file (.json or .csv)
+    #[arg(long, global = true)]
+    pub stats_out: Option<String>,
+
+    /// Copy or move failed inputs into output_dir/_failed/
+    #[arg(long, value_enum, global = true)]
+    pub dead_letter: Option<DeadLetterMode>,
+
+    /// Print the result as text or as one JSON document on standard output
+    #[arg(long = "output", value_enum, global = true)]
+    pub output_format: Option<OutputFormat>,
+
+    /// More output: -v for request details, -vv for everything
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    pub verbose: u8,
+
+    /// Less output: -q for warnings and errors, -qq for errors only
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    pub quiet: u8,
+
+    /// Log level: error, warn, info, debug or trace
+    #[arg(long, value_enum, global = true)]
+    pub log_level: Option<LogLevel>,
+
+    /// Log output format: text or json
+    #[arg(long, value_enum, global = true)]
+    pub log_format: Option<LogFormat>,
+
+    /// Plain log lines without colors, also when NO_COLOR is set or output is not a terminal
+    #[arg(long, global = true)]
+    pub no_color: bool,
+
+    /// Language of console messages: en, fi or ja, by default from LANG
+    #[arg(long, value_enum, global = true)]
+    pub lang: Option<Lang>,
+
+    /// Show a live dashboard instead of log lines, with keys to pause, skip and abort
+    #[arg(long, global = true)]
+    pub tui: bool,
+
+    /// Slack incoming webhook URL for the run summary
+    #[arg(long, global = true)]
+    pub slack_webhook: Option<String>,
+
+    /// Discord webhook URL for the run summary
+    #[arg(long, global = true)]
+    pub discord_webhook: Option<String>,
+
+    /// MQTT broker as host:port to publish image and run results to
+    #[arg(long, global = true)]
+    pub mqtt_broker: Option<String>,
+
+    /// Show a desktop notification when the run finishes
+    #[arg(long, global = true)]
+    pub desktop_notify: bool,
+
+    /// Serve Prometheus metrics on this address, e.g. 127.0.0.1:9184
+    #[arg(long, global = true)]
+    pub metrics_addr: Option<String>,
+
+    /// Send at most this many generation requests per minute
+    #[arg(long, global = true)]
+    pub max_requests_per_minute: Option<u32>,
+
+    /// User-Agent header sent to the Stable Diffusion API
+    #[arg(long, global = true)]
+    pub user_agent: Option<String>,
+
+    /// User name for a Web UI started with --api-auth, the password is read from URASOE_API_PASSWORD
+    #[arg(long, value_name = "NAME", global = true)]
+    pub api_username: Option<String>,
+
+    /// Identifier sent in the X-Urasoe-Run-Id header, a new one is generated for every run by default
+    #[arg(long, global = true)]
+    pub run_id: Option<String>,
+
+    /// Put the outputs, configuration snapshot, manifest and log of the run into output_dir/<run id>/
+    #[arg(long, global = true)]
+    pub run_dirs: bool,
+
+    /// Keep the progress of the run in this JSON file for external monitors, relative to the output directory
+    #[arg(long, value_name = "FILE", global = true)]
+    pub progress_file: Option<String>,
+
+    /// Largest batch to request at once; bigger batches are split into sequential requests
+    #[arg(long, global = true)]
+    pub max_batch_per_request: Option<u32>,
+
+    /// Request the images missing from a short response once more
+    #[arg(long, global = true)]
+    pub top_up_missing: bool,
+
+    /// Only generate images during these hours, e.g. 22:00-07:00
+    #[arg(long, global = true)]
+    pub allowed_hours: Option<TimeWindow>,
+
+    /// Save every API response to this directory for later replay
+    #[arg(long, value_name = "DIR", conflicts_with = "replay_fixtures", global = true)]
+    pub record_fixtures: Option<String>,
+
+    /// Answer generation requests with responses recorded in this directory instead of calling the API
+    #[arg(long, value_name = "DIR", global = true)]
+    pub replay_fixtures: Option<String>,
+
+    /// Preset file applied on top of the configuration, repeat to run several presets
+    #[arg(long = "preset", value_name = "FILE", global = true)]
+    pub presets: Vec<String>,
+
+    /// ControlNet weights to generate every input with, comma separated, e.g. 0.3,0.6,0.9
+    #[arg(long, value_delimiter = ',', global = true)]
+    pub sweep_weight: Vec<f32>,
+
+    /// Keep only this many of the images of each input, the sharpest unless another scorer is configured
+    #[arg(long, global = true)]
+    pub keep_best: Option<usize>,
+
+    /// Score an image of each input has to reach, re-rolling with a new seed otherwise
+    #[arg(long, global = true)]
+    pub min_score: Option<f64>,
+
+    /// Most re-rolls of an input that does not reach the minimum score
+    #[arg(long, global = true)]
+    pub max_rerolls: Option<u32>,
+
+    /// Treat the inputs as numbered frames of a video, keeping the seed and their order
+    #[arg(long, global = true)]
+    pub sequence: bool,
+
+    /// Assemble the generated frames into this video with ffmpeg, relative to the output directory
+    #[arg(long, value_name = "FILE", global = true)]
+    pub sequence_video: Option<String>,
+
+    /// Start each backend at batch size 1 and ramp up to batch_size over the first images
+    #[arg(long, global = true)]
+    pub ramp: bool,
+
+    /// Encode the saved PNG images again with this compression
+    #[arg(long, value_enum, global = true)]
+    pub png_compression: Option<PngCompression>,
+
+    /// Assemble the images of the run into this timelapse video with ffmpeg, relative to the output directory
+    #[arg(long, value_name = "FILE", global = true)]
+    pub timelapse: Option<String>,
+
+    /// Attention weight of a term of the prompt, e.g. `--emphasize "black belt=1.2"`, can be repeated
+    #[arg(long, value_name = "TERM=WEIGHT", global = true)]
+    pub emphasize: Vec<Emphasis>,
+
+    /// Use the caption file next to each input, `photo.txt` or `photo.caption`, as its prompt or after it
+    #[arg(long, value_enum, value_name = "MODE", global = true)]
+    pub captions: Option<CaptionMode>,
+
+    /// Directory with a style reference image named after each input
+    #[arg(long, value_name = "DIR", global = true)]
+    pub style_dir: Option<String>,
+
+    /// Save the live preview of the server into previews/ every this many sampling steps
+    #[arg(long, value_name = "STEPS", global = true)]
+    pub preview_every: Option<u32>,
+
+    /// How often the sampling progress is polled while it is shown, in milliseconds, 0 turns polling off
+    #[arg(long, value_name = "MS", global = true)]
+    pub progress_poll_ms: Option<u64>,
+
+    /// How long the server lists cached for option validation are used, in seconds, 0 always asks the server
+    #[arg(long, value_name = "SECONDS", global = true)]
+    pub list_cache_ttl: Option<u64>,
+
     /// Path to config file
-    #[arg(long, default_value = DEFAULT_CONFIG_PATH)]
+    #[arg(long, default_value = DEFAULT_CONFIG_PATH, global = true)]
     pub config: String,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Configuration file key set by each command line option, by argument id
+///
+/// Options missing here only exist on the command line.
+#[cfg(feature = "cli")]
+pub const CONFIG_KEYS: &[(&str, &str)] = &[
+    ("input_dir", "input_dir"),
+    ("recursive", "recursive"),
+    ("include", "include"),
+    ("exclude", "exclude"),
+    ("output_dir", "output_dir"),
+    ("output_naming", "output_naming"),
+    ("batch_size", "batch_size"),
+    ("width", "width"),
+    ("height", "height"),
+    ("model", "model"),
+    ("controlnet_module", "controlnet_module"),
+    ("controlnet_weight", "controlnet_weight"),
+    ("resize_mode", "resize_mode"),
+    ("letterbox", "letterbox"),
+    ("no_pixel_perfect", "pixel_perfect: false"),
+    ("max_processor_res", "max_processor_res"),
+    ("sampler", "sampler_name"),
+    ("scheduler", "scheduler"),
+    ("steps", "steps"),
+    ("cfg", "cfg"),
+    ("seed", "seed"),
+    ("seed_strategy", "seed_strategy"),
+    ("seed_salt", "seed_salt"),
+    ("subseed", "subseed"),
+    ("subseed_strength", "subseed_strength"),
+    ("seed_sweep", "sweep.seeds"),
+    ("max_retries", "max_retries"),
+    ("retry_delay", "retry_delay_ms"),
+    ("nan_fallback_sampler", "nan_fallback.sampler_name"),
+    ("server_restart_wait", "server_restart_wait_secs"),
+    ("batch_break", "batch_break_ms"),
+    ("adaptive_breaks", "adaptive_breaks"),
+    ("concurrency", "concurrency"),
+    ("validate_options", "validate_options"),
+    ("parameters_files", "parameters_files"),
+    ("composites", "composites"),
+    ("server_files", "server_files.enabled"),
+    ("override_setting", "override_settings"),
+    ("validate_timeout", "validate_timeout_ms"),
+    ("shuffle", "shuffle, shuffle_seed"),
+    ("stratified", "stratified"),
+    ("estimate", "estimate"),
+    ("non_interactive", "non_interactive"),
+    ("prompt_policy", "prompt_policy"),
+    ("stats_out", "stats_out"),
+    ("dead_letter", "dead_letter"),
+    ("output_format", "output_format"),
+    ("log_level", "log_level"),
+    ("log_format", "log_format"),
+    ("no_color", "color: never"),
+    ("lang", "lang"),
+    ("tui", "tui"),
+    ("slack_webhook", "notifications.slack_webhook"),
+    ("discord_webhook", "notifications.discord_webhook"),
+    ("desktop_notify", "notifications.desktop"),
+    ("mqtt_broker", "mqtt.broker"),
+    ("metrics_addr", "metrics_addr"),
+    ("max_requests_per_minute", "max_requests_per_minute"),
+    ("user_agent", "user_agent"),
+    ("api_username", "api_username"),
+    ("run_id", "run_id"),
+    ("run_dirs", "run_dirs"),
+    ("progress_file", "progress_file"),
+    ("max_batch_per_request", "max_batch_per_request"),
+    ("top_up_missing", "top_up_missing"),
+    ("allowed_hours", "schedule.allowed_hours"),
+    ("record_fixtures", "fixtures.mode: record, fixtures.dir"),
+    ("replay_fixtures", "fixtures.mode: replay, fixtures.dir"),
+    ("presets", "presets"),
+    ("sweep_weight", "sweep.controlnet_weight"),
+    ("keep_best", "selection.keep"),
+    ("min_score", "selection.min_score"),
+    ("max_rerolls", "selection.max_rerolls"),
+    ("sequence", "sequence.enabled"),
+    ("sequence_video", "sequence.enabled, sequence.video"),
+    ("timelapse", "timelapse.video"),
+    ("ramp", "ramp.enabled"),
+    ("png_compression", "png.compression"),
+    ("preview_every", "previews.every_steps"),
+    ("progress_poll_ms", "progress_poll_ms"),
+    ("list_cache_ttl", "list_cache.ttl_secs"),
+    ("style_dir", "style_reference.dir"),
+    ("emphasize", "emphasize"),
+    ("captions", "prompt_sources"),
+];
+
+/// Configuration file key set by a command line option, if it has one
+#[cfg(feature = "cli")]
+pub fn config_key(arg_id: &str) -> Option<&'static str> {
+    CONFIG_KEYS
+        .iter()
+        .find(|(id, _)| *id == arg_id)
+        .map(|(_, key)| *key)
+}
+
+#[cfg(feature = "cli")]
+impl Args {
+    /// Command line definition with the configuration file key of every
+    /// option appended to its help
+    pub fn command_with_config_keys() -> clap::Command {
+        Self::command().mut_args(|arg| match config_key(arg.get_id().as_str()) {
+            Some(key) => {
+                let help = arg.get_help().map(ToString::to_string).unwrap_or_default();
+                arg.help(format!("{} [config: {}]", help, key))
+            }
+            None => arg,
+        })
+    }
+
+    /// Parse the command line, with the configuration file keys shown in `--help`
+    pub fn parse_with_config_keys() -> Self {
+        let matches = Self::command_with_config_keys().get_matches();
+        Self::from_arg_matches(&matches).unwrap_or_else(|e| e.exit())
+    }
+
+    /// Log level steps requested with `-v` and `-q`, positive for more output
+    pub fn verbosity(&self) -> i8 {
+        self.verbose.min(i8::MAX as u8) as i8 - self.quiet.min(i8::MAX as u8) as i8
+    }
+
+    /// Log level to print with, before the configuration file is read
+    pub fn log_level(&self) -> LogLevel {
+        self.log_level.unwrap_or_default().adjusted(self.verbosity())
+    }
+
+    /// Color mode to print with, before the configuration file is read
+    pub fn color(&self) -> ColorMode {
+        if self.no_color { ColorMode::Never } else { ColorMode::Auto }
+    }
+}
+
+/// Subcommands, `generate` being the default when none is given
+#[cfg(feature = "cli")]
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Generate images for every input of the input directory
+    Generate,
+    /// Continue an interrupted run, skipping inputs its job queue already finished
+    Resume,
+    /// Check the configured options against the Stable Diffusion server
+    Validate,
+    /// Check the server, its extensions and models, and the output directory, with hints for fixing problems
+    Doctor,
+    /// Print the manual page in roff format, e.g. `urasoe man > urasoe.1`
+    Man,
+    /// Time generating one input with combinations of samplers, steps and sizes
+    Benchmark {
+        /// Input image used for every generation
+        image: PathBuf,
+
+        /// Samplers to compare, comma separated, defaults to the configured sampler
+        #[arg(long, value_delimiter = ',')]
+        samplers: Vec<String>,
+
+        /// Step counts to compare, comma separated, defaults to the configured steps
+        #[arg(long, value_delimiter = ',')]
+        step_counts: Vec<u32>,
+
+        /// Sizes to compare as WIDTHxHEIGHT, comma separated, defaults to the configured size
+        #[arg(long, value_delimiter = ',')]
+        sizes: Vec<ImageSize>,
+
+        /// Generations per combination, averaged
+        #[arg(long, default_value_t = 1)]
+        repeat: u32,
+    },
+    /// Generate one input with every combination of the values of one or two parameters into a labeled sheet
+    Compare {
+        /// Input image used for every generation
+        image: PathBuf,
+
+        /// Parameter and values laid out in columns, e.g. `cfg=5,7,9`
+        #[arg(long)]
+        x: Axis,
+
+        /// Parameter and values laid out in rows, e.g. `weight=0.4,0.8,1.2`
+        #[arg(long)]
+        y: Option<Axis>,
+    },
+    /// Generate the images of a metadata file again, command line options overriding its settings
+    Regenerate {
+        /// Metadata file written next to generated images, `<input>-metadata.json`
+        metadata: PathBuf,
+
+        /// Input image to use instead of the recorded source image
+        #[arg(long)]
+        image: Option<PathBuf>,
+    },
+    /// Serve a web gallery of the output directory for reviewing results in a browser
+    #[cfg(feature = "server")]
+    Serve {
+        /// Address to listen on
+        #[arg(long, default_value = crate::gallery::DEFAULT_ADDRESS)]
+        addr: String,
+    },
+    /// Upscale the generated images of an earlier run with the upscaler of the configuration
+    Upscale {
+        /// Output directory of the run
+        run_dir: PathBuf,
+        /// Upscale images again that already have an upscaled companion
+        #[arg(long)]
+        force: bool,
+    },
+    /// Generate from an image read on standard input, writing the first result to standard output
+    Pipe,
+    /// List the checkpoints, ControlNet models, modules, samplers and schedulers the server offers
+    Models {
+        /// Print the lists as JSON instead of a table
+        #[arg(long)]
+        json: bool,
+    },
+    /// List past generations of the output directory, `--model` narrowing them to a checkpoint or ControlNet model
+    History {
+        /// Only generations whose prompt contains this text
+        #[arg(long)]
+        prompt: Option<String>,
+        /// Only failed inputs
+        #[arg(long)]
+        failed: bool,
+        /// Print the generations as JSON instead of a table
+        #[arg(long)]
+        json: bool,
+    },
+    /// Sum up the metadata of an output directory by checkpoint, ControlNet model, prompt and day
+    Rollup {
+        /// Output directory of the runs, defaults to the output directory of the configuration
+        dir: Option<PathBuf>,
+        /// Print the summary as JSON instead of a table
+        #[arg(long)]
+        json: bool,
+        /// Also write the summary to this file, as CSV or JSON depending on the extension
+        #[arg(long, value_name = "FILE")]
+        out: Option<PathBuf>,
+    },
+    /// Report the resolution, format and size of each input and flag problems before generating
+    Inspect {
+        /// Directory of the input images, defaults to the input directory of the configuration
+        dir: Option<PathBuf>,
+        /// Print the reports as JSON instead of a table
+        #[arg(long)]
+        json: bool,
+    },
+    /// Write a configuration file with the default settings
+    Init {
+        /// Overwrite an existing configuration file
+        #[arg(long)]
+        force: bool,
+    },
+    /// Add inputs to the job queue of the output directory, where a running generation picks them up
+    Enqueue {
+        /// Input images to add
+        #[arg(required = true)]
+        images: Vec<PathBuf>,
+        /// Priority of the inputs, higher ones are processed before the rest of the queue
+        #[arg(long, default_value_t = 0, allow_hyphen_values = true)]
+        priority: i32,
+    },
+    /// Remove the job queue and failed inputs of the output directory
+    Clean {
+        /// Remove the whole output directory, including generated images
+        #[arg(long)]
+        all: bool,
+    },
+    /// Run persistently, processing job files dropped into a spool directory
+    Daemon {
+        /// Directory watched for job description files
+        #[arg(long)]
+        spool_dir: Option<String>,
+
+        /// How often the spool directory is checked, in milliseconds
+        #[arg(long)]
+        poll_interval: Option<u64>,
+
+        /// Address of the gRPC job control service, needs the grpc feature
+        #[arg(long)]
+        grpc_addr: Option<String>,
+    },
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -98,14 +946,32 @@ pub struct Config {
     #[serde(default = "default_input_dir")]
     /// Directory containing input images
     pub input_dir: String,
+    #[serde(default)]
+    /// Whether the subdirectories of the input directory are processed as well, mirrored in the output directory
+    pub recursive: bool,
+    #[serde(default)]
+    /// Glob patterns of the inputs to process, relative to the input directory; all when empty
+    pub include: Vec<String>,
+    #[serde(default)]
+    /// Glob patterns of the inputs to leave out, relative to the input directory
+    pub exclude: Vec<String>,
     #[serde(default = "default_output_dir")]
     /// Directory where output images will be saved
     pub output_dir: String,
+    #[serde(default)]
+    /// How the output folder of each input is named
+    pub output_naming: OutputNaming,
 
     // Image generation settings
     #[serde(default = "default_batch_size")]
     /// Number of images to generate for each input
     pub batch_size: u32,
+    #[serde(default)]
+    /// Largest batch requested at once; bigger batches are split into sequential requests
+    pub max_batch_per_request: Option<u32>,
+    #[serde(default)]
+    /// Request the images missing from a response with fewer images than asked for once more
+    pub top_up_missing: bool,
     #[serde(default = "default_width")]
     /// Width of generated images
     pub width: u32,
@@ -118,6 +984,21 @@ pub struct Config {
     #[serde(default = "default_cfg")]
     /// CFG scale for generation
     pub cfg: f32,
+    #[serde(default = "default_seed")]
+    /// Seed for generation, -1 lets the server pick a random seed per request
+    pub seed: i64,
+    #[serde(default)]
+    /// How the seed of each input is chosen
+    pub seed_strategy: SeedStrategy,
+    #[serde(default)]
+    /// Text hashed along with each input by the from_input_hash seed strategy
+    pub seed_salt: String,
+    #[serde(default = "default_seed")]
+    /// Variation seed mixed into the seed by `subseed_strength`, -1 lets the server pick one
+    pub subseed: i64,
+    #[serde(default)]
+    /// How much of the variation seed is mixed in, 0 for none
+    pub subseed_strength: f32,
 
     // ControlNet settings
     #[serde(default = "default_model")]
@@ -129,6 +1010,21 @@ pub struct Config {
     #[serde(default = "default_controlnet_weight")]
     /// ControlNet weight (0.0-1.0)
     pub controlnet_weight: f32,
+    #[serde(default)]
+    /// ControlNet units applied together, replacing the model, module and weight above when given
+    pub controlnet_units: Vec<ControlNetUnitConfig>,
+    #[serde(default)]
+    /// How the ControlNet extension fits the control image to the size of the generated images
+    pub resize_mode: ResizeMode,
+    #[serde(default)]
+    /// Pad the control image with black to the aspect ratio of the generated images before sending it
+    pub letterbox: bool,
+    #[serde(default = "default_pixel_perfect")]
+    /// Let the server pick the preprocessor resolution of the ControlNet unit above from the image size
+    pub pixel_perfect: bool,
+    #[serde(default = "default_max_processor_res")]
+    /// Highest preprocessor resolution matched to the control image of units without pixel perfect
+    pub max_processor_res: u32,
 
     // Sampler settings
     #[serde(default = "default_sampler_name")]
@@ -147,6 +1043,39 @@ pub struct Config {
     #[serde(default = "default_sd_api_url")]
     /// URL for the Stable Diffusion API
     pub sd_api_url: String,
+    #[serde(default)]
+    /// Additional Stable Diffusion API URLs, each served by its own worker
+    pub extra_api_urls: Vec<String>,
+    #[serde(default)]
+    /// Web UI settings sent as override_settings with every generation request
+    pub override_settings: serde_json::Map<String, serde_json::Value>,
+    #[serde(default)]
+    /// Settings added to override_settings for the requests to one API URL, e.g. the VAE it has installed
+    pub backend_override_settings: BTreeMap<String, serde_json::Map<String, serde_json::Value>>,
+    #[serde(default)]
+    /// Maximum number of generation requests per minute across all backends
+    pub max_requests_per_minute: Option<u32>,
+    #[serde(default = "default_user_agent")]
+    /// User-Agent header of requests to the API
+    pub user_agent: String,
+    #[serde(default)]
+    /// User name for basic authentication, URASOE_API_USERNAME when unset
+    pub api_username: Option<String>,
+    #[serde(default)]
+    /// Password for basic authentication, URASOE_API_PASSWORD when unset
+    pub api_password: Option<String>,
+    #[serde(default)]
+    /// Bearer token sent instead of a user name and password, URASOE_API_TOKEN when unset
+    pub api_token: Option<String>,
+    #[serde(default)]
+    /// Identifier of the run sent with every request, a new one is generated for every run when unset
+    pub run_id: Option<String>,
+    #[serde(default)]
+    /// Give every run a directory of its own in the output directory, named after the run id
+    pub run_dirs: bool,
+    #[serde(default)]
+    /// JSON file the progress of the run is kept in, relative to the output directory
+    pub progress_file: Option<String>,
 
     // Prompt settings
     #[serde(default = "default_prompt")]
@@ -155,6 +1084,12 @@ pub struct Config {
     #[serde(default = "default_negative_prompt")]
     /// Negative prompt to exclude certain features
     pub negative_prompt: String,
+    #[serde(default)]
+    /// Strategies deciding the prompts of each input, applied in order, the prompts above when empty
+    pub prompt_sources: Vec<PromptSourceConfig>,
+    #[serde(default)]
+    /// Attention weight of terms of the prompt, rendered as `(term:weight)` in the prompt sent
+    pub emphasize: BTreeMap<String, f32>,
 
     // Error handling settings
     #[serde(default = "default_max_retries")]
@@ -162,10 +1097,33 @@ pub struct Config {
     pub max_retries: u32,
     #[serde(default = "default_retry_delay")]
     /// Delay between retries in milliseconds
-    pub retry_delay_ms: u64,    // Batch processing settings
+    pub retry_delay_ms: u64,
+    #[serde(default)]
+    /// Regular expressions of additional errors that warrant a retry
+    pub retry_on: Vec<String>,
+    #[serde(default = "default_reload_on_cuda_error")]
+    /// Unload and reload the checkpoint before the final retry when CUDA errors persist
+    pub reload_on_cuda_error: bool,
+    #[serde(default)]
+    /// Also interrupt the running generation when recovering from CUDA errors
+    pub interrupt_on_cuda_error: bool,
+    #[serde(default)]
+    /// Settings to retry with once when an image produces NaN tensors
+    pub nan_fallback: NanFallbackConfig,
+    #[serde(default = "default_server_restart_wait_secs")]
+    /// How long to wait for the server to come back after losing the connection mid-run, in seconds, 0 never waits
+    pub server_restart_wait_secs: u64,
+
+    // Batch processing settings
     #[serde(default = "default_batch_break")]
     /// Break duration between batches in milliseconds
     pub batch_break_ms: u64,
+    #[serde(default)]
+    /// Take breaks only when generation times degrade instead of after every batch
+    pub adaptive_breaks: bool,
+    #[serde(default = "default_concurrency")]
+    /// Inputs in flight at the same time on each backend, each worker taking its own breaks
+    pub concurrency: usize,
 
     // API validation settings
     #[serde(default = "default_validate_options")]
@@ -174,11 +1132,150 @@ pub struct Config {
     #[serde(default = "default_validate_timeout")]
     /// Timeout for option validation requests in milliseconds
     pub validate_timeout_ms: u64,
+    #[serde(default = "default_estimate")]
+    /// Whether to generate one sample first and estimate the duration and size of the run
+    pub estimate: bool,
+
+    // Queue settings
+    #[serde(default)]
+    /// Process inputs in random order
+    pub shuffle: bool,
+    #[serde(default)]
+    /// Seed for the random order, a new seed is picked for every run when unset
+    pub shuffle_seed: Option<u64>,
+    #[serde(default)]
+    /// Interleave inputs from different subdirectories
+    pub stratified: bool,
+    #[serde(default)]
+    /// Path of the persistent job queue file, defaults to a file inside output_dir
+    pub queue_file: Option<String>,
+
+    // Reporting settings
+    #[serde(default)]
+    /// File to write processing statistics to, as JSON or CSV depending on the extension
+    pub stats_out: Option<String>,
+    #[serde(default)]
+    /// Whether to copy or move failed inputs into the `_failed` folder of output_dir
+    pub dead_letter: DeadLetterMode,
+    #[serde(default)]
+    /// Print the result of the run as JSON on standard output, logging to standard error
+    pub output_format: OutputFormat,
+    #[serde(default = "default_parameters_files")]
+    /// Whether to write `<stem>-<n>-parameters.txt` next to each image, in the infotext format of the Web UI
+    pub parameters_files: bool,
+    #[serde(default)]
+    /// Whether to write `<stem>-<n>-composite.png` next to each image, the input and the image side by side
+    pub composites: bool,
+    #[serde(default)]
+    /// Where to send a summary when the run finishes
+    pub notifications: NotificationConfig,
+    #[serde(default)]
+    /// MQTT broker to publish results to, and for the daemon to take jobs from
+    pub mqtt: MqttConfig,
+    #[serde(default)]
+    /// Address to serve Prometheus metrics on at `/metrics`, disabled when unset
+    pub metrics_addr: Option<String>,
+
+    // Scheduling settings
+    #[serde(default)]
+    /// When images may be generated, the run pauses between images outside these hours
+    pub schedule: ScheduleConfig,
+    #[serde(default)]
+    /// Spool directory and polling settings of `urasoe daemon`
+    pub daemon: DaemonConfig,
+    #[serde(default)]
+    /// Shell commands run before and after each image and the whole run
+    pub hooks: HooksConfig,
+    #[serde(default)]
+    /// Rhai scripts filtering inputs, changing API requests and post-processing outputs
+    pub plugins: Vec<String>,
+    #[serde(default)]
+    /// Recording API responses to disk, or replaying them instead of calling the API
+    pub fixtures: FixtureConfig,
+    #[serde(default)]
+    /// Preset files, each run over the same inputs with its settings on top of this configuration
+    pub presets: Vec<String>,
+    #[serde(default)]
+    /// Values to generate every input with, keeping the seed fixed
+    pub sweep: SweepConfig,
+    #[serde(default)]
+    /// Scoring the images of each input and keeping only the best ones
+    pub selection: SelectionConfig,
+    #[serde(default)]
+    /// Processing the inputs as the numbered frames of a video
+    pub sequence: SequenceConfig,
+    #[serde(default)]
+    /// Assembling the images of a run into a timelapse video
+    pub timelapse: TimelapseConfig,
+    #[serde(default)]
+    /// Ramping the batch size up over the first images of each backend
+    pub ramp: RampConfig,
+    #[serde(default)]
+    /// Encoding of the saved PNG images
+    pub png: PngConfig,
+    #[serde(default)]
+    /// Downloading the images saved by the server instead of receiving them in the response
+    pub server_files: ServerFilesConfig,
+    #[serde(default)]
+    /// Saving the live previews of the server while generating
+    pub previews: PreviewConfig,
+    #[serde(default = "default_poll_interval")]
+    /// How often the sampling progress of the backends is polled while it is shown, in milliseconds; 0 turns it off
+    pub progress_poll_ms: u64,
+    #[serde(default)]
+    /// Caching the lists of the server used for option validation
+    pub list_cache: ListCacheConfig,
+    #[serde(default)]
+    /// Upscaler used by the upscale command
+    pub upscale: UpscaleConfig,
+    #[serde(default)]
+    /// Pairing every input with a style reference image
+    pub style_reference: StyleReferenceConfig,
 
-    // Printing visibility
+    // Logging settings
+    #[serde(default)]
+    /// Most verbose log level printed to the console
+    pub log_level: LogLevel,
+    #[serde(default)]
+    /// Log output format, colored text or one JSON object per line
+    pub log_format: LogFormat,
+    #[serde(default)]
+    /// When log lines are colored: auto, always or never
+    pub color: ColorMode,
+    #[serde(default)]
+    /// Language of console messages, from the LC_ALL, LC_MESSAGES or LANG environment variables when not set
+    pub lang: Option<Lang>,
+    #[serde(default)]
+    /// Show a live dashboard in the terminal instead of log lines
+    pub tui: bool,
+
+    // Confirmation settings
+    #[serde(default)]
+    /// Never wait for answers on standard input, also implied when it is not a terminal
+    pub non_interactive: bool,
+    #[serde(default)]
+    /// Answer to questions when running non-interactively
+    pub prompt_policy: PromptPolicy,
+
+    // Run mode
+    #[serde(skip)]
+    /// If true, continue without asking for confirmation
+    pub assume_yes: bool,
+    #[serde(skip)]
+    /// If true, continue the job queue of a previous run instead of starting a new one
+    pub resume: bool,
     #[serde(skip)]
-    /// If true, enables verbose printing
-    pub verbose: bool,
+    /// If true, start a new job queue even when the previous run was interrupted
+    pub force: bool,
+}
+
+/// Name of a preset, taken from its file name
+pub fn preset_name(preset_path: &Path) -> String {
+    preset_path
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string()
 }
 
 // Default functions for Config - These values match those in urasoe.config.yml
@@ -210,6 +1307,10 @@ pub fn default_steps() -> u32 {
 pub fn default_cfg() -> f32 {
     7.5
 }
+/// Default seed - -1 for a random seed per request
+pub fn default_seed() -> i64 {
+    -1
+}
 /// Default ControlNet model - "canny" from config file
 pub fn default_model() -> String {
     "canny".to_string()
@@ -222,6 +1323,18 @@ pub fn default_controlnet_module() -> String {
 pub fn default_controlnet_weight() -> f32 {
     0.8
 }
+/// Default end of the guidance of a ControlNet unit - 1.0, the last sampling step
+pub fn default_guidance_end() -> f32 {
+    1.0
+}
+/// Default preprocessor resolution - pixel perfect, picked by the server
+pub fn default_pixel_perfect() -> bool {
+    true
+}
+/// Default highest preprocessor resolution matched to the control image - 2048
+pub fn default_max_processor_res() -> u32 {
+    2048
+}
 /// Default sampler name - "DPM++ 2M" from config file
 pub fn default_sampler_name() -> String {
     "DPM++ 2M".to_string()
@@ -234,10 +1347,18 @@ pub fn default_sampler_index() -> String {
 pub fn default_checkpoint_model() -> String {
     "realisticVisionV51_v51VAE".to_string()
 }
+/// Default CUDA recovery - reload the checkpoint, as in config file
+pub fn default_reload_on_cuda_error() -> bool {
+    true
+}
 /// Default Stable Diffusion API URL - "http://127.0.0.1:7860/" from config file
 pub fn default_sd_api_url() -> String {
     "http://127.0.0.1:7860/".to_string()
 }
+/// Default user agent - "urasoe/<version>"
+pub fn default_user_agent() -> String {
+    DEFAULT_USER_AGENT.to_string()
+}
 /// Default prompt - from config file
 pub fn default_prompt() -> String {
     "karate master in dojo, high detail, realistic photography".to_string()
@@ -254,16 +1375,35 @@ pub fn default_max_retries() -> u32 {
 pub fn default_retry_delay() -> u64 {
     10000
 }
+/// Default wait for a restarted server - 300 seconds
+pub fn default_server_restart_wait_secs() -> u64 {
+    300
+}
 /// Default batch break duration - 15000ms from config file
 pub fn default_batch_break() -> u64 {
     15000
 }
 
+/// Default inputs in flight on each backend - 1, one after another
+pub fn default_concurrency() -> usize {
+    1
+}
+
+/// Default for writing parameters files - true
+pub fn default_parameters_files() -> bool {
+    true
+}
+
 /// Default for validating options - true from config file
 pub fn default_validate_options() -> bool {
     true
 }
 
+/// Default for estimating the run - true from config file
+pub fn default_estimate() -> bool {
+    true
+}
+
 /// Default timeout for option validation - 5000ms from config file
 pub fn default_validate_timeout() -> u64 {
     5000
@@ -275,43 +1415,318 @@ impl Config {
         if let Ok(file) = fs::read_to_string(config_path) {
             serde_yaml::from_str(&file).context("Failed to parse config file")
         } else {
-            println!("{} {}", "Config file not found:".yellow(), config_path);
-            println!("{}", "Using default configuration".yellow());
+            warn!("{}", tr_args(Msg::ConfigNotFound, &[&config_path]).yellow());
+            warn!("{}", tr(Msg::UsingDefaultConfig).yellow());
             Ok(Config {
                 input_dir: default_input_dir(),
+                recursive: false,
+                include: Vec::new(),
+                exclude: Vec::new(),
                 output_dir: default_output_dir(),
+                output_naming: OutputNaming::Stem,
                 batch_size: default_batch_size(),
+                max_batch_per_request: None,
+                top_up_missing: false,
                 width: default_width(),
                 height: default_height(),
                 steps: default_steps(),
                 cfg: default_cfg(),
+                seed: default_seed(),
+                seed_strategy: SeedStrategy::Fixed,
+                seed_salt: String::new(),
+                subseed: default_seed(),
+                subseed_strength: 0.0,
                 model: default_model(),
                 controlnet_module: default_controlnet_module(),
                 controlnet_weight: default_controlnet_weight(),
+                controlnet_units: Vec::new(),
+                resize_mode: ResizeMode::CropAndResize,
+                letterbox: false,
+                pixel_perfect: default_pixel_perfect(),
+                max_processor_res: default_max_processor_res(),
                 sampler_name: default_sampler_name(),
                 scheduler: default_sampler_index(),
                 checkpoint_model: default_checkpoint_model(),
                 sd_api_url: default_sd_api_url(),
+                extra_api_urls: Vec::new(),
+                override_settings: serde_json::Map::new(),
+                backend_override_settings: BTreeMap::new(),
+                max_requests_per_minute: None,
+                user_agent: default_user_agent(),
+                api_username: None,
+                api_password: None,
+                api_token: None,
+                run_id: None,
+                run_dirs: false,
+                progress_file: None,
                 prompt: default_prompt(),
-                negative_prompt: default_negative_prompt(),                max_retries: default_max_retries(),
+                negative_prompt: default_negative_prompt(),
+                prompt_sources: Vec::new(),
+                emphasize: BTreeMap::new(),
+                max_retries: default_max_retries(),
                 retry_delay_ms: default_retry_delay(),
+                retry_on: Vec::new(),
+                reload_on_cuda_error: default_reload_on_cuda_error(),
+                interrupt_on_cuda_error: false,
+                nan_fallback: NanFallbackConfig::default(),
+                server_restart_wait_secs: default_server_restart_wait_secs(),
                 batch_break_ms: default_batch_break(),
+                adaptive_breaks: false,
+                concurrency: default_concurrency(),
                 validate_options: default_validate_options(),
                 validate_timeout_ms: default_validate_timeout(),
-                verbose: false,
+                estimate: default_estimate(),
+                shuffle: false,
+                shuffle_seed: None,
+                stratified: false,
+                queue_file: None,
+                stats_out: None,
+                dead_letter: DeadLetterMode::Off,
+                output_format: OutputFormat::Text,
+                parameters_files: default_parameters_files(),
+                composites: false,
+                notifications: NotificationConfig::default(),
+                mqtt: MqttConfig::default(),
+                metrics_addr: None,
+                schedule: ScheduleConfig::default(),
+                daemon: DaemonConfig::default(),
+                hooks: HooksConfig::default(),
+                plugins: Vec::new(),
+                fixtures: FixtureConfig::default(),
+                presets: Vec::new(),
+                sweep: SweepConfig::default(),
+                selection: SelectionConfig::default(),
+                sequence: SequenceConfig::default(),
+                timelapse: TimelapseConfig::default(),
+                ramp: RampConfig::default(),
+                png: PngConfig::default(),
+                server_files: ServerFilesConfig::default(),
+                previews: PreviewConfig::default(),
+                progress_poll_ms: default_poll_interval(),
+                list_cache: ListCacheConfig::default(),
+                upscale: UpscaleConfig::default(),
+                style_reference: StyleReferenceConfig::default(),
+                log_level: LogLevel::Info,
+                log_format: LogFormat::Text,
+                color: ColorMode::Auto,
+                lang: None,
+                tui: false,
+                non_interactive: false,
+                prompt_policy: PromptPolicy::Continue,
+                assume_yes: false,
+                resume: false,
+                force: false,
             })
         }
-    } // Apply command line arguments over config file values
+    }
+
+    /// Configuration of one preset of a multi-preset run
+    ///
+    /// The top-level settings of the preset file replace those of this
+    /// configuration, and command line arguments are applied on top again.
+    /// Unless the preset sets them, output and statistics go to files named
+    /// after the preset, so the runs of different presets do not mix.
+    ///
+    /// # Arguments
+    /// * `preset_path` - YAML file with the settings of the preset
+    /// * `args` - Command line arguments
+    #[cfg(feature = "cli")]
+    pub fn with_preset(&self, preset_path: &Path, args: &Args) -> Result<Config> {
+        let text = fs::read_to_string(preset_path)
+            .context(format!("Failed to read preset: {}", preset_path.display()))?;
+        let preset: serde_yaml::Mapping = serde_yaml::from_str::<Option<_>>(&text)
+            .context(format!("Failed to parse preset: {}", preset_path.display()))?
+            .unwrap_or_default();
+
+        let mut merged = match serde_yaml::to_value(self)? {
+            serde_yaml::Value::Mapping(mapping) => mapping,
+            _ => serde_yaml::Mapping::new(),
+        };
+        for (key, value) in &preset {
+            merged.insert(key.clone(), value.clone());
+        }
+        let mut config: Config = serde_yaml::from_value(serde_yaml::Value::Mapping(merged))
+            .context(format!("Invalid settings in preset: {}", preset_path.display()))?;
+        config.assume_yes = self.assume_yes;
+        config.resume = self.resume;
+        config.force = self.force;
+        config.apply_args(args);
+        config.presets.clear();
+        // The level of this configuration already has -v and -q applied
+        if !preset.contains_key("log_level") {
+            config.log_level = self.log_level;
+        }
+
+        let name = preset_name(preset_path);
+        if !preset.contains_key("output_dir") {
+            config.output_dir = Path::new(&self.output_dir).join(&name).to_string_lossy().to_string();
+        }
+        if !preset.contains_key("stats_out")
+            && let Some(stats_out) = &config.stats_out
+        {
+            let stats_out = Path::new(stats_out);
+            let mut file_name = stats_out.file_stem().unwrap_or_default().to_os_string();
+            file_name.push(format!(".{}", name));
+            if let Some(extension) = stats_out.extension() {
+                file_name.push(".");
+                file_name.push(extension);
+            }
+            config.stats_out = Some(stats_out.with_file_name(file_name).to_string_lossy().to_string());
+        }
+        Ok(config)
+    }
+
+    /// All configured Stable Diffusion API URLs, without duplicates
+    pub fn api_urls(&self) -> Vec<String> {
+        let mut urls = vec![self.sd_api_url.clone()];
+        for url in &self.extra_api_urls {
+            if !urls.contains(url) {
+                urls.push(url.clone());
+            }
+        }
+        urls
+    }
+
+    /// Credentials for the API, each taken from the environment when not configured
+    ///
+    /// A bearer token is preferred over a user name and password.
+    ///
+    /// # Returns
+    /// The credentials, `None` for an API without authentication
+    pub fn api_auth(&self) -> Option<ApiAuth> {
+        let setting = |value: &Option<String>, variable: &str| {
+            value.clone().or_else(|| std::env::var(variable).ok()).filter(|value| !value.is_empty())
+        };
+        if let Some(token) = setting(&self.api_token, API_TOKEN_ENV) {
+            return Some(ApiAuth::Bearer(token));
+        }
+        let username = setting(&self.api_username, API_USERNAME_ENV)?;
+        Some(ApiAuth::Basic {
+            username,
+            password: setting(&self.api_password, API_PASSWORD_ENV).unwrap_or_default(),
+        })
+    }
+
+    /// User agent, run id and credentials sent with every request to the API
+    ///
+    /// # Arguments
+    /// * `run_id` - Identifier of the run, `None` outside runs
+    pub fn request_identity(&self, run_id: Option<&str>) -> RequestIdentity {
+        RequestIdentity::new(&self.user_agent, run_id).with_auth(self.api_auth().as_ref())
+    }
+
+    /// Settings added to override_settings for the requests to one backend
+    ///
+    /// URLs are compared without their trailing slash.
+    ///
+    /// # Arguments
+    /// * `api_url` - API URL of the backend
+    pub fn backend_settings(&self, api_url: &str) -> serde_json::Map<String, serde_json::Value> {
+        self.backend_override_settings
+            .iter()
+            .find(|(url, _)| url.trim_end_matches('/') == api_url.trim_end_matches('/'))
+            .map(|(_, settings)| settings.clone())
+            .unwrap_or_default()
+    }
+
+    /// ControlNet units of the generation, the single unit of `model` unless `controlnet_units` are given
+    pub fn active_controlnet_units(&self) -> Vec<ControlNetUnitConfig> {
+        if !self.controlnet_units.is_empty() {
+            return self.controlnet_units.clone();
+        }
+        vec![ControlNetUnitConfig {
+            model: self.model.clone(),
+            module: self.controlnet_module.clone(),
+            weight: self.controlnet_weight,
+            guidance_start: 0.0,
+            guidance_end: default_guidance_end(),
+            pixel_perfect: self.pixel_perfect,
+            processor_res: None,
+        }]
+    }
+
+    /// Name of the output folder of an input, as chosen by `output_naming`
+    pub fn output_name(&self, image_path: &Path) -> String {
+        self.output_naming.name_for(image_path, Path::new(&self.input_dir))
+    }
+
+    /// Folders of an input below the input directory, which recursive runs mirror in the output directory
+    pub fn mirrored_dir(&self, image_path: &Path) -> PathBuf {
+        if !self.recursive {
+            return PathBuf::new();
+        }
+        image_path
+            .parent()
+            .and_then(|parent| parent.strip_prefix(&self.input_dir).ok())
+            .map(Path::to_path_buf)
+            .unwrap_or_default()
+    }
+
+    /// Output folder holding the images of an input
+    pub fn output_folder(&self, image_path: &Path) -> PathBuf {
+        Path::new(&self.output_dir)
+            .join(self.mirrored_dir(image_path))
+            .join(self.output_name(image_path))
+    }
+
+    /// Input images of the run, as chosen by `recursive`, `include` and `exclude`
+    pub fn input_images(&self) -> Result<Vec<PathBuf>> {
+        let filter = InputFilter::new(&self.include, &self.exclude)?;
+        ImageProcessor::find_images(Path::new(&self.input_dir), self.recursive, &filter)
+    }
+
+    /// Input path the outputs of an input are named after, its file name replaced by the output name
+    ///
+    /// Sinks name the outputs after the stem of the path they are given.
+    pub fn named_input(&self, image_path: &Path) -> PathBuf {
+        if self.output_naming == OutputNaming::Stem {
+            return image_path.to_path_buf();
+        }
+        let mut name = self.output_name(image_path);
+        if let Some(extension) = image_path.extension() {
+            name = format!("{}.{}", name, extension.to_string_lossy());
+        }
+        image_path.with_file_name(name)
+    }
+
+    /// Path of the persistent job queue file for this configuration
+    pub fn queue_path(&self) -> PathBuf {
+        match &self.queue_file {
+            Some(queue_file) => PathBuf::from(queue_file),
+            None => Path::new(&self.output_dir).join(DEFAULT_QUEUE_FILE),
+        }
+    }
+
+    // Apply command line arguments over config file values
+    #[cfg(feature = "cli")]
     pub fn apply_args(&mut self, args: &Args) {
         if let Some(input_dir) = &args.input_dir {
             self.input_dir = input_dir.clone();
         }
+        if args.recursive {
+            self.recursive = true;
+        }
+        if !args.include.is_empty() {
+            self.include = args.include.clone();
+        }
+        if !args.exclude.is_empty() {
+            self.exclude = args.exclude.clone();
+        }
         if let Some(output_dir) = &args.output_dir {
             self.output_dir = output_dir.clone();
         }
+        if let Some(output_naming) = args.output_naming {
+            self.output_naming = output_naming;
+        }
         if let Some(batch_size) = args.batch_size {
             self.batch_size = batch_size;
         }
+        if let Some(max_batch_per_request) = args.max_batch_per_request {
+            self.max_batch_per_request = Some(max_batch_per_request);
+        }
+        if args.top_up_missing {
+            self.top_up_missing = true;
+        }
         if let Some(width) = args.width {
             self.width = width;
         }
@@ -327,6 +1742,21 @@ impl Config {
         if let Some(controlnet_weight) = args.controlnet_weight {
             self.controlnet_weight = controlnet_weight;
         }
+        if let Some(resize_mode) = args.resize_mode {
+            self.resize_mode = resize_mode;
+        }
+        if args.letterbox {
+            self.letterbox = true;
+        }
+        if args.no_pixel_perfect {
+            self.pixel_perfect = false;
+            for unit in &mut self.controlnet_units {
+                unit.pixel_perfect = false;
+            }
+        }
+        if let Some(max_processor_res) = args.max_processor_res {
+            self.max_processor_res = max_processor_res;
+        }
         if let Some(sampler) = &args.sampler {
             self.sampler_name = sampler.clone();
         }
@@ -339,19 +1769,232 @@ impl Config {
         if let Some(cfg) = args.cfg {
             self.cfg = cfg;
         }
+        if let Some(seed) = args.seed {
+            self.seed = seed;
+        }
+        if let Some(seed_strategy) = args.seed_strategy {
+            self.seed_strategy = seed_strategy;
+        }
+        if let Some(seed_salt) = &args.seed_salt {
+            self.seed_salt = seed_salt.clone();
+        }
+        if let Some(subseed) = args.subseed {
+            self.subseed = subseed;
+        }
+        if let Some(subseed_strength) = args.subseed_strength {
+            self.subseed_strength = subseed_strength;
+        }
+        if let Some(seed_sweep) = args.seed_sweep {
+            self.sweep.seeds = seed_sweep;
+        }
         if let Some(max_retries) = args.max_retries {
             self.max_retries = max_retries;
         }
         if let Some(retry_delay) = args.retry_delay {
             self.retry_delay_ms = retry_delay;
-        }        if let Some(batch_break) = args.batch_break {
+        }
+        if let Some(nan_fallback_sampler) = &args.nan_fallback_sampler {
+            self.nan_fallback.enabled = true;
+            self.nan_fallback.sampler_name = Some(nan_fallback_sampler.clone());
+        }
+        if let Some(server_restart_wait) = args.server_restart_wait {
+            self.server_restart_wait_secs = server_restart_wait;
+        }
+        if let Some(batch_break) = args.batch_break {
             self.batch_break_ms = batch_break;
         }
+        if args.adaptive_breaks {
+            self.adaptive_breaks = true;
+        }
+        if let Some(concurrency) = args.concurrency {
+            self.concurrency = concurrency;
+        }
+        if let Some(parameters_files) = args.parameters_files {
+            self.parameters_files = parameters_files;
+        }
+        if args.composites {
+            self.composites = true;
+        }
+        if args.server_files {
+            self.server_files.enabled = true;
+        }
+        for setting in &args.override_setting {
+            self.override_settings.insert(setting.key.clone(), setting.value.clone());
+        }
         if let Some(validate_options) = args.validate_options {
             self.validate_options = validate_options;
         }
         if let Some(validate_timeout) = args.validate_timeout {
             self.validate_timeout_ms = validate_timeout;
         }
+        if let Some(shuffle) = args.shuffle {
+            self.shuffle = true;
+            if shuffle.is_some() {
+                self.shuffle_seed = shuffle;
+            }
+        }
+        if args.stratified {
+            self.stratified = true;
+        }
+        if let Some(estimate) = args.estimate {
+            self.estimate = estimate;
+        }
+        if args.yes {
+            self.assume_yes = true;
+        }
+        if args.resume {
+            self.resume = true;
+        }
+        if args.force {
+            self.force = true;
+        }
+        if args.non_interactive {
+            self.non_interactive = true;
+        }
+        if let Some(prompt_policy) = args.prompt_policy {
+            self.prompt_policy = prompt_policy;
+        }
+        if let Some(stats_out) = &args.stats_out {
+            self.stats_out = Some(stats_out.clone());
+        }
+        if let Some(dead_letter) = args.dead_letter {
+            self.dead_letter = dead_letter;
+        }
+        if let Some(output_format) = args.output_format {
+            self.output_format = output_format;
+        }
+        if let Some(slack_webhook) = &args.slack_webhook {
+            self.notifications.slack_webhook = Some(slack_webhook.clone());
+        }
+        if let Some(discord_webhook) = &args.discord_webhook {
+            self.notifications.discord_webhook = Some(discord_webhook.clone());
+        }
+        if args.desktop_notify {
+            self.notifications.desktop = true;
+        }
+        if let Some(mqtt_broker) = &args.mqtt_broker {
+            self.mqtt.broker = Some(mqtt_broker.clone());
+        }
+        if let Some(metrics_addr) = &args.metrics_addr {
+            self.metrics_addr = Some(metrics_addr.clone());
+        }
+        if let Some(max_requests_per_minute) = args.max_requests_per_minute {
+            self.max_requests_per_minute = Some(max_requests_per_minute);
+        }
+        if let Some(user_agent) = &args.user_agent {
+            self.user_agent = user_agent.clone();
+        }
+        if let Some(api_username) = &args.api_username {
+            self.api_username = Some(api_username.clone());
+        }
+        if let Some(run_id) = &args.run_id {
+            self.run_id = Some(run_id.clone());
+        }
+        if args.run_dirs {
+            self.run_dirs = true;
+        }
+        if let Some(progress_file) = &args.progress_file {
+            self.progress_file = Some(progress_file.clone());
+        }
+        if let Some(allowed_hours) = args.allowed_hours {
+            self.schedule.allowed_hours = Some(allowed_hours);
+        }
+        if let Some(record_fixtures) = &args.record_fixtures {
+            self.fixtures.mode = FixtureMode::Record;
+            self.fixtures.dir = record_fixtures.clone();
+        }
+        if !args.presets.is_empty() {
+            self.presets = args.presets.clone();
+        }
+        if !args.sweep_weight.is_empty() {
+            self.sweep.controlnet_weight = args.sweep_weight.clone();
+        }
+        if let Some(keep_best) = args.keep_best {
+            self.selection.keep = keep_best;
+            self.selection.scorer.get_or_insert(ScorerConfig::Laplacian);
+        }
+        if let Some(min_score) = args.min_score {
+            self.selection.min_score = Some(min_score);
+            self.selection.scorer.get_or_insert(ScorerConfig::Laplacian);
+        }
+        if let Some(max_rerolls) = args.max_rerolls {
+            self.selection.max_rerolls = max_rerolls;
+        }
+        if args.sequence {
+            self.sequence.enabled = true;
+        }
+        if let Some(sequence_video) = &args.sequence_video {
+            self.sequence.enabled = true;
+            self.sequence.video = Some(sequence_video.clone());
+        }
+        if let Some(timelapse) = &args.timelapse {
+            self.timelapse.video = Some(timelapse.clone());
+        }
+        if args.ramp {
+            self.ramp.enabled = true;
+        }
+        if let Some(png_compression) = args.png_compression {
+            self.png.compression = Some(png_compression);
+        }
+        if let Some(preview_every) = args.preview_every {
+            self.previews.every_steps = preview_every;
+        }
+        if let Some(progress_poll_ms) = args.progress_poll_ms {
+            self.progress_poll_ms = progress_poll_ms;
+        }
+        if let Some(list_cache_ttl) = args.list_cache_ttl {
+            self.list_cache.ttl_secs = list_cache_ttl;
+        }
+        // Presets apply the arguments again on top of a configuration that already has them
+        if let Some(captions) = args.captions
+            && !self.prompt_sources.contains(&captions.source())
+        {
+            self.prompt_sources.push(captions.source());
+        }
+        for emphasis in &args.emphasize {
+            self.emphasize.insert(emphasis.term.clone(), emphasis.weight);
+        }
+        if let Some(style_dir) = &args.style_dir {
+            self.style_reference.dir = Some(style_dir.clone());
+        }
+        if let Some(replay_fixtures) = &args.replay_fixtures {
+            self.fixtures.mode = FixtureMode::Replay;
+            self.fixtures.dir = replay_fixtures.clone();
+        }
+        if let Some(Command::Resume) = &args.command {
+            self.resume = true;
+        }
+        if let Some(Command::Daemon {
+            spool_dir,
+            poll_interval,
+            grpc_addr,
+        }) = &args.command
+        {
+            if let Some(spool_dir) = spool_dir {
+                self.daemon.spool_dir = spool_dir.clone();
+            }
+            if let Some(poll_interval) = poll_interval {
+                self.daemon.poll_interval_ms = *poll_interval;
+            }
+            if let Some(grpc_addr) = grpc_addr {
+                self.daemon.grpc_addr = Some(grpc_addr.clone());
+            }
+        }
+        if let Some(log_level) = args.log_level {
+            self.log_level = log_level;
+        }
+        self.log_level = self.log_level.adjusted(args.verbosity());
+        if let Some(log_format) = args.log_format {
+            self.log_format = log_format;
+        }
+        if args.no_color {
+            self.color = ColorMode::Never;
+        }
+        if let Some(lang) = args.lang {
+            self.lang = Some(lang);
+        }
+        if args.tui {
+            self.tui = true;
+        }
     }
 }
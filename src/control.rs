@@ -0,0 +1,313 @@
+/**
+ * Run control for ControlNet Image Generator
+ *
+ * This module holds the live state of a run and the requests made while it
+ * is in progress: pausing before the next input, skipping the inputs being
+ * generated and aborting the run. The runner reports progress here, and the
+ * dashboard and key controls read it and forward key presses.
+ */
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+use crate::api_types::ProgressResponse;
+use crate::pipeline::RunEvents;
+use crate::processing::{FailureReason, ImageResult, StatsCollector};
+
+/// Number of failures kept for display
+pub const RECENT_FAILURES: usize = 5;
+
+/// How often a paused run checks whether it may continue
+const PAUSE_POLL: Duration = Duration::from_millis(200);
+
+/// Sampling progress of the image a backend is generating
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SamplingProgress {
+    /// Sampling steps done
+    pub step: u32,
+    /// Sampling steps of the image in total
+    pub steps: u32,
+    /// Estimated time left of the job, as reported by the server
+    pub eta: Option<Duration>,
+}
+
+impl SamplingProgress {
+    /// Sampling progress reported by the server, `None` while it is not sampling
+    pub fn from_response(progress: &ProgressResponse) -> Option<Self> {
+        if progress.state.sampling_steps == 0 {
+            return None;
+        }
+        Some(Self {
+            step: progress.state.sampling_step.min(progress.state.sampling_steps),
+            steps: progress.state.sampling_steps,
+            eta: (progress.eta_relative > 0.0).then(|| Duration::from_secs_f64(progress.eta_relative)),
+        })
+    }
+}
+
+/// An input currently being generated
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActiveInput {
+    /// Path of the input image
+    pub path: PathBuf,
+    /// Backend generating it
+    pub backend: String,
+    /// When generation started
+    pub started: Instant,
+    /// Sampling progress last polled from the backend, when progress is polled
+    pub sampling: Option<SamplingProgress>,
+}
+
+/// A failed input, as shown in the list of recent failures
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecentFailure {
+    /// Path of the input image
+    pub path: String,
+    /// Category of the failure
+    pub reason: FailureReason,
+}
+
+/// Progress of the run so far
+#[derive(Debug, Clone, Default)]
+pub struct RunStatus {
+    /// Inputs in the run, including those finished by an earlier run
+    pub total: usize,
+    /// Inputs finished successfully
+    pub done: usize,
+    /// Inputs that failed
+    pub failed: usize,
+    /// Images generated across all inputs
+    pub generated: usize,
+    /// Time spent generating, summed over the inputs finished in this run
+    pub generation_time: Duration,
+    /// Inputs finished in this run
+    pub finished_in_run: usize,
+    /// Inputs being generated right now
+    pub active: Vec<ActiveInput>,
+    /// Most recent failures, newest last
+    pub recent_failures: VecDeque<RecentFailure>,
+    /// When the run started
+    pub started: Option<Instant>,
+    /// Whether the run has ended
+    pub finished: bool,
+}
+
+impl RunStatus {
+    /// Inputs still waiting to be processed
+    pub fn pending(&self) -> usize {
+        self.total
+            .saturating_sub(self.done + self.failed + self.active.len())
+    }
+
+    /// Average generation time per input finished in this run
+    pub fn average_generation(&self) -> Option<Duration> {
+        (self.finished_in_run > 0).then(|| self.generation_time / self.finished_in_run as u32)
+    }
+
+    /// Estimated time until the remaining inputs are finished, spread over the busy backends
+    pub fn remaining(&self) -> Option<Duration> {
+        let workers = self.active.len().max(1) as u32;
+        self.average_generation()
+            .map(|average| average * (self.pending() + self.active.len()) as u32 / workers)
+    }
+}
+
+/// Shared state and requests of one run
+#[derive(Debug, Default)]
+pub struct RunControl {
+    status: Mutex<RunStatus>,
+    processing: AtomicBool,
+    paused: AtomicBool,
+    aborted: AtomicBool,
+    interrupted: AtomicBool,
+    skip: Notify,
+    events: RunEvents,
+    stats: Mutex<Option<Arc<StatsCollector>>>,
+    sampling_shown: AtomicBool,
+}
+
+impl RunControl {
+    /// Create the control of a new run, ready to be shared between tasks
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    /// Subscribers to the events of the run
+    pub fn events(&self) -> &RunEvents {
+        &self.events
+    }
+
+    /// Statistics of the run as they are collected, once it has started processing
+    pub fn stats(&self) -> Option<Arc<StatsCollector>> {
+        self.stats.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Share the statistics collected by the runner
+    pub fn set_stats(&self, stats: Arc<StatsCollector>) {
+        *self.stats.lock().unwrap_or_else(|e| e.into_inner()) = Some(stats);
+    }
+
+    /// Snapshot of the progress of the run
+    pub fn status(&self) -> RunStatus {
+        self.lock().clone()
+    }
+
+    /// Start tracking a run
+    ///
+    /// # Arguments
+    /// * `total` - Number of inputs in the run
+    /// * `done` - Inputs finished successfully by an earlier run
+    /// * `failed` - Inputs that failed in an earlier run
+    pub fn begin(&self, total: usize, done: usize, failed: usize) {
+        self.processing.store(false, Ordering::Relaxed);
+        *self.lock() = RunStatus {
+            total,
+            done,
+            failed,
+            started: Some(Instant::now()),
+            ..Default::default()
+        };
+    }
+
+    /// Mark the run as taking inputs from the queue, after any questions were answered
+    pub fn start_processing(&self) {
+        self.processing.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether inputs are being taken from the queue, so key presses are not answers to questions
+    pub fn is_processing(&self) -> bool {
+        self.processing.load(Ordering::Relaxed) && !self.is_finished()
+    }
+
+    /// Ask for the sampling progress of the inputs, as something shows it
+    pub fn show_sampling(&self) {
+        self.sampling_shown.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether the sampling progress of the inputs is shown, so it is worth polling
+    pub fn shows_sampling(&self) -> bool {
+        self.sampling_shown.load(Ordering::Relaxed)
+    }
+
+    /// Mark an input as being generated
+    pub fn input_started(&self, path: &Path, backend: &str) {
+        self.lock().active.push(ActiveInput {
+            path: path.to_path_buf(),
+            backend: backend.to_string(),
+            started: Instant::now(),
+            sampling: None,
+        });
+    }
+
+    /// Record the sampling progress polled for an input being generated
+    pub fn input_progress(&self, path: &Path, progress: &ProgressResponse) {
+        let mut status = self.lock();
+        if let Some(active) = status.active.iter_mut().find(|active| active.path == path) {
+            active.sampling = SamplingProgress::from_response(progress);
+        }
+    }
+
+    /// Record the outcome of an input
+    pub fn input_finished(&self, path: &Path, result: &ImageResult) {
+        let mut status = self.lock();
+        status.active.retain(|active| active.path != path);
+        status.finished_in_run += 1;
+        status.generation_time += Duration::from_millis(result.generation_ms);
+        if result.success {
+            status.done += 1;
+            status.generated += result.generated;
+        } else {
+            status.failed += 1;
+            if status.recent_failures.len() == RECENT_FAILURES {
+                status.recent_failures.pop_front();
+            }
+            status.recent_failures.push_back(RecentFailure {
+                path: result.path.clone(),
+                reason: result.reason.unwrap_or(FailureReason::Other),
+            });
+        }
+    }
+
+    /// Record that an input was left unfinished, to be taken by a later run
+    pub fn input_released(&self, path: &Path) {
+        self.lock().active.retain(|active| active.path != path);
+    }
+
+    /// Mark the run as ended
+    pub fn end(&self) {
+        let mut status = self.lock();
+        status.active.clear();
+        status.finished = true;
+    }
+
+    /// Whether the run has ended
+    pub fn is_finished(&self) -> bool {
+        self.lock().finished
+    }
+
+    /// Stop taking new inputs until `resume` is called
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::Relaxed);
+    }
+
+    /// Continue taking new inputs
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::Relaxed);
+    }
+
+    /// Pause a running run or resume a paused one
+    pub fn toggle_pause(&self) {
+        self.paused.fetch_xor(true, Ordering::Relaxed);
+    }
+
+    /// Whether the run is paused
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    /// Give up on the inputs being generated right now
+    pub fn skip_current(&self) {
+        self.skip.notify_waiters();
+    }
+
+    /// Wait until the inputs being generated are skipped
+    pub async fn skipped(&self) {
+        self.skip.notified().await;
+    }
+
+    /// Stop the run, leaving the remaining inputs queued
+    pub fn abort(&self) {
+        self.aborted.store(true, Ordering::Relaxed);
+        self.skip_current();
+    }
+
+    /// Whether the run was aborted
+    pub fn is_aborted(&self) -> bool {
+        self.aborted.load(Ordering::Relaxed)
+    }
+
+    /// Abort the run on an interrupt signal, such as Ctrl+C
+    pub fn interrupt(&self) {
+        self.interrupted.store(true, Ordering::Relaxed);
+        self.abort();
+    }
+
+    /// Whether the run was aborted by an interrupt signal
+    pub fn is_interrupted(&self) -> bool {
+        self.interrupted.load(Ordering::Relaxed)
+    }
+
+    /// Wait while the run is paused, returning early when it is aborted
+    pub async fn wait_while_paused(&self) {
+        while self.is_paused() && !self.is_aborted() {
+            tokio::time::sleep(PAUSE_POLL).await;
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, RunStatus> {
+        self.status.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
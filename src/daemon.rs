@@ -0,0 +1,523 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+/**
+ * Daemon mode for ControlNet Image Generator
+ *
+ * This module runs the generator as a long-lived service. Job description
+ * files are dropped into a spool directory; the daemon picks them up one at
+ * a time, highest priority first, runs the batch they describe and archives
+ * each job file together with the statistics of its run.
+ */
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "cli")]
+use std::time::Duration;
+use tracing::{error, info};
+#[cfg(feature = "cli")]
+use tracing::warn;
+
+use crate::control::RunControl;
+#[cfg(feature = "cli")]
+use crate::i18n::tr;
+use crate::i18n::{Msg, tr_args};
+use crate::style::*;
+#[cfg(feature = "cli")]
+use crate::{
+    config::{Args, Config},
+    metrics::Metrics,
+    runner,
+};
+
+/// Folder inside the spool directory for finished jobs
+pub const DONE_DIR: &str = "done";
+
+/// Folder inside the spool directory for jobs that could not be run
+pub const FAILED_DIR: &str = "failed";
+
+/// First line of the error log of a cancelled job
+pub const CANCELLED: &str = "Cancelled";
+
+/// Daemon settings
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DaemonConfig {
+    /// Directory watched for job description files
+    #[serde(default = "default_spool_dir")]
+    pub spool_dir: String,
+    /// How often the spool directory is checked for new jobs, in milliseconds
+    #[serde(default = "default_poll_interval")]
+    pub poll_interval_ms: u64,
+    /// Address of the gRPC job control service, e.g. "127.0.0.1:50051",
+    /// only served when built with the `grpc` feature
+    #[serde(default)]
+    pub grpc_addr: Option<String>,
+}
+
+impl Default for DaemonConfig {
+    fn default() -> Self {
+        Self {
+            spool_dir: default_spool_dir(),
+            poll_interval_ms: default_poll_interval(),
+            grpc_addr: None,
+        }
+    }
+}
+
+fn default_spool_dir() -> String {
+    "./spool".to_string()
+}
+
+fn default_poll_interval() -> u64 {
+    5000
+}
+
+/// A job description file dropped into the spool directory
+///
+/// ```yaml
+/// input_dir: "./incoming/shoot-42"
+/// output_dir: "./results/shoot-42"  # optional
+/// preset: "presets/depth.yml"       # optional configuration file
+/// priority: 10                      # optional, higher runs first
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JobSpec {
+    /// Directory with the input images
+    pub input_dir: String,
+    /// Directory for the generated images, defaults to the configured output_dir
+    #[serde(default)]
+    pub output_dir: Option<String>,
+    /// Configuration file to use instead of the daemon configuration,
+    /// relative to the job file
+    #[serde(default)]
+    pub preset: Option<String>,
+    /// Jobs with a higher priority are processed first
+    #[serde(default)]
+    pub priority: i32,
+}
+
+impl JobSpec {
+    /// Read a job description file
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .context(format!("Failed to read job file: {}", path.display()))?;
+        serde_yaml::from_str(&text).context(format!("Failed to parse job file: {}", path.display()))
+    }
+
+    /// Build the configuration for this job
+    ///
+    /// # Arguments
+    /// * `job_path` - Path of the job file, presets are resolved relative to it
+    /// * `base` - Daemon configuration, settings of a preset are applied on top of it
+    /// * `args` - Command line arguments, applied on top of a preset
+    #[cfg(feature = "cli")]
+    pub fn to_config(&self, job_path: &Path, base: &Config, args: &Args) -> Result<Config> {
+        let mut config = match &self.preset {
+            Some(preset) => {
+                let preset_path = job_path.parent().unwrap_or(Path::new(".")).join(preset);
+                base.with_preset(&preset_path, args)?
+            }
+            None => base.clone(),
+        };
+        // Nobody is there to answer questions
+        config.assume_yes = true;
+        config.input_dir = self.input_dir.clone();
+        if let Some(output_dir) = &self.output_dir {
+            config.output_dir = output_dir.clone();
+        }
+        Ok(config)
+    }
+}
+
+/// Whether a path looks like a job description file
+fn is_job_file(path: &Path) -> bool {
+    path.is_file()
+        && path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| matches!(extension.to_lowercase().as_str(), "yml" | "yaml"))
+}
+
+/// Where a job is in its life cycle
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    /// No job file with that id exists
+    Unknown,
+    /// Waiting in the spool directory
+    Pending,
+    /// Being run by the daemon
+    Running,
+    /// Finished and archived to the done folder
+    Done,
+    /// Could not be run and archived to the failed folder
+    Failed,
+    /// Cancelled before or while running
+    Cancelled,
+}
+
+/// The job the daemon is running, shared with the job control service
+#[derive(Debug, Default)]
+pub struct ActiveJob {
+    current: Mutex<Option<(PathBuf, Arc<RunControl>)>>,
+}
+
+impl ActiveJob {
+    /// Create an empty tracker, ready to be shared between tasks
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    /// Path and run control of the running job, if any
+    pub fn current(&self) -> Option<(PathBuf, Arc<RunControl>)> {
+        self.current.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    #[cfg(feature = "cli")]
+    fn set(&self, job: Option<(PathBuf, Arc<RunControl>)>) {
+        *self.current.lock().unwrap_or_else(|e| e.into_inner()) = job;
+    }
+}
+
+/// Id of a job, the file stem of its job file
+pub fn job_id(job_path: &Path) -> String {
+    job_path
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Whether an id can name a job file, so ids cannot reach outside the spool directory
+fn is_valid_job_id(id: &str) -> bool {
+    !id.is_empty()
+        && id
+            .chars()
+            .all(|character| character.is_ascii_alphanumeric() || matches!(character, '-' | '_' | '.'))
+        && !id.starts_with('.')
+}
+
+/// Write a job file into the spool directory
+///
+/// The file is written under a temporary name and renamed, so the daemon
+/// never reads a half-written job.
+///
+/// # Returns
+/// The id of the new job
+pub fn submit_job(spool_dir: &Path, spec: &JobSpec) -> Result<String> {
+    fs::create_dir_all(spool_dir).context(format!(
+        "Failed to create spool directory: {}",
+        spool_dir.display()
+    ))?;
+    let id = format!(
+        "job-{}-{:04x}",
+        chrono::Utc::now().format("%Y%m%d-%H%M%S%3f"),
+        rand::random::<u16>()
+    );
+    let temporary = spool_dir.join(format!(".{}.tmp", id));
+    fs::write(&temporary, serde_yaml::to_string(spec)?)
+        .context(format!("Failed to write job file: {}", temporary.display()))?;
+    fs::rename(&temporary, spool_dir.join(format!("{}.yml", id)))
+        .context("Failed to add job to the spool directory")?;
+    info!(event = "job_submitted", "{}", tr_args(Msg::JobSubmitted, &[&id]).blue());
+    Ok(id)
+}
+
+/// Find the job file of an id, in the spool directory or an archive folder
+fn find_job(spool_dir: &Path, id: &str) -> Option<(PathBuf, Option<&'static str>)> {
+    if !is_valid_job_id(id) {
+        return None;
+    }
+    let folders = [None, Some(DONE_DIR), Some(FAILED_DIR)];
+    folders.into_iter().find_map(|folder| {
+        let dir = folder.map_or(spool_dir.to_path_buf(), |folder| spool_dir.join(folder));
+        ["yml", "yaml"]
+            .iter()
+            .map(|extension| dir.join(format!("{}.{}", id, extension)))
+            .find(|path| path.is_file())
+            .map(|path| (path, folder))
+    })
+}
+
+/// State of a job and the report written when it was archived
+///
+/// # Arguments
+/// * `spool_dir` - Spool directory
+/// * `id` - Id of the job
+/// * `active` - The job the daemon is running
+pub fn job_state(spool_dir: &Path, id: &str, active: &ActiveJob) -> (JobState, Option<String>) {
+    let Some((path, folder)) = find_job(spool_dir, id) else {
+        return (JobState::Unknown, None);
+    };
+    let report = |extension: &str| fs::read_to_string(path.with_extension(extension)).ok();
+    match folder {
+        None if active.current().is_some_and(|(running, _)| running == path) => (JobState::Running, None),
+        None => (JobState::Pending, None),
+        Some(DONE_DIR) => (JobState::Done, report("stats.json")),
+        Some(_) => {
+            let log = report("error.log");
+            if log.as_deref().is_some_and(|log| log.starts_with(CANCELLED)) {
+                (JobState::Cancelled, log)
+            } else {
+                (JobState::Failed, log)
+            }
+        }
+    }
+}
+
+/// Cancel a job: a pending one is archived right away, a running one is
+/// aborted after the inputs being generated
+///
+/// # Returns
+/// The state of the job after the request
+pub fn cancel_job(spool_dir: &Path, id: &str, active: &ActiveJob) -> Result<JobState> {
+    match job_state(spool_dir, id, active).0 {
+        JobState::Pending => {
+            if let Some((path, _)) = find_job(spool_dir, id) {
+                archive_job(spool_dir, &path, FAILED_DIR, &format!("{}\n", CANCELLED), "error.log")?;
+            }
+            info!(event = "job_cancelled", "{}", tr_args(Msg::JobCancelled, &[&id]).yellow());
+            Ok(JobState::Cancelled)
+        }
+        JobState::Running => {
+            if let Some((_, control)) = active.current() {
+                control.abort();
+            }
+            info!(event = "job_cancelled", "{}", tr_args(Msg::CancellingJob, &[&id]).yellow());
+            Ok(JobState::Running)
+        }
+        state => Ok(state),
+    }
+}
+
+/// Find the job to run next: highest priority first, then by file name
+///
+/// Job files that cannot be parsed are moved to the failed folder.
+///
+/// # Arguments
+/// * `spool_dir` - Directory to look for job files in
+///
+/// # Returns
+/// The path and description of the next job, if any
+pub fn next_job(spool_dir: &Path) -> Result<Option<(PathBuf, JobSpec)>> {
+    next_job_except(spool_dir, &HashSet::new())
+}
+
+/// Find the job to run next, passing over the given job files
+///
+/// # Arguments
+/// * `spool_dir` - Directory to look for job files in
+/// * `skipped` - Job files not to run, e.g. ones that could not be archived
+///
+/// # Returns
+/// The path and description of the next job, if any
+pub fn next_job_except(spool_dir: &Path, skipped: &HashSet<PathBuf>) -> Result<Option<(PathBuf, JobSpec)>> {
+    let mut jobs = Vec::new();
+    for entry in fs::read_dir(spool_dir)
+        .context(format!("Failed to read spool directory: {}", spool_dir.display()))?
+    {
+        let path = entry?.path();
+        if !is_job_file(&path) || skipped.contains(&path) {
+            continue;
+        }
+        match JobSpec::load(&path) {
+            Ok(spec) => jobs.push((path, spec)),
+            Err(e) => {
+                error!("{}", tr_args(Msg::InvalidJobFile, &[&format!("{:#}", e)]).red());
+                archive_job(spool_dir, &path, FAILED_DIR, &format!("{:#}\n", e), "error.log")?;
+            }
+        }
+    }
+
+    jobs.sort_by(|(a_path, a), (b_path, b)| {
+        b.priority.cmp(&a.priority).then_with(|| a_path.cmp(b_path))
+    });
+    Ok(jobs.into_iter().next())
+}
+
+/// Move a job file into an archive folder of the spool directory, together
+/// with a report file named after it
+///
+/// # Arguments
+/// * `spool_dir` - Spool directory
+/// * `job_path` - Job file to archive
+/// * `folder` - Archive folder, `DONE_DIR` or `FAILED_DIR`
+/// * `report` - Contents of the report file
+/// * `report_extension` - Extension of the report file, e.g. "stats.json"
+///
+/// # Returns
+/// Path of the archived job file
+pub fn archive_job(
+    spool_dir: &Path,
+    job_path: &Path,
+    folder: &str,
+    report: &str,
+    report_extension: &str,
+) -> Result<PathBuf> {
+    let archive_dir = spool_dir.join(folder);
+    fs::create_dir_all(&archive_dir).context(format!(
+        "Failed to create archive directory: {}",
+        archive_dir.display()
+    ))?;
+
+    let file_name = job_path.file_name().context("Job file has no name")?;
+    let archived = archive_dir.join(file_name);
+    fs::rename(job_path, &archived)
+        .context(format!("Failed to archive job file: {}", job_path.display()))?;
+
+    let stem = Path::new(file_name).file_stem().unwrap_or(file_name);
+    let report_path = archive_dir.join(format!("{}.{}", stem.to_string_lossy(), report_extension));
+    fs::write(&report_path, report)
+        .context(format!("Failed to write job report: {}", report_path.display()))?;
+
+    Ok(archived)
+}
+
+/// Run a single spooled job and archive its file
+///
+/// # Arguments
+/// * `spool_dir` - Spool directory
+/// * `job_path` - Job file to run
+/// * `spec` - Parsed job description
+/// * `base` - Daemon configuration
+/// * `args` - Command line arguments
+/// * `metrics` - Metrics shared by all jobs of the daemon
+/// * `active` - Tracks the running job, so it can be cancelled
+///
+/// # Returns
+/// Path of the archived job file, or of the job file left in the spool
+/// when the daemon was interrupted while running it
+#[cfg(feature = "cli")]
+pub async fn run_job(
+    spool_dir: &Path,
+    job_path: &Path,
+    spec: &JobSpec,
+    base: &Config,
+    args: &Args,
+    metrics: &Metrics,
+    active: &ActiveJob,
+) -> Result<PathBuf> {
+    info!(
+        event = "job_started",
+        priority = spec.priority,
+        "{}",
+        tr_args(Msg::StartingJob, &[&job_path.display()]).blue()
+    );
+
+    let control = RunControl::new();
+    active.set(Some((job_path.to_path_buf(), Arc::clone(&control))));
+    let outcome = match spec.to_config(job_path, base, args) {
+        Ok(config) => runner::run_batch(&config, metrics, &control).await,
+        Err(e) => Err(e),
+    };
+    active.set(None);
+
+    if control.is_interrupted() {
+        warn!("{}", tr_args(Msg::JobLeftInSpool, &[&job_path.display()]).yellow());
+        return Ok(job_path.to_path_buf());
+    }
+    if control.is_aborted() {
+        warn!(event = "job_cancelled", "{}", tr_args(Msg::JobCancelled, &[&job_path.display()]).yellow());
+        return archive_job(spool_dir, job_path, FAILED_DIR, &format!("{}\n", CANCELLED), "error.log");
+    }
+    match outcome {
+        Ok(stats) => {
+            let report = match stats {
+                Some(stats) => stats.to_json()?,
+                None => "null".to_string(),
+            };
+            let archived = archive_job(spool_dir, job_path, DONE_DIR, &report, "stats.json")?;
+            info!(event = "job_finished", "{}", tr_args(Msg::JobArchived, &[&archived.display()]).green());
+            Ok(archived)
+        }
+        Err(e) => {
+            error!("{}", tr_args(Msg::JobFailed, &[&format!("{:#}", e)]).red());
+            archive_job(spool_dir, job_path, FAILED_DIR, &format!("{:#}\n", e), "error.log")
+        }
+    }
+}
+
+/// Process spooled jobs until interrupted with Ctrl+C
+///
+/// With `daemon.grpc_addr` set and the `grpc` feature enabled, the job
+/// control service is served alongside, and with `mqtt.broker` set jobs are
+/// also taken from MQTT.
+///
+/// # Arguments
+/// * `base` - Configuration with the daemon settings, used for jobs without a preset
+/// * `args` - Command line arguments
+/// * `metrics` - Metrics shared by all jobs of the daemon
+#[cfg(feature = "cli")]
+pub async fn run(base: &Config, args: &Args, metrics: &Metrics) -> Result<()> {
+    let daemon = &base.daemon;
+    let spool_dir = Path::new(&daemon.spool_dir);
+    fs::create_dir_all(spool_dir).context(format!(
+        "Failed to create spool directory: {}",
+        spool_dir.display()
+    ))?;
+    info!("{}", tr_args(Msg::WatchingSpool, &[&spool_dir.display()]).blue());
+
+    let active = ActiveJob::new();
+    let _grpc_server: Option<tokio::task::JoinHandle<()>> = match &daemon.grpc_addr {
+        #[cfg(feature = "grpc")]
+        Some(address) => Some(crate::grpc::serve(address, spool_dir, Arc::clone(&active)).await?.1),
+        #[cfg(not(feature = "grpc"))]
+        Some(_) => {
+            warn!("{}", tr(Msg::GrpcUnavailable).yellow());
+            None
+        }
+        None => None,
+    };
+    let _mqtt_intake = match &base.mqtt.broker {
+        Some(_) => Some(crate::mqtt::serve_job_intake(&base.mqtt, spool_dir).await?),
+        None => None,
+    };
+
+    // Jobs that failed and could not be archived either are not run again this session
+    let mut skipped = HashSet::new();
+    loop {
+        match next_job_except(spool_dir, &skipped) {
+            Ok(Some((job_path, spec))) => {
+                let job = run_job(spool_dir, &job_path, &spec, base, args, metrics, &active);
+                tokio::pin!(job);
+                let result = tokio::select! {
+                    result = &mut job => result,
+                    _ = tokio::signal::ctrl_c() => {
+                        // The inputs being generated are interrupted and the job stays for the next start
+                        if let Some((_, control)) = active.current() {
+                            control.interrupt();
+                        }
+                        if let Err(e) = job.await {
+                            error!("{}", tr_args(Msg::JobFailed, &[&format!("{:#}", e)]).red());
+                        }
+                        info!("{}", tr(Msg::DaemonStopped).blue());
+                        return Ok(());
+                    }
+                };
+                if let Err(e) = result {
+                    // A job that cannot be finished is set aside, so it is not run again and again
+                    error!("{}", tr_args(Msg::JobFailed, &[&format!("{:#}", e)]).red());
+                    let report = format!("{:#}\n", e);
+                    if job_path.exists()
+                        && let Err(e) = archive_job(spool_dir, &job_path, FAILED_DIR, &report, "error.log")
+                    {
+                        // Left in the spool, the job would otherwise be picked up again right away
+                        error!("{}", tr_args(Msg::JobArchiveFailed, &[&job_path.display(), &format!("{:#}", e)]).red());
+                        skipped.insert(job_path.clone());
+                    }
+                }
+                continue;
+            }
+            Ok(None) => {}
+            Err(e) => warn!("{}", tr_args(Msg::CheckJobsFailed, &[&format!("{:#}", e)]).yellow()),
+        }
+
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_millis(daemon.poll_interval_ms)) => {}
+            _ = tokio::signal::ctrl_c() => {
+                info!("{}", tr(Msg::DaemonStopped).blue());
+                return Ok(());
+            }
+        }
+    }
+}
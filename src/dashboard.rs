@@ -0,0 +1,397 @@
+/**
+ * Terminal dashboard for ControlNet Image Generator
+ *
+ * This module draws a live overview of a long run in the terminal instead
+ * of scrolling logs: progress, the inputs being generated, timings and the
+ * most recent failures. Single key presses pause, resume, skip or abort the
+ * run, also when logs are shown instead of the dashboard. It only needs ANSI
+ * escape sequences, and on Unix terminals keys are read without waiting for
+ * Enter.
+ */
+use std::io::{self, IsTerminal, Read, Write};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use crate::control::{RunControl, RunStatus};
+use crate::i18n::{Msg, tr};
+use crate::logging::{self, LogLevel};
+use crate::status_line;
+use crate::style::*;
+
+/// How often the dashboard is redrawn
+const REFRESH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How long the key reader waits for a key before checking whether to stop
+const KEY_POLL: Duration = Duration::from_millis(200);
+
+/// Width of the progress bar in characters
+const PROGRESS_WIDTH: usize = 40;
+
+/// Switch to the alternate screen and hide the cursor
+const ENTER_SCREEN: &str = "\x1b[?1049h\x1b[?25l";
+
+/// Show the cursor and return to the normal screen
+const LEAVE_SCREEN: &str = "\x1b[?25h\x1b[?1049l";
+
+/// Move the cursor to the top left and clear the screen
+const CLEAR_SCREEN: &str = "\x1b[H\x1b[J";
+
+/// Whether the dashboard is shown on the alternate screen
+static ON_ALTERNATE_SCREEN: AtomicBool = AtomicBool::new(false);
+
+/// A running dashboard, drawn until the run ends
+pub struct Dashboard {
+    control: Arc<RunControl>,
+    task: JoinHandle<()>,
+    log_level: LogLevel,
+    keys: KeyControls,
+}
+
+impl Dashboard {
+    /// Start drawing the dashboard and listening for keys
+    ///
+    /// Log lines below errors are hidden while the dashboard is shown, as
+    /// they would scroll it away.
+    ///
+    /// # Returns
+    /// The dashboard, or `None` when standard output is not a terminal
+    pub fn start(control: Arc<RunControl>) -> Option<Self> {
+        if !io::stdout().is_terminal() {
+            warn!("{}", tr(Msg::DashboardNeedsTerminal).yellow());
+            return None;
+        }
+
+        let log_level = logging::current_level();
+        logging::set_level(LogLevel::Error);
+        control.show_sampling();
+        let keys = KeyControls::spawn(Arc::clone(&control));
+
+        let draw_control = Arc::clone(&control);
+        let task = tokio::spawn(async move {
+            print!("{}", ENTER_SCREEN);
+            ON_ALTERNATE_SCREEN.store(true, Ordering::Relaxed);
+            while !draw_control.is_finished() {
+                draw(&draw_control);
+                tokio::time::sleep(REFRESH_INTERVAL).await;
+            }
+        });
+        Some(Self {
+            control,
+            task,
+            log_level,
+            keys,
+        })
+    }
+
+    /// Stop drawing, restore the terminal and print the final state once
+    pub async fn stop(self) {
+        self.control.end();
+        let _ = self.task.await;
+        self.keys.stop();
+        leave_alternate_screen();
+        let lines = render(&self.control.status(), false, self.control.is_aborted(), Instant::now());
+        println!("{}", lines.join("\n"));
+        logging::set_level(self.log_level);
+    }
+}
+
+/// Return to the normal screen, if the dashboard left it
+fn leave_alternate_screen() {
+    if ON_ALTERNATE_SCREEN.swap(false, Ordering::Relaxed) {
+        let mut stdout = io::stdout().lock();
+        let _ = write!(stdout, "{}", LEAVE_SCREEN);
+        let _ = stdout.flush();
+    }
+}
+
+/// Put the terminal back as it was before the run, for exiting the program without stopping the run
+///
+/// Leaves the alternate screen of the dashboard and restores the settings
+/// changed for reading single keys.
+pub fn restore_terminal() {
+    leave_alternate_screen();
+    terminal::restore();
+}
+
+/// Draw one frame of the dashboard
+fn draw(control: &RunControl) {
+    let lines = render(&control.status(), control.is_paused(), control.is_aborted(), Instant::now());
+    let mut stdout = io::stdout().lock();
+    let _ = write!(stdout, "{}{}", CLEAR_SCREEN, lines.join("\n"));
+    let _ = stdout.flush();
+}
+
+/// Lines of the dashboard for the given state of the run
+///
+/// # Arguments
+/// * `status` - Progress of the run
+/// * `paused` - Whether the run is paused
+/// * `aborted` - Whether the run was aborted
+/// * `now` - Current time, for elapsed times
+pub fn render(status: &RunStatus, paused: bool, aborted: bool, now: Instant) -> Vec<String> {
+    let finished = status.done + status.failed;
+    let percent = (finished * 100).checked_div(status.total).unwrap_or(0);
+    let state = if aborted {
+        " [ABORTED]".red().bold().to_string()
+    } else if paused {
+        " [PAUSED]".yellow().bold().to_string()
+    } else {
+        String::new()
+    };
+
+    let mut lines = vec![
+        format!(
+            "{}  {}/{} inputs ({}%){}",
+            "urasoe".bold(),
+            finished,
+            status.total,
+            percent,
+            state
+        ),
+        progress_bar(finished, status.total),
+        format!(
+            "Done {}   Failed {}   Pending {}   Generated {} images",
+            status.done.to_string().green(),
+            status.failed.to_string().red(),
+            status.pending(),
+            status.generated
+        ),
+    ];
+
+    let elapsed = status
+        .started
+        .map(|started| now.saturating_duration_since(started))
+        .unwrap_or_default();
+    let mut timing = format!("Elapsed {}", format_duration(elapsed));
+    if let (Some(average), Some(remaining)) = (status.average_generation(), status.remaining()) {
+        timing.push_str(&format!(
+            "   Average {:.1}s per input   ETA {}",
+            average.as_secs_f64(),
+            format_duration(remaining)
+        ));
+    }
+    lines.push(timing);
+
+    lines.push(String::new());
+    lines.push("Processing:".bold().to_string());
+    if status.active.is_empty() {
+        lines.push("  -".to_string());
+    }
+    for active in &status.active {
+        let mut line = format!(
+            "  {} on {} ({}s)",
+            active.path.file_name().unwrap_or_default().to_string_lossy(),
+            active.backend,
+            now.saturating_duration_since(active.started).as_secs()
+        );
+        if let Some(sampling) = &active.sampling {
+            line.push_str(&format!("  {}", status_line::sampling_text(sampling)));
+        }
+        lines.push(line);
+    }
+
+    if !status.recent_failures.is_empty() {
+        lines.push(String::new());
+        lines.push("Recent failures:".bold().to_string());
+        for failure in status.recent_failures.iter().rev() {
+            let name = std::path::Path::new(&failure.path)
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string();
+            lines.push(format!("  {}  {}", name, failure.reason.label().red()));
+        }
+    }
+
+    lines.push(String::new());
+    lines.push("p pause/resume   s skip current   q abort".dimmed().to_string());
+    lines
+}
+
+/// Progress bar with `#` for finished and `-` for remaining inputs
+fn progress_bar(finished: usize, total: usize) -> String {
+    status_line::bar(finished, total, PROGRESS_WIDTH)
+}
+
+/// Format a duration as hours, minutes and seconds
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    format!("{:02}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+/// Apply a key pressed during a run
+///
+/// # Returns
+/// Whether the key was recognized
+pub fn handle_key(control: &RunControl, key: u8) -> bool {
+    match key.to_ascii_lowercase() {
+        b'p' => control.toggle_pause(),
+        b'r' => control.resume(),
+        b's' => control.skip_current(),
+        b'q' => control.abort(),
+        _ => return false,
+    }
+    true
+}
+
+/// Confirmation of a recognized key, for runs showing logs instead of the dashboard
+pub fn key_feedback(control: &RunControl, key: u8) -> Option<&'static str> {
+    match key.to_ascii_lowercase() {
+        b'p' | b'r' if control.is_paused() => Some(tr(Msg::Paused)),
+        b'p' | b'r' => Some(tr(Msg::Resumed)),
+        b's' => Some(tr(Msg::SkippingInput)),
+        b'q' => Some(tr(Msg::Aborting)),
+        _ => None,
+    }
+}
+
+/// Single key presses read from standard input on a background thread
+/// while a run takes inputs, until stopped
+pub struct KeyControls {
+    stop: Arc<AtomicBool>,
+    thread: thread::JoinHandle<()>,
+}
+
+impl KeyControls {
+    /// Listen for keys during a run that shows logs, printing which keys there are
+    ///
+    /// # Returns
+    /// The key controls, or `None` when standard input is not a terminal
+    pub fn start(control: Arc<RunControl>) -> Option<Self> {
+        if !io::stdin().is_terminal() {
+            return None;
+        }
+        info!("{}", tr(Msg::KeysHint).blue());
+        Some(Self::spawn(control))
+    }
+
+    /// Read keys on a background thread
+    ///
+    /// Keys are only read while the run takes inputs, so answers to
+    /// questions asked before that still reach the question, and the
+    /// terminal is switched to reading single keys only for that time.
+    fn spawn(control: Arc<RunControl>) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = Arc::clone(&stop);
+        let thread = thread::spawn(move || {
+            let mut raw_mode = None;
+            let mut key = [0u8; 1];
+            while !stopped.load(Ordering::Relaxed) {
+                if !control.is_processing() {
+                    raw_mode = None;
+                    thread::sleep(KEY_POLL);
+                    continue;
+                }
+                if raw_mode.is_none() {
+                    raw_mode = terminal::RawMode::enable();
+                }
+                if !terminal::key_ready(KEY_POLL) {
+                    continue;
+                }
+                match io::stdin().lock().read(&mut key) {
+                    Ok(1) => {
+                        if handle_key(&control, key[0])
+                            && let Some(feedback) = key_feedback(&control, key[0])
+                        {
+                            info!("{}", feedback.yellow());
+                        }
+                    }
+                    _ => break,
+                }
+            }
+        });
+        Self { stop, thread }
+    }
+
+    /// Stop reading keys and restore the terminal
+    pub fn stop(self) {
+        self.stop.store(true, Ordering::Relaxed);
+        let _ = self.thread.join();
+    }
+}
+
+#[cfg(unix)]
+mod terminal {
+    use std::sync::Mutex;
+
+    /// Settings before raw mode was enabled, while it is
+    static ORIGINAL: Mutex<Option<libc::termios>> = Mutex::new(None);
+
+    /// Terminal settings delivering key presses without Enter and echo,
+    /// restored when dropped
+    pub struct RawMode;
+
+    impl RawMode {
+        /// Switch standard input to unbuffered, silent reads
+        pub fn enable() -> Option<Self> {
+            // SAFETY: termios is a plain C struct filled in by tcgetattr before use
+            unsafe {
+                let mut original: libc::termios = std::mem::zeroed();
+                if libc::tcgetattr(libc::STDIN_FILENO, &mut original) != 0 {
+                    return None;
+                }
+                let mut raw = original;
+                // Keep signals, so Ctrl+C still stops the program
+                raw.c_lflag &= !(libc::ICANON | libc::ECHO);
+                raw.c_cc[libc::VMIN] = 1;
+                raw.c_cc[libc::VTIME] = 0;
+                if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) != 0 {
+                    return None;
+                }
+                *ORIGINAL.lock().unwrap_or_else(|e| e.into_inner()) = Some(original);
+                Some(Self)
+            }
+        }
+    }
+
+    /// Restore the settings from before raw mode, if it is enabled
+    pub fn restore() {
+        if let Some(original) = ORIGINAL.lock().unwrap_or_else(|e| e.into_inner()).take() {
+            // SAFETY: restores the settings read by tcgetattr in enable
+            unsafe {
+                libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &original);
+            }
+        }
+    }
+
+    /// Wait until a key can be read from standard input without blocking
+    pub fn key_ready(timeout: std::time::Duration) -> bool {
+        let mut stdin = libc::pollfd {
+            fd: libc::STDIN_FILENO,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // SAFETY: polls a single pollfd that lives for the duration of the call
+        unsafe { libc::poll(&mut stdin, 1, timeout.as_millis() as libc::c_int) > 0 }
+    }
+
+    impl Drop for RawMode {
+        fn drop(&mut self) {
+            restore();
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod terminal {
+    /// Keys are read line by line where raw mode is not available
+    pub struct RawMode;
+
+    impl RawMode {
+        pub fn enable() -> Option<Self> {
+            None
+        }
+    }
+
+    /// Reads block until a line is entered, so a key always counts as ready
+    pub fn key_ready(_timeout: std::time::Duration) -> bool {
+        true
+    }
+
+    /// Nothing was changed to restore
+    pub fn restore() {}
+}
@@ -0,0 +1,130 @@
+use serde::{Deserialize, Serialize};
+/**
+ * Request digests for ControlNet Image Generator
+ *
+ * This module hashes what was sent to generate an image, so the metadata can
+ * tell whether two runs asked for exactly the same thing. The txt2img payload
+ * is hashed with the base64 control images left out, and the input image is
+ * hashed on its own, both with SHA-256.
+ */
+use serde_json::Value;
+
+/// Hashes of one txt2img request
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestDigest {
+    /// SHA-256 of the payload without its control images, as hex
+    pub payload_sha256: String,
+    /// SHA-256 of the input image file, as hex
+    pub image_sha256: String,
+}
+
+impl RequestDigest {
+    /// Hash a payload and the input image it was built from
+    ///
+    /// The control images of the ControlNet units are blanked before hashing,
+    /// and a random seed is replaced with the seed the server reported, so a
+    /// request repeated with that seed gets the same payload hash.
+    ///
+    /// # Arguments
+    /// * `payload` - txt2img payload as sent to the API
+    /// * `image` - Contents of the input image file
+    /// * `seed` - Seed the server used, if it reported one
+    pub fn new(payload: &Value, image: &[u8], seed: Option<i64>) -> Self {
+        let mut payload = payload.clone();
+        if let Some(units) = payload
+            .pointer_mut("/alwayson_scripts/controlnet/args")
+            .and_then(Value::as_array_mut)
+        {
+            // The extension takes the control image as `input_image` or `image`
+            for unit in units {
+                for key in ["input_image", "image"] {
+                    if let Some(image) = unit.get_mut(key) {
+                        *image = Value::Null;
+                    }
+                }
+            }
+        }
+        if let Some(seed) = seed
+            && payload["seed"].as_i64().is_some_and(|requested| requested < 0)
+        {
+            payload["seed"] = Value::from(seed);
+        }
+        // Object keys are kept sorted, so the serialization is stable
+        Self {
+            payload_sha256: sha256_hex(payload.to_string().as_bytes()),
+            image_sha256: sha256_hex(image),
+        }
+    }
+}
+
+/// Initial hash values of SHA-256
+const H0: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+/// Round constants of SHA-256
+const K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+/// SHA-256 of some bytes
+pub fn sha256(data: &[u8]) -> [u8; 32] {
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+
+    let mut state = H0;
+    for block in message.chunks_exact(64) {
+        let mut w = [0u32; 64];
+        for (i, word) in block.chunks_exact(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16].wrapping_add(s0).wrapping_add(w[i - 7]).wrapping_add(s1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = state;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let choice = (e & f) ^ (!e & g);
+            let t1 = h.wrapping_add(s1).wrapping_add(choice).wrapping_add(K[i]).wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let majority = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(majority);
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+        for (value, add) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *value = value.wrapping_add(add);
+        }
+    }
+
+    let mut digest = [0u8; 32];
+    for (bytes, value) in digest.chunks_exact_mut(4).zip(state) {
+        bytes.copy_from_slice(&value.to_be_bytes());
+    }
+    digest
+}
+
+/// SHA-256 of some bytes as lowercase hex
+pub fn sha256_hex(data: &[u8]) -> String {
+    sha256(data).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
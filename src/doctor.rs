@@ -0,0 +1,254 @@
+use anyhow::Result;
+/**
+ * Diagnostics for ControlNet Image Generator
+ *
+ * This module implements `urasoe doctor`, which checks everything a run
+ * depends on before any image is generated: whether each backend answers
+ * and accepts the credentials, which Web UI and ControlNet versions it runs,
+ * whether the configured models exist, and whether the output directory is
+ * writable with enough free space. Every failed check comes with a hint on
+ * how to fix it.
+ */
+use std::fs;
+use std::path::Path;
+
+use crate::api::StableDiffusionClient;
+use crate::config::Config;
+use crate::style::*;
+
+/// Free space in the output directory below which a warning is given
+pub const MIN_FREE_BYTES: u64 = 1024 * 1024 * 1024;
+
+/// Outcome of a single check
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckStatus {
+    /// Everything is fine
+    Pass,
+    /// A run may work, but something deserves attention
+    Warn,
+    /// A run will not work until this is fixed
+    Fail,
+}
+
+/// A single line of the checklist
+#[derive(Debug, Clone, PartialEq)]
+pub struct Check {
+    /// What was checked
+    pub name: String,
+    /// Outcome of the check
+    pub status: CheckStatus,
+    /// What was found
+    pub detail: String,
+    /// How to fix a warning or failure
+    pub hint: Option<String>,
+}
+
+impl Check {
+    fn pass(name: &str, detail: impl Into<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            status: CheckStatus::Pass,
+            detail: detail.into(),
+            hint: None,
+        }
+    }
+
+    fn warn(name: &str, detail: impl Into<String>, hint: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            status: CheckStatus::Warn,
+            detail: detail.into(),
+            hint: Some(hint.to_string()),
+        }
+    }
+
+    fn fail(name: &str, detail: impl Into<String>, hint: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            status: CheckStatus::Fail,
+            detail: detail.into(),
+            hint: Some(hint.to_string()),
+        }
+    }
+}
+
+/// Run every check for the given configuration
+///
+/// Checks of a backend that cannot be reached are skipped after the
+/// reachability check.
+pub async fn run_checks(config: &Config) -> Vec<Check> {
+    let mut checks = Vec::new();
+    for api_url in config.api_urls() {
+        let client = StableDiffusionClient::with_timeout(&api_url, config.validate_timeout_ms)
+            .with_identity(config.request_identity(None));
+        check_backend(&client, &api_url, config, &mut checks).await;
+    }
+    checks.push(check_output_writable(&config.output_dir));
+    checks.push(check_free_space(&config.output_dir));
+    checks
+}
+
+/// Check reachability, credentials, versions and models of one backend
+async fn check_backend(client: &StableDiffusionClient, api_url: &str, config: &Config, checks: &mut Vec<Check>) {
+    match client.api_status().await {
+        Ok(status) if status.is_success() => {
+            checks.push(Check::pass("API reachable", api_url));
+            checks.push(Check::pass("API authentication", "accepted"));
+        }
+        Ok(status) if status.as_u16() == 401 || status.as_u16() == 403 => {
+            checks.push(Check::pass("API reachable", api_url));
+            checks.push(Check::fail(
+                "API authentication",
+                format!("rejected with {}", status),
+                "Set api_username and api_password, or api_token, to the credentials of --api-auth or of the proxy in front of the Web UI",
+            ));
+            return;
+        }
+        Ok(status) => {
+            checks.push(Check::fail(
+                "API reachable",
+                format!("{} answered {}", api_url, status),
+                "Start the Web UI with --api so the API endpoints are available",
+            ));
+            return;
+        }
+        Err(e) => {
+            checks.push(Check::fail(
+                "API reachable",
+                format!("{}: {:#}", api_url, e),
+                "Start the Web UI with --api, or point sd_api_url to where it runs",
+            ));
+            return;
+        }
+    }
+
+    checks.push(match client.get_webui_version().await {
+        Ok(version) => Check::pass("Web UI version", version),
+        Err(e) => Check::warn(
+            "Web UI version",
+            format!("unknown: {:#}", e),
+            "Update the Web UI, older versions do not report their version",
+        ),
+    });
+
+    match client.get_controlnet_version().await {
+        Ok(version) => checks.push(Check::pass("ControlNet extension", format!("API version {}", version))),
+        Err(e) => {
+            checks.push(Check::fail(
+                "ControlNet extension",
+                format!("not found: {:#}", e),
+                "Install sd-webui-controlnet from the Extensions tab and restart the Web UI",
+            ));
+            return;
+        }
+    }
+
+    let mut model_config = config.clone();
+    model_config.validate_options = true;
+    // Recently cached lists save the requests, but the output directory is left as it was
+    model_config.list_cache.read_only = true;
+    checks.push(match client.validate_config_options(&model_config).await {
+        Ok(issues) if issues.is_empty() => Check::pass(
+            "Configured models",
+            format!("{}, {}, {}", config.checkpoint_model, config.model, config.sampler_name),
+        ),
+        Ok(issues) => Check::fail(
+            "Configured models",
+            issues.join("; "),
+            "Run `urasoe models` to list the names the server offers",
+        ),
+        Err(e) => Check::fail(
+            "Configured models",
+            format!("{:#}", e),
+            "Run `urasoe models` to list the names the server offers",
+        ),
+    });
+}
+
+/// Check that files can be created in the output directory
+pub fn check_output_writable(output_dir: &str) -> Check {
+    let name = "Output directory writable";
+    let writable = fs::create_dir_all(output_dir)
+        .and_then(|_| tempfile::Builder::new().prefix(".urasoe-doctor-").tempfile_in(output_dir));
+    match writable {
+        Ok(_) => Check::pass(name, output_dir),
+        Err(e) => Check::fail(
+            name,
+            format!("{}: {}", output_dir, e),
+            "Choose another output_dir or fix the permissions of this one",
+        ),
+    }
+}
+
+/// Check that the output directory has room for generated images
+pub fn check_free_space(output_dir: &str) -> Check {
+    let name = "Free disk space";
+    match free_space(Path::new(output_dir)) {
+        Some(bytes) if bytes < MIN_FREE_BYTES => Check::warn(
+            name,
+            format!("{:.1} GB available", bytes as f64 / 1e9),
+            "Free some space or choose an output_dir on another disk",
+        ),
+        Some(bytes) => Check::pass(name, format!("{:.1} GB available", bytes as f64 / 1e9)),
+        None => Check::warn(
+            name,
+            "could not be determined",
+            "Make sure the output disk has room for the generated images",
+        ),
+    }
+}
+
+/// Bytes available to unprivileged users on the file system of a path
+#[cfg(unix)]
+fn free_space(path: &Path) -> Option<u64> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    // SAFETY: statvfs is a plain C struct filled in by statvfs before use
+    unsafe {
+        let mut stats: libc::statvfs = std::mem::zeroed();
+        if libc::statvfs(path.as_ptr(), &mut stats) != 0 {
+            return None;
+        }
+        Some(stats.f_bavail as u64 * stats.f_frsize as u64)
+    }
+}
+
+#[cfg(not(unix))]
+fn free_space(_path: &Path) -> Option<u64> {
+    None
+}
+
+/// Format the checks as a checklist, with hints below warnings and failures
+pub fn render(checks: &[Check]) -> String {
+    let mut output = String::new();
+    for check in checks {
+        let mark = match check.status {
+            CheckStatus::Pass => "✓".green(),
+            CheckStatus::Warn => "!".yellow(),
+            CheckStatus::Fail => "✗".red(),
+        };
+        output.push_str(&format!("{} {}: {}\n", mark, check.name, check.detail));
+        if let Some(hint) = &check.hint {
+            output.push_str(&format!("    {}\n", hint.dimmed()));
+        }
+    }
+    output
+}
+
+/// Run every check and print the checklist
+///
+/// # Returns
+/// An error when any check failed
+pub async fn run(config: &Config) -> Result<()> {
+    let checks = run_checks(config).await;
+    print!("{}", render(&checks));
+    let failed = checks
+        .iter()
+        .filter(|check| check.status == CheckStatus::Fail)
+        .count();
+    if failed > 0 {
+        return Err(anyhow::anyhow!("{} of {} checks failed", failed, checks.len()));
+    }
+    Ok(())
+}
@@ -0,0 +1,122 @@
+/**
+ * Exit codes for ControlNet Image Generator
+ *
+ * This module maps the outcome of a run, or the error that stopped it, to
+ * the exit code of the process, so wrapping scripts and CI can branch on
+ * the result instead of parsing the console output.
+ */
+use std::fmt;
+use std::process::ExitCode;
+
+use crate::processing::{FailureReason, ProcessingStats};
+
+/// Outcome of the process, each with its own exit code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitStatus {
+    /// Every input succeeded, or there was nothing to do
+    Success,
+    /// An unexpected error stopped the program
+    Error,
+    /// Some inputs failed
+    PartialFailure,
+    /// Every input failed
+    AllFailed,
+    /// The configuration could not be read or does not match the server
+    ConfigInvalid,
+    /// The Stable Diffusion server could not be reached
+    ApiUnreachable,
+    /// The run was stopped with Ctrl+C
+    Interrupted,
+}
+
+impl ExitStatus {
+    /// Exit code of the process
+    pub fn code(self) -> u8 {
+        match self {
+            ExitStatus::Success => 0,
+            ExitStatus::Error => 1,
+            ExitStatus::PartialFailure => 2,
+            ExitStatus::AllFailed => 3,
+            ExitStatus::ConfigInvalid => 4,
+            ExitStatus::ApiUnreachable => 5,
+            // 128 plus the number of SIGINT, as shells report a program stopped by it
+            ExitStatus::Interrupted => 130,
+        }
+    }
+
+    /// Status of a finished run
+    ///
+    /// A run where every input failed to reach the server counts as the
+    /// server being unreachable.
+    pub fn from_stats(stats: &ProcessingStats) -> Self {
+        let failed = stats.failed_paths.len();
+        if failed == 0 {
+            return ExitStatus::Success;
+        }
+        if stats.success_count > 0 {
+            return ExitStatus::PartialFailure;
+        }
+        let failures = stats.failures_by_reason();
+        if failures.len() == 1 && failures.contains_key(&FailureReason::Connection) {
+            ExitStatus::ApiUnreachable
+        } else {
+            ExitStatus::AllFailed
+        }
+    }
+
+    /// Status of several runs together, e.g. one per preset
+    ///
+    /// Runs that processed nothing are left out.
+    pub fn combine<'a>(runs: impl IntoIterator<Item = &'a ProcessingStats>) -> Self {
+        let statuses: Vec<ExitStatus> = runs.into_iter().map(Self::from_stats).collect();
+        if statuses.iter().all(|status| *status == ExitStatus::Success) {
+            ExitStatus::Success
+        } else if statuses.iter().all(|status| *status == ExitStatus::ApiUnreachable) {
+            ExitStatus::ApiUnreachable
+        } else if statuses
+            .iter()
+            .all(|status| matches!(status, ExitStatus::AllFailed | ExitStatus::ApiUnreachable))
+        {
+            ExitStatus::AllFailed
+        } else {
+            ExitStatus::PartialFailure
+        }
+    }
+
+    /// Status of an error that stopped the program
+    ///
+    /// Looks through the whole chain of causes: configuration errors and
+    /// connection failures get their own codes, anything else is `Error`.
+    pub fn from_error(error: &anyhow::Error) -> Self {
+        for cause in error.chain() {
+            if cause.is::<ConfigInvalid>() || cause.is::<serde_yaml::Error>() {
+                return ExitStatus::ConfigInvalid;
+            }
+            if let Some(error) = cause.downcast_ref::<reqwest::Error>()
+                && (error.is_connect() || error.is_timeout())
+            {
+                return ExitStatus::ApiUnreachable;
+            }
+        }
+        ExitStatus::Error
+    }
+}
+
+impl From<ExitStatus> for ExitCode {
+    fn from(status: ExitStatus) -> Self {
+        ExitCode::from(status.code())
+    }
+}
+
+/// Error for a configuration that is readable but not usable, e.g. naming
+/// a model the server does not have
+#[derive(Debug)]
+pub struct ConfigInvalid(pub String);
+
+impl fmt::Display for ConfigInvalid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ConfigInvalid {}
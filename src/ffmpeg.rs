@@ -0,0 +1,82 @@
+use anyhow::{Context, Result};
+/**
+ * Video encoding with ffmpeg for ControlNet Image Generator
+ *
+ * This module writes ffconcat lists of images and encodes them into a video
+ * with an ffmpeg subprocess, for frame sequences and run timelapses. ffmpeg
+ * is optional: when it is not installed the video is left out with a warning.
+ */
+use std::fs;
+use std::path::Path;
+use tokio::process::Command;
+use tracing::warn;
+
+use crate::i18n::{Msg, tr};
+use crate::style::*;
+
+/// Write an ffconcat list showing each image for one frame
+///
+/// # Arguments
+/// * `list` - Path of the list file
+/// * `images` - Images in order, relative to the list file or absolute
+/// * `fps` - Frames per second of the video
+pub fn write_concat_list(list: &Path, images: &[String], fps: u32) -> Result<()> {
+    let duration = 1.0 / f64::from(fps.max(1));
+    let entries: String = images
+        .iter()
+        .map(|image| format!("file '{}'\nduration {}\n", image.replace('\'', "'\\''"), duration))
+        .collect();
+    fs::write(list, format!("ffconcat version 1.0\n{}", entries)).context("Failed to write the frame list")
+}
+
+/// Encode the images of an ffconcat list into a video
+///
+/// Videos ending in `.webm` are encoded with VP9, others with H.264.
+///
+/// # Arguments
+/// * `list` - ffconcat list written by `write_concat_list`
+/// * `fps` - Frames per second of the video
+/// * `size` - Width and height every image is scaled and padded to, for images of differing sizes
+/// * `video` - Video file to write
+///
+/// # Returns
+/// Whether the video was written, `false` when ffmpeg is not installed
+pub async fn encode(list: &Path, fps: u32, size: Option<(u32, u32)>, video: &Path) -> Result<bool> {
+    let codec = match video.extension().and_then(|extension| extension.to_str()) {
+        Some(extension) if extension.eq_ignore_ascii_case("webm") => "libvpx-vp9",
+        _ => "libx264",
+    };
+    let mut command = Command::new("ffmpeg");
+    command
+        .args(["-y", "-loglevel", "error", "-f", "concat", "-safe", "0", "-i"])
+        .arg(list);
+    if let Some((width, height)) = size {
+        // yuv420p needs even dimensions
+        let (width, height) = (width.max(2) & !1, height.max(2) & !1);
+        command.arg("-vf").arg(format!(
+            "scale={0}:{1}:force_original_aspect_ratio=decrease,pad={0}:{1}:(ow-iw)/2:(oh-ih)/2",
+            width, height
+        ));
+    }
+    let output = command
+        .args(["-r", &fps.to_string(), "-c:v", codec, "-pix_fmt", "yuv420p"])
+        .arg(video)
+        .output()
+        .await;
+    let output = match output {
+        Ok(output) => output,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            warn!("{}", tr(Msg::FfmpegNotFound).yellow());
+            return Ok(false);
+        }
+        Err(e) => return Err(e).context("Failed to start ffmpeg"),
+    };
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "ffmpeg exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(true)
+}
@@ -1,7 +1,8 @@
 use anyhow::{Context, Result};
-use base64::{Engine, prelude::BASE64_STANDARD};
 use chrono::Utc;
-use colored::*;
+use image::codecs::png::{CompressionType, FilterType, PngEncoder};
+use image::{DynamicImage, ImageFormat};
+use tracing::{info, warn};
 use serde::{Deserialize, Serialize};
 /**
  * File operations for ControlNet Image Generator
@@ -11,38 +12,397 @@ use serde::{Deserialize, Serialize};
  * - Creating and maintaining metadata for generated images
  * - Managing output directories and file naming conventions
  */
-use std::fs;
-use std::path::Path;
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
 
-use crate::config::Config;
+/// Name of the folder inside output_dir collecting failed inputs
+pub const DEAD_LETTER_DIR: &str = "_failed";
+
+/// How hard the PNG encoder compresses saved images
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "snake_case")]
+pub enum PngCompression {
+    /// Quickest to write, largest files
+    Fast,
+    /// Balance of speed and size
+    Default,
+    /// Smallest files, slowest to write
+    Best,
+}
+
+impl From<PngCompression> for CompressionType {
+    fn from(compression: PngCompression) -> Self {
+        match compression {
+            PngCompression::Fast => CompressionType::Fast,
+            PngCompression::Default => CompressionType::Default,
+            PngCompression::Best => CompressionType::Best,
+        }
+    }
+}
+
+/// Encoding of the saved PNG images
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PngConfig {
+    /// Compression to encode the images again with, unset to save them as the server sent them
+    #[serde(default)]
+    pub compression: Option<PngCompression>,
+    /// Whether images the server sends with 16 bits per channel keep them, otherwise they are reduced to 8
+    #[serde(default = "default_sixteen_bit")]
+    pub sixteen_bit: bool,
+}
+
+impl Default for PngConfig {
+    fn default() -> Self {
+        Self {
+            compression: None,
+            sixteen_bit: default_sixteen_bit(),
+        }
+    }
+}
+
+/// Default for keeping 16 bits per channel - true
+pub fn default_sixteen_bit() -> bool {
+    true
+}
+
+/// Encode a generated PNG image again with the configured settings
+///
+/// Images that need no change, and images that are not PNG, are returned as they are.
+///
+/// # Arguments
+/// * `image` - Image as the server sent it
+/// * `png` - Encoding settings
+///
+/// # Returns
+/// The image to save
+pub fn encode_png(image: Vec<u8>, png: &PngConfig) -> Result<Vec<u8>> {
+    if !matches!(image::guess_format(&image), Ok(ImageFormat::Png)) {
+        return Ok(image);
+    }
+    // The bit depth is in the IHDR chunk, which directly follows the signature
+    let sixteen_bit = image.get(24) == Some(&16);
+    let reduce = sixteen_bit && !png.sixteen_bit;
+    if png.compression.is_none() && !reduce {
+        return Ok(image);
+    }
+
+    let mut decoded = image::load_from_memory_with_format(&image, ImageFormat::Png)
+        .context("Failed to decode generated image")?;
+    if reduce {
+        decoded = if decoded.color().has_alpha() {
+            DynamicImage::ImageRgba8(decoded.to_rgba8())
+        } else {
+            DynamicImage::ImageRgb8(decoded.to_rgb8())
+        };
+    }
+    let mut encoded = Vec::new();
+    let encoder = PngEncoder::new_with_quality(
+        &mut encoded,
+        png.compression.unwrap_or(PngCompression::Default).into(),
+        FilterType::Adaptive,
+    );
+    decoded.write_with_encoder(encoder).context("Failed to encode generated image")?;
+    Ok(encoded)
+}
+
+use crate::config::{
+    Config, ControlNetUnitConfig, DeadLetterMode, default_batch_size, default_controlnet_module,
+    default_controlnet_weight, default_sampler_index, default_sampler_name, default_seed,
+};
 use crate::api::StableDiffusionResponse;
+use crate::composite;
+use crate::i18n::{Msg, tr, tr_args};
+use crate::prompt_source::emphasize;
+use crate::sink::{FileSystemSink, OutputSink};
+use crate::style::*;
 
 /// Metadata for generated images
 ///
 /// Stores information about the generation process and parameters used,
 /// which is saved alongside the generated images for reproducibility.
-#[derive(Serialize, Deserialize, Debug)]
+/// Other tools can read it back with `read` or `find_metadata`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct ImageMetadata {
     /// Timestamp when the image was generated
-    timestamp: String,
+    pub timestamp: String,
     /// Text prompt used for image generation
-    prompt: String,
+    pub prompt: String,
     /// Negative prompt used for image generation
-    negative_prompt: String,
+    pub negative_prompt: String,
+    /// Attention weight of terms of the prompt, put into the prompt sent as `(term:weight)`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub emphasize: BTreeMap<String, f32>,
     /// ControlNet model used (e.g., canny, depth, openpose)
-    controlnet_model: String,
+    pub controlnet_model: String,
     /// Stable Diffusion checkpoint model used
-    checkpoint_model: String,
+    pub checkpoint_model: String,
     /// Number of diffusion steps
-    steps: u32,
+    pub steps: u32,
     /// CFG scale value used for generation
-    cfg_scale: f32,
+    pub cfg_scale: f32,
     /// Width of the generated image in pixels
-    width: u32,
+    pub width: u32,
     /// Height of the generated image in pixels
-    height: u32,
+    pub height: u32,
+    /// Seed of the first image, the one the server picked when a random seed was asked for
+    #[serde(default = "default_seed")]
+    pub seed: i64,
+    /// Seed of each image, when they do not count up from `seed`, e.g. for images of several re-rolls
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub seeds: Vec<i64>,
+    /// Sampler used for generation
+    #[serde(default = "default_sampler_name")]
+    pub sampler_name: String,
+    /// Scheduler used with the sampler
+    #[serde(default = "default_sampler_index")]
+    pub scheduler: String,
+    /// ControlNet module used (e.g., canny, depth, openpose)
+    #[serde(default = "default_controlnet_module")]
+    pub controlnet_module: String,
+    /// Weight of the ControlNet influence
+    #[serde(default = "default_controlnet_weight")]
+    pub controlnet_weight: f32,
+    /// ControlNet units applied together, replacing the model, module and weight above when given
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub controlnet_units: Vec<ControlNetUnitConfig>,
+    /// Number of images generated for the input
+    #[serde(default = "default_batch_size")]
+    pub batch_size: u32,
     /// Filename of the source image used for ControlNet
-    source_image: String,
+    pub source_image: String,
+    /// SHA-256 of the txt2img payload without its control images, to spot identical requests
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payload_sha256: Option<String>,
+    /// SHA-256 of the source image file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_sha256: Option<String>,
+    /// Style reference image the input was paired with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub style_image: Option<String>,
+    /// Identifier of the run that generated the images
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run_id: Option<String>,
+}
+
+impl ImageMetadata {
+    /// Settings of the configuration used for one input, stamped with the current time
+    pub fn from_config(config: &Config, input_image_path: &Path) -> Self {
+        Self {
+            timestamp: Utc::now().to_rfc3339(),
+            prompt: config.prompt.clone(),
+            negative_prompt: config.negative_prompt.clone(),
+            emphasize: config.emphasize.clone(),
+            controlnet_model: config.model.clone(),
+            checkpoint_model: config.checkpoint_model.clone(),
+            steps: config.steps,
+            cfg_scale: config.cfg,
+            width: config.width,
+            height: config.height,
+            seed: config.seed,
+            seeds: Vec::new(),
+            sampler_name: config.sampler_name.clone(),
+            scheduler: config.scheduler.clone(),
+            controlnet_module: config.controlnet_module.clone(),
+            controlnet_weight: config.controlnet_weight,
+            controlnet_units: config.controlnet_units.clone(),
+            batch_size: config.batch_size,
+            source_image: input_image_path.to_string_lossy().to_string(),
+            payload_sha256: None,
+            image_sha256: None,
+            style_image: config
+                .style_reference
+                .find(input_image_path)
+                .ok()
+                .flatten()
+                .map(|style| style.to_string_lossy().to_string()),
+            run_id: config.run_id.clone(),
+        }
+    }
+
+    /// Seed of one image of the batch
+    ///
+    /// Images of a batch count up from the seed, unless the seed of each image is recorded.
+    ///
+    /// # Arguments
+    /// * `index` - Position of the image in the batch, starting at 0
+    pub fn seed_of(&self, index: usize) -> i64 {
+        match self.seeds.get(index) {
+            Some(&seed) => seed,
+            None if self.seed < 0 => self.seed,
+            None => self.seed + index as i64,
+        }
+    }
+
+    /// Generation parameters of one image in the infotext format of the Web UI
+    ///
+    /// The text can be pasted into the prompt box of the Web UI and read with
+    /// "Read generation parameters".
+    ///
+    /// # Arguments
+    /// * `index` - Position of the image in the batch, starting at 0
+    pub fn infotext(&self, index: usize) -> String {
+        let mut text = emphasize(&self.prompt, &self.emphasize);
+        if !self.negative_prompt.is_empty() {
+            text.push_str(&format!("\nNegative prompt: {}", self.negative_prompt));
+        }
+        let seed = self.seed_of(index);
+        let mut parameters = vec![
+            format!("Steps: {}", self.steps),
+            format!("Sampler: {}", self.sampler_name),
+        ];
+        if !self.scheduler.is_empty() {
+            parameters.push(format!("Schedule type: {}", self.scheduler));
+        }
+        parameters.extend([
+            format!("CFG scale: {}", python_float(self.cfg_scale)),
+            format!("Seed: {}", seed),
+            format!("Size: {}x{}", self.width, self.height),
+            format!("Model: {}", self.checkpoint_model),
+        ]);
+        if self.controlnet_units.is_empty() {
+            parameters.push(format!(
+                "ControlNet 0: \"Module: {}, Model: {}, Weight: {}\"",
+                self.controlnet_module,
+                self.controlnet_model,
+                python_float(self.controlnet_weight)
+            ));
+        }
+        for (number, unit) in self.controlnet_units.iter().enumerate() {
+            parameters.push(format!(
+                "ControlNet {}: \"Module: {}, Model: {}, Weight: {}, Guidance Start: {}, Guidance End: {}\"",
+                number,
+                unit.module,
+                unit.model,
+                python_float(unit.weight),
+                python_float(unit.guidance_start),
+                python_float(unit.guidance_end)
+            ));
+        }
+        text.push_str(&format!("\n{}", parameters.join(", ")));
+        text
+    }
+
+    /// Configuration generating these images again
+    ///
+    /// The recorded settings replace those of the given configuration, and
+    /// prompt sources are left out so the recorded prompts are used as they are.
+    ///
+    /// # Arguments
+    /// * `config` - Configuration providing the settings not recorded, such as the server
+    pub fn apply_to(&self, config: &Config) -> Config {
+        Config {
+            prompt: self.prompt.clone(),
+            negative_prompt: self.negative_prompt.clone(),
+            prompt_sources: Vec::new(),
+            emphasize: self.emphasize.clone(),
+            model: self.controlnet_model.clone(),
+            checkpoint_model: self.checkpoint_model.clone(),
+            steps: self.steps,
+            cfg: self.cfg_scale,
+            width: self.width,
+            height: self.height,
+            seed: self.seed,
+            sampler_name: self.sampler_name.clone(),
+            scheduler: self.scheduler.clone(),
+            controlnet_module: self.controlnet_module.clone(),
+            controlnet_weight: self.controlnet_weight,
+            controlnet_units: self.controlnet_units.clone(),
+            batch_size: self.batch_size,
+            ..config.clone()
+        }
+    }
+
+    /// Read a metadata file written next to generated images
+    ///
+    /// # Arguments
+    /// * `path` - Path of a `<stem>-metadata.json` file
+    ///
+    /// # Returns
+    /// The settings the images were generated with
+    pub fn read(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path).context(format!("Failed to read {}", path.display()))?;
+        serde_json::from_str(&text).context(format!("Failed to parse metadata {}", path.display()))
+    }
+}
+
+/// Number as Python prints floats, which the Web UI does in its infotexts: `7.0`, `7.5`
+fn python_float(value: f32) -> String {
+    if value.fract() == 0.0 {
+        format!("{:.1}", value)
+    } else {
+        value.to_string()
+    }
+}
+
+/// Find the metadata of every input in an output directory
+///
+/// Walks the directory and its subdirectories, such as those of presets,
+/// skipping the dead-letter folder. Files that cannot be read are left out
+/// with a warning.
+///
+/// # Arguments
+/// * `output_dir` - Directory the images were saved to
+///
+/// # Returns
+/// Path and contents of each `*-metadata.json` file, sorted by path
+pub fn find_metadata(output_dir: &Path) -> Result<Vec<(PathBuf, ImageMetadata)>> {
+    let mut found = Vec::new();
+    if output_dir.is_dir() {
+        collect_metadata(output_dir, &mut found)?;
+    }
+    found.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(found)
+}
+
+fn collect_metadata(dir: &Path, found: &mut Vec<(PathBuf, ImageMetadata)>) -> Result<()> {
+    let entries = fs::read_dir(dir).context(format!("Failed to read {}", dir.display()))?;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            if entry.file_name() != DEAD_LETTER_DIR {
+                collect_metadata(&path, found)?;
+            }
+        } else if entry.file_name().to_string_lossy().ends_with("-metadata.json") {
+            match ImageMetadata::read(&path) {
+                Ok(metadata) => found.push((path, metadata)),
+                Err(e) => warn!("{}", tr_args(Msg::SkippingMetadata, &[&format!("{:#}", e)]).yellow()),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// What was stored for one input
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct SavedImages {
+    /// Where each image was stored, in the order the server returned them
+    pub paths: Vec<PathBuf>,
+    /// Size of the stored images together, in bytes
+    pub bytes: u64,
+    /// Where the metadata was stored, `None` when there was nothing to save
+    pub metadata_path: Option<PathBuf>,
+    /// Images of the response that could not be decoded and were left out
+    pub skipped: usize,
+    /// Images requested but not stored, as the response had fewer or undecodable ones
+    pub missing: usize,
+    /// Where images left out by the best-of-N selection were moved
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub rejected: Vec<PathBuf>,
+}
+
+impl SavedImages {
+    /// Add what was stored by another save for the same input, keeping the first metadata path
+    pub fn extend(&mut self, other: SavedImages) {
+        self.paths.extend(other.paths);
+        self.bytes += other.bytes;
+        self.metadata_path = self.metadata_path.take().or(other.metadata_path);
+        self.skipped += other.skipped;
+        self.missing += other.missing;
+        self.rejected.extend(other.rejected);
+    }
 }
 
 pub struct FileManager;
@@ -60,59 +420,223 @@ impl FileManager {
     /// * `config` - Configuration settings used for generation
     ///
     /// # Returns
-    /// A Result indicating success or failure of the save operation
+    /// The paths and sizes of what was written
     pub fn save_generated_images(
         result: &StableDiffusionResponse,
         input_image_path: &Path,
         config: &Config,
-    ) -> Result<()> {
+    ) -> Result<SavedImages> {
+        Self::save_to_sink(&FileSystemSink::from_config(config), result, input_image_path, config)
+    }
+
+    /// Save generated images and their metadata to the given sink
+    ///
+    /// Images that cannot be decoded are left out with a warning, unless
+    /// none of them can, which fails the save.
+    ///
+    /// # Arguments
+    /// * `sink` - Where the images and metadata are stored
+    /// * `result` - The StableDiffusionResponse containing generated images
+    /// * `input_image_path` - Path to the original input image used
+    /// * `config` - Configuration settings used for generation
+    ///
+    /// # Returns
+    /// Where the sink stored each image and the metadata, and how much was stored
+    pub fn save_to_sink(
+        sink: &dyn OutputSink,
+        result: &StableDiffusionResponse,
+        input_image_path: &Path,
+        config: &Config,
+    ) -> Result<SavedImages> {
         if result.images.is_empty() {
-            println!("{}", "No images generated to save".yellow());
-            return Ok(());
+            warn!("{}", tr(Msg::NoImagesToSave).yellow());
+            return Ok(SavedImages {
+                missing: config.batch_size as usize,
+                ..SavedImages::default()
+            });
         }
 
-        let base_name = input_image_path
-            .file_stem()
-            .context("Failed to extract file name")?
-            .to_string_lossy();
-
-        let output_subdir = Path::new(&config.output_dir).join(&*base_name);
-
-        // Create subdirectory for this input image if it doesn't exist
-        fs::create_dir_all(&output_subdir).context("Failed to create output subdirectory")?;
+        let mut images = Vec::with_capacity(result.images.len());
+        let mut decoded = Vec::with_capacity(result.images.len());
+        let mut first_error = None;
+        for (index, image) in result.images.iter().enumerate() {
+            match image.bytes() {
+                Ok(image) => {
+                    images.push(image);
+                    decoded.push(index);
+                }
+                Err(e) => {
+                    warn!("{}", tr_args(Msg::UndecodableImage, &[&(index + 1), &format!("{:#}", e)]).yellow());
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+        if images.is_empty()
+            && let Some(error) = first_error
+        {
+            return Err(error);
+        }
+        let images: Vec<Vec<u8>> = images
+            .into_iter()
+            .map(|image| encode_png(image, &config.png))
+            .collect::<Result<_>>()?;
 
         // Configuration used to create the image is stored in metadata
-        let metadata = ImageMetadata {
-            timestamp: Utc::now().to_rfc3339(),
-            prompt: config.prompt.clone(),
-            negative_prompt: config.negative_prompt.clone(),
-            controlnet_model: config.model.clone(),
-            checkpoint_model: config.checkpoint_model.clone(),
-            steps: config.steps,
-            cfg_scale: config.cfg,
-            width: config.width,
-            height: config.height,
-            source_image: input_image_path.to_string_lossy().to_string(),
+        let mut metadata = ImageMetadata::from_config(config, input_image_path);
+        metadata.seed = result.seed().unwrap_or(metadata.seed);
+        // Images of re-rolls have seeds of their own
+        let seeds = result.seeds();
+        if seeds.iter().enumerate().any(|(index, &seed)| seed != metadata.seed + index as i64) {
+            metadata.seeds = seeds;
+        }
+        // A retry after NaN tensors may have switched to the fallback sampler
+        if let Some(sampler_name) = result.sampler_name()
+            && sampler_name != metadata.sampler_name
+            && sampler_name != format!("{} {}", metadata.sampler_name, metadata.scheduler)
+        {
+            metadata.sampler_name = sampler_name;
+            metadata.scheduler = String::new();
+        }
+        if let Some(digest) = &result.digest {
+            metadata.payload_sha256 = Some(digest.payload_sha256.clone());
+            metadata.image_sha256 = Some(digest.image_sha256.clone());
+        }
+        // Inputs of the same name in different folders are kept apart by the output naming
+        let named_input = config.named_input(input_image_path);
+        let metadata_path = sink.save_metadata(&named_input, &metadata)?;
+        let paths = sink.save_images(&named_input, &images)?;
+        if config.parameters_files {
+            // The server knows the parameters of each image best, the metadata fills in otherwise
+            let infotexts = result.infotexts();
+            let parameters: Vec<String> = decoded
+                .iter()
+                .map(|&index| infotexts.get(index).cloned().unwrap_or_else(|| metadata.infotext(index)))
+                .collect();
+            sink.save_parameters(&named_input, &parameters)?;
+        }
+        if config.composites {
+            // A missing composite is not worth failing the input over
+            let composites: Result<Vec<Vec<u8>>> = images
+                .iter()
+                .zip(&decoded)
+                .map(|(image, &index)| {
+                    composite::render_png(input_image_path, image, &composite::caption(&metadata, index))
+                })
+                .collect();
+            if let Err(e) = composites.and_then(|composites| sink.save_composites(&named_input, &composites)) {
+                warn!("{}", tr_args(Msg::CompositesFailed, &[&format!("{:#}", e)]).yellow());
+            }
+        }
+        let saved = SavedImages {
+            paths,
+            bytes: images.iter().map(|image| image.len() as u64).sum(),
+            metadata_path: Some(metadata_path),
+            skipped: result.images.len() - images.len(),
+            missing: (config.batch_size as usize).saturating_sub(images.len()),
+            rejected: Vec::new(),
         };
+        if saved.missing > 0 {
+            warn!(
+                "{}",
+                tr_args(Msg::SavingFewer, &[&images.len(), &config.batch_size, &saved.missing]).yellow()
+            );
+        }
+        for output_path in &saved.paths {
+            info!(
+                event = "image_saved",
+                output = %output_path.display(),
+                "{}",
+                tr_args(Msg::Saved, &[&output_path.display()]).green()
+            );
+        }
 
-        // Save metadata
-        let metadata_path = output_subdir.join(format!("{}-metadata.json", base_name));
-        fs::write(&metadata_path, serde_json::to_string_pretty(&metadata)?)
-            .context("Failed to write metadata file")?;
+        Ok(saved)
+    }
+}
 
-        // Save generated images
-        for (index, image_base64) in result.images.iter().enumerate() {
-            let image_data = BASE64_STANDARD
-                .decode(image_base64)
-                .context("Failed to decode base64 image")?;
+impl FileManager {
+    /// Put a failed input into the dead-letter folder along with its error log
+    ///
+    /// Depending on `config.dead_letter`, the input is copied or moved into
+    /// `output_dir/_failed/`, in the folders of the input directory for recursive
+    /// runs, and a `<file name>.error.log` file describing the
+    /// failure is written next to it, along with the settings it was generated
+    /// with in `<file name>.metadata.json`. When the input failed before, the
+    /// new failure is appended to its error log.
+    ///
+    /// # Arguments
+    /// * `input_image_path` - Path to the input image that failed
+    /// * `error_message` - Description of the failure
+    /// * `attempts` - Number of generation attempts made
+    /// * `config` - Configuration settings used for generation
+    ///
+    /// # Returns
+    /// The path of the dead-lettered input, or None when dead-lettering is off
+    pub fn dead_letter(
+        input_image_path: &Path,
+        error_message: &str,
+        attempts: u32,
+        config: &Config,
+    ) -> Result<Option<PathBuf>> {
+        if config.dead_letter == DeadLetterMode::Off {
+            return Ok(None);
+        }
 
-            let output_path = output_subdir.join(format!("{}-{}.png", base_name, index + 1));
-            fs::write(&output_path, image_data).context("Failed to write image file")?;
+        let file_name = input_image_path
+            .file_name()
+            .context("Failed to extract file name")?;
+        // Recursive runs mirror the input folders, so inputs of the same name stay apart
+        let mirrored = config
+            .recursive
+            .then(|| input_image_path.parent()?.strip_prefix(&config.input_dir).ok())
+            .flatten()
+            .unwrap_or(Path::new(""));
+        let dead_letter_dir = Path::new(&config.output_dir).join(DEAD_LETTER_DIR).join(mirrored);
+        fs::create_dir_all(&dead_letter_dir).context("Failed to create dead-letter directory")?;
 
-            println!("{} {}", "Saved:".green(), output_path.display());
+        let target_path = dead_letter_dir.join(file_name);
+        if input_image_path.exists() {
+            match config.dead_letter {
+                DeadLetterMode::Move => {
+                    // Renaming fails across file systems, so fall back to copy and delete
+                    if fs::rename(input_image_path, &target_path).is_err() {
+                        fs::copy(input_image_path, &target_path)
+                            .context("Failed to copy input to dead-letter directory")?;
+                        fs::remove_file(input_image_path)
+                            .context("Failed to remove moved input")?;
+                    }
+                }
+                _ => {
+                    fs::copy(input_image_path, &target_path)
+                        .context("Failed to copy input to dead-letter directory")?;
+                }
+            }
         }
 
-        Ok(())
+        let log_path = dead_letter_dir.join(format!("{}.error.log", file_name.to_string_lossy()));
+        let log = format!(
+            "timestamp: {}\nsource_image: {}\nattempts: {}\nerror: {}\n",
+            Utc::now().to_rfc3339(),
+            input_image_path.display(),
+            attempts,
+            error_message
+        );
+        // An earlier failure of the same input keeps its record at the top of the log
+        let log = if log_path.exists() { format!("\n{}", log) } else { log };
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&log_path)
+            .and_then(|mut file| file.write_all(log.as_bytes()))
+            .context("Failed to write dead-letter error log")?;
+        // The settings of the failure, for searching the history
+        let metadata_path = dead_letter_dir.join(format!("{}.metadata.json", file_name.to_string_lossy()));
+        let metadata = ImageMetadata::from_config(config, input_image_path);
+        fs::write(&metadata_path, serde_json::to_string_pretty(&metadata)?)
+            .context("Failed to write dead-letter metadata")?;
+
+        warn!("{}", tr_args(Msg::DeadLettered, &[&target_path.display()]).yellow());
+        Ok(Some(target_path))
     }
 }
 
@@ -123,6 +647,6 @@ pub fn save_generated_images(
     result: &StableDiffusionResponse,
     input_image_path: &Path,
     config: &Config,
-) -> Result<()> {
+) -> Result<SavedImages> {
     FileManager::save_generated_images(result, input_image_path, config)
 }
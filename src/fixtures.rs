@@ -0,0 +1,134 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+/**
+ * Recorded API responses for ControlNet Image Generator
+ *
+ * This module stores the raw responses of the generation API while a real
+ * run is in progress, and plays them back later in place of the server.
+ * Replaying a recorded run exercises saving, statistics and reporting
+ * deterministically and without a GPU.
+ */
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tracing::debug;
+
+/// Whether API responses are recorded, replayed or neither
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "snake_case")]
+pub enum FixtureMode {
+    /// Talk to the API as usual
+    #[default]
+    Off,
+    /// Save every successful generation response to the fixture directory
+    Record,
+    /// Answer generation requests from the fixture directory instead of the API
+    Replay,
+}
+
+/// Fixture settings
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FixtureConfig {
+    /// Whether responses are recorded or replayed
+    #[serde(default)]
+    pub mode: FixtureMode,
+    /// Directory holding the recorded responses
+    #[serde(default = "default_fixture_dir")]
+    pub dir: String,
+}
+
+impl Default for FixtureConfig {
+    fn default() -> Self {
+        Self {
+            mode: FixtureMode::Off,
+            dir: default_fixture_dir(),
+        }
+    }
+}
+
+fn default_fixture_dir() -> String {
+    "./fixtures".to_string()
+}
+
+/// Recorded responses of one run, shared by all clients of the run
+///
+/// Responses are stored per input as `<path>.<n>.json`, where the path is
+/// that of the input relative to the input directory and `n` counts the
+/// requests made for that input, so split batches replay in the order they
+/// were recorded.
+#[derive(Debug)]
+pub struct Fixtures {
+    mode: FixtureMode,
+    dir: PathBuf,
+    input_dir: PathBuf,
+    counters: Mutex<HashMap<PathBuf, u32>>,
+}
+
+impl Fixtures {
+    /// Set up fixtures for a run, or `None` when they are turned off
+    ///
+    /// # Arguments
+    /// * `config` - Fixture settings
+    /// * `input_dir` - Input directory of the run, which fixture paths are relative to
+    pub fn from_config(config: &FixtureConfig, input_dir: &Path) -> Option<Arc<Self>> {
+        match config.mode {
+            FixtureMode::Off => None,
+            mode => Some(Arc::new(Self {
+                mode,
+                dir: PathBuf::from(&config.dir),
+                input_dir: input_dir.to_path_buf(),
+                counters: Mutex::new(HashMap::new()),
+            })),
+        }
+    }
+
+    /// Whether responses come from the fixture directory instead of the API
+    pub fn is_replay(&self) -> bool {
+        self.mode == FixtureMode::Replay
+    }
+
+    /// Path of the next fixture for the given input
+    ///
+    /// Inputs of the same name in different folders of the input directory
+    /// get fixtures in matching folders of the fixture directory.
+    fn next_path(&self, image_path: &Path) -> PathBuf {
+        let relative = match image_path.strip_prefix(&self.input_dir) {
+            Ok(relative) => relative.to_path_buf(),
+            Err(_) => PathBuf::from(image_path.file_name().unwrap_or_default()),
+        };
+        let mut counters = self.counters.lock().unwrap_or_else(|e| e.into_inner());
+        let counter = counters.entry(relative.clone()).or_default();
+        *counter += 1;
+        self.dir.join(format!("{}.{}.json", relative.to_string_lossy(), counter))
+    }
+
+    /// Save a raw API response for the given input
+    pub fn record(&self, image_path: &Path, response_text: &str) -> Result<()> {
+        self.recording(image_path)?
+            .write_all(response_text.as_bytes())
+            .context("Failed to write fixture")
+    }
+
+    /// Create the file of the next recorded API response for the given input,
+    /// for writing the response into while it is being received
+    pub fn recording(&self, image_path: &Path) -> Result<fs::File> {
+        let path = self.next_path(image_path);
+        fs::create_dir_all(path.parent().unwrap_or(&self.dir)).context("Failed to create fixture directory")?;
+        debug!("Recording API response to {}", path.display());
+        fs::File::create(&path).context(format!("Failed to write fixture {}", path.display()))
+    }
+
+    /// Load the next recorded API response for the given input
+    pub fn replay(&self, image_path: &Path) -> Result<String> {
+        let path = self.next_path(image_path);
+        debug!("Replaying API response from {}", path.display());
+        fs::read_to_string(&path).context(format!(
+            "No recorded response for {} at {}",
+            image_path.display(),
+            path.display()
+        ))
+    }
+}
@@ -0,0 +1,457 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::fmt::Write as _;
+use std::fs;
+use std::net::SocketAddr;
+/**
+ * Web gallery for ControlNet Image Generator
+ *
+ * This module implements `urasoe serve`, a small read-only web server for
+ * browsing the output directory: the state of the last run, every input
+ * with its generated variants and metadata, and the failed inputs with
+ * their error logs. Like the metrics endpoint it is a minimal HTTP/1.1
+ * responder, so teammates can review results from a browser without any
+ * extra dependencies.
+ */
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+use crate::composite::COMPOSITE_SUFFIX;
+use crate::file_utils::DEAD_LETTER_DIR;
+use crate::i18n::{Msg, tr_args};
+use crate::queue::{DEFAULT_QUEUE_FILE, JobQueue, JobStatus};
+
+/// Default address of the gallery
+pub const DEFAULT_ADDRESS: &str = "127.0.0.1:8080";
+
+/// Generated images and metadata of one input
+#[derive(Debug, Clone, Serialize)]
+pub struct InputEntry {
+    /// Name of the input, the file stem its folder is named after
+    pub name: String,
+    /// Generated images relative to the output directory, in variant order
+    pub images: Vec<String>,
+    /// Contents of the metadata file, when there is one
+    pub metadata: Option<serde_json::Value>,
+    /// Last modification of the folder in seconds since the Unix epoch
+    pub modified: u64,
+}
+
+/// A failed input in the dead-letter folder
+#[derive(Debug, Clone, Serialize)]
+pub struct FailureEntry {
+    /// File name of the input, below the input folders mirrored for recursive runs
+    pub name: String,
+    /// Path of the input relative to the output directory
+    pub image: String,
+    /// Contents of the error log, when there is one
+    pub log: Option<String>,
+}
+
+/// Counts of the job queue of the last run
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RunEntry {
+    /// Inputs still waiting or in progress
+    pub pending: usize,
+    /// Inputs processed successfully
+    pub done: usize,
+    /// Inputs that failed
+    pub failed: usize,
+}
+
+/// Everything found in the output directory
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Gallery {
+    /// State of the last run, when its job queue is still there
+    pub run: Option<RunEntry>,
+    /// Inputs with generated images, most recent first
+    pub inputs: Vec<InputEntry>,
+    /// Failed inputs, by name
+    pub failures: Vec<FailureEntry>,
+}
+
+/// Which entries the gallery page shows
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Filter {
+    /// Only show entries whose name contains this text, ignoring case
+    pub query: String,
+    /// Hide the generated inputs
+    pub failed_only: bool,
+    /// Hide the failed inputs
+    pub generated_only: bool,
+}
+
+impl Filter {
+    /// Read the filter from the query string of a request, e.g. `q=cat&show=failed`
+    pub fn from_query(query: &str) -> Self {
+        let mut filter = Self::default();
+        for pair in query.split('&') {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let value = percent_decode(value);
+            match key {
+                "q" => filter.query = value,
+                "show" => {
+                    filter.failed_only = value == "failed";
+                    filter.generated_only = value == "generated";
+                }
+                _ => {}
+            }
+        }
+        filter
+    }
+
+    /// Whether an entry with the given name matches the text filter
+    pub fn matches(&self, name: &str) -> bool {
+        name.to_lowercase().contains(&self.query.to_lowercase())
+    }
+}
+
+/// Decode `%XX` escapes and `+` of a query string value
+pub fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        match bytes[index] {
+            b'+' => decoded.push(b' '),
+            b'%' if index + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[index + 1..index + 3]).ok();
+                match hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                    Some(byte) => {
+                        decoded.push(byte);
+                        index += 2;
+                    }
+                    None => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        index += 1;
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+/// Escape text for use in HTML
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Seconds since the Unix epoch of a modification time
+fn epoch_seconds(time: std::io::Result<SystemTime>) -> u64 {
+    time.ok()
+        .and_then(|time| time.duration_since(SystemTime::UNIX_EPOCH).ok())
+        .map_or(0, |duration| duration.as_secs())
+}
+
+/// Read the output directory
+///
+/// Every folder holding PNG images is an input, its `*-metadata.json` file
+/// the metadata. Failed inputs are read from the dead-letter folder.
+pub fn scan(output_dir: &Path) -> Result<Gallery> {
+    let mut gallery = Gallery::default();
+    if !output_dir.is_dir() {
+        return Ok(gallery);
+    }
+
+    let queue_path = output_dir.join(DEFAULT_QUEUE_FILE);
+    if queue_path.is_file() {
+        match JobQueue::open(&queue_path) {
+            Ok(queue) => {
+                gallery.run = Some(RunEntry {
+                    pending: queue.count(JobStatus::Pending) + queue.count(JobStatus::InProgress),
+                    done: queue.count(JobStatus::Done),
+                    failed: queue.count(JobStatus::Failed),
+                })
+            }
+            Err(e) => warn!("{}", tr_args(Msg::QueueReadFailed, &[&queue_path.display(), &format!("{:#}", e)])),
+        }
+    }
+
+    let entries = fs::read_dir(output_dir).context(format!("Failed to read {}", output_dir.display()))?;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if !path.is_dir() {
+            continue;
+        }
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name == DEAD_LETTER_DIR {
+            gallery.failures = scan_failures(&path)?;
+            continue;
+        }
+
+        let mut images = Vec::new();
+        let mut metadata = None;
+        for file in fs::read_dir(&path)?.flatten() {
+            let file_name = file.file_name().to_string_lossy().to_string();
+            // Composites show the input as well, they are not among the generated images
+            let composite = file_name.ends_with(&format!("{}.png", COMPOSITE_SUFFIX));
+            if file_name.ends_with(".png") && !composite {
+                images.push(format!("{}/{}", name, file_name));
+            } else if file_name.ends_with("-metadata.json") {
+                metadata = fs::read_to_string(file.path())
+                    .ok()
+                    .and_then(|text| serde_json::from_str(&text).ok());
+            }
+        }
+        if images.is_empty() {
+            continue;
+        }
+        images.sort_by_key(|image| variant_number(image));
+        gallery.inputs.push(InputEntry {
+            name,
+            images,
+            metadata,
+            modified: epoch_seconds(entry.metadata().and_then(|metadata| metadata.modified())),
+        });
+    }
+    gallery
+        .inputs
+        .sort_by(|a, b| b.modified.cmp(&a.modified).then_with(|| a.name.cmp(&b.name)));
+    Ok(gallery)
+}
+
+/// Number of a generated variant, so `-10.png` sorts after `-9.png`
+fn variant_number(image: &str) -> (u32, String) {
+    let number = image
+        .trim_end_matches(".png")
+        .rsplit('-')
+        .next()
+        .and_then(|number| number.parse().ok())
+        .unwrap_or(u32::MAX);
+    (number, image.to_string())
+}
+
+/// Read the failed inputs and their error logs of the dead-letter folder
+fn scan_failures(dead_letter_dir: &Path) -> Result<Vec<FailureEntry>> {
+    let mut failures = Vec::new();
+    scan_failure_folder(dead_letter_dir, "", &mut failures)?;
+    failures.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(failures)
+}
+
+/// Read the failed inputs of a folder of the dead-letter folder and of the input folders mirrored in it
+///
+/// # Arguments
+/// * `dir` - Folder to read
+/// * `prefix` - Path of the folder relative to the dead-letter folder, with a trailing slash
+/// * `failures` - Failed inputs found so far
+fn scan_failure_folder(dir: &Path, prefix: &str, failures: &mut Vec<FailureEntry>) -> Result<()> {
+    for file in fs::read_dir(dir)?.flatten() {
+        let file_name = file.file_name().to_string_lossy().to_string();
+        if file.path().is_dir() {
+            scan_failure_folder(&file.path(), &format!("{}{}/", prefix, file_name), failures)?;
+            continue;
+        }
+        if file_name.ends_with(".error.log") || file_name.ends_with(".metadata.json") || !file.path().is_file() {
+            continue;
+        }
+        let log = fs::read_to_string(dir.join(format!("{}.error.log", file_name))).ok();
+        let name = format!("{}{}", prefix, file_name);
+        failures.push(FailureEntry {
+            image: format!("{}/{}", DEAD_LETTER_DIR, name),
+            name,
+            log,
+        });
+    }
+    Ok(())
+}
+
+/// Render the gallery page
+pub fn render(gallery: &Gallery, filter: &Filter) -> String {
+    let mut out = String::new();
+    let _ = write!(
+        out,
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>urasoe gallery</title>\
+         <style>body{{font-family:sans-serif;margin:1em}}img{{height:192px;margin:2px}}\
+         section{{border-bottom:1px solid #ccc;padding:0.5em 0}}pre{{background:#f4f4f4;padding:0.5em;white-space:pre-wrap}}\
+         summary{{cursor:pointer}}</style></head><body>\n<h1>urasoe gallery</h1>\n"
+    );
+    let _ = writeln!(
+        out,
+        "<form><input name=\"q\" value=\"{}\" placeholder=\"Filter by name\"> \
+         <select name=\"show\"><option value=\"\">All</option><option value=\"generated\"{}>Generated</option>\
+         <option value=\"failed\"{}>Failed</option></select> <button>Filter</button></form>",
+        escape(&filter.query),
+        if filter.generated_only { " selected" } else { "" },
+        if filter.failed_only { " selected" } else { "" }
+    );
+    if let Some(run) = &gallery.run {
+        let _ = writeln!(
+            out,
+            "<p>Last run: {} done, {} failed, {} pending</p>",
+            run.done, run.failed, run.pending
+        );
+    }
+
+    if !filter.failed_only {
+        let inputs: Vec<&InputEntry> = gallery.inputs.iter().filter(|input| filter.matches(&input.name)).collect();
+        let _ = writeln!(out, "<h2>Generated ({})</h2>", inputs.len());
+        for input in inputs {
+            let _ = writeln!(out, "<section><h3>{}</h3>", escape(&input.name));
+            for image in &input.images {
+                let _ = writeln!(
+                    out,
+                    "<a href=\"/files/{0}\"><img src=\"/files/{0}\" alt=\"{0}\" loading=\"lazy\"></a>",
+                    escape(image)
+                );
+            }
+            if let Some(metadata) = &input.metadata {
+                let pretty = serde_json::to_string_pretty(metadata).unwrap_or_default();
+                let _ = writeln!(
+                    out,
+                    "<details><summary>Metadata</summary><pre>{}</pre></details>",
+                    escape(&pretty)
+                );
+            }
+            let _ = writeln!(out, "</section>");
+        }
+    }
+
+    if !filter.generated_only {
+        let failures: Vec<&FailureEntry> = gallery.failures.iter().filter(|failure| filter.matches(&failure.name)).collect();
+        let _ = writeln!(out, "<h2>Failed ({})</h2>", failures.len());
+        for failure in failures {
+            let _ = writeln!(
+                out,
+                "<section><h3>{0}</h3><a href=\"/files/{1}\"><img src=\"/files/{1}\" alt=\"{0}\" loading=\"lazy\"></a>",
+                escape(&failure.name),
+                escape(&failure.image)
+            );
+            if let Some(log) = &failure.log {
+                let _ = writeln!(out, "<pre>{}</pre>", escape(log));
+            }
+            let _ = writeln!(out, "</section>");
+        }
+    }
+
+    out.push_str("</body></html>\n");
+    out
+}
+
+/// Resolve a requested file inside the output directory
+///
+/// Paths leaving the output directory, e.g. with `..`, are refused.
+pub fn resolve_file(output_dir: &Path, requested: &str) -> Option<PathBuf> {
+    let relative = Path::new(requested);
+    if relative
+        .components()
+        .any(|component| !matches!(component, Component::Normal(_)))
+    {
+        return None;
+    }
+    let path = output_dir.join(relative);
+    path.is_file().then_some(path)
+}
+
+/// Content type of a served file, by extension
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("webp") => "image/webp",
+        Some("json") => "application/json",
+        _ => "text/plain; charset=utf-8",
+    }
+}
+
+/// Build a complete HTTP response
+fn response(status: &str, content_type: &str, body: &[u8]) -> Vec<u8> {
+    let mut response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )
+    .into_bytes();
+    response.extend_from_slice(body);
+    response
+}
+
+/// Answer one request line, e.g. `GET /?q=cat HTTP/1.1`
+fn handle(output_dir: &Path, request_line: &str) -> Vec<u8> {
+    let mut parts = request_line.split_whitespace();
+    let (Some("GET"), Some(target)) = (parts.next(), parts.next()) else {
+        return response("405 Method Not Allowed", "text/plain", b"");
+    };
+    let (route, query) = target.split_once('?').unwrap_or((target, ""));
+
+    if route == "/" || route == "/api/gallery" {
+        let gallery = match scan(output_dir) {
+            Ok(gallery) => gallery,
+            Err(e) => {
+                let message = format!("{:#}", e);
+                return response("500 Internal Server Error", "text/plain", message.as_bytes());
+            }
+        };
+        return if route == "/" {
+            let page = render(&gallery, &Filter::from_query(query));
+            response("200 OK", "text/html; charset=utf-8", page.as_bytes())
+        } else {
+            let json = serde_json::to_vec_pretty(&gallery).unwrap_or_default();
+            response("200 OK", "application/json", &json)
+        };
+    }
+
+    if let Some(requested) = route.strip_prefix("/files/")
+        && let Some(path) = resolve_file(output_dir, &percent_decode(requested))
+        && let Ok(body) = fs::read(&path)
+    {
+        return response("200 OK", content_type(&path), &body);
+    }
+    response("404 Not Found", "text/plain", b"")
+}
+
+/// Serve the gallery of the output directory on `http://<address>/` until the task is dropped
+///
+/// The output directory is read again for every page, so results of a run
+/// still in progress show up on reload. `/api/gallery` returns the same
+/// listing as JSON and `/files/<path>` the files of the output directory.
+///
+/// # Arguments
+/// * `address` - Address to listen on, e.g. "127.0.0.1:8080"
+/// * `output_dir` - Output directory to browse
+///
+/// # Returns
+/// The bound address and the handle of the spawned server task
+pub async fn serve(address: &str, output_dir: &Path) -> Result<(SocketAddr, JoinHandle<()>)> {
+    let listener = TcpListener::bind(address)
+        .await
+        .context(format!("Failed to bind gallery to {}", address))?;
+    let local_addr = listener.local_addr()?;
+    info!("{}", tr_args(Msg::ServingGallery, &[&output_dir.display(), &local_addr]));
+
+    let output_dir = output_dir.to_path_buf();
+    let handle = tokio::spawn(async move {
+        loop {
+            let (mut stream, peer) = match listener.accept().await {
+                Ok(connection) => connection,
+                Err(e) => {
+                    warn!("{}", tr_args(Msg::AcceptFailed, &[&"gallery", &e]));
+                    continue;
+                }
+            };
+            let output_dir = output_dir.clone();
+            tokio::spawn(async move {
+                let mut buffer = [0u8; 4096];
+                let read = stream.read(&mut buffer).await.unwrap_or(0);
+                let request = String::from_utf8_lossy(&buffer[..read]);
+                let request_line = request.lines().next().unwrap_or_default().to_string();
+                debug!("Gallery request from {}: {}", peer, request_line);
+
+                let response = tokio::task::spawn_blocking(move || handle(&output_dir, &request_line))
+                    .await
+                    .unwrap_or_default();
+                let _ = stream.write_all(&response).await;
+                let _ = stream.shutdown().await;
+            });
+        }
+    });
+    Ok((local_addr, handle))
+}
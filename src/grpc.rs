@@ -0,0 +1,557 @@
+use anyhow::{Context, Result};
+use bytes::Bytes;
+use h2::RecvStream;
+use h2::server::SendResponse;
+use http::{HeaderMap, HeaderValue, Request, Response};
+/**
+ * gRPC job control for ControlNet Image Generator
+ *
+ * This module serves the `urasoe.v1.JobControl` service of
+ * `proto/urasoe.proto` next to the daemon: jobs can be submitted to the
+ * spool directory, queried, cancelled and followed as a stream of status
+ * updates. It speaks plaintext HTTP/2 directly and encodes the few small
+ * messages by hand, so the only extra dependencies are the HTTP/2 crates
+ * the HTTP client already uses. Built only with the `grpc` feature.
+ */
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+use crate::daemon::{self, ActiveJob, JobSpec, JobState};
+use crate::i18n::{Msg, tr_args};
+use crate::style::*;
+
+/// How often a progress stream checks the job for changes
+pub const STREAM_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Path prefix of the methods of the service
+const SERVICE: &str = "/urasoe.v1.JobControl/";
+
+/// gRPC status codes used by the service
+const OK: u32 = 0;
+const INVALID_ARGUMENT: u32 = 3;
+const NOT_FOUND: u32 = 5;
+const UNIMPLEMENTED: u32 = 12;
+const INTERNAL: u32 = 13;
+
+/// A field value of an encoded protobuf message
+enum Value<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+}
+
+fn put_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+/// Write a varint field, left out when zero as in proto3
+fn put_uint(buf: &mut Vec<u8>, field: u32, value: u64) {
+    if value != 0 {
+        put_varint(buf, u64::from(field) << 3);
+        put_varint(buf, value);
+    }
+}
+
+/// Write a string field, left out when empty as in proto3
+fn put_string(buf: &mut Vec<u8>, field: u32, value: &str) {
+    if !value.is_empty() {
+        put_varint(buf, (u64::from(field) << 3) | 2);
+        put_varint(buf, value.len() as u64);
+        buf.extend_from_slice(value.as_bytes());
+    }
+}
+
+fn read_varint(bytes: &[u8], position: &mut usize) -> Result<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = *bytes.get(*position).context("Truncated varint")?;
+        *position += 1;
+        value |= u64::from(byte & 0x7F) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(anyhow::anyhow!("Varint too long"))
+}
+
+/// Split an encoded message into its fields, skipping fixed-width ones
+fn fields(bytes: &[u8]) -> Result<Vec<(u32, Value<'_>)>> {
+    let mut fields = Vec::new();
+    let mut position = 0;
+    while position < bytes.len() {
+        let key = read_varint(bytes, &mut position)?;
+        let field = (key >> 3) as u32;
+        match key & 7 {
+            0 => fields.push((field, Value::Varint(read_varint(bytes, &mut position)?))),
+            1 => position += 8,
+            2 => {
+                let length = read_varint(bytes, &mut position)? as usize;
+                let end = position
+                    .checked_add(length)
+                    .filter(|end| *end <= bytes.len())
+                    .context("Truncated field")?;
+                fields.push((field, Value::Bytes(&bytes[position..end])));
+                position = end;
+            }
+            5 => position += 4,
+            wire_type => return Err(anyhow::anyhow!("Unsupported wire type {}", wire_type)),
+        }
+    }
+    if position > bytes.len() {
+        return Err(anyhow::anyhow!("Truncated field"));
+    }
+    Ok(fields)
+}
+
+fn string_value(value: &Value) -> Result<String> {
+    match value {
+        Value::Bytes(bytes) => String::from_utf8(bytes.to_vec()).context("Invalid UTF-8 in string field"),
+        Value::Varint(_) => Err(anyhow::anyhow!("Expected a string field")),
+    }
+}
+
+fn varint_value(value: &Value) -> Result<u64> {
+    match value {
+        Value::Varint(value) => Ok(*value),
+        Value::Bytes(_) => Err(anyhow::anyhow!("Expected a varint field")),
+    }
+}
+
+/// `SubmitRequest` message
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SubmitRequest {
+    /// Directory with the input images
+    pub input_dir: String,
+    /// Directory for the generated images, empty for the daemon output_dir
+    pub output_dir: String,
+    /// Configuration file used instead of the daemon configuration, empty for none
+    pub preset: String,
+    /// Jobs with a higher priority run first
+    pub priority: i32,
+}
+
+impl SubmitRequest {
+    /// Encode in the protobuf wire format
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        put_string(&mut buf, 1, &self.input_dir);
+        put_string(&mut buf, 2, &self.output_dir);
+        put_string(&mut buf, 3, &self.preset);
+        // Negative int32 values are sign extended to ten bytes
+        put_uint(&mut buf, 4, i64::from(self.priority) as u64);
+        buf
+    }
+
+    /// Decode from the protobuf wire format
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        let mut message = Self::default();
+        for (field, value) in fields(bytes)? {
+            match field {
+                1 => message.input_dir = string_value(&value)?,
+                2 => message.output_dir = string_value(&value)?,
+                3 => message.preset = string_value(&value)?,
+                4 => message.priority = varint_value(&value)? as i32,
+                _ => {}
+            }
+        }
+        Ok(message)
+    }
+}
+
+/// `JobRequest` message
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct JobRequest {
+    /// Id of the job
+    pub id: String,
+}
+
+impl JobRequest {
+    /// Encode in the protobuf wire format
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        put_string(&mut buf, 1, &self.id);
+        buf
+    }
+
+    /// Decode from the protobuf wire format
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        let mut message = Self::default();
+        for (field, value) in fields(bytes)? {
+            if field == 1 {
+                message.id = string_value(&value)?;
+            }
+        }
+        Ok(message)
+    }
+}
+
+/// `JobStatus` message
+#[derive(Debug, Clone, PartialEq)]
+pub struct JobStatus {
+    /// Id of the job
+    pub id: String,
+    /// Where the job is in its life cycle
+    pub state: JobState,
+    /// Inputs in a running job
+    pub total: u32,
+    /// Inputs finished successfully so far
+    pub done: u32,
+    /// Inputs failed so far
+    pub failed: u32,
+    /// Images generated so far
+    pub generated: u32,
+    /// Statistics of a finished job as JSON, or the error log of a failed one
+    pub report: String,
+}
+
+/// Number of a job state in `proto/urasoe.proto`
+fn state_number(state: JobState) -> u64 {
+    match state {
+        JobState::Unknown => 0,
+        JobState::Pending => 1,
+        JobState::Running => 2,
+        JobState::Done => 3,
+        JobState::Failed => 4,
+        JobState::Cancelled => 5,
+    }
+}
+
+fn state_from_number(number: u64) -> JobState {
+    match number {
+        1 => JobState::Pending,
+        2 => JobState::Running,
+        3 => JobState::Done,
+        4 => JobState::Failed,
+        5 => JobState::Cancelled,
+        _ => JobState::Unknown,
+    }
+}
+
+impl JobStatus {
+    /// Encode in the protobuf wire format
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        put_string(&mut buf, 1, &self.id);
+        put_uint(&mut buf, 2, state_number(self.state));
+        put_uint(&mut buf, 3, u64::from(self.total));
+        put_uint(&mut buf, 4, u64::from(self.done));
+        put_uint(&mut buf, 5, u64::from(self.failed));
+        put_uint(&mut buf, 6, u64::from(self.generated));
+        put_string(&mut buf, 7, &self.report);
+        buf
+    }
+
+    /// Decode from the protobuf wire format
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        let mut message = Self {
+            id: String::new(),
+            state: JobState::Unknown,
+            total: 0,
+            done: 0,
+            failed: 0,
+            generated: 0,
+            report: String::new(),
+        };
+        for (field, value) in fields(bytes)? {
+            match field {
+                1 => message.id = string_value(&value)?,
+                2 => message.state = state_from_number(varint_value(&value)?),
+                3 => message.total = varint_value(&value)? as u32,
+                4 => message.done = varint_value(&value)? as u32,
+                5 => message.failed = varint_value(&value)? as u32,
+                6 => message.generated = varint_value(&value)? as u32,
+                7 => message.report = string_value(&value)?,
+                _ => {}
+            }
+        }
+        Ok(message)
+    }
+
+    /// Whether the job will not change any more
+    pub fn is_final(&self) -> bool {
+        !matches!(self.state, JobState::Pending | JobState::Running)
+    }
+}
+
+/// Wrap a message in a gRPC length-prefixed frame, uncompressed
+pub fn frame(message: &[u8]) -> Bytes {
+    let mut framed = Vec::with_capacity(message.len() + 5);
+    framed.push(0);
+    framed.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    framed.extend_from_slice(message);
+    Bytes::from(framed)
+}
+
+/// Read the single message of a gRPC request body
+pub fn unframe(body: &[u8]) -> Result<&[u8]> {
+    let header = body.get(..5).context("Request body is shorter than a gRPC frame")?;
+    if header[0] != 0 {
+        return Err(anyhow::anyhow!("Compressed messages are not supported"));
+    }
+    let length = u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize;
+    body.get(5..5 + length).context("Truncated gRPC message")
+}
+
+/// An error answered with a gRPC status
+struct Status {
+    code: u32,
+    message: String,
+}
+
+impl Status {
+    fn new(code: u32, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+/// State shared by all requests
+struct Service {
+    spool_dir: PathBuf,
+    active: Arc<ActiveJob>,
+}
+
+impl Service {
+    /// Current status of a job, with the progress of a running one
+    fn status(&self, id: &str) -> JobStatus {
+        let (state, report) = daemon::job_state(&self.spool_dir, id, &self.active);
+        let mut status = JobStatus {
+            id: id.to_string(),
+            state,
+            total: 0,
+            done: 0,
+            failed: 0,
+            generated: 0,
+            report: report.unwrap_or_default(),
+        };
+        if state == JobState::Running
+            && let Some((_, control)) = self.active.current()
+        {
+            let progress = control.status();
+            status.total = progress.total as u32;
+            status.done = progress.done as u32;
+            status.failed = progress.failed as u32;
+            status.generated = progress.generated as u32;
+        }
+        status
+    }
+
+    /// Status of a job that has to exist
+    fn existing(&self, id: &str) -> Result<JobStatus, Status> {
+        let status = self.status(id);
+        if status.state == JobState::Unknown {
+            return Err(Status::new(NOT_FOUND, format!("No job with id '{}'", id)));
+        }
+        Ok(status)
+    }
+
+    fn submit(&self, request: SubmitRequest) -> Result<JobStatus, Status> {
+        if request.input_dir.is_empty() {
+            return Err(Status::new(INVALID_ARGUMENT, "input_dir is required"));
+        }
+        let optional = |value: String| (!value.is_empty()).then_some(value);
+        let spec = JobSpec {
+            input_dir: request.input_dir,
+            output_dir: optional(request.output_dir),
+            preset: optional(request.preset),
+            priority: request.priority,
+        };
+        let id = daemon::submit_job(&self.spool_dir, &spec)
+            .map_err(|e| Status::new(INTERNAL, format!("{:#}", e)))?;
+        Ok(self.status(&id))
+    }
+
+    fn cancel(&self, request: JobRequest) -> Result<JobStatus, Status> {
+        self.existing(&request.id)?;
+        daemon::cancel_job(&self.spool_dir, &request.id, &self.active)
+            .map_err(|e| Status::new(INTERNAL, format!("{:#}", e)))?;
+        Ok(self.status(&request.id))
+    }
+}
+
+/// Headers of a gRPC response
+fn response_headers() -> Response<()> {
+    let mut response = Response::new(());
+    response
+        .headers_mut()
+        .insert("content-type", HeaderValue::from_static("application/grpc"));
+    response
+}
+
+/// Trailers carrying a gRPC status
+fn status_trailers(code: u32, message: &str) -> HeaderMap {
+    let mut trailers = HeaderMap::new();
+    trailers.insert("grpc-status", HeaderValue::from(code));
+    if let Ok(message) = HeaderValue::from_str(message)
+        && !message.is_empty()
+    {
+        trailers.insert("grpc-message", message);
+    }
+    trailers
+}
+
+/// Answer with an error status and no message
+fn send_error(mut respond: SendResponse<Bytes>, status: Status) {
+    let mut response = response_headers();
+    response
+        .headers_mut()
+        .extend(status_trailers(status.code, &status.message));
+    if let Err(e) = respond.send_response(response, true) {
+        debug!("Failed to send gRPC error: {}", e);
+    }
+}
+
+/// Answer with a single message
+fn send_unary(mut respond: SendResponse<Bytes>, message: &JobStatus) -> Result<()> {
+    let mut stream = respond.send_response(response_headers(), false)?;
+    stream.send_data(frame(&message.encode()), false)?;
+    stream.send_trailers(status_trailers(OK, ""))?;
+    Ok(())
+}
+
+/// Send the status of a job whenever it changes, until it is archived
+async fn send_progress(mut respond: SendResponse<Bytes>, service: &Service, id: &str) -> Result<()> {
+    let mut stream = respond.send_response(response_headers(), false)?;
+    let mut last = None;
+    loop {
+        let status = service.status(id);
+        if last.as_ref() != Some(&status) {
+            stream.send_data(frame(&status.encode()), false)?;
+        }
+        if status.is_final() {
+            break;
+        }
+        last = Some(status);
+        tokio::time::sleep(STREAM_INTERVAL).await;
+    }
+    stream.send_trailers(status_trailers(OK, ""))?;
+    Ok(())
+}
+
+/// Read the whole request body
+async fn read_body(mut body: RecvStream) -> Result<Vec<u8>> {
+    let mut buffer = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk?;
+        let _ = body.flow_control().release_capacity(chunk.len());
+        buffer.extend_from_slice(&chunk);
+    }
+    Ok(buffer)
+}
+
+/// Answer one call
+async fn handle(request: Request<RecvStream>, respond: SendResponse<Bytes>, service: Arc<Service>) {
+    let method = request.uri().path().strip_prefix(SERVICE).unwrap_or_default().to_string();
+    debug!("gRPC call: {}", request.uri().path());
+    let body = match read_body(request.into_body()).await {
+        Ok(body) => body,
+        Err(e) => return send_error(respond, Status::new(INTERNAL, format!("{:#}", e))),
+    };
+    let message = match unframe(&body) {
+        Ok(message) => message,
+        Err(e) => return send_error(respond, Status::new(INVALID_ARGUMENT, format!("{:#}", e))),
+    };
+    let invalid = |e: anyhow::Error| Status::new(INVALID_ARGUMENT, format!("{:#}", e));
+
+    let reply = match method.as_str() {
+        "Submit" => SubmitRequest::decode(message)
+            .map_err(invalid)
+            .and_then(|request| service.submit(request)),
+        "Status" => JobRequest::decode(message)
+            .map_err(invalid)
+            .and_then(|request| service.existing(&request.id)),
+        "Cancel" => JobRequest::decode(message)
+            .map_err(invalid)
+            .and_then(|request| service.cancel(request)),
+        "StreamProgress" => {
+            let result = JobRequest::decode(message)
+                .map_err(invalid)
+                .and_then(|request| service.existing(&request.id).map(|_| request));
+            match result {
+                Ok(request) => {
+                    if let Err(e) = send_progress(respond, &service, &request.id).await {
+                        debug!("Progress stream of {} ended: {:#}", request.id, e);
+                    }
+                }
+                Err(status) => send_error(respond, status),
+            }
+            return;
+        }
+        _ => Err(Status::new(UNIMPLEMENTED, format!("Unknown method '{}'", method))),
+    };
+    match reply {
+        Ok(status) => {
+            if let Err(e) = send_unary(respond, &status) {
+                debug!("Failed to send gRPC reply: {:#}", e);
+            }
+        }
+        Err(status) => send_error(respond, status),
+    }
+}
+
+/// Serve the job control service on `address` until the task is dropped
+///
+/// Presets of submitted jobs are resolved relative to the spool directory.
+///
+/// # Arguments
+/// * `address` - Address to listen on, e.g. "127.0.0.1:50051"
+/// * `spool_dir` - Spool directory of the daemon
+/// * `active` - The job the daemon is running
+///
+/// # Returns
+/// The bound address and the handle of the spawned server task
+pub async fn serve(address: &str, spool_dir: &Path, active: Arc<ActiveJob>) -> Result<(SocketAddr, JoinHandle<()>)> {
+    let listener = TcpListener::bind(address)
+        .await
+        .context(format!("Failed to bind gRPC service to {}", address))?;
+    let local_addr = listener.local_addr()?;
+    info!("{}", tr_args(Msg::ServingGrpc, &[&local_addr]).blue());
+
+    let service = Arc::new(Service {
+        spool_dir: spool_dir.to_path_buf(),
+        active,
+    });
+    let handle = tokio::spawn(async move {
+        loop {
+            let (socket, peer) = match listener.accept().await {
+                Ok(connection) => connection,
+                Err(e) => {
+                    warn!("{}", tr_args(Msg::AcceptFailed, &[&"gRPC", &e]));
+                    continue;
+                }
+            };
+            let service = Arc::clone(&service);
+            tokio::spawn(async move {
+                let mut connection = match h2::server::handshake(socket).await {
+                    Ok(connection) => connection,
+                    Err(e) => {
+                        debug!("HTTP/2 handshake with {} failed: {}", peer, e);
+                        return;
+                    }
+                };
+                while let Some(call) = connection.accept().await {
+                    match call {
+                        Ok((request, respond)) => {
+                            tokio::spawn(handle(request, respond, Arc::clone(&service)));
+                        }
+                        Err(e) => {
+                            debug!("gRPC connection from {} failed: {}", peer, e);
+                            break;
+                        }
+                    }
+                }
+            });
+        }
+    });
+    Ok((local_addr, handle))
+}
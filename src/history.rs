@@ -0,0 +1,236 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+/**
+ * Generation history for ControlNet Image Generator
+ *
+ * This module searches past runs recorded in an output directory: the
+ * metadata files written next to generated images, the failure records of
+ * the dead-letter folder and the failed inputs of the job queue. Entries can
+ * be narrowed down by prompt, model and failure, so old results are found
+ * without reading through the JSON files by hand.
+ */
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+use crate::file_utils::{DEAD_LETTER_DIR, ImageMetadata, find_metadata};
+use crate::i18n::{Msg, tr_args};
+use crate::queue::{JobQueue, JobStatus};
+use crate::style::*;
+
+/// Titles of the history table columns
+pub const TABLE_HEADERS: [&str; 8] = ["TIME", "STATUS", "INPUT", "CHECKPOINT", "CONTROLNET", "SEED", "PROMPT", "OUTPUT"];
+
+/// Most characters of a prompt shown in the history table
+const PROMPT_WIDTH: usize = 40;
+
+/// Which entries of the history are listed
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HistoryQuery {
+    /// Only entries whose prompt contains this text, ignoring case
+    pub prompt: Option<String>,
+    /// Only entries whose checkpoint, ControlNet model or module contains this text, ignoring case
+    pub model: Option<String>,
+    /// Only failed inputs
+    pub failed: bool,
+}
+
+/// One generation, or failure, of an input
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HistoryEntry {
+    /// Input image
+    pub source_image: String,
+    /// Whether generating the input failed
+    pub failed: bool,
+    /// Generated images, in variant order
+    pub images: Vec<PathBuf>,
+    /// Metadata file of the generation, or failure record of a failed input
+    pub record: Option<PathBuf>,
+    /// Settings the input was generated with, unknown for failures that were not dead-lettered
+    pub metadata: Option<ImageMetadata>,
+    /// Error of a failed input, from its dead-letter error log
+    pub error: Option<String>,
+}
+
+impl HistoryEntry {
+    /// When the input was generated or failed, empty when not recorded
+    pub fn timestamp(&self) -> &str {
+        self.metadata.as_ref().map_or("", |metadata| metadata.timestamp.as_str())
+    }
+}
+
+impl HistoryQuery {
+    /// Whether an entry is listed
+    ///
+    /// Entries without recorded settings never match a prompt or model.
+    pub fn matches(&self, entry: &HistoryEntry) -> bool {
+        if self.failed && !entry.failed {
+            return false;
+        }
+        if self.prompt.is_none() && self.model.is_none() {
+            return true;
+        }
+        let Some(metadata) = &entry.metadata else {
+            return false;
+        };
+        let contains = |text: &str, part: &str| text.to_lowercase().contains(&part.to_lowercase());
+        let prompt_matches = self.prompt.as_ref().is_none_or(|prompt| contains(&metadata.prompt, prompt));
+        let model_matches = self.model.as_ref().is_none_or(|model| {
+            [&metadata.checkpoint_model, &metadata.controlnet_model, &metadata.controlnet_module]
+                .iter()
+                .any(|name| contains(name, model))
+        });
+        prompt_matches && model_matches
+    }
+}
+
+/// Search the runs recorded in an output directory
+///
+/// Preset subdirectories are searched too.
+///
+/// # Arguments
+/// * `output_dir` - Output directory of the runs
+/// * `queue_path` - Job queue file of the last run, for failed inputs that were not dead-lettered
+/// * `query` - Which entries are listed
+///
+/// # Returns
+/// The matching entries, most recent first
+pub fn search(output_dir: &Path, queue_path: &Path, query: &HistoryQuery) -> Result<Vec<HistoryEntry>> {
+    let mut entries = Vec::new();
+    for (path, metadata) in find_metadata(output_dir)? {
+        entries.push(HistoryEntry {
+            source_image: metadata.source_image.clone(),
+            failed: false,
+            images: generated_images(&path),
+            record: Some(path),
+            metadata: Some(metadata),
+            error: None,
+        });
+    }
+    if output_dir.is_dir() {
+        collect_failures(output_dir, &mut entries)?;
+    }
+    if queue_path.is_file() {
+        let queue = JobQueue::open(queue_path)?;
+        for queued in queue.entries().iter().filter(|queued| queued.status == JobStatus::Failed) {
+            let source_image = queued.path.to_string_lossy().to_string();
+            if !entries.iter().any(|entry| entry.failed && entry.source_image == source_image) {
+                entries.push(HistoryEntry {
+                    source_image,
+                    failed: true,
+                    images: Vec::new(),
+                    record: None,
+                    metadata: None,
+                    error: None,
+                });
+            }
+        }
+    }
+
+    entries.retain(|entry| query.matches(entry));
+    entries.sort_by(|a, b| b.timestamp().cmp(a.timestamp()));
+    Ok(entries)
+}
+
+/// Images generated with a metadata file, the `<stem>-<n>.png` files next to it
+pub fn generated_images(metadata_path: &Path) -> Vec<PathBuf> {
+    let name = metadata_path.file_name().unwrap_or_default().to_string_lossy();
+    let Some(stem) = name.strip_suffix("-metadata.json") else {
+        return Vec::new();
+    };
+    let prefix = format!("{}-", stem);
+    let dir = metadata_path.parent().unwrap_or(Path::new("."));
+    let mut images: Vec<(u32, PathBuf)> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let index = name.strip_prefix(&prefix)?.strip_suffix(".png")?.parse().ok()?;
+            Some((index, entry.path()))
+        })
+        .collect();
+    images.sort();
+    images.into_iter().map(|(_, path)| path).collect()
+}
+
+/// Read the failure records of every dead-letter folder below a directory
+fn collect_failures(dir: &Path, entries: &mut Vec<HistoryEntry>) -> Result<()> {
+    let children = fs::read_dir(dir).context(format!("Failed to read {}", dir.display()))?;
+    for child in children.flatten() {
+        let path = child.path();
+        if !path.is_dir() {
+            continue;
+        }
+        if child.file_name() == DEAD_LETTER_DIR {
+            collect_dead_letters(&path, entries)?;
+        } else {
+            collect_failures(&path, entries)?;
+        }
+    }
+    Ok(())
+}
+
+/// Read the failure records of a dead-letter folder, including the input folders mirrored in it
+fn collect_dead_letters(path: &Path, entries: &mut Vec<HistoryEntry>) -> Result<()> {
+    for file in fs::read_dir(path)?.flatten() {
+        if file.path().is_dir() {
+            collect_dead_letters(&file.path(), entries)?;
+            continue;
+        }
+        let name = file.file_name().to_string_lossy().to_string();
+        let Some(input) = name.strip_suffix(".metadata.json") else {
+            continue;
+        };
+        let metadata = match ImageMetadata::read(&file.path()) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                warn!("{}", tr_args(Msg::SkippingFailureRecord, &[&format!("{:#}", e)]).yellow());
+                continue;
+            }
+        };
+        let log = fs::read_to_string(path.join(format!("{}.error.log", input))).unwrap_or_default();
+        let error = log.lines().find_map(|line| line.strip_prefix("error: ")).map(str::to_string);
+        entries.push(HistoryEntry {
+            source_image: metadata.source_image.clone(),
+            failed: true,
+            images: Vec::new(),
+            record: Some(file.path()),
+            metadata: Some(metadata),
+            error,
+        });
+    }
+    Ok(())
+}
+
+/// Cells of the history table, in the order of `TABLE_HEADERS`
+pub fn row(entry: &HistoryEntry) -> Vec<String> {
+    let metadata = entry.metadata.as_ref();
+    let timestamp = entry.timestamp().split('.').next().unwrap_or_default().to_string();
+    let status = if entry.failed { "failed" } else { "generated" };
+    let outputs = if entry.failed {
+        entry.error.clone().unwrap_or_default()
+    } else {
+        entry.images.iter().map(|image| image.display().to_string()).collect::<Vec<_>>().join(" ")
+    };
+    vec![
+        timestamp,
+        status.to_string(),
+        entry.source_image.clone(),
+        metadata.map_or(String::new(), |metadata| metadata.checkpoint_model.clone()),
+        metadata.map_or(String::new(), |metadata| metadata.controlnet_model.clone()),
+        metadata.map_or(String::new(), |metadata| metadata.seed.to_string()),
+        metadata.map_or(String::new(), |metadata| shorten(&metadata.prompt, PROMPT_WIDTH)),
+        outputs,
+    ]
+}
+
+/// Text cut to a number of characters, ending with an ellipsis when cut
+pub fn shorten(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        return text.to_string();
+    }
+    let mut short: String = text.chars().take(width - 1).collect();
+    short.push('…');
+    short
+}
@@ -0,0 +1,181 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+/**
+ * Pipeline hooks for ControlNet Image Generator
+ *
+ * This module runs user supplied shell commands at the start and end of a
+ * run and before and after each image, so external steps such as EXIF
+ * tools or uploads slot into the pipeline. Paths and generation parameters
+ * are passed to the commands as `URASOE_*` environment variables.
+ */
+use std::path::Path;
+use tokio::process::Command;
+use tracing::debug;
+
+use crate::config::Config;
+use crate::processing::{ImageResult, ProcessingStats};
+use crate::style::*;
+
+/// Shell commands run at points of the pipeline
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct HooksConfig {
+    /// Run before the first image; a failure aborts the run
+    #[serde(default)]
+    pub before_run: Option<String>,
+    /// Run after the last image
+    #[serde(default)]
+    pub after_run: Option<String>,
+    /// Run before each image; a failure marks the image as failed
+    #[serde(default)]
+    pub before_image: Option<String>,
+    /// Run after each image, whether it succeeded or not
+    #[serde(default)]
+    pub after_image: Option<String>,
+}
+
+/// Point of the pipeline a hook is attached to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookEvent {
+    BeforeRun,
+    AfterRun,
+    BeforeImage,
+    AfterImage,
+}
+
+impl HookEvent {
+    /// Name of the hook as used in the configuration file
+    pub fn name(&self) -> &'static str {
+        match self {
+            HookEvent::BeforeRun => "before_run",
+            HookEvent::AfterRun => "after_run",
+            HookEvent::BeforeImage => "before_image",
+            HookEvent::AfterImage => "after_image",
+        }
+    }
+}
+
+/// Environment variables passed to hook commands
+pub type HookEnv = Vec<(String, String)>;
+
+impl HooksConfig {
+    /// Command configured for an event
+    pub fn command(&self, event: HookEvent) -> Option<&str> {
+        match event {
+            HookEvent::BeforeRun => self.before_run.as_deref(),
+            HookEvent::AfterRun => self.after_run.as_deref(),
+            HookEvent::BeforeImage => self.before_image.as_deref(),
+            HookEvent::AfterImage => self.after_image.as_deref(),
+        }
+    }
+
+    /// Run the command configured for an event, if any
+    ///
+    /// # Arguments
+    /// * `event` - Point of the pipeline that was reached
+    /// * `env` - Environment variables for the command
+    ///
+    /// # Returns
+    /// An error when the command could not be started or exited unsuccessfully
+    pub async fn fire(&self, event: HookEvent, env: &HookEnv) -> Result<()> {
+        match self.command(event) {
+            Some(command) => run_hook(event, command, env).await,
+            None => Ok(()),
+        }
+    }
+}
+
+/// Run a hook command through the platform shell
+async fn run_hook(event: HookEvent, command: &str, env: &HookEnv) -> Result<()> {
+    debug!("{} {} {}", "Running hook".blue(), event.name(), command);
+
+    let mut shell = if cfg!(target_os = "windows") {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    let output = shell
+        .arg(command)
+        .envs(env.iter().map(|(name, value)| (name, value)))
+        .output()
+        .await
+        .context(format!("Failed to start {} hook", event.name()))?;
+
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        debug!(event = "hook_output", hook = event.name(), "{}", line);
+    }
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow::anyhow!(
+            "{} hook exited with {}: {}",
+            event.name(),
+            output.status,
+            stderr.trim()
+        ));
+    }
+    Ok(())
+}
+
+/// Environment describing the run: directories and generation parameters
+pub fn run_env(config: &Config) -> HookEnv {
+    [
+        ("URASOE_INPUT_DIR", config.input_dir.clone()),
+        ("URASOE_OUTPUT_DIR", config.output_dir.clone()),
+        ("URASOE_CHECKPOINT", config.checkpoint_model.clone()),
+        ("URASOE_MODEL", config.model.clone()),
+        ("URASOE_MODULE", config.controlnet_module.clone()),
+        ("URASOE_PROMPT", config.prompt.clone()),
+        ("URASOE_NEGATIVE_PROMPT", config.negative_prompt.clone()),
+        ("URASOE_STEPS", config.steps.to_string()),
+        ("URASOE_CFG", config.cfg.to_string()),
+        ("URASOE_WIDTH", config.width.to_string()),
+        ("URASOE_HEIGHT", config.height.to_string()),
+        ("URASOE_BATCH_SIZE", config.batch_size.to_string()),
+        ("URASOE_RUN_ID", config.run_id.clone().unwrap_or_default()),
+    ]
+    .into_iter()
+    .map(|(name, value)| (name.to_string(), value))
+    .collect()
+}
+
+/// Environment for the end of a run, adding the outcome counts
+pub fn finished_run_env(config: &Config, stats: &ProcessingStats) -> HookEnv {
+    let mut env = run_env(config);
+    env.push(("URASOE_SUCCESS_COUNT".to_string(), stats.success_count.to_string()));
+    env.push(("URASOE_FAILED_COUNT".to_string(), stats.failed_paths.len().to_string()));
+    env.push(("URASOE_GENERATED_COUNT".to_string(), stats.generated_count.to_string()));
+    if let Some(stats_out) = &config.stats_out {
+        env.push(("URASOE_STATS_FILE".to_string(), stats_out.clone()));
+    }
+    env
+}
+
+/// Environment for a single image, adding its paths and the backend used
+pub fn image_env(config: &Config, image_path: &Path, backend: &str) -> HookEnv {
+    let mut env = run_env(config);
+    env.push(("URASOE_IMAGE".to_string(), image_path.to_string_lossy().into_owned()));
+    env.push((
+        "URASOE_IMAGE_OUTPUT_DIR".to_string(),
+        config.output_folder(image_path).to_string_lossy().into_owned(),
+    ));
+    env.push(("URASOE_BACKEND".to_string(), backend.to_string()));
+    env
+}
+
+/// Environment for a processed image, adding its outcome
+pub fn finished_image_env(config: &Config, image_path: &Path, backend: &str, result: &ImageResult) -> HookEnv {
+    let mut env = image_env(config, image_path, backend);
+    let status = if result.success { "success" } else { "failed" };
+    env.push(("URASOE_STATUS".to_string(), status.to_string()));
+    env.push(("URASOE_GENERATED".to_string(), result.generated.to_string()));
+    env.push(("URASOE_ATTEMPTS".to_string(), result.attempts.to_string()));
+    env.push(("URASOE_DURATION_MS".to_string(), result.duration_ms.to_string()));
+    env.push(("URASOE_OUTPUTS".to_string(), result.outputs.join("\n")));
+    if let Some(error) = &result.error {
+        env.push(("URASOE_ERROR".to_string(), error.clone()));
+    }
+    env
+}
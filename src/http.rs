@@ -0,0 +1,341 @@
+use anyhow::{Context, Result};
+use base64::{Engine, prelude::BASE64_STANDARD};
+use chrono::Utc;
+use futures::future::BoxFuture;
+use reqwest::header::{AUTHORIZATION, HeaderValue, USER_AGENT};
+use reqwest::{Client, Request, RequestBuilder, Response};
+use serde::de::DeserializeOwned;
+use tokio::sync::mpsc;
+use tracing::warn;
+/**
+ * HTTP layer for ControlNet Image Generator
+ *
+ * This module lets programs using the library decide how requests to the
+ * Stable Diffusion API are made. `HttpStack` pairs a `reqwest::Client`,
+ * which may be built with proxies, default headers or TLS settings of its
+ * own, with a stack of `Middleware` that sees every request and response,
+ * e.g. for tracing, refreshing credentials or instrumenting tests.
+ * `RequestIdentity` sends the user agent, run id and credentials of the
+ * configuration with every request.
+ */
+use std::fmt;
+use std::io::{self, BufReader, Read, Write};
+use std::sync::Arc;
+
+use crate::i18n::{Msg, tr_args};
+use crate::style::*;
+
+/// User agent sent with every request unless configured otherwise
+pub const DEFAULT_USER_AGENT: &str = concat!("urasoe/", env!("CARGO_PKG_VERSION"));
+
+/// Header naming the run a request belongs to
+pub const RUN_ID_HEADER: &str = "x-urasoe-run-id";
+
+/// Environment variable with the user name for the API, used when the configuration has none
+pub const API_USERNAME_ENV: &str = "URASOE_API_USERNAME";
+
+/// Environment variable with the password for the API, used when the configuration has none
+pub const API_PASSWORD_ENV: &str = "URASOE_API_PASSWORD";
+
+/// Environment variable with the bearer token for the API, used when the configuration has none
+pub const API_TOKEN_ENV: &str = "URASOE_API_TOKEN";
+
+/// Chunks of a response body received but not yet parsed, bounding what is buffered
+const CHUNKS_IN_FLIGHT: usize = 16;
+
+/// A layer around every request sent to the API
+///
+/// A middleware may change the request, answer it without calling the
+/// rest of the stack, or send it again, e.g. after refreshing a token.
+pub trait Middleware: Send + Sync {
+    /// Handle one request, passing it on with `next.run(request)`
+    ///
+    /// # Arguments
+    /// * `request` - Request about to be sent
+    /// * `next` - The rest of the stack, ending in the HTTP client
+    ///
+    /// # Returns
+    /// The response given to the caller
+    fn handle<'a>(&'a self, request: Request, next: Next<'a>) -> BoxFuture<'a, Result<Response>>;
+}
+
+/// The part of the stack after a middleware
+#[derive(Clone, Copy)]
+pub struct Next<'a> {
+    client: &'a Client,
+    middleware: &'a [Arc<dyn Middleware>],
+}
+
+impl<'a> Next<'a> {
+    /// Pass the request on to the next middleware, or send it when none is left
+    pub fn run(self, request: Request) -> BoxFuture<'a, Result<Response>> {
+        match self.middleware.split_first() {
+            Some((first, rest)) => first.handle(
+                request,
+                Next {
+                    client: self.client,
+                    middleware: rest,
+                },
+            ),
+            None => Box::pin(async move { Ok(self.client.execute(request).await?) }),
+        }
+    }
+}
+
+/// HTTP client and the middleware requests pass through, outermost first
+#[derive(Clone)]
+pub struct HttpStack {
+    client: Client,
+    middleware: Vec<Arc<dyn Middleware>>,
+}
+
+impl HttpStack {
+    /// Stack sending requests with the given client, without middleware
+    pub fn new(client: Client) -> Self {
+        Self {
+            client,
+            middleware: Vec::new(),
+        }
+    }
+
+    /// Send requests with another client, keeping the middleware
+    pub fn with_client(mut self, client: Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Add a middleware inside those added before
+    pub fn with(mut self, middleware: Arc<dyn Middleware>) -> Self {
+        self.middleware.push(middleware);
+        self
+    }
+
+    /// Client requests are built and sent with
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// Build a request and send it through the middleware
+    ///
+    /// Errors of the HTTP client keep their type in the chain of causes,
+    /// so connection failures can still be told apart.
+    pub async fn send(&self, request: RequestBuilder) -> Result<Response> {
+        let next = Next {
+            client: &self.client,
+            middleware: &self.middleware,
+        };
+        next.run(request.build()?).await
+    }
+}
+
+impl Default for HttpStack {
+    fn default() -> Self {
+        Self::new(default_client())
+    }
+}
+
+impl fmt::Debug for HttpStack {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HttpStack")
+            .field("client", &self.client)
+            .field("middleware", &self.middleware.len())
+            .finish()
+    }
+}
+
+/// HTTP client sending the default user agent
+pub fn default_client() -> Client {
+    default_client_builder().build().unwrap_or_else(|_| Client::new())
+}
+
+/// Builder of an HTTP client sending the default user agent, for adding e.g. a timeout
+pub fn default_client_builder() -> reqwest::ClientBuilder {
+    Client::builder().user_agent(DEFAULT_USER_AGENT)
+}
+
+/// Credentials of a secured API
+#[derive(Clone, PartialEq)]
+pub enum ApiAuth {
+    /// HTTP basic authentication, as the Web UI started with `--api-auth user:password` expects
+    Basic { username: String, password: String },
+    /// Bearer token, e.g. for an authenticating reverse proxy
+    Bearer(String),
+}
+
+impl ApiAuth {
+    /// Value of the `Authorization` header, marked sensitive so it is never logged
+    pub fn header_value(&self) -> Result<HeaderValue> {
+        let value = match self {
+            Self::Basic { username, password } => {
+                format!("Basic {}", BASE64_STANDARD.encode(format!("{}:{}", username, password)))
+            }
+            Self::Bearer(token) => format!("Bearer {}", token),
+        };
+        let mut value = HeaderValue::from_str(&value).context("Credentials cannot be sent in a header")?;
+        value.set_sensitive(true);
+        Ok(value)
+    }
+}
+
+impl fmt::Debug for ApiAuth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Basic { username, .. } => f
+                .debug_struct("Basic")
+                .field("username", username)
+                .field("password", &"<redacted>")
+                .finish(),
+            Self::Bearer(_) => f.debug_tuple("Bearer").field(&"<redacted>").finish(),
+        }
+    }
+}
+
+/// Identifies the program and the run behind every request
+///
+/// Sets the `User-Agent` header and, during a run, the `X-Urasoe-Run-Id`
+/// header, so the logs of a server shared by several people can tell
+/// which traffic came from which run. With credentials, the
+/// `Authorization` header is set as well.
+#[derive(Debug, Clone)]
+pub struct RequestIdentity {
+    user_agent: HeaderValue,
+    run_id: Option<HeaderValue>,
+    authorization: Option<HeaderValue>,
+}
+
+impl RequestIdentity {
+    /// Identity with the given user agent and run
+    ///
+    /// Values that cannot be sent in a header are replaced by the default
+    /// user agent, or left out for the run id, with a warning.
+    ///
+    /// # Arguments
+    /// * `user_agent` - Value of the `User-Agent` header
+    /// * `run_id` - Identifier of the run, `None` outside runs
+    pub fn new(user_agent: &str, run_id: Option<&str>) -> Self {
+        let user_agent = HeaderValue::from_str(user_agent).unwrap_or_else(|_| {
+            warn!("{}", tr_args(Msg::InvalidUserAgent, &[&format!("{:?}", user_agent)]).yellow());
+            HeaderValue::from_static(DEFAULT_USER_AGENT)
+        });
+        let run_id = run_id.and_then(|run_id| match HeaderValue::from_str(run_id) {
+            Ok(value) => Some(value),
+            Err(_) => {
+                warn!("{}", tr_args(Msg::InvalidRunId, &[&format!("{:?}", run_id)]).yellow());
+                None
+            }
+        });
+        Self {
+            user_agent,
+            run_id,
+            authorization: None,
+        }
+    }
+
+    /// Also send the given credentials, nothing when `None`
+    ///
+    /// Credentials that cannot be sent in a header are left out with a warning.
+    pub fn with_auth(mut self, auth: Option<&ApiAuth>) -> Self {
+        self.authorization = auth.and_then(|auth| match auth.header_value() {
+            Ok(value) => Some(value),
+            Err(e) => {
+                warn!("{}", tr_args(Msg::InvalidCredentials, &[&format!("{:#}", e)]).yellow());
+                None
+            }
+        });
+        self
+    }
+}
+
+impl Middleware for RequestIdentity {
+    fn handle<'a>(&'a self, mut request: Request, next: Next<'a>) -> BoxFuture<'a, Result<Response>> {
+        request.headers_mut().insert(USER_AGENT, self.user_agent.clone());
+        if let Some(run_id) = &self.run_id {
+            request.headers_mut().insert(RUN_ID_HEADER, run_id.clone());
+        }
+        if let Some(authorization) = &self.authorization {
+            request.headers_mut().insert(AUTHORIZATION, authorization.clone());
+        }
+        next.run(request)
+    }
+}
+
+/// Parse a JSON response body while it is being received
+///
+/// The body is handed to the parser chunk by chunk instead of being read
+/// into memory first, so a response of several hundred megabytes of base64
+/// images is never held twice, once as text and once parsed.
+///
+/// # Arguments
+/// * `response` - Response whose body is JSON
+/// * `copy` - Where to also write the body as received, e.g. a fixture file
+///
+/// # Returns
+/// The parsed body, or the error of receiving it when the connection failed
+pub async fn read_json_streamed<T>(mut response: Response, mut copy: Option<&mut (dyn Write + Send)>) -> Result<T>
+where
+    T: DeserializeOwned + Send + 'static,
+{
+    let (sender, chunks) = mpsc::channel(CHUNKS_IN_FLIGHT);
+    let parser = tokio::task::spawn_blocking(move || {
+        serde_json::from_reader::<_, T>(BufReader::new(ChunkReader {
+            chunks,
+            current: Vec::new(),
+            position: 0,
+        }))
+    });
+
+    let mut received = Ok(());
+    loop {
+        match response.chunk().await {
+            Ok(Some(chunk)) => {
+                if let Some(copy) = copy.as_mut() {
+                    copy.write_all(&chunk).context("Failed to copy the response body")?;
+                }
+                // The parser stops reading at the first syntax error, which it reports itself
+                if sender.send(chunk.to_vec()).await.is_err() {
+                    break;
+                }
+            }
+            Ok(None) => break,
+            Err(e) => {
+                received = Err(e);
+                break;
+            }
+        }
+    }
+    drop(sender);
+
+    let parsed = parser.await.context("Response parser stopped unexpectedly")?;
+    received.context("Failed to receive the response body")?;
+    Ok(parsed?)
+}
+
+/// Blocking reader over the chunks of a body received on the async side
+struct ChunkReader {
+    chunks: mpsc::Receiver<Vec<u8>>,
+    current: Vec<u8>,
+    position: usize,
+}
+
+impl Read for ChunkReader {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.current.len() {
+            match self.chunks.blocking_recv() {
+                Some(chunk) => {
+                    self.current = chunk;
+                    self.position = 0;
+                }
+                None => return Ok(0),
+            }
+        }
+        let count = buffer.len().min(self.current.len() - self.position);
+        buffer[..count].copy_from_slice(&self.current[self.position..self.position + count]);
+        self.position += count;
+        Ok(count)
+    }
+}
+
+/// New identifier for a run, the start time followed by random digits
+pub fn new_run_id() -> String {
+    format!("{}-{:08x}", Utc::now().format("%Y%m%dT%H%M%SZ"), rand::random::<u32>())
+}
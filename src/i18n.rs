@@ -0,0 +1,1076 @@
+use serde::{Deserialize, Serialize};
+/**
+ * Localization for ControlNet Image Generator
+ *
+ * This module holds the catalog of console messages in English, Finnish and
+ * Japanese. The language is picked with `--lang` or the `lang` configuration
+ * key, and otherwise from the LC_ALL, LC_MESSAGES or LANG environment
+ * variables, falling back to English.
+ *
+ * Messages are templates where `{}` takes the next argument and `{0}`, `{1}`
+ * a given one, so translations can order the values as their grammar needs.
+ * JSON log lines stay in English unless a language is given explicitly, and
+ * machine readable output is not translated.
+ */
+use std::fmt::Display;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// Language of console messages
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum Lang {
+    /// English
+    #[default]
+    En,
+    /// Finnish, suomi
+    Fi,
+    /// Japanese, 日本語
+    Ja,
+}
+
+impl Lang {
+    /// Language of a locale name such as "fi_FI.UTF-8" or "ja", None for other languages
+    pub fn from_locale(locale: &str) -> Option<Lang> {
+        let language = locale.split(['_', '.', '@', '-']).next()?.to_lowercase();
+        match language.as_str() {
+            "en" => Some(Lang::En),
+            "fi" => Some(Lang::Fi),
+            "ja" => Some(Lang::Ja),
+            _ => None,
+        }
+    }
+
+    /// Language of the environment, from the first of LC_ALL, LC_MESSAGES and LANG that is set
+    pub fn from_env() -> Lang {
+        ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|name| std::env::var(name).ok())
+            .find(|value| !value.is_empty())
+            .and_then(|locale| Lang::from_locale(&locale))
+            .unwrap_or_default()
+    }
+}
+
+/// Language used by `tr`, as the discriminant of `Lang`
+static LANG: AtomicU8 = AtomicU8::new(Lang::En as u8);
+
+/// Select the language of console messages
+pub fn set_lang(lang: Lang) {
+    LANG.store(lang as u8, Ordering::Relaxed);
+}
+
+/// Currently selected language of console messages
+pub fn lang() -> Lang {
+    match LANG.load(Ordering::Relaxed) {
+        1 => Lang::Fi,
+        2 => Lang::Ja,
+        _ => Lang::En,
+    }
+}
+
+/// A console message of the catalog
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Msg {
+    Starting,
+    NoImagesFound,
+    AllInputsFiltered,
+    FoundImages,
+    ShufflingInputs,
+    ResumingRun,
+    RunCancelled,
+    RunAborted,
+    StatisticsWritten,
+    Processing,
+    GenerationFailed,
+    Skipped,
+    LeftPending,
+    Progress,
+    GeneratingSample,
+    SampleFailed,
+    Estimate,
+    ContinueRemaining,
+    GenerationComplete,
+    ProcessedSummary,
+    TimingSummary,
+    Throughput,
+    FailedImages,
+    MissingImages,
+    RetryAttempt,
+    ExhaustedRetries,
+    TakingBreak,
+    ValidationIssues,
+    ConfigValid,
+    ConfigWritten,
+    KeysHint,
+    Paused,
+    Resumed,
+    SkippingInput,
+    Aborting,
+    Interrupting,
+    RunLogCloseFailed,
+    RetryingFailed,
+    RunId,
+    WritingProgress,
+    RecordingFixtures,
+    ReplayingFixtures,
+    DistributingWork,
+    InFlightPerBackend,
+    BackendFailed,
+    SequenceFailed,
+    TimelapseFailed,
+    NotificationsUnavailable,
+    HookFailed,
+    RunningPreset,
+    PresetFailed,
+    ResultsByPreset,
+    InterruptFailed,
+    DeadLetterFailed,
+    LoadingModel,
+    DownloadingAgain,
+    ApiStatus,
+    ValidationDisabled,
+    ValidatingOptions,
+    CheckpointsUnchecked,
+    SamplersUnchecked,
+    ControlNetModelsUnchecked,
+    ControlNetModulesUnchecked,
+    SkippingMetadata,
+    NoImagesToSave,
+    UndecodableImage,
+    CompositesFailed,
+    DeadLettered,
+    SavingFewer,
+    Saved,
+    ContinueAnyway,
+    ValidateConfigFailed,
+    DashboardUnavailable,
+    ListSchedulersFailed,
+    NoMatchingGenerations,
+    RollupWritten,
+    NoInputImages,
+    NoProblems,
+    NoSeedRecorded,
+    Regenerating,
+    RequestMatches,
+    RequestDiffers,
+    SourceDiffers,
+    Queued,
+    Removed,
+    RemoveOutputDir,
+    NothingRemoved,
+    SkippedByPlugin,
+    PluginsUnavailable,
+    ConfigNotFound,
+    UsingDefaultConfig,
+    ScoringFailed,
+    NoImageReachedMinScore,
+    Rerolling,
+    RerollFailed,
+    ToppingUp,
+    TopUpFailed,
+    SplittingBatch,
+    RetryReducedBatch,
+    NanFallback,
+    NotRetryable,
+    GpuErrorRetry,
+    RetryableError,
+    RecoveringGpu,
+    UnloadCheckpointFailed,
+    ReloadCheckpointFailed,
+    WaitingForRestart,
+    ServerDown,
+    ServerBack,
+    SlowingDown,
+    PresetResult,
+    PresetNoResults,
+    InputsWithProblems,
+    SummaryTitle,
+    SummaryProcessed,
+    SummaryDuration,
+    SummaryFailureReasons,
+    SummaryReport,
+    DesktopSummary,
+    DesktopNotificationFailed,
+    SummarySent,
+    NotifyFailed,
+    JobSubmitted,
+    JobCancelled,
+    CancellingJob,
+    InvalidJobFile,
+    StartingJob,
+    JobLeftInSpool,
+    JobArchived,
+    JobFailed,
+    WatchingSpool,
+    GrpcUnavailable,
+    CheckJobsFailed,
+    DaemonStopped,
+    SweepingWithSeed,
+    WarmingUp,
+    WarmUpFailed,
+    Benchmarking,
+    MemoryStatsFailed,
+    BenchmarkFailed,
+    RampFailed,
+    RampSlower,
+    RampingUp,
+    SkippingFailureRecord,
+    ListsFromCache,
+    ListCacheWriteFailed,
+    ProgressWriteFailed,
+    RunDirectory,
+    DashboardNeedsTerminal,
+    QueueReadFailed,
+    ServingGallery,
+    AcceptFailed,
+    MetricsUnavailable,
+    ServingMetrics,
+    ServingGrpc,
+    InvalidUserAgent,
+    InvalidRunId,
+    InvalidCredentials,
+    NoStyleReference,
+    ComparingWithSeed,
+    GeneratingCell,
+    CellFailed,
+    KeptBest,
+    AnsweredByPolicy,
+    AnswerYes,
+    AnswerNo,
+    SequenceSeed,
+    SequenceMissingFrames,
+    GatheredFrames,
+    AssembledVideo,
+    UnnumberedFrames,
+    OutsideHours,
+    HoursStarted,
+    MqttPublishFailed,
+    MqttDisabled,
+    MqttTakingJobs,
+    MqttJobRejected,
+    NoTimelapseImages,
+    AssembledTimelapse,
+    FfmpegNotFound,
+    Upscaling,
+    Upscaled,
+    UpscaleFailed,
+    UpscaleSummary,
+    JobArchiveFailed,
+}
+
+impl Msg {
+    /// Every message of the catalog
+    pub const ALL: [Msg; 185] = [
+        Msg::Starting,
+        Msg::NoImagesFound,
+        Msg::AllInputsFiltered,
+        Msg::FoundImages,
+        Msg::ShufflingInputs,
+        Msg::ResumingRun,
+        Msg::RunCancelled,
+        Msg::RunAborted,
+        Msg::StatisticsWritten,
+        Msg::Processing,
+        Msg::GenerationFailed,
+        Msg::Skipped,
+        Msg::LeftPending,
+        Msg::Progress,
+        Msg::GeneratingSample,
+        Msg::SampleFailed,
+        Msg::Estimate,
+        Msg::ContinueRemaining,
+        Msg::GenerationComplete,
+        Msg::ProcessedSummary,
+        Msg::TimingSummary,
+        Msg::Throughput,
+        Msg::FailedImages,
+        Msg::MissingImages,
+        Msg::RetryAttempt,
+        Msg::ExhaustedRetries,
+        Msg::TakingBreak,
+        Msg::ValidationIssues,
+        Msg::ConfigValid,
+        Msg::ConfigWritten,
+        Msg::KeysHint,
+        Msg::Paused,
+        Msg::Resumed,
+        Msg::SkippingInput,
+        Msg::Aborting,
+        Msg::Interrupting,
+        Msg::RunLogCloseFailed,
+        Msg::RetryingFailed,
+        Msg::RunId,
+        Msg::WritingProgress,
+        Msg::RecordingFixtures,
+        Msg::ReplayingFixtures,
+        Msg::DistributingWork,
+        Msg::InFlightPerBackend,
+        Msg::BackendFailed,
+        Msg::SequenceFailed,
+        Msg::TimelapseFailed,
+        Msg::NotificationsUnavailable,
+        Msg::HookFailed,
+        Msg::RunningPreset,
+        Msg::PresetFailed,
+        Msg::ResultsByPreset,
+        Msg::InterruptFailed,
+        Msg::DeadLetterFailed,
+        Msg::LoadingModel,
+        Msg::DownloadingAgain,
+        Msg::ApiStatus,
+        Msg::ValidationDisabled,
+        Msg::ValidatingOptions,
+        Msg::CheckpointsUnchecked,
+        Msg::SamplersUnchecked,
+        Msg::ControlNetModelsUnchecked,
+        Msg::ControlNetModulesUnchecked,
+        Msg::SkippingMetadata,
+        Msg::NoImagesToSave,
+        Msg::UndecodableImage,
+        Msg::CompositesFailed,
+        Msg::DeadLettered,
+        Msg::SavingFewer,
+        Msg::Saved,
+        Msg::ContinueAnyway,
+        Msg::ValidateConfigFailed,
+        Msg::DashboardUnavailable,
+        Msg::ListSchedulersFailed,
+        Msg::NoMatchingGenerations,
+        Msg::RollupWritten,
+        Msg::NoInputImages,
+        Msg::NoProblems,
+        Msg::NoSeedRecorded,
+        Msg::Regenerating,
+        Msg::RequestMatches,
+        Msg::RequestDiffers,
+        Msg::SourceDiffers,
+        Msg::Queued,
+        Msg::Removed,
+        Msg::RemoveOutputDir,
+        Msg::NothingRemoved,
+        Msg::SkippedByPlugin,
+        Msg::PluginsUnavailable,
+        Msg::ConfigNotFound,
+        Msg::UsingDefaultConfig,
+        Msg::ScoringFailed,
+        Msg::NoImageReachedMinScore,
+        Msg::Rerolling,
+        Msg::RerollFailed,
+        Msg::ToppingUp,
+        Msg::TopUpFailed,
+        Msg::SplittingBatch,
+        Msg::RetryReducedBatch,
+        Msg::NanFallback,
+        Msg::NotRetryable,
+        Msg::GpuErrorRetry,
+        Msg::RetryableError,
+        Msg::RecoveringGpu,
+        Msg::UnloadCheckpointFailed,
+        Msg::ReloadCheckpointFailed,
+        Msg::WaitingForRestart,
+        Msg::ServerDown,
+        Msg::ServerBack,
+        Msg::SlowingDown,
+        Msg::PresetResult,
+        Msg::PresetNoResults,
+        Msg::InputsWithProblems,
+        Msg::SummaryTitle,
+        Msg::SummaryProcessed,
+        Msg::SummaryDuration,
+        Msg::SummaryFailureReasons,
+        Msg::SummaryReport,
+        Msg::DesktopSummary,
+        Msg::DesktopNotificationFailed,
+        Msg::SummarySent,
+        Msg::NotifyFailed,
+        Msg::JobSubmitted,
+        Msg::JobCancelled,
+        Msg::CancellingJob,
+        Msg::InvalidJobFile,
+        Msg::StartingJob,
+        Msg::JobLeftInSpool,
+        Msg::JobArchived,
+        Msg::JobFailed,
+        Msg::WatchingSpool,
+        Msg::GrpcUnavailable,
+        Msg::CheckJobsFailed,
+        Msg::DaemonStopped,
+        Msg::SweepingWithSeed,
+        Msg::WarmingUp,
+        Msg::WarmUpFailed,
+        Msg::Benchmarking,
+        Msg::MemoryStatsFailed,
+        Msg::BenchmarkFailed,
+        Msg::RampFailed,
+        Msg::RampSlower,
+        Msg::RampingUp,
+        Msg::SkippingFailureRecord,
+        Msg::ListsFromCache,
+        Msg::ListCacheWriteFailed,
+        Msg::ProgressWriteFailed,
+        Msg::RunDirectory,
+        Msg::DashboardNeedsTerminal,
+        Msg::QueueReadFailed,
+        Msg::ServingGallery,
+        Msg::AcceptFailed,
+        Msg::MetricsUnavailable,
+        Msg::ServingMetrics,
+        Msg::ServingGrpc,
+        Msg::InvalidUserAgent,
+        Msg::InvalidRunId,
+        Msg::InvalidCredentials,
+        Msg::NoStyleReference,
+        Msg::ComparingWithSeed,
+        Msg::GeneratingCell,
+        Msg::CellFailed,
+        Msg::KeptBest,
+        Msg::AnsweredByPolicy,
+        Msg::AnswerYes,
+        Msg::AnswerNo,
+        Msg::SequenceSeed,
+        Msg::SequenceMissingFrames,
+        Msg::GatheredFrames,
+        Msg::AssembledVideo,
+        Msg::UnnumberedFrames,
+        Msg::OutsideHours,
+        Msg::HoursStarted,
+        Msg::MqttPublishFailed,
+        Msg::MqttDisabled,
+        Msg::MqttTakingJobs,
+        Msg::MqttJobRejected,
+        Msg::NoTimelapseImages,
+        Msg::AssembledTimelapse,
+        Msg::FfmpegNotFound,
+        Msg::Upscaling,
+        Msg::Upscaled,
+        Msg::UpscaleFailed,
+        Msg::UpscaleSummary,
+        Msg::JobArchiveFailed,
+    ];
+
+    /// Template of the message in the given language
+    pub fn template(self, lang: Lang) -> &'static str {
+        match lang {
+            Lang::En => self.english(),
+            Lang::Fi => self.finnish(),
+            Lang::Ja => self.japanese(),
+        }
+    }
+
+    fn english(self) -> &'static str {
+        match self {
+            Msg::Starting => "ControlNet Image Generator Starting...",
+            Msg::NoImagesFound => "No images found in {}",
+            Msg::AllInputsFiltered => "All images were skipped by input filters",
+            Msg::FoundImages => "Found {} images to process",
+            Msg::ShufflingInputs => "Shuffling inputs with seed {}",
+            Msg::ResumingRun => "Resuming run, {} of {} inputs already done",
+            Msg::RunCancelled => "Run cancelled, the remaining images stay queued",
+            Msg::RunAborted => "Run aborted, the remaining images stay queued for `urasoe resume`",
+            Msg::StatisticsWritten => "Statistics written to: {}",
+            Msg::Processing => "Processing: {}",
+            Msg::GenerationFailed => "Failed to generate images for: {}",
+            Msg::Skipped => "Skipped: {}",
+            Msg::LeftPending => "Left pending for the next run: {}",
+            Msg::Progress => "Progress: {}/{} ({} failed)",
+            Msg::GeneratingSample => "Generating a sample to estimate the run",
+            Msg::SampleFailed => "Sample generation failed, no estimate available",
+            Msg::Estimate => "Estimated time: {} min, output size: {} MB, done by {}",
+            Msg::ContinueRemaining => "Continue with the remaining {} images?",
+            Msg::GenerationComplete => "✓ Image generation complete!",
+            Msg::ProcessedSummary => "Processed successfully: {}/{} images, Generated: {} new images",
+            Msg::TimingSummary => "Average generation time: {}ms, Retries: {}, Total time: {}s",
+            Msg::Throughput => "Throughput: {} images/min, {} MP/min",
+            Msg::FailedImages => "Failed images ({})",
+            Msg::MissingImages => "Missing images: the server returned {} images fewer than requested",
+            Msg::RetryAttempt => "Retry attempt {}/{} after waiting {}ms",
+            Msg::ExhaustedRetries => "Exhausted all {} retry attempts for {}",
+            Msg::TakingBreak => "Taking a break to clear GPU memory ({}ms)",
+            Msg::ValidationIssues => "⚠️ Configuration validation issues found:",
+            Msg::ConfigValid => "✓ All configuration options are valid",
+            Msg::ConfigWritten => "Configuration written to: {}",
+            Msg::KeysHint => "Keys: p pause, r resume, s skip the current input, q abort",
+            Msg::Paused => "Pausing after the current image, press r to resume",
+            Msg::Resumed => "Resuming",
+            Msg::SkippingInput => "Skipping the current input",
+            Msg::Aborting => "Aborting, the remaining images stay queued",
+            Msg::Interrupting => "Interrupted, stopping the running generations, press Ctrl+C again to quit at once",
+            Msg::RunLogCloseFailed => "Failed to close the run log: {}",
+            Msg::RetryingFailed => "Retrying {} inputs that failed before",
+            Msg::RunId => "Run id: {}",
+            Msg::WritingProgress => "Writing progress to {}",
+            Msg::RecordingFixtures => "Recording API responses to {}",
+            Msg::ReplayingFixtures => "Replaying API responses from {}",
+            Msg::DistributingWork => "Distributing work across backends: {}",
+            Msg::InFlightPerBackend => "Inputs in flight per backend: {}",
+            Msg::BackendFailed => "Backend failed: {} {}",
+            Msg::SequenceFailed => "Failed to assemble the sequence: {}",
+            Msg::TimelapseFailed => "Failed to assemble the timelapse: {}",
+            Msg::NotificationsUnavailable => "Notifications need a build with the notifications feature, not sending the summary",
+            Msg::HookFailed => "Hook failed: {}",
+            Msg::RunningPreset => "Running preset {} ({})",
+            Msg::PresetFailed => "Preset failed: {} {}",
+            Msg::ResultsByPreset => "Results by preset:",
+            Msg::InterruptFailed => "Failed to interrupt generation: {}",
+            Msg::DeadLetterFailed => "Failed to dead-letter input: {}",
+            Msg::LoadingModel => "Loading model: {}",
+            Msg::DownloadingAgain => "Downloading again: {}",
+            Msg::ApiStatus => "API responded with status: {}",
+            Msg::ValidationDisabled => "Option validation disabled in config.",
+            Msg::ValidatingOptions => "Validating configuration options against API...",
+            Msg::CheckpointsUnchecked => "Could not validate checkpoint models: {}",
+            Msg::SamplersUnchecked => "Could not validate samplers: {}",
+            Msg::ControlNetModelsUnchecked => "Could not validate ControlNet models: {}",
+            Msg::ControlNetModulesUnchecked => "Could not validate ControlNet modules: {}",
+            Msg::SkippingMetadata => "Skipping metadata: {}",
+            Msg::NoImagesToSave => "No images generated to save",
+            Msg::UndecodableImage => "Skipping undecodable image {}: {}",
+            Msg::CompositesFailed => "Failed to save composites: {}",
+            Msg::DeadLettered => "Dead-lettered: {}",
+            Msg::SavingFewer => "Saving {} of {} requested images, {} missing",
+            Msg::Saved => "Saved: {}",
+            Msg::ContinueAnyway => "Continue anyway?",
+            Msg::ValidateConfigFailed => "Failed to validate configuration: {}",
+            Msg::DashboardUnavailable => "The dashboard needs a build with the tui feature, showing logs instead",
+            Msg::ListSchedulersFailed => "Failed to list schedulers: {}",
+            Msg::NoMatchingGenerations => "No matching generations in {}",
+            Msg::RollupWritten => "Rollup written to {}",
+            Msg::NoInputImages => "No input images in {}",
+            Msg::NoProblems => "No problems found in {} inputs",
+            Msg::NoSeedRecorded => "No seed recorded, the images will differ from the originals",
+            Msg::Regenerating => "Regenerating {} (seed {})",
+            Msg::RequestMatches => "Request matches the original request",
+            Msg::RequestDiffers => "Request differs from the original request",
+            Msg::SourceDiffers => "Source image differs from the original source image",
+            Msg::Queued => "Queued {} with priority {}",
+            Msg::Removed => "Removed {}",
+            Msg::RemoveOutputDir => "Remove the output directory {} with all generated images?",
+            Msg::NothingRemoved => "Nothing was removed",
+            Msg::SkippedByPlugin => "Skipping {} ({})",
+            Msg::PluginsUnavailable => "Plugins need a build with the scripting feature, running without them",
+            Msg::ConfigNotFound => "Config file not found: {}",
+            Msg::UsingDefaultConfig => "Using default configuration",
+            Msg::ScoringFailed => "Failed to score images, keeping them: {}",
+            Msg::NoImageReachedMinScore => "No image reached the minimum score, keeping the best available",
+            Msg::Rerolling => "Best score {} is below {}, re-rolling with a new seed ({}/{})",
+            Msg::RerollFailed => "Re-roll failed, keeping the images so far: {}",
+            Msg::ToppingUp => "Response is missing {} images, requesting them once more",
+            Msg::TopUpFailed => "Top-up failed, keeping the images so far: {}",
+            Msg::SplittingBatch => "Splitting batch into {} requests of at most {} images",
+            Msg::RetryReducedBatch => "Retry attempt {} with reduced batch size",
+            Msg::NanFallback => "NaN tensors produced, retrying with the fallback sampler: {}",
+            Msg::NotRetryable => "Error is not retryable, giving up: {}",
+            Msg::GpuErrorRetry => "CUDA/GPU error detected, will retry {}/{}: {}",
+            Msg::RetryableError => "Retryable error, will retry {}/{}: {}",
+            Msg::RecoveringGpu => "CUDA errors persist, reloading the checkpoint before the final attempt",
+            Msg::UnloadCheckpointFailed => "Failed to unload checkpoint: {}",
+            Msg::ReloadCheckpointFailed => "Failed to reload checkpoint: {}",
+            Msg::WaitingForRestart => "Lost the connection to the server, waiting for it to come back for up to {}s",
+            Msg::ServerDown => "The server did not come back, failing the inputs until it answers again",
+            Msg::ServerBack => "The server is back after {}s, reloading the checkpoint",
+            Msg::SlowingDown => "Generation is slowing down, possibly running out of GPU memory. Pausing for {}ms",
+            Msg::PresetResult => "{} ({}): {} succeeded, {} failed, {} generated, {}ms average, {}s total",
+            Msg::PresetNoResults => "{} ({}): no results",
+            Msg::InputsWithProblems => "{} of {} inputs have problems",
+            Msg::SummaryTitle => "urasoe run finished",
+            Msg::SummaryProcessed => "Processed successfully: {}/{} images, generated {} new images, {} failed",
+            Msg::SummaryDuration => "Duration: {} min",
+            Msg::SummaryFailureReasons => "Failure reasons: {}",
+            Msg::SummaryReport => "Report: {}",
+            Msg::DesktopSummary => "{} succeeded, {} failed, {} images generated",
+            Msg::DesktopNotificationFailed => "Failed to show desktop notification: {}",
+            Msg::SummarySent => "Sent run summary to {}",
+            Msg::NotifyFailed => "Failed to notify {}: {}",
+            Msg::JobSubmitted => "Job submitted: {}",
+            Msg::JobCancelled => "Job cancelled: {}",
+            Msg::CancellingJob => "Cancelling running job: {}",
+            Msg::InvalidJobFile => "Invalid job file: {}",
+            Msg::StartingJob => "Starting job: {}",
+            Msg::JobLeftInSpool => "Job left in the spool for the next start: {}",
+            Msg::JobArchived => "Job archived to: {}",
+            Msg::JobFailed => "Job failed: {}",
+            Msg::WatchingSpool => "Watching spool directory: {}",
+            Msg::GrpcUnavailable => "gRPC job control needs a build with the grpc feature, ignoring grpc_addr",
+            Msg::CheckJobsFailed => "Failed to check for jobs: {}",
+            Msg::DaemonStopped => "Daemon stopped",
+            Msg::SweepingWithSeed => "Sweeping with seed: {}",
+            Msg::WarmingUp => "Warming up...",
+            Msg::WarmUpFailed => "Warm-up failed: {}",
+            Msg::Benchmarking => "Benchmarking: {}, {} steps, {}",
+            Msg::MemoryStatsFailed => "Failed to read memory statistics: {}",
+            Msg::BenchmarkFailed => "Failed: {}, {} steps, {}: {}",
+            Msg::RampFailed => "Batch size {} failed, staying at batch size {}",
+            Msg::RampSlower => "Batch size {} takes {} ms per image against {} ms, staying at batch size {}",
+            Msg::RampingUp => "Ramping up to batch size {}",
+            Msg::SkippingFailureRecord => "Skipping failure record: {}",
+            Msg::ListsFromCache => "Could not fetch the server lists: {}, using the {} cached at {}",
+            Msg::ListCacheWriteFailed => "Failed to write the list cache: {}",
+            Msg::ProgressWriteFailed => "Failed to write the progress file: {}",
+            Msg::RunDirectory => "Run directory: {}",
+            Msg::DashboardNeedsTerminal => "The dashboard needs a terminal, showing logs instead",
+            Msg::QueueReadFailed => "Failed to read job queue {}: {}",
+            Msg::ServingGallery => "Serving the gallery of {} on http://{}/",
+            Msg::AcceptFailed => "Failed to accept {} connection: {}",
+            Msg::MetricsUnavailable => "The metrics endpoint needs a build with the server feature, ignoring metrics_addr",
+            Msg::ServingMetrics => "Serving metrics on http://{}/metrics",
+            Msg::ServingGrpc => "Serving gRPC job control on {}",
+            Msg::InvalidUserAgent => "Invalid user agent, using the default: {}",
+            Msg::InvalidRunId => "Invalid run id, not sending it: {}",
+            Msg::InvalidCredentials => "Invalid API credentials, not sending them: {}",
+            Msg::NoStyleReference => "No style reference for {}",
+            Msg::ComparingWithSeed => "Comparing with seed: {}",
+            Msg::GeneratingCell => "Generating: {}",
+            Msg::CellFailed => "Failed to generate {}: {}",
+            Msg::KeptBest => "Kept the best {} of {}",
+            Msg::AnsweredByPolicy => "Answered by the prompt policy: {}",
+            Msg::AnswerYes => "yes",
+            Msg::AnswerNo => "no",
+            Msg::SequenceSeed => "Processing a frame sequence with seed: {}",
+            Msg::SequenceMissingFrames => "Sequence is missing {} frames without a generated image",
+            Msg::GatheredFrames => "Gathered {} frames in {}",
+            Msg::AssembledVideo => "Assembled video: {}",
+            Msg::UnnumberedFrames => "Inputs without a frame number in their name: {}",
+            Msg::OutsideHours => "Outside allowed hours {} - pausing for {} min",
+            Msg::HoursStarted => "Allowed hours started, resuming",
+            Msg::MqttPublishFailed => "MQTT publish failed: {}",
+            Msg::MqttDisabled => "MQTT disabled for this run: {}",
+            Msg::MqttTakingJobs => "Taking jobs from MQTT topic {}",
+            Msg::MqttJobRejected => "Rejected MQTT job: {}",
+            Msg::NoTimelapseImages => "No generated images for the timelapse",
+            Msg::AssembledTimelapse => "Assembled timelapse: {} of {} images",
+            Msg::FfmpegNotFound => "ffmpeg was not found, not assembling a video",
+            Msg::Upscaling => "Upscaling {} images with {} x{}",
+            Msg::Upscaled => "Upscaled: {}",
+            Msg::UpscaleFailed => "Failed to upscale {}: {}",
+            Msg::UpscaleSummary => "Upscaled {}, skipped {}, failed {}",
+            Msg::JobArchiveFailed => "Failed to archive job {}, skipping it until the daemon restarts: {}",
+        }
+    }
+
+    fn finnish(self) -> &'static str {
+        match self {
+            Msg::Starting => "ControlNet-kuvageneraattori käynnistyy...",
+            Msg::NoImagesFound => "Hakemistosta {} ei löytynyt kuvia",
+            Msg::AllInputsFiltered => "Syötesuodattimet ohittivat kaikki kuvat",
+            Msg::FoundImages => "Löytyi {} käsiteltävää kuvaa",
+            Msg::ShufflingInputs => "Sekoitetaan syötteet siemenluvulla {}",
+            Msg::ResumingRun => "Jatketaan ajoa, {}/{} syötettä on jo valmiina",
+            Msg::RunCancelled => "Ajo peruttiin, jäljellä olevat kuvat jäävät jonoon",
+            Msg::RunAborted => "Ajo keskeytettiin, jäljellä olevat kuvat jäävät jonoon komennolle `urasoe resume`",
+            Msg::StatisticsWritten => "Tilastot kirjoitettiin tiedostoon: {}",
+            Msg::Processing => "Käsitellään: {}",
+            Msg::GenerationFailed => "Kuvien luonti epäonnistui: {}",
+            Msg::Skipped => "Ohitettiin: {}",
+            Msg::LeftPending => "Jätettiin odottamaan seuraavaa ajoa: {}",
+            Msg::Progress => "Edistyminen: {}/{} ({} epäonnistui)",
+            Msg::GeneratingSample => "Luodaan näyte ajon keston arvioimiseksi",
+            Msg::SampleFailed => "Näytteen luonti epäonnistui, arviota ei ole saatavilla",
+            Msg::Estimate => "Arvioitu kesto: {} min, tulosteiden koko: {} Mt, valmis {}",
+            Msg::ContinueRemaining => "Jatketaanko jäljellä olevilla {} kuvalla?",
+            Msg::GenerationComplete => "✓ Kuvien luonti valmis!",
+            Msg::ProcessedSummary => "Käsitelty onnistuneesti: {}/{} kuvaa, luotu: {} uutta kuvaa",
+            Msg::TimingSummary => "Keskimääräinen luontiaika: {} ms, uusintayritykset: {}, kokonaisaika: {} s",
+            Msg::Throughput => "Läpimeno: {} kuvaa/min, {} MP/min",
+            Msg::FailedImages => "Epäonnistuneet kuvat ({})",
+            Msg::MissingImages => "Puuttuvat kuvat: palvelin palautti {} kuvaa pyydettyä vähemmän",
+            Msg::RetryAttempt => "Uusintayritys {}/{} {} ms odotuksen jälkeen",
+            Msg::ExhaustedRetries => "Kaikki {} uusintayritystä käytettiin kuvalle {}",
+            Msg::TakingBreak => "Pidetään tauko GPU-muistin vapauttamiseksi ({} ms)",
+            Msg::ValidationIssues => "⚠️ Asetusten tarkistus löysi ongelmia:",
+            Msg::ConfigValid => "✓ Kaikki asetukset ovat kelvollisia",
+            Msg::ConfigWritten => "Asetukset kirjoitettiin tiedostoon: {}",
+            Msg::KeysHint => "Näppäimet: p tauko, r jatka, s ohita nykyinen syöte, q keskeytä",
+            Msg::Paused => "Pidetään tauko nykyisen kuvan jälkeen, jatka painamalla r",
+            Msg::Resumed => "Jatketaan",
+            Msg::SkippingInput => "Ohitetaan nykyinen syöte",
+            Msg::Aborting => "Keskeytetään, jäljellä olevat kuvat jäävät jonoon",
+            Msg::Interrupting => "Keskeytetty, käynnissä olevat generoinnit pysäytetään, paina Ctrl+C uudelleen lopettaaksesi heti",
+            Msg::RunLogCloseFailed => "Ajolokin sulkeminen epäonnistui: {}",
+            Msg::RetryingFailed => "Yritetään uudelleen {} aiemmin epäonnistunutta syötettä",
+            Msg::RunId => "Ajon tunniste: {}",
+            Msg::WritingProgress => "Edistyminen kirjoitetaan tiedostoon {}",
+            Msg::RecordingFixtures => "API-vastaukset tallennetaan hakemistoon {}",
+            Msg::ReplayingFixtures => "API-vastaukset toistetaan hakemistosta {}",
+            Msg::DistributingWork => "Työ jaetaan taustapalvelimille: {}",
+            Msg::InFlightPerBackend => "Samanaikaisia syötteitä taustapalvelinta kohden: {}",
+            Msg::BackendFailed => "Taustapalvelin epäonnistui: {} {}",
+            Msg::SequenceFailed => "Sarjan kokoaminen epäonnistui: {}",
+            Msg::TimelapseFailed => "Aikavälikuvauksen kokoaminen epäonnistui: {}",
+            Msg::NotificationsUnavailable => "Ilmoitukset vaativat notifications-ominaisuuden sisältävän käännöksen, yhteenvetoa ei lähetetä",
+            Msg::HookFailed => "Koukku epäonnistui: {}",
+            Msg::RunningPreset => "Ajetaan esiasetus {} ({})",
+            Msg::PresetFailed => "Esiasetus epäonnistui: {} {}",
+            Msg::ResultsByPreset => "Tulokset esiasetuksittain:",
+            Msg::InterruptFailed => "Generoinnin keskeyttäminen epäonnistui: {}",
+            Msg::DeadLetterFailed => "Syötteen siirto hylättyihin epäonnistui: {}",
+            Msg::LoadingModel => "Ladataan malli: {}",
+            Msg::DownloadingAgain => "Ladataan uudelleen: {}",
+            Msg::ApiStatus => "API vastasi tilakoodilla: {}",
+            Msg::ValidationDisabled => "Asetusten tarkistus on poistettu käytöstä asetuksissa.",
+            Msg::ValidatingOptions => "Tarkistetaan asetuksia API:a vasten...",
+            Msg::CheckpointsUnchecked => "Checkpoint-mallien tarkistus ei onnistunut: {}",
+            Msg::SamplersUnchecked => "Näytteistäjien tarkistus ei onnistunut: {}",
+            Msg::ControlNetModelsUnchecked => "ControlNet-mallien tarkistus ei onnistunut: {}",
+            Msg::ControlNetModulesUnchecked => "ControlNet-moduulien tarkistus ei onnistunut: {}",
+            Msg::SkippingMetadata => "Metatiedot ohitetaan: {}",
+            Msg::NoImagesToSave => "Tallennettavia kuvia ei generoitu",
+            Msg::UndecodableImage => "Ohitetaan purkukelvoton kuva {}: {}",
+            Msg::CompositesFailed => "Yhdistelmäkuvien tallennus epäonnistui: {}",
+            Msg::DeadLettered => "Siirretty hylättyihin: {}",
+            Msg::SavingFewer => "Tallennetaan {} / {} pyydetystä kuvasta, {} puuttuu",
+            Msg::Saved => "Tallennettu: {}",
+            Msg::ContinueAnyway => "Jatketaanko silti?",
+            Msg::ValidateConfigFailed => "Asetusten tarkistus epäonnistui: {}",
+            Msg::DashboardUnavailable => "Koontinäyttö vaatii tui-ominaisuuden sisältävän käännöksen, näytetään lokit",
+            Msg::ListSchedulersFailed => "Ajoittimien listaus epäonnistui: {}",
+            Msg::NoMatchingGenerations => "Hakemistosta {} ei löytynyt vastaavia generointeja",
+            Msg::RollupWritten => "Kooste kirjoitettu tiedostoon {}",
+            Msg::NoInputImages => "Hakemistossa {} ei ole syötekuvia",
+            Msg::NoProblems => "{} syötteestä ei löytynyt ongelmia",
+            Msg::NoSeedRecorded => "Siementä ei ole tallennettu, kuvat eroavat alkuperäisistä",
+            Msg::Regenerating => "Generoidaan uudelleen {} (siemen {})",
+            Msg::RequestMatches => "Pyyntö vastaa alkuperäistä pyyntöä",
+            Msg::RequestDiffers => "Pyyntö eroaa alkuperäisestä pyynnöstä",
+            Msg::SourceDiffers => "Lähdekuva eroaa alkuperäisestä lähdekuvasta",
+            Msg::Queued => "Lisätty jonoon {} prioriteetilla {}",
+            Msg::Removed => "Poistettu {}",
+            Msg::RemoveOutputDir => "Poistetaanko tuloskansio {} kaikkine luotuine kuvineen?",
+            Msg::NothingRemoved => "Mitään ei poistettu",
+            Msg::SkippedByPlugin => "Ohitetaan {} ({})",
+            Msg::PluginsUnavailable => "Liitännäiset vaativat scripting-ominaisuuden sisältävän käännöksen, ajetaan ilman niitä",
+            Msg::ConfigNotFound => "Asetustiedostoa ei löytynyt: {}",
+            Msg::UsingDefaultConfig => "Käytetään oletusasetuksia",
+            Msg::ScoringFailed => "Kuvien pisteytys epäonnistui, kuvat säilytetään: {}",
+            Msg::NoImageReachedMinScore => "Yksikään kuva ei saavuttanut vähimmäispisteitä, säilytetään parhaat saatavilla olevat",
+            Msg::Rerolling => "Paras pistemäärä {} on alle {}, luodaan uudelleen uudella siemenluvulla ({}/{})",
+            Msg::RerollFailed => "Uudelleenluonti epäonnistui, säilytetään tähänastiset kuvat: {}",
+            Msg::ToppingUp => "Vastauksesta puuttuu {} kuvaa, pyydetään ne vielä kerran",
+            Msg::TopUpFailed => "Puuttuvien kuvien pyyntö epäonnistui, säilytetään tähänastiset kuvat: {}",
+            Msg::SplittingBatch => "Jaetaan erä {} pyyntöön, joissa on enintään {} kuvaa",
+            Msg::RetryReducedBatch => "Uusintayritys {} pienennetyllä eräkoolla",
+            Msg::NanFallback => "Syntyi NaN-tensoreita, yritetään uudelleen varasamplerilla: {}",
+            Msg::NotRetryable => "Virhettä ei voi yrittää uudelleen, luovutetaan: {}",
+            Msg::GpuErrorRetry => "CUDA/GPU-virhe havaittiin, yritetään uudelleen {}/{}: {}",
+            Msg::RetryableError => "Uudelleen yritettävä virhe, yritetään uudelleen {}/{}: {}",
+            Msg::RecoveringGpu => "CUDA-virheet jatkuvat, ladataan tarkistuspiste uudelleen ennen viimeistä yritystä",
+            Msg::UnloadCheckpointFailed => "Tarkistuspisteen poistaminen muistista epäonnistui: {}",
+            Msg::ReloadCheckpointFailed => "Tarkistuspisteen uudelleenlataus epäonnistui: {}",
+            Msg::WaitingForRestart => "Yhteys palvelimeen katkesi, odotetaan sen palaamista enintään {} s",
+            Msg::ServerDown => "Palvelin ei palannut, syötteet epäonnistuvat kunnes se vastaa taas",
+            Msg::ServerBack => "Palvelin palasi {} s kuluttua, ladataan tarkistuspiste uudelleen",
+            Msg::SlowingDown => "Generointi hidastuu, GPU-muisti saattaa olla loppumassa. Pidetään {} ms tauko",
+            Msg::PresetResult => "{} ({}): {} onnistui, {} epäonnistui, {} luotiin, keskimäärin {} ms, yhteensä {} s",
+            Msg::PresetNoResults => "{} ({}): ei tuloksia",
+            Msg::InputsWithProblems => "{}/{} syötteessä on ongelmia",
+            Msg::SummaryTitle => "urasoe-ajo valmis",
+            Msg::SummaryProcessed => "Käsitelty onnistuneesti: {}/{} kuvaa, luotu {} uutta kuvaa, {} epäonnistui",
+            Msg::SummaryDuration => "Kesto: {} min",
+            Msg::SummaryFailureReasons => "Epäonnistumisen syyt: {}",
+            Msg::SummaryReport => "Raportti: {}",
+            Msg::DesktopSummary => "{} onnistui, {} epäonnistui, {} kuvaa luotiin",
+            Msg::DesktopNotificationFailed => "Työpöytäilmoituksen näyttäminen epäonnistui: {}",
+            Msg::SummarySent => "Ajon yhteenveto lähetettiin palveluun {}",
+            Msg::NotifyFailed => "Ilmoitus palveluun {} epäonnistui: {}",
+            Msg::JobSubmitted => "Työ lisättiin: {}",
+            Msg::JobCancelled => "Työ peruttiin: {}",
+            Msg::CancellingJob => "Perutaan käynnissä oleva työ: {}",
+            Msg::InvalidJobFile => "Virheellinen työtiedosto: {}",
+            Msg::StartingJob => "Aloitetaan työ: {}",
+            Msg::JobLeftInSpool => "Työ jätettiin jonohakemistoon seuraavaa käynnistystä varten: {}",
+            Msg::JobArchived => "Työ arkistoitiin: {}",
+            Msg::JobFailed => "Työ epäonnistui: {}",
+            Msg::WatchingSpool => "Seurataan jonohakemistoa: {}",
+            Msg::GrpcUnavailable => "gRPC-työnhallinta vaatii grpc-ominaisuuden sisältävän käännöksen, grpc_addr ohitetaan",
+            Msg::CheckJobsFailed => "Töiden tarkistus epäonnistui: {}",
+            Msg::DaemonStopped => "Taustapalvelu pysäytettiin",
+            Msg::SweepingWithSeed => "Käydään läpi siemenluvulla: {}",
+            Msg::WarmingUp => "Lämmitellään...",
+            Msg::WarmUpFailed => "Lämmittely epäonnistui: {}",
+            Msg::Benchmarking => "Mitataan: {}, {} askelta, {}",
+            Msg::MemoryStatsFailed => "Muistitilastojen lukeminen epäonnistui: {}",
+            Msg::BenchmarkFailed => "Epäonnistui: {}, {} askelta, {}: {}",
+            Msg::RampFailed => "Eräkoko {} epäonnistui, pysytään eräkoossa {}",
+            Msg::RampSlower => "Eräkoko {} vie {} ms kuvaa kohden, kun aiemmin {} ms, pysytään eräkoossa {}",
+            Msg::RampingUp => "Kasvatetaan eräkoko arvoon {}",
+            Msg::SkippingFailureRecord => "Ohitetaan epäonnistumistietue: {}",
+            Msg::ListsFromCache => "Palvelimen listoja ei voitu hakea: {}, käytetään välimuistin listaa {} ajalta {}",
+            Msg::ListCacheWriteFailed => "Listavälimuistin kirjoittaminen epäonnistui: {}",
+            Msg::ProgressWriteFailed => "Edistymistiedoston kirjoittaminen epäonnistui: {}",
+            Msg::RunDirectory => "Ajon hakemisto: {}",
+            Msg::DashboardNeedsTerminal => "Koontinäyttö vaatii päätteen, näytetään lokit",
+            Msg::QueueReadFailed => "Työjonon {} lukeminen epäonnistui: {}",
+            Msg::ServingGallery => "Galleria hakemistosta {} osoitteessa http://{}/",
+            Msg::AcceptFailed => "{}-yhteyden hyväksyminen epäonnistui: {}",
+            Msg::MetricsUnavailable => "Mittariosoite vaatii server-ominaisuuden sisältävän käännöksen, metrics_addr ohitetaan",
+            Msg::ServingMetrics => "Mittarit osoitteessa http://{}/metrics",
+            Msg::ServingGrpc => "gRPC-työnhallinta osoitteessa {}",
+            Msg::InvalidUserAgent => "Virheellinen user agent, käytetään oletusta: {}",
+            Msg::InvalidRunId => "Virheellinen ajon tunniste, sitä ei lähetetä: {}",
+            Msg::InvalidCredentials => "Virheelliset API-tunnukset, niitä ei lähetetä: {}",
+            Msg::NoStyleReference => "Kuvalle {} ei ole tyyliviitettä",
+            Msg::ComparingWithSeed => "Vertaillaan siemenluvulla: {}",
+            Msg::GeneratingCell => "Luodaan: {}",
+            Msg::CellFailed => "Kuvan {} luonti epäonnistui: {}",
+            Msg::KeptBest => "Säilytettiin parhaat {}/{}",
+            Msg::AnsweredByPolicy => "Kysymyskäytäntö vastasi: {}",
+            Msg::AnswerYes => "kyllä",
+            Msg::AnswerNo => "ei",
+            Msg::SequenceSeed => "Käsitellään kuvasarja siemenluvulla: {}",
+            Msg::SequenceMissingFrames => "Sarjasta puuttuu {} ruutua, joille ei luotu kuvaa",
+            Msg::GatheredFrames => "Koottiin {} ruutua hakemistoon {}",
+            Msg::AssembledVideo => "Video koottiin: {}",
+            Msg::UnnumberedFrames => "Syötteet, joiden nimessä ei ole ruudun numeroa: {}",
+            Msg::OutsideHours => "Sallittujen tuntien {} ulkopuolella - tauko {} min",
+            Msg::HoursStarted => "Sallitut tunnit alkoivat, jatketaan",
+            Msg::MqttPublishFailed => "MQTT-julkaisu epäonnistui: {}",
+            Msg::MqttDisabled => "MQTT poistettu käytöstä tämän ajon ajaksi: {}",
+            Msg::MqttTakingJobs => "Otetaan töitä MQTT-aiheesta {}",
+            Msg::MqttJobRejected => "MQTT-työ hylättiin: {}",
+            Msg::NoTimelapseImages => "Aikasarjavideolle ei ole luotuja kuvia",
+            Msg::AssembledTimelapse => "Aikasarjavideo koottiin: {} {} kuvasta",
+            Msg::FfmpegNotFound => "ffmpeg-ohjelmaa ei löytynyt, videota ei koota",
+            Msg::Upscaling => "Suurennetaan {} kuvaa: {} x{}",
+            Msg::Upscaled => "Suurennettu: {}",
+            Msg::UpscaleFailed => "Kuvan {} suurentaminen epäonnistui: {}",
+            Msg::UpscaleSummary => "Suurennettu {}, ohitettu {}, epäonnistui {}",
+            Msg::JobArchiveFailed => "Työn {} arkistointi epäonnistui, se ohitetaan taustapalvelun uudelleenkäynnistykseen asti: {}",
+        }
+    }
+
+    fn japanese(self) -> &'static str {
+        match self {
+            Msg::Starting => "ControlNet 画像ジェネレーターを起動しています...",
+            Msg::NoImagesFound => "{} に画像が見つかりません",
+            Msg::AllInputsFiltered => "入力フィルターによってすべての画像がスキップされました",
+            Msg::FoundImages => "処理する画像が {} 枚見つかりました",
+            Msg::ShufflingInputs => "シード {} で入力をシャッフルしています",
+            Msg::ResumingRun => "実行を再開します。{1} 件中 {0} 件は処理済みです",
+            Msg::RunCancelled => "実行をキャンセルしました。残りの画像はキューに残ります",
+            Msg::RunAborted => "実行を中断しました。残りの画像は `urasoe resume` のためにキューに残ります",
+            Msg::StatisticsWritten => "統計を書き込みました: {}",
+            Msg::Processing => "処理中: {}",
+            Msg::GenerationFailed => "画像の生成に失敗しました: {}",
+            Msg::Skipped => "スキップしました: {}",
+            Msg::LeftPending => "次回の実行まで保留しました: {}",
+            Msg::Progress => "進捗: {}/{} (失敗 {})",
+            Msg::GeneratingSample => "実行時間を見積もるためにサンプルを生成しています",
+            Msg::SampleFailed => "サンプルの生成に失敗したため、見積もりはありません",
+            Msg::Estimate => "推定時間: {} 分、出力サイズ: {} MB、完了予定: {}",
+            Msg::ContinueRemaining => "残りの {} 枚の画像で続行しますか?",
+            Msg::GenerationComplete => "✓ 画像の生成が完了しました!",
+            Msg::ProcessedSummary => "正常に処理: {}/{} 枚、生成: {} 枚の新しい画像",
+            Msg::TimingSummary => "平均生成時間: {}ms、リトライ: {} 回、合計時間: {}s",
+            Msg::Throughput => "スループット: {} 枚/分、{} MP/分",
+            Msg::FailedImages => "失敗した画像 ({})",
+            Msg::MissingImages => "不足した画像: サーバーが返した画像は要求より {} 枚少なかった",
+            Msg::RetryAttempt => "{2}ms 待機後に再試行 {0}/{1}",
+            Msg::ExhaustedRetries => "{1} の再試行 {0} 回をすべて使い切りました",
+            Msg::TakingBreak => "GPU メモリを解放するために休憩します ({}ms)",
+            Msg::ValidationIssues => "⚠️ 設定の検証で問題が見つかりました:",
+            Msg::ConfigValid => "✓ すべての設定項目は有効です",
+            Msg::ConfigWritten => "設定を書き込みました: {}",
+            Msg::KeysHint => "キー: p 一時停止、r 再開、s 現在の入力をスキップ、q 中断",
+            Msg::Paused => "現在の画像の後で一時停止します。r で再開します",
+            Msg::Resumed => "再開します",
+            Msg::SkippingInput => "現在の入力をスキップします",
+            Msg::Aborting => "中断します。残りの画像はキューに残ります",
+            Msg::Interrupting => "中断されました。実行中の生成を停止します。すぐに終了するにはもう一度 Ctrl+C を押してください",
+            Msg::RunLogCloseFailed => "実行ログを閉じられませんでした: {}",
+            Msg::RetryingFailed => "前回失敗した {} 件の入力を再試行します",
+            Msg::RunId => "実行ID: {}",
+            Msg::WritingProgress => "進捗を {} に書き込みます",
+            Msg::RecordingFixtures => "APIの応答を {} に記録します",
+            Msg::ReplayingFixtures => "APIの応答を {} から再生します",
+            Msg::DistributingWork => "バックエンドに作業を分散します: {}",
+            Msg::InFlightPerBackend => "バックエンドごとの同時処理数: {}",
+            Msg::BackendFailed => "バックエンドが失敗しました: {} {}",
+            Msg::SequenceFailed => "シーケンスを作成できませんでした: {}",
+            Msg::TimelapseFailed => "タイムラプスを作成できませんでした: {}",
+            Msg::NotificationsUnavailable => "通知には notifications 機能付きのビルドが必要です。概要は送信しません",
+            Msg::HookFailed => "フックが失敗しました: {}",
+            Msg::RunningPreset => "プリセット {} を実行します ({})",
+            Msg::PresetFailed => "プリセットが失敗しました: {} {}",
+            Msg::ResultsByPreset => "プリセット別の結果:",
+            Msg::InterruptFailed => "生成を中断できませんでした: {}",
+            Msg::DeadLetterFailed => "入力をデッドレターに移せませんでした: {}",
+            Msg::LoadingModel => "モデルを読み込んでいます: {}",
+            Msg::DownloadingAgain => "再ダウンロードします: {}",
+            Msg::ApiStatus => "APIの応答ステータス: {}",
+            Msg::ValidationDisabled => "設定でオプションの検証が無効になっています。",
+            Msg::ValidatingOptions => "APIに対して設定オプションを検証しています...",
+            Msg::CheckpointsUnchecked => "チェックポイントモデルを検証できませんでした: {}",
+            Msg::SamplersUnchecked => "サンプラーを検証できませんでした: {}",
+            Msg::ControlNetModelsUnchecked => "ControlNet モデルを検証できませんでした: {}",
+            Msg::ControlNetModulesUnchecked => "ControlNet モジュールを検証できませんでした: {}",
+            Msg::SkippingMetadata => "メタデータをスキップします: {}",
+            Msg::NoImagesToSave => "保存する生成画像がありません",
+            Msg::UndecodableImage => "デコードできない画像 {} をスキップします: {}",
+            Msg::CompositesFailed => "合成画像を保存できませんでした: {}",
+            Msg::DeadLettered => "デッドレターに移しました: {}",
+            Msg::SavingFewer => "要求した {1} 枚のうち {0} 枚を保存します。{2} 枚不足しています",
+            Msg::Saved => "保存しました: {}",
+            Msg::ContinueAnyway => "このまま続行しますか？",
+            Msg::ValidateConfigFailed => "設定を検証できませんでした: {}",
+            Msg::DashboardUnavailable => "ダッシュボードには tui 機能付きのビルドが必要です。代わりにログを表示します",
+            Msg::ListSchedulersFailed => "スケジューラーを一覧表示できませんでした: {}",
+            Msg::NoMatchingGenerations => "{} に一致する生成はありません",
+            Msg::RollupWritten => "集計を {} に書き込みました",
+            Msg::NoInputImages => "{} に入力画像がありません",
+            Msg::NoProblems => "{} 件の入力に問題は見つかりませんでした",
+            Msg::NoSeedRecorded => "シードが記録されていないため、画像は元と異なります",
+            Msg::Regenerating => "{} を再生成します (シード {})",
+            Msg::RequestMatches => "リクエストは元のリクエストと一致します",
+            Msg::RequestDiffers => "リクエストが元のリクエストと異なります",
+            Msg::SourceDiffers => "元画像が元のソース画像と異なります",
+            Msg::Queued => "{} を優先度 {} でキューに追加しました",
+            Msg::Removed => "{} を削除しました",
+            Msg::RemoveOutputDir => "出力ディレクトリ {} を生成済みの画像ごと削除しますか?",
+            Msg::NothingRemoved => "何も削除しませんでした",
+            Msg::SkippedByPlugin => "{} をスキップします ({})",
+            Msg::PluginsUnavailable => "プラグインには scripting 機能付きのビルドが必要です。プラグインなしで実行します",
+            Msg::ConfigNotFound => "設定ファイルが見つかりません: {}",
+            Msg::UsingDefaultConfig => "既定の設定を使用します",
+            Msg::ScoringFailed => "画像を採点できませんでした。画像はそのまま残します: {}",
+            Msg::NoImageReachedMinScore => "最低スコアに達した画像がないため、最良の画像を残します",
+            Msg::Rerolling => "最高スコア {} が {} 未満のため、新しいシードで再生成します ({}/{})",
+            Msg::RerollFailed => "再生成に失敗しました。これまでの画像を残します: {}",
+            Msg::ToppingUp => "応答に {} 枚の画像が不足しているため、もう一度要求します",
+            Msg::TopUpFailed => "不足分の要求に失敗しました。これまでの画像を残します: {}",
+            Msg::SplittingBatch => "バッチを最大 {1} 枚ずつの {0} 件のリクエストに分割します",
+            Msg::RetryReducedBatch => "バッチサイズを減らして再試行します ({})",
+            Msg::NanFallback => "NaN テンソルが生成されたため、代替サンプラーで再試行します: {}",
+            Msg::NotRetryable => "再試行できないエラーのため、中止します: {}",
+            Msg::GpuErrorRetry => "CUDA/GPU エラーを検出しました。再試行します {}/{}: {}",
+            Msg::RetryableError => "再試行可能なエラーです。再試行します {}/{}: {}",
+            Msg::RecoveringGpu => "CUDA エラーが続くため、最後の試行の前にチェックポイントを再読み込みします",
+            Msg::UnloadCheckpointFailed => "チェックポイントをアンロードできませんでした: {}",
+            Msg::ReloadCheckpointFailed => "チェックポイントを再読み込みできませんでした: {}",
+            Msg::WaitingForRestart => "サーバーとの接続が切れました。最大 {} 秒間復帰を待ちます",
+            Msg::ServerDown => "サーバーが復帰しませんでした。応答が戻るまで入力を失敗として扱います",
+            Msg::ServerBack => "サーバーが {} 秒後に復帰しました。チェックポイントを再読み込みします",
+            Msg::SlowingDown => "生成が遅くなっています。GPU メモリが不足している可能性があります。{}ms 一時停止します",
+            Msg::PresetResult => "{} ({}): 成功 {}、失敗 {}、生成 {}、平均 {}ms、合計 {}秒",
+            Msg::PresetNoResults => "{} ({}): 結果なし",
+            Msg::InputsWithProblems => "{1} 件中 {0} 件の入力に問題があります",
+            Msg::SummaryTitle => "urasoe の実行が完了しました",
+            Msg::SummaryProcessed => "正常に処理: {}/{} 枚、新規生成 {} 枚、失敗 {} 枚",
+            Msg::SummaryDuration => "所要時間: {} 分",
+            Msg::SummaryFailureReasons => "失敗の理由: {}",
+            Msg::SummaryReport => "レポート: {}",
+            Msg::DesktopSummary => "成功 {}、失敗 {}、生成画像 {} 枚",
+            Msg::DesktopNotificationFailed => "デスクトップ通知を表示できませんでした: {}",
+            Msg::SummarySent => "実行の概要を {} に送信しました",
+            Msg::NotifyFailed => "{} に通知できませんでした: {}",
+            Msg::JobSubmitted => "ジョブを登録しました: {}",
+            Msg::JobCancelled => "ジョブを取り消しました: {}",
+            Msg::CancellingJob => "実行中のジョブを取り消します: {}",
+            Msg::InvalidJobFile => "無効なジョブファイル: {}",
+            Msg::StartingJob => "ジョブを開始します: {}",
+            Msg::JobLeftInSpool => "次回の起動のためにジョブをスプールに残しました: {}",
+            Msg::JobArchived => "ジョブをアーカイブしました: {}",
+            Msg::JobFailed => "ジョブが失敗しました: {}",
+            Msg::WatchingSpool => "スプールディレクトリを監視しています: {}",
+            Msg::GrpcUnavailable => "gRPC ジョブ制御には grpc 機能付きのビルドが必要です。grpc_addr を無視します",
+            Msg::CheckJobsFailed => "ジョブを確認できませんでした: {}",
+            Msg::DaemonStopped => "デーモンを停止しました",
+            Msg::SweepingWithSeed => "シード {} でスイープします",
+            Msg::WarmingUp => "ウォームアップ中...",
+            Msg::WarmUpFailed => "ウォームアップに失敗しました: {}",
+            Msg::Benchmarking => "ベンチマーク中: {}、{} ステップ、{}",
+            Msg::MemoryStatsFailed => "メモリ統計を読み取れませんでした: {}",
+            Msg::BenchmarkFailed => "失敗: {}、{} ステップ、{}: {}",
+            Msg::RampFailed => "バッチサイズ {} で失敗したため、バッチサイズ {} のままにします",
+            Msg::RampSlower => "バッチサイズ {} は 1 枚あたり {} ms かかります (以前は {} ms)。バッチサイズ {} のままにします",
+            Msg::RampingUp => "バッチサイズを {} に引き上げます",
+            Msg::SkippingFailureRecord => "失敗記録をスキップします: {}",
+            Msg::ListsFromCache => "サーバーの一覧を取得できませんでした: {}。{2} にキャッシュされた {1} を使用します",
+            Msg::ListCacheWriteFailed => "一覧キャッシュを書き込めませんでした: {}",
+            Msg::ProgressWriteFailed => "進捗ファイルを書き込めませんでした: {}",
+            Msg::RunDirectory => "実行ディレクトリ: {}",
+            Msg::DashboardNeedsTerminal => "ダッシュボードには端末が必要です。代わりにログを表示します",
+            Msg::QueueReadFailed => "ジョブキュー {} を読み取れませんでした: {}",
+            Msg::ServingGallery => "{} のギャラリーを http://{}/ で公開しています",
+            Msg::AcceptFailed => "{} の接続を受け付けられませんでした: {}",
+            Msg::MetricsUnavailable => "メトリクスのエンドポイントには server 機能付きのビルドが必要です。metrics_addr を無視します",
+            Msg::ServingMetrics => "メトリクスを http://{}/metrics で公開しています",
+            Msg::ServingGrpc => "gRPC ジョブ制御を {} で公開しています",
+            Msg::InvalidUserAgent => "無効なユーザーエージェントのため、既定値を使用します: {}",
+            Msg::InvalidRunId => "無効な実行IDのため送信しません: {}",
+            Msg::InvalidCredentials => "無効なAPI認証情報のため送信しません: {}",
+            Msg::NoStyleReference => "{} のスタイル参照がありません",
+            Msg::ComparingWithSeed => "シード {} で比較します",
+            Msg::GeneratingCell => "生成中: {}",
+            Msg::CellFailed => "{} を生成できませんでした: {}",
+            Msg::KeptBest => "{1} 枚中ベストの {0} 枚を残しました",
+            Msg::AnsweredByPolicy => "確認ポリシーによる回答: {}",
+            Msg::AnswerYes => "はい",
+            Msg::AnswerNo => "いいえ",
+            Msg::SequenceSeed => "シード {} でフレームシーケンスを処理します",
+            Msg::SequenceMissingFrames => "シーケンスに生成画像のないフレームが {} 枚あります",
+            Msg::GatheredFrames => "{1} に {0} フレームを集めました",
+            Msg::AssembledVideo => "動画を作成しました: {}",
+            Msg::UnnumberedFrames => "名前にフレーム番号のない入力: {}",
+            Msg::OutsideHours => "許可時間 {} の外です - {} 分間一時停止します",
+            Msg::HoursStarted => "許可時間になりました。再開します",
+            Msg::MqttPublishFailed => "MQTT の発行に失敗しました: {}",
+            Msg::MqttDisabled => "この実行では MQTT を無効にします: {}",
+            Msg::MqttTakingJobs => "MQTT トピック {} からジョブを受け付けます",
+            Msg::MqttJobRejected => "MQTT ジョブを拒否しました: {}",
+            Msg::NoTimelapseImages => "タイムラプス用の生成画像がありません",
+            Msg::AssembledTimelapse => "{1} 枚の画像からタイムラプスを作成しました: {0}",
+            Msg::FfmpegNotFound => "ffmpeg が見つからないため、動画を作成しません",
+            Msg::Upscaling => "{} 枚の画像を {} x{} で拡大します",
+            Msg::Upscaled => "拡大しました: {}",
+            Msg::UpscaleFailed => "{} を拡大できませんでした: {}",
+            Msg::UpscaleSummary => "拡大 {}、スキップ {}、失敗 {}",
+            Msg::JobArchiveFailed => "ジョブ {} をアーカイブできませんでした。デーモンを再起動するまでスキップします: {}",
+        }
+    }
+}
+
+/// Message in the selected language, for messages without values
+pub fn tr(msg: Msg) -> &'static str {
+    msg.template(lang())
+}
+
+/// Message in the selected language with the values filled in
+///
+/// # Arguments
+/// * `msg` - Message of the catalog
+/// * `args` - Values for the placeholders of the template
+///
+/// # Returns
+/// * `String` - The message, placeholders without a value are left as they are
+pub fn tr_args(msg: Msg, args: &[&dyn Display]) -> String {
+    fill(tr(msg), args)
+}
+
+/// Fill the `{}` and `{N}` placeholders of a template
+pub fn fill(template: &str, args: &[&dyn Display]) -> String {
+    let mut text = String::with_capacity(template.len());
+    let mut next = 0;
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        text.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let Some(end) = after.find('}') else {
+            rest = &rest[start..];
+            break;
+        };
+        let placeholder = &after[..end];
+        let index = if placeholder.is_empty() {
+            next += 1;
+            Some(next - 1)
+        } else {
+            placeholder.parse::<usize>().ok()
+        };
+        match index.and_then(|index| args.get(index)) {
+            Some(value) => text.push_str(&value.to_string()),
+            None => text.push_str(&rest[start..start + end + 2]),
+        }
+        rest = &after[end + 1..];
+    }
+    text.push_str(rest);
+    text
+}
@@ -4,14 +4,114 @@ use base64::{Engine, prelude::BASE64_STANDARD};
  * Image processing utilities for ControlNet Image Generator
  *
  * This module provides functionality for working with images, including:
- * - Discovering image files in directories
+ * - Discovering image files in directories, optionally recursively and
+ *   filtered by glob patterns
  * - Converting images to base64 for API transmission
  * - Supporting various image formats like JPEG, PNG, and WEBP
  */
+use regex::Regex;
 use std::fs;
 use std::io::Read;
 use std::path::{Path, PathBuf};
 
+use crate::config::Config;
+
+/// Extensions of the files picked up as inputs, compared in lower case
+const IMAGE_EXTENSIONS: [&str; 4] = ["jpg", "jpeg", "png", "webp"];
+
+/// Glob patterns choosing inputs by their path relative to the input directory
+///
+/// Patterns use `/` between folders, `*` and `?` match within a folder name,
+/// `**` across folders and `[...]` one of a set of characters. A pattern
+/// without `/` is matched against the file name alone, at any depth.
+#[derive(Debug, Clone, Default)]
+pub struct InputFilter {
+    include: Vec<(Regex, bool)>,
+    exclude: Vec<(Regex, bool)>,
+}
+
+impl InputFilter {
+    /// Filter keeping the inputs matching any include pattern, all when there are none, and no exclude pattern
+    ///
+    /// # Arguments
+    /// * `include` - Glob patterns of the inputs to keep
+    /// * `exclude` - Glob patterns of the inputs to leave out
+    pub fn new(include: &[String], exclude: &[String]) -> Result<Self> {
+        let compile = |patterns: &[String]| -> Result<Vec<(Regex, bool)>> {
+            patterns
+                .iter()
+                .map(|pattern| Ok((glob_to_regex(pattern)?, !pattern.contains('/'))))
+                .collect()
+        };
+        Ok(Self {
+            include: compile(include)?,
+            exclude: compile(exclude)?,
+        })
+    }
+
+    /// Whether an input is kept
+    ///
+    /// # Arguments
+    /// * `relative` - Path of the input relative to the input directory
+    pub fn matches(&self, relative: &Path) -> bool {
+        let path = relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        let file_name = relative.file_name().unwrap_or_default().to_string_lossy();
+        let is_match = |(regex, by_name): &(Regex, bool)| regex.is_match(if *by_name { &file_name } else { &path });
+        (self.include.is_empty() || self.include.iter().any(is_match)) && !self.exclude.iter().any(is_match)
+    }
+}
+
+/// Regular expression matching the same paths as a glob pattern
+fn glob_to_regex(pattern: &str) -> Result<Regex> {
+    let mut regex = String::from("^");
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                // `**/` also matches no folder at all
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    regex.push_str("(?:.*/)?");
+                } else {
+                    regex.push_str(".*");
+                }
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            '[' => {
+                let mut class = String::from("[");
+                if chars.peek() == Some(&'!') {
+                    chars.next();
+                    class.push('^');
+                }
+                // A `]` right at the start belongs to the set
+                let start = class.len();
+                loop {
+                    match chars.next() {
+                        Some(']') if class.len() > start => break,
+                        Some(c @ ('\\' | '[' | '&' | '~')) => {
+                            class.push('\\');
+                            class.push(c);
+                        }
+                        Some(c) => class.push(c),
+                        None => anyhow::bail!("Invalid glob pattern {}: unclosed [", pattern),
+                    }
+                }
+                class.push(']');
+                regex.push_str(&class);
+            }
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex.push('$');
+    Regex::new(&regex).context(format!("Invalid glob pattern {}", pattern))
+}
+
 /// Image processor for handling image-related operations
 pub struct ImageProcessor;
 
@@ -27,23 +127,43 @@ impl ImageProcessor {
     /// # Returns
     /// A Result containing a vector of PathBufs to the discovered image files
     pub fn get_image_list(directory_path: &str) -> Result<Vec<PathBuf>> {
-        let entries = fs::read_dir(directory_path)
-            .context(format!("Error reading directory: {}", directory_path))?;
+        Self::find_images(Path::new(directory_path), false, &InputFilter::default())
+    }
 
-        let image_paths: Vec<PathBuf> = entries
-            .filter_map(|entry| {
-                let entry = entry.ok()?;
+    /// Find the image files of a directory, optionally in its subdirectories as well
+    ///
+    /// # Arguments
+    /// * `directory_path` - Path to the directory containing images
+    /// * `recursive` - Whether to look into subdirectories
+    /// * `filter` - Glob patterns the paths relative to the directory must match
+    ///
+    /// # Returns
+    /// The paths of the images found, sorted
+    pub fn find_images(directory_path: &Path, recursive: bool, filter: &InputFilter) -> Result<Vec<PathBuf>> {
+        let mut image_paths = Vec::new();
+        let mut directories = vec![directory_path.to_path_buf()];
+        while let Some(directory) = directories.pop() {
+            let entries = fs::read_dir(&directory)
+                .context(format!("Error reading directory: {}", directory.display()))?;
+            for entry in entries.flatten() {
                 let path = entry.path();
-                let extension = path.extension()?.to_str()?.to_lowercase();
-
-                if ["jpg", "jpeg", "png", "webp"].contains(&extension.as_str()) {
-                    Some(path)
-                } else {
-                    None
+                if path.is_dir() {
+                    // Linked folders are not followed, so a link to a parent cannot loop
+                    if recursive && entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+                        directories.push(path);
+                    }
+                    continue;
                 }
-            })
-            .collect();
-
+                let is_image = path
+                    .extension()
+                    .and_then(|extension| extension.to_str())
+                    .is_some_and(|extension| IMAGE_EXTENSIONS.contains(&extension.to_lowercase().as_str()));
+                if is_image && filter.matches(path.strip_prefix(directory_path).unwrap_or(&path)) {
+                    image_paths.push(path);
+                }
+            }
+        }
+        image_paths.sort();
         Ok(image_paths)
     }
 
@@ -67,6 +187,81 @@ impl ImageProcessor {
 
         Ok(BASE64_STANDARD.encode(&buffer))
     }
+
+    /// Pad an image with black to an aspect ratio, keeping it centered
+    ///
+    /// The image keeps its resolution and only grows in the direction it is
+    /// short in, so nothing is cropped or stretched.
+    ///
+    /// # Arguments
+    /// * `image` - Encoded image
+    /// * `width` - Width of the aspect ratio
+    /// * `height` - Height of the aspect ratio
+    ///
+    /// # Returns
+    /// The padded image encoded as PNG
+    pub fn letterbox(image: &[u8], width: u32, height: u32) -> Result<Vec<u8>> {
+        let image = image::load_from_memory(image).context("Failed to decode control image")?;
+        let (image_width, image_height) = (image.width(), image.height());
+        let (padded_width, padded_height) = Self::letterbox_size((image_width, image_height), width, height);
+
+        let mut padded = image::RgbaImage::from_pixel(padded_width, padded_height, image::Rgba([0, 0, 0, 255]));
+        image::imageops::overlay(
+            &mut padded,
+            &image.to_rgba8(),
+            i64::from((padded_width - image_width) / 2),
+            i64::from((padded_height - image_height) / 2),
+        );
+        let mut encoded = Vec::new();
+        padded
+            .write_to(&mut std::io::Cursor::new(&mut encoded), image::ImageFormat::Png)
+            .context("Failed to encode control image")?;
+        Ok(encoded)
+    }
+
+    /// Size of an image after padding it to an aspect ratio with `letterbox`
+    ///
+    /// # Arguments
+    /// * `size` - Width and height of the image
+    /// * `width` - Width of the aspect ratio
+    /// * `height` - Height of the aspect ratio
+    pub fn letterbox_size((image_width, image_height): (u32, u32), width: u32, height: u32) -> (u32, u32) {
+        let (width, height) = (u64::from(width.max(1)), u64::from(height.max(1)));
+        // Compare image_width / image_height with width / height without rounding
+        if u64::from(image_width) * height < u64::from(image_height) * width {
+            ((u64::from(image_height) * width).div_ceil(height) as u32, image_height)
+        } else {
+            (image_width, (u64::from(image_width) * height).div_ceil(width) as u32)
+        }
+    }
+
+    /// Width and height of the control image of an input as `control_image_base64` sends it
+    ///
+    /// # Arguments
+    /// * `image_path` - Path to the input image
+    /// * `config` - Configuration with the size and whether to letterbox
+    pub fn control_image_size(image_path: &Path, config: &Config) -> Result<(u32, u32)> {
+        let size = image::image_dimensions(image_path)
+            .context(format!("Error reading image: {}", image_path.display()))?;
+        Ok(if config.letterbox {
+            Self::letterbox_size(size, config.width, config.height)
+        } else {
+            size
+        })
+    }
+
+    /// Control image of an input as base64, letterboxed to the size of the generated images when configured
+    ///
+    /// # Arguments
+    /// * `image_path` - Path to the input image
+    /// * `config` - Configuration with the size and whether to letterbox
+    pub fn control_image_base64(image_path: &Path, config: &Config) -> Result<String> {
+        if !config.letterbox {
+            return Self::image_to_base64(image_path);
+        }
+        let image = fs::read(image_path).context(format!("Error reading image: {}", image_path.display()))?;
+        Ok(BASE64_STANDARD.encode(Self::letterbox(&image, config.width, config.height)?))
+    }
 }
 
 // Legacy functions for backward compatibility
@@ -0,0 +1,254 @@
+use anyhow::Result;
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader};
+/**
+ * Input triage for ControlNet Image Generator
+ *
+ * This module looks through the inputs of a run before any GPU time is spent
+ * on them: the resolution, format and orientation of each image, the size of
+ * the request it makes and the size of the images generated from it under
+ * the current configuration. Files that are likely to give surprising
+ * results, such as corrupt images, photos rotated only by their EXIF data or
+ * inputs cropped heavily to fit the configured size, are flagged.
+ */
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::config::{Config, ResizeMode};
+use crate::image::{ImageProcessor, InputFilter};
+use crate::sidecar::Sidecar;
+
+/// Column headers of the inspection table
+pub const TABLE_HEADERS: [&str; 7] = ["INPUT", "SIZE", "FORMAT", "ORIENTATION", "PAYLOAD", "OUTPUT", "PROBLEMS"];
+
+/// Largest control image sent as base64 before it is flagged, in bytes
+pub const MAX_PAYLOAD_BYTES: usize = 16 * 1024 * 1024;
+
+/// Largest factor an input is scaled up by to the output size before it is flagged
+pub const MAX_UPSCALE: f64 = 2.0;
+
+/// Largest share of an input cropped, padded or stretched to fit the output size before it is flagged
+pub const MAX_FIT_CHANGE: f64 = 0.25;
+
+/// Shape of an input image
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Orientation {
+    /// Wider than it is tall
+    Landscape,
+    /// Taller than it is wide
+    Portrait,
+    /// As wide as it is tall
+    Square,
+}
+
+impl Orientation {
+    /// Shape of an image of the given size
+    pub fn of(width: u32, height: u32) -> Self {
+        match width.cmp(&height) {
+            std::cmp::Ordering::Greater => Orientation::Landscape,
+            std::cmp::Ordering::Less => Orientation::Portrait,
+            std::cmp::Ordering::Equal => Orientation::Square,
+        }
+    }
+}
+
+/// What was found out about one input
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct InputReport {
+    /// Path of the input image
+    pub path: PathBuf,
+    /// Width and height of the image as stored, when it could be read
+    pub size: Option<(u32, u32)>,
+    /// Format of the image by its contents
+    pub format: Option<String>,
+    /// Shape of the image as stored
+    pub orientation: Option<Orientation>,
+    /// Whether EXIF data asks for the image to be rotated or flipped when shown
+    pub exif_rotated: bool,
+    /// Size of the control image sent as base64, in bytes
+    pub payload_bytes: Option<usize>,
+    /// Width and height of the generated images
+    pub output_size: (u32, u32),
+    /// Likely problems with the input
+    pub problems: Vec<String>,
+}
+
+impl InputReport {
+    /// Whether anything was flagged
+    pub fn has_problems(&self) -> bool {
+        !self.problems.is_empty()
+    }
+}
+
+/// Inspect every input of a directory
+///
+/// Subdirectories and glob patterns are followed as configured for the run.
+///
+/// # Arguments
+/// * `dir` - Directory of the input images
+/// * `config` - Configuration the inputs would be generated with
+///
+/// # Returns
+/// A report per input, in file name order
+pub fn inspect_dir(dir: &Path, config: &Config) -> Result<Vec<InputReport>> {
+    let filter = InputFilter::new(&config.include, &config.exclude)?;
+    let paths = ImageProcessor::find_images(dir, config.recursive, &filter)?;
+    let mut reports: Vec<InputReport> = paths.iter().map(|path| inspect(path, config)).collect();
+
+    // The images of an input are saved into a folder named by the output naming
+    let mut names: HashMap<PathBuf, Vec<usize>> = HashMap::new();
+    for (index, path) in paths.iter().enumerate() {
+        names.entry(config.output_folder(path)).or_default().push(index);
+    }
+    for indexes in names.values().filter(|indexes| indexes.len() > 1) {
+        for &index in indexes {
+            let others: Vec<String> = indexes
+                .iter()
+                .filter(|&&other| other != index)
+                .map(|&other| paths[other].file_name().unwrap_or_default().to_string_lossy().to_string())
+                .collect();
+            reports[index]
+                .problems
+                .push(format!("shares its output folder with {}", others.join(", ")));
+        }
+    }
+    Ok(reports)
+}
+
+/// Inspect one input
+///
+/// # Arguments
+/// * `path` - Path of the input image
+/// * `config` - Configuration the input would be generated with
+pub fn inspect(path: &Path, config: &Config) -> InputReport {
+    let mut report = InputReport {
+        path: path.to_path_buf(),
+        size: None,
+        format: None,
+        orientation: None,
+        exif_rotated: false,
+        payload_bytes: None,
+        output_size: (config.width, config.height),
+        problems: Vec::new(),
+    };
+
+    match decode(path) {
+        Ok((image, format, orientation)) => {
+            let (width, height) = (image.width(), image.height());
+            report.size = Some((width, height));
+            report.orientation = Some(Orientation::of(width, height));
+            report.exif_rotated = orientation != image::metadata::Orientation::NoTransforms;
+            if let Some(format) = format {
+                report.format = Some(format!("{:?}", format).to_lowercase());
+                if ImageFormat::from_path(path).is_ok_and(|by_extension| by_extension != format) {
+                    report.problems.push(format!(
+                        "the extension does not match its {} contents",
+                        report.format.as_deref().unwrap_or_default()
+                    ));
+                }
+            }
+            if report.exif_rotated {
+                report
+                    .problems
+                    .push("EXIF data rotates it, the server sees it as stored".to_string());
+            }
+            if image.color().has_alpha() && image.to_rgba8().pixels().any(|pixel| pixel[3] < 255) {
+                report.problems.push("has transparent areas".to_string());
+            }
+            report.problems.extend(fit_problems((width, height), config));
+        }
+        Err(e) => report.problems.push(format!("cannot be decoded: {:#}", e)),
+    }
+
+    match ImageProcessor::control_image_base64(path, config) {
+        Ok(payload) => {
+            if payload.len() > MAX_PAYLOAD_BYTES {
+                report.problems.push(format!("sends {} per request", format_bytes(payload.len())));
+            }
+            report.payload_bytes = Some(payload.len());
+        }
+        Err(e) if report.size.is_some() => report.problems.push(format!("cannot be sent: {:#}", e)),
+        Err(_) => {}
+    }
+    if let Err(e) = Sidecar::load_for(path) {
+        report.problems.push(format!("sidecar: {:#}", e));
+    }
+    report
+}
+
+/// Decode an image fully, with its format by contents and its EXIF orientation
+fn decode(path: &Path) -> Result<(DynamicImage, Option<ImageFormat>, image::metadata::Orientation)> {
+    let reader = ImageReader::open(path)?.with_guessed_format()?;
+    let format = reader.format();
+    let mut decoder = reader.into_decoder()?;
+    let orientation = decoder.orientation().unwrap_or(image::metadata::Orientation::NoTransforms);
+    Ok((DynamicImage::from_decoder(decoder)?, format, orientation))
+}
+
+/// Problems fitting an input of a size to the output size of the configuration
+fn fit_problems((width, height): (u32, u32), config: &Config) -> Vec<String> {
+    let mut problems = Vec::new();
+    let (input_width, input_height) = (f64::from(width.max(1)), f64::from(height.max(1)));
+    let (output_width, output_height) = (f64::from(config.width.max(1)), f64::from(config.height.max(1)));
+    let upscale = (output_width / input_width).max(output_height / input_height);
+    if upscale > MAX_UPSCALE {
+        problems.push(format!("is scaled up {:.1}x to {}x{}", upscale, config.width, config.height));
+    }
+
+    // Letterboxed inputs are padded to the output aspect ratio before sending
+    let aspect = (input_width / input_height) / (output_width / output_height);
+    let change = 1.0 - aspect.min(1.0 / aspect);
+    if !config.letterbox && change > MAX_FIT_CHANGE {
+        let action = match config.resize_mode {
+            ResizeMode::JustResize => "stretches",
+            ResizeMode::CropAndResize => "crops",
+            ResizeMode::ResizeAndFill => "pads",
+        };
+        problems.push(format!(
+            "{} {:.0}% to fit {}x{}",
+            action,
+            change * 100.0,
+            config.width,
+            config.height
+        ));
+    }
+    problems
+}
+
+/// Size in bytes for people, e.g. `1.2 MB`
+fn format_bytes(bytes: usize) -> String {
+    match bytes {
+        0..1_000 => format!("{} B", bytes),
+        1_000..1_000_000 => format!("{:.1} kB", bytes as f64 / 1_000.0),
+        _ => format!("{:.1} MB", bytes as f64 / 1_000_000.0),
+    }
+}
+
+/// Cells of the inspection table, in the order of `TABLE_HEADERS`
+pub fn row(report: &InputReport) -> Vec<String> {
+    let orientation = report.orientation.map_or_else(
+        || "-".to_string(),
+        |orientation| {
+            let orientation = format!("{:?}", orientation).to_lowercase();
+            if report.exif_rotated {
+                format!("{} (EXIF rotated)", orientation)
+            } else {
+                orientation
+            }
+        },
+    );
+    vec![
+        report.path.file_name().unwrap_or_default().to_string_lossy().to_string(),
+        report.size.map_or_else(|| "-".to_string(), |(width, height)| format!("{}x{}", width, height)),
+        report.format.clone().unwrap_or_else(|| "-".to_string()),
+        orientation,
+        report.payload_bytes.map_or_else(|| "-".to_string(), format_bytes),
+        format!("{}x{}", report.output_size.0, report.output_size.1),
+        if report.problems.is_empty() {
+            "-".to_string()
+        } else {
+            report.problems.join("; ")
+        },
+    ]
+}
@@ -1,15 +1,72 @@
 pub mod api;
 pub mod api_types;
+pub mod benchmark;
 /**
  * Library for ControlNet Image Generator
  *
  * This library provides functionality for generating images with ControlNet,
  * using Stable Diffusion Automatic1111.
  */
+#[cfg(feature = "cli")]
+pub mod commands;
+pub mod compare;
+pub mod composite;
 pub mod config;
+pub mod control;
+pub mod daemon;
+#[cfg(feature = "tui")]
+pub mod dashboard;
+pub mod digest;
+pub mod doctor;
+pub mod exit;
+pub mod ffmpeg;
 pub mod file_utils;
+pub mod fixtures;
+#[cfg(feature = "server")]
+pub mod gallery;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod history;
+pub mod hooks;
+pub mod http;
+pub mod i18n;
 pub mod image;
+pub mod inspect;
+pub mod list_cache;
+pub mod logging;
+#[cfg(feature = "cli")]
+pub mod manpage;
+pub mod metrics;
+pub mod mqtt;
+pub mod notify;
+pub mod pipeline;
+#[cfg(feature = "scripting")]
+pub mod plugins;
+pub mod preview;
 pub mod processing;
+pub mod progress;
+pub mod prompt;
+pub mod prompt_source;
+pub mod queue;
+pub mod ramp;
+pub mod rollup;
+pub mod run_dir;
+pub mod runner;
+pub mod schedule;
+pub mod selection;
+pub mod sequence;
+pub mod server_files;
+pub mod sheet;
+pub mod sidecar;
+pub mod sink;
+#[cfg(feature = "cli")]
+pub mod status_line;
+pub mod style;
+pub mod style_reference;
+pub mod sweep;
+pub mod timelapse;
+pub mod upscale;
+pub mod version;
 
 #[cfg(test)]
 mod tests;
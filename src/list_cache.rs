@@ -0,0 +1,209 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+/**
+ * Server list cache for ControlNet Image Generator
+ *
+ * This module keeps the checkpoint, sampler and ControlNet model and module
+ * lists of each server in a small JSON file in the output directory. Option
+ * validation at the start of a run, `urasoe validate` and `urasoe doctor`
+ * take the lists from there while they are younger than the configured time
+ * to live instead of asking the server for each of them again. When the
+ * server cannot be reached, the lists saved earlier are used regardless of
+ * their age, so a mistyped model name is still caught while offline.
+ */
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+
+use crate::config::Config;
+use crate::i18n::{Msg, tr_args};
+use crate::style::*;
+
+/// Default file name of the list cache, stored inside the output directory
+pub const DEFAULT_LIST_CACHE_FILE: &str = ".urasoe-lists.json";
+
+/// Settings of the server list cache
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ListCacheConfig {
+    /// Whether the lists of the server are cached at all
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// How long cached lists are used before asking the server again, in seconds; 0 always asks
+    #[serde(default = "default_ttl_secs")]
+    pub ttl_secs: u64,
+    /// File of the cache, relative to the output directory
+    #[serde(default = "default_file")]
+    pub file: String,
+    /// Whether fetched lists are left out of the cache, for checks that must not write
+    #[serde(skip)]
+    pub read_only: bool,
+}
+
+impl Default for ListCacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_enabled(),
+            ttl_secs: default_ttl_secs(),
+            file: default_file(),
+            read_only: false,
+        }
+    }
+}
+
+/// Default for caching the lists - on
+pub fn default_enabled() -> bool {
+    true
+}
+
+/// Default time to live of the cached lists - one hour
+pub fn default_ttl_secs() -> u64 {
+    3600
+}
+
+/// Default file of the cache
+pub fn default_file() -> String {
+    DEFAULT_LIST_CACHE_FILE.to_string()
+}
+
+/// A list of names as the server returned it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CachedList {
+    /// Time the list was fetched, RFC 3339
+    pub fetched: String,
+    /// Names in the list
+    pub names: Vec<String>,
+}
+
+impl CachedList {
+    /// Age of the list at a time, `None` when its timestamp cannot be read
+    fn age(&self, now: DateTime<Utc>) -> Option<chrono::Duration> {
+        DateTime::parse_from_rfc3339(&self.fetched)
+            .ok()
+            .map(|fetched| now.signed_duration_since(fetched))
+    }
+}
+
+/// Cached lists, by API URL and then by list name
+pub type CachedLists = BTreeMap<String, BTreeMap<String, CachedList>>;
+
+/// Cache of the lists of one server
+#[derive(Debug)]
+pub struct ListCache {
+    /// File of the cache, `None` when caching is off
+    path: Option<PathBuf>,
+    api_url: String,
+    ttl: chrono::Duration,
+    read_only: bool,
+    lists: CachedLists,
+}
+
+impl ListCache {
+    /// Cache of the configured server, with the lists saved so far
+    ///
+    /// An unreadable cache file is ignored and replaced on the next write.
+    ///
+    /// # Arguments
+    /// * `config` - Configuration of the run, the file being relative to its output directory
+    pub fn from_config(config: &Config) -> Self {
+        let path = config
+            .list_cache
+            .enabled
+            .then(|| Path::new(&config.output_dir).join(&config.list_cache.file));
+        let lists = path.as_deref().map(read).unwrap_or_default();
+        Self {
+            path,
+            api_url: config.sd_api_url.clone(),
+            ttl: chrono::Duration::seconds(i64::try_from(config.list_cache.ttl_secs).unwrap_or(i64::MAX)),
+            read_only: config.list_cache.read_only,
+            lists,
+        }
+    }
+
+    /// Cached list of the server when it is younger than the time to live
+    pub fn fresh(&self, name: &str) -> Option<&CachedList> {
+        let list = self.lists.get(&self.api_url)?.get(name)?;
+        list.age(Utc::now()).filter(|age| *age < self.ttl).map(|_| list)
+    }
+
+    /// A list of the server, from the cache while fresh and fetched otherwise
+    ///
+    /// A fetched list replaces the cached one. When fetching fails, the
+    /// cached list is used whatever its age, with a warning.
+    ///
+    /// # Arguments
+    /// * `name` - Name of the list in the cache, such as `samplers`
+    /// * `fetch` - Request for the list to the server
+    ///
+    /// # Returns
+    /// The names in the list, or the error of the request when nothing is cached
+    pub async fn list<F>(&mut self, name: &str, fetch: F) -> Result<Vec<String>>
+    where
+        F: Future<Output = Result<Vec<String>>>,
+    {
+        if self.path.is_none() {
+            return fetch.await;
+        }
+        if let Some(list) = self.fresh(name) {
+            debug!("Using the {} cached at {}", name, list.fetched);
+            return Ok(list.names.clone());
+        }
+        match fetch.await {
+            Ok(names) => {
+                self.store(name, names.clone());
+                Ok(names)
+            }
+            Err(e) => match self.lists.get(&self.api_url).and_then(|lists| lists.get(name)) {
+                Some(list) => {
+                    warn!("{}", tr_args(Msg::ListsFromCache, &[&format!("{:#}", e), &name, &list.fetched]).yellow());
+                    Ok(list.names.clone())
+                }
+                None => Err(e),
+            },
+        }
+    }
+
+    /// Save a fetched list
+    ///
+    /// Failing to write is only logged, the lists are then fetched again next time.
+    fn store(&mut self, name: &str, names: Vec<String>) {
+        let Some(path) = self.path.as_ref().filter(|_| !self.read_only) else {
+            return;
+        };
+        // Lists saved by other runs since this cache was read are kept
+        let mut lists = read(path);
+        lists.entry(self.api_url.clone()).or_default().insert(
+            name.to_string(),
+            CachedList {
+                fetched: Utc::now().to_rfc3339(),
+                names,
+            },
+        );
+        if let Err(e) = write(path, &lists) {
+            warn!("{}", tr_args(Msg::ListCacheWriteFailed, &[&format!("{:#}", e)]).yellow());
+        }
+        self.lists = lists;
+    }
+}
+
+/// Read the cached lists of a file, none when it is missing or unreadable
+fn read(path: &Path) -> CachedLists {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+/// Replace the file with the lists through a temporary file
+fn write(path: &Path, lists: &CachedLists) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut temporary = path.to_path_buf().into_os_string();
+    temporary.push(".tmp");
+    fs::write(&temporary, serde_json::to_string_pretty(lists)?)
+        .context(format!("Failed to write {}", path.display()))?;
+    fs::rename(&temporary, path).context(format!("Failed to replace {}", path.display()))
+}
@@ -0,0 +1,527 @@
+use serde::{Deserialize, Serialize};
+/**
+ * Logging for ControlNet Image Generator
+ *
+ * This module provides a small `tracing` subscriber that prints events to the
+ * console in the same colored, line-oriented format the application has always
+ * used. Events are filtered by level, and the fields of the enclosing spans
+ * (for example the image being processed) are shown at debug level and above.
+ *
+ * Alternatively events can be written as one JSON object per line, for log
+ * collectors such as Loki or Elasticsearch when running as a service.
+ */
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicU64, Ordering};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Level, Metadata, Subscriber};
+use tracing::level_filters::LevelFilter;
+
+use crate::style::*;
+
+/// Log level selectable from the command line and configuration file
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    /// Only errors
+    Error,
+    /// Errors and warnings
+    Warn,
+    /// Regular progress output
+    #[default]
+    Info,
+    /// Per-request details
+    Debug,
+    /// Everything, including low-level tracing
+    Trace,
+}
+
+impl LogLevel {
+    /// All levels from least to most verbose
+    const ALL: [LogLevel; 5] = [
+        LogLevel::Error,
+        LogLevel::Warn,
+        LogLevel::Info,
+        LogLevel::Debug,
+        LogLevel::Trace,
+    ];
+
+    /// Corresponding `tracing` level
+    pub fn as_level(&self) -> Level {
+        match self {
+            LogLevel::Error => Level::ERROR,
+            LogLevel::Warn => Level::WARN,
+            LogLevel::Info => Level::INFO,
+            LogLevel::Debug => Level::DEBUG,
+            LogLevel::Trace => Level::TRACE,
+        }
+    }
+
+    /// Level the given number of steps more verbose, or less when negative,
+    /// stopping at errors and trace
+    pub fn adjusted(self, steps: i8) -> LogLevel {
+        let index = (self as i8 + steps).clamp(0, Self::ALL.len() as i8 - 1);
+        Self::ALL[index as usize]
+    }
+}
+
+/// Output format of log events
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Colored human readable lines
+    #[default]
+    Text,
+    /// One JSON object per event and line
+    Json,
+}
+
+/// When log lines are colored
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum ColorMode {
+    /// Colored on a terminal, plain when the output is redirected or NO_COLOR is set
+    #[default]
+    Auto,
+    /// Always colored
+    Always,
+    /// Never colored
+    Never,
+}
+
+/// Most verbose level printed by the console subscriber, as an index into `LogLevel::ALL`
+static MAX_LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Info as u8);
+
+/// Output format used by the console subscriber, as `LogFormat` discriminant
+static FORMAT: AtomicU8 = AtomicU8::new(LogFormat::Text as u8);
+
+/// Whether the console subscriber writes to standard error instead of standard output
+static TO_STDERR: AtomicBool = AtomicBool::new(false);
+
+/// Whether log lines are plain, without colors, for CI logs and redirected output
+static PLAIN: AtomicBool = AtomicBool::new(false);
+
+/// File the events are copied into as JSON lines, the log of the current run
+static LOG_FILE: Mutex<Option<File>> = Mutex::new(None);
+
+/// Line kept below the log lines and redrawn after each of them, e.g. the progress of a run
+static STATUS_LINE: Mutex<Option<String>> = Mutex::new(None);
+
+/// Go to the start of the line and clear it
+const CLEAR_LINE: &str = "\r\x1b[K";
+
+/// Install the console subscriber as the global default
+///
+/// # Arguments
+/// * `level` - Most verbose level that will be printed
+///
+/// # Returns
+/// `false` if a global subscriber had already been installed
+pub fn init(level: LogLevel) -> bool {
+    set_level(level);
+    tracing::subscriber::set_global_default(ConsoleSubscriber::new()).is_ok()
+}
+
+/// Change the level of the console subscriber after it has been installed
+///
+/// Used once the configuration file has been read, as logging is needed
+/// before the configured level is known.
+pub fn set_level(level: LogLevel) {
+    MAX_LEVEL.store(level as u8, Ordering::Relaxed);
+    tracing::callsite::rebuild_interest_cache();
+}
+
+/// Level currently printed by the console subscriber
+pub fn current_level() -> LogLevel {
+    LogLevel::ALL[MAX_LEVEL.load(Ordering::Relaxed) as usize]
+}
+
+/// Change the output format of the console subscriber
+///
+/// JSON output disables terminal colors, so messages carry no escape codes.
+pub fn set_format(format: LogFormat) {
+    FORMAT.store(format as u8, Ordering::Relaxed);
+    if format == LogFormat::Json {
+        crate::style::set_enabled(false);
+    }
+}
+
+/// Output format currently used by the console subscriber
+pub fn current_format() -> LogFormat {
+    match FORMAT.load(Ordering::Relaxed) {
+        1 => LogFormat::Json,
+        _ => LogFormat::Text,
+    }
+}
+
+/// Send log lines to standard error, keeping standard output for results
+pub fn set_stderr(enabled: bool) {
+    TO_STDERR.store(enabled, Ordering::Relaxed);
+}
+
+/// Choose between colored and plain log lines
+///
+/// In `Auto` mode lines are plain when the stream they go to is not a
+/// terminal, as in CI jobs, or when the `NO_COLOR` environment variable is
+/// set. Call this after `set_stderr`, as the stream decides. JSON output is
+/// never colored.
+pub fn set_color(mode: ColorMode) {
+    let no_color = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+    let terminal = if TO_STDERR.load(Ordering::Relaxed) {
+        io::stderr().is_terminal()
+    } else {
+        io::stdout().is_terminal()
+    };
+    let colored = match mode {
+        ColorMode::Always => true,
+        ColorMode::Never => false,
+        ColorMode::Auto => terminal && !no_color,
+    };
+    PLAIN.store(!colored, Ordering::Relaxed);
+    crate::style::set_enabled(colored && current_format() == LogFormat::Text);
+}
+
+/// Whether log lines are plain, so progress should be reported line by line
+pub fn is_plain() -> bool {
+    PLAIN.load(Ordering::Relaxed)
+}
+
+/// Whether a status line can be kept below the log lines, as they go to a terminal as colored text
+pub fn supports_status_line() -> bool {
+    let terminal = if TO_STDERR.load(Ordering::Relaxed) {
+        io::stderr().is_terminal()
+    } else {
+        io::stdout().is_terminal()
+    };
+    terminal && !is_plain() && current_format() == LogFormat::Text
+}
+
+/// Show a line below the log lines, replacing the previous one, or remove it with `None`
+pub fn set_status_line(line: Option<String>) {
+    let mut status_line = STATUS_LINE.lock().unwrap_or_else(|e| e.into_inner());
+    write_console(&format!("{}{}", CLEAR_LINE, line.as_deref().unwrap_or_default()));
+    *status_line = line;
+}
+
+/// Write text to the configured stream as it is
+fn write_console(text: &str) {
+    if TO_STDERR.load(Ordering::Relaxed) {
+        let mut stderr = io::stderr().lock();
+        let _ = write!(stderr, "{}", text);
+        let _ = stderr.flush();
+    } else {
+        let mut stdout = io::stdout().lock();
+        let _ = write!(stdout, "{}", text);
+        let _ = stdout.flush();
+    }
+}
+
+/// Copy the events into a file as well, one JSON object per line
+///
+/// Events are appended whatever the console format, without colors, until
+/// the file is changed or `None` is given.
+///
+/// # Arguments
+/// * `path` - File to append to, created when missing
+pub fn set_log_file(path: Option<&Path>) -> io::Result<()> {
+    let file = match path {
+        Some(path) => Some(fs::OpenOptions::new().create(true).append(true).open(path)?),
+        None => None,
+    };
+    *LOG_FILE.lock().unwrap_or_else(|e| e.into_inner()) = file;
+    Ok(())
+}
+
+/// Print a finished log line to the configured stream
+fn emit(line: impl fmt::Display) {
+    let status_line = STATUS_LINE.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(status_line) = status_line.as_ref() {
+        // The log line takes the place of the status line, which is drawn again below it
+        write_console(&format!("{}{}\n{}", CLEAR_LINE, line, status_line));
+    } else if TO_STDERR.load(Ordering::Relaxed) {
+        eprintln!("{}", line);
+    } else {
+        println!("{}", line);
+    }
+}
+
+thread_local! {
+    /// Stack of spans entered on the current thread
+    static CURRENT_SPANS: RefCell<Vec<Id>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Data kept for every open span
+struct SpanData {
+    /// Name of the span, e.g. "image"
+    name: &'static str,
+    /// Recorded fields in recording order
+    fields: Vec<(&'static str, serde_json::Value)>,
+    /// Number of handles referring to the span
+    ref_count: usize,
+}
+
+/// Subscriber printing events to the console
+///
+/// The message of each event is printed as-is, preserving any colors applied
+/// by the caller. Debug and trace events are dimmed and, like all events at
+/// those verbosity levels, prefixed with the fields of the enclosing spans.
+pub struct ConsoleSubscriber {
+    /// Next span identifier to hand out
+    next_id: AtomicU64,
+    /// Open spans by identifier
+    spans: Mutex<HashMap<u64, SpanData>>,
+}
+
+impl ConsoleSubscriber {
+    /// Create a subscriber printing events up to the level set with `set_level`
+    pub fn new() -> Self {
+        Self {
+            next_id: AtomicU64::new(1),
+            spans: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Render the spans entered on the current thread as a prefix
+    fn span_prefix(&self) -> String {
+        let spans = self.spans.lock().unwrap_or_else(|e| e.into_inner());
+        CURRENT_SPANS.with(|stack| {
+            stack
+                .borrow()
+                .iter()
+                .filter_map(|id| spans.get(&id.into_u64()))
+                .map(|span| format!("[{}{}] ", span.name, render_fields(&span.fields)))
+                .collect()
+        })
+    }
+
+    /// Collect the fields of the spans entered on the current thread
+    fn span_fields(&self) -> serde_json::Map<String, serde_json::Value> {
+        let spans = self.spans.lock().unwrap_or_else(|e| e.into_inner());
+        CURRENT_SPANS.with(|stack| {
+            stack
+                .borrow()
+                .iter()
+                .filter_map(|id| spans.get(&id.into_u64()))
+                .flat_map(|span| span.fields.iter())
+                .map(|(name, value)| (name.to_string(), value.clone()))
+                .collect()
+        })
+    }
+
+    /// Print an event as a colored line of text
+    fn write_text(&self, level: Level, visitor: FieldVisitor) {
+        let verbose = current_level() >= LogLevel::Debug;
+        let mut line = String::new();
+        if verbose {
+            line.push_str(&self.span_prefix().dimmed().to_string());
+        }
+        if level >= Level::DEBUG {
+            line.push_str(&visitor.message.dimmed().to_string());
+        } else {
+            line.push_str(&visitor.message);
+        }
+        if verbose && !visitor.fields.is_empty() {
+            line.push_str(&render_fields(&visitor.fields).dimmed().to_string());
+        }
+        emit(line);
+    }
+
+    /// Print an event as a single line of JSON
+    fn write_json(&self, level: Level, target: &str, visitor: FieldVisitor) {
+        emit(self.json_line(level, target, visitor));
+    }
+
+    /// Render an event as a JSON object
+    fn json_line(&self, level: Level, target: &str, visitor: FieldVisitor) -> serde_json::Value {
+        let mut object = serde_json::Map::new();
+        object.insert("timestamp".to_string(), chrono::Utc::now().to_rfc3339().into());
+        object.insert("level".to_string(), level.as_str().to_lowercase().into());
+        object.insert("target".to_string(), target.into());
+        object.insert("message".to_string(), visitor.message.into());
+        let spans = self.span_fields();
+        if !spans.is_empty() {
+            object.insert("span".to_string(), spans.into());
+        }
+        for (name, value) in visitor.fields {
+            object.insert(name.to_string(), value);
+        }
+        serde_json::Value::Object(object)
+    }
+}
+
+/// Remove the color escape sequences the caller may have applied to a message
+fn strip_colors(message: &str) -> String {
+    let mut plain = String::with_capacity(message.len());
+    let mut chars = message.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            chars.by_ref().find(|c| c.is_ascii_alphabetic());
+        } else {
+            plain.push(c);
+        }
+    }
+    plain
+}
+
+/// Render recorded fields as ` key=value` pairs
+fn render_fields(fields: &[(&'static str, serde_json::Value)]) -> String {
+    fields
+        .iter()
+        .map(|(name, value)| match value {
+            serde_json::Value::String(text) => format!(" {}={}", name, text),
+            other => format!(" {}={}", name, other),
+        })
+        .collect()
+}
+
+impl Default for ConsoleSubscriber {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Subscriber for ConsoleSubscriber {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        *metadata.level() <= current_level().as_level()
+    }
+
+    fn max_level_hint(&self) -> Option<LevelFilter> {
+        Some(LevelFilter::from_level(current_level().as_level()))
+    }
+
+    fn new_span(&self, attributes: &Attributes<'_>) -> Id {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let mut visitor = FieldVisitor::default();
+        attributes.record(&mut visitor);
+
+        let mut spans = self.spans.lock().unwrap_or_else(|e| e.into_inner());
+        spans.insert(
+            id,
+            SpanData {
+                name: attributes.metadata().name(),
+                fields: visitor.fields,
+                ref_count: 1,
+            },
+        );
+        Id::from_u64(id)
+    }
+
+    fn record(&self, span: &Id, values: &Record<'_>) {
+        let mut visitor = FieldVisitor::default();
+        values.record(&mut visitor);
+
+        let mut spans = self.spans.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(data) = spans.get_mut(&span.into_u64()) {
+            data.fields.extend(visitor.fields);
+        }
+    }
+
+    fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut visitor = FieldVisitor::default();
+        event.record(&mut visitor);
+
+        let level = *event.metadata().level();
+        if let Some(file) = LOG_FILE.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+            let copy = FieldVisitor {
+                message: strip_colors(&visitor.message),
+                fields: visitor.fields.clone(),
+            };
+            let _ = writeln!(file, "{}", self.json_line(level, event.metadata().target(), copy));
+        }
+        match current_format() {
+            LogFormat::Text => self.write_text(level, visitor),
+            LogFormat::Json => self.write_json(level, event.metadata().target(), visitor),
+        }
+    }
+
+    fn enter(&self, span: &Id) {
+        CURRENT_SPANS.with(|stack| stack.borrow_mut().push(span.clone()));
+    }
+
+    fn exit(&self, span: &Id) {
+        CURRENT_SPANS.with(|stack| {
+            let mut stack = stack.borrow_mut();
+            if let Some(position) = stack.iter().rposition(|entered| entered == span) {
+                stack.remove(position);
+            }
+        });
+    }
+
+    fn clone_span(&self, span: &Id) -> Id {
+        let mut spans = self.spans.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(data) = spans.get_mut(&span.into_u64()) {
+            data.ref_count += 1;
+        }
+        span.clone()
+    }
+
+    fn try_close(&self, span: Id) -> bool {
+        let mut spans = self.spans.lock().unwrap_or_else(|e| e.into_inner());
+        let id = span.into_u64();
+        let closed = match spans.get_mut(&id) {
+            Some(data) => {
+                data.ref_count -= 1;
+                data.ref_count == 0
+            }
+            None => false,
+        };
+        if closed {
+            spans.remove(&id);
+        }
+        closed
+    }
+}
+
+/// Collects the message and other fields of an event or span
+#[derive(Default)]
+struct FieldVisitor {
+    /// The `message` field of an event
+    message: String,
+    /// All other fields in recording order
+    fields: Vec<(&'static str, serde_json::Value)>,
+}
+
+impl Visit for FieldVisitor {
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.fields.push((field.name(), value.into()));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.fields.push((field.name(), value.into()));
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.fields.push((field.name(), value.into()));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.fields.push((field.name(), value.into()));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message.push_str(value);
+        } else {
+            self.fields.push((field.name(), value.into()));
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.message.push_str(&format!("{:?}", value));
+        } else {
+            self.fields.push((field.name(), format!("{:?}", value).into()));
+        }
+    }
+}
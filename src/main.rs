@@ -1,5 +1,4 @@
-use anyhow::{Context, Result};
-use clap::Parser;
+use anyhow::Result;
 use colored::*;
 /**
  * Generate Images with ControlNet
@@ -8,173 +7,152 @@ use colored::*;
  * to generate images using ControlNet, and stores the results in organized subfolders.
  * It supports various ControlNet models including canny edge, depth, and pose detection.
  */
-use std::fs;
-
-// Import modules
-mod api;
-mod config;
-mod file_utils;
-mod image;
-mod processing;
-
-use config::{Args, Config};
+use tracing::info;
+#[cfg(not(feature = "server"))]
+use tracing::warn;
+
+use std::process::ExitCode;
+use urasoe::config::{Args, Command, Config, OutputFormat};
+use urasoe::exit::ExitStatus;
+use urasoe::history::HistoryQuery;
+use urasoe::i18n::{self, Lang, Msg, tr};
+use urasoe::logging::{LogFormat, LogLevel};
+use urasoe::{benchmark, commands, daemon, doctor, logging, manpage, metrics, version};
 
 #[tokio::main]
-async fn main() -> Result<()> {
-    let args: Args = Args::parse();
+async fn main() -> ExitCode {
+    match run().await {
+        Ok(status) => status.into(),
+        Err(e) => {
+            eprintln!("Error: {:?}", e);
+            ExitStatus::from_error(&e).into()
+        }
+    }
+}
+
+async fn run() -> Result<ExitStatus> {
+    let args: Args = Args::parse_with_config_keys();
+    // Keep machine-readable output free of log lines
+    let machine_output =
+        matches!(
+            args.command,
+            Some(Command::Models { json: true })
+                | Some(Command::History { json: true, .. })
+                | Some(Command::Inspect { json: true, .. })
+                | Some(Command::Rollup { json: true, .. })
+        );
+    let log_level = if machine_output {
+        LogLevel::Error
+    } else {
+        args.log_level()
+    };
+    logging::init(log_level);
+    logging::set_format(args.log_format.unwrap_or_default());
+    // Piped images and JSON results own standard output
+    let stdout_reserved = matches!(args.command, Some(Command::Pipe))
+        || args.output_format == Some(OutputFormat::Json);
+    logging::set_stderr(stdout_reserved);
+    logging::set_color(args.color());
+    i18n::set_lang(message_lang(args.lang, args.log_format.unwrap_or_default()));
+
+    // Creating the configuration file does not need one to exist
+    if let Some(Command::Init { force }) = &args.command {
+        return commands::init(&args.config, *force).map(|_| ExitStatus::Success);
+    }
+    if let Some(Command::Man) = &args.command {
+        print!("{}", manpage::render());
+        return Ok(ExitStatus::Success);
+    }
+    if args.version {
+        print!("{}", version::render());
+        if !args.check {
+            return Ok(ExitStatus::Success);
+        }
+        let mut config = Config::load(&args.config)?;
+        config.apply_args(&args);
+        return version::check(&config).await;
+    }
 
-    println!("{}", "ControlNet Image Generator Starting...".blue());    // Load configuration from file
+    info!("{}", tr(Msg::Starting).blue());    // Load configuration from file
     let mut config: Config = Config::load(&args.config)?;
 
     // Override with command line arguments
     config.apply_args(&args);
-
-    // Create API client with timeout for option validation
-    let client = api::StableDiffusionClient::with_timeout(&config.sd_api_url, config.validate_timeout_ms);
-    
-    // Validate configuration options if enabled
-    if config.validate_options {
-        match client.validate_config_options(&config).await {
-            Ok(issues) => {
-                if !issues.is_empty() {
-                    println!("{}", "⚠️ Configuration validation issues found:".yellow().bold());
-                    for issue in issues {
-                        println!("{}", format!("  - {}", issue).yellow());
-                    }
-                    println!("{}", "Continue anyway? (Y/n)".yellow());
-                    let mut input = String::new();
-                    std::io::stdin().read_line(&mut input)?;
-                    if !input.trim().is_empty() && input.trim().to_lowercase() != "y" {
-                        return Ok(());
-                    }
-                } else {
-                    println!("{}", "✓ All configuration options are valid".green());
-                }
-            },
-            Err(e) => {
-                println!("{} {}", "Failed to validate configuration:".yellow(), e);
-                println!("{}", "Continue anyway? (Y/n)".yellow());
-                let mut input = String::new();
-                std::io::stdin().read_line(&mut input)?;
-                if !input.trim().is_empty() && input.trim().to_lowercase() != "y" {
-                    return Ok(());
-                }
-            }
-        }
+    if !machine_output {
+        logging::set_level(config.log_level);
     }
-    
-    // Print effective configuration
-    if config.verbose {
-        println!("{} {}", "Using ControlNet model:".blue(), config.model);
-        println!(
-            "{} {}",
-            "Using ControlNet module:".blue(),
-            config.controlnet_module
-        );
-        println!(
-            "{} {}",
-            "ControlNet weight:".blue(),
-            config.controlnet_weight
-        );
-        println!(
-            "{} {}",
-            "Using checkpoint model:".blue(),
-            config.checkpoint_model
-        );        println!(
-            "{} {} {}",
-            "Using sampler:".blue(),
-            config.sampler_name,
-            config.scheduler
-        );
-        println!("{} {}", "Reading images from:".blue(), config.input_dir);
-        println!("{} {}", "Saving output to:".blue(), config.output_dir);
-        println!("{} {}", "Batch size:".blue(), config.batch_size);        println!(
-            "{} {}x{}",
-            "Image dimensions:".blue(),
-            config.width,
-            config.height
-        );
-        println!("{} {}", "Sampling steps:".blue(), config.steps);
-        println!("{} {}", "CFG scale:".blue(), config.cfg);
-        println!("{} {}", "Max retries:".blue(), config.max_retries);        println!(
-            "{} {}ms",
-            "Retry delay:".blue(),
-            config.retry_delay_ms
-        );        println!(
-            "{} {}ms",
-            "Batch break:".blue(),
-            config.batch_break_ms
-        );
+    logging::set_format(config.log_format);
+    logging::set_stderr(stdout_reserved || config.output_format == OutputFormat::Json);
+    logging::set_color(config.color);
+    i18n::set_lang(message_lang(config.lang, config.log_format));
+
+    let finished = match &args.command {
+        Some(Command::Validate) => Some(commands::validate(&config).await),
+        Some(Command::Models { json }) => Some(commands::models(&config, *json).await),
+        Some(Command::Enqueue { images, priority }) => Some(commands::enqueue(&config, images, *priority)),
+        Some(Command::Clean { all }) => Some(commands::clean(&config, *all)),
+        Some(Command::History { prompt, failed, json }) => {
+            let query = HistoryQuery {
+                prompt: prompt.clone(),
+                model: args.model.clone(),
+                failed: *failed,
+            };
+            Some(commands::history(&config, &query, *json))
+        }
+        Some(Command::Doctor) => Some(doctor::run(&config).await),
+        Some(Command::Inspect { dir, json }) => Some(commands::inspect(&config, dir.as_deref(), *json)),
+        Some(Command::Rollup { dir, json, out }) => {
+            Some(commands::rollup(&config, dir.as_deref(), *json, out.as_deref()))
+        }
+        Some(Command::Benchmark { image, samplers, step_counts, sizes, repeat }) => {
+            let combinations = benchmark::combinations(&config, samplers, step_counts, sizes);
+            Some(commands::benchmark(&config, image, &combinations, *repeat).await)
+        }
+        Some(Command::Compare { image, x, y }) => Some(commands::compare(&config, image, x, y.as_ref()).await),
+        Some(Command::Regenerate { metadata, image }) => {
+            Some(commands::regenerate(&config, &args, metadata, image.as_deref()).await.map(|_| ()))
+        }
+        #[cfg(feature = "server")]
+        Some(Command::Serve { addr }) => Some(commands::serve(&config, addr).await),
+        Some(Command::Upscale { run_dir, force }) => Some(commands::upscale(&config, run_dir, *force).await),
+        Some(Command::Pipe) => Some(commands::pipe(&config).await),
+        _ => None,
+    };
+    if let Some(result) = finished {
+        return result.map(|_| ExitStatus::Success);
     }
 
-    // Ensure output directory exists
-    fs::create_dir_all(&config.output_dir).context("Failed to create output directory")?;
-
-    // Using our improved image processor
-    let image_paths: Vec<std::path::PathBuf> = image::ImageProcessor::get_image_list(&config.input_dir)?;
+    // Expose metrics for scraping while work is in progress
+    let run_metrics = metrics::Metrics::new();
+    let metrics_server: Option<tokio::task::JoinHandle<()>> = match &config.metrics_addr {
+        #[cfg(feature = "server")]
+        Some(address) => Some(metrics::serve(address, run_metrics.clone()).await?.1),
+        #[cfg(not(feature = "server"))]
+        Some(_) => {
+            warn!("{}", tr(Msg::MetricsUnavailable).yellow());
+            None
+        }
+        None => None,
+    };
 
-    if image_paths.is_empty() {
-        println!("{} {}", "No images found in".red(), config.input_dir);
-        return Ok(());
+    if let Some(Command::Daemon { .. }) = &args.command {
+        return daemon::run(&config, &args, &run_metrics).await.map(|_| ExitStatus::Success);
     }
 
-    println!(
-        "{} {} {}",
-        "Found".green(),
-        image_paths.len(),
-        "images to process".green()
-    );
-    // Create Stable Diffusion client and load model
-    let sd_client = api::StableDiffusionClient::new(&config.sd_api_url);
-    sd_client.load_model(&config.checkpoint_model).await?;
+    let status = commands::generate(&config, &args, &run_metrics).await?;
 
-    // Set up retry manager and batch manager
-    let retry_manager =
-        processing::RetryManager::with_config(config.max_retries, config.retry_delay_ms);
-    let batch_manager = processing::BatchManager::with_config(
-        1, // Process one image at a time
-        config.batch_break_ms,
-    );
-
-    // Initialize processing statistics
-    let mut stats = processing::ProcessingStats::new();
-    let total_images = image_paths.len();
-
-    // Process all images with retry logic
-    for (index, image_path) in image_paths.iter().enumerate() {
-        println!("{} {}", "Processing:".blue(), image_path.display()); // Use retry manager to handle potential CUDA errors
-        let result = retry_manager
-            .process_with_retry(&sd_client, &image_path, &config)
-            .await;
-
-        match result {            Ok(Some(generated)) => {
-                if file_utils::FileManager::save_generated_images(&generated, image_path, &config).is_ok() {
-                    stats.success_count += 1;
-                    stats.generated_count += generated.images.len();
-                } else {
-                    stats
-                        .failed_paths
-                        .push(image_path.to_string_lossy().to_string());
-                }
-            }
-            _ => {
-                println!(
-                    "{} {}",
-                    "Failed to generate images for:".red(),
-                    image_path.display()
-                );
-                stats
-                    .failed_paths
-                    .push(image_path.to_string_lossy().to_string());
-            }
-        }
-
-        // Take a break between batches if needed
-        batch_manager.manage_batch_break(index, total_images).await;
+    if let Some(server) = metrics_server {
+        server.abort();
     }
 
-    // Display final statistics
-    stats.display(total_images);
+    Ok(status)
+}
 
-    Ok(())
+/// Language of console messages, English in JSON logs for log collectors unless a language is given
+fn message_lang(lang: Option<Lang>, log_format: LogFormat) -> Lang {
+    match (lang, log_format) {
+        (Some(lang), _) => lang,
+        (None, LogFormat::Json) => Lang::En,
+        (None, LogFormat::Text) => Lang::from_env(),
+    }
 }
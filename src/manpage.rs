@@ -0,0 +1,129 @@
+use clap::{Arg, ArgAction, Command};
+/**
+ * Manual page for ControlNet Image Generator
+ *
+ * This module renders the command line definition as a roff manual page,
+ * printed by `urasoe man` so it can be installed next to the binary. The
+ * page is built from the same definition as `--help`, including the
+ * configuration file key of every option, so the two never disagree.
+ */
+use std::fmt::Write;
+
+use crate::config::{Args, DEFAULT_CONFIG_PATH};
+
+/// Render the manual page of urasoe
+pub fn render() -> String {
+    let mut command = Args::command_with_config_keys();
+    command.build();
+    render_command(&command)
+}
+
+/// Render a manual page for a command line definition
+///
+/// # Arguments
+/// * `command` - Built command, so global options are known to its subcommands
+pub fn render_command(command: &Command) -> String {
+    let name = command.get_name();
+    let version = command.get_version().unwrap_or_default();
+    let about = command.get_about().map(ToString::to_string).unwrap_or_default();
+
+    let mut page = String::new();
+    let _ = writeln!(page, ".TH {} 1 \"\" \"{} {}\" \"User Commands\"", name.to_uppercase(), name, version);
+    let _ = writeln!(page, ".SH NAME\n{} \\- {}", name, escape(&about));
+    let _ = writeln!(
+        page,
+        ".SH SYNOPSIS\n\\fB{}\\fR [\\fIOPTIONS\\fR] [\\fICOMMAND\\fR]",
+        name
+    );
+    let _ = writeln!(page, ".SH DESCRIPTION\n{}", escape(&about));
+    page.push_str(".PP\nEvery option can be given before or after the command. Options that can also be set in the configuration file name the key in brackets.\n");
+
+    page.push_str(".SH COMMANDS\n");
+    for subcommand in command.get_subcommands().filter(|subcommand| !subcommand.is_hide_set()) {
+        let _ = writeln!(page, ".TP\n\\fB{}\\fR", subcommand.get_name());
+        if let Some(about) = subcommand.get_about() {
+            let _ = writeln!(page, "{}", escape(&about.to_string()));
+        }
+        for arg in subcommand.get_arguments().filter(|arg| !arg.is_global_set()) {
+            if is_documented(arg) {
+                let _ = writeln!(page, ".RS\n.TP\n{}\n{}\n.RE", arg_synopsis(arg), arg_help(arg));
+            }
+        }
+    }
+
+    page.push_str(".SH OPTIONS\n");
+    for arg in command.get_arguments().filter(|arg| is_documented(arg)) {
+        let _ = writeln!(page, ".TP\n{}\n{}", arg_synopsis(arg), arg_help(arg));
+    }
+
+    let _ = writeln!(
+        page,
+        ".SH FILES\n.TP\n\\fI{}\\fR\nDefault configuration file, written with \\fB{} init\\fR.",
+        DEFAULT_CONFIG_PATH, name
+    );
+    let _ = writeln!(page, ".SH SEE ALSO\n\\fB{} \\-\\-help\\fR, \\fB{} doctor\\fR", name, name);
+    page
+}
+
+/// Whether an option belongs on the page, leaving out help and version flags
+fn is_documented(arg: &Arg) -> bool {
+    !arg.is_hide_set()
+        && !arg.is_positional()
+        && !matches!(arg.get_action(), ArgAction::Help | ArgAction::HelpShort | ArgAction::HelpLong | ArgAction::Version)
+}
+
+/// Flags and value of an option, e.g. `-y, --yes` or `--steps STEPS`
+fn arg_synopsis(arg: &Arg) -> String {
+    let mut flags = Vec::new();
+    if let Some(short) = arg.get_short() {
+        flags.push(format!("\\fB\\-{}\\fR", short));
+    }
+    if let Some(long) = arg.get_long() {
+        flags.push(format!("\\fB\\-\\-{}\\fR", escape(long)));
+    }
+    let mut synopsis = flags.join(", ");
+    if arg.get_action().takes_values() {
+        let value_names = arg
+            .get_value_names()
+            .map(|names| names.iter().map(ToString::to_string).collect::<Vec<_>>().join(" "))
+            .unwrap_or_else(|| arg.get_id().as_str().to_uppercase());
+        let _ = write!(synopsis, " \\fI{}\\fR", escape(&value_names));
+    }
+    synopsis
+}
+
+/// Help of an option with its possible and default values
+fn arg_help(arg: &Arg) -> String {
+    let mut help = arg.get_help().map(ToString::to_string).unwrap_or_default();
+    if !arg.get_action().takes_values() {
+        return escape(&help);
+    }
+    let possible: Vec<String> = arg
+        .get_possible_values()
+        .iter()
+        .filter(|value| !value.is_hide_set())
+        .map(|value| value.get_name().to_string())
+        .collect();
+    if !possible.is_empty() {
+        let _ = write!(help, " [possible values: {}]", possible.join(", "));
+    }
+    let defaults: Vec<String> = arg
+        .get_default_values()
+        .iter()
+        .map(|value| value.to_string_lossy().to_string())
+        .collect();
+    if !defaults.is_empty() {
+        let _ = write!(help, " [default: {}]", defaults.join(", "));
+    }
+    escape(&help)
+}
+
+/// Escape text for roff, so backslashes, dashes and leading dots are printed as is
+pub fn escape(text: &str) -> String {
+    let escaped = text.replace('\\', "\\e").replace('-', "\\-");
+    if escaped.starts_with('.') || escaped.starts_with('\'') {
+        format!("\\&{}", escaped)
+    } else {
+        escaped
+    }
+}
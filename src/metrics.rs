@@ -0,0 +1,198 @@
+#[cfg(feature = "server")]
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+#[cfg(feature = "server")]
+use std::net::SocketAddr;
+/**
+ * Prometheus metrics for ControlNet Image Generator
+ *
+ * This module keeps counters and a generation duration histogram for
+ * long-running processes, and serves them in the Prometheus text exposition
+ * format on a `/metrics` endpoint so existing Grafana dashboards can watch
+ * the pipeline.
+ */
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "server")]
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+#[cfg(feature = "server")]
+use tokio::net::TcpListener;
+#[cfg(feature = "server")]
+use tokio::task::JoinHandle;
+#[cfg(feature = "server")]
+use tracing::{debug, info, warn};
+
+#[cfg(feature = "server")]
+use crate::i18n::{Msg, tr_args};
+use crate::processing::{FailureReason, ImageResult};
+
+/// Upper bounds of the generation duration histogram buckets, in seconds
+pub const DURATION_BUCKETS: [f64; 10] = [1.0, 2.5, 5.0, 10.0, 20.0, 30.0, 60.0, 120.0, 300.0, 600.0];
+
+/// Counters and histograms describing the work done so far
+#[derive(Debug, Default)]
+pub struct Metrics {
+    /// Inputs processed successfully
+    images_processed: AtomicU64,
+    /// Images generated across all inputs
+    images_generated: AtomicU64,
+    /// Retries made across all inputs
+    retries: AtomicU64,
+    /// Failed inputs by failure category
+    failures: Mutex<BTreeMap<FailureReason, u64>>,
+    /// Cumulative counts per histogram bucket, plus the overflow bucket
+    duration_buckets: [AtomicU64; DURATION_BUCKETS.len() + 1],
+    /// Sum of all observed generation durations, in milliseconds
+    duration_sum_ms: AtomicU64,
+}
+
+impl Metrics {
+    /// Create an empty set of metrics, ready to be shared between tasks
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    /// Update the metrics with the outcome of one input
+    pub fn observe(&self, result: &ImageResult) {
+        if result.success {
+            self.images_processed.fetch_add(1, Ordering::Relaxed);
+            self.images_generated
+                .fetch_add(result.generated as u64, Ordering::Relaxed);
+        } else {
+            let reason = result.reason.unwrap_or(FailureReason::Other);
+            let mut failures = self.failures.lock().unwrap_or_else(|e| e.into_inner());
+            *failures.entry(reason).or_default() += 1;
+        }
+        self.retries.fetch_add(
+            result.attempts.saturating_sub(1) as u64,
+            Ordering::Relaxed,
+        );
+
+        let seconds = result.generation_ms as f64 / 1000.0;
+        let bucket = DURATION_BUCKETS
+            .iter()
+            .position(|&bound| seconds <= bound)
+            .unwrap_or(DURATION_BUCKETS.len());
+        self.duration_buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.duration_sum_ms
+            .fetch_add(result.generation_ms, Ordering::Relaxed);
+    }
+
+    /// Render the metrics in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        write_counter(
+            &mut out,
+            "urasoe_images_processed_total",
+            "Input images processed successfully",
+            self.images_processed.load(Ordering::Relaxed),
+        );
+        write_counter(
+            &mut out,
+            "urasoe_images_generated_total",
+            "Images generated across all inputs",
+            self.images_generated.load(Ordering::Relaxed),
+        );
+        write_counter(
+            &mut out,
+            "urasoe_retries_total",
+            "Generation retries made",
+            self.retries.load(Ordering::Relaxed),
+        );
+
+        let _ = writeln!(out, "# HELP urasoe_images_failed_total Input images that failed, by reason");
+        let _ = writeln!(out, "# TYPE urasoe_images_failed_total counter");
+        let failures = self.failures.lock().unwrap_or_else(|e| e.into_inner());
+        for (reason, count) in failures.iter() {
+            let reason = serde_json::to_value(reason)
+                .ok()
+                .and_then(|value| value.as_str().map(String::from))
+                .unwrap_or_default();
+            let _ = writeln!(out, "urasoe_images_failed_total{{reason=\"{}\"}} {}", reason, count);
+        }
+
+        let _ = writeln!(out, "# HELP urasoe_generation_duration_seconds Time spent generating images for one input");
+        let _ = writeln!(out, "# TYPE urasoe_generation_duration_seconds histogram");
+        let mut cumulative = 0;
+        for (index, bound) in DURATION_BUCKETS.iter().enumerate() {
+            cumulative += self.duration_buckets[index].load(Ordering::Relaxed);
+            let _ = writeln!(
+                out,
+                "urasoe_generation_duration_seconds_bucket{{le=\"{}\"}} {}",
+                bound, cumulative
+            );
+        }
+        cumulative += self.duration_buckets[DURATION_BUCKETS.len()].load(Ordering::Relaxed);
+        let _ = writeln!(out, "urasoe_generation_duration_seconds_bucket{{le=\"+Inf\"}} {}", cumulative);
+        let _ = writeln!(
+            out,
+            "urasoe_generation_duration_seconds_sum {}",
+            self.duration_sum_ms.load(Ordering::Relaxed) as f64 / 1000.0
+        );
+        let _ = writeln!(out, "urasoe_generation_duration_seconds_count {}", cumulative);
+
+        out
+    }
+}
+
+/// Write a single counter with its help and type lines
+fn write_counter(out: &mut String, name: &str, help: &str, value: u64) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} counter", name);
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+/// Serve the metrics on `http://<address>/metrics` until the task is dropped
+///
+/// A deliberately small HTTP/1.1 responder: every connection gets one
+/// response and is closed, which is all a Prometheus scraper needs.
+///
+/// # Arguments
+/// * `address` - Address to listen on, e.g. "127.0.0.1:9184"
+/// * `metrics` - Metrics to expose
+///
+/// # Returns
+/// The bound address and the handle of the spawned server task
+#[cfg(feature = "server")]
+pub async fn serve(address: &str, metrics: Arc<Metrics>) -> Result<(SocketAddr, JoinHandle<()>)> {
+    let listener = TcpListener::bind(address)
+        .await
+        .context(format!("Failed to bind metrics endpoint to {}", address))?;
+    let local_addr = listener.local_addr()?;
+    info!("{}", tr_args(Msg::ServingMetrics, &[&local_addr]));
+
+    let handle = tokio::spawn(async move {
+        loop {
+            let (mut stream, peer) = match listener.accept().await {
+                Ok(connection) => connection,
+                Err(e) => {
+                    warn!("{}", tr_args(Msg::AcceptFailed, &[&"metrics", &e]));
+                    continue;
+                }
+            };
+            let metrics = Arc::clone(&metrics);
+            tokio::spawn(async move {
+                let mut buffer = [0u8; 1024];
+                let read = stream.read(&mut buffer).await.unwrap_or(0);
+                let request = String::from_utf8_lossy(&buffer[..read]);
+                debug!("Metrics request from {}: {}", peer, request.lines().next().unwrap_or_default());
+
+                let response = if request.starts_with("GET /metrics") {
+                    let body = metrics.render();
+                    format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    )
+                } else {
+                    "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+                };
+                let _ = stream.write_all(response.as_bytes()).await;
+                let _ = stream.shutdown().await;
+            });
+        }
+    });
+    Ok((local_addr, handle))
+}
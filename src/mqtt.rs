@@ -0,0 +1,425 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+/**
+ * MQTT integration for ControlNet Image Generator
+ *
+ * This module publishes the outcome of every image and the summary of every
+ * run to an MQTT broker, and lets the daemon take jobs from a topic, so
+ * urasoe fits into home automation and IoT pipelines. It contains a small
+ * MQTT 3.1.1 client covering what that needs: connecting, subscribing and
+ * publishing at QoS 0, and keeping the connection alive.
+ */
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::net::tcp::OwnedWriteHalf;
+use tokio::sync::{Mutex, mpsc};
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+use crate::daemon::{self, JobSpec};
+use crate::i18n::{Msg, tr_args};
+use crate::processing::{ImageResult, ProcessingStats};
+use crate::style::*;
+
+/// How long connecting to the broker may take
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Topic the daemon takes jobs from, below the topic prefix
+pub const JOBS_TOPIC: &str = "jobs";
+
+/// Topic accepted jobs are announced on, below the topic prefix
+pub const SUBMITTED_TOPIC: &str = "jobs/submitted";
+
+/// Topic rejected job messages are announced on, below the topic prefix
+pub const REJECTED_TOPIC: &str = "jobs/rejected";
+
+/// Topic the outcome of every image is published on, below the topic prefix
+pub const IMAGE_TOPIC: &str = "image";
+
+/// Topic the summary of every run is published on, below the topic prefix
+pub const RUN_TOPIC: &str = "run";
+
+/// MQTT packet types, as the high nibble of the first byte
+const CONNECT: u8 = 0x10;
+const CONNACK: u8 = 0x20;
+const PUBLISH: u8 = 0x30;
+const SUBSCRIBE: u8 = 0x82;
+const SUBACK: u8 = 0x90;
+const PINGREQ: u8 = 0xC0;
+const DISCONNECT: u8 = 0xE0;
+
+/// MQTT broker settings
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MqttConfig {
+    /// Broker address as host:port, e.g. "localhost:1883", disabled when unset
+    #[serde(default)]
+    pub broker: Option<String>,
+    /// Client id presented to the broker
+    #[serde(default = "default_client_id")]
+    pub client_id: String,
+    /// Prefix of all topics, e.g. "urasoe" for "urasoe/image"
+    #[serde(default = "default_topic_prefix")]
+    pub topic_prefix: String,
+    /// User name, when the broker requires one
+    #[serde(default)]
+    pub username: Option<String>,
+    /// Password, when the broker requires one
+    #[serde(default)]
+    pub password: Option<String>,
+    /// Seconds between keep-alive pings
+    #[serde(default = "default_keep_alive")]
+    pub keep_alive_secs: u16,
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        Self {
+            broker: None,
+            client_id: default_client_id(),
+            topic_prefix: default_topic_prefix(),
+            username: None,
+            password: None,
+            keep_alive_secs: default_keep_alive(),
+        }
+    }
+}
+
+fn default_client_id() -> String {
+    "urasoe".to_string()
+}
+
+fn default_topic_prefix() -> String {
+    "urasoe".to_string()
+}
+
+fn default_keep_alive() -> u16 {
+    60
+}
+
+impl MqttConfig {
+    /// Full name of a topic below the prefix
+    pub fn topic(&self, name: &str) -> String {
+        format!("{}/{}", self.topic_prefix.trim_end_matches('/'), name)
+    }
+}
+
+/// A message received on a subscribed topic
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    /// Topic the message was published on
+    pub topic: String,
+    /// Payload of the message
+    pub payload: Vec<u8>,
+}
+
+/// Append the MQTT variable length encoding of a remaining length
+fn put_length(buf: &mut Vec<u8>, mut length: usize) {
+    loop {
+        let mut byte = (length % 128) as u8;
+        length /= 128;
+        if length > 0 {
+            byte |= 0x80;
+        }
+        buf.push(byte);
+        if length == 0 {
+            break;
+        }
+    }
+}
+
+/// Append a length-prefixed UTF-8 string
+pub fn put_string(buf: &mut Vec<u8>, value: &str) {
+    buf.extend_from_slice(&(value.len() as u16).to_be_bytes());
+    buf.extend_from_slice(value.as_bytes());
+}
+
+/// Build a packet from its first byte and the rest of its contents
+pub fn packet(first_byte: u8, body: &[u8]) -> Vec<u8> {
+    let mut buf = vec![first_byte];
+    put_length(&mut buf, body.len());
+    buf.extend_from_slice(body);
+    buf
+}
+
+/// Read one packet, returning its first byte and the rest of its contents
+pub async fn read_packet<R: AsyncRead + Unpin>(reader: &mut R) -> Result<(u8, Vec<u8>)> {
+    let first_byte = reader.read_u8().await?;
+    let mut length = 0usize;
+    for shift in 0..4 {
+        let byte = reader.read_u8().await?;
+        length |= usize::from(byte & 0x7F) << (7 * shift);
+        if byte & 0x80 == 0 {
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).await?;
+            return Ok((first_byte, body));
+        }
+    }
+    Err(anyhow::anyhow!("Malformed MQTT packet length"))
+}
+
+/// Read a length-prefixed string at the start of a packet body
+fn read_string(body: &[u8]) -> Result<(String, &[u8])> {
+    let length = u16::from_be_bytes([
+        *body.first().context("Truncated MQTT string")?,
+        *body.get(1).context("Truncated MQTT string")?,
+    ]) as usize;
+    let value = body.get(2..2 + length).context("Truncated MQTT string")?;
+    Ok((String::from_utf8(value.to_vec())?, &body[2 + length..]))
+}
+
+/// Parse the body of a PUBLISH packet
+pub fn parse_publish(first_byte: u8, body: &[u8]) -> Result<Message> {
+    let (topic, rest) = read_string(body)?;
+    // Messages above QoS 0 carry a packet id before the payload
+    let qos = (first_byte >> 1) & 3;
+    let payload = if qos > 0 {
+        rest.get(2..).context("Truncated MQTT packet id")?
+    } else {
+        rest
+    };
+    Ok(Message {
+        topic,
+        payload: payload.to_vec(),
+    })
+}
+
+/// A connection to an MQTT broker
+///
+/// Messages of subscribed topics arrive on the receiver returned by
+/// `connect`. The connection is closed when the client is dropped.
+pub struct MqttClient {
+    writer: Arc<Mutex<OwnedWriteHalf>>,
+    tasks: Vec<JoinHandle<()>>,
+    next_packet_id: AtomicU16,
+    topic_prefix: String,
+}
+
+impl MqttClient {
+    /// Connect to the configured broker
+    ///
+    /// # Arguments
+    /// * `config` - Broker settings, `broker` has to be set
+    /// * `client_id` - Client id, unique among the connections to the broker
+    ///
+    /// # Returns
+    /// The client and the receiver of messages on subscribed topics
+    pub async fn connect(config: &MqttConfig, client_id: &str) -> Result<(Self, mpsc::UnboundedReceiver<Message>)> {
+        let broker = config.broker.as_deref().context("No MQTT broker configured")?;
+        let broker = broker.strip_prefix("mqtt://").unwrap_or(broker);
+        let mut stream = tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect(broker))
+            .await
+            .context(format!("Timed out connecting to MQTT broker {}", broker))?
+            .context(format!("Failed to connect to MQTT broker {}", broker))?;
+
+        let mut flags = 0x02; // Clean session
+        let mut body = Vec::new();
+        put_string(&mut body, "MQTT");
+        body.push(4); // Protocol level 3.1.1
+        if config.username.is_some() {
+            flags |= 0x80;
+        }
+        if config.password.is_some() {
+            flags |= 0x40;
+        }
+        body.push(flags);
+        body.extend_from_slice(&config.keep_alive_secs.to_be_bytes());
+        put_string(&mut body, client_id);
+        if let Some(username) = &config.username {
+            put_string(&mut body, username);
+        }
+        if let Some(password) = &config.password {
+            put_string(&mut body, password);
+        }
+        stream.write_all(&packet(CONNECT, &body)).await?;
+
+        let (first_byte, body) = tokio::time::timeout(CONNECT_TIMEOUT, read_packet(&mut stream))
+            .await
+            .context("Timed out waiting for the MQTT broker to accept the connection")??;
+        if first_byte != CONNACK || body.len() < 2 {
+            return Err(anyhow::anyhow!("MQTT broker answered with an unexpected packet"));
+        }
+        if body[1] != 0 {
+            let reason = match body[1] {
+                1 => "unacceptable protocol version",
+                2 => "client id rejected",
+                3 => "server unavailable",
+                4 => "bad user name or password",
+                5 => "not authorized",
+                _ => "unknown reason",
+            };
+            return Err(anyhow::anyhow!("MQTT broker refused the connection: {}", reason));
+        }
+        debug!("Connected to MQTT broker {} as {}", broker, client_id);
+
+        let (mut reader, writer) = stream.into_split();
+        let writer = Arc::new(Mutex::new(writer));
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let read_task = tokio::spawn(async move {
+            loop {
+                match read_packet(&mut reader).await {
+                    Ok((first_byte, body)) if first_byte & 0xF0 == PUBLISH => match parse_publish(first_byte, &body) {
+                        Ok(message) => {
+                            if sender.send(message).is_err() {
+                                break;
+                            }
+                        }
+                        Err(e) => debug!("Ignoring malformed MQTT message: {:#}", e),
+                    },
+                    Ok((first_byte, _)) if first_byte == SUBACK => debug!("MQTT subscription acknowledged"),
+                    Ok(_) => {}
+                    Err(e) => {
+                        debug!("MQTT connection closed: {:#}", e);
+                        break;
+                    }
+                }
+            }
+        });
+        let ping_writer = Arc::clone(&writer);
+        let interval = Duration::from_secs(u64::from(config.keep_alive_secs.max(2)) / 2);
+        let ping_task = tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                if ping_writer.lock().await.write_all(&[PINGREQ, 0]).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok((
+            Self {
+                writer,
+                tasks: vec![read_task, ping_task],
+                next_packet_id: AtomicU16::new(1),
+                topic_prefix: config.topic_prefix.clone(),
+            },
+            receiver,
+        ))
+    }
+
+    /// Subscribe to a topic at QoS 0
+    pub async fn subscribe(&self, topic: &str) -> Result<()> {
+        let packet_id = self.next_packet_id.fetch_add(1, Ordering::Relaxed).max(1);
+        let mut body = packet_id.to_be_bytes().to_vec();
+        put_string(&mut body, topic);
+        body.push(0);
+        self.writer
+            .lock()
+            .await
+            .write_all(&packet(SUBSCRIBE, &body))
+            .await
+            .context(format!("Failed to subscribe to {}", topic))
+    }
+
+    /// Publish a message at QoS 0
+    pub async fn publish(&self, topic: &str, payload: &[u8]) -> Result<()> {
+        let mut body = Vec::new();
+        put_string(&mut body, topic);
+        body.extend_from_slice(payload);
+        self.writer
+            .lock()
+            .await
+            .write_all(&packet(PUBLISH, &body))
+            .await
+            .context(format!("Failed to publish to {}", topic))
+    }
+
+    /// Publish JSON on a topic below the prefix, logging failures instead of returning them
+    pub async fn publish_json(&self, name: &str, value: &serde_json::Value) {
+        let topic = format!("{}/{}", self.topic_prefix.trim_end_matches('/'), name);
+        if let Err(e) = self.publish(&topic, value.to_string().as_bytes()).await {
+            warn!("{}", tr_args(Msg::MqttPublishFailed, &[&format!("{:#}", e)]).yellow());
+        }
+    }
+
+    /// Publish the outcome of one image on `<prefix>/image`
+    pub async fn publish_image(&self, result: &ImageResult) {
+        match serde_json::to_value(result) {
+            Ok(value) => self.publish_json(IMAGE_TOPIC, &value).await,
+            Err(e) => warn!("{}", tr_args(Msg::MqttPublishFailed, &[&format!("{:#}", e)]).yellow()),
+        }
+    }
+
+    /// Publish the summary of a finished run on `<prefix>/run`
+    pub async fn publish_run_summary(&self, stats: &ProcessingStats, total_images: usize) {
+        match stats.to_json_value() {
+            Ok(mut value) => {
+                value["total_images"] = json!(total_images);
+                self.publish_json(RUN_TOPIC, &value).await
+            }
+            Err(e) => warn!("{}", tr_args(Msg::MqttPublishFailed, &[&format!("{:#}", e)]).yellow()),
+        }
+    }
+
+    /// Say goodbye to the broker and close the connection
+    pub async fn disconnect(self) {
+        let _ = self.writer.lock().await.write_all(&[DISCONNECT, 0]).await;
+    }
+}
+
+impl Drop for MqttClient {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+/// Connect for publishing when a broker is configured
+///
+/// A broker that cannot be reached is logged and does not fail the run.
+pub async fn connect_publisher(config: &MqttConfig) -> Option<MqttClient> {
+    config.broker.as_ref()?;
+    match MqttClient::connect(config, &config.client_id).await {
+        Ok((client, _)) => Some(client),
+        Err(e) => {
+            warn!("{}", tr_args(Msg::MqttDisabled, &[&format!("{:#}", e)]).yellow());
+            None
+        }
+    }
+}
+
+/// Take jobs from `<prefix>/jobs` and add them to the spool directory of the daemon
+///
+/// Each message is a job description in YAML or JSON, as in a job file. The
+/// id of an accepted job is announced on `<prefix>/jobs/submitted`, messages
+/// that are not valid jobs on `<prefix>/jobs/rejected`.
+///
+/// # Arguments
+/// * `config` - Broker settings
+/// * `spool_dir` - Spool directory of the daemon
+///
+/// # Returns
+/// The handle of the spawned intake task
+pub async fn serve_job_intake(config: &MqttConfig, spool_dir: &Path) -> Result<JoinHandle<()>> {
+    // Runs publish with the configured id, which the broker allows only once
+    let client_id = format!("{}-intake", config.client_id);
+    let (client, mut messages) = MqttClient::connect(config, &client_id).await?;
+    let topic = config.topic(JOBS_TOPIC);
+    client.subscribe(&topic).await?;
+    info!("{}", tr_args(Msg::MqttTakingJobs, &[&topic]).blue());
+
+    let spool_dir: PathBuf = spool_dir.to_path_buf();
+    Ok(tokio::spawn(async move {
+        while let Some(message) = messages.recv().await {
+            let submitted = serde_yaml::from_slice::<JobSpec>(&message.payload)
+                .context("Invalid job message")
+                .and_then(|spec| daemon::submit_job(&spool_dir, &spec).map(|id| (id, spec)));
+            match submitted {
+                Ok((id, spec)) => {
+                    let value = json!({ "id": id, "input_dir": spec.input_dir, "priority": spec.priority });
+                    client.publish_json(SUBMITTED_TOPIC, &value).await;
+                }
+                Err(e) => {
+                    warn!("{}", tr_args(Msg::MqttJobRejected, &[&format!("{:#}", e)]).yellow());
+                    let value = json!({ "error": format!("{:#}", e), "payload": String::from_utf8_lossy(&message.payload) });
+                    client.publish_json(REJECTED_TOPIC, &value).await;
+                }
+            }
+        }
+    }))
+}
@@ -0,0 +1,214 @@
+#[cfg(feature = "notifications")]
+use anyhow::{Context, Result};
+#[cfg(feature = "notifications")]
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "notifications")]
+use serde_json::json;
+#[cfg(feature = "notifications")]
+use tracing::{info, warn};
+/**
+ * Run completion notifications for ControlNet Image Generator
+ *
+ * This module posts a short summary of a finished run to chat webhooks
+ * and the local desktop, so long batches can be left unattended while the
+ * team still learns how they went.
+ */
+#[cfg(feature = "notifications")]
+use std::time::Duration;
+#[cfg(feature = "notifications")]
+use tokio::process::Command;
+
+use crate::i18n::{Msg, tr, tr_args};
+use crate::processing::ProcessingStats;
+
+/// Number of failure categories listed in a notification
+const MAX_LISTED_FAILURE_REASONS: usize = 3;
+
+/// Notification settings
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct NotificationConfig {
+    /// Slack incoming webhook URL
+    #[serde(default)]
+    pub slack_webhook: Option<String>,
+    /// Discord webhook URL
+    #[serde(default)]
+    pub discord_webhook: Option<String>,
+    /// Show a native desktop notification
+    #[serde(default)]
+    pub desktop: bool,
+}
+
+impl NotificationConfig {
+    /// Whether any notifier is configured
+    pub fn is_enabled(&self) -> bool {
+        self.slack_webhook.is_some() || self.discord_webhook.is_some() || self.desktop
+    }
+}
+
+/// Format a plain text summary of a finished run
+///
+/// # Arguments
+/// * `stats` - Statistics of the finished run
+/// * `total_images` - Number of inputs in the run
+/// * `report` - Path of the statistics report or output directory to point readers at
+///
+/// # Returns
+/// A multi-line message suitable for chat services
+pub fn format_summary(stats: &ProcessingStats, total_images: usize, report: &str) -> String {
+    let mut lines = vec![
+        tr(Msg::SummaryTitle).to_string(),
+        tr_args(
+            Msg::SummaryProcessed,
+            &[
+                &stats.success_count,
+                &total_images,
+                &stats.generated_count,
+                &stats.failed_paths.len(),
+            ],
+        ),
+        tr_args(
+            Msg::SummaryDuration,
+            &[&format!("{:.1}", stats.elapsed_ms as f64 / 60_000.0)],
+        ),
+    ];
+
+    let failures = stats.failures_by_reason();
+    if !failures.is_empty() {
+        let mut groups: Vec<_> = failures.iter().collect();
+        groups.sort_by_key(|(_, paths)| std::cmp::Reverse(paths.len()));
+        let reasons: Vec<String> = groups
+            .iter()
+            .take(MAX_LISTED_FAILURE_REASONS)
+            .map(|(reason, paths)| format!("{} ({})", reason, paths.len()))
+            .collect();
+        lines.push(tr_args(Msg::SummaryFailureReasons, &[&reasons.join(", ")]));
+    }
+
+    lines.push(tr_args(Msg::SummaryReport, &[&report]));
+    lines.join("\n")
+}
+
+/// Post the run summary to all configured webhooks
+///
+/// Failures to deliver are logged as warnings and do not fail the run.
+///
+/// # Arguments
+/// * `config` - Notification settings
+/// * `stats` - Statistics of the finished run
+/// * `total_images` - Number of inputs in the run
+/// * `report` - Path of the statistics report or output directory
+#[cfg(feature = "notifications")]
+pub async fn send_run_summary(
+    config: &NotificationConfig,
+    stats: &ProcessingStats,
+    total_images: usize,
+    report: &str,
+) {
+    if !config.is_enabled() {
+        return;
+    }
+
+    if config.desktop {
+        let body = tr_args(
+            Msg::DesktopSummary,
+            &[&stats.success_count, &stats.failed_paths.len(), &stats.generated_count],
+        );
+        if let Err(e) = send_desktop_notification(tr(Msg::SummaryTitle), &body).await {
+            warn!("{}", tr_args(Msg::DesktopNotificationFailed, &[&format!("{:#}", e)]));
+        }
+    }
+    if config.slack_webhook.is_none() && config.discord_webhook.is_none() {
+        return;
+    }
+
+    let client = Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .unwrap_or_else(|_| Client::new());
+    let summary = format_summary(stats, total_images, report);
+
+    if let Some(url) = &config.slack_webhook {
+        match post_webhook(&client, url, &json!({ "text": summary })).await {
+            Ok(()) => info!("{}", tr_args(Msg::SummarySent, &[&"Slack"])),
+            Err(e) => warn!("{}", tr_args(Msg::NotifyFailed, &[&"Slack", &format!("{:#}", e)])),
+        }
+    }
+    if let Some(url) = &config.discord_webhook {
+        match post_webhook(&client, url, &json!({ "content": summary })).await {
+            Ok(()) => info!("{}", tr_args(Msg::SummarySent, &[&"Discord"])),
+            Err(e) => warn!("{}", tr_args(Msg::NotifyFailed, &[&"Discord", &format!("{:#}", e)])),
+        }
+    }
+}
+
+/// Post a JSON payload to a webhook, failing on non-success status codes
+#[cfg(feature = "notifications")]
+async fn post_webhook(client: &Client, url: &str, payload: &serde_json::Value) -> Result<()> {
+    let response = client
+        .post(url)
+        .json(payload)
+        .send()
+        .await
+        .context("Failed to send webhook request")?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let text = response.text().await.unwrap_or_default();
+        return Err(anyhow::anyhow!("Webhook responded with {} {}", status, text));
+    }
+    Ok(())
+}
+
+/// Show a native desktop notification
+///
+/// Uses `notify-send` on Linux and other Unix systems, `osascript` on macOS
+/// and a PowerShell balloon tip on Windows.
+///
+/// # Arguments
+/// * `title` - Title of the notification
+/// * `body` - Text of the notification
+#[cfg(feature = "notifications")]
+pub async fn send_desktop_notification(title: &str, body: &str) -> Result<()> {
+    let status = desktop_notification_command(title, body)
+        .status()
+        .await
+        .context("Failed to run the desktop notification command")?;
+
+    if !status.success() {
+        return Err(anyhow::anyhow!("Desktop notification command exited with {}", status));
+    }
+    Ok(())
+}
+
+/// Build the platform specific command showing a desktop notification
+#[cfg(feature = "notifications")]
+fn desktop_notification_command(title: &str, body: &str) -> Command {
+    if cfg!(target_os = "macos") {
+        let mut command = Command::new("osascript");
+        command.arg("-e").arg(format!(
+            "display notification \"{}\" with title \"{}\"",
+            body.replace('"', "'"),
+            title.replace('"', "'")
+        ));
+        command
+    } else if cfg!(target_os = "windows") {
+        let script = format!(
+            "Add-Type -AssemblyName System.Windows.Forms; \
+             $n = New-Object System.Windows.Forms.NotifyIcon; \
+             $n.Icon = [System.Drawing.SystemIcons]::Information; \
+             $n.Visible = $true; \
+             $n.ShowBalloonTip(10000, '{}', '{}', 'Info'); \
+             Start-Sleep -Seconds 5",
+            title.replace('\'', "''"),
+            body.replace('\'', "''")
+        );
+        let mut command = Command::new("powershell");
+        command.args(["-NoProfile", "-Command", &script]);
+        command
+    } else {
+        let mut command = Command::new("notify-send");
+        command.args(["--app-name=urasoe", title, body]);
+        command
+    }
+}
@@ -0,0 +1,153 @@
+use anyhow::Result;
+use futures::Stream;
+/**
+ * Embeddable pipeline for ControlNet Image Generator
+ *
+ * This module lets other programs, such as GUI frontends, run the whole
+ * folder workflow and render their own progress. A `Pipeline` processes the
+ * input directory of its configuration on a background task and reports what
+ * happens as typed `RunEvent`s, the same moments the JSON log lines name
+ * `image_started`, `attempt_failed`, `image_saved` and `run_finished`.
+ */
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::task::JoinHandle;
+
+use crate::config::Config;
+use crate::control::RunControl;
+use crate::metrics::Metrics;
+use crate::processing::{ImageResult, ProcessingStats};
+use crate::runner;
+use crate::sink::{FileSystemSink, OutputSink};
+
+/// Something that happened during a run
+#[derive(Debug, Clone)]
+pub enum RunEvent {
+    /// An input was taken from the queue
+    ImageStarted {
+        /// Path of the input image
+        input: PathBuf,
+        /// Backend generating it
+        backend: String,
+    },
+    /// A generation request failed
+    AttemptFailed {
+        /// Path of the input image
+        input: PathBuf,
+        /// Number of the failed attempt, starting from 1
+        attempt: u32,
+        /// Whether another attempt follows
+        will_retry: bool,
+        /// The error with its causes
+        error: String,
+    },
+    /// A generated image was stored in the output sink
+    ImageSaved {
+        /// Path of the input image
+        input: PathBuf,
+        /// Where the sink stored the image, a path in the output directory by default
+        output: PathBuf,
+    },
+    /// An input was finished, successfully or not
+    ImageFinished(ImageResult),
+    /// The run ended, with its statistics unless there was nothing to process or it failed
+    RunFinished(Option<ProcessingStats>),
+}
+
+/// Subscribers to the events of a run, shared by everything taking part in it
+#[derive(Debug, Clone, Default)]
+pub struct RunEvents {
+    subscribers: Arc<Mutex<Vec<UnboundedSender<RunEvent>>>>,
+}
+
+impl RunEvents {
+    /// Receive every event emitted from now on
+    pub fn subscribe(&self) -> UnboundedReceiver<RunEvent> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        self.lock().push(sender);
+        receiver
+    }
+
+    /// Send an event to every subscriber, forgetting those that stopped listening
+    pub fn emit(&self, event: RunEvent) {
+        self.lock()
+            .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+
+    /// Whether anyone listens, so events need not be built otherwise
+    pub fn has_subscribers(&self) -> bool {
+        !self.lock().is_empty()
+    }
+
+    /// Drop every subscription, ending the streams of the subscribers
+    pub fn close(&self) {
+        self.lock().clear();
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<UnboundedSender<RunEvent>>> {
+        self.subscribers.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// The folder workflow of one configuration, run in the background
+pub struct Pipeline {
+    config: Config,
+    control: Arc<RunControl>,
+    metrics: Arc<Metrics>,
+    sink: Arc<dyn OutputSink>,
+}
+
+impl Pipeline {
+    /// Pipeline processing the input directory of the given configuration
+    pub fn new(config: Config) -> Self {
+        Self {
+            sink: Arc::new(FileSystemSink::from_config(&config)),
+            config,
+            control: RunControl::new(),
+            metrics: Metrics::new(),
+        }
+    }
+
+    /// Store generated images in the given sink instead of the output directory
+    pub fn with_sink(mut self, sink: Arc<dyn OutputSink>) -> Self {
+        self.sink = sink;
+        self
+    }
+
+    /// Control of the run, to pause, skip or abort it and read its progress
+    pub fn control(&self) -> Arc<RunControl> {
+        Arc::clone(&self.control)
+    }
+
+    /// Metrics of the run, e.g. to serve them next to the frontend
+    pub fn metrics(&self) -> Arc<Metrics> {
+        Arc::clone(&self.metrics)
+    }
+
+    /// Start the run on a background task
+    ///
+    /// Must be called within a Tokio runtime.
+    ///
+    /// # Returns
+    /// * `UnboundedReceiver<RunEvent>` - Events of the run, closed after `RunFinished`
+    /// * `JoinHandle` - Result of the run, the statistics or the error that stopped it
+    pub fn start(self) -> (UnboundedReceiver<RunEvent>, JoinHandle<Result<Option<ProcessingStats>>>) {
+        let events = self.control.events().subscribe();
+        let handle = tokio::spawn(async move {
+            let result =
+                runner::run_batch_with_sink(&self.config, &self.metrics, &self.control, self.sink.as_ref()).await;
+            self.control.end();
+            let stats = result.as_ref().ok().cloned().flatten();
+            self.control.events().emit(RunEvent::RunFinished(stats));
+            self.control.events().close();
+            result
+        });
+        (events, handle)
+    }
+}
+
+/// Events of a run as a `Stream`, for frontends built on stream combinators
+pub fn event_stream(mut events: UnboundedReceiver<RunEvent>) -> impl Stream<Item = RunEvent> {
+    futures::stream::poll_fn(move |context| events.poll_recv(context))
+}
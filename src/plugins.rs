@@ -0,0 +1,210 @@
+use anyhow::{Context, Result};
+/**
+ * Plugins for ControlNet Image Generator
+ *
+ * This module lets Rhai scripts listed under `plugins` in the configuration
+ * customize stages of the pipeline without forking the crate. A script
+ * takes part in a stage by defining its function:
+ *
+ * - `filter_input(image)` returns `false` to skip an input
+ * - `mutate_payload(image, payload)` returns the request to send to the API instead
+ * - `post_process(image, outputs)` returns the paths of the outputs of the input instead
+ *
+ * Returning nothing leaves things unchanged. Scripts run one after another
+ * in the order they are listed, and a script error fails the image it was
+ * called for. Besides the standard Rhai functions, scripts can check, copy,
+ * rename, remove and resize files.
+ */
+use rhai::{AST, Array, Dynamic, Engine, EvalAltResult, Scope};
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info};
+
+use crate::file_utils::SavedImages;
+use crate::i18n::{Msg, tr_args};
+use crate::style::*;
+
+/// Pipeline stage a plugin can take part in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PluginStage {
+    /// Decide whether an input image is processed at all
+    InputFilter,
+    /// Change the request sent to the Stable Diffusion API
+    PayloadMutator,
+    /// Act on the saved output images
+    OutputPostprocessor,
+}
+
+impl PluginStage {
+    /// Name of the script function implementing the stage
+    pub fn function(&self) -> &'static str {
+        match self {
+            PluginStage::InputFilter => "filter_input",
+            PluginStage::PayloadMutator => "mutate_payload",
+            PluginStage::OutputPostprocessor => "post_process",
+        }
+    }
+
+    /// Number of parameters of the script function
+    fn arity(&self) -> usize {
+        match self {
+            PluginStage::InputFilter => 1,
+            PluginStage::PayloadMutator | PluginStage::OutputPostprocessor => 2,
+        }
+    }
+}
+
+/// Compiled plugin scripts with the engine running them
+pub struct Plugins {
+    engine: Engine,
+    scripts: Vec<(String, AST)>,
+}
+
+impl Plugins {
+    /// Compile the scripts of the configuration
+    ///
+    /// # Arguments
+    /// * `paths` - Rhai script files, in the order they are called
+    ///
+    /// # Returns
+    /// The plugins, or an error naming the first script that does not compile
+    pub fn load(paths: &[String]) -> Result<Self> {
+        let engine = engine();
+        let mut scripts = Vec::with_capacity(paths.len());
+        for path in paths {
+            let ast = engine
+                .compile_file(PathBuf::from(path))
+                .map_err(|e| anyhow::anyhow!("{}", e))
+                .context(format!("Failed to load plugin {}", path))?;
+            scripts.push((path.clone(), ast));
+        }
+        Ok(Self { engine, scripts })
+    }
+
+    /// Whether any script takes part in a stage
+    pub fn handles(&self, stage: PluginStage) -> bool {
+        self.scripts_for(stage).next().is_some()
+    }
+
+    /// Scripts defining the function of a stage, in configuration order
+    fn scripts_for(&self, stage: PluginStage) -> impl Iterator<Item = &(String, AST)> {
+        self.scripts.iter().filter(move |(_, ast)| {
+            ast.iter_functions()
+                .any(|function| function.name == stage.function() && function.params.len() == stage.arity())
+        })
+    }
+
+    /// Call the function of a stage in a script
+    fn call(&self, stage: PluginStage, (path, ast): &(String, AST), args: impl rhai::FuncArgs) -> Result<Dynamic> {
+        debug!("{} {} ({})", "Calling plugin".blue(), path, stage.function());
+        self.engine
+            .call_fn::<Dynamic>(&mut Scope::new(), ast, stage.function(), args)
+            .map_err(|e| anyhow::anyhow!("{}", e))
+            .context(format!("Plugin {} failed in {}", path, stage.function()))
+    }
+
+    /// Drop the inputs that a `filter_input` function returns `false` for
+    ///
+    /// # Arguments
+    /// * `image_paths` - Candidate input images
+    ///
+    /// # Returns
+    /// The inputs every filter kept, in their original order
+    pub fn filter_inputs(&self, image_paths: Vec<PathBuf>) -> Result<Vec<PathBuf>> {
+        if !self.handles(PluginStage::InputFilter) {
+            return Ok(image_paths);
+        }
+
+        let mut kept = Vec::with_capacity(image_paths.len());
+        'images: for image_path in image_paths {
+            for script in self.scripts_for(PluginStage::InputFilter) {
+                let image = image_path.to_string_lossy().into_owned();
+                let keep = self.call(PluginStage::InputFilter, script, (image,))?;
+                if keep.as_bool() == Ok(false) {
+                    info!("{}", tr_args(Msg::SkippedByPlugin, &[&image_path.display(), &script.0]).yellow());
+                    continue 'images;
+                }
+            }
+            kept.push(image_path);
+        }
+        Ok(kept)
+    }
+
+    /// Let `mutate_payload` functions change an API request, one after another
+    ///
+    /// # Arguments
+    /// * `image_path` - Input image the request is for
+    /// * `payload` - Request as built from the configuration
+    ///
+    /// # Returns
+    /// The request to send
+    pub fn mutate_payload(&self, image_path: &Path, mut payload: Value) -> Result<Value> {
+        for script in self.scripts_for(PluginStage::PayloadMutator) {
+            let image = image_path.to_string_lossy().into_owned();
+            let request = rhai::serde::to_dynamic(&payload).map_err(|e| anyhow::anyhow!("{}", e))?;
+            let answer = self.call(PluginStage::PayloadMutator, script, (image, request))?;
+            if !answer.is_unit() {
+                payload = rhai::serde::from_dynamic(&answer)
+                    .map_err(|e| anyhow::anyhow!("{}", e))
+                    .context(format!("Plugin {} returned an invalid payload", script.0))?;
+            }
+        }
+        Ok(payload)
+    }
+
+    /// Hand the saved output images to `post_process` functions, one after another
+    ///
+    /// A function may change, move, add or remove output files, and returns
+    /// the paths of the outputs of the input as they are afterwards.
+    ///
+    /// # Arguments
+    /// * `image_path` - Input image the outputs were generated from
+    /// * `saved` - What was saved for the input
+    ///
+    /// # Returns
+    /// What was saved, with the output paths the functions returned
+    pub fn post_process(&self, image_path: &Path, mut saved: SavedImages) -> Result<SavedImages> {
+        for script in self.scripts_for(PluginStage::OutputPostprocessor) {
+            let image = image_path.to_string_lossy().into_owned();
+            let outputs: Array = saved
+                .paths
+                .iter()
+                .map(|path| Dynamic::from(path.to_string_lossy().into_owned()))
+                .collect();
+            let answer = self.call(PluginStage::OutputPostprocessor, script, (image, outputs))?;
+            if !answer.is_unit() {
+                let outputs: Vec<String> = rhai::serde::from_dynamic(&answer)
+                    .map_err(|e| anyhow::anyhow!("{}", e))
+                    .context(format!("Plugin {} returned invalid outputs", script.0))?;
+                saved.paths = outputs.into_iter().map(PathBuf::from).collect();
+            }
+        }
+        Ok(saved)
+    }
+}
+
+/// Rhai engine with the file functions available to plugins
+fn engine() -> Engine {
+    let mut engine = Engine::new();
+    engine.register_fn("file_exists", |path: &str| Path::new(path).is_file());
+    engine.register_fn("copy_file", |from: &str, to: &str| -> Result<(), Box<EvalAltResult>> {
+        fs::copy(from, to).map(|_| ()).map_err(|e| format!("Failed to copy {}: {}", from, e).into())
+    });
+    engine.register_fn("rename_file", |from: &str, to: &str| -> Result<(), Box<EvalAltResult>> {
+        fs::rename(from, to).map_err(|e| format!("Failed to rename {}: {}", from, e).into())
+    });
+    engine.register_fn("remove_file", |path: &str| -> Result<(), Box<EvalAltResult>> {
+        fs::remove_file(path).map_err(|e| format!("Failed to remove {}: {}", path, e).into())
+    });
+    engine.register_fn("resize_image", |path: &str, width: i64, height: i64| -> Result<(), Box<EvalAltResult>> {
+        let (Ok(width), Ok(height)) = (u32::try_from(width), u32::try_from(height)) else {
+            return Err(format!("Invalid size {}x{}", width, height).into());
+        };
+        image::open(path)
+            .map(|image| image.resize_exact(width, height, image::imageops::FilterType::Lanczos3))
+            .and_then(|image| image.save(path))
+            .map_err(|e| format!("Failed to resize {}: {}", path, e).into())
+    });
+    engine
+}
@@ -0,0 +1,185 @@
+use anyhow::{Context, Result};
+use base64::{Engine, prelude::BASE64_STANDARD};
+use serde::{Deserialize, Serialize};
+/**
+ * Live previews for ControlNet Image Generator
+ *
+ * This module polls the progress of the server while an input is generated
+ * and saves the live preview it offers every few sampling steps into a
+ * `previews/` folder of the output directory. A prompt that goes wrong shows
+ * in the first steps, without waiting for the full render.
+ */
+use std::fs;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::debug;
+
+use crate::api_types::ProgressResponse;
+
+use crate::api::StableDiffusionClient;
+use crate::style::*;
+
+/// Folder of output_dir the previews are saved in
+pub const PREVIEWS_DIR: &str = "previews";
+
+/// Saving live previews while generating
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PreviewConfig {
+    /// Save a preview every this many sampling steps, 0 saves none
+    #[serde(default)]
+    pub every_steps: u32,
+    /// How often the progress is polled, in milliseconds
+    #[serde(default = "default_poll_interval")]
+    pub poll_interval_ms: u64,
+}
+
+impl Default for PreviewConfig {
+    fn default() -> Self {
+        Self {
+            every_steps: 0,
+            poll_interval_ms: default_poll_interval(),
+        }
+    }
+}
+
+/// Default progress polling interval - 1000 ms
+pub fn default_poll_interval() -> u64 {
+    1000
+}
+
+impl PreviewConfig {
+    /// Whether previews are saved
+    pub fn is_enabled(&self) -> bool {
+        self.every_steps > 0
+    }
+
+    /// Run a generation, saving the previews of the server until it finishes
+    ///
+    /// The progress is polled alongside the generation, and polling stops as
+    /// soon as the generation is done. Failing to poll or save is only logged.
+    ///
+    /// # Arguments
+    /// * `client` - Client of the server generating the input
+    /// * `output_dir` - Output directory, the previews going to its `previews/` folder
+    /// * `image_path` - Input being generated, naming the previews
+    /// * `generation` - Generation of the input
+    ///
+    /// # Returns
+    /// The result of the generation
+    pub async fn while_generating<F: Future>(
+        &self,
+        client: &StableDiffusionClient,
+        output_dir: &Path,
+        image_path: &Path,
+        generation: F,
+    ) -> F::Output {
+        let Some(mut saver) = self.saver(output_dir, image_path) else {
+            return generation.await;
+        };
+        client
+            .poll_progress(self.poll_interval(), generation, |progress| saver.record(progress))
+            .await
+    }
+
+    /// Time between progress requests
+    pub fn poll_interval(&self) -> Duration {
+        Duration::from_millis(self.poll_interval_ms.max(1))
+    }
+
+    /// Saver of the previews of an input, `None` when previews are off
+    ///
+    /// # Arguments
+    /// * `output_dir` - Output directory, the previews going to its `previews/` folder
+    /// * `image_path` - Input being generated, naming the previews
+    pub fn saver(&self, output_dir: &Path, image_path: &Path) -> Option<PreviewSaver> {
+        if !self.is_enabled() {
+            return None;
+        }
+        let stem = image_path.file_stem().unwrap_or_default().to_string_lossy();
+        Some(PreviewSaver::new(output_dir.join(PREVIEWS_DIR), &stem, self.every_steps))
+    }
+}
+
+/// Saves the previews of one input
+#[derive(Debug)]
+pub struct PreviewSaver {
+    dir: PathBuf,
+    stem: String,
+    every_steps: u32,
+    next_step: u32,
+    last_step: u32,
+}
+
+impl PreviewSaver {
+    /// Saver of the previews of an input
+    ///
+    /// # Arguments
+    /// * `dir` - Folder the previews are saved in
+    /// * `stem` - File stem of the input, the previews being `<stem>-step-<n>.<format>`
+    /// * `every_steps` - Sampling steps between saved previews
+    pub fn new(dir: PathBuf, stem: &str, every_steps: u32) -> Self {
+        let every_steps = every_steps.max(1);
+        Self {
+            dir,
+            stem: stem.to_string(),
+            every_steps,
+            next_step: every_steps,
+            last_step: 0,
+        }
+    }
+
+    /// Fetch the progress of the server and save its preview if one is due
+    ///
+    /// # Returns
+    /// Path of the saved preview, `None` when none was due or the server had none
+    pub async fn poll(&mut self, client: &StableDiffusionClient) -> Result<Option<PathBuf>> {
+        let progress = client.get_progress().await?;
+        self.save(progress.state.sampling_step, progress.current_image.as_deref())
+    }
+
+    /// Save the preview of a fetched progress if one is due, only logging a failure
+    pub fn record(&mut self, progress: &ProgressResponse) {
+        if let Err(e) = self.save(progress.state.sampling_step, progress.current_image.as_deref()) {
+            debug!("{} {:#}", "Failed to save preview:".yellow(), e);
+        }
+    }
+
+    /// Save a preview taken at a sampling step if one is due
+    ///
+    /// A step lower than the one before starts a new image, e.g. the next
+    /// request of a split batch or a retry, and the previews start over.
+    ///
+    /// # Arguments
+    /// * `step` - Sampling step the preview was taken at
+    /// * `image` - Base64-encoded preview, as reported by the server
+    ///
+    /// # Returns
+    /// Path of the saved preview, `None` when none was due
+    pub fn save(&mut self, step: u32, image: Option<&str>) -> Result<Option<PathBuf>> {
+        if step < self.last_step {
+            self.next_step = self.every_steps;
+        }
+        self.last_step = step;
+        let Some(image) = image.filter(|image| !image.is_empty()) else {
+            return Ok(None);
+        };
+        if step < self.next_step {
+            return Ok(None);
+        }
+
+        // Some versions send the preview as a data URL
+        let image = image.split_once("base64,").map_or(image, |(_, data)| data);
+        let bytes = BASE64_STANDARD.decode(image).context("Failed to decode preview")?;
+        let extension = image::guess_format(&bytes)
+            .ok()
+            .and_then(|format| format.extensions_str().first().copied())
+            .unwrap_or("png");
+        fs::create_dir_all(&self.dir).context("Failed to create previews folder")?;
+        let path = self.dir.join(format!("{}-step-{:03}.{}", self.stem, step, extension));
+        fs::write(&path, bytes).context(format!("Failed to write {}", path.display()))?;
+        debug!("{} {}", "Saved preview:".blue(), path.display());
+        self.next_step = (step / self.every_steps + 1) * self.every_steps;
+        Ok(Some(path))
+    }
+}
@@ -1,6 +1,8 @@
-use anyhow::Result;
-use colored::*;
-use std::path::Path;
+use anyhow::{Context, Result};
+use tracing::{debug, error, info, warn};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
 /**
  * Advanced processing utilities for ControlNet Image Generator
  *
@@ -12,11 +14,20 @@ use std::path::Path;
  * - BatchManager: Manages batched processing with breaks to allow GPU memory to clear
  * - ProcessingStats: Tracks success/failure statistics for batch processing
  */
-use std::thread;
-use std::time::Duration;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::fs;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
 
 use crate::api;
 use crate::config;
+use crate::file_utils::SavedImages;
+use crate::i18n::{Msg, tr, tr_args};
+use crate::pipeline::{RunEvent, RunEvents};
+use crate::selection;
+use crate::style::*;
 
 /// Maximum number of retry attempts for operations that may fail due to CUDA/GPU memory issues
 #[allow(dead_code)]
@@ -26,6 +37,12 @@ pub const MAX_RETRIES: u32 = 3;
 #[allow(dead_code)]
 pub const RETRY_DELAY_MS: u64 = 10000;
 
+/// First delay between health checks while waiting for a restarted server, doubled after each
+pub const RESTART_POLL_MS: u64 = 1000;
+
+/// Longest delay between health checks while waiting for a restarted server
+pub const MAX_RESTART_POLL_MS: u64 = 30000;
+
 /// Duration between batch processing in milliseconds to allow GPU memory to clear
 #[allow(dead_code)]
 pub const BATCH_BREAK_MS: u64 = 15000;
@@ -42,6 +59,22 @@ pub const DEFAULT_BATCH_SIZE: u32 = 1;
 pub struct RetryManager {
     max_retries: u32,
     retry_delay_ms: u64,
+    /// Additional user-defined patterns of errors that warrant a retry
+    retry_patterns: Vec<Regex>,
+    /// Limit on how often generation requests may be sent
+    rate_limiter: Option<RateLimiter>,
+    /// Reload the checkpoint before the final attempt when every attempt failed with a CUDA error
+    reload_on_cuda_error: bool,
+    /// Also interrupt the running generation as part of that recovery
+    interrupt_on_cuda_error: bool,
+    /// Subscribers told about failed attempts
+    events: RunEvents,
+    /// Settings retried with once the server produced NaN tensors
+    nan_fallback: Option<NanFallbackConfig>,
+    /// How long to wait for the server to come back after losing the connection
+    restart_wait: Option<Duration>,
+    /// Set when the server did not come back in time, so later inputs do not wait again
+    server_down: AtomicBool,
 }
 
 impl Default for RetryManager {
@@ -57,6 +90,14 @@ impl RetryManager {
         Self {
             max_retries: MAX_RETRIES,
             retry_delay_ms: RETRY_DELAY_MS,
+            retry_patterns: Vec::new(),
+            rate_limiter: None,
+            reload_on_cuda_error: false,
+            interrupt_on_cuda_error: false,
+            events: RunEvents::default(),
+            nan_fallback: None,
+            restart_wait: None,
+            server_down: AtomicBool::new(false),
         }
     }
 
@@ -65,9 +106,99 @@ impl RetryManager {
         Self {
             max_retries,
             retry_delay_ms,
+            retry_patterns: Vec::new(),
+            rate_limiter: None,
+            reload_on_cuda_error: false,
+            interrupt_on_cuda_error: false,
+            events: RunEvents::default(),
+            nan_fallback: None,
+            restart_wait: None,
+            server_down: AtomicBool::new(false),
         }
     }
+
+    /// Add user-defined regular expressions of errors that should be retried
+    ///
+    /// The patterns are matched case-insensitively against the full error chain,
+    /// in addition to the built-in CUDA/GPU error detection.
+    ///
+    /// # Arguments
+    /// * `patterns` - Regular expressions, typically from the `retry_on` config list
+    ///
+    /// # Returns
+    /// The RetryManager, or an error naming the first invalid pattern
+    pub fn with_retry_patterns(mut self, patterns: &[String]) -> Result<Self> {
+        for pattern in patterns {
+            let regex = Regex::new(&format!("(?i){}", pattern))
+                .context(format!("Invalid retry_on pattern: {}", pattern))?;
+            self.retry_patterns.push(regex);
+        }
+        Ok(self)
+    }
     
+    /// Limit how many generation requests are sent per minute
+    ///
+    /// All attempts made through this RetryManager share the limit, so it also
+    /// holds when several workers use the same manager.
+    ///
+    /// # Arguments
+    /// * `max_requests_per_minute` - Allowed request rate, `None` for no limit
+    pub fn with_rate_limit(mut self, max_requests_per_minute: Option<u32>) -> Self {
+        self.rate_limiter = max_requests_per_minute.map(RateLimiter::per_minute);
+        self
+    }
+
+    /// Recover from persistent CUDA errors before the final attempt
+    ///
+    /// When every attempt so far failed with a CUDA/GPU error, the checkpoint is
+    /// unloaded and reloaded before the last retry, as a fresh model load often
+    /// clears fragmented VRAM.
+    ///
+    /// # Arguments
+    /// * `reload_model` - Unload and reload the checkpoint
+    /// * `interrupt` - Interrupt the running generation first
+    pub fn with_cuda_recovery(mut self, reload_model: bool, interrupt: bool) -> Self {
+        self.reload_on_cuda_error = reload_model;
+        self.interrupt_on_cuda_error = interrupt;
+        self
+    }
+
+    /// Retry with other settings once the server produced NaN tensors
+    ///
+    /// NaNs come from a lack of precision of the sampler or half precision
+    /// weights, so the same request would only fail again. The first NaN
+    /// error switches the remaining attempts to the fallback settings, with
+    /// one more attempt granted when none were left.
+    ///
+    /// # Arguments
+    /// * `nan_fallback` - Fallback settings, ignored unless enabled
+    pub fn with_nan_fallback(mut self, nan_fallback: &NanFallbackConfig) -> Self {
+        self.nan_fallback = nan_fallback.enabled.then(|| nan_fallback.clone());
+        self
+    }
+
+    /// Wait for the server to come back when the connection is lost mid-run
+    ///
+    /// A refused connection after the checkpoint was loaded means the server
+    /// restarted or crashed. Its health is then checked with a growing delay
+    /// until it answers, the checkpoint is loaded again and the attempt is
+    /// repeated, instead of failing this and every following input. When the
+    /// server stays away longer than `max_wait_secs`, the input fails and later
+    /// inputs fail at once until a request succeeds again.
+    ///
+    /// # Arguments
+    /// * `max_wait_secs` - Longest wait for the server, 0 to fail right away
+    pub fn with_restart_recovery(mut self, max_wait_secs: u64) -> Self {
+        self.restart_wait = (max_wait_secs > 0).then(|| Duration::from_secs(max_wait_secs));
+        self
+    }
+
+    /// Report failed attempts to the subscribers of a run
+    pub fn with_events(mut self, events: RunEvents) -> Self {
+        self.events = events;
+        self
+    }
+
     /// Get the maximum number of retry attempts (for testing purposes)
     #[allow(dead_code)]
     pub fn get_max_retries(&self) -> u32 {
@@ -95,56 +226,320 @@ impl RetryManager {
     where
         P: AsRef<Path>,
     {
+        self.process_with_attempts(client, image_path, config).await.0
+    }
+
+    /// Process an image with retry logic, also reporting the number of attempts made
+    ///
+    /// Behaves like `process_with_retry`, but returns the attempt count alongside the
+    /// result so callers can record it in the processing statistics.
+    ///
+    /// When `batch_size` exceeds `max_batch_per_request`, the images are requested
+    /// in several sequential requests, each retried on its own, and merged into a
+    /// single response in request order. The reported attempt count is then one
+    /// plus the retries of all requests.
+    pub async fn process_with_attempts<P>(
+        &self,
+        client: &api::StableDiffusionClient,
+        image_path: P,
+        config: &config::Config,
+    ) -> (Result<Option<api::StableDiffusionResponse>>, u32)
+    where
+        P: AsRef<Path>,
+    {
+        self.process_with_overrides(client, image_path, config, RetryOverrides::default())
+            .await
+    }
+
+    /// Process an image with retry logic, using per-image retry settings
+    ///
+    /// Behaves like `process_with_attempts`, with the retry count and delay
+    /// taken from `overrides` where set, e.g. from the sidecar file of the image.
+    ///
+    /// When `selection.min_score` is set and no image reaches it, the image is
+    /// generated again with a new seed, up to `selection.max_rerolls` times. The
+    /// images of every roll are returned together, for the selection to keep the
+    /// best available, and the attempts of every roll are counted.
+    pub async fn process_with_overrides<P>(
+        &self,
+        client: &api::StableDiffusionClient,
+        image_path: P,
+        config: &config::Config,
+        overrides: RetryOverrides,
+    ) -> (Result<Option<api::StableDiffusionResponse>>, u32)
+    where
+        P: AsRef<Path>,
+    {
+        let image_path = image_path.as_ref();
+        let (mut result, mut attempts) = self.request_images(client, image_path, config, overrides).await;
+        let (Some(scorer), Some(min_score)) = (&config.selection.scorer, config.selection.min_score) else {
+            return (result, attempts);
+        };
+
+        let mut best: Option<f64> = None;
+        let mut scored = 0;
+        let mut rerolls = 0;
+        while let Ok(Some(response)) = &result {
+            match selection::best_score(scorer, &response.images[scored..]).await {
+                Ok(Some(score)) => best = Some(best.map_or(score, |best| best.max(score))),
+                Ok(None) => {}
+                Err(e) => {
+                    warn!("{}", tr_args(Msg::ScoringFailed, &[&format!("{:#}", e)]).yellow());
+                    break;
+                }
+            }
+            scored = response.images.len();
+            if best.is_some_and(|best| best >= min_score) {
+                break;
+            }
+            if rerolls == config.selection.max_rerolls {
+                info!("{}", tr(Msg::NoImageReachedMinScore).yellow());
+                break;
+            }
+
+            rerolls += 1;
+            let best = best.map_or_else(|| "-".to_string(), |best| format!("{:.2}", best));
+            info!(
+                "{}",
+                tr_args(Msg::Rerolling, &[&best, &min_score, &rerolls, &config.selection.max_rerolls]).yellow()
+            );
+            let reroll_config = config::Config {
+                seed: -1,
+                ..config.clone()
+            };
+            let (reroll, reroll_attempts) = self.request_images(client, image_path, &reroll_config, overrides).await;
+            attempts += reroll_attempts;
+            match (reroll, &mut result) {
+                (Ok(Some(reroll)), Ok(Some(response))) => response.append(reroll),
+                (Err(e), _) => {
+                    warn!("{}", tr_args(Msg::RerollFailed, &[&format!("{:#}", e)]).yellow());
+                    break;
+                }
+                _ => {}
+            }
+        }
+        (result, attempts)
+    }
+
+    /// Request the images of an input, topping up a short response once when configured
+    ///
+    /// Images the server did not return, or returned undecodable, are requested
+    /// again in one more batch with the seeds following those of the first.
+    async fn request_images(
+        &self,
+        client: &api::StableDiffusionClient,
+        image_path: &Path,
+        config: &config::Config,
+        overrides: RetryOverrides,
+    ) -> (Result<Option<api::StableDiffusionResponse>>, u32) {
+        let (mut result, mut attempts) = self.request_batch(client, image_path, config, overrides).await;
+        if config.top_up_missing
+            && let Ok(Some(response)) = &mut result
+        {
+            let missing = (config.batch_size as usize).saturating_sub(response.decodable_images());
+            if missing > 0 {
+                info!("{}", tr_args(Msg::ToppingUp, &[&missing]).yellow());
+                let top_up_config = config::Config {
+                    batch_size: missing as u32,
+                    seed: if config.seed < 0 { config.seed } else { config.seed + response.images.len() as i64 },
+                    ..config.clone()
+                };
+                let (top_up, top_up_attempts) =
+                    self.request_batch(client, image_path, &top_up_config, overrides).await;
+                attempts += top_up_attempts;
+                match top_up {
+                    Ok(Some(top_up)) => response.append(top_up),
+                    Ok(None) => {}
+                    Err(e) => warn!("{}", tr_args(Msg::TopUpFailed, &[&format!("{:#}", e)]).yellow()),
+                }
+            }
+        }
+        (result, attempts)
+    }
+
+    /// Request the images of an input, splitting the batch as configured
+    async fn request_batch(
+        &self,
+        client: &api::StableDiffusionClient,
+        image_path: &Path,
+        config: &config::Config,
+        overrides: RetryOverrides,
+    ) -> (Result<Option<api::StableDiffusionResponse>>, u32) {
+        let chunks = split_batch(config.batch_size, config.max_batch_per_request);
+        if chunks.len() <= 1 {
+            return self
+                .attempt_request(client, image_path, config, overrides)
+                .await;
+        }
+
+        info!("{}", tr_args(Msg::SplittingBatch, &[&chunks.len(), &chunks[0]]).blue());
+        let mut merged: Option<api::StableDiffusionResponse> = None;
+        let mut retries = 0;
+        let mut requested = 0;
+        for chunk in chunks {
+            // A fixed seed continues where the previous request ended, as the server numbers the seeds of a batch
+            let chunk_config = config::Config {
+                batch_size: chunk,
+                seed: if config.seed < 0 { config.seed } else { config.seed + i64::from(requested) },
+                ..config.clone()
+            };
+            requested += chunk;
+            let (result, attempts) = self
+                .attempt_request(client, image_path, &chunk_config, overrides)
+                .await;
+            retries += attempts.saturating_sub(1);
+            match result {
+                Ok(Some(response)) => match &mut merged {
+                    Some(merged) => merged.append(response),
+                    None => merged = Some(response),
+                },
+                Ok(None) => {}
+                Err(error) => return (Err(error), retries + 1),
+            }
+        }
+        (Ok(merged), retries + 1)
+    }
+
+    /// Send a single generation request, retrying it as configured
+    async fn attempt_request(
+        &self,
+        client: &api::StableDiffusionClient,
+        image_path: &Path,
+        config: &config::Config,
+        overrides: RetryOverrides,
+    ) -> (Result<Option<api::StableDiffusionResponse>>, u32) {
+        // Zero retries still makes the single attempt
+        let mut max_retries = overrides.max_retries.unwrap_or(self.max_retries).max(1);
+        let retry_delay_ms = overrides.retry_delay_ms.unwrap_or(self.retry_delay_ms);
+        let mut fallback_config: Option<config::Config> = None;
         let mut attempt = 0;
+        let mut cuda_failures = 0;
+        let mut restarts = 0;
         let mut last_error = None;
-        let image_path_ref = image_path.as_ref();
+        let image_path_ref = image_path;
 
         // For logging only, convert to string representation safely
         let path_display = image_path_ref.display().to_string();
 
-        while attempt < self.max_retries {
+        while attempt < max_retries {
             if attempt > 0 {
-                let delay = self.retry_delay_ms * attempt as u64;
-                println!(
-                    "{} {}/{} {}{}{}",
-                    "Retry attempt".yellow(),
-                    attempt,
-                    self.max_retries,
-                    "after waiting".yellow(),
-                    " ".yellow(),
-                    format!("{}ms", delay).yellow()
-                );
-                thread::sleep(Duration::from_millis(delay));
+                let delay = retry_delay_ms * attempt as u64;
+                warn!("{}", tr_args(Msg::RetryAttempt, &[&attempt, &max_retries, &delay]).yellow());
+                tokio::time::sleep(Duration::from_millis(delay)).await;
 
-                println!(
-                    "{} {} {}",
-                    "Retry attempt".yellow(),
-                    attempt,
-                    "with reduced batch size".yellow()
-                );
+                if self.reload_on_cuda_error
+                    && attempt + 1 == max_retries
+                    && cuda_failures == attempt
+                {
+                    self.recover_gpu(client).await;
+                }
+
+                warn!("{}", tr_args(Msg::RetryReducedBatch, &[&attempt]).yellow());
             }
 
+            if let Some(rate_limiter) = &self.rate_limiter {
+                rate_limiter.acquire().await;
+            }
             match client
-                .generate_with_controlnet(image_path_ref, config)
+                .generate_with_controlnet(image_path_ref, fallback_config.as_ref().unwrap_or(config))
                 .await
             {
-                Ok(result) => return Ok(result),
+                Ok(result) => {
+                    self.server_down.store(false, Ordering::Relaxed);
+                    return (Ok(result), attempt + 1);
+                }
                 Err(error) => {
                     attempt += 1;
-                    if self.is_cuda_error(&error) && attempt < self.max_retries {
-                        println!(
-                            "{} {}/{}: {}",
-                            "CUDA/GPU error detected, will retry".yellow(),
+                    if let Some(max_wait) = self.restart_wait
+                        && FailureReason::from_message(&format!("{:#}", error)) == FailureReason::Connection
+                        && self.wait_for_restart(client, &config.checkpoint_model, max_wait).await
+                    {
+                        if self.events.has_subscribers() {
+                            self.events.emit(RunEvent::AttemptFailed {
+                                input: image_path_ref.to_path_buf(),
+                                attempt,
+                                will_retry: true,
+                                error: format!("{:#}", error),
+                            });
+                        }
+                        // The attempt lost to the first restart is granted back
+                        restarts += 1;
+                        if restarts == 1 {
+                            max_retries = max_retries.max(attempt + 1);
+                        }
+                        last_error = Some(error);
+                        continue;
+                    }
+                    if fallback_config.is_none()
+                        && let Some(nan_fallback) = &self.nan_fallback
+                        && is_nan_message(&format!("{:#}", error))
+                    {
+                        warn!(
+                            event = "attempt_failed",
+                            attempt,
+                            retryable = true,
+                            reason = "nan_tensors",
+                            "{}",
+                            tr_args(Msg::NanFallback, &[&error]).yellow()
+                        );
+                        if self.events.has_subscribers() {
+                            self.events.emit(RunEvent::AttemptFailed {
+                                input: image_path_ref.to_path_buf(),
+                                attempt,
+                                will_retry: true,
+                                error: format!("{:#}", error),
+                            });
+                        }
+                        fallback_config = Some(nan_fallback.apply(config));
+                        max_retries = max_retries.max(attempt + 1);
+                        last_error = Some(error);
+                        continue;
+                    }
+                    let retryability = self.retryability(&error);
+                    if self.events.has_subscribers() {
+                        self.events.emit(RunEvent::AttemptFailed {
+                            input: image_path_ref.to_path_buf(),
                             attempt,
-                            self.max_retries,
-                            error
+                            will_retry: attempt < max_retries && retryability.is_retryable(),
+                            error: format!("{:#}", error),
+                        });
+                    }
+                    if retryability == Retryability::Gpu {
+                        cuda_failures += 1;
+                    }
+                    if !retryability.is_retryable() {
+                        error!(
+                            event = "attempt_failed",
+                            attempt,
+                            retryable = false,
+                            "{}",
+                            tr_args(Msg::NotRetryable, &[&error]).red()
+                        );
+                        return (Err(error), attempt);
+                    }
+                    if retryability == Retryability::Gpu && attempt < max_retries {
+                        warn!(
+                            event = "attempt_failed",
+                            attempt,
+                            retryable = true,
+                            reason = %retryability,
+                            "{}",
+                            tr_args(Msg::GpuErrorRetry, &[&attempt, &max_retries, &error]).yellow()
                         );
                         // Try to free memory by yielding to the async runtime
                         tokio::task::yield_now().await;
-                    } else if attempt >= self.max_retries {
+                    } else if attempt >= max_retries {
                         last_error = Some(error);
                         break;
                     } else {
+                        warn!(
+                            event = "attempt_failed",
+                            attempt,
+                            retryable = true,
+                            reason = %retryability,
+                            "{}",
+                            tr_args(Msg::RetryableError, &[&attempt, &max_retries, &error]).yellow()
+                        );
                         last_error = Some(error);
                     }
                 }
@@ -156,26 +551,112 @@ impl RetryManager {
             anyhow::anyhow!("Exhausted all retry attempts without a specific error")
         });
 
-        println!(
-            "{} {} {} {}",
-            "Exhausted all".red(),
-            self.max_retries,
-            "retry attempts for".red(),
-            path_display
+        if attempt > 1 {
+            error!("{}", tr_args(Msg::ExhaustedRetries, &[&max_retries, &path_display]).red());
+        }
+
+        (Err(error), attempt)
+    }
+
+    /// Free GPU memory on the server by reloading the checkpoint
+    ///
+    /// Failures are logged and otherwise ignored, the final attempt is made anyway.
+    async fn recover_gpu(&self, client: &api::StableDiffusionClient) {
+        warn!(event = "gpu_recovery", "{}", tr(Msg::RecoveringGpu).yellow());
+        if self.interrupt_on_cuda_error
+            && let Err(e) = client.interrupt().await
+        {
+            warn!("{}", tr_args(Msg::InterruptFailed, &[&format!("{:#}", e)]).yellow());
+        }
+        if let Err(e) = client.unload_checkpoint().await {
+            warn!("{}", tr_args(Msg::UnloadCheckpointFailed, &[&format!("{:#}", e)]).yellow());
+        }
+        if let Err(e) = client.reload_checkpoint().await {
+            warn!("{}", tr_args(Msg::ReloadCheckpointFailed, &[&format!("{:#}", e)]).yellow());
+        }
+    }
+
+    /// Wait until a server that stopped answering is healthy again and reload the checkpoint
+    ///
+    /// # Returns
+    /// Whether the server came back within `max_wait`
+    async fn wait_for_restart(&self, client: &api::StableDiffusionClient, checkpoint: &str, max_wait: Duration) -> bool {
+        if self.server_down.load(Ordering::Relaxed) {
+            return false;
+        }
+        warn!(event = "server_restart", "{}", tr_args(Msg::WaitingForRestart, &[&max_wait.as_secs()]).yellow());
+        let started = Instant::now();
+        let mut delay = Duration::from_millis(RESTART_POLL_MS);
+        loop {
+            tokio::time::sleep(delay.min(max_wait.saturating_sub(started.elapsed()))).await;
+            if client.api_status().await.is_ok_and(|status| status.is_success()) {
+                break;
+            }
+            if started.elapsed() >= max_wait {
+                error!(event = "server_down", "{}", tr(Msg::ServerDown).red());
+                self.server_down.store(true, Ordering::Relaxed);
+                return false;
+            }
+            delay = (delay * 2).min(Duration::from_millis(MAX_RESTART_POLL_MS));
+        }
+        info!(
+            event = "server_back",
+            "{}",
+            tr_args(Msg::ServerBack, &[&format!("{:.1}", started.elapsed().as_secs_f64())]).green()
         );
+        if let Err(e) = client.load_model(checkpoint).await {
+            warn!("{}", tr_args(Msg::ReloadCheckpointFailed, &[&format!("{:#}", e)]).yellow());
+        }
+        true
+    }
 
-        Err(error)
-    }    /// Check if an error is likely related to CUDA/GPU memory issues
-    /// 
-    /// Analyzes error messages to determine if they are related to GPU memory problems.
-    /// This is used to decide whether to retry operations that might succeed with
-    /// a second attempt after GPU memory has been cleared.
+    /// Why an error is or is not worth another attempt
+    ///
+    /// The error's own classification comes first, so CUDA/GPU issues,
+    /// connection failures, timeouts and 5xx responses are always retried;
+    /// otherwise its message, including the context chain, is matched
+    /// against the user-defined `retry_on` patterns.
+    ///
+    /// # Arguments
+    /// * `error` - The error to analyze
+    ///
+    /// # Returns
+    /// The retryability of the error with its reason
+    pub fn retryability(&self, error: &anyhow::Error) -> Retryability {
+        let retryability = error.retryability();
+        if retryability.is_retryable() {
+            return retryability;
+        }
+        let error_msg = format!("{:#}", error);
+        self.retry_patterns
+            .iter()
+            .find(|pattern| pattern.is_match(&error_msg))
+            .map_or(retryability, |pattern| {
+                // Report the pattern as configured, without the case-insensitivity flag
+                let pattern = pattern.as_str();
+                Retryability::Pattern(pattern.strip_prefix("(?i)").unwrap_or(pattern).to_string())
+            })
+    }
+
+    /// Check if an error warrants another attempt
+    ///
+    /// An error is retryable when it looks like a CUDA/GPU issue or a
+    /// transient failure of the connection or server, or when its message
+    /// (including the context chain) matches one of the user-defined
+    /// `retry_on` patterns.
+    ///
+    /// # Arguments
+    /// * `error` - The error to analyze
+    ///
+    /// # Returns
+    /// `true` if the operation should be attempted again
+    pub fn is_retryable(&self, error: &anyhow::Error) -> bool {
+        self.retryability(error).is_retryable()
+    }
+
+    /// Check if an error is likely related to CUDA/GPU memory issues
     ///
-    /// The method checks for several categories of GPU-related error patterns:
-    /// - Direct mentions of GPU technologies (CUDA, VRAM, NVIDIA)
-    /// - Memory errors that aren't explicitly system memory related
-    /// - Timeout errors that might indicate GPU processing issues
-    /// - Device-specific hardware errors
+    /// See `is_cuda_message` for the patterns looked for.
     ///
     /// # Arguments
     /// * `error` - The error to analyze
@@ -183,45 +664,214 @@ impl RetryManager {
     /// # Returns
     /// `true` if the error appears to be GPU/CUDA related, `false` otherwise
     pub fn is_cuda_error(&self, error: &anyhow::Error) -> bool {
-        let error_msg = error.to_string().to_lowercase();
-        
-        // GPU-specific terms
-        if error_msg.contains("cuda") || 
-           error_msg.contains("gpu") || 
-           error_msg.contains("vram") ||
-           error_msg.contains("nvidia") {
-            return true;
-        }
-        
-        // More specific memory-related phrases that are likely GPU-related
-        // Make sure we exclude system memory errors by checking for system/heap indicators
-        if (error_msg.contains("out of memory") && !error_msg.contains("heap") && !error_msg.contains("system")) || 
-           (error_msg.contains("memory exhausted") && !error_msg.contains("system")) ||
-           (error_msg.contains("memory allocation failed") && !error_msg.contains("heap")) ||
-           (error_msg.contains("not enough") && error_msg.contains("memory") && !error_msg.contains("system")) {
-            return true;
-        }
-        
-        // Timeout often indicates GPU processing issues
-        if error_msg.contains("timed out") || 
-           error_msg.contains("timeout") && error_msg.contains("compute") {
-            return true;
-        }
-        
-        // Device-specific errors often related to GPU
-        if (error_msg.contains("device") && error_msg.contains("error")) ||
-           error_msg.contains("hardware error") {
-            return true;
-        }
-        
-        false
+        error.retryability() == Retryability::Gpu
+    }
+}
+
+/// Why an error is or is not worth another attempt
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Retryability {
+    /// Looks like a CUDA/GPU problem, which often passes once memory is freed
+    Gpu,
+    /// Connection failure, timeout or 5xx response, which usually passes on its own
+    Transient,
+    /// Matches the given user-defined `retry_on` pattern
+    Pattern(String),
+    /// Nothing suggests another attempt would go differently
+    Permanent,
+}
+
+impl Retryability {
+    /// Whether another attempt is worth making
+    pub fn is_retryable(&self) -> bool {
+        !matches!(self, Self::Permanent)
+    }
+}
+
+impl fmt::Display for Retryability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Gpu => f.write_str("CUDA/GPU error"),
+            Self::Transient => f.write_str("transient error"),
+            Self::Pattern(pattern) => write!(f, "matches retry_on pattern {}", pattern),
+            Self::Permanent => f.write_str("not retryable"),
+        }
+    }
+}
+
+/// Errors that know whether they are worth retrying
+///
+/// Lets programs using the library drive their own retry loops with the
+/// same decisions `RetryManager` makes, minus the `retry_on` patterns of
+/// a configuration.
+pub trait Retryable {
+    /// Why the error is or is not worth another attempt
+    fn retryability(&self) -> Retryability;
+
+    /// Whether the error is worth another attempt
+    fn is_retryable(&self) -> bool {
+        self.retryability().is_retryable()
+    }
+}
+
+impl Retryable for anyhow::Error {
+    fn retryability(&self) -> Retryability {
+        // The whole chain is read, as the cause is often wrapped in context
+        let message = format!("{:#}", self);
+        if is_cuda_message(&message) {
+            return Retryability::Gpu;
+        }
+        let transport = self.chain().any(|cause| {
+            cause
+                .downcast_ref::<reqwest::Error>()
+                .is_some_and(|e| e.is_connect() || e.is_timeout() || e.is_request())
+        });
+        match FailureReason::from_message(&message) {
+            _ if transport => Retryability::Transient,
+            FailureReason::Connection | FailureReason::Timeout | FailureReason::HttpServerError => {
+                Retryability::Transient
+            }
+            _ => Retryability::Permanent,
+        }
+    }
+}
+
+/// Check if an error message reports NaN tensors, the NansException of the Web UI
+///
+/// # Arguments
+/// * `message` - The error message to analyze
+pub fn is_nan_message(message: &str) -> bool {
+    let message = message.to_lowercase();
+    message.contains("nansexception") || message.contains("tensor with all nans")
+}
+
+/// Settings of the retry made once the server produced NaN tensors
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NanFallbackConfig {
+    /// Whether to retry with these settings when the server reports a NansException
+    #[serde(default = "default_nan_fallback_enabled")]
+    pub enabled: bool,
+    /// Sampler of the retry, the configured one when unset
+    #[serde(default = "default_nan_fallback_sampler")]
+    pub sampler_name: Option<String>,
+    /// Scheduler of the retry, the configured one when unset
+    #[serde(default)]
+    pub scheduler: Option<String>,
+    /// Web UI settings sent in override_settings with the retry
+    #[serde(default = "default_nan_fallback_settings")]
+    pub override_settings: serde_json::Map<String, serde_json::Value>,
+}
+
+impl Default for NanFallbackConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_nan_fallback_enabled(),
+            sampler_name: default_nan_fallback_sampler(),
+            scheduler: None,
+            override_settings: default_nan_fallback_settings(),
+        }
+    }
+}
+
+/// Default of retrying after NaN tensors - enabled
+pub fn default_nan_fallback_enabled() -> bool {
+    true
+}
+
+/// Default sampler retried with after NaN tensors - Euler
+pub fn default_nan_fallback_sampler() -> Option<String> {
+    Some("Euler".to_string())
+}
+
+/// Default settings sent with the retry after NaN tensors - cross attention upcast to float32
+pub fn default_nan_fallback_settings() -> serde_json::Map<String, serde_json::Value> {
+    serde_json::Map::from_iter([("upcast_attn".to_string(), serde_json::Value::Bool(true))])
+}
+
+impl NanFallbackConfig {
+    /// Configuration of the retry, the fallback settings applied over those of the failed attempt
+    ///
+    /// # Arguments
+    /// * `config` - Configuration of the failed attempt
+    pub fn apply(&self, config: &config::Config) -> config::Config {
+        let mut fallback = config.clone();
+        if let Some(sampler_name) = &self.sampler_name {
+            fallback.sampler_name = sampler_name.clone();
+        }
+        if let Some(scheduler) = &self.scheduler {
+            fallback.scheduler = scheduler.clone();
+        }
+        fallback.override_settings.extend(self.override_settings.clone());
+        fallback
+    }
+}
+
+/// Check if an error message is likely related to CUDA/GPU memory issues
+///
+/// Used to decide whether to retry operations that might succeed with
+/// a second attempt after GPU memory has been cleared.
+///
+/// The message is checked for several categories of GPU-related patterns:
+/// - Direct mentions of GPU technologies (CUDA, VRAM, NVIDIA)
+/// - Memory errors that aren't explicitly system memory related
+/// - Timeout errors that might indicate GPU processing issues
+/// - Device-specific hardware errors
+///
+/// # Arguments
+/// * `message` - The error message to analyze
+///
+/// # Returns
+/// `true` if the message appears to be GPU/CUDA related, `false` otherwise
+pub fn is_cuda_message(message: &str) -> bool {
+    let error_msg = message.to_lowercase();
+
+    // GPU-specific terms
+    if error_msg.contains("cuda") ||
+       error_msg.contains("gpu") ||
+       error_msg.contains("vram") ||
+       error_msg.contains("nvidia") {
+        return true;
+    }
+
+    // More specific memory-related phrases that are likely GPU-related
+    // Make sure we exclude system memory errors by checking for system/heap indicators
+    if (error_msg.contains("out of memory") && !error_msg.contains("heap") && !error_msg.contains("system")) ||
+       (error_msg.contains("memory exhausted") && !error_msg.contains("system")) ||
+       (error_msg.contains("memory allocation failed") && !error_msg.contains("heap")) ||
+       (error_msg.contains("not enough") && error_msg.contains("memory") && !error_msg.contains("system")) {
+        return true;
     }
+
+    // Timeout often indicates GPU processing issues
+    if error_msg.contains("timed out") ||
+       error_msg.contains("timeout") && error_msg.contains("compute") {
+        return true;
+    }
+
+    // Device-specific errors often related to GPU
+    if (error_msg.contains("device") && error_msg.contains("error")) ||
+       error_msg.contains("hardware error") {
+        return true;
+    }
+
+    false
 }
 
+/// Number of recent generation times averaged by adaptive breaks
+pub const ADAPTIVE_WINDOW: usize = 5;
+
+/// How much slower than the best rolling average generation may get before a break
+pub const ADAPTIVE_DEGRADATION_FACTOR: f64 = 1.25;
+
+/// Upper limit of the multiplier applied to the break duration while degradation continues
+pub const ADAPTIVE_MAX_MULTIPLIER: u32 = 16;
+
 /// Helper for managing batch processing with breaks to allow GPU memory to clear
 pub struct BatchManager {
     batch_size: u32,
     break_duration_ms: u64,
+    /// Degradation tracking, when breaks are taken only as generation slows down
+    adaptive: Option<Mutex<AdaptiveBreaks>>,
 }
 
 impl Default for BatchManager {
@@ -237,6 +887,7 @@ impl BatchManager {
         Self {
             batch_size: DEFAULT_BATCH_SIZE,
             break_duration_ms: BATCH_BREAK_MS,
+            adaptive: None,
         }
     }
 
@@ -245,16 +896,23 @@ impl BatchManager {
         Self {
             batch_size,
             break_duration_ms,
+            adaptive: None,
         }
     }
 
+    /// Take breaks only when generation times degrade instead of after every batch
+    pub fn with_adaptive_breaks(mut self, enabled: bool) -> Self {
+        self.adaptive = enabled.then(|| Mutex::new(AdaptiveBreaks::new(self.break_duration_ms)));
+        self
+    }
+
     /// Check if we should take a break after processing an item at the given index
     ///
     /// Returns true if the current item is the last in a batch (except for the very last item)
     #[allow(dead_code)]
     pub async fn should_take_break(&self, index: usize) -> bool {
         // Check if this is the end of a batch (but not the last item)
-        (index + 1) % self.batch_size as usize == 0 && index > 0
+        (index + 1).is_multiple_of(self.batch_size as usize) && index > 0
     }    /// Take a break between batches if needed
     /// 
     /// This method determines if the current processing index is at the end of a batch
@@ -266,29 +924,356 @@ impl BatchManager {
     /// * `total_count` - Total number of items to process
     pub async fn manage_batch_break(&self, index: usize, total_count: usize) {
         let is_end_of_batch =
-            (index + 1) % self.batch_size as usize == 0 && index < total_count - 1;
+            (index + 1).is_multiple_of(self.batch_size as usize) && index < total_count - 1;
 
         if is_end_of_batch {
-            println!(
-                "{} {}{}{}",
-                "Taking a break to clear GPU memory".blue(),
-                "(".blue(),
-                format!("{}ms", self.break_duration_ms).blue(),
-                ")".blue()
-            );
-            thread::sleep(Duration::from_millis(self.break_duration_ms));
+            self.take_break().await;
+        }
+    }
+
+    /// Take a break after real GPU work if it completes a batch
+    ///
+    /// Unlike `manage_batch_break`, only inputs that were actually generated
+    /// are counted, so skipped or already finished inputs never cause a pause.
+    ///
+    /// # Arguments
+    /// * `generations` - Number of inputs generated so far, including the current one
+    /// * `more_pending` - Whether any inputs are still waiting to be processed
+    pub async fn manage_generation_break(&self, generations: usize, more_pending: bool) {
+        if generations > 0 && generations.is_multiple_of(self.batch_size as usize) && more_pending {
+            self.take_break().await;
+        }
+    }
 
-            // Yield to the async runtime to help with memory management
-            tokio::task::yield_now().await;
+    /// Take a break after real GPU work, either every full batch or, with
+    /// adaptive breaks, only when generation has started to slow down
+    ///
+    /// # Arguments
+    /// * `generations` - Number of inputs generated so far, including the current one
+    /// * `generation_time` - Time the current input spent generating
+    /// * `more_pending` - Whether any inputs are still waiting to be processed
+    pub async fn after_generation(&self, generations: usize, generation_time: Duration, more_pending: bool) {
+        let Some(adaptive) = &self.adaptive else {
+            return self.manage_generation_break(generations, more_pending).await;
+        };
+        let pause = adaptive
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .record(generation_time);
+        if let Some(pause) = pause.filter(|_| more_pending) {
+            warn!("{}", tr_args(Msg::SlowingDown, &[&pause.as_millis()]).yellow());
+            self.pause(pause).await;
+        }
+    }
+
+    /// Pause for the configured duration to allow GPU memory to clear
+    async fn take_break(&self) {
+        info!("{}", tr_args(Msg::TakingBreak, &[&self.break_duration_ms]).blue());
+        self.pause(Duration::from_millis(self.break_duration_ms)).await;
+    }
+
+    /// Sleep for the given duration
+    async fn pause(&self, duration: Duration) {
+        tokio::time::sleep(duration).await;
+
+        // Yield to the async runtime to help with memory management
+        tokio::task::yield_now().await;
+    }
+}
+
+/// Decides when to pause based on a rolling average of generation times
+///
+/// The best rolling average seen so far serves as the baseline. Once the
+/// current average is more than `ADAPTIVE_DEGRADATION_FACTOR` times slower,
+/// every further input gets a break, doubling in length for as long as the
+/// degradation continues.
+#[derive(Debug)]
+pub struct AdaptiveBreaks {
+    /// Break taken on the first sign of degradation
+    base_break: Duration,
+    /// Most recent generation times, oldest first
+    recent: VecDeque<Duration>,
+    /// Lowest rolling average seen so far
+    baseline: Option<Duration>,
+    /// Breaks taken in a row without recovering
+    consecutive: u32,
+}
+
+impl AdaptiveBreaks {
+    /// Create the tracker with the duration of the first break
+    pub fn new(base_break_ms: u64) -> Self {
+        Self {
+            base_break: Duration::from_millis(base_break_ms),
+            recent: VecDeque::with_capacity(ADAPTIVE_WINDOW),
+            baseline: None,
+            consecutive: 0,
+        }
+    }
+
+    /// Add the generation time of one input
+    ///
+    /// # Returns
+    /// The break to take now, or `None` while generation times are healthy
+    pub fn record(&mut self, generation_time: Duration) -> Option<Duration> {
+        if self.recent.len() == ADAPTIVE_WINDOW {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(generation_time);
+        if self.recent.len() < ADAPTIVE_WINDOW {
+            return None;
+        }
+
+        let average = self.recent.iter().sum::<Duration>() / ADAPTIVE_WINDOW as u32;
+        let baseline = *self.baseline.get_or_insert(average);
+        if average < baseline {
+            self.baseline = Some(average);
+        }
+
+        if average.as_secs_f64() > baseline.as_secs_f64() * ADAPTIVE_DEGRADATION_FACTOR {
+            let multiplier = 2u32.saturating_pow(self.consecutive).min(ADAPTIVE_MAX_MULTIPLIER);
+            self.consecutive += 1;
+            Some(self.base_break * multiplier)
+        } else {
+            self.consecutive = 0;
+            None
         }
     }
 }
 
+/// Retry settings overriding those of the RetryManager for a single image
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RetryOverrides {
+    /// Maximum number of attempts for this image
+    #[serde(default)]
+    pub max_retries: Option<u32>,
+    /// Delay between retries in milliseconds for this image
+    #[serde(default)]
+    pub retry_delay_ms: Option<u64>,
+}
+
+/// Split a batch into request sizes no larger than the given maximum
+///
+/// # Arguments
+/// * `batch_size` - Number of images wanted
+/// * `max_per_request` - Largest batch a single request may ask for, `None` for no limit
+///
+/// # Returns
+/// The batch size of each sequential request, largest first
+pub fn split_batch(batch_size: u32, max_per_request: Option<u32>) -> Vec<u32> {
+    let max = match max_per_request {
+        Some(max) if max > 0 && max < batch_size => max,
+        _ => return vec![batch_size],
+    };
+    let mut chunks = vec![max; (batch_size / max) as usize];
+    if !batch_size.is_multiple_of(max) {
+        chunks.push(batch_size % max);
+    }
+    chunks
+}
+
+/// Token bucket limiting how often requests are sent
+///
+/// Tokens are refilled continuously at the configured rate up to the bucket
+/// capacity, and every request takes one token, waiting for it if needed.
+/// The limiter is shared by reference, so all workers draw from one bucket.
+#[derive(Debug)]
+pub struct RateLimiter {
+    /// Tokens added per second
+    rate_per_second: f64,
+    /// Maximum number of tokens the bucket holds
+    capacity: f64,
+    /// Available tokens and when they were last refilled
+    state: Mutex<(f64, Instant)>,
+}
+
+impl RateLimiter {
+    /// Create a limiter allowing the given number of requests per minute
+    ///
+    /// The bucket holds a single token, so requests are spread evenly over
+    /// the minute instead of being sent in a burst.
+    pub fn per_minute(max_requests_per_minute: u32) -> Self {
+        Self::with_capacity(max_requests_per_minute.max(1) as f64 / 60.0, 1)
+    }
+
+    /// Create a limiter with an explicit refill rate and burst capacity
+    ///
+    /// # Arguments
+    /// * `rate_per_second` - Tokens added per second
+    /// * `capacity` - Maximum number of requests that may be sent back to back
+    pub fn with_capacity(rate_per_second: f64, capacity: u32) -> Self {
+        let capacity = capacity.max(1) as f64;
+        Self {
+            rate_per_second,
+            capacity,
+            state: Mutex::new((capacity, Instant::now())),
+        }
+    }
+
+    /// Take a token, waiting until one is available
+    pub async fn acquire(&self) {
+        let wait = {
+            let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+            let (tokens, refilled_at) = &mut *state;
+            let now = Instant::now();
+            *tokens = (*tokens + now.duration_since(*refilled_at).as_secs_f64() * self.rate_per_second)
+                .min(self.capacity);
+            *refilled_at = now;
+
+            // Take the token now, possibly going into debt that the wait pays off
+            *tokens -= 1.0;
+            if *tokens >= 0.0 {
+                return;
+            }
+            Duration::from_secs_f64(-*tokens / self.rate_per_second)
+        };
+
+        debug!("{} {}ms", "Rate limit reached, waiting".yellow(), wait.as_millis());
+        tokio::time::sleep(wait).await;
+    }
+}
+
+/// Broad category of why processing an input failed
+///
+/// Used to group the failure report so it is clear at a glance whether the
+/// settings are too heavy for the GPU, the server is misbehaving or the
+/// inputs themselves are broken.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureReason {
+    /// GPU ran out of memory or reported a CUDA error
+    CudaOom,
+    /// Generation produced NaN tensors, from a lack of precision
+    NanTensors,
+    /// Request or generation timed out
+    Timeout,
+    /// Server rejected the request with a 4xx status
+    HttpClientError,
+    /// Server failed with a 5xx status
+    HttpServerError,
+    /// Server could not be reached
+    Connection,
+    /// Response or image data could not be decoded
+    DecodeError,
+    /// Input image could not be read
+    InvalidInput,
+    /// Anything not matching the categories above
+    Other,
+}
+
+impl FailureReason {
+    /// Classify an error message into a failure category
+    ///
+    /// GPU errors are checked first, as the server usually reports them
+    /// wrapped in a 500 response.
+    pub fn from_message(message: &str) -> Self {
+        let message = message.to_lowercase();
+
+        // NaN tensors are a problem of precision, told apart from other server errors first
+        if is_nan_message(&message) {
+            Self::NanTensors
+        } else if message.contains("cuda")
+            || message.contains("out of memory")
+            || message.contains("vram")
+            || message.contains("gpu")
+        {
+            Self::CudaOom
+        } else if message.contains("timed out") || message.contains("timeout") {
+            Self::Timeout
+        } else if let Some(status) = http_status(&message) {
+            if (400..500).contains(&status) {
+                Self::HttpClientError
+            } else if status >= 500 {
+                Self::HttpServerError
+            } else {
+                Self::Other
+            }
+        } else if message.contains("error sending request")
+            || message.contains("connection refused")
+            || message.contains("connect")
+        {
+            Self::Connection
+        } else if message.contains("decode") || message.contains("failed to parse") {
+            Self::DecodeError
+        } else if message.contains("error opening image")
+            || message.contains("error reading image")
+            || message.contains("no such file")
+        {
+            Self::InvalidInput
+        } else {
+            Self::Other
+        }
+    }
+
+    /// Short human readable label of the category
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::CudaOom => "CUDA/GPU out of memory",
+            Self::NanTensors => "NaN tensors",
+            Self::Timeout => "Timeout",
+            Self::HttpClientError => "HTTP 4xx",
+            Self::HttpServerError => "HTTP 5xx",
+            Self::Connection => "API unreachable",
+            Self::DecodeError => "Decode error",
+            Self::InvalidInput => "Invalid input",
+            Self::Other => "Other",
+        }
+    }
+}
+
+impl fmt::Display for FailureReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.label())
+    }
+}
+
+/// Timing measurements for processing a single input image
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ImageTiming {
+    /// Time the input waited in the queue before processing started
+    pub queue_wait: Duration,
+    /// Time spent in generation requests, including retries
+    pub generation: Duration,
+    /// Total time spent on the input, including saving
+    pub total: Duration,
+}
+
+/// Outcome of processing a single input image
+#[derive(Debug, Clone, Serialize)]
+pub struct ImageResult {
+    /// Path of the input image
+    pub path: String,
+    /// Whether generation and saving succeeded
+    pub success: bool,
+    /// Number of images generated for this input
+    pub generated: usize,
+    /// Megapixels generated for this input
+    pub megapixels: f64,
+    /// Time spent on this input in milliseconds, including retries
+    pub duration_ms: u64,
+    /// Time the input waited in the queue in milliseconds
+    pub queue_wait_ms: u64,
+    /// Time spent in generation requests in milliseconds
+    pub generation_ms: u64,
+    /// Number of generation attempts made
+    pub attempts: u32,
+    /// Error message when processing failed
+    pub error: Option<String>,
+    /// Category of the failure, when processing failed
+    pub reason: Option<FailureReason>,
+    /// Paths of the images saved for this input
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub outputs: Vec<String>,
+    /// Size of the images saved for this input, in bytes
+    pub output_bytes: u64,
+    /// Images requested for this input that the server did not return or returned undecodable
+    pub missing: usize,
+}
+
 /// Statistics for batch processing
 /// 
 /// Tracks and reports on the success and failure of image generation operations.
 /// Used to provide summary information to the user after processing is complete.
-#[derive(Debug, Default)]
+#[derive(Debug, Clone, Default, Serialize)]
 pub struct ProcessingStats {
     /// Number of images successfully processed
     pub success_count: usize,
@@ -296,6 +1281,13 @@ pub struct ProcessingStats {
     pub generated_count: usize,
     /// Paths of images that failed processing
     pub failed_paths: Vec<String>,
+    /// Per-image outcomes in processing order
+    pub images: Vec<ImageResult>,
+    /// Wall-clock duration of the whole run in milliseconds, set by `finish`
+    pub elapsed_ms: u64,
+    /// Moment the run started
+    #[serde(skip)]
+    started_at: Option<Instant>,
 }
 
 impl ProcessingStats {
@@ -304,38 +1296,414 @@ impl ProcessingStats {
         Self::default()
     }
 
+    /// Mark the start of the run, used for queue wait and throughput calculations
+    pub fn start(&mut self) {
+        self.started_at = Some(Instant::now());
+    }
+
+    /// Time elapsed since `start` was called, zero if the run was never started
+    pub fn since_start(&self) -> Duration {
+        self.started_at
+            .map(|started_at| started_at.elapsed())
+            .unwrap_or_default()
+    }
+
+    /// Mark the end of the run, fixing the total elapsed time
+    pub fn finish(&mut self) {
+        self.elapsed_ms = self.since_start().as_millis() as u64;
+    }
+
+    /// Record a successfully processed input
+    pub fn record_success(
+        &mut self,
+        path: &Path,
+        generated: usize,
+        megapixels: f64,
+        timing: ImageTiming,
+        attempts: u32,
+    ) {
+        self.success_count += 1;
+        self.generated_count += generated;
+        self.images.push(ImageResult {
+            path: path.to_string_lossy().to_string(),
+            success: true,
+            generated,
+            megapixels,
+            duration_ms: timing.total.as_millis() as u64,
+            queue_wait_ms: timing.queue_wait.as_millis() as u64,
+            generation_ms: timing.generation.as_millis() as u64,
+            attempts,
+            error: None,
+            reason: None,
+            outputs: Vec::new(),
+            output_bytes: 0,
+            missing: 0,
+        });
+    }
+
+    /// Record an input that failed processing, with the reason
+    pub fn record_failure(&mut self, path: &Path, timing: ImageTiming, attempts: u32, reason: &str) {
+        let path = path.to_string_lossy().to_string();
+        self.failed_paths.push(path.clone());
+        self.images.push(ImageResult {
+            path,
+            success: false,
+            generated: 0,
+            megapixels: 0.0,
+            duration_ms: timing.total.as_millis() as u64,
+            queue_wait_ms: timing.queue_wait.as_millis() as u64,
+            generation_ms: timing.generation.as_millis() as u64,
+            attempts,
+            error: Some(reason.to_string()),
+            reason: Some(FailureReason::from_message(reason)),
+            outputs: Vec::new(),
+            output_bytes: 0,
+            missing: 0,
+        });
+    }
+
+    /// Attach the saved images to the most recently recorded input
+    pub fn record_outputs(&mut self, outputs: &[PathBuf]) {
+        if let Some(image) = self.images.last_mut() {
+            image.outputs = outputs
+                .iter()
+                .map(|output| output.to_string_lossy().to_string())
+                .collect();
+        }
+    }
+
+    /// Attach what was saved to the most recently recorded input
+    pub fn record_saved(&mut self, saved: &SavedImages) {
+        self.record_outputs(&saved.paths);
+        if let Some(image) = self.images.last_mut() {
+            image.output_bytes = saved.bytes;
+            image.missing = saved.missing;
+        }
+    }
+
+    /// Images requested but not saved, as the server returned fewer than asked for
+    pub fn missing_images(&self) -> usize {
+        self.images.iter().map(|image| image.missing).sum()
+    }
+
+    /// Size of all saved images, in bytes
+    pub fn bytes_written(&self) -> u64 {
+        self.images.iter().map(|image| image.output_bytes).sum()
+    }
+
+    /// Failed inputs grouped by failure category
+    pub fn failures_by_reason(&self) -> BTreeMap<FailureReason, Vec<&str>> {
+        let mut groups: BTreeMap<FailureReason, Vec<&str>> = BTreeMap::new();
+        for image in &self.images {
+            if let Some(reason) = image.reason {
+                groups.entry(reason).or_default().push(&image.path);
+            }
+        }
+        groups
+    }
+
+    /// Total megapixels generated during the run
+    pub fn total_megapixels(&self) -> f64 {
+        self.images.iter().map(|image| image.megapixels).sum()
+    }
+
+    /// Average generation time per processed input in milliseconds
+    pub fn average_generation_ms(&self) -> u64 {
+        if self.images.is_empty() {
+            return 0;
+        }
+        self.images.iter().map(|image| image.generation_ms).sum::<u64>() / self.images.len() as u64
+    }
+
+    /// Generated images per minute over the whole run
+    pub fn images_per_minute(&self) -> f64 {
+        per_minute(self.generated_count as f64, self.elapsed_ms)
+    }
+
+    /// Generated megapixels per minute over the whole run
+    pub fn megapixels_per_minute(&self) -> f64 {
+        per_minute(self.total_megapixels(), self.elapsed_ms)
+    }
+
+    /// Total number of retries across all inputs
+    pub fn total_retries(&self) -> u32 {
+        self.images
+            .iter()
+            .map(|image| image.attempts.saturating_sub(1))
+            .sum()
+    }
+
+    /// Serialize the statistics as a pretty-printed JSON document
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(&self.to_json_value()?)?)
+    }
+
+    /// The statistics as JSON, including the derived totals and rates
+    pub fn to_json_value(&self) -> Result<serde_json::Value> {
+        let mut value = serde_json::to_value(self)?;
+        value["failed_count"] = serde_json::json!(self.failed_paths.len());
+        value["total_retries"] = serde_json::json!(self.total_retries());
+        value["total_megapixels"] = serde_json::json!(self.total_megapixels());
+        value["bytes_written"] = serde_json::json!(self.bytes_written());
+        value["missing_images"] = serde_json::json!(self.missing_images());
+        value["average_generation_ms"] = serde_json::json!(self.average_generation_ms());
+        value["images_per_minute"] = serde_json::json!(self.images_per_minute());
+        value["megapixels_per_minute"] = serde_json::json!(self.megapixels_per_minute());
+        value["failures_by_reason"] = serde_json::json!(
+            self.failures_by_reason()
+                .into_iter()
+                .map(|(reason, paths)| (reason, paths.len()))
+                .collect::<BTreeMap<_, _>>()
+        );
+        Ok(value)
+    }
+
+    /// Serialize the per-image outcomes as CSV, one row per input
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from(
+            "path,success,generated,megapixels,duration_ms,queue_wait_ms,generation_ms,attempts,reason,error\n",
+        );
+        for image in &self.images {
+            csv.push_str(&format!(
+                "{},{},{},{:.2},{},{},{},{},{},{}\n",
+                csv_field(&image.path),
+                image.success,
+                image.generated,
+                image.megapixels,
+                image.duration_ms,
+                image.queue_wait_ms,
+                image.generation_ms,
+                image.attempts,
+                image.reason.map(|reason| reason.label()).unwrap_or_default(),
+                csv_field(image.error.as_deref().unwrap_or_default())
+            ));
+        }
+        csv
+    }
+
+    /// Write the statistics to a file, as CSV when the extension is `.csv` and JSON otherwise
+    pub fn write_to_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let is_csv = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("csv"));
+        let content = if is_csv { self.to_csv() } else { self.to_json()? };
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).context("Failed to create statistics directory")?;
+        }
+        fs::write(path, content)
+            .context(format!("Failed to write statistics file: {}", path.display()))
+    }
+
     /// Display processing statistics with color formatting
     pub fn display(&self, total_images: usize) {
-        println!("{}", "✓ Image generation complete!".green().bold());
-        println!(
-            "{} {}/{}{}{}{}",
-            "Processed successfully:".green(),
-            self.success_count.to_string().bold(),
-            total_images,
-            " images".green(),
-            ", Generated: ".green(),
-            format!("{} new images", self.generated_count).bold()
+        info!(
+            event = "run_finished",
+            success = self.success_count,
+            failed = self.failed_paths.len(),
+            generated = self.generated_count,
+            elapsed_ms = self.elapsed_ms,
+            "{}",
+            tr(Msg::GenerationComplete).green().bold()
+        );
+        info!(
+            "{}",
+            tr_args(Msg::ProcessedSummary, &[&self.success_count, &total_images, &self.generated_count]).green()
         );
 
-        if !self.failed_paths.is_empty() {
-            let failed_names: Vec<&str> = self
-                .failed_paths
-                .iter()
-                .map(|p| {
-                    Path::new(p)
-                        .file_name()
-                        .unwrap_or_default()
-                        .to_str()
-                        .unwrap_or("unknown")
-                })
-                .collect();
+        if !self.images.is_empty() {
+            info!(
+                "{}",
+                tr_args(
+                    Msg::TimingSummary,
+                    &[
+                        &self.average_generation_ms(),
+                        &self.total_retries(),
+                        &format!("{:.1}", self.elapsed_ms as f64 / 1000.0),
+                    ]
+                )
+                .blue()
+            );
+            info!(
+                "{}",
+                tr_args(
+                    Msg::Throughput,
+                    &[
+                        &format!("{:.2}", self.images_per_minute()),
+                        &format!("{:.2}", self.megapixels_per_minute()),
+                    ]
+                )
+                .blue()
+            );
+        }
+
+        let missing = self.missing_images();
+        if missing > 0 {
+            warn!("{}", tr_args(Msg::MissingImages, &[&missing]).yellow());
+        }
+
+        let failures = self.failures_by_reason();
+        if !failures.is_empty() {
+            warn!("{}:", tr_args(Msg::FailedImages, &[&self.failed_paths.len()]).yellow());
+            for (reason, paths) in &failures {
+                let failed_names: Vec<&str> = paths.iter().map(|p| file_name_of(p)).collect();
+                warn!(
+                    "  {} {}: {}",
+                    reason.label().yellow().bold(),
+                    format!("({})", paths.len()).yellow(),
+                    failed_names.join(", ").yellow()
+                );
+            }
+        } else if !self.failed_paths.is_empty() {
+            let failed_names: Vec<&str> = self.failed_paths.iter().map(|p| file_name_of(p)).collect();
 
-            println!(
-                "{} {}: {}",
-                "Failed images".yellow(),
-                format!("({})", self.failed_paths.len()).yellow(),
+            warn!(
+                "{}: {}",
+                tr_args(Msg::FailedImages, &[&self.failed_paths.len()]).yellow(),
                 failed_names.join(", ").yellow()
             );
         }
     }
 }
+
+/// Statistics of a run collected by several workers at once
+///
+/// Counters are atomic, so progress can be read at any time without waiting
+/// for a worker, e.g. by the dashboard or a program embedding the pipeline.
+/// The per-input results are kept behind a lock, and `snapshot` copies
+/// everything into a `ProcessingStats` for reports.
+#[derive(Debug)]
+pub struct StatsCollector {
+    started_at: Instant,
+    success_count: AtomicUsize,
+    generated_count: AtomicUsize,
+    failed_count: AtomicUsize,
+    stats: Mutex<ProcessingStats>,
+}
+
+impl StatsCollector {
+    /// Start collecting the statistics of a run starting now
+    pub fn new() -> Arc<Self> {
+        let mut stats = ProcessingStats::new();
+        stats.start();
+        Arc::new(Self {
+            started_at: Instant::now(),
+            success_count: AtomicUsize::new(0),
+            generated_count: AtomicUsize::new(0),
+            failed_count: AtomicUsize::new(0),
+            stats: Mutex::new(stats),
+        })
+    }
+
+    /// Time elapsed since the run started
+    pub fn since_start(&self) -> Duration {
+        self.started_at.elapsed()
+    }
+
+    /// Inputs processed successfully so far
+    pub fn success_count(&self) -> usize {
+        self.success_count.load(Ordering::Relaxed)
+    }
+
+    /// Images generated so far
+    pub fn generated_count(&self) -> usize {
+        self.generated_count.load(Ordering::Relaxed)
+    }
+
+    /// Inputs that failed so far
+    pub fn failed_count(&self) -> usize {
+        self.failed_count.load(Ordering::Relaxed)
+    }
+
+    /// Record a successfully processed input and what was saved for it
+    ///
+    /// # Returns
+    /// The recorded outcome of the input
+    pub fn record_success(
+        &self,
+        path: &Path,
+        generated: usize,
+        megapixels: f64,
+        timing: ImageTiming,
+        attempts: u32,
+        saved: &SavedImages,
+    ) -> ImageResult {
+        let mut stats = self.lock();
+        stats.record_success(path, generated, megapixels, timing, attempts);
+        stats.record_saved(saved);
+        self.success_count.fetch_add(1, Ordering::Relaxed);
+        self.generated_count.fetch_add(generated, Ordering::Relaxed);
+        stats
+            .images
+            .last()
+            .cloned()
+            .expect("an input was just recorded")
+    }
+
+    /// Record an input that failed processing, with the reason
+    ///
+    /// # Returns
+    /// The recorded outcome of the input
+    pub fn record_failure(
+        &self,
+        path: &Path,
+        timing: ImageTiming,
+        attempts: u32,
+        reason: &str,
+    ) -> ImageResult {
+        let mut stats = self.lock();
+        stats.record_failure(path, timing, attempts, reason);
+        self.failed_count.fetch_add(1, Ordering::Relaxed);
+        stats
+            .images
+            .last()
+            .cloned()
+            .expect("an input was just recorded")
+    }
+
+    /// Copy of the statistics so far, with the time elapsed until now
+    pub fn snapshot(&self) -> ProcessingStats {
+        let mut stats = self.lock().clone();
+        stats.finish();
+        stats
+    }
+
+    fn lock(&self) -> MutexGuard<'_, ProcessingStats> {
+        self.stats.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// File name part of a path string, for compact reporting
+fn file_name_of(path: &str) -> &str {
+    Path::new(path)
+        .file_name()
+        .unwrap_or_default()
+        .to_str()
+        .unwrap_or("unknown")
+}
+
+/// Extract the HTTP status code from an "API error: <status>" message
+fn http_status(message: &str) -> Option<u16> {
+    let rest = message.split("api error:").nth(1)?.trim_start();
+    let digits: String = rest.chars().take_while(|c| c.is_ascii_digit()).collect();
+    digits.parse().ok()
+}
+
+/// Rate per minute of an amount over a duration in milliseconds
+fn per_minute(amount: f64, elapsed_ms: u64) -> f64 {
+    if elapsed_ms == 0 {
+        return 0.0;
+    }
+    amount * 60_000.0 / elapsed_ms as f64
+}
+
+/// Quote a CSV field when it contains separators, quotes or line breaks
+pub fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
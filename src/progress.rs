@@ -0,0 +1,207 @@
+use anyhow::{Context, Result};
+use chrono::{Local, Utc};
+/**
+ * Progress file for ControlNet Image Generator
+ *
+ * This module keeps a small JSON file with the progress of a running batch
+ * up to date: the inputs being generated, the counts so far, the estimated
+ * time left and the last error. External dashboards, Home Assistant or a
+ * plain `watch cat progress.json` can then follow a headless run without
+ * parsing its log. The file is replaced in one step on every change, so a
+ * reader never sees it half written.
+ */
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Instant;
+use tracing::warn;
+
+use crate::config::Config;
+use crate::control::{RunControl, RunStatus};
+use crate::i18n::{Msg, tr_args};
+use crate::processing::ImageResult;
+use crate::style::*;
+
+/// State of the run in the progress file
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProgressState {
+    /// Inputs are being processed
+    Running,
+    /// Every input was processed
+    Finished,
+    /// The run was aborted before processing every input
+    Aborted,
+    /// Every backend stopped with an error
+    Failed,
+}
+
+/// An input being generated
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CurrentInput {
+    /// Path of the input image
+    pub input: String,
+    /// Backend generating it
+    pub backend: String,
+    /// Time spent on it so far, in seconds
+    pub elapsed_seconds: u64,
+}
+
+/// The most recent failure of the run
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LastError {
+    /// Path of the input image
+    pub input: String,
+    /// Error message
+    pub error: String,
+}
+
+/// Contents of the progress file
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Progress {
+    /// Identifier of the run
+    pub run_id: Option<String>,
+    /// State of the run
+    pub state: ProgressState,
+    /// Inputs in the run, including those finished by an earlier run
+    pub total: usize,
+    /// Inputs finished successfully
+    pub done: usize,
+    /// Inputs that failed
+    pub failed: usize,
+    /// Inputs waiting to be processed
+    pub pending: usize,
+    /// Images generated in this run
+    pub generated: usize,
+    /// Inputs being generated right now
+    pub current: Vec<CurrentInput>,
+    /// Time since the run started, in seconds
+    pub elapsed_seconds: u64,
+    /// Estimated time left, in seconds, once an input has finished
+    pub eta_seconds: Option<u64>,
+    /// Estimated local time the run finishes
+    pub eta: Option<String>,
+    /// The most recent failure
+    pub last_error: Option<LastError>,
+    /// Time the file was written
+    pub updated: String,
+}
+
+impl Progress {
+    /// Progress of a run from its live status
+    ///
+    /// # Arguments
+    /// * `status` - Status of the run
+    /// * `state` - State of the run
+    /// * `run_id` - Identifier of the run
+    /// * `last_error` - The most recent failure
+    pub fn from_status(
+        status: &RunStatus,
+        state: ProgressState,
+        run_id: Option<String>,
+        last_error: Option<LastError>,
+    ) -> Self {
+        let now = Instant::now();
+        let remaining = status.remaining().filter(|_| state == ProgressState::Running);
+        Self {
+            run_id,
+            state,
+            total: status.total,
+            done: status.done,
+            failed: status.failed,
+            pending: status.pending(),
+            generated: status.generated,
+            current: status
+                .active
+                .iter()
+                .map(|active| CurrentInput {
+                    input: active.path.display().to_string(),
+                    backend: active.backend.clone(),
+                    elapsed_seconds: now.saturating_duration_since(active.started).as_secs(),
+                })
+                .collect(),
+            elapsed_seconds: status
+                .started
+                .map(|started| now.saturating_duration_since(started).as_secs())
+                .unwrap_or_default(),
+            eta_seconds: remaining.map(|remaining| remaining.as_secs()),
+            eta: remaining.map(|remaining| {
+                (Local::now() + chrono::Duration::from_std(remaining).unwrap_or_default()).to_rfc3339()
+            }),
+            last_error,
+            updated: Utc::now().to_rfc3339(),
+        }
+    }
+
+    /// Read a progress file
+    pub fn read(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path).context(format!("Failed to read {}", path.display()))?;
+        serde_json::from_str(&content).context(format!("Failed to parse {}", path.display()))
+    }
+}
+
+/// Progress file of a running batch
+#[derive(Debug)]
+pub struct ProgressFile {
+    path: PathBuf,
+    run_id: Option<String>,
+    /// The most recent failure, its lock also keeping workers from writing at the same time
+    last_error: Mutex<Option<LastError>>,
+}
+
+impl ProgressFile {
+    /// Progress file of a run, when one is configured
+    ///
+    /// # Arguments
+    /// * `config` - Configuration of the run, the file being relative to its output directory
+    pub fn from_config(config: &Config) -> Option<Self> {
+        config.progress_file.as_ref().map(|file| Self {
+            path: Path::new(&config.output_dir).join(file),
+            run_id: config.run_id.clone(),
+            last_error: Mutex::new(None),
+        })
+    }
+
+    /// Path of the progress file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Write the progress of the run, remembering the error of a failed input
+    ///
+    /// Failing to write is only logged, the run carries on without the file.
+    ///
+    /// # Arguments
+    /// * `control` - Control of the run, holding its status
+    /// * `state` - State of the run
+    /// * `result` - Outcome of the input that just finished, if any
+    pub fn update(&self, control: &RunControl, state: ProgressState, result: Option<&ImageResult>) {
+        let mut last_error = self.last_error.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(result) = result
+            && let Some(error) = &result.error
+        {
+            *last_error = Some(LastError {
+                input: result.path.clone(),
+                error: error.clone(),
+            });
+        }
+        // The status is taken under the lock, so a newer status is never overwritten by an older one
+        let progress = Progress::from_status(&control.status(), state, self.run_id.clone(), last_error.clone());
+        if let Err(e) = self.write(&progress) {
+            warn!("{}", tr_args(Msg::ProgressWriteFailed, &[&format!("{:#}", e)]).yellow());
+        }
+    }
+
+    /// Replace the file with the progress through a temporary file
+    fn write(&self, progress: &Progress) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut temporary = self.path.clone().into_os_string();
+        temporary.push(".tmp");
+        fs::write(&temporary, serde_json::to_string_pretty(progress)?)
+            .context(format!("Failed to write {}", self.path.display()))?;
+        fs::rename(&temporary, &self.path).context(format!("Failed to replace {}", self.path.display()))
+    }
+}
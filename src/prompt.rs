@@ -0,0 +1,65 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+/**
+ * Confirmation questions for ControlNet Image Generator
+ *
+ * This module asks the user whether to go on, for example after the
+ * configuration failed validation. Unattended runs never wait for an answer:
+ * when `--non-interactive` is given or standard input is not a terminal,
+ * the configured policy answers instead.
+ */
+use std::io::{self, IsTerminal};
+use tracing::info;
+
+use crate::config::Config;
+use crate::i18n::{Msg, tr, tr_args};
+use crate::style::*;
+
+/// Answer given to confirmation questions when nobody can be asked
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "snake_case")]
+pub enum PromptPolicy {
+    /// Go on as if the question was answered with yes
+    #[default]
+    Continue,
+    /// Stop as if the question was answered with no
+    Abort,
+}
+
+/// Whether questions are answered by the policy instead of the user
+///
+/// Without the `cli` feature nobody is asked, as the program embedding the
+/// library owns standard input.
+pub fn is_non_interactive(config: &Config) -> bool {
+    !cfg!(feature = "cli") || config.non_interactive || !io::stdin().is_terminal()
+}
+
+/// Ask a yes or no question, defaulting to yes
+///
+/// `--yes` always answers yes. Otherwise, when running non-interactively,
+/// the configured prompt policy answers without reading standard input.
+///
+/// # Arguments
+/// * `config` - Configuration with the answering settings
+/// * `question` - Question to show, without the answer hint
+///
+/// # Returns
+/// Whether to go on
+pub fn confirm(config: &Config, question: &str) -> Result<bool> {
+    if config.assume_yes {
+        return Ok(true);
+    }
+    if is_non_interactive(config) {
+        let answer = config.prompt_policy == PromptPolicy::Continue;
+        let answer_text = tr(if answer { Msg::AnswerYes } else { Msg::AnswerNo });
+        info!("{} {}", question.yellow(), tr_args(Msg::AnsweredByPolicy, &[&answer_text]).blue());
+        return Ok(answer);
+    }
+
+    // Standard output may be reserved for the result document
+    eprintln!("{}", format!("{} (Y/n)", question).yellow());
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+    Ok(input.trim().is_empty() || input.trim().eq_ignore_ascii_case("y"))
+}
@@ -0,0 +1,485 @@
+use anyhow::{Context, Result};
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+/**
+ * Prompt sources for ControlNet Image Generator
+ *
+ * This module decides the prompt sent for each input. A `PromptSource` takes
+ * the prompt decided so far and returns the one to use, so the strategies
+ * listed under `prompt_sources` in the configuration apply one after another:
+ * the configured prompt is the start, sidecar files and caption files may
+ * replace it for single images, the words of file names can be added, the
+ * interrogator of the server can describe the input, and templates combine
+ * all of these with the name of the input.
+ */
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use tracing::debug;
+
+use crate::api::StableDiffusionClient;
+use crate::config::Config;
+use crate::sidecar::Sidecar;
+use crate::style::*;
+
+/// Prompts of one generation
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Prompt {
+    /// Positive prompt
+    pub prompt: String,
+    /// Negative prompt
+    pub negative_prompt: String,
+}
+
+impl Prompt {
+    /// Prompts set in the configuration
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            prompt: config.prompt.clone(),
+            negative_prompt: config.negative_prompt.clone(),
+        }
+    }
+}
+
+/// What a prompt source may use to decide the prompts of an input
+#[derive(Clone, Copy)]
+pub struct PromptContext<'a> {
+    /// Input image the prompts are for
+    pub image_path: &'a Path,
+    /// Client of the backend generating the images
+    pub client: &'a StableDiffusionClient,
+}
+
+/// One way of deciding the prompts of an input
+pub trait PromptSource: Send + Sync {
+    /// Name used in log messages
+    fn name(&self) -> &'static str;
+
+    /// Prompts to use for an input
+    ///
+    /// # Arguments
+    /// * `context` - The input and the backend generating it
+    /// * `prompt` - Prompts decided by the sources before this one
+    ///
+    /// # Returns
+    /// The prompts handed to the next source
+    fn prompt<'a>(&'a self, context: PromptContext<'a>, prompt: Prompt) -> BoxFuture<'a, Result<Prompt>>;
+}
+
+/// Prompt source as declared under `prompt_sources` in the configuration file
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum PromptSourceConfig {
+    /// The prompts of the configuration, replacing those of earlier sources
+    Static,
+    /// `prompt` and `negative_prompt` of the sidecar files of the input
+    Sidecar,
+    /// Caption file next to the input, `photo.txt` or `photo.caption` for `photo.png`
+    Caption {
+        /// How the caption becomes the prompt, `{prompt}` and `{caption}` are replaced
+        #[serde(default = "default_caption_template")]
+        template: String,
+        /// Extensions of caption files, the first one found being used
+        #[serde(default = "default_caption_extensions")]
+        extensions: Vec<String>,
+    },
+    /// Words of the file name of the input, `sunset beach` for `sunset_beach_03.jpg`
+    Filename {
+        /// Characters separating the words, besides whitespace
+        #[serde(default = "default_filename_separators")]
+        separators: String,
+        /// Whether words that are numbers are left out
+        #[serde(default = "default_strip_numbers")]
+        strip_numbers: bool,
+        /// How the words are combined with the prompt, `{prompt}` and `{words}` are replaced
+        #[serde(default = "default_filename_template")]
+        template: String,
+    },
+    /// Caption of the input from the interrogator of the server
+    Interrogate {
+        /// Interrogator to use, e.g. "clip" or "deepdanbooru"
+        #[serde(default = "default_interrogate_model")]
+        model: String,
+        /// How the caption is combined with the prompt, `{prompt}` and `{caption}` are replaced
+        #[serde(default = "default_interrogate_template")]
+        template: String,
+    },
+    /// Prompts filled in from templates
+    Template {
+        /// Template of the prompt, the prompt so far when unset
+        #[serde(default)]
+        prompt: Option<String>,
+        /// Template of the negative prompt, the negative prompt so far when unset
+        #[serde(default)]
+        negative_prompt: Option<String>,
+    },
+}
+
+/// Default use of a caption file - the caption as the prompt
+pub fn default_caption_template() -> String {
+    "{caption}".to_string()
+}
+
+/// Default extensions of caption files - `txt`, then `caption`
+pub fn default_caption_extensions() -> Vec<String> {
+    vec!["txt".to_string(), "caption".to_string()]
+}
+
+/// How the caption files of the inputs are used, as given with `--captions`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "snake_case")]
+pub enum CaptionMode {
+    /// The caption replaces the prompt
+    Replace,
+    /// The caption is added after the prompt
+    Append,
+}
+
+impl CaptionMode {
+    /// Caption source using the caption files this way
+    pub fn source(self) -> PromptSourceConfig {
+        let template = match self {
+            CaptionMode::Replace => default_caption_template(),
+            CaptionMode::Append => "{prompt}, {caption}".to_string(),
+        };
+        PromptSourceConfig::Caption {
+            template,
+            extensions: default_caption_extensions(),
+        }
+    }
+}
+
+/// Default separators of the words of a file name - underscores and hyphens
+pub fn default_filename_separators() -> String {
+    "_-".to_string()
+}
+
+/// Default handling of numbers in file names - left out
+pub fn default_strip_numbers() -> bool {
+    true
+}
+
+/// Default combination of file name words and prompt - the words after the prompt
+pub fn default_filename_template() -> String {
+    "{prompt}, {words}".to_string()
+}
+
+/// Default interrogator - "clip"
+pub fn default_interrogate_model() -> String {
+    "clip".to_string()
+}
+
+/// Default combination of caption and prompt - the caption after the prompt
+pub fn default_interrogate_template() -> String {
+    "{prompt}, {caption}".to_string()
+}
+
+impl PromptSourceConfig {
+    /// Source described by this entry
+    ///
+    /// # Arguments
+    /// * `config` - Configuration with the prompts the static source uses
+    pub fn build(&self, config: &Config) -> Box<dyn PromptSource> {
+        match self {
+            PromptSourceConfig::Static => Box::new(StaticPrompt(Prompt::from_config(config))),
+            PromptSourceConfig::Sidecar => Box::new(SidecarPrompt),
+            PromptSourceConfig::Caption { template, extensions } => Box::new(CaptionPrompt {
+                template: template.clone(),
+                extensions: extensions.clone(),
+            }),
+            PromptSourceConfig::Filename {
+                separators,
+                strip_numbers,
+                template,
+            } => Box::new(FilenamePrompt {
+                separators: separators.clone(),
+                strip_numbers: *strip_numbers,
+                template: template.clone(),
+            }),
+            PromptSourceConfig::Interrogate { model, template } => Box::new(InterrogatePrompt {
+                model: model.clone(),
+                template: template.clone(),
+            }),
+            PromptSourceConfig::Template {
+                prompt,
+                negative_prompt,
+            } => Box::new(TemplatePrompt {
+                prompt: prompt.clone(),
+                negative_prompt: negative_prompt.clone(),
+            }),
+        }
+    }
+}
+
+/// Always the same prompts
+#[derive(Debug, Clone)]
+pub struct StaticPrompt(pub Prompt);
+
+impl PromptSource for StaticPrompt {
+    fn name(&self) -> &'static str {
+        "static"
+    }
+
+    fn prompt<'a>(&'a self, _context: PromptContext<'a>, _prompt: Prompt) -> BoxFuture<'a, Result<Prompt>> {
+        Box::pin(async move { Ok(self.0.clone()) })
+    }
+}
+
+/// Prompts set in the sidecar files of an input, the prompts so far otherwise
+#[derive(Debug, Clone, Copy)]
+pub struct SidecarPrompt;
+
+impl PromptSource for SidecarPrompt {
+    fn name(&self) -> &'static str {
+        "sidecar"
+    }
+
+    fn prompt<'a>(&'a self, context: PromptContext<'a>, prompt: Prompt) -> BoxFuture<'a, Result<Prompt>> {
+        Box::pin(async move {
+            let sidecar = Sidecar::load_for(context.image_path)?;
+            Ok(Prompt {
+                prompt: sidecar.prompt.unwrap_or(prompt.prompt),
+                negative_prompt: sidecar.negative_prompt.unwrap_or(prompt.negative_prompt),
+            })
+        })
+    }
+}
+
+/// Prompt from the caption file of an input, the prompt so far when it has none
+///
+/// Captioned datasets keep the caption of `photo.png` in `photo.txt` or `photo.caption`.
+#[derive(Debug, Clone)]
+pub struct CaptionPrompt {
+    /// How the caption becomes the prompt
+    pub template: String,
+    /// Extensions of caption files, in the order they are looked for
+    pub extensions: Vec<String>,
+}
+
+impl PromptSource for CaptionPrompt {
+    fn name(&self) -> &'static str {
+        "caption"
+    }
+
+    fn prompt<'a>(&'a self, context: PromptContext<'a>, prompt: Prompt) -> BoxFuture<'a, Result<Prompt>> {
+        Box::pin(async move {
+            let Some(caption_path) = self
+                .extensions
+                .iter()
+                .map(|extension| context.image_path.with_extension(extension))
+                .find(|path| path.is_file())
+            else {
+                return Ok(prompt);
+            };
+            let caption = fs::read_to_string(&caption_path)
+                .context(format!("Failed to read caption file: {}", caption_path.display()))?;
+            Ok(Prompt {
+                prompt: render(&self.template, &[("prompt", &prompt.prompt), ("caption", caption.trim())]),
+                negative_prompt: prompt.negative_prompt,
+            })
+        })
+    }
+}
+
+/// Adds the words of the file name of an input
+#[derive(Debug, Clone)]
+pub struct FilenamePrompt {
+    /// Characters separating the words, besides whitespace
+    pub separators: String,
+    /// Whether words that are numbers are left out
+    pub strip_numbers: bool,
+    /// How the words are combined with the prompt
+    pub template: String,
+}
+
+impl FilenamePrompt {
+    /// Words of a file name without its extension, joined with spaces
+    pub fn words(&self, file_stem: &str) -> String {
+        file_stem
+            .split(|c: char| c.is_whitespace() || self.separators.contains(c))
+            .filter(|word| !word.is_empty())
+            .filter(|word| !(self.strip_numbers && word.chars().all(|c| c.is_ascii_digit())))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+impl PromptSource for FilenamePrompt {
+    fn name(&self) -> &'static str {
+        "filename"
+    }
+
+    fn prompt<'a>(&'a self, context: PromptContext<'a>, prompt: Prompt) -> BoxFuture<'a, Result<Prompt>> {
+        Box::pin(async move {
+            let words = self.words(&file_part(context.image_path.file_stem()));
+            Ok(Prompt {
+                prompt: render(&self.template, &[("prompt", &prompt.prompt), ("words", &words)]),
+                negative_prompt: prompt.negative_prompt,
+            })
+        })
+    }
+}
+
+/// Adds a caption of the input from the interrogator of the server
+#[derive(Debug, Clone)]
+pub struct InterrogatePrompt {
+    /// Interrogator to use
+    pub model: String,
+    /// How the caption is combined with the prompt
+    pub template: String,
+}
+
+impl PromptSource for InterrogatePrompt {
+    fn name(&self) -> &'static str {
+        "interrogate"
+    }
+
+    fn prompt<'a>(&'a self, context: PromptContext<'a>, prompt: Prompt) -> BoxFuture<'a, Result<Prompt>> {
+        Box::pin(async move {
+            let caption = context.client.interrogate(context.image_path, &self.model).await?;
+            debug!("{} {}", "Caption:".blue(), caption);
+            Ok(Prompt {
+                prompt: render(&self.template, &[("prompt", &prompt.prompt), ("caption", &caption)]),
+                negative_prompt: prompt.negative_prompt,
+            })
+        })
+    }
+}
+
+/// Fills the prompts in from templates
+///
+/// `{prompt}` and `{negative_prompt}` are replaced by the prompts so far,
+/// `{file_name}` and `{file_stem}` by the name of the input with and without
+/// extension, and `{dir}` by the name of the directory it is in.
+#[derive(Debug, Clone)]
+pub struct TemplatePrompt {
+    /// Template of the prompt, the prompt so far when unset
+    pub prompt: Option<String>,
+    /// Template of the negative prompt, the negative prompt so far when unset
+    pub negative_prompt: Option<String>,
+}
+
+impl PromptSource for TemplatePrompt {
+    fn name(&self) -> &'static str {
+        "template"
+    }
+
+    fn prompt<'a>(&'a self, context: PromptContext<'a>, prompt: Prompt) -> BoxFuture<'a, Result<Prompt>> {
+        Box::pin(async move {
+            let file_name = file_part(context.image_path.file_name());
+            let file_stem = file_part(context.image_path.file_stem());
+            let dir = file_part(context.image_path.parent().and_then(Path::file_name));
+            let values = [
+                ("prompt", prompt.prompt.as_str()),
+                ("negative_prompt", prompt.negative_prompt.as_str()),
+                ("file_name", file_name.as_str()),
+                ("file_stem", file_stem.as_str()),
+                ("dir", dir.as_str()),
+            ];
+            let fill = |template: &Option<String>, current: &str| match template {
+                Some(template) => render(template, &values),
+                None => current.to_string(),
+            };
+            Ok(Prompt {
+                prompt: fill(&self.prompt, &prompt.prompt),
+                negative_prompt: fill(&self.negative_prompt, &prompt.negative_prompt),
+            })
+        })
+    }
+}
+
+/// Decide the prompts of an input with the sources of the configuration
+///
+/// Without any sources the prompts of the configuration are used.
+///
+/// # Arguments
+/// * `config` - Configuration with the prompts and their sources
+/// * `context` - The input and the backend generating it
+///
+/// # Returns
+/// The prompts after every source had its turn
+pub async fn resolve(config: &Config, context: PromptContext<'_>) -> Result<Prompt> {
+    let mut prompt = Prompt::from_config(config);
+    for source in &config.prompt_sources {
+        let source = source.build(config);
+        prompt = source.prompt(context, prompt).await?;
+        debug!("{} {}: {}", "Prompt from".blue(), source.name(), prompt.prompt);
+    }
+    prompt.prompt = emphasize(&prompt.prompt, &config.emphasize);
+    Ok(prompt)
+}
+
+/// Weight of a term of the prompt, given as `TERM=WEIGHT` on the command line
+#[derive(Debug, Clone, PartialEq)]
+pub struct Emphasis {
+    /// Word or phrase of the prompt
+    pub term: String,
+    /// Attention weight, above 1 for more and below 1 for less
+    pub weight: f32,
+}
+
+impl FromStr for Emphasis {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        let (term, weight) = value
+            .rsplit_once('=')
+            .context(format!("Invalid emphasis '{}', expected TERM=WEIGHT", value))?;
+        let term = term.trim();
+        if term.is_empty() {
+            return Err(anyhow::anyhow!("No term given in '{}'", value));
+        }
+        let weight = weight
+            .trim()
+            .parse()
+            .context(format!("Invalid weight in '{}'", value))?;
+        Ok(Self {
+            term: term.to_string(),
+            weight,
+        })
+    }
+}
+
+/// Put the weights of terms into a prompt in the attention syntax of the Web UI
+///
+/// Every whole-word occurrence of a term, ignoring case, becomes
+/// `(term:weight)`. Terms the prompt does not contain are appended.
+///
+/// # Arguments
+/// * `prompt` - Prompt without weights
+/// * `weights` - Weight of each term
+///
+/// # Returns
+/// The prompt with the weights
+pub fn emphasize(prompt: &str, weights: &BTreeMap<String, f32>) -> String {
+    let mut prompt = prompt.to_string();
+    for (term, weight) in weights {
+        let pattern =
+            Regex::new(&format!(r"(?i)\b{}\b", regex::escape(term))).expect("an escaped term is a valid pattern");
+        if pattern.is_match(&prompt) {
+            prompt = pattern
+                .replace_all(&prompt, |found: &regex::Captures| format!("({}:{})", &found[0], weight))
+                .to_string();
+        } else if prompt.trim().is_empty() {
+            prompt = format!("({}:{})", term, weight);
+        } else {
+            prompt = format!("{}, ({}:{})", prompt.trim_end(), term, weight);
+        }
+    }
+    prompt
+}
+
+/// Replace the `{name}` placeholders of a template, leaving unknown ones as they are
+pub fn render(template: &str, values: &[(&str, &str)]) -> String {
+    values.iter().fold(template.to_string(), |text, (name, value)| {
+        text.replace(&format!("{{{}}}", name), value)
+    })
+}
+
+/// Lossy text of a path component, empty when missing
+fn file_part(part: Option<&std::ffi::OsStr>) -> String {
+    part.map(|part| part.to_string_lossy().to_string()).unwrap_or_default()
+}
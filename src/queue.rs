@@ -0,0 +1,421 @@
+use anyhow::{Context, Result};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{HashMap, VecDeque};
+/**
+ * Disk-backed job queue for ControlNet Image Generator
+ *
+ * This module replaces the plain in-memory list of input paths with a small
+ * persistent queue stored as a JSONL file. Every state change is appended as
+ * one line, so the file doubles as a log that can be replayed after a crash
+ * and appended to by other processes while a run is in progress.
+ */
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Default file name of the queue, stored inside the output directory
+pub const DEFAULT_QUEUE_FILE: &str = ".urasoe-queue.jsonl";
+
+/// Processing state of a single queued input
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    /// Waiting to be processed
+    Pending,
+    /// Currently being processed
+    InProgress,
+    /// Processed successfully
+    Done,
+    /// Processing failed after all retries
+    Failed,
+}
+
+/// One line of the queue file
+///
+/// The queue file is a log of these records. Replaying them in order gives
+/// the current state of every input, the last record for a path winning.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct QueueEntry {
+    /// Path to the input image
+    pub path: PathBuf,
+    /// Status of the input after this record
+    pub status: JobStatus,
+    /// Inputs with a higher priority are processed first, set when first enqueued
+    #[serde(default, skip_serializing_if = "is_default_priority")]
+    pub priority: i32,
+}
+
+fn is_default_priority(priority: &i32) -> bool {
+    *priority == 0
+}
+
+/// Persistent queue of input images
+///
+/// Entries keep the order in which they were first enqueued, and pending
+/// entries are handed out highest priority first in that order. The queue picks
+/// up records appended to its file by other processes whenever the next
+/// pending entry is requested, so other tools can feed a running generation
+/// loop.
+pub struct JobQueue {
+    /// Location of the JSONL queue file
+    file_path: PathBuf,
+    /// Current state of every known input, in enqueue order
+    entries: Vec<QueueEntry>,
+    /// Index of each path within `entries`
+    index: HashMap<PathBuf, usize>,
+    /// Byte offset up to which the queue file has been read
+    read_offset: u64,
+}
+
+impl JobQueue {
+    /// Create a new, empty queue, truncating any existing queue file
+    ///
+    /// # Arguments
+    /// * `file_path` - Path of the JSONL file backing the queue
+    ///
+    /// # Returns
+    /// A Result containing the empty JobQueue
+    pub fn create<P: AsRef<Path>>(file_path: P) -> Result<Self> {
+        let file_path = file_path.as_ref().to_path_buf();
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent).context("Failed to create queue directory")?;
+        }
+        File::create(&file_path)
+            .context(format!("Failed to create queue file: {}", file_path.display()))?;
+
+        Ok(Self {
+            file_path,
+            entries: Vec::new(),
+            index: HashMap::new(),
+            read_offset: 0,
+        })
+    }
+
+    /// Open an existing queue file, replaying its records
+    ///
+    /// Creates an empty queue file if none exists yet. Entries that were
+    /// in progress when the previous process stopped are treated as pending.
+    ///
+    /// # Arguments
+    /// * `file_path` - Path of the JSONL file backing the queue
+    ///
+    /// # Returns
+    /// A Result containing the restored JobQueue
+    pub fn open<P: AsRef<Path>>(file_path: P) -> Result<Self> {
+        let file_path = file_path.as_ref().to_path_buf();
+        if !file_path.exists() {
+            return Self::create(&file_path);
+        }
+
+        let mut queue = Self {
+            file_path,
+            entries: Vec::new(),
+            index: HashMap::new(),
+            read_offset: 0,
+        };
+        queue.reload()?;
+
+        for entry in queue.entries.iter_mut() {
+            if entry.status == JobStatus::InProgress {
+                entry.status = JobStatus::Pending;
+            }
+        }
+
+        Ok(queue)
+    }
+
+    /// Whether a queue file holds inputs that its run stopped before processing or failed
+    ///
+    /// Failed inputs that no longer exist, e.g. because they were moved into
+    /// the dead-letter folder, do not count.
+    ///
+    /// # Arguments
+    /// * `file_path` - Path of the JSONL file backing the queue
+    pub fn is_unfinished<P: AsRef<Path>>(file_path: P) -> Result<bool> {
+        let file_path = file_path.as_ref();
+        if !file_path.exists() {
+            return Ok(false);
+        }
+        // Inputs in progress when the run stopped are pending again once opened
+        let queue = Self::open(file_path)?;
+        Ok(queue.count(JobStatus::Pending) > 0 || queue.retryable().next().is_some())
+    }
+
+    /// Failed inputs that still exist and can be tried again
+    fn retryable(&self) -> impl Iterator<Item = &QueueEntry> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.status == JobStatus::Failed && entry.path.exists())
+    }
+
+    /// Queue the inputs that failed for another try
+    ///
+    /// Failed inputs that no longer exist stay failed.
+    ///
+    /// # Returns
+    /// Number of inputs queued again
+    pub fn retry_failed(&mut self) -> Result<usize> {
+        self.reload()?;
+        let failed: Vec<PathBuf> = self.retryable().map(|entry| entry.path.clone()).collect();
+        for path in &failed {
+            self.record(path, JobStatus::Pending)?;
+        }
+        Ok(failed.len())
+    }
+
+    /// Path of the file backing this queue
+    pub fn file_path(&self) -> &Path {
+        &self.file_path
+    }
+
+    /// Add an input to the queue as pending
+    ///
+    /// Inputs that are already known keep their current state.
+    ///
+    /// # Returns
+    /// `true` if the input was newly added
+    pub fn enqueue<P: AsRef<Path>>(&mut self, path: P) -> Result<bool> {
+        self.enqueue_with_priority(path, 0)
+    }
+
+    /// Add an input to the queue as pending with the given priority
+    ///
+    /// Inputs that are already known keep their current state and priority.
+    ///
+    /// # Returns
+    /// `true` if the input was newly added
+    pub fn enqueue_with_priority<P: AsRef<Path>>(&mut self, path: P, priority: i32) -> Result<bool> {
+        self.reload()?;
+        let path = path.as_ref();
+        if self.index.contains_key(path) {
+            return Ok(false);
+        }
+        self.write(QueueEntry {
+            path: path.to_path_buf(),
+            status: JobStatus::Pending,
+            priority,
+        })?;
+        Ok(true)
+    }
+
+    /// Add several inputs to the queue, returning how many were newly added
+    pub fn enqueue_all<P: AsRef<Path>>(&mut self, paths: &[P]) -> Result<usize> {
+        let mut added = 0;
+        for path in paths {
+            if self.enqueue(path)? {
+                added += 1;
+            }
+        }
+        Ok(added)
+    }
+
+    /// Append a record to a queue file without opening the queue
+    ///
+    /// Used by producers in other processes to feed a running queue, such as
+    /// `urasoe enqueue`. The running queue picks the record up before handing
+    /// out its next input, so a higher priority jumps ahead of the backlog.
+    ///
+    /// # Arguments
+    /// * `file_path` - Path of the JSONL file backing the queue
+    /// * `path` - Input to add as pending
+    /// * `priority` - Priority of the input, higher is processed first
+    pub fn append_to<P: AsRef<Path>, Q: AsRef<Path>>(file_path: P, path: Q, priority: i32) -> Result<()> {
+        let entry = QueueEntry {
+            path: path.as_ref().to_path_buf(),
+            status: JobStatus::Pending,
+            priority,
+        };
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(file_path.as_ref())
+            .context("Failed to open queue file for appending")?;
+        append_record(&mut file, &entry)
+    }
+
+    /// Take the next pending input and mark it as in progress
+    ///
+    /// The input with the highest priority is taken, the earliest enqueued
+    /// one among equal priorities. Records appended to the queue file since
+    /// the last call are picked up first.
+    ///
+    /// # Returns
+    /// The path of the next input, or None when nothing is pending
+    pub fn next_pending(&mut self) -> Result<Option<PathBuf>> {
+        self.reload()?;
+        let next = self
+            .entries
+            .iter()
+            .filter(|entry| entry.status == JobStatus::Pending)
+            .min_by_key(|entry| Reverse(entry.priority))
+            .map(|entry| entry.path.clone());
+
+        if let Some(path) = &next {
+            self.record(path, JobStatus::InProgress)?;
+        }
+        Ok(next)
+    }
+
+    /// Mark an input as successfully processed
+    pub fn mark_done<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        self.record(path.as_ref(), JobStatus::Done)
+    }
+
+    /// Put an input taken by `next_pending` back to pending, for a later run to take
+    pub fn release<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        self.record(path.as_ref(), JobStatus::Pending)
+    }
+
+    /// Mark an input as failed
+    pub fn mark_failed<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        self.record(path.as_ref(), JobStatus::Failed)
+    }
+
+    /// Current status of an input, if it is known to the queue
+    pub fn status<P: AsRef<Path>>(&self, path: P) -> Option<JobStatus> {
+        self.index
+            .get(path.as_ref())
+            .map(|&position| self.entries[position].status)
+    }
+
+    /// All entries in enqueue order
+    pub fn entries(&self) -> &[QueueEntry] {
+        &self.entries
+    }
+
+    /// Total number of inputs known to the queue
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether the queue has no inputs at all
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Number of inputs with the given status
+    pub fn count(&self, status: JobStatus) -> usize {
+        self.entries
+            .iter()
+            .filter(|entry| entry.status == status)
+            .count()
+    }
+
+    /// Append a status change to the file and apply it in memory
+    fn record(&mut self, path: &Path, status: JobStatus) -> Result<()> {
+        let priority = self
+            .index
+            .get(path)
+            .map_or(0, |&position| self.entries[position].priority);
+        self.write(QueueEntry {
+            path: path.to_path_buf(),
+            status,
+            priority,
+        })
+    }
+
+    /// Append a record to the file and apply it in memory
+    fn write(&mut self, entry: QueueEntry) -> Result<()> {
+        // Catch up with other writers first so their records are not skipped
+        self.reload()?;
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(&self.file_path)
+            .context("Failed to open queue file for appending")?;
+        append_record(&mut file, &entry)?;
+        // Read the record back with anything other writers appended around it
+        self.reload()
+    }
+
+    /// Read records appended to the queue file since the last read
+    fn reload(&mut self) -> Result<()> {
+        let mut file = match File::open(&self.file_path) {
+            Ok(file) => file,
+            Err(_) => return Ok(()),
+        };
+        file.seek(SeekFrom::Start(self.read_offset))?;
+
+        let mut reader = BufReader::new(file);
+        let mut line = String::new();
+        loop {
+            line.clear();
+            let read = reader.read_line(&mut line)?;
+            // Stop at EOF or at a partially written trailing line
+            if read == 0 || !line.ends_with('\n') {
+                break;
+            }
+            self.read_offset += read as u64;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry: QueueEntry = serde_json::from_str(line.trim())
+                .context(format!("Invalid queue record: {}", line.trim()))?;
+            self.apply(entry);
+        }
+        Ok(())
+    }
+
+    /// Apply a record to the in-memory state
+    fn apply(&mut self, entry: QueueEntry) {
+        match self.index.get(&entry.path) {
+            Some(&position) => self.entries[position].status = entry.status,
+            None => {
+                self.index.insert(entry.path.clone(), self.entries.len());
+                self.entries.push(entry);
+            }
+        }
+    }
+}
+
+/// Append a record as one line in a single write, so lines of other writers do not interleave with it
+fn append_record(file: &mut File, entry: &QueueEntry) -> Result<()> {
+    let line = format!("{}\n", serde_json::to_string(entry)?);
+    file.write_all(line.as_bytes()).context("Failed to append to queue file")
+}
+
+/// Arrange inputs in the order they should be queued
+///
+/// Inputs are sorted by path, then optionally shuffled with a seed, so the
+/// same seed always gives the same order. Stratified ordering interleaves
+/// inputs from different directories round-robin, so a partial run still
+/// covers every part of the library.
+///
+/// # Arguments
+/// * `paths` - Inputs to arrange
+/// * `shuffle_seed` - Seed for shuffling, `None` keeps the sorted order
+/// * `stratified` - Interleave inputs by their parent directory
+///
+/// # Returns
+/// The inputs in queue order
+pub fn order_inputs(mut paths: Vec<PathBuf>, shuffle_seed: Option<u64>, stratified: bool) -> Vec<PathBuf> {
+    paths.sort();
+    if let Some(seed) = shuffle_seed {
+        paths.shuffle(&mut StdRng::seed_from_u64(seed));
+    }
+    if !stratified {
+        return paths;
+    }
+
+    // Group by directory in order of first appearance, keeping the order within groups
+    let mut groups: Vec<(PathBuf, VecDeque<PathBuf>)> = Vec::new();
+    for path in paths {
+        let parent = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        match groups.iter_mut().find(|(directory, _)| *directory == parent) {
+            Some((_, group)) => group.push_back(path),
+            None => groups.push((parent, VecDeque::from([path]))),
+        }
+    }
+
+    let mut ordered = Vec::new();
+    while !groups.is_empty() {
+        for (_, group) in groups.iter_mut() {
+            ordered.extend(group.pop_front());
+        }
+        groups.retain(|(_, group)| !group.is_empty());
+    }
+    ordered
+}
@@ -0,0 +1,157 @@
+use serde::{Deserialize, Serialize};
+/**
+ * Soft-start batch size ramp for ControlNet Image Generator
+ *
+ * Instead of asking for the full batch size from the first image, each
+ * backend starts with one image per request and doubles the batch size after
+ * a few successful inputs until the configured size is reached. A batch size
+ * the GPU cannot take then fails on a cheap request early in the run, after
+ * which the backend stays at the last size that worked. The time per image is
+ * watched as well, since a batch that no longer fits into VRAM often gets
+ * much slower before it fails outright.
+ */
+use tracing::{info, warn};
+
+use crate::i18n::{Msg, tr_args};
+use crate::style::*;
+
+/// Settings of the batch size ramp
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RampConfig {
+    /// Whether to start with a batch size of 1 and ramp up to batch_size
+    #[serde(default)]
+    pub enabled: bool,
+    /// Successful inputs needed at a batch size before doubling it
+    #[serde(default = "default_images_per_step")]
+    pub images_per_step: u32,
+    /// How many times slower per image a larger batch may be before the ramp stops
+    #[serde(default = "default_max_latency_factor")]
+    pub max_latency_factor: f64,
+}
+
+impl Default for RampConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            images_per_step: default_images_per_step(),
+            max_latency_factor: default_max_latency_factor(),
+        }
+    }
+}
+
+/// Default inputs per ramp step - 2
+pub fn default_images_per_step() -> u32 {
+    2
+}
+
+/// Default latency limit of the ramp - 1.5 times the time per image of the previous size
+pub fn default_max_latency_factor() -> f64 {
+    1.5
+}
+
+/// Batch size of one backend while ramping up
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchRamp {
+    /// Batch size to reach
+    target: u32,
+    /// Batch size of the next request
+    current: u32,
+    /// Last batch size that worked, with its average time per image in milliseconds
+    previous: Option<(u32, f64)>,
+    /// Successful inputs at the current batch size
+    successes: u32,
+    /// Time per image summed over those inputs, in milliseconds
+    per_image_ms: f64,
+    /// Whether the ramp stopped below the target
+    held: bool,
+    images_per_step: u32,
+    max_latency_factor: f64,
+}
+
+impl BatchRamp {
+    /// Start a ramp towards the configured batch size
+    ///
+    /// Without the ramp enabled, the batch size is the target from the start.
+    ///
+    /// # Arguments
+    /// * `config` - Settings of the ramp
+    /// * `target` - Configured batch size
+    pub fn new(config: &RampConfig, target: u32) -> Self {
+        let target = target.max(1);
+        Self {
+            target,
+            current: if config.enabled { 1 } else { target },
+            previous: None,
+            successes: 0,
+            per_image_ms: 0.0,
+            held: false,
+            images_per_step: config.images_per_step.max(1),
+            max_latency_factor: config.max_latency_factor,
+        }
+    }
+
+    /// Batch size to use for the next input
+    pub fn batch_size(&self) -> u32 {
+        self.current
+    }
+
+    /// Whether the batch size no longer changes
+    pub fn is_settled(&self) -> bool {
+        self.held || self.current == self.target
+    }
+
+    /// Record the outcome of an input generated at the current batch size
+    ///
+    /// A failure or a time per image above the limit ends the ramp at the
+    /// previous batch size, enough successful inputs double the batch size.
+    ///
+    /// # Arguments
+    /// * `success` - Whether the input succeeded
+    /// * `generated` - Number of images generated for the input
+    /// * `generation_ms` - Time spent in generation requests
+    pub fn record(&mut self, success: bool, generated: usize, generation_ms: u64) {
+        if self.is_settled() {
+            return;
+        }
+        if !success {
+            if let Some((previous, _)) = self.previous {
+                warn!("{}", tr_args(Msg::RampFailed, &[&self.current, &previous]).yellow());
+                self.hold(previous);
+            }
+            return;
+        }
+
+        self.successes += 1;
+        self.per_image_ms += generation_ms as f64 / generated.max(1) as f64;
+        if self.successes < self.images_per_step {
+            return;
+        }
+        let average = self.per_image_ms / f64::from(self.successes);
+        if let Some((previous, previous_ms)) = self.previous
+            && average > previous_ms * self.max_latency_factor
+        {
+            warn!(
+                "{}",
+                tr_args(
+                    Msg::RampSlower,
+                    &[&self.current, &format!("{:.0}", average), &format!("{:.0}", previous_ms), &previous]
+                )
+                .yellow()
+            );
+            self.hold(previous);
+            return;
+        }
+
+        self.previous = Some((self.current, average));
+        self.current = (self.current * 2).min(self.target);
+        self.successes = 0;
+        self.per_image_ms = 0.0;
+        info!("{}", tr_args(Msg::RampingUp, &[&self.current]).blue());
+    }
+
+    /// End the ramp at a batch size
+    fn hold(&mut self, batch_size: u32) {
+        self.current = batch_size;
+        self.held = true;
+    }
+}
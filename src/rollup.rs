@@ -0,0 +1,192 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+/**
+ * Metadata rollup for ControlNet Image Generator
+ *
+ * This module sums up what an output directory holds from the metadata files
+ * written next to the generated images: how many inputs and images each
+ * checkpoint, ControlNet model, prompt and day produced and how much disk
+ * space their images take. It only needs the metadata files, so it also
+ * works for runs made before run manifests were written.
+ */
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::file_utils::find_metadata;
+use crate::history::{generated_images, shorten};
+use crate::processing::csv_field;
+
+/// Column headers of the rollup table
+pub const TABLE_HEADERS: [&str; 5] = ["GROUP", "NAME", "INPUTS", "IMAGES", "SIZE"];
+
+/// Most characters of a prompt shown in the rollup table
+const PROMPT_WIDTH: usize = 50;
+
+/// Inputs and images of one checkpoint, model, prompt or day
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct RollupCount {
+    /// Inputs generated
+    pub inputs: usize,
+    /// Images generated for them
+    pub images: usize,
+    /// Size of those images, in bytes
+    pub bytes: u64,
+}
+
+impl RollupCount {
+    fn add(&mut self, images: usize, bytes: u64) {
+        self.inputs += 1;
+        self.images += images;
+        self.bytes += bytes;
+    }
+}
+
+/// Summary of the generations recorded in an output directory
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct Rollup {
+    /// Every generation together
+    pub total: RollupCount,
+    /// Size of everything in the output directory, including metadata, queue and failed inputs, in bytes
+    pub disk_usage: u64,
+    /// Generations by checkpoint
+    pub checkpoints: BTreeMap<String, RollupCount>,
+    /// Generations by ControlNet model
+    pub controlnet_models: BTreeMap<String, RollupCount>,
+    /// Generations by prompt
+    pub prompts: BTreeMap<String, RollupCount>,
+    /// Generations by day, `YYYY-MM-DD`, those without a timestamp under `unknown`
+    pub dates: BTreeMap<String, RollupCount>,
+}
+
+impl Rollup {
+    /// Sum up the metadata files of an output directory, preset and run subdirectories included
+    ///
+    /// # Arguments
+    /// * `output_dir` - Output directory of the runs
+    pub fn scan(output_dir: &Path) -> Result<Self> {
+        if !output_dir.is_dir() {
+            anyhow::bail!("{} is not a directory", output_dir.display());
+        }
+        let mut rollup = Rollup {
+            disk_usage: disk_usage(output_dir),
+            ..Rollup::default()
+        };
+        for (path, metadata) in find_metadata(output_dir)? {
+            let images = generated_images(&path);
+            let bytes = images
+                .iter()
+                .filter_map(|image| fs::metadata(image).ok())
+                .map(|file| file.len())
+                .sum();
+            let date = metadata.timestamp.get(..10).unwrap_or("unknown").to_string();
+            let mut controlnet_models: Vec<String> = metadata
+                .controlnet_units
+                .iter()
+                .map(|unit| unit.model.clone())
+                .collect();
+            if controlnet_models.is_empty() {
+                controlnet_models.push(metadata.controlnet_model.clone());
+            }
+
+            rollup.total.add(images.len(), bytes);
+            rollup.checkpoints.entry(metadata.checkpoint_model).or_default().add(images.len(), bytes);
+            for model in controlnet_models {
+                rollup.controlnet_models.entry(model).or_default().add(images.len(), bytes);
+            }
+            rollup.prompts.entry(metadata.prompt).or_default().add(images.len(), bytes);
+            rollup.dates.entry(date).or_default().add(images.len(), bytes);
+        }
+        Ok(rollup)
+    }
+
+    /// Group, name and count of every line of the summary, the total first
+    fn lines(&self) -> Vec<(&'static str, &str, &RollupCount)> {
+        let mut lines = vec![("total", "", &self.total)];
+        let groups = [
+            ("checkpoint", &self.checkpoints),
+            ("controlnet", &self.controlnet_models),
+            ("prompt", &self.prompts),
+            ("date", &self.dates),
+        ];
+        for (group, counts) in groups {
+            lines.extend(counts.iter().map(|(name, count)| (group, name.as_str(), count)));
+        }
+        lines
+    }
+
+    /// Cells of the rollup table, in the order of `TABLE_HEADERS`
+    pub fn rows(&self) -> Vec<Vec<String>> {
+        let mut rows: Vec<Vec<String>> = self
+            .lines()
+            .into_iter()
+            .map(|(group, name, count)| {
+                vec![
+                    group.to_string(),
+                    shorten(name, PROMPT_WIDTH),
+                    count.inputs.to_string(),
+                    count.images.to_string(),
+                    format_bytes(count.bytes),
+                ]
+            })
+            .collect();
+        rows.push(vec![
+            "disk".to_string(),
+            String::new(),
+            String::new(),
+            String::new(),
+            format_bytes(self.disk_usage),
+        ]);
+        rows
+    }
+
+    /// Serialize the summary as CSV, one row per group and name, sizes in bytes
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("group,name,inputs,images,bytes\n");
+        for (group, name, count) in self.lines() {
+            csv.push_str(&format!(
+                "{},{},{},{},{}\n",
+                group,
+                csv_field(name),
+                count.inputs,
+                count.images,
+                count.bytes
+            ));
+        }
+        csv.push_str(&format!("disk,,,,{}\n", self.disk_usage));
+        csv
+    }
+
+    /// Write the summary to a file, as CSV when the extension is `.csv` and JSON otherwise
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let is_csv = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("csv"));
+        let content = if is_csv { self.to_csv() } else { serde_json::to_string_pretty(self)? };
+        fs::write(path, content).context(format!("Failed to write {}", path.display()))
+    }
+}
+
+/// Size of the files below a directory together, in bytes
+fn disk_usage(dir: &Path) -> u64 {
+    fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => disk_usage(&entry.path()),
+            _ => entry.metadata().map(|metadata| metadata.len()).unwrap_or_default(),
+        })
+        .sum()
+}
+
+/// Size in bytes for people, e.g. `1.2 GB`
+fn format_bytes(bytes: u64) -> String {
+    match bytes {
+        0..1_000 => format!("{} B", bytes),
+        1_000..1_000_000 => format!("{:.1} kB", bytes as f64 / 1_000.0),
+        1_000_000..1_000_000_000 => format!("{:.1} MB", bytes as f64 / 1_000_000.0),
+        _ => format!("{:.1} GB", bytes as f64 / 1_000_000_000.0),
+    }
+}
@@ -0,0 +1,181 @@
+use anyhow::{Context, Result};
+use chrono::Utc;
+/**
+ * Run directories for ControlNet Image Generator
+ *
+ * Every run has an identifier, the start time followed by a short random
+ * hash, which is sent with its requests and recorded in the metadata of its
+ * images. With run directories each run also gets a folder of its own in the
+ * output directory, named after the identifier, holding its images, job
+ * queue, a snapshot of its configuration, a manifest and its log.
+ */
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+use crate::config::Config;
+use crate::http;
+use crate::i18n::{Msg, tr_args};
+use crate::logging;
+use crate::processing::ProcessingStats;
+use crate::style::*;
+
+/// Name of the manifest file of a run directory
+pub const MANIFEST_FILE: &str = "run.json";
+
+/// Name of the configuration snapshot of a run directory
+pub const CONFIG_SNAPSHOT_FILE: &str = "config.yml";
+
+/// Name of the log file of a run directory, one JSON object per line
+pub const LOG_FILE: &str = "run.log";
+
+/// Configuration of one run, with its identifier decided
+///
+/// The configured identifier is kept, otherwise a new one is generated. With
+/// run directories the output directory becomes the directory of the run, so
+/// resuming a run takes the identifier of the earlier run.
+///
+/// # Arguments
+/// * `config` - Configuration of the run
+pub fn prepare(config: &Config) -> Config {
+    let run_id = config.run_id.clone().unwrap_or_else(http::new_run_id);
+    let output_dir = if config.run_dirs {
+        Path::new(&config.output_dir).join(&run_id).to_string_lossy().to_string()
+    } else {
+        config.output_dir.clone()
+    };
+    Config {
+        run_id: Some(run_id),
+        output_dir,
+        ..config.clone()
+    }
+}
+
+/// What a run directory holds, written to its `run.json`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RunManifest {
+    /// Identifier of the run, also the name of its directory
+    pub run_id: String,
+    /// Time the run started
+    pub started: String,
+    /// Time the run finished, `None` while it runs or when it was interrupted
+    #[serde(default)]
+    pub finished: Option<String>,
+    /// Directory of the inputs
+    pub input_dir: String,
+    /// Number of inputs of the run
+    pub inputs: usize,
+    /// Inputs processed successfully
+    #[serde(default)]
+    pub succeeded: usize,
+    /// Inputs that failed
+    #[serde(default)]
+    pub failed: usize,
+    /// Images generated
+    #[serde(default)]
+    pub generated: usize,
+}
+
+impl RunManifest {
+    /// Start a run in its directory
+    ///
+    /// Writes the configuration snapshot and the manifest, and copies the log
+    /// of the run into the log file of the directory until it is closed with
+    /// `logging::set_log_file(None)`.
+    ///
+    /// # Arguments
+    /// * `config` - Configuration of the run, as returned by `prepare`
+    /// * `inputs` - Number of inputs of the run
+    pub fn start(config: &Config, inputs: usize) -> Result<Self> {
+        let dir = Path::new(&config.output_dir);
+        fs::create_dir_all(dir).context(format!("Failed to create run directory {}", dir.display()))?;
+        // The snapshot is kept next to the images, so it leaves out the API and broker credentials
+        let mut snapshot = config.clone();
+        snapshot.api_password = None;
+        snapshot.api_token = None;
+        snapshot.mqtt.password = None;
+        let snapshot = serde_yaml::to_string(&snapshot).context("Failed to serialize the configuration")?;
+        fs::write(dir.join(CONFIG_SNAPSHOT_FILE), snapshot).context("Failed to write the configuration snapshot")?;
+        logging::set_log_file(Some(&dir.join(LOG_FILE))).context("Failed to open the run log")?;
+
+        let manifest = Self {
+            run_id: config.run_id.clone().unwrap_or_default(),
+            started: Utc::now().to_rfc3339(),
+            finished: None,
+            input_dir: config.input_dir.clone(),
+            inputs,
+            succeeded: 0,
+            failed: 0,
+            generated: 0,
+        };
+        manifest.write(dir)?;
+        info!("{}", tr_args(Msg::RunDirectory, &[&dir.display()]).blue());
+        Ok(manifest)
+    }
+
+    /// Record the outcome of the run
+    ///
+    /// # Arguments
+    /// * `dir` - Directory of the run
+    /// * `stats` - Statistics of the finished run
+    pub fn finish(mut self, dir: &Path, stats: &ProcessingStats) -> Result<Self> {
+        self.finished = Some(Utc::now().to_rfc3339());
+        self.record(dir, stats)
+    }
+
+    /// Record the outcome of an aborted run, leaving it unfinished so it can be resumed
+    ///
+    /// # Arguments
+    /// * `dir` - Directory of the run
+    /// * `stats` - Statistics of the run so far
+    pub fn abort(self, dir: &Path, stats: &ProcessingStats) -> Result<Self> {
+        self.record(dir, stats)
+    }
+
+    fn record(mut self, dir: &Path, stats: &ProcessingStats) -> Result<Self> {
+        self.succeeded = stats.success_count;
+        self.failed = stats.failed_paths.len();
+        self.generated = stats.generated_count;
+        self.write(dir)?;
+        Ok(self)
+    }
+
+    /// Read the manifest of a run directory
+    ///
+    /// # Arguments
+    /// * `dir` - Directory of the run
+    pub fn read(dir: &Path) -> Result<Self> {
+        let path = dir.join(MANIFEST_FILE);
+        let content = fs::read_to_string(&path).context(format!("Failed to read {}", path.display()))?;
+        serde_json::from_str(&content).context(format!("Failed to parse {}", path.display()))
+    }
+
+    fn write(&self, dir: &Path) -> Result<()> {
+        let path = dir.join(MANIFEST_FILE);
+        let content = serde_json::to_string_pretty(self)?;
+        fs::write(&path, content).context(format!("Failed to write {}", path.display()))
+    }
+}
+
+/// Run directories of an output directory, those holding a manifest
+///
+/// # Arguments
+/// * `output_dir` - Output directory the runs were made in
+///
+/// # Returns
+/// Directory and manifest of each run, oldest first
+pub fn list(output_dir: &Path) -> Result<Vec<(PathBuf, RunManifest)>> {
+    let mut runs = Vec::new();
+    if !output_dir.is_dir() {
+        return Ok(runs);
+    }
+    for entry in fs::read_dir(output_dir).context(format!("Failed to read {}", output_dir.display()))?.flatten() {
+        let path = entry.path();
+        if path.join(MANIFEST_FILE).is_file() {
+            runs.push((path.clone(), RunManifest::read(&path)?));
+        }
+    }
+    runs.sort_by(|a, b| a.1.started.cmp(&b.1.started));
+    Ok(runs)
+}
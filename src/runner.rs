@@ -0,0 +1,889 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+/**
+ * Batch runner for ControlNet Image Generator
+ *
+ * This module runs one batch from start to finish: it lists the input images,
+ * loads the checkpoint, works through the job queue with retries and batch
+ * breaks, and reports the statistics. It is shared by the one-off command
+ * line run and the daemon processing spooled jobs.
+ */
+use futures::future::join_all;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use tracing::{Instrument, error, info, info_span, warn};
+
+#[cfg(feature = "cli")]
+use crate::config::{Args, preset_name};
+use crate::config::Config;
+use crate::control::RunControl;
+use crate::file_utils::{FileManager, SavedImages};
+use crate::fixtures::{FixtureMode, Fixtures};
+use crate::http::RequestIdentity;
+use crate::i18n::{Msg, tr, tr_args};
+use crate::hooks::{self, HookEvent};
+use crate::metrics::Metrics;
+use crate::processing::{BatchManager, ImageResult, ImageTiming, ProcessingStats, RetryManager, StatsCollector};
+use crate::queue::{JobQueue, JobStatus, order_inputs};
+use crate::progress::{ProgressFile, ProgressState};
+use crate::ramp::BatchRamp;
+use crate::run_dir::{self, RunManifest};
+use crate::sidecar::Sidecar;
+use crate::sink::{FileSystemSink, LabeledSink, OutputSink};
+use crate::mqtt::{self, MqttClient};
+use crate::pipeline::RunEvent;
+#[cfg(feature = "scripting")]
+use crate::plugins::Plugins;
+use crate::style::*;
+#[cfg(feature = "notifications")]
+use crate::notify;
+use crate::{api, logging, prompt, selection, sequence};
+
+/// Process all images of the configured input directory
+///
+/// # Arguments
+/// * `config` - Configuration of the batch
+/// * `metrics` - Metrics updated after every input
+/// * `control` - Progress of the run, and pause, skip and abort requests
+///
+/// # Returns
+/// Statistics of the run, or `None` when there were no images to process
+pub async fn run_batch(config: &Config, metrics: &Metrics, control: &RunControl) -> Result<Option<ProcessingStats>> {
+    let config = run_dir::prepare(config);
+    run_prepared_batch(&config, metrics, control, &FileSystemSink::from_config(&config)).await
+}
+
+/// Process all images of the configured input directory, storing the
+/// generated images in the given sink instead of the output directory
+///
+/// The output directory still holds the job queue and dead-lettered inputs.
+///
+/// # Arguments
+/// * `config` - Configuration of the batch
+/// * `metrics` - Metrics updated after every input
+/// * `control` - Progress of the run, and pause, skip and abort requests
+/// * `sink` - Where generated images and their metadata are stored
+///
+/// # Returns
+/// Statistics of the run, or `None` when there were no images to process
+pub async fn run_batch_with_sink(
+    config: &Config,
+    metrics: &Metrics,
+    control: &RunControl,
+    sink: &dyn OutputSink,
+) -> Result<Option<ProcessingStats>> {
+    run_prepared_batch(&run_dir::prepare(config), metrics, control, sink).await
+}
+
+/// Process all images of the input directory of a configuration with its run id decided
+///
+/// The log of a run directory is closed however the run ends.
+async fn run_prepared_batch(
+    config: &Config,
+    metrics: &Metrics,
+    control: &RunControl,
+    sink: &dyn OutputSink,
+) -> Result<Option<ProcessingStats>> {
+    let result = process_batch(config, metrics, control, sink).await;
+    if config.run_dirs
+        && let Err(e) = logging::set_log_file(None)
+    {
+        warn!("{}", tr_args(Msg::RunLogCloseFailed, &[&format!("{:#}", e)]).yellow());
+    }
+    result
+}
+
+async fn process_batch(
+    config: &Config,
+    metrics: &Metrics,
+    control: &RunControl,
+    sink: &dyn OutputSink,
+) -> Result<Option<ProcessingStats>> {
+    // Frames of a sequence share the seed and keep their order
+    let sequence_config;
+    let config = if config.sequence.enabled {
+        sequence_config = config.sequence.prepare(config);
+        &sequence_config
+    } else {
+        config
+    };
+
+    // Ensure output directory exists
+    fs::create_dir_all(&config.output_dir).context("Failed to create output directory")?;
+
+    // Using our improved image processor
+    let image_paths: Vec<std::path::PathBuf> = config.input_images()?;
+
+    if image_paths.is_empty() {
+        error!("{}", tr_args(Msg::NoImagesFound, &[&config.input_dir]).red());
+        return Ok(None);
+    }
+
+    // Let input filter plugins skip images
+    #[cfg(feature = "scripting")]
+    let plugins = Arc::new(Plugins::load(&config.plugins)?);
+    #[cfg(feature = "scripting")]
+    let image_paths = plugins.filter_inputs(image_paths)?;
+    #[cfg(not(feature = "scripting"))]
+    if !config.plugins.is_empty() {
+        warn!("{}", tr(Msg::PluginsUnavailable).yellow());
+    }
+    if image_paths.is_empty() {
+        warn!("{}", tr(Msg::AllInputsFiltered).yellow());
+        return Ok(None);
+    }
+
+    info!("{}", tr_args(Msg::FoundImages, &[&image_paths.len()]).green());
+    config
+        .hooks
+        .fire(HookEvent::BeforeRun, &hooks::run_env(config))
+        .await?;
+    // Set up retry manager and batch manager
+    let retry_manager = RetryManager::with_config(config.max_retries, config.retry_delay_ms)
+        .with_retry_patterns(&config.retry_on)?
+        .with_rate_limit(config.max_requests_per_minute)
+        .with_cuda_recovery(config.reload_on_cuda_error, config.interrupt_on_cuda_error)
+        .with_nan_fallback(&config.nan_fallback)
+        .with_restart_recovery(config.server_restart_wait_secs)
+        .with_events(control.events().clone());
+    let batch_manager = BatchManager::with_config(
+        1, // Process one image at a time
+        config.batch_break_ms,
+    )
+    .with_adaptive_breaks(config.adaptive_breaks);
+
+    // Arrange the inputs and track them in the persistent job queue
+    let shuffle_seed = config
+        .shuffle
+        .then(|| config.shuffle_seed.unwrap_or_else(rand::random));
+    if let Some(seed) = shuffle_seed {
+        info!("{}", tr_args(Msg::ShufflingInputs, &[&seed]).blue());
+    }
+    let mut image_paths = order_inputs(image_paths, shuffle_seed, config.stratified);
+    if config.sequence.enabled {
+        image_paths = sequence::order_frames(image_paths);
+    }
+    // A run that was interrupted or had failures is continued unless asked to start over
+    let resume = config.resume || (!config.force && JobQueue::is_unfinished(config.queue_path())?);
+    let mut job_queue = if resume {
+        JobQueue::open(config.queue_path())?
+    } else {
+        JobQueue::create(config.queue_path())?
+    };
+    for image_path in &image_paths {
+        // An unreadable sidecar fails the image later, with the parse error in its report
+        let priority = Sidecar::priority_for(image_path).unwrap_or_default();
+        job_queue.enqueue_with_priority(image_path, priority)?;
+    }
+    if resume {
+        info!("{}", tr_args(Msg::ResumingRun, &[&job_queue.count(JobStatus::Done), &job_queue.len()]).blue());
+        let retried = job_queue.retry_failed()?;
+        if retried > 0 {
+            info!("{}", tr_args(Msg::RetryingFailed, &[&retried]).blue());
+        }
+    }
+    let manifest = if config.run_dirs {
+        Some(RunManifest::start(config, job_queue.len())?)
+    } else {
+        None
+    };
+    control.begin(
+        job_queue.len(),
+        job_queue.count(JobStatus::Done),
+        job_queue.count(JobStatus::Failed),
+    );
+
+    // Initialize processing statistics
+    let stats = StatsCollector::new();
+    control.set_stats(Arc::clone(&stats));
+
+    // Servers shared with others can attribute every request to this run
+    let run_id = config.run_id.as_deref().unwrap_or_default();
+    info!(run_id = %run_id, "{}", tr_args(Msg::RunId, &[&run_id]).blue());
+
+    let shared = SharedRun {
+        config,
+        metrics,
+        identity: config.request_identity(Some(run_id)),
+        retry_manager,
+        batch_manager,
+        job_queue: Mutex::new(job_queue),
+        stats,
+        fixtures: Fixtures::from_config(&config.fixtures, Path::new(&config.input_dir)),
+        control,
+        mqtt: mqtt::connect_publisher(&config.mqtt).await,
+        sink,
+        progress: ProgressFile::from_config(config),
+        #[cfg(feature = "scripting")]
+        plugins,
+    };
+    if let Some(progress) = &shared.progress {
+        info!("{}", tr_args(Msg::WritingProgress, &[&progress.path().display()]).blue());
+        progress.update(control, ProgressState::Running, None);
+    }
+
+    match config.fixtures.mode {
+        FixtureMode::Record => info!("{}", tr_args(Msg::RecordingFixtures, &[&config.fixtures.dir]).blue()),
+        FixtureMode::Replay => info!("{}", tr_args(Msg::ReplayingFixtures, &[&config.fixtures.dir]).blue()),
+        FixtureMode::Off => {}
+    }
+
+    // Every worker of every backend pulls the next queued image as soon as it is free
+    let api_urls = config.api_urls();
+    if api_urls.len() > 1 {
+        info!("{}", tr_args(Msg::DistributingWork, &[&api_urls.join(", ")]).blue());
+    }
+    if config.concurrency > 1 {
+        info!("{}", tr_args(Msg::InFlightPerBackend, &[&config.concurrency]).blue());
+    }
+    let mut ramps: Vec<Mutex<BatchRamp>> = api_urls
+        .iter()
+        .map(|_| Mutex::new(BatchRamp::new(&config.ramp, config.batch_size)))
+        .collect();
+    if config.estimate
+        && lock(&shared.job_queue).len() > 1
+        && !estimate_and_confirm(&shared, &api_urls, ramps[0].get_mut().unwrap_or_else(|e| e.into_inner())).await?
+    {
+        info!("{}", tr(Msg::RunCancelled).yellow());
+        return Ok(None);
+    }
+
+    control.start_processing();
+    let results = join_all(api_urls.iter().zip(&ramps).map(|(url, ramp)| run_backend(&shared, url, ramp))).await;
+    if control.is_aborted() {
+        warn!("{}", tr(Msg::RunAborted).yellow());
+    }
+    let mut errors = Vec::new();
+    for (url, result) in api_urls.iter().zip(results) {
+        if let Err(e) = result {
+            if api_urls.len() > 1 {
+                error!("{}", tr_args(Msg::BackendFailed, &[url, &format!("{:#}", e)]).red());
+            }
+            errors.push(e);
+        }
+    }
+    if let Some(progress) = &shared.progress {
+        let state = if errors.len() == api_urls.len() {
+            ProgressState::Failed
+        } else if control.is_aborted() {
+            ProgressState::Aborted
+        } else {
+            ProgressState::Finished
+        };
+        progress.update(control, state, None);
+    }
+    if errors.len() == api_urls.len() {
+        return Err(errors.remove(0));
+    }
+
+    let mqtt = shared.mqtt;
+    let job_queue = shared.job_queue.into_inner().unwrap_or_else(|e| e.into_inner());
+    let stats = shared.stats.snapshot();
+    let total_images = job_queue.len();
+
+    // Display final statistics
+    stats.display(total_images);
+    sink.finalize_run(&stats).context("Failed to finalize the output sink")?;
+    if config.sequence.enabled
+        && let Err(e) = config.sequence.assemble(&image_paths, Path::new(&config.output_dir)).await
+    {
+        warn!("{}", tr_args(Msg::SequenceFailed, &[&format!("{:#}", e)]).yellow());
+    }
+    if let Err(e) = config
+        .timelapse
+        .assemble(&stats, Path::new(&config.output_dir), (config.width, config.height))
+        .await
+    {
+        warn!("{}", tr_args(Msg::TimelapseFailed, &[&format!("{:#}", e)]).yellow());
+    }
+
+    if let Some(manifest) = manifest {
+        if control.is_aborted() {
+            manifest.abort(Path::new(&config.output_dir), &stats)?;
+        } else {
+            manifest.finish(Path::new(&config.output_dir), &stats)?;
+        }
+    }
+
+    if let Some(stats_out) = &config.stats_out {
+        stats.write_to_file(stats_out)?;
+        info!("{}", tr_args(Msg::StatisticsWritten, &[stats_out]).blue());
+    }
+
+    #[cfg(feature = "notifications")]
+    {
+        let report = config.stats_out.as_deref().unwrap_or(&config.output_dir);
+        notify::send_run_summary(&config.notifications, &stats, total_images, report).await;
+    }
+    #[cfg(not(feature = "notifications"))]
+    if config.notifications.is_enabled() {
+        warn!("{}", tr(Msg::NotificationsUnavailable).yellow());
+    }
+    if let Some(mqtt) = mqtt {
+        mqtt.publish_run_summary(&stats, total_images).await;
+        mqtt.disconnect().await;
+    }
+
+    if let Err(e) = config
+        .hooks
+        .fire(HookEvent::AfterRun, &hooks::finished_run_env(config, &stats))
+        .await
+    {
+        warn!("{}", tr_args(Msg::HookFailed, &[&format!("{:#}", e)]).yellow());
+    }
+
+    Ok(Some(stats))
+}
+
+/// Outcome of one preset of a multi-preset run
+#[derive(Debug)]
+pub struct PresetRun {
+    /// Name of the preset, taken from its file name
+    pub name: String,
+    /// Checkpoint the preset generated with
+    pub checkpoint: String,
+    /// Statistics of the preset, `None` when it processed nothing or failed
+    pub stats: Option<ProcessingStats>,
+}
+
+/// Process the input directory once for each configured preset
+///
+/// Presets using the same checkpoint run one after another, in the order the
+/// first of them is listed, so each checkpoint is loaded as few times as
+/// possible. A preset that fails is reported and the others still run.
+///
+/// # Arguments
+/// * `config` - Configuration the presets are applied to
+/// * `args` - Command line arguments, applied on top of each preset
+/// * `metrics` - Metrics updated after every input
+/// * `control` - Progress of the current preset, and pause, skip and abort requests
+///
+/// # Returns
+/// The outcome of every preset, in the order they were run
+#[cfg(feature = "cli")]
+pub async fn run_presets(
+    config: &Config,
+    args: &Args,
+    metrics: &Metrics,
+    control: &RunControl,
+) -> Result<Vec<PresetRun>> {
+    let mut presets = Vec::new();
+    for preset in &config.presets {
+        let preset_path = Path::new(preset);
+        presets.push((preset_name(preset_path), config.with_preset(preset_path, args)?));
+    }
+
+    let mut checkpoints: Vec<String> = Vec::new();
+    for (_, preset_config) in &presets {
+        if !checkpoints.contains(&preset_config.checkpoint_model) {
+            checkpoints.push(preset_config.checkpoint_model.clone());
+        }
+    }
+    presets.sort_by_key(|(_, preset_config)| {
+        checkpoints
+            .iter()
+            .position(|checkpoint| *checkpoint == preset_config.checkpoint_model)
+    });
+
+    let mut runs = Vec::new();
+    for (name, preset_config) in presets {
+        if control.is_aborted() {
+            break;
+        }
+        info!("{}", tr_args(Msg::RunningPreset, &[&name, &preset_config.checkpoint_model]).blue());
+        let stats = match run_batch(&preset_config, metrics, control).await {
+            Ok(stats) => stats,
+            Err(e) => {
+                error!("{}", tr_args(Msg::PresetFailed, &[&name, &format!("{:#}", e)]).red());
+                None
+            }
+        };
+        runs.push(PresetRun {
+            name,
+            checkpoint: preset_config.checkpoint_model,
+            stats,
+        });
+    }
+
+    display_preset_summary(&runs);
+    Ok(runs)
+}
+
+/// Print one line of statistics per preset
+#[cfg(feature = "cli")]
+fn display_preset_summary(runs: &[PresetRun]) {
+    info!("{}", tr(Msg::ResultsByPreset).green().bold());
+    for run in runs {
+        match &run.stats {
+            Some(stats) => info!(
+                "  {}",
+                tr_args(
+                    Msg::PresetResult,
+                    &[
+                        &run.name.bold(),
+                        &run.checkpoint,
+                        &stats.success_count,
+                        &stats.failed_paths.len(),
+                        &stats.generated_count,
+                        &stats.average_generation_ms(),
+                        &format!("{:.1}", stats.elapsed_ms as f64 / 1000.0),
+                    ]
+                )
+            ),
+            None => warn!("  {}", tr_args(Msg::PresetNoResults, &[&run.name.bold(), &run.checkpoint])),
+        }
+    }
+}
+
+/// Estimated duration and output size of a run, based on one sample input
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RunEstimate {
+    /// Time needed for the remaining inputs
+    pub duration: Duration,
+    /// Size of all outputs of the run, including the sample
+    pub output_bytes: u64,
+}
+
+impl RunEstimate {
+    /// Extrapolate from the sample to the whole run
+    ///
+    /// # Arguments
+    /// * `sample_ms` - Time the sample input took
+    /// * `sample_bytes` - Size of the outputs of the sample
+    /// * `remaining` - Inputs left after the sample
+    /// * `total` - Inputs in the whole run
+    /// * `workers` - Number of backends processing inputs in parallel
+    /// * `batch_break_ms` - Break taken after each input
+    pub fn from_sample(
+        sample_ms: u64,
+        sample_bytes: u64,
+        remaining: usize,
+        total: usize,
+        workers: usize,
+        batch_break_ms: u64,
+    ) -> Self {
+        let per_worker = remaining.div_ceil(workers.max(1)) as u64;
+        Self {
+            duration: Duration::from_millis(per_worker * (sample_ms + batch_break_ms)),
+            output_bytes: sample_bytes * total as u64,
+        }
+    }
+
+    /// Local time at which the run is expected to finish
+    pub fn finish_at(&self) -> DateTime<Local> {
+        Local::now() + chrono::Duration::from_std(self.duration).unwrap_or_default()
+    }
+}
+
+/// Process the first input as a timed sample, print an estimate for the
+/// whole run and ask whether to continue
+///
+/// The sample counts towards the batch size ramp of the first backend.
+///
+/// # Returns
+/// Whether to continue with the remaining inputs
+async fn estimate_and_confirm(shared: &SharedRun<'_>, api_urls: &[String], ramp: &mut BatchRamp) -> Result<bool> {
+    let config = shared.config;
+    let api_url = &api_urls[0];
+    let sd_client = api::StableDiffusionClient::new(api_url)
+        .with_identity(shared.identity.clone())
+        .with_fixtures(shared.fixtures.clone());
+    #[cfg(feature = "scripting")]
+    let sd_client = sd_client.with_plugins(Arc::clone(&shared.plugins));
+    sd_client.load_model(&config.checkpoint_model).await?;
+
+    config.schedule.wait_for_window().await;
+    let Some(image_path) = lock(&shared.job_queue).next_pending()? else {
+        return Ok(true);
+    };
+    info!("{}", tr(Msg::GeneratingSample).blue());
+    let Some(sample) = process_image(shared, &sd_client, api_url, &image_path, ramp.batch_size()).await? else {
+        return Ok(true);
+    };
+    ramp.record(sample.success, sample.generated, sample.generation_ms);
+    if shared.control.is_aborted() {
+        return Ok(true);
+    }
+    if !sample.success {
+        warn!("{}", tr(Msg::SampleFailed).yellow());
+        return Ok(true);
+    }
+
+    let sample_bytes = directory_size(&config.output_folder(&image_path));
+    let (remaining, total) = {
+        let job_queue = lock(&shared.job_queue);
+        (job_queue.count(JobStatus::Pending), job_queue.len())
+    };
+    let estimate = RunEstimate::from_sample(
+        sample.duration_ms,
+        sample_bytes,
+        remaining,
+        total,
+        api_urls.len(),
+        config.batch_break_ms,
+    );
+
+    info!(
+        event = "run_estimate",
+        duration_ms = estimate.duration.as_millis() as u64,
+        output_bytes = estimate.output_bytes,
+        "{}",
+        tr_args(
+            Msg::Estimate,
+            &[
+                &format!("{:.1}", estimate.duration.as_secs_f64() / 60.0),
+                &format!("{:.1}", estimate.output_bytes as f64 / 1_000_000.0),
+                &estimate.finish_at().format("%Y-%m-%d %H:%M"),
+            ]
+        )
+        .blue()
+    );
+
+    prompt::confirm(config, &tr_args(Msg::ContinueRemaining, &[&remaining]))
+}
+
+/// Total size of the files in a directory, zero if it cannot be read
+fn directory_size(directory: &Path) -> u64 {
+    fs::read_dir(directory)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok()?.metadata().ok())
+                .filter(|metadata| metadata.is_file())
+                .map(|metadata| metadata.len())
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+/// State shared by the workers processing one batch
+struct SharedRun<'a> {
+    config: &'a Config,
+    metrics: &'a Metrics,
+    identity: RequestIdentity,
+    retry_manager: RetryManager,
+    batch_manager: BatchManager,
+    job_queue: Mutex<JobQueue>,
+    stats: Arc<StatsCollector>,
+    fixtures: Option<Arc<Fixtures>>,
+    control: &'a RunControl,
+    mqtt: Option<MqttClient>,
+    sink: &'a dyn OutputSink,
+    progress: Option<ProgressFile>,
+    #[cfg(feature = "scripting")]
+    plugins: Arc<Plugins>,
+}
+
+/// Lock a mutex, recovering the data if another worker panicked
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Process queued images on one backend until the queue is empty
+///
+/// The checkpoint is loaded once, then `concurrency` workers keep that many
+/// inputs in flight on the backend.
+///
+/// # Arguments
+/// * `shared` - State shared with the other backends
+/// * `api_url` - URL of the Stable Diffusion API of the backend
+/// * `ramp` - Batch size ramp of the backend, shared by its workers
+///
+/// # Returns
+/// Number of images the workers of the backend processed
+async fn run_backend(shared: &SharedRun<'_>, api_url: &str, ramp: &Mutex<BatchRamp>) -> Result<usize> {
+    // Create Stable Diffusion client and load model
+    let sd_client = api::StableDiffusionClient::new(api_url)
+        .with_identity(shared.identity.clone())
+        .with_fixtures(shared.fixtures.clone());
+    #[cfg(feature = "scripting")]
+    let sd_client = sd_client.with_plugins(Arc::clone(&shared.plugins));
+    sd_client.load_model(&shared.config.checkpoint_model).await?;
+
+    let workers = (0..shared.config.concurrency.max(1)).map(|_| run_worker(shared, &sd_client, api_url, ramp));
+    let mut processed = 0;
+    for result in join_all(workers).await {
+        processed += result?;
+    }
+    Ok(processed)
+}
+
+/// Process queued images one at a time until the queue is empty
+///
+/// Each worker counts its own generations, so its breaks pace only the
+/// inputs it sends.
+///
+/// # Arguments
+/// * `shared` - State shared with the other workers
+/// * `sd_client` - Client of the backend, with the checkpoint loaded
+/// * `api_url` - URL of that backend, for logs and hooks
+/// * `ramp` - Batch size ramp of the backend
+///
+/// # Returns
+/// Number of images this worker processed
+async fn run_worker(
+    shared: &SharedRun<'_>,
+    sd_client: &api::StableDiffusionClient,
+    api_url: &str,
+    ramp: &Mutex<BatchRamp>,
+) -> Result<usize> {
+    let config = shared.config;
+    let mut processed = 0;
+    let mut generations = 0;
+
+    // Process queued images with retry logic
+    loop {
+        // Pause between images while outside the allowed hours or on request
+        config.schedule.wait_for_window().await;
+        shared.control.wait_while_paused().await;
+        if shared.control.is_aborted() {
+            break;
+        }
+        let Some(image_path) = lock(&shared.job_queue).next_pending()? else {
+            break;
+        };
+        let batch_size = lock(ramp).batch_size();
+        let Some(image_result) = process_image(shared, sd_client, api_url, &image_path, batch_size).await? else {
+            break;
+        };
+        processed += 1;
+
+        // Take a break between batches, counting only inputs that reached the GPU
+        if image_result.attempts > 0 {
+            lock(ramp).record(image_result.success, image_result.generated, image_result.generation_ms);
+            generations += 1;
+            let more_pending = lock(&shared.job_queue).count(JobStatus::Pending) > 0;
+            shared
+                .batch_manager
+                .after_generation(generations, Duration::from_millis(image_result.generation_ms), more_pending)
+                .await;
+        }
+    }
+
+    Ok(processed)
+}
+
+/// Poll the progress of the backend while an input is generated, for its previews and the progress display
+///
+/// Without previews, and with nothing showing the progress, the generation runs without polling.
+async fn with_progress<F: std::future::Future>(
+    shared: &SharedRun<'_>,
+    sd_client: &api::StableDiffusionClient,
+    image_path: &Path,
+    named_input: &Path,
+    generation: F,
+) -> F::Output {
+    let config = shared.config;
+    let mut previews = config.previews.saver(Path::new(&config.output_dir), named_input);
+    let shown = shared.control.shows_sampling() && config.progress_poll_ms > 0;
+    let interval = match (&previews, shown) {
+        (Some(_), _) => config.previews.poll_interval(),
+        (None, true) => Duration::from_millis(config.progress_poll_ms),
+        (None, false) => return generation.await,
+    };
+    sd_client
+        .poll_progress(interval, generation, |progress| {
+            if let Some(saver) = &mut previews {
+                saver.record(progress);
+            }
+            if shown {
+                shared.control.input_progress(image_path, progress);
+            }
+        })
+        .await
+}
+
+/// Generate, save and record the images for one input
+///
+/// # Arguments
+/// * `shared` - State shared with the other workers
+/// * `sd_client` - Client of the backend to use
+/// * `api_url` - URL of that backend, for logs and hooks
+/// * `image_path` - Input image taken from the queue
+/// * `batch_size` - Images to generate for the input, lower than configured while ramping up
+///
+/// # Returns
+/// The recorded result of the input, `None` when the run was aborted before it finished
+async fn process_image(
+    shared: &SharedRun<'_>,
+    sd_client: &api::StableDiffusionClient,
+    api_url: &str,
+    image_path: &Path,
+    batch_size: u32,
+) -> Result<Option<ImageResult>> {
+    let config = shared.config;
+    let image_span = info_span!("image", path = %image_path.display(), backend = api_url);
+    image_span.in_scope(|| {
+        info!(event = "image_started", "{}", tr_args(Msg::Processing, &[&image_path.display()]).blue())
+    });
+    shared.control.input_started(image_path, api_url);
+    if let Some(progress) = &shared.progress {
+        progress.update(shared.control, ProgressState::Running, None);
+    }
+    shared.control.events().emit(RunEvent::ImageStarted {
+        input: image_path.to_path_buf(),
+        backend: api_url.to_string(),
+    });
+    let started = Instant::now();
+    let mut timing = ImageTiming {
+        queue_wait: shared.stats.since_start(),
+        ..Default::default()
+    };
+    let before_image = config
+        .hooks
+        .fire(HookEvent::BeforeImage, &hooks::image_env(config, image_path, api_url))
+        .instrument(image_span.clone())
+        .await;
+    let sidecar = before_image.and_then(|_| Sidecar::load_for(image_path));
+    let seeded = sidecar.and_then(|sidecar| {
+        let seed = config.seed_strategy.seed_for(config.seed, image_path, &config.seed_salt)?;
+        Ok((sidecar, seed))
+    });
+    let named_input = config.named_input(image_path);
+    let mut skipped = false;
+    let mut attempts = 0;
+    let outcome = match seeded {
+        Ok((sidecar, seed)) => {
+            let input_config = Config {
+                seed,
+                batch_size,
+                ..config.clone()
+            };
+            let mut outcome = Ok((0, SavedImages::default()));
+            // Without a sweep there is one variant, the configuration itself
+            for variant in config.sweep.variants(&input_config) {
+                let generation = shared
+                    .retry_manager
+                    .process_with_overrides(sd_client, image_path, &variant.config, sidecar.retry);
+                let generation = with_progress(shared, sd_client, image_path, &named_input, generation)
+                    .instrument(image_span.clone());
+                let (result, variant_attempts) = tokio::select! {
+                    outcome = generation => outcome,
+                    _ = shared.control.skipped() => {
+                        skipped = true;
+                        // Stop the server working on an image nobody wants anymore
+                        if let Err(e) = sd_client.interrupt().await {
+                            warn!("{}", tr_args(Msg::InterruptFailed, &[&format!("{:#}", e)]).yellow());
+                        }
+                        (Err(anyhow::anyhow!("Skipped by user")), 1)
+                    }
+                };
+                attempts += variant_attempts;
+                timing.generation = started.elapsed();
+
+                let sink = variant.label.as_deref().map(|label| LabeledSink::new(shared.sink, label));
+                let sink = sink.as_ref().map_or(shared.sink, |sink| sink as &dyn OutputSink);
+                let variant_outcome = match result {
+                    Ok(Some(generated)) => image_span
+                        .in_scope(|| FileManager::save_to_sink(sink, &generated, image_path, &variant.config))
+                        .map(|saved| (generated.images.len(), saved)),
+                    Ok(None) => Err(anyhow::anyhow!("API returned no result")),
+                    Err(error) => Err(error),
+                };
+                match (&mut outcome, variant_outcome) {
+                    (Ok((count, saved)), Ok((variant_count, variant_saved))) => {
+                        *count += variant_count;
+                        saved.extend(variant_saved);
+                    }
+                    (_, Err(error)) => {
+                        outcome = Err(error);
+                        break;
+                    }
+                    (Err(_), Ok(_)) => {}
+                }
+            }
+            match outcome {
+                Ok((generated_count, saved)) => {
+                    let saved = selection::select(&config.selection, saved)
+                        .instrument(image_span.clone())
+                        .await;
+                    #[cfg(feature = "scripting")]
+                    let saved = saved.and_then(|saved| {
+                        image_span.in_scope(|| shared.plugins.post_process(image_path, saved))
+                    });
+                    saved.map(|saved| (generated_count, saved))
+                }
+                Err(error) => Err(error),
+            }
+        }
+        Err(e) => Err(e),
+    };
+
+    // Aborting leaves the inputs being generated pending, so resuming the run takes them again
+    if skipped && shared.control.is_aborted() && outcome.is_err() {
+        lock(&shared.job_queue).release(image_path)?;
+        shared.control.input_released(image_path);
+        image_span.in_scope(|| info!("{}", tr_args(Msg::LeftPending, &[&image_path.display()]).yellow()));
+        return Ok(None);
+    }
+
+    timing.total = started.elapsed();
+    let entered = image_span.enter();
+
+    // The collector records the result under one lock, as other workers record theirs too
+    let image_result = match outcome {
+        Ok((generated_count, saved)) => {
+            for output in &saved.paths {
+                shared.control.events().emit(RunEvent::ImageSaved {
+                    input: image_path.to_path_buf(),
+                    output: output.clone(),
+                });
+            }
+            let megapixels =
+                generated_count as f64 * (config.width * config.height) as f64 / 1_000_000.0;
+            let image_result =
+                shared.stats.record_success(image_path, generated_count, megapixels, timing, attempts, &saved);
+            lock(&shared.job_queue).mark_done(image_path)?;
+            image_result
+        }
+        Err(error) => {
+            error!("{}", tr_args(Msg::GenerationFailed, &[&image_path.display()]).red());
+            let error_message = format!("{:#}", error);
+            let image_result = shared.stats.record_failure(image_path, timing, attempts, &error_message);
+            lock(&shared.job_queue).mark_failed(image_path)?;
+            if skipped {
+                info!("{}", tr_args(Msg::Skipped, &[&image_path.display()]).yellow());
+            } else if let Err(dead_letter_error) =
+                FileManager::dead_letter(image_path, &error_message, attempts, config)
+            {
+                error!("{}", tr_args(Msg::DeadLetterFailed, &[&dead_letter_error]).red());
+            }
+            image_result
+        }
+    };
+
+    drop(entered);
+    shared.metrics.observe(&image_result);
+    shared.control.input_finished(image_path, &image_result);
+    if let Some(progress) = &shared.progress {
+        progress.update(shared.control, ProgressState::Running, Some(&image_result));
+    }
+    shared.control.events().emit(RunEvent::ImageFinished(image_result.clone()));
+    if logging::is_plain() {
+        // CI logs get no redrawn progress, so every input reports where the run stands
+        let progress = shared.control.status();
+        info!(
+            "{}",
+            tr_args(
+                Msg::Progress,
+                &[&(progress.done + progress.failed), &progress.total, &progress.failed]
+            )
+            .blue()
+        );
+    }
+    if let Some(mqtt) = &shared.mqtt {
+        mqtt.publish_image(&image_result).await;
+    }
+    let env = hooks::finished_image_env(config, image_path, api_url, &image_result);
+    if let Err(e) = config
+        .hooks
+        .fire(HookEvent::AfterImage, &env)
+        .instrument(image_span.clone())
+        .await
+    {
+        warn!("{}", tr_args(Msg::HookFailed, &[&format!("{:#}", e)]).yellow());
+    }
+
+    Ok(Some(image_result))
+}
@@ -0,0 +1,131 @@
+use anyhow::{Context, Result};
+use chrono::{Local, NaiveTime, Timelike};
+use serde::{Deserialize, Serialize};
+/**
+ * Time window scheduling for ControlNet Image Generator
+ *
+ * This module restricts GPU-heavy work to designated hours, for example
+ * overnight. Outside the allowed window the run pauses between images; the
+ * job queue already records which inputs are done, so an interrupted pause
+ * loses nothing.
+ */
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+use tracing::info;
+
+use crate::i18n::{Msg, tr, tr_args};
+use crate::style::*;
+
+/// Longest single sleep while waiting for the window to open, so clock
+/// changes are noticed reasonably soon
+const MAX_WAIT_STEP: Duration = Duration::from_secs(60);
+
+/// Scheduling settings
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ScheduleConfig {
+    /// Hours during which images may be generated, e.g. "22:00-07:00"
+    #[serde(default)]
+    pub allowed_hours: Option<TimeWindow>,
+}
+
+impl ScheduleConfig {
+    /// Wait until the current local time is inside the allowed window
+    ///
+    /// Returns immediately when no window is configured.
+    pub async fn wait_for_window(&self) {
+        let Some(window) = &self.allowed_hours else {
+            return;
+        };
+
+        let mut announced = false;
+        while let Some(remaining) = window.time_until_open(Local::now().time()) {
+            if !announced {
+                info!(
+                    event = "schedule_paused",
+                    "{}",
+                    tr_args(Msg::OutsideHours, &[&window, &remaining.as_secs().div_ceil(60)]).yellow()
+                );
+                announced = true;
+            }
+            tokio::time::sleep(remaining.min(MAX_WAIT_STEP)).await;
+        }
+        if announced {
+            info!(event = "schedule_resumed", "{}", tr(Msg::HoursStarted).green());
+        }
+    }
+}
+
+/// Daily time window, which may wrap past midnight
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct TimeWindow {
+    /// Start of the window, inclusive
+    pub start: NaiveTime,
+    /// End of the window, exclusive
+    pub end: NaiveTime,
+}
+
+impl TimeWindow {
+    /// Whether the given time of day falls inside the window
+    ///
+    /// A window whose start equals its end covers the whole day.
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start < self.end {
+            time >= self.start && time < self.end
+        } else if self.start > self.end {
+            time >= self.start || time < self.end
+        } else {
+            true
+        }
+    }
+
+    /// Time left until the window opens, or `None` when it is already open
+    pub fn time_until_open(&self, now: NaiveTime) -> Option<Duration> {
+        if self.contains(now) {
+            return None;
+        }
+        let now_secs = now.num_seconds_from_midnight() as i64;
+        let start_secs = self.start.num_seconds_from_midnight() as i64;
+        let seconds = (start_secs - now_secs).rem_euclid(24 * 60 * 60);
+        Some(Duration::from_secs(seconds as u64))
+    }
+}
+
+impl FromStr for TimeWindow {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        let (start, end) = value
+            .split_once('-')
+            .context(format!("Invalid time window '{}', expected HH:MM-HH:MM", value))?;
+        let parse = |time: &str| {
+            NaiveTime::parse_from_str(time.trim(), "%H:%M")
+                .context(format!("Invalid time '{}' in window '{}'", time.trim(), value))
+        };
+        Ok(Self {
+            start: parse(start)?,
+            end: parse(end)?,
+        })
+    }
+}
+
+impl TryFrom<String> for TimeWindow {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        value.parse()
+    }
+}
+
+impl From<TimeWindow> for String {
+    fn from(window: TimeWindow) -> Self {
+        window.to_string()
+    }
+}
+
+impl fmt::Display for TimeWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.start.format("%H:%M"), self.end.format("%H:%M"))
+    }
+}
@@ -0,0 +1,225 @@
+use anyhow::{Context, Result};
+use base64::{Engine, prelude::BASE64_STANDARD};
+use serde::{Deserialize, Serialize};
+/**
+ * Best-of-N selection for ControlNet Image Generator
+ *
+ * This module scores the images generated for an input and keeps only the
+ * best ones, moving the others into a `rejected/` folder next to them, so
+ * less of a large batch needs to be looked through by hand. Images are
+ * scored by their sharpness, or by an HTTP endpoint such as a CLIP aesthetic
+ * or BRISQUE service.
+ */
+use serde_json::json;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info};
+
+use crate::api::GeneratedImage;
+use crate::file_utils::SavedImages;
+use crate::http;
+use crate::i18n::{Msg, tr_args};
+use crate::style::*;
+
+/// Folder next to the kept images that the rejected ones are moved into
+pub const REJECTED_DIR: &str = "rejected";
+
+/// Which images of an input are kept
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SelectionConfig {
+    /// How images are scored, every image is kept when unset
+    #[serde(default)]
+    pub scorer: Option<ScorerConfig>,
+    /// Number of best scoring images kept per input
+    #[serde(default = "default_keep")]
+    pub keep: usize,
+    /// Score at least one image of an input has to reach, re-rolling with a new seed otherwise
+    #[serde(default)]
+    pub min_score: Option<f64>,
+    /// Most re-rolls of an input before accepting the best images available
+    #[serde(default = "default_max_rerolls")]
+    pub max_rerolls: u32,
+}
+
+impl Default for SelectionConfig {
+    fn default() -> Self {
+        Self {
+            scorer: None,
+            keep: default_keep(),
+            min_score: None,
+            max_rerolls: default_max_rerolls(),
+        }
+    }
+}
+
+/// Default number of images kept - 1
+pub fn default_keep() -> usize {
+    1
+}
+
+/// Default number of re-rolls - 2
+pub fn default_max_rerolls() -> u32 {
+    2
+}
+
+/// How the images are scored
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ScorerConfig {
+    /// Variance of the Laplacian of the grayscale image, sharper images score higher
+    Laplacian,
+    /// Score returned by an HTTP endpoint
+    ///
+    /// The endpoint receives `{"image": "<base64 PNG>"}` as a POST request
+    /// and answers with `{"score": <number>}`.
+    Endpoint {
+        /// URL of the endpoint
+        url: String,
+        /// Whether lower scores are better, as with BRISQUE
+        #[serde(default)]
+        lower_is_better: bool,
+    },
+}
+
+impl ScorerConfig {
+    /// Score of an image, better images scoring higher
+    ///
+    /// # Arguments
+    /// * `image` - Encoded image, as saved
+    pub async fn score(&self, image: &[u8]) -> Result<f64> {
+        match self {
+            ScorerConfig::Laplacian => laplacian_variance(image),
+            ScorerConfig::Endpoint { url, lower_is_better } => {
+                let score = endpoint_score(url, image).await?;
+                Ok(if *lower_is_better { -score } else { score })
+            }
+        }
+    }
+}
+
+/// Variance of the Laplacian of an image in grayscale, a measure of its sharpness
+///
+/// # Arguments
+/// * `image` - Encoded image
+///
+/// # Returns
+/// The variance, 0 for images too small to have any edges
+pub fn laplacian_variance(image: &[u8]) -> Result<f64> {
+    let gray = image::load_from_memory(image)
+        .context("Failed to decode image for scoring")?
+        .to_luma8();
+    let (width, height) = gray.dimensions();
+    if width < 3 || height < 3 {
+        return Ok(0.0);
+    }
+
+    let pixel = |x: u32, y: u32| f64::from(gray.get_pixel(x, y)[0]);
+    let mut laplacians = Vec::with_capacity(((width - 2) * (height - 2)) as usize);
+    for y in 1..height - 1 {
+        for x in 1..width - 1 {
+            laplacians.push(
+                pixel(x - 1, y) + pixel(x + 1, y) + pixel(x, y - 1) + pixel(x, y + 1) - 4.0 * pixel(x, y),
+            );
+        }
+    }
+    let count = laplacians.len() as f64;
+    let mean = laplacians.iter().sum::<f64>() / count;
+    Ok(laplacians.iter().map(|value| (value - mean).powi(2)).sum::<f64>() / count)
+}
+
+/// Best score among the images of a response
+///
+/// # Arguments
+/// * `scorer` - How the images are scored
+/// * `images` - Base64-encoded images, as returned by the API
+///
+/// # Returns
+/// The best score, `None` when there are no images
+pub async fn best_score(scorer: &ScorerConfig, images: &[GeneratedImage]) -> Result<Option<f64>> {
+    let mut best: Option<f64> = None;
+    for image in images {
+        let image = image.bytes()?;
+        let score = scorer.score(&image).await?;
+        best = Some(best.map_or(score, |best| best.max(score)));
+    }
+    Ok(best)
+}
+
+/// Ask an HTTP endpoint for the score of an image
+async fn endpoint_score(url: &str, image: &[u8]) -> Result<f64> {
+    let response = http::default_client()
+        .post(url)
+        .json(&json!({ "image": BASE64_STANDARD.encode(image) }))
+        .send()
+        .await
+        .context(format!("Failed to reach scorer {}", url))?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let text = response.text().await.unwrap_or_default();
+        return Err(anyhow::anyhow!("Scorer error: {} - {}", status, text));
+    }
+    let body = response.json::<serde_json::Value>().await?;
+    body["score"].as_f64().context("Scorer response has no score")
+}
+
+/// Keep the best scoring images of an input, moving the others into `rejected/`
+///
+/// Nothing is scored when no scorer is configured or there are no more
+/// images than are kept. Images with equal scores are kept in the order
+/// they were generated.
+///
+/// # Arguments
+/// * `config` - Scorer and number of images to keep
+/// * `saved` - What was saved for the input, the images being files
+///
+/// # Returns
+/// The kept images in `paths`, and where the others were moved in `rejected`
+pub async fn select(config: &SelectionConfig, saved: SavedImages) -> Result<SavedImages> {
+    let Some(scorer) = &config.scorer else {
+        return Ok(saved);
+    };
+    let keep = config.keep.max(1);
+    if saved.paths.len() <= keep {
+        return Ok(saved);
+    }
+
+    let mut scored = Vec::with_capacity(saved.paths.len());
+    for path in &saved.paths {
+        let image = fs::read(path).context(format!("Failed to read {} for scoring", path.display()))?;
+        let score = scorer.score(&image).await?;
+        debug!("{} {}: {:.2}", "Score of".blue(), path.display(), score);
+        scored.push((score, path.clone()));
+    }
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+    let rejected: Vec<PathBuf> = scored.split_off(keep).into_iter().map(|(_, path)| path).collect();
+
+    let mut moved = Vec::with_capacity(rejected.len());
+    for path in &rejected {
+        moved.push(move_to_rejected(path)?);
+    }
+    let total = saved.paths.len();
+    let paths: Vec<PathBuf> = saved.paths.into_iter().filter(|path| !rejected.contains(path)).collect();
+    info!("{}", tr_args(Msg::KeptBest, &[&paths.len(), &total]).green());
+    Ok(SavedImages {
+        paths,
+        rejected: moved,
+        ..saved
+    })
+}
+
+/// Move an image, with its parameters file if it has one, into the `rejected/` folder of its directory
+fn move_to_rejected(path: &Path) -> Result<PathBuf> {
+    let dir = path.parent().unwrap_or(Path::new(".")).join(REJECTED_DIR);
+    fs::create_dir_all(&dir).context("Failed to create rejected folder")?;
+    let target = dir.join(path.file_name().context("Failed to extract file name")?);
+    fs::rename(path, &target).context(format!("Failed to move {} to {}", path.display(), dir.display()))?;
+
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let parameters = path.with_file_name(format!("{}-parameters.txt", stem));
+    if parameters.is_file() {
+        let parameters_target = dir.join(parameters.file_name().unwrap_or_default());
+        fs::rename(&parameters, parameters_target)
+            .context(format!("Failed to move {} to {}", parameters.display(), dir.display()))?;
+    }
+    Ok(target)
+}
@@ -0,0 +1,155 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+/**
+ * Video sequences for ControlNet Image Generator
+ *
+ * This module treats numbered inputs such as `frame_0001.png` as the frames
+ * of a video. A sequence run holds the seed and the settings fixed for every
+ * frame and processes the frames in order. Afterwards the first image of each
+ * frame is gathered under its frame name, and ffmpeg can assemble them into a
+ * video when it is installed.
+ */
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+use crate::config::{Config, SeedStrategy};
+use crate::ffmpeg;
+use crate::i18n::{Msg, tr_args};
+use crate::style::*;
+
+/// Folder of output_dir the generated frames are gathered in
+pub const FRAMES_DIR: &str = "sequence";
+
+/// File listing the gathered frames for ffmpeg, inside the frames folder
+const FRAME_LIST: &str = "frames.txt";
+
+/// Settings of sequence runs
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SequenceConfig {
+    /// Whether the inputs are frames of one video
+    #[serde(default)]
+    pub enabled: bool,
+    /// Video file to assemble from the generated frames with ffmpeg, relative to output_dir
+    #[serde(default)]
+    pub video: Option<String>,
+    /// Frames per second of the assembled video
+    #[serde(default = "default_fps")]
+    pub fps: u32,
+}
+
+impl Default for SequenceConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            video: None,
+            fps: default_fps(),
+        }
+    }
+}
+
+/// Default frame rate of assembled videos - 24
+pub fn default_fps() -> u32 {
+    24
+}
+
+impl SequenceConfig {
+    /// Configuration of a run over the frames of a sequence
+    ///
+    /// A random seed is drawn once when none is configured, so every frame
+    /// uses the same seed, and the inputs are not shuffled or interleaved.
+    /// Seeds are not derived from the frames, as they would differ per frame.
+    pub fn prepare(&self, config: &Config) -> Config {
+        let mut config = config.clone();
+        config.seed_strategy = SeedStrategy::Fixed;
+        if config.seed < 0 {
+            config.seed = rand::random_range(0..i64::from(u32::MAX));
+        }
+        info!("{}", tr_args(Msg::SequenceSeed, &[&config.seed]).blue());
+        config.shuffle = false;
+        config.stratified = false;
+        config
+    }
+
+    /// Gather the generated frames and assemble the video if one is configured
+    ///
+    /// The first image generated for each frame is copied into `sequence/` of
+    /// the output directory under the name of the input frame, so the frame
+    /// numbering is kept. Frames without an image are left out with a warning.
+    ///
+    /// # Arguments
+    /// * `frames` - Input frames in order
+    /// * `output_dir` - Output directory the images were written to
+    ///
+    /// # Returns
+    /// Path of the assembled video, `None` when none was configured or ffmpeg is missing
+    pub async fn assemble(&self, frames: &[PathBuf], output_dir: &Path) -> Result<Option<PathBuf>> {
+        let frames_dir = output_dir.join(FRAMES_DIR);
+        fs::create_dir_all(&frames_dir).context("Failed to create the sequence folder")?;
+
+        let mut gathered = Vec::with_capacity(frames.len());
+        for frame in frames {
+            let stem = frame.file_stem().context("Failed to extract file name")?.to_string_lossy();
+            let Some(image) = first_output(output_dir, &stem) else {
+                continue;
+            };
+            let name = format!("{}.png", stem);
+            fs::copy(&image, frames_dir.join(&name)).context(format!("Failed to copy {}", image.display()))?;
+            gathered.push(name);
+        }
+        if gathered.len() < frames.len() {
+            warn!("{}", tr_args(Msg::SequenceMissingFrames, &[&(frames.len() - gathered.len())]).yellow());
+        }
+        info!("{}", tr_args(Msg::GatheredFrames, &[&gathered.len(), &frames_dir.display()]).green());
+
+        let Some(video) = &self.video else {
+            return Ok(None);
+        };
+        if gathered.is_empty() {
+            return Ok(None);
+        }
+        let list = frames_dir.join(FRAME_LIST);
+        ffmpeg::write_concat_list(&list, &gathered, self.fps)?;
+        let video = output_dir.join(video);
+        if !ffmpeg::encode(&list, self.fps, None, &video).await? {
+            return Ok(None);
+        }
+        info!("{}", tr_args(Msg::AssembledVideo, &[&video.display()]).green());
+        Ok(Some(video))
+    }
+}
+
+/// Frame number of an input, the digits at the end of its file name
+pub fn frame_number(path: &Path) -> Option<u64> {
+    let stem = path.file_stem()?.to_str()?;
+    let digits = stem.len() - stem.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+    stem[stem.len() - digits..].parse().ok()
+}
+
+/// Put frames in order of their frame numbers
+///
+/// Numbers without leading zeros sort correctly, `frame_2` before `frame_10`.
+/// Inputs without a number keep their place after the numbered ones.
+pub fn order_frames(mut frames: Vec<PathBuf>) -> Vec<PathBuf> {
+    let unnumbered = frames.iter().filter(|frame| frame_number(frame).is_none()).count();
+    if unnumbered > 0 {
+        warn!("{}", tr_args(Msg::UnnumberedFrames, &[&unnumbered]).yellow());
+    }
+    frames.sort_by_key(|frame| frame_number(frame).map_or((1, 0), |number| (0, number)));
+    frames
+}
+
+/// First image generated for an input, as written by `FileSystemSink`
+fn first_output(output_dir: &Path, stem: &str) -> Option<PathBuf> {
+    let prefix = format!("{}-", stem);
+    fs::read_dir(output_dir.join(stem))
+        .ok()?
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let index: u32 = name.strip_prefix(&prefix)?.strip_suffix(".png")?.parse().ok()?;
+            Some((index, entry.path()))
+        })
+        .min()
+        .map(|(_, path)| path)
+}
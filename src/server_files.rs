@@ -0,0 +1,130 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+/**
+ * Server-side saved images for ControlNet Image Generator
+ *
+ * Instead of sending every image back as base64 in the txt2img response, the
+ * server can save the images itself and report where. The images are then
+ * fetched by path from the file endpoint of the server, which roughly halves
+ * the transferred data for big batches on a local network, and each download
+ * is checked against the SHA-256 reported by the server.
+ */
+use serde_json::Value;
+
+use crate::api::StableDiffusionResponse;
+use crate::digest::sha256_hex;
+
+/// Settings of fetching images saved by the server
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ServerFilesConfig {
+    /// Whether the server saves the images instead of sending them in the response
+    #[serde(default)]
+    pub enabled: bool,
+    /// Field of the generation info listing the paths of the saved images
+    #[serde(default = "default_paths_field")]
+    pub paths_field: String,
+    /// Field of the generation info listing the SHA-256 of each saved image
+    #[serde(default = "default_hashes_field")]
+    pub hashes_field: String,
+}
+
+impl Default for ServerFilesConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            paths_field: default_paths_field(),
+            hashes_field: default_hashes_field(),
+        }
+    }
+}
+
+/// Default field of the saved image paths - image_paths
+pub fn default_paths_field() -> String {
+    "image_paths".to_string()
+}
+
+/// Default field of the saved image hashes - image_sha256
+pub fn default_hashes_field() -> String {
+    "image_sha256".to_string()
+}
+
+/// Image saved by the server
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerFile {
+    /// Path of the image on the server
+    pub path: String,
+    /// SHA-256 of the image as hex, when the server reported it
+    pub sha256: Option<String>,
+}
+
+impl ServerFilesConfig {
+    /// Ask the server to save the images instead of sending them
+    ///
+    /// # Arguments
+    /// * `payload` - Body of the txt2img request
+    pub fn apply_to_payload(&self, payload: &mut Value) {
+        if self.enabled {
+            payload["save_images"] = Value::Bool(true);
+            payload["send_images"] = Value::Bool(false);
+        }
+    }
+
+    /// Images the server saved for a response, as listed in its generation info
+    ///
+    /// # Arguments
+    /// * `response` - Response of the txt2img request
+    ///
+    /// # Returns
+    /// The saved images in batch order, an error when the server reported none
+    pub fn files(&self, response: &StableDiffusionResponse) -> Result<Vec<ServerFile>> {
+        let info: Value = response
+            .info
+            .as_deref()
+            .map(serde_json::from_str)
+            .transpose()
+            .context("Failed to parse the generation info")?
+            .unwrap_or_default();
+        let paths: Vec<String> = info[&self.paths_field]
+            .as_array()
+            .map(|paths| paths.iter().filter_map(|path| path.as_str().map(str::to_string)).collect())
+            .unwrap_or_default();
+        if paths.is_empty() {
+            return Err(anyhow::anyhow!(
+                "The server did not report the saved images in '{}' of the generation info",
+                self.paths_field
+            ));
+        }
+        let hashes = info[&self.hashes_field].as_array();
+        Ok(paths
+            .into_iter()
+            .enumerate()
+            .map(|(index, path)| ServerFile {
+                path,
+                sha256: hashes
+                    .and_then(|hashes| hashes.get(index))
+                    .and_then(|hash| hash.as_str())
+                    .map(str::to_lowercase),
+            })
+            .collect())
+    }
+}
+
+impl ServerFile {
+    /// Check a download against the hash reported by the server
+    ///
+    /// Files without a reported hash are accepted as they are.
+    ///
+    /// # Arguments
+    /// * `bytes` - Downloaded contents
+    pub fn verify(&self, bytes: &[u8]) -> Result<()> {
+        match &self.sha256 {
+            Some(expected) if *expected != sha256_hex(bytes) => Err(anyhow::anyhow!(
+                "Checksum mismatch for {}: expected {}, got {}",
+                self.path,
+                expected,
+                sha256_hex(bytes)
+            )),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use anyhow::Result;
+/**
+ * Labeled image sheets for ControlNet Image Generator
+ *
+ * This module lays out images in a grid on a single canvas, with a label
+ * above every column and left of every row. Labels are drawn with a small
+ * built-in bitmap font, so no font files are needed; it covers digits,
+ * letters (shown in upper case) and common punctuation.
+ */
+use image::{DynamicImage, GenericImage, Rgb, RgbImage};
+
+/// Size of a font pixel in canvas pixels
+pub const GLYPH_SCALE: u32 = 3;
+
+/// Space around labels and between cells in pixels
+pub const PADDING: u32 = 8;
+
+/// Width and height of a glyph in font pixels
+const GLYPH_WIDTH: u32 = 5;
+const GLYPH_HEIGHT: u32 = 7;
+
+/// Color of the canvas behind the cells and labels
+pub const BACKGROUND: Rgb<u8> = Rgb([255, 255, 255]);
+const TEXT: Rgb<u8> = Rgb([0, 0, 0]);
+const MISSING: Rgb<u8> = Rgb([200, 200, 200]);
+
+/// Rows of a 5x7 glyph, the lowest five bits of each row from left to right
+fn glyph(character: char) -> [u8; 7] {
+    match character.to_ascii_uppercase() {
+        'A' => [0x0E, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'B' => [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E],
+        'C' => [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E],
+        'D' => [0x1E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x1E],
+        'E' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F],
+        'F' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10],
+        'G' => [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F],
+        'H' => [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'I' => [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        'J' => [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C],
+        'K' => [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11],
+        'L' => [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F],
+        'M' => [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11],
+        'N' => [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11],
+        'O' => [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'P' => [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10],
+        'Q' => [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D],
+        'R' => [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11],
+        'S' => [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E],
+        'T' => [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+        'U' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'V' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04],
+        'W' => [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A],
+        'X' => [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11],
+        'Y' => [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04],
+        'Z' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F],
+        '0' => [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E],
+        '1' => [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        '2' => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F],
+        '3' => [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E],
+        '4' => [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02],
+        '5' => [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E],
+        '6' => [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E],
+        '7' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E],
+        '9' => [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C],
+        ' ' => [0x00; 7],
+        '=' => [0x00, 0x00, 0x1F, 0x00, 0x1F, 0x00, 0x00],
+        '.' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C],
+        ',' => [0x00, 0x00, 0x00, 0x00, 0x0C, 0x04, 0x08],
+        ':' => [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00],
+        '-' => [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00],
+        '+' => [0x00, 0x04, 0x04, 0x1F, 0x04, 0x04, 0x00],
+        '_' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1F],
+        '/' => [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00],
+        '(' => [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02],
+        ')' => [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08],
+        _ => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04],
+    }
+}
+
+/// Width of a label in canvas pixels
+pub fn text_width(text: &str) -> u32 {
+    let characters = text.chars().count() as u32;
+    (characters * (GLYPH_WIDTH + 1)).saturating_sub(1) * GLYPH_SCALE
+}
+
+/// Height of a label in canvas pixels
+pub fn text_height() -> u32 {
+    GLYPH_HEIGHT * GLYPH_SCALE
+}
+
+/// Draw a label with its top left corner at the given position, clipped to the canvas
+pub fn draw_text(canvas: &mut RgbImage, x: u32, y: u32, text: &str) {
+    for (index, character) in text.chars().enumerate() {
+        let left = x + index as u32 * (GLYPH_WIDTH + 1) * GLYPH_SCALE;
+        for (row, bits) in glyph(character).iter().enumerate() {
+            for column in 0..GLYPH_WIDTH {
+                if bits & (1 << (GLYPH_WIDTH - 1 - column)) == 0 {
+                    continue;
+                }
+                for dy in 0..GLYPH_SCALE {
+                    for dx in 0..GLYPH_SCALE {
+                        let px = left + column * GLYPH_SCALE + dx;
+                        let py = y + row as u32 * GLYPH_SCALE + dy;
+                        if px < canvas.width() && py < canvas.height() {
+                            canvas.put_pixel(px, py, TEXT);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Lay out images in a grid with column and row labels
+///
+/// Missing cells, such as generations that failed, are filled with gray.
+/// Every cell has the size of the largest image.
+///
+/// # Arguments
+/// * `column_labels` - Label above each column
+/// * `row_labels` - Label left of each row, empty labels leave out the label column
+/// * `cells` - Images row by row, `column_labels.len() * row_labels.len()` of them
+pub fn render(column_labels: &[String], row_labels: &[String], cells: &[Option<DynamicImage>]) -> Result<RgbImage> {
+    let columns = column_labels.len() as u32;
+    let rows = row_labels.len() as u32;
+    if cells.len() as u32 != columns * rows {
+        return Err(anyhow::anyhow!(
+            "Expected {} images for a {}x{} sheet, got {}",
+            columns * rows,
+            columns,
+            rows,
+            cells.len()
+        ));
+    }
+    let (cell_width, cell_height) = cells
+        .iter()
+        .flatten()
+        .fold((0, 0), |(width, height), image| {
+            (width.max(image.width()), height.max(image.height()))
+        });
+    if cell_width == 0 {
+        return Err(anyhow::anyhow!("No images to put on the sheet"));
+    }
+
+    let label_column = row_labels
+        .iter()
+        .map(|label| text_width(label))
+        .max()
+        .filter(|width| *width > 0)
+        .map_or(0, |width| width + 2 * PADDING);
+    let label_row = text_height() + 2 * PADDING;
+    let width = label_column + columns * (cell_width + PADDING) + PADDING;
+    let height = label_row + rows * (cell_height + PADDING);
+    let mut canvas = RgbImage::from_pixel(width, height, BACKGROUND);
+
+    for (column, label) in column_labels.iter().enumerate() {
+        let left = label_column + PADDING + column as u32 * (cell_width + PADDING);
+        let centered = left + cell_width.saturating_sub(text_width(label)) / 2;
+        draw_text(&mut canvas, centered, PADDING, label);
+    }
+    for (row, label) in row_labels.iter().enumerate() {
+        let top = label_row + row as u32 * (cell_height + PADDING);
+        draw_text(&mut canvas, PADDING, top + cell_height.saturating_sub(text_height()) / 2, label);
+    }
+
+    for (index, cell) in cells.iter().enumerate() {
+        let column = index as u32 % columns;
+        let row = index as u32 / columns;
+        let left = label_column + PADDING + column * (cell_width + PADDING);
+        let top = label_row + row * (cell_height + PADDING);
+        match cell {
+            Some(image) => canvas.copy_from(&image.to_rgb8(), left, top)?,
+            None => {
+                let gray = RgbImage::from_pixel(cell_width, cell_height, MISSING);
+                canvas.copy_from(&gray, left, top)?;
+            }
+        }
+    }
+    Ok(canvas)
+}
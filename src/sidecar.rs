@@ -0,0 +1,104 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+/**
+ * Per-image sidecar files for ControlNet Image Generator
+ *
+ * This module reads optional YAML files placed next to input images, named
+ * after the image with a `.yml` or `.yaml` extension (`photo.png` uses
+ * `photo.yml`). They adjust settings for that one image, for example to give
+ * known-difficult inputs more retries than the rest of the batch. A
+ * `.urasoe.yml` file in the directory of the images provides defaults for
+ * every image in that directory.
+ */
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::processing::RetryOverrides;
+
+/// Extensions recognized for sidecar files, in lookup order
+const SIDECAR_EXTENSIONS: [&str; 2] = ["yml", "yaml"];
+
+/// File name of the sidecar applying to every image of its directory
+pub const DIRECTORY_SIDECAR: &str = ".urasoe.yml";
+
+/// Settings read from the sidecar file of an input image
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Sidecar {
+    /// Retry settings for this image
+    #[serde(flatten)]
+    pub retry: RetryOverrides,
+    /// Queue priority of this image, higher priorities are processed first
+    #[serde(default)]
+    pub priority: Option<i32>,
+    /// Prompt for this image, used by the `sidecar` prompt source
+    #[serde(default)]
+    pub prompt: Option<String>,
+    /// Negative prompt for this image, used by the `sidecar` prompt source
+    #[serde(default)]
+    pub negative_prompt: Option<String>,
+    /// Style reference image for this image, relative to its directory
+    #[serde(default)]
+    pub style: Option<String>,
+}
+
+impl Sidecar {
+    /// Path of the sidecar file of an image, if one exists
+    pub fn path_for(image_path: &Path) -> Option<PathBuf> {
+        SIDECAR_EXTENSIONS
+            .iter()
+            .map(|extension| image_path.with_extension(extension))
+            .find(|path| path.is_file())
+    }
+
+    /// Read the sidecar file of an image
+    ///
+    /// # Arguments
+    /// * `image_path` - Input image the sidecar belongs to
+    ///
+    /// # Returns
+    /// The settings of the image sidecar on top of those of the directory
+    /// sidecar, or the defaults when there is neither
+    pub fn load_for(image_path: &Path) -> Result<Self> {
+        let directory = image_path
+            .parent()
+            .map(|parent| parent.join(DIRECTORY_SIDECAR))
+            .filter(|path| path.is_file());
+        let directory = match directory {
+            Some(path) => Self::load(&path)?,
+            None => Self::default(),
+        };
+        let image = match Self::path_for(image_path) {
+            Some(path) => Self::load(&path)?,
+            None => Self::default(),
+        };
+        Ok(directory.merge(image))
+    }
+
+    /// Queue priority of an image, 0 unless a sidecar sets one
+    pub fn priority_for(image_path: &Path) -> Result<i32> {
+        Ok(Self::load_for(image_path)?.priority.unwrap_or_default())
+    }
+
+    /// Combine two sidecars, settings of `other` taking precedence
+    fn merge(self, other: Self) -> Self {
+        Self {
+            retry: RetryOverrides {
+                max_retries: other.retry.max_retries.or(self.retry.max_retries),
+                retry_delay_ms: other.retry.retry_delay_ms.or(self.retry.retry_delay_ms),
+            },
+            priority: other.priority.or(self.priority),
+            prompt: other.prompt.or(self.prompt),
+            negative_prompt: other.negative_prompt.or(self.negative_prompt),
+            style: other.style.or(self.style),
+        }
+    }
+
+    /// Read a single sidecar file
+    fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .context(format!("Failed to read sidecar file: {}", path.display()))?;
+        serde_yaml::from_str::<Option<Self>>(&text)
+            .map(Option::unwrap_or_default)
+            .context(format!("Failed to parse sidecar file: {}", path.display()))
+    }
+}
@@ -0,0 +1,309 @@
+use anyhow::{Context, Result};
+/**
+ * Output sinks for ControlNet Image Generator
+ *
+ * This module decides where generated images and their metadata end up.
+ * The `OutputSink` trait is implemented by `FileSystemSink`, which writes
+ * into the output directory as the command line always has, and by
+ * `MemorySink`, which keeps everything in memory for tests and embedding
+ * programs. `LabeledSink` adds a label to the output names of another sink.
+ * Other destinations, such as object storage or a database, only
+ * need to implement the trait.
+ */
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+use crate::composite::COMPOSITE_SUFFIX;
+use crate::config::Config;
+use crate::file_utils::ImageMetadata;
+use crate::processing::ProcessingStats;
+
+/// Destination of the images generated for each input
+pub trait OutputSink: Send + Sync {
+    /// Store the images generated for one input
+    ///
+    /// # Arguments
+    /// * `input_image_path` - Path of the input image the images were generated for
+    /// * `images` - Decoded PNG images, in the order the server returned them
+    ///
+    /// # Returns
+    /// * `Result<Vec<PathBuf>>` - Where each image was stored, paths or keys of the sink
+    fn save_images(&self, input_image_path: &Path, images: &[Vec<u8>]) -> Result<Vec<PathBuf>>;
+
+    /// Store the generation settings of one input
+    ///
+    /// # Returns
+    /// * `Result<PathBuf>` - Where the metadata was stored, a path or key of the sink
+    fn save_metadata(&self, input_image_path: &Path, metadata: &ImageMetadata) -> Result<PathBuf>;
+
+    /// Store the generation parameters of each image of one input as infotext
+    ///
+    /// Sinks that have no use for them can leave them out, which is the default.
+    ///
+    /// # Arguments
+    /// * `input_image_path` - Path of the input image the images were generated for
+    /// * `parameters` - Infotext of each image, in the order of `save_images`
+    ///
+    /// # Returns
+    /// * `Result<Vec<PathBuf>>` - Where each text was stored, paths or keys of the sink
+    fn save_parameters(&self, _input_image_path: &Path, _parameters: &[String]) -> Result<Vec<PathBuf>> {
+        Ok(Vec::new())
+    }
+
+    /// Store the before/after composite of each image of one input
+    ///
+    /// Sinks that have no use for them can leave them out, which is the default.
+    ///
+    /// # Arguments
+    /// * `input_image_path` - Path of the input image the images were generated for
+    /// * `composites` - PNG composite of each image, in the order of `save_images`
+    ///
+    /// # Returns
+    /// * `Result<Vec<PathBuf>>` - Where each composite was stored, paths or keys of the sink
+    fn save_composites(&self, _input_image_path: &Path, _composites: &[Vec<u8>]) -> Result<Vec<PathBuf>> {
+        Ok(Vec::new())
+    }
+
+    /// Called once when a run ends, e.g. to flush or upload a summary
+    fn finalize_run(&self, _stats: &ProcessingStats) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// File name stem of an input, naming its outputs
+fn input_stem(input_image_path: &Path) -> Result<String> {
+    Ok(input_image_path
+        .file_stem()
+        .context("Failed to extract file name")?
+        .to_string_lossy()
+        .to_string())
+}
+
+/// Writes into one subdirectory of the output directory per input:
+/// `<stem>/<stem>-1.png`, `<stem>/<stem>-2.png` and `<stem>/<stem>-metadata.json`,
+/// with the parameters of each image in `<stem>/<stem>-1-parameters.txt` and its
+/// composite in `<stem>/<stem>-1-composite.png` and so on
+///
+/// With an input directory to mirror, the subdirectories of the inputs below it
+/// are repeated in the output directory.
+#[derive(Debug, Clone)]
+pub struct FileSystemSink {
+    output_dir: PathBuf,
+    mirrored_input_dir: Option<PathBuf>,
+}
+
+impl FileSystemSink {
+    /// Sink writing into the given output directory
+    pub fn new(output_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            output_dir: output_dir.into(),
+            mirrored_input_dir: None,
+        }
+    }
+
+    /// Sink writing into the output directory of a configuration, mirroring the input folders of recursive runs
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            output_dir: PathBuf::from(&config.output_dir),
+            mirrored_input_dir: config.recursive.then(|| PathBuf::from(&config.input_dir)),
+        }
+    }
+
+    /// Directory holding the outputs of an input, created when missing
+    fn input_dir(&self, input_image_path: &Path) -> Result<(PathBuf, String)> {
+        let stem = input_stem(input_image_path)?;
+        let mirrored = self
+            .mirrored_input_dir
+            .as_ref()
+            .and_then(|input_dir| input_image_path.parent()?.strip_prefix(input_dir).ok())
+            .unwrap_or(Path::new(""));
+        let dir = self.output_dir.join(mirrored).join(&stem);
+        fs::create_dir_all(&dir).context("Failed to create output subdirectory")?;
+        Ok((dir, stem))
+    }
+}
+
+impl OutputSink for FileSystemSink {
+    fn save_images(&self, input_image_path: &Path, images: &[Vec<u8>]) -> Result<Vec<PathBuf>> {
+        let (dir, stem) = self.input_dir(input_image_path)?;
+        let mut saved = Vec::with_capacity(images.len());
+        for (index, image) in images.iter().enumerate() {
+            let output_path = dir.join(format!("{}-{}.png", stem, index + 1));
+            fs::write(&output_path, image).context("Failed to write image file")?;
+            saved.push(output_path);
+        }
+        Ok(saved)
+    }
+
+    fn save_metadata(&self, input_image_path: &Path, metadata: &ImageMetadata) -> Result<PathBuf> {
+        let (dir, stem) = self.input_dir(input_image_path)?;
+        let metadata_path = dir.join(format!("{}-metadata.json", stem));
+        fs::write(&metadata_path, serde_json::to_string_pretty(metadata)?)
+            .context("Failed to write metadata file")?;
+        Ok(metadata_path)
+    }
+
+    fn save_parameters(&self, input_image_path: &Path, parameters: &[String]) -> Result<Vec<PathBuf>> {
+        let (dir, stem) = self.input_dir(input_image_path)?;
+        let mut saved = Vec::with_capacity(parameters.len());
+        for (index, text) in parameters.iter().enumerate() {
+            let parameters_path = dir.join(format!("{}-{}-parameters.txt", stem, index + 1));
+            fs::write(&parameters_path, text).context("Failed to write parameters file")?;
+            saved.push(parameters_path);
+        }
+        Ok(saved)
+    }
+
+    fn save_composites(&self, input_image_path: &Path, composites: &[Vec<u8>]) -> Result<Vec<PathBuf>> {
+        let (dir, stem) = self.input_dir(input_image_path)?;
+        let mut saved = Vec::with_capacity(composites.len());
+        for (index, composite) in composites.iter().enumerate() {
+            let composite_path = dir.join(format!("{}-{}{}.png", stem, index + 1, COMPOSITE_SUFFIX));
+            fs::write(&composite_path, composite).context("Failed to write composite file")?;
+            saved.push(composite_path);
+        }
+        Ok(saved)
+    }
+}
+
+/// Keeps images and metadata in memory, under the paths `FileSystemSink` would use
+/// relative to the output directory
+#[derive(Debug, Default)]
+pub struct MemorySink {
+    images: Mutex<BTreeMap<PathBuf, Vec<u8>>>,
+    metadata: Mutex<BTreeMap<PathBuf, ImageMetadata>>,
+    parameters: Mutex<BTreeMap<PathBuf, String>>,
+    composites: Mutex<BTreeMap<PathBuf, Vec<u8>>>,
+    finished_runs: Mutex<Vec<ProcessingStats>>,
+}
+
+impl MemorySink {
+    /// Empty sink
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stored images by path
+    pub fn images(&self) -> BTreeMap<PathBuf, Vec<u8>> {
+        lock(&self.images).clone()
+    }
+
+    /// Stored metadata by the path of its input
+    pub fn metadata(&self) -> BTreeMap<PathBuf, ImageMetadata> {
+        lock(&self.metadata).clone()
+    }
+
+    /// Stored infotexts by path
+    pub fn parameters(&self) -> BTreeMap<PathBuf, String> {
+        lock(&self.parameters).clone()
+    }
+
+    /// Stored composites by path
+    pub fn composites(&self) -> BTreeMap<PathBuf, Vec<u8>> {
+        lock(&self.composites).clone()
+    }
+
+    /// Statistics of every run that was finalized
+    pub fn finished_runs(&self) -> Vec<ProcessingStats> {
+        lock(&self.finished_runs).clone()
+    }
+}
+
+impl OutputSink for MemorySink {
+    fn save_images(&self, input_image_path: &Path, images: &[Vec<u8>]) -> Result<Vec<PathBuf>> {
+        let stem = input_stem(input_image_path)?;
+        let mut stored = lock(&self.images);
+        let mut saved = Vec::with_capacity(images.len());
+        for (index, image) in images.iter().enumerate() {
+            let path = Path::new(&stem).join(format!("{}-{}.png", stem, index + 1));
+            stored.insert(path.clone(), image.clone());
+            saved.push(path);
+        }
+        Ok(saved)
+    }
+
+    fn save_metadata(&self, input_image_path: &Path, metadata: &ImageMetadata) -> Result<PathBuf> {
+        lock(&self.metadata).insert(input_image_path.to_path_buf(), metadata.clone());
+        Ok(input_image_path.to_path_buf())
+    }
+
+    fn save_parameters(&self, input_image_path: &Path, parameters: &[String]) -> Result<Vec<PathBuf>> {
+        let stem = input_stem(input_image_path)?;
+        let mut stored = lock(&self.parameters);
+        let mut saved = Vec::with_capacity(parameters.len());
+        for (index, text) in parameters.iter().enumerate() {
+            let path = Path::new(&stem).join(format!("{}-{}-parameters.txt", stem, index + 1));
+            stored.insert(path.clone(), text.clone());
+            saved.push(path);
+        }
+        Ok(saved)
+    }
+
+    fn save_composites(&self, input_image_path: &Path, composites: &[Vec<u8>]) -> Result<Vec<PathBuf>> {
+        let stem = input_stem(input_image_path)?;
+        let mut stored = lock(&self.composites);
+        let mut saved = Vec::with_capacity(composites.len());
+        for (index, composite) in composites.iter().enumerate() {
+            let path = Path::new(&stem).join(format!("{}-{}{}.png", stem, index + 1, COMPOSITE_SUFFIX));
+            stored.insert(path.clone(), composite.clone());
+            saved.push(path);
+        }
+        Ok(saved)
+    }
+
+    fn finalize_run(&self, stats: &ProcessingStats) -> Result<()> {
+        lock(&self.finished_runs).push(stats.clone());
+        Ok(())
+    }
+}
+
+/// Stores the outputs of an input in another sink under a labeled name,
+/// e.g. `kata.png` with the label `weight-0.6` as if the input were `kata-weight-0.6.png`
+///
+/// Used for the outputs of one value of a sweep. It does not finalize the
+/// run, that is left to the sink it wraps.
+pub struct LabeledSink<'a> {
+    inner: &'a dyn OutputSink,
+    label: &'a str,
+}
+
+impl<'a> LabeledSink<'a> {
+    /// Sink adding `label` to the names of the outputs stored in `inner`
+    pub fn new(inner: &'a dyn OutputSink, label: &'a str) -> Self {
+        Self { inner, label }
+    }
+
+    /// Input path the wrapped sink names the outputs after
+    fn labeled(&self, input_image_path: &Path) -> Result<PathBuf> {
+        let stem = input_stem(input_image_path)?;
+        let mut name = format!("{}-{}", stem, self.label);
+        if let Some(extension) = input_image_path.extension() {
+            name = format!("{}.{}", name, extension.to_string_lossy());
+        }
+        Ok(input_image_path.with_file_name(name))
+    }
+}
+
+impl OutputSink for LabeledSink<'_> {
+    fn save_images(&self, input_image_path: &Path, images: &[Vec<u8>]) -> Result<Vec<PathBuf>> {
+        self.inner.save_images(&self.labeled(input_image_path)?, images)
+    }
+
+    fn save_metadata(&self, input_image_path: &Path, metadata: &ImageMetadata) -> Result<PathBuf> {
+        self.inner.save_metadata(&self.labeled(input_image_path)?, metadata)
+    }
+
+    fn save_parameters(&self, input_image_path: &Path, parameters: &[String]) -> Result<Vec<PathBuf>> {
+        self.inner.save_parameters(&self.labeled(input_image_path)?, parameters)
+    }
+
+    fn save_composites(&self, input_image_path: &Path, composites: &[Vec<u8>]) -> Result<Vec<PathBuf>> {
+        self.inner.save_composites(&self.labeled(input_image_path)?, composites)
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
@@ -0,0 +1,136 @@
+/**
+ * Live status line for ControlNet Image Generator
+ *
+ * This module keeps one line below the scrolling log lines of a run up to
+ * date: how many inputs are finished with the time left, and the sampling
+ * step of each input being generated with the estimate of its server. The
+ * line is redrawn in place, so it is only shown when the log lines go to a
+ * terminal; plain logs report the progress line by line instead.
+ */
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+
+use crate::control::{RunControl, RunStatus, SamplingProgress};
+use crate::logging;
+
+/// How often the status line is redrawn
+const REFRESH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Width of the progress bar of the run in characters
+const RUN_BAR_WIDTH: usize = 20;
+
+/// Width of the progress bar of an input in characters
+const SAMPLING_BAR_WIDTH: usize = 10;
+
+/// Terminal width assumed when `COLUMNS` is not set
+const DEFAULT_COLUMNS: usize = 80;
+
+/// A status line being redrawn until the run ends
+pub struct StatusLine {
+    task: JoinHandle<()>,
+}
+
+impl StatusLine {
+    /// Start redrawing the status line below the log lines
+    ///
+    /// The sampling progress of the inputs is polled from then on.
+    ///
+    /// # Returns
+    /// The status line, or `None` when the log lines do not go to a terminal
+    pub fn start(control: Arc<RunControl>) -> Option<Self> {
+        if !logging::supports_status_line() {
+            return None;
+        }
+        control.show_sampling();
+        let task = tokio::spawn(async move {
+            loop {
+                // Questions asked before processing starts are not drawn over
+                if control.is_processing() {
+                    logging::set_status_line(Some(render(&control.status(), Instant::now())));
+                }
+                tokio::time::sleep(REFRESH_INTERVAL).await;
+            }
+        });
+        Some(Self { task })
+    }
+
+    /// Stop redrawing and remove the status line
+    pub fn stop(self) {
+        self.task.abort();
+        logging::set_status_line(None);
+    }
+}
+
+/// Status line for the given state of the run, cut to the width of the terminal
+///
+/// # Arguments
+/// * `status` - Progress of the run
+/// * `now` - Current time, for the time left
+pub fn render(status: &RunStatus, now: Instant) -> String {
+    let finished = status.done + status.failed;
+    let mut line = format!(
+        "{} {}/{} inputs",
+        bar(finished, status.total, RUN_BAR_WIDTH),
+        finished,
+        status.total
+    );
+    if status.failed > 0 {
+        line.push_str(&format!(", {} failed", status.failed));
+    }
+    if let Some(remaining) = status.remaining() {
+        line.push_str(&format!(", {} left", format_seconds(remaining)));
+    }
+    for active in &status.active {
+        let name = active.path.file_name().unwrap_or_default().to_string_lossy();
+        match &active.sampling {
+            Some(sampling) => line.push_str(&format!(" | {} {}", name, sampling_text(sampling))),
+            None => line.push_str(&format!(
+                " | {} {}",
+                name,
+                format_seconds(now.saturating_duration_since(active.started))
+            )),
+        }
+    }
+    let columns = std::env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_COLUMNS);
+    // The last column is left free, as some terminals wrap when it is written
+    line.chars().take(columns.saturating_sub(1)).collect()
+}
+
+/// Progress bar of an input with its sampling step and the time left, e.g. `[####------] 12/30 steps, 8s left`
+pub fn sampling_text(sampling: &SamplingProgress) -> String {
+    let mut text = format!(
+        "{} {}/{} steps",
+        bar(sampling.step as usize, sampling.steps as usize, SAMPLING_BAR_WIDTH),
+        sampling.step,
+        sampling.steps
+    );
+    if let Some(eta) = sampling.eta {
+        text.push_str(&format!(", {} left", format_seconds(eta)));
+    }
+    text
+}
+
+/// Progress bar with `#` for the finished and `-` for the remaining part
+///
+/// # Arguments
+/// * `finished` - Finished units
+/// * `total` - Units in total
+/// * `width` - Width of the bar between the brackets, in characters
+pub fn bar(finished: usize, total: usize, width: usize) -> String {
+    let filled = (finished * width).checked_div(total).unwrap_or(0).min(width);
+    format!("[{}{}]", "#".repeat(filled), "-".repeat(width - filled))
+}
+
+/// Duration as whole seconds, with minutes once it is longer than one, e.g. `8s` or `2m05s`
+fn format_seconds(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    if seconds < 60 {
+        format!("{}s", seconds)
+    } else {
+        format!("{}m{:02}s", seconds / 60, seconds % 60)
+    }
+}
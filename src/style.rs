@@ -0,0 +1,86 @@
+#[cfg(feature = "cli")]
+pub use colored::{ColoredString, Colorize};
+/**
+ * Terminal styling for ControlNet Image Generator
+ *
+ * Log messages highlight their labels with colors. With the `cli` feature the
+ * `colored` crate paints them; without it, as when the library is embedded in
+ * another program, the same calls leave the text plain, so the core modules
+ * do not depend on terminal machinery.
+ */
+#[cfg(not(feature = "cli"))]
+pub use plain::{ColoredString, Colorize};
+
+/// Turn colors on or off regardless of the terminal
+pub fn set_enabled(enabled: bool) {
+    #[cfg(feature = "cli")]
+    colored::control::set_override(enabled);
+    #[cfg(not(feature = "cli"))]
+    let _ = enabled;
+}
+
+#[cfg(not(feature = "cli"))]
+mod plain {
+    use std::fmt;
+    use std::ops::Deref;
+
+    /// Text with the styling dropped
+    #[derive(Debug, Clone, Default, PartialEq, Eq)]
+    pub struct ColoredString(String);
+
+    impl fmt::Display for ColoredString {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.pad(&self.0)
+        }
+    }
+
+    impl Deref for ColoredString {
+        type Target = str;
+
+        fn deref(&self) -> &str {
+            &self.0
+        }
+    }
+
+    /// The styling methods of `colored::Colorize` used by this crate, all leaving text unchanged
+    pub trait Colorize: Sized {
+        /// The text without styling
+        fn plain(self) -> ColoredString;
+
+        fn blue(self) -> ColoredString {
+            self.plain()
+        }
+
+        fn green(self) -> ColoredString {
+            self.plain()
+        }
+
+        fn red(self) -> ColoredString {
+            self.plain()
+        }
+
+        fn yellow(self) -> ColoredString {
+            self.plain()
+        }
+
+        fn bold(self) -> ColoredString {
+            self.plain()
+        }
+
+        fn dimmed(self) -> ColoredString {
+            self.plain()
+        }
+    }
+
+    impl Colorize for &str {
+        fn plain(self) -> ColoredString {
+            ColoredString(self.to_string())
+        }
+    }
+
+    impl Colorize for ColoredString {
+        fn plain(self) -> ColoredString {
+            self
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+/**
+ * Style references for ControlNet Image Generator
+ *
+ * This module pairs every structure input with a style reference image. The
+ * input guides the composition through the ControlNet unit of the
+ * configuration, while a second unit, such as IP-Adapter or reference-only,
+ * carries over the look of the style image in the same request. The style
+ * image comes from the sidecar file of the input, from a style directory
+ * holding an image of the same name, or from a default style image.
+ */
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+
+use crate::api_types::ControlNetUnit;
+use crate::i18n::{Msg, tr_args};
+use crate::image::image_to_base64;
+use crate::sidecar::Sidecar;
+use crate::style::*;
+
+/// Extensions of style images looked up in the style directory, in lookup order
+const STYLE_EXTENSIONS: [&str; 4] = ["png", "jpg", "jpeg", "webp"];
+
+/// Where the style reference of each input comes from and how it is applied
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StyleReferenceConfig {
+    /// Directory with a style image named after each input, `styles/kata.jpg` for `kata.png`
+    #[serde(default)]
+    pub dir: Option<String>,
+    /// Style image of the inputs without one of their own
+    #[serde(default)]
+    pub default: Option<String>,
+    /// Preprocessor of the style unit
+    #[serde(default = "default_style_module")]
+    pub module: String,
+    /// ControlNet model of the style unit as known to the server, "None" for reference-only
+    #[serde(default = "default_style_model")]
+    pub model: String,
+    /// Strength of the style unit
+    #[serde(default = "default_style_weight")]
+    pub weight: f32,
+}
+
+impl Default for StyleReferenceConfig {
+    fn default() -> Self {
+        Self {
+            dir: None,
+            default: None,
+            module: default_style_module(),
+            model: default_style_model(),
+            weight: default_style_weight(),
+        }
+    }
+}
+
+/// Default preprocessor of the style unit - ip-adapter_clip_sd15
+pub fn default_style_module() -> String {
+    "ip-adapter_clip_sd15".to_string()
+}
+
+/// Default model of the style unit - ip-adapter_sd15
+pub fn default_style_model() -> String {
+    "ip-adapter_sd15".to_string()
+}
+
+/// Default strength of the style unit - 0.8
+pub fn default_style_weight() -> f32 {
+    0.8
+}
+
+impl StyleReferenceConfig {
+    /// Style image of an input
+    ///
+    /// A `style` set in the sidecar files of the input comes first, relative
+    /// to the directory of the input, then an image of the same file stem in
+    /// the style directory, then the default style image.
+    ///
+    /// # Arguments
+    /// * `image_path` - Structure input
+    ///
+    /// # Returns
+    /// Path of the style image, `None` when the input has none
+    pub fn find(&self, image_path: &Path) -> Result<Option<PathBuf>> {
+        if let Some(style) = Sidecar::load_for(image_path)?.style {
+            return Ok(Some(image_path.parent().unwrap_or(Path::new("")).join(style)));
+        }
+        if let Some(dir) = &self.dir {
+            let stem = image_path.file_stem().unwrap_or_default();
+            let found = STYLE_EXTENSIONS
+                .iter()
+                .map(|extension| Path::new(dir).join(stem).with_extension(extension))
+                .find(|path| path.is_file());
+            if found.is_some() {
+                return Ok(found);
+            }
+        }
+        Ok(self.default.as_ref().map(PathBuf::from))
+    }
+
+    /// ControlNet unit applying the style image of an input
+    ///
+    /// Inputs without a style image are generated from their structure alone,
+    /// with a warning when style references are configured.
+    ///
+    /// # Arguments
+    /// * `image_path` - Structure input
+    ///
+    /// # Returns
+    /// The style unit with its image, `None` when the input has no style image
+    pub fn unit_for(&self, image_path: &Path) -> Result<Option<ControlNetUnit>> {
+        let Some(style) = self.find(image_path)? else {
+            if self.dir.is_some() {
+                warn!("{}", tr_args(Msg::NoStyleReference, &[&image_path.display()]).yellow());
+            }
+            return Ok(None);
+        };
+        debug!("{} {} {}", "Pairing".blue(), image_path.display(), format!("with style {}", style.display()).blue());
+        Ok(Some(ControlNetUnit {
+            input_image: Some(image_to_base64(&style)?),
+            ..ControlNetUnit::new(&self.module, &self.model, self.weight)
+        }))
+    }
+}
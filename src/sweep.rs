@@ -0,0 +1,110 @@
+use serde::{Deserialize, Serialize};
+/**
+ * Parameter sweeps for ControlNet Image Generator
+ *
+ * This module lets a normal run generate every input once for each value of
+ * a swept setting, with the seed held fixed so only that setting changes
+ * between the outputs. The outputs of each value are labeled with it, e.g.
+ * `kata-weight-0.6/kata-weight-0.6-1.png`, so tuning the ControlNet weight
+ * no longer takes a run per weight. A seed sweep instead generates variants
+ * with consecutive seeds, labeled e.g. `kata-seed-42`.
+ */
+use tracing::info;
+
+use crate::config::Config;
+use crate::i18n::{Msg, tr_args};
+use crate::style::*;
+
+/// Values to sweep over for every input
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SweepConfig {
+    /// ControlNet weights to generate each input with, e.g. [0.3, 0.6, 0.9, 1.2]
+    #[serde(default)]
+    pub controlnet_weight: Vec<f32>,
+    /// Variants to generate with consecutive seeds, starting from the configured one, 0 for none
+    #[serde(default)]
+    pub seeds: u32,
+}
+
+/// Configuration of one generation of a sweep
+#[derive(Debug, Clone)]
+pub struct SweepVariant {
+    /// Label added to the names of the outputs, `None` when not sweeping
+    pub label: Option<String>,
+    /// Configuration to generate with
+    pub config: Config,
+}
+
+impl SweepConfig {
+    /// Whether no values are swept
+    pub fn is_empty(&self) -> bool {
+        self.controlnet_weight.is_empty() && self.seeds == 0
+    }
+
+    /// Configurations to generate one input with
+    ///
+    /// A random seed is drawn once when none is configured, so every value
+    /// of the sweep uses the same seed. With a seed sweep, every weight is
+    /// generated with each of the consecutive seeds.
+    ///
+    /// # Arguments
+    /// * `config` - Configuration of the run
+    ///
+    /// # Returns
+    /// One variant per swept value, or the configuration itself without a label
+    pub fn variants(&self, config: &Config) -> Vec<SweepVariant> {
+        if self.is_empty() {
+            return vec![SweepVariant {
+                label: None,
+                config: config.clone(),
+            }];
+        }
+
+        let mut base = config.clone();
+        if base.seed < 0 {
+            base.seed = rand::random_range(0..i64::from(u32::MAX));
+            info!("{}", tr_args(Msg::SweepingWithSeed, &[&base.seed]).blue());
+        }
+        let weights: Vec<(Option<String>, f32)> = if self.controlnet_weight.is_empty() {
+            vec![(None, base.controlnet_weight)]
+        } else {
+            self.controlnet_weight.iter().map(|&weight| (Some(weight_label(weight)), weight)).collect()
+        };
+        let seeds: Vec<(Option<String>, i64)> = if self.seeds == 0 {
+            vec![(None, base.seed)]
+        } else {
+            (0..i64::from(self.seeds))
+                .map(|offset| (Some(seed_label(base.seed + offset)), base.seed + offset))
+                .collect()
+        };
+        weights
+            .iter()
+            .flat_map(|(weight_label, weight)| {
+                seeds.iter().map(|(seed_label, seed)| SweepVariant {
+                    label: Some(
+                        [weight_label.as_deref(), seed_label.as_deref()]
+                            .into_iter()
+                            .flatten()
+                            .collect::<Vec<_>>()
+                            .join("-"),
+                    ),
+                    config: Config {
+                        controlnet_weight: *weight,
+                        seed: *seed,
+                        ..base.clone()
+                    },
+                })
+            })
+            .collect()
+    }
+}
+
+/// Label of the outputs generated with a ControlNet weight, e.g. "weight-0.6"
+pub fn weight_label(weight: f32) -> String {
+    format!("weight-{}", weight)
+}
+
+/// Label of the outputs generated with a seed, e.g. "seed-42"
+pub fn seed_label(seed: i64) -> String {
+    format!("seed-{}", seed)
+}
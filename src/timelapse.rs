@@ -0,0 +1,106 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+/**
+ * Run timelapses for ControlNet Image Generator
+ *
+ * This module assembles the images generated by a run into a timelapse video
+ * once the run ends, in the order they were completed, to share what an
+ * overnight batch produced. The video is encoded with ffmpeg, as MP4 or, for
+ * files ending in `.webm`, as WebM.
+ */
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+use crate::ffmpeg;
+use crate::i18n::{Msg, tr, tr_args};
+use crate::processing::ProcessingStats;
+use crate::style::*;
+
+/// File listing the images of the timelapse for ffmpeg, inside the output directory
+const TIMELAPSE_LIST: &str = ".timelapse.txt";
+
+/// Settings of run timelapses
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TimelapseConfig {
+    /// Video file to assemble the generated images into, relative to output_dir
+    #[serde(default)]
+    pub video: Option<String>,
+    /// Images shown per second
+    #[serde(default = "default_timelapse_fps")]
+    pub fps: u32,
+}
+
+impl Default for TimelapseConfig {
+    fn default() -> Self {
+        Self {
+            video: None,
+            fps: default_timelapse_fps(),
+        }
+    }
+}
+
+/// Default rate of timelapses - 4 images per second
+pub fn default_timelapse_fps() -> u32 {
+    4
+}
+
+/// Generated images of a run in the order they were completed
+///
+/// Outputs that are not files, as stored by sinks other than the output
+/// directory, are left out.
+///
+/// # Arguments
+/// * `stats` - Statistics of the run, listing the outputs of each input
+pub fn completed_images(stats: &ProcessingStats) -> Vec<PathBuf> {
+    stats
+        .images
+        .iter()
+        .flat_map(|image| image.outputs.iter().map(PathBuf::from))
+        .filter(|output| output.is_file())
+        .collect()
+}
+
+impl TimelapseConfig {
+    /// Assemble the images generated by a run into the configured video
+    ///
+    /// Images of differing sizes are scaled and padded to the size of the run.
+    ///
+    /// # Arguments
+    /// * `stats` - Statistics of the finished run
+    /// * `output_dir` - Output directory of the run
+    /// * `size` - Width and height of the video
+    ///
+    /// # Returns
+    /// Path of the video, `None` when none was configured, nothing was generated or ffmpeg is missing
+    pub async fn assemble(
+        &self,
+        stats: &ProcessingStats,
+        output_dir: &Path,
+        size: (u32, u32),
+    ) -> Result<Option<PathBuf>> {
+        let Some(video) = &self.video else {
+            return Ok(None);
+        };
+        let images = completed_images(stats);
+        if images.is_empty() {
+            warn!("{}", tr(Msg::NoTimelapseImages).yellow());
+            return Ok(None);
+        }
+        let images: Vec<String> = images
+            .iter()
+            .map(|image| fs::canonicalize(image).unwrap_or_else(|_| image.clone()).to_string_lossy().to_string())
+            .collect();
+
+        let list = output_dir.join(TIMELAPSE_LIST);
+        ffmpeg::write_concat_list(&list, &images, self.fps)?;
+        let video = output_dir.join(video);
+        let encoded = ffmpeg::encode(&list, self.fps, Some(size), &video).await;
+        let _ = fs::remove_file(&list);
+        if !encoded? {
+            return Ok(None);
+        }
+        info!("{}", tr_args(Msg::AssembledTimelapse, &[&video.display(), &images.len()]).green());
+        Ok(Some(video))
+    }
+}
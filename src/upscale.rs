@@ -0,0 +1,162 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+/**
+ * Upscaling pass for ControlNet Image Generator
+ *
+ * This module upscales the images of an earlier run with an upscaler of the
+ * extras tab of the server, as a pass of its own. Images that are not wanted
+ * can be deleted first, so only the favorites cost upscaling time. Each image
+ * gets an upscaled companion next to it, e.g. `kata-1-up.png` for `kata-1.png`.
+ */
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+use crate::api::StableDiffusionClient;
+use crate::file_utils::DEAD_LETTER_DIR;
+use crate::i18n::{Msg, tr_args};
+use crate::preview::PREVIEWS_DIR;
+use crate::selection::REJECTED_DIR;
+use crate::sequence::FRAMES_DIR;
+use crate::style::*;
+
+/// Suffix of the file stem of upscaled images
+pub const UPSCALED_SUFFIX: &str = "-up";
+
+/// Folders of an output directory whose images are not upscaled
+const SKIPPED_DIRS: [&str; 4] = [DEAD_LETTER_DIR, REJECTED_DIR, PREVIEWS_DIR, FRAMES_DIR];
+
+/// Settings of the upscaling pass
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct UpscaleConfig {
+    /// Upscaler of the extras tab, as listed by the server
+    #[serde(default = "default_upscaler")]
+    pub upscaler: String,
+    /// Factor the width and height are multiplied by
+    #[serde(default = "default_scale")]
+    pub scale: f32,
+}
+
+impl Default for UpscaleConfig {
+    fn default() -> Self {
+        Self {
+            upscaler: default_upscaler(),
+            scale: default_scale(),
+        }
+    }
+}
+
+/// Default upscaler - R-ESRGAN 4x+
+pub fn default_upscaler() -> String {
+    "R-ESRGAN 4x+".to_string()
+}
+
+/// Default upscaling factor - 2
+pub fn default_scale() -> f32 {
+    2.0
+}
+
+/// What an upscaling pass did
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct UpscaleSummary {
+    /// Upscaled images written
+    pub upscaled: Vec<PathBuf>,
+    /// Images skipped as they already had an upscaled companion
+    pub skipped: usize,
+    /// Images that failed to upscale
+    pub failed: Vec<PathBuf>,
+}
+
+/// Upscaled companion of a generated image, `kata-1-up.png` for `kata-1.png`
+pub fn upscaled_path(image: &Path) -> PathBuf {
+    let stem = image.file_stem().unwrap_or_default().to_string_lossy();
+    image.with_file_name(format!("{}{}.png", stem, UPSCALED_SUFFIX))
+}
+
+/// Generated images of a run directory, the `<stem>-<n>.png` files
+///
+/// The dead-letter, rejected, previews and sequence folders are left out, as
+/// are images that are already upscaled.
+///
+/// # Arguments
+/// * `run_dir` - Output directory of the run
+///
+/// # Returns
+/// The images sorted by path
+pub fn find_images(run_dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut images = Vec::new();
+    collect_images(run_dir, &mut images)?;
+    images.sort();
+    Ok(images)
+}
+
+fn collect_images(dir: &Path, images: &mut Vec<PathBuf>) -> Result<()> {
+    let entries = fs::read_dir(dir).context(format!("Failed to read {}", dir.display()))?;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+        if path.is_dir() {
+            if !SKIPPED_DIRS.contains(&name.as_str()) {
+                collect_images(&path, images)?;
+            }
+        } else if is_generated_image(&name) {
+            images.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Whether a file name is that of a generated image, ending in `-<n>.png`
+fn is_generated_image(name: &str) -> bool {
+    name.strip_suffix(".png")
+        .and_then(|stem| stem.rsplit_once('-'))
+        .is_some_and(|(_, index)| !index.is_empty() && index.chars().all(|c| c.is_ascii_digit()))
+}
+
+impl UpscaleConfig {
+    /// Upscale the generated images of a run directory
+    ///
+    /// Images that already have an upscaled companion are skipped unless
+    /// `force` is set, so an interrupted pass can be run again. An image that
+    /// fails to upscale is reported and the others are still upscaled.
+    ///
+    /// # Arguments
+    /// * `client` - Client of the server doing the upscaling
+    /// * `run_dir` - Output directory of the run
+    /// * `force` - Upscale images again that already have a companion
+    pub async fn run(&self, client: &StableDiffusionClient, run_dir: &Path, force: bool) -> Result<UpscaleSummary> {
+        let images = find_images(run_dir)?;
+        info!("{}", tr_args(Msg::Upscaling, &[&images.len(), &self.upscaler, &self.scale]).blue());
+
+        let mut summary = UpscaleSummary::default();
+        for image in images {
+            let target = upscaled_path(&image);
+            if target.exists() && !force {
+                summary.skipped += 1;
+                continue;
+            }
+            let upscaled = client.upscale(&image, &self.upscaler, self.scale).await;
+            match upscaled.and_then(|bytes| {
+                fs::write(&target, bytes).context(format!("Failed to write {}", target.display()))
+            }) {
+                Ok(()) => {
+                    info!("{}", tr_args(Msg::Upscaled, &[&target.display()]).green());
+                    summary.upscaled.push(target);
+                }
+                Err(e) => {
+                    warn!("{}", tr_args(Msg::UpscaleFailed, &[&image.display(), &format!("{:#}", e)]).yellow());
+                    summary.failed.push(image);
+                }
+            }
+        }
+        info!(
+            "{}",
+            tr_args(
+                Msg::UpscaleSummary,
+                &[&summary.upscaled.len(), &summary.skipped, &summary.failed.len()]
+            )
+            .green()
+        );
+        Ok(summary)
+    }
+}
@@ -0,0 +1,165 @@
+use anyhow::{Context, Result};
+/**
+ * Version information for ControlNet Image Generator
+ *
+ * This module implements `urasoe --version`, which tells which build is
+ * running: the package version, git commit, build date and enabled
+ * features. With `--check` it also asks every configured server for its
+ * Web UI and ControlNet extension versions and whether this build is known
+ * to work with them.
+ */
+use std::fmt::{self, Write};
+
+use crate::api::StableDiffusionClient;
+use crate::config::Config;
+use crate::exit::ExitStatus;
+use crate::style::*;
+
+/// Short hash of the commit this build was made from
+pub const GIT_HASH: &str = env!("URASOE_GIT_HASH");
+
+/// Date of the build, YYYY-MM-DD
+pub const BUILD_DATE: &str = env!("URASOE_BUILD_DATE");
+
+/// Enabled cargo features, comma separated, or "none"
+pub const FEATURES: &str = env!("URASOE_FEATURES");
+
+/// Oldest Web UI version with every request field in use, the scheduler came in 1.9.0
+pub const MIN_WEBUI_VERSION: (u32, u32, u32) = (1, 9, 0);
+
+/// Oldest ControlNet extension API version with the `alwayson_scripts` units in use
+pub const MIN_CONTROLNET_API: u64 = 1;
+
+/// Whether this build is known to work with a server
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    /// The versions are supported
+    Compatible,
+    /// The versions could not be recognised, generating may or may not work
+    Untested,
+    /// Generating will not work with these versions
+    Incompatible,
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Verdict::Compatible => "compatible",
+            Verdict::Untested => "untested",
+            Verdict::Incompatible => "incompatible",
+        })
+    }
+}
+
+/// Version of the program with the build metadata, one field per line
+pub fn render() -> String {
+    format!(
+        "{} {}\ncommit:   {}\nbuilt:    {}\nfeatures: {}\n",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION"),
+        GIT_HASH,
+        BUILD_DATE,
+        FEATURES
+    )
+}
+
+/// Parse the release number out of a Web UI version
+///
+/// Accepts A1111 versions such as "v1.10.1" and Forge versions such as
+/// "f2.0.1v1.10.1-previous-665-gae278f79", which carry the A1111 release
+/// they are based on after the last "v" followed by a digit.
+///
+/// # Arguments
+/// * `version` - Version reported by the Web UI
+///
+/// # Returns
+/// * `Option<(u32, u32, u32)>` - Major, minor and patch number, None when not recognised
+pub fn parse_webui_version(version: &str) -> Option<(u32, u32, u32)> {
+    let version = version.trim();
+    let release = version
+        .match_indices('v')
+        .map(|(index, _)| &version[index + 1..])
+        .rfind(|rest| rest.starts_with(|c: char| c.is_ascii_digit()))
+        .unwrap_or(version);
+    let release: String = release.chars().take_while(|c| c.is_ascii_digit() || *c == '.').collect();
+    let mut numbers = release.split('.').filter(|part| !part.is_empty()).map(str::parse::<u32>);
+    let major = numbers.next()?.ok()?;
+    let minor = numbers.next()?.ok()?;
+    let patch = numbers.next().and_then(Result::ok).unwrap_or(0);
+    Some((major, minor, patch))
+}
+
+/// Decide whether this build works with the given server versions
+///
+/// # Arguments
+/// * `webui` - Version reported by the Web UI, None if it did not report one
+/// * `controlnet` - API version of the ControlNet extension, None if it is not installed
+///
+/// # Returns
+/// * `(Verdict, String)` - The verdict and the reason for it
+pub fn verdict(webui: Option<&str>, controlnet: Option<u64>) -> (Verdict, String) {
+    let Some(controlnet) = controlnet else {
+        return (Verdict::Incompatible, "the ControlNet extension is not installed".to_string());
+    };
+    if controlnet < MIN_CONTROLNET_API {
+        return (
+            Verdict::Incompatible,
+            format!("ControlNet API version {} is older than {}", controlnet, MIN_CONTROLNET_API),
+        );
+    }
+    let (major, minor, patch) = MIN_WEBUI_VERSION;
+    match webui.map(|version| (version, parse_webui_version(version))) {
+        None => (Verdict::Untested, "the Web UI does not report its version".to_string()),
+        Some((version, None)) => (Verdict::Untested, format!("Web UI version {} is not recognised", version)),
+        Some((version, Some(release))) if release < MIN_WEBUI_VERSION => (
+            Verdict::Incompatible,
+            format!("Web UI {} is older than v{}.{}.{}", version, major, minor, patch),
+        ),
+        Some(_) => (Verdict::Compatible, "all versions are supported".to_string()),
+    }
+}
+
+/// Report the versions of every configured server and whether they are supported
+///
+/// # Arguments
+/// * `config` - Configuration naming the servers
+///
+/// # Returns
+/// * `Result<ExitStatus>` - `ConfigInvalid` if a server is incompatible, an Error if one cannot be reached
+pub async fn check(config: &Config) -> Result<ExitStatus> {
+    let mut status = ExitStatus::Success;
+    let mut report = String::new();
+    for api_url in config.api_urls() {
+        let client = StableDiffusionClient::with_timeout(&api_url, config.validate_timeout_ms)
+            .with_identity(config.request_identity(None));
+        let api_status = client
+            .api_status()
+            .await
+            .with_context(|| format!("Failed to reach {}", api_url))?;
+        if !api_status.is_success() {
+            anyhow::bail!("{} answered {}, run `urasoe doctor` for hints", api_url, api_status);
+        }
+        let webui = client.get_webui_version().await.ok();
+        let controlnet = client.get_controlnet_version().await.ok();
+        let (verdict, reason) = verdict(webui.as_deref(), controlnet);
+
+        let _ = writeln!(report, "\nserver:     {}", api_url);
+        let _ = writeln!(report, "web ui:     {}", webui.as_deref().unwrap_or("unknown"));
+        let _ = writeln!(
+            report,
+            "controlnet: {}",
+            controlnet.map_or("not installed".to_string(), |version| format!("API version {}", version))
+        );
+        let verdict_text = match verdict {
+            Verdict::Compatible => verdict.to_string().green(),
+            Verdict::Untested => verdict.to_string().yellow(),
+            Verdict::Incompatible => verdict.to_string().red(),
+        };
+        let _ = writeln!(report, "verdict:    {}, {}", verdict_text, reason);
+        if verdict == Verdict::Incompatible {
+            status = ExitStatus::ConfigInvalid;
+        }
+    }
+    print!("{}", report);
+    Ok(status)
+}
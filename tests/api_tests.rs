@@ -1,7 +1,8 @@
 //! API module tests for urasoe
 
 use std::path::Path;
-use urasoe::api::{StableDiffusionClient, load_model as legacy_load_model, generate_with_controlnet as legacy_generate_with_controlnet};
+use urasoe::api::{StableDiffusionClient, StableDiffusionResponse, load_model as legacy_load_model, generate_with_controlnet as legacy_generate_with_controlnet};
+use urasoe::api_types::GenerationRequest;
 use urasoe::config::Config;
 use reqwest::Client;
 
@@ -43,3 +44,94 @@ async fn test_legacy_generate_with_controlnet_error() {
     let result = legacy_generate_with_controlnet(&client, fake_path, &config).await;
     assert!(result.is_err() || result.as_ref().unwrap().is_none());
 }
+
+#[test]
+fn test_generation_request_from_config() {
+    let mut config = Config::load("nonexistent_config.yml").unwrap();
+    config.model = "depth".to_string();
+    config.controlnet_module = "depth_midas".to_string();
+    config.scheduler = "Karras".to_string();
+    config.subseed = 5678;
+    config.subseed_strength = 0.15;
+
+    let request = GenerationRequest::from(&config);
+    assert_eq!(request.prompt, config.prompt);
+    assert_eq!((request.width, request.height), (config.width, config.height));
+    assert_eq!(request.controlnet_units.len(), 1);
+    assert_eq!(request.controlnet_units[0].model, "control_depth_sd15");
+    assert_eq!(request.controlnet_units[0].module, "depth_midas");
+
+    let payload = request.to_payload("aW5wdXQ=");
+    assert_eq!(payload["sampler_name"], format!("{} Karras", config.sampler_name));
+    assert_eq!(payload["subseed"], 5678);
+    assert_eq!(payload["subseed_strength"], 0.15_f32);
+    assert_eq!(payload["override_settings"]["sd_model_checkpoint"], config.checkpoint_model);
+    assert_eq!(payload["alwayson_scripts"]["controlnet"]["args"][0]["input_image"], "aW5wdXQ=");
+    assert_eq!(payload["alwayson_scripts"]["controlnet"]["args"][0]["enabled"], true);
+    assert_eq!(payload["alwayson_scripts"]["controlnet"]["args"][0]["resize_mode"], 1);
+
+    config.resize_mode = urasoe::config::ResizeMode::ResizeAndFill;
+    let payload = GenerationRequest::from(&config).to_payload("aW5wdXQ=");
+    assert_eq!(payload["alwayson_scripts"]["controlnet"]["args"][0]["resize_mode"], 2);
+
+    // Without a checkpoint the loaded one is kept
+    let request = GenerationRequest { checkpoint: String::new(), ..request };
+    assert!(request.to_payload("aW5wdXQ=").get("override_settings").is_none());
+}
+
+#[test]
+fn test_generation_request_with_several_units() {
+    let mut config = Config::load("nonexistent_config.yml").unwrap();
+    config.controlnet_units = serde_yaml::from_str(
+        "- model: canny\n- model: depth\n  module: depth_midas\n  weight: 0.5\n  guidance_end: 0.75\n",
+    )
+    .unwrap();
+
+    let payload = GenerationRequest::from(&config).to_payload("aW5wdXQ=");
+    let args = payload["alwayson_scripts"]["controlnet"]["args"].as_array().unwrap();
+    assert_eq!(args.len(), 2);
+    assert_eq!(args[0]["model"], "control_canny_sd15");
+    assert_eq!(args[0]["module"], "canny");
+    assert_eq!(args[0]["guidance_end"], 1.0);
+    assert_eq!(args[1]["model"], "control_depth_sd15");
+    assert_eq!(args[1]["module"], "depth_midas");
+    assert_eq!(args[1]["weight"], 0.5);
+    assert_eq!(args[1]["guidance_end"], 0.75);
+    assert_eq!(args[1]["input_image"], "aW5wdXQ=");
+}
+
+#[test]
+fn test_response_images_are_decoded_as_parsed() {
+    let response: StableDiffusionResponse =
+        serde_json::from_str(r#"{"images": ["Zmlyc3Q=", "not base64", "c2Vjb25k"], "parameters": null, "info": null}"#)
+            .unwrap();
+
+    assert_eq!(response.images.len(), 3);
+    assert_eq!(response.decodable_images(), 2);
+    assert_eq!(response.images[0].bytes().unwrap(), b"first");
+    assert!(response.images[1].bytes().is_err());
+    assert_eq!(response.images[2].bytes().unwrap(), b"second");
+    // Written back in the format of the API
+    let json = serde_json::to_value(StableDiffusionResponse { images: response.images[2..].to_vec(), ..response })
+        .unwrap();
+    assert_eq!(json["images"], serde_json::json!(["c2Vjb25k"]));
+}
+
+#[test]
+fn test_response_append_keeps_seeds_of_each_image() {
+    let response = |images: &[&str], info: &str| -> StableDiffusionResponse {
+        let images: Vec<String> = images.iter().map(|image| format!("\"{}\"", image)).collect();
+        serde_json::from_str(&format!(r#"{{"images": [{}], "info": {:?}}}"#, images.join(","), info)).unwrap()
+    };
+    let mut first = response(&["YQ==", "Yg=="], r#"{"seed": 1, "all_seeds": [1, 2], "infotexts": ["a", "b"]}"#);
+    first.append(response(&["Yw=="], r#"{"seed": 9, "all_seeds": [9], "infotexts": ["c"]}"#));
+    assert_eq!(first.images.len(), 3);
+    assert_eq!(first.seed(), Some(1));
+    assert_eq!(first.seeds(), [1, 2, 9]);
+    assert_eq!(first.infotexts(), ["a", "b", "c"]);
+
+    // Seeds that are not known for every image are not guessed
+    first.append(response(&["ZA=="], r#"{"seed": 5}"#));
+    assert!(first.seeds().is_empty());
+    assert!(first.infotexts().is_empty());
+}
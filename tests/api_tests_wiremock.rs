@@ -1,9 +1,11 @@
 //! Additional API module tests for urasoe with wiremock for HTTP mocking
 
+use base64::{Engine, prelude::BASE64_STANDARD};
 use wiremock::{MockServer, Mock, ResponseTemplate};
-use wiremock::matchers::{method, path};
+use wiremock::matchers::{body_partial_json, method, path};
 use serde_json::json;
 use urasoe::api::StableDiffusionClient;
+use urasoe::api_types::{ControlNetUnit, GenerationRequest};
 use urasoe::config::Config;
 
 /// Test loading a model with successful response
@@ -106,7 +108,7 @@ async fn test_generate_with_controlnet_success() {
     assert!(result.is_ok());
     let response = result.unwrap().expect("Response should be Some");
     assert_eq!(response.images.len(), 1);
-    assert_eq!(response.images[0], base64_image);
+    assert_eq!(response.images[0].bytes().unwrap(), BASE64_STANDARD.decode(base64_image).unwrap());
 }
 
 /// Test error handling when API returns error status
@@ -216,3 +218,181 @@ async fn test_generate_with_controlnet_invalid_json() {
     // Should be an error since the response couldn't be parsed
     assert!(result.is_err());
 }
+
+/// Test generating with a request built without a configuration
+#[tokio::test]
+async fn test_generate_with_request_and_two_units() {
+    let mock_server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/sdapi/v1/txt2img"))
+        .and(body_partial_json(json!({
+            "prompt": "a dojo at dawn",
+            "width": 640,
+            "sampler_name": "Euler a",
+            "alwayson_scripts": {"controlnet": {"args": [
+                {"module": "canny", "model": "control_canny_sd15", "input_image": "aW5wdXQ="},
+                {"module": "depth", "model": "control_depth_sd15", "input_image": "ZGVwdGg="}
+            ]}}
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({"images": ["aW1hZ2U="]})))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let image_path = temp_dir.path().join("input.png");
+    std::fs::write(&image_path, "input").unwrap();
+
+    let mut depth = ControlNetUnit::new("depth", "control_depth_sd15", 0.5);
+    depth.input_image = Some("ZGVwdGg=".to_string());
+    let request = GenerationRequest {
+        prompt: "a dojo at dawn".to_string(),
+        negative_prompt: String::new(),
+        width: 640,
+        height: 480,
+        batch_size: 1,
+        steps: 20,
+        cfg_scale: 7.0,
+        seed: -1,
+        subseed: -1,
+        subseed_strength: 0.0,
+        sampler_name: "Euler a".to_string(),
+        scheduler: String::new(),
+        checkpoint: String::new(),
+        controlnet_units: vec![ControlNetUnit::new("canny", "control_canny_sd15", 1.0), depth],
+    };
+
+    let client = StableDiffusionClient::new(&format!("{}/", mock_server.uri()));
+    let response = client.generate(&image_path, &request).await.unwrap().unwrap();
+    assert_eq!(response.images.len(), 1);
+    assert_eq!(response.images[0].bytes().unwrap(), b"image");
+}
+
+/// Test that override settings are sent along, those of the backend included
+#[tokio::test]
+async fn test_generate_sends_override_settings() {
+    let mock_server = MockServer::start().await;
+    let uri = format!("{}/", mock_server.uri().trim_end_matches('/'));
+    Mock::given(method("POST"))
+        .and(path("/sdapi/v1/txt2img"))
+        .and(body_partial_json(json!({
+            "override_settings": {"sd_model_checkpoint": "dreamshaper_8", "CLIP_stop_at_last_layers": 2, "eta_noise_seed_delta": 1}
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({"images": []})))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let mut config = Config::load("nonexistent_file.yml").unwrap();
+    config.checkpoint_model = "dreamshaper_8".to_string();
+    config.override_settings.insert("CLIP_stop_at_last_layers".to_string(), json!(2));
+    config.backend_override_settings.insert(
+        mock_server.uri(),
+        json!({"eta_noise_seed_delta": 1}).as_object().unwrap().clone(),
+    );
+    let temp_dir = tempfile::tempdir().unwrap();
+    let image_path = temp_dir.path().join("test_image.png");
+    std::fs::write(&image_path, "input").unwrap();
+
+    let client = StableDiffusionClient::new(&uri);
+    let response = client.generate_with_controlnet(&image_path, &config).await.unwrap();
+    assert!(response.is_some());
+}
+
+/// Test polling the progress while a generation runs
+#[tokio::test]
+async fn test_progress_is_polled_while_generating() {
+    let mock_server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/sdapi/v1/progress"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "progress": 0.5,
+            "eta_relative": 3.5,
+            "state": {"sampling_step": 10, "sampling_steps": 20}
+        })))
+        .mount(&mock_server)
+        .await;
+
+    let client = StableDiffusionClient::new(&format!("{}/", mock_server.uri()));
+    let mut polled = Vec::new();
+    let generation = async {
+        tokio::time::sleep(std::time::Duration::from_millis(150)).await;
+        "generated"
+    };
+    let output = client
+        .poll_progress(std::time::Duration::from_millis(20), generation, |progress| polled.push(progress.clone()))
+        .await;
+
+    assert_eq!(output, "generated");
+    assert!(polled.len() >= 2);
+    assert_eq!(polled[0].state.sampling_step, 10);
+    assert_eq!(polled[0].eta_relative, 3.5);
+}
+
+/// Without pixel perfect the preprocessor resolution follows the control image, up to the cap
+#[tokio::test]
+async fn test_processor_res_matches_the_control_image() {
+    let mock_server = MockServer::start().await;
+    for processor_res in [900, 640, 384] {
+        Mock::given(method("POST"))
+            .and(path("/sdapi/v1/txt2img"))
+            .and(body_partial_json(json!({
+                "alwayson_scripts": {"controlnet": {"args": [
+                    {"module": "canny", "processor_res": processor_res, "pixel_perfect": false}
+                ]}}
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"images": ["aW1hZ2U="]})))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+    }
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let image_path = temp_dir.path().join("kata.png");
+    image::RgbImage::new(1200, 900).save(&image_path).unwrap();
+
+    let mut config = Config::load("nonexistent_config.yml").unwrap();
+    config.pixel_perfect = false;
+    let client = StableDiffusionClient::new(&format!("{}/", mock_server.uri()));
+    assert!(client.generate_with_controlnet(&image_path, &config).await.unwrap().is_some());
+
+    config.max_processor_res = 640;
+    assert!(client.generate_with_controlnet(&image_path, &config).await.unwrap().is_some());
+
+    // A resolution set on the unit is sent as is
+    config.controlnet_units = serde_yaml::from_str("- model: canny\n  pixel_perfect: false\n  processor_res: 384\n").unwrap();
+    assert!(client.generate_with_controlnet(&image_path, &config).await.unwrap().is_some());
+    mock_server.verify().await;
+}
+
+/// Test that payload plugins loaded once change every request
+#[cfg(feature = "scripting")]
+#[tokio::test]
+async fn test_generate_with_plugins_mutates_payload() {
+    let mock_server = MockServer::start().await;
+    let base64_image = "iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAQAAAC1HAwCAAAAC0lEQVR42mP8/w8AAgMBApUAAAAASUVORK5CYII=";
+    Mock::given(method("POST"))
+        .and(path("/sdapi/v1/txt2img"))
+        .and(body_partial_json(json!({"steps": 50})))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({"images": [base64_image], "info": "{}"})))
+        .expect(2)
+        .mount(&mock_server)
+        .await;
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let image_path = temp_dir.path().join("test_image.png");
+    image::RgbImage::new(8, 8).save(&image_path).unwrap();
+    let script = temp_dir.path().join("steps.rhai");
+    std::fs::write(&script, "fn mutate_payload(image, payload) { payload.steps = 50; payload }").unwrap();
+    let plugins = urasoe::plugins::Plugins::load(&[script.to_string_lossy().to_string()]).unwrap();
+
+    let uri = format!("{}/", mock_server.uri().trim_end_matches('/'));
+    let client = StableDiffusionClient::new(&uri).with_plugins(std::sync::Arc::new(plugins));
+    let mut config = Config::load("nonexistent_file.yml").unwrap();
+    config.sd_api_url = uri;
+    assert!(client.generate_with_controlnet(&image_path, &config).await.unwrap().is_some());
+
+    // The script is not read again for later requests
+    std::fs::write(&script, "fn mutate_payload(image, payload) {").unwrap();
+    assert!(client.generate_with_controlnet(&image_path, &config).await.unwrap().is_some());
+}
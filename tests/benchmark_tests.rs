@@ -0,0 +1,101 @@
+//! Benchmark tests for urasoe
+
+use serde_json::json;
+use std::fs;
+use tempfile::tempdir;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use urasoe::benchmark::{ImageSize, combinations, rows, run, vram_peak};
+use urasoe::config::Config;
+
+#[test]
+fn test_image_size_parse() {
+    let size: ImageSize = "768x512".parse().unwrap();
+    assert_eq!(size, ImageSize { width: 768, height: 512 });
+    assert_eq!(size.to_string(), "768x512");
+    assert!("768".parse::<ImageSize>().is_err());
+    assert!("axb".parse::<ImageSize>().is_err());
+}
+
+#[test]
+fn test_combinations_default_to_configuration() {
+    let config = Config::load("nonexistent_config.yml").unwrap();
+    let defaults = combinations(&config, &[], &[], &[]);
+    assert_eq!(defaults.len(), 1);
+    assert_eq!(defaults[0].sampler, config.sampler_name);
+    assert_eq!(defaults[0].steps, config.steps);
+
+    let samplers = vec!["Euler a".to_string(), "DDIM".to_string()];
+    let sizes = vec![ImageSize { width: 512, height: 512 }];
+    let grid = combinations(&config, &samplers, &[10, 20, 30], &sizes);
+    assert_eq!(grid.len(), 6);
+    assert_eq!(grid[5].sampler, "DDIM");
+    assert_eq!(grid[5].steps, 30);
+
+    let applied = grid[5].apply(&config);
+    assert_eq!(applied.sampler_name, "DDIM");
+    assert_eq!((applied.width, applied.height, applied.batch_size), (512, 512, 1));
+}
+
+#[test]
+fn test_vram_peak() {
+    assert_eq!(vram_peak(&json!({"cuda": {"active": {"peak": 2048}}})), Some(2048));
+    assert_eq!(vram_peak(&json!({"cuda": {"allocated": {"peak": 1024}}})), Some(1024));
+    assert_eq!(vram_peak(&json!({"cuda": {"error": "no CUDA"}})), None);
+}
+
+#[tokio::test]
+async fn test_benchmark_run_and_table() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/options"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/sdapi/v1/txt2img"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "images": ["aW1hZ2U="],
+            "parameters": {},
+            "info": "{}"
+        })))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/sdapi/v1/memory"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "cuda": {"active": {"peak": 3 * 1024 * 1024 * 1024_u64}}
+        })))
+        .mount(&server)
+        .await;
+
+    let temp_dir = tempdir().unwrap();
+    let image = temp_dir.path().join("input.png");
+    fs::write(&image, b"png").unwrap();
+    let mut config = Config::load("nonexistent_config.yml").unwrap();
+    config.sd_api_url = format!("{}/", server.uri());
+
+    let grid = combinations(&config, &[], &[10, 20], &[]);
+    let results = run(&config, &image, &grid, 2).await.unwrap();
+    assert_eq!(results.len(), 2);
+    assert!(results.iter().all(|result| result.error.is_none()));
+    assert_eq!(results[0].vram_peak_bytes, Some(3 * 1024 * 1024 * 1024));
+
+    // One warm-up and two timed generations per combination
+    let generations = server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .filter(|request| request.url.path() == "/sdapi/v1/txt2img")
+        .count();
+    assert_eq!(generations, 5);
+
+    let table = rows(&results);
+    assert_eq!(table.len(), 2);
+    assert_eq!(table[0][4], "3072 MB");
+
+    let missing = run(&config, &temp_dir.path().join("missing.png"), &grid, 1).await;
+    assert!(missing.is_err());
+}
@@ -0,0 +1,255 @@
+//! Subcommand tests for urasoe
+
+use clap::Parser;
+use serde_json::json;
+use std::fs;
+use tempfile::tempdir;
+use wiremock::matchers::{body_partial_json, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use urasoe::api::{GeneratedImage, StableDiffusionClient};
+use urasoe::commands::{
+    ModelListing, RunOutcome, clean, enqueue, init, pipe_image, regenerate, render_table, result_document,
+};
+use urasoe::config::{Args, Command, Config};
+use urasoe::digest::sha256_hex;
+use urasoe::file_utils::{FileManager, ImageMetadata};
+use urasoe::processing::{ImageTiming, ProcessingStats};
+use urasoe::queue::JobQueue;
+use urasoe::runner::PresetRun;
+use urasoe::prompt::PromptPolicy;
+
+#[test]
+fn test_init_writes_default_configuration() {
+    let temp_dir = tempdir().unwrap();
+    let path = temp_dir.path().join("urasoe.config.yml");
+    let path = path.to_str().unwrap();
+
+    init(path, false).unwrap();
+    let config = Config::load(path).unwrap();
+    let defaults = Config::load("nonexistent_config.yml").unwrap();
+    assert_eq!(config.checkpoint_model, defaults.checkpoint_model);
+    assert_eq!(config.negative_prompt, defaults.negative_prompt);
+    assert_eq!(config.cfg, defaults.cfg);
+
+    assert!(init(path, false).is_err());
+    init(path, true).unwrap();
+}
+
+#[test]
+fn test_clean_removes_queue_and_failed_inputs() {
+    let temp_dir = tempdir().unwrap();
+    let mut config = Config::load("nonexistent_config.yml").unwrap();
+    config.output_dir = temp_dir.path().join("output").to_string_lossy().to_string();
+    let output_dir = temp_dir.path().join("output");
+    fs::create_dir_all(output_dir.join("_failed")).unwrap();
+    fs::create_dir_all(output_dir.join("photo")).unwrap();
+    JobQueue::create(config.queue_path()).unwrap();
+
+    clean(&config, false).unwrap();
+    assert!(!config.queue_path().exists());
+    assert!(!output_dir.join("_failed").exists());
+    assert!(output_dir.join("photo").exists());
+
+    config.non_interactive = true;
+    config.prompt_policy = PromptPolicy::Abort;
+    clean(&config, true).unwrap();
+    assert!(output_dir.join("photo").exists());
+
+    config.assume_yes = true;
+    clean(&config, true).unwrap();
+    assert!(!output_dir.exists());
+}
+
+#[test]
+fn test_enqueue_puts_urgent_inputs_ahead() {
+    let temp_dir = tempdir().unwrap();
+    let mut config = Config::load("nonexistent_config.yml").unwrap();
+    config.output_dir = temp_dir.path().join("output").to_string_lossy().to_string();
+    let urgent = temp_dir.path().join("urgent.png");
+    fs::write(&urgent, "png").unwrap();
+    let mut queue = JobQueue::create(config.queue_path()).unwrap();
+    queue.enqueue("backlog.png").unwrap();
+
+    let args = Args::parse_from(["urasoe", "enqueue", urgent.to_str().unwrap(), "--priority", "5"]);
+    let Some(Command::Enqueue { images, priority }) = args.command else {
+        panic!("expected the enqueue command");
+    };
+    enqueue(&config, &images, priority).unwrap();
+    assert_eq!(queue.next_pending().unwrap(), Some(urgent));
+
+    assert!(enqueue(&config, &[temp_dir.path().join("missing.png")], 0).is_err());
+}
+
+#[test]
+fn test_subcommands_accept_global_flags() {
+    let args = Args::parse_from(["urasoe", "resume", "--input-dir", "./photos", "-y"]);
+    assert!(matches!(args.command, Some(Command::Resume)));
+
+    let mut config = Config::load("nonexistent_config.yml").unwrap();
+    config.apply_args(&args);
+    assert!(config.resume);
+    assert!(config.assume_yes);
+    assert_eq!(config.input_dir, "./photos");
+
+    let args = Args::parse_from(["urasoe", "--steps", "12", "generate"]);
+    assert!(matches!(args.command, Some(Command::Generate)));
+    assert_eq!(args.steps, Some(12));
+}
+
+#[test]
+fn test_render_table_aligns_columns() {
+    let rows = vec![
+        vec!["checkpoint".to_string(), "sd15".to_string()],
+        vec!["sampler".to_string(), "Euler a".to_string()],
+    ];
+    let table = render_table(&["TYPE", "NAME"], &rows);
+    assert_eq!(table, "TYPE        NAME\ncheckpoint  sd15\nsampler     Euler a\n");
+}
+
+#[tokio::test]
+async fn test_model_listing_without_scheduler_endpoint() {
+    let server = MockServer::start().await;
+    let responses = [
+        ("/sdapi/v1/sd-models", json!([{"title": "sd15.safetensors [abc]"}])),
+        ("/controlnet/model_list", json!({"model_list": [{"model_name": "control_canny_sd15"}]})),
+        ("/controlnet/module_list", json!({"module_list": ["canny", "depth"]})),
+        ("/sdapi/v1/samplers", json!([{"name": "Euler a"}])),
+    ];
+    for (endpoint, body) in responses {
+        Mock::given(method("GET"))
+            .and(path(endpoint))
+            .respond_with(ResponseTemplate::new(200).set_body_json(body))
+            .mount(&server)
+            .await;
+    }
+
+    let client = StableDiffusionClient::new(&format!("{}/", server.uri()));
+    let listing = ModelListing::fetch(&client).await.unwrap();
+    assert_eq!(listing.checkpoints, ["sd15.safetensors [abc]"]);
+    assert_eq!(listing.controlnet_modules, ["canny", "depth"]);
+    assert!(listing.schedulers.is_empty());
+    assert_eq!(listing.rows().len(), 5);
+    assert_eq!(listing.rows()[0], ["checkpoint", "sd15.safetensors [abc]"]);
+}
+
+#[test]
+fn test_result_document() {
+    let mut config = Config::load("nonexistent_config.yml").unwrap();
+    config.output_dir = "./results".to_string();
+    let mut stats = ProcessingStats::new();
+    stats.record_success(std::path::Path::new("in/a.png"), 1, 0.5, ImageTiming::default(), 1);
+    stats.record_outputs(&["results/a/a_0.png".into()]);
+    stats.record_failure(std::path::Path::new("in/b.png"), ImageTiming::default(), 2, "Timeout");
+
+    let document = result_document(&config, &RunOutcome::Batch(Some(stats))).unwrap();
+    assert_eq!(document["output_dir"], "./results");
+    assert_eq!(document["stats"]["success_count"], 1);
+    assert_eq!(document["stats"]["failed_count"], 1);
+    assert_eq!(document["stats"]["images"][0]["outputs"], json!(["results/a/a_0.png"]));
+    assert!(document["stats"]["images"][1].get("outputs").is_none());
+
+    let runs = vec![PresetRun {
+        name: "depth".to_string(),
+        checkpoint: "model.safetensors".to_string(),
+        stats: None,
+    }];
+    let document = result_document(&config, &RunOutcome::Presets(runs)).unwrap();
+    assert_eq!(
+        document,
+        json!({"presets": [{"name": "depth", "checkpoint": "model.safetensors", "stats": null}]})
+    );
+}
+
+#[tokio::test]
+async fn test_pipe_image_returns_first_generated_image() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/sdapi/v1/txt2img"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "images": ["Zmlyc3Q=", "c2Vjb25k"],
+            "parameters": {},
+            "info": "{}"
+        })))
+        .mount(&server)
+        .await;
+
+    let mut config = Config::load("nonexistent_config.yml").unwrap();
+    config.sd_api_url = format!("{}/", server.uri());
+    config.batch_size = 4;
+
+    let image = pipe_image(&config, b"input image").await.unwrap();
+    assert_eq!(image, b"first");
+
+    let requests = server.received_requests().await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&requests[0].body).unwrap();
+    assert_eq!(body["batch_size"], 1);
+
+    assert!(pipe_image(&config, b"").await.is_err());
+}
+
+#[tokio::test]
+async fn test_regenerate_reproduces_metadata_with_overrides() {
+    const PNG_BASE64: &str = "iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAQAAAC1HAwCAAAAC0lEQVR42mP8/w8AAgMBApUAAAAASUVORK5CYII=";
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/options"))
+        .and(body_partial_json(json!({"sd_model_checkpoint": "dojo-checkpoint"})))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/sdapi/v1/txt2img"))
+        .and(body_partial_json(json!({"prompt": "mae geri", "seed": 1234, "steps": 60, "cfg_scale": 6.5})))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "images": [PNG_BASE64],
+            "info": "{\"seed\": 1234}"
+        })))
+        .mount(&server)
+        .await;
+
+    let temp_dir = tempdir().unwrap();
+    let input = temp_dir.path().join("kata.png");
+    fs::write(&input, "png").unwrap();
+    let mut config = Config::load("nonexistent_config.yml").unwrap();
+    config.output_dir = temp_dir.path().join("output").to_string_lossy().to_string();
+    config.prompt = "mae geri".to_string();
+    config.checkpoint_model = "dojo-checkpoint".to_string();
+    config.cfg = 6.5;
+    config.steps = 30;
+    let response = urasoe::api::StableDiffusionResponse {
+        images: vec![GeneratedImage::from_base64(PNG_BASE64).unwrap()],
+        parameters: None,
+        info: Some("{\"seed\": 1234, \"all_seeds\": [1234]}".to_string()),
+        digest: None,
+    };
+    let saved = FileManager::save_generated_images(&response, &input, &config).unwrap();
+    let metadata_path = saved.metadata_path.unwrap();
+    assert_eq!(ImageMetadata::read(&metadata_path).unwrap().seed, 1234);
+
+    // Settings of the configuration file are replaced by the recorded ones
+    let mut current = Config::load("nonexistent_config.yml").unwrap();
+    current.sd_api_url = format!("{}/", server.uri());
+    let metadata_arg = metadata_path.to_str().unwrap();
+    let args = Args::parse_from(["urasoe", "regenerate", metadata_arg, "--steps", "60"]);
+    let saved = regenerate(&current, &args, &metadata_path, None).await.unwrap();
+    let regenerated = temp_dir.path().join("output").join("kata").join("regenerated").join("kata");
+    assert_eq!(saved.paths, [regenerated.join("kata-1.png")]);
+    let metadata = ImageMetadata::read(&regenerated.join("kata-metadata.json")).unwrap();
+    assert_eq!(metadata.steps, 60);
+    assert_eq!(metadata.checkpoint_model, "dojo-checkpoint");
+    assert_eq!(metadata.image_sha256.as_deref(), Some(sha256_hex(b"png").as_str()));
+
+    // Regenerating the regenerated images sends the same request again
+    let regenerated_metadata = regenerated.join("kata-metadata.json");
+    let args = Args::parse_from(["urasoe", "regenerate", regenerated_metadata.to_str().unwrap()]);
+    regenerate(&current, &args, &regenerated_metadata, Some(&input)).await.unwrap();
+    let again = ImageMetadata::read(&regenerated.join("regenerated").join("kata").join("kata-metadata.json")).unwrap();
+    assert!(metadata.payload_sha256.is_some());
+    assert_eq!(again.payload_sha256, metadata.payload_sha256);
+
+    let args = Args::parse_from(["urasoe", "regenerate", metadata_arg, "--steps", "60"]);
+    fs::remove_file(&input).unwrap();
+    let error = regenerate(&current, &args, &metadata_path, None).await.unwrap_err();
+    assert!(error.to_string().contains("--image"));
+}
@@ -0,0 +1,115 @@
+//! Comparison grid tests for urasoe
+
+use base64::{Engine, prelude::BASE64_STANDARD};
+use image::{DynamicImage, ImageFormat, RgbImage};
+use serde_json::json;
+use std::fs;
+use std::io::Cursor;
+use tempfile::tempdir;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use urasoe::compare::{Axis, GridParam, run, sheet_path};
+use urasoe::config::Config;
+use urasoe::sheet::{self, PADDING, text_height, text_width};
+
+fn png_base64(width: u32, height: u32) -> String {
+    let mut bytes = Vec::new();
+    DynamicImage::ImageRgb8(RgbImage::new(width, height))
+        .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+        .unwrap();
+    BASE64_STANDARD.encode(bytes)
+}
+
+#[test]
+fn test_axis_parse() {
+    let axis: Axis = "cfg=5, 7,9".parse().unwrap();
+    assert_eq!(axis.param, GridParam::Cfg);
+    assert_eq!(axis.values, vec!["5", "7", "9"]);
+    assert_eq!(axis.label("7"), "cfg=7");
+
+    let axis: Axis = "controlnet_weight=0.4,0.8".parse().unwrap();
+    assert_eq!(axis.param, GridParam::Weight);
+    assert!("cfg".parse::<Axis>().is_err());
+    assert!("cfg=".parse::<Axis>().is_err());
+    assert!("color=red".parse::<Axis>().is_err());
+}
+
+#[test]
+fn test_grid_param_apply() {
+    let mut config = Config::load("nonexistent_config.yml").unwrap();
+    GridParam::Weight.apply(&mut config, "1.2").unwrap();
+    assert_eq!(config.controlnet_weight, 1.2);
+    GridParam::Sampler.apply(&mut config, "DDIM").unwrap();
+    assert_eq!(config.sampler_name, "DDIM");
+    assert!(GridParam::Steps.apply(&mut config, "many").is_err());
+}
+
+#[test]
+fn test_sheet_render_layout() {
+    let cells = vec![
+        Some(DynamicImage::ImageRgb8(RgbImage::new(16, 16))),
+        None,
+        Some(DynamicImage::ImageRgb8(RgbImage::new(16, 16))),
+        Some(DynamicImage::ImageRgb8(RgbImage::new(16, 16))),
+    ];
+    let columns = vec!["cfg=5".to_string(), "cfg=7".to_string()];
+    let rows = vec!["steps=10".to_string(), "steps=20".to_string()];
+    let canvas = sheet::render(&columns, &rows, &cells).unwrap();
+
+    let label_column = text_width("steps=10") + 2 * PADDING;
+    assert_eq!(canvas.width(), label_column + 2 * (16 + PADDING) + PADDING);
+    assert_eq!(canvas.height(), text_height() + 2 * PADDING + 2 * (16 + PADDING));
+
+    // The failed cell is gray
+    let left = label_column + PADDING + 16 + PADDING;
+    let top = text_height() + 2 * PADDING;
+    assert_eq!(canvas.get_pixel(left, top).0, [200, 200, 200]);
+
+    assert!(sheet::render(&columns, &rows, &cells[..3]).is_err());
+    assert!(sheet::render(&columns[..1], &rows[..1], &[None]).is_err());
+}
+
+#[tokio::test]
+async fn test_compare_run_uses_fixed_seed() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/sdapi/v1/txt2img"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "images": [png_base64(8, 8)],
+            "parameters": {},
+            "info": "{}"
+        })))
+        .mount(&server)
+        .await;
+
+    let temp_dir = tempdir().unwrap();
+    let image = temp_dir.path().join("input.png");
+    fs::write(&image, b"png").unwrap();
+    let mut config = Config::load("nonexistent_config.yml").unwrap();
+    config.sd_api_url = format!("{}/", server.uri());
+    config.output_dir = temp_dir.path().join("out").to_string_lossy().to_string();
+
+    let x: Axis = "cfg=5,7,9".parse().unwrap();
+    let y: Axis = "weight=0.4,1.2".parse().unwrap();
+    let saved = run(&config, &image, &x, Some(&y)).await.unwrap();
+    assert_eq!(saved, sheet_path(&config, &image, &x, Some(&y)));
+    assert!(saved.ends_with("input-compare-cfg-weight.png"));
+    assert!(saved.is_file());
+
+    let requests = server.received_requests().await.unwrap();
+    assert_eq!(requests.len(), 6);
+    let payloads: Vec<serde_json::Value> = requests
+        .iter()
+        .map(|request| serde_json::from_slice(&request.body).unwrap())
+        .collect();
+    let seed = &payloads[0]["seed"];
+    assert!(seed.as_i64().unwrap() >= 0);
+    assert!(payloads.iter().all(|payload| &payload["seed"] == seed));
+    assert_eq!(payloads[1]["cfg_scale"], json!(7.0));
+
+    // Values that do not fit the parameter fail before anything is generated
+    let bad: Axis = "steps=10,many".parse().unwrap();
+    assert!(run(&config, &image, &bad, None).await.is_err());
+    assert_eq!(server.received_requests().await.unwrap().len(), 6);
+}
@@ -0,0 +1,93 @@
+//! Before/after composite module tests for urasoe
+
+use base64::{Engine, prelude::BASE64_STANDARD};
+use image::{DynamicImage, Rgb, RgbImage};
+use std::path::{Path, PathBuf};
+
+use urasoe::api::{GeneratedImage, StableDiffusionResponse};
+use urasoe::composite::{caption, render};
+use urasoe::config::Config;
+use urasoe::file_utils::{FileManager, ImageMetadata};
+use urasoe::sheet::{PADDING, text_height};
+use urasoe::sink::{LabeledSink, MemorySink};
+
+/// Generated image as the server returns it, a small PNG encoded as base64
+fn generated_base64() -> String {
+    let mut png = Vec::new();
+    RgbImage::from_pixel(8, 8, Rgb([0, 0, 255]))
+        .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+        .unwrap();
+    BASE64_STANDARD.encode(png)
+}
+
+#[test]
+fn test_source_is_scaled_next_to_the_generated_image() {
+    let source = DynamicImage::ImageRgb8(RgbImage::from_pixel(100, 50, Rgb([255, 0, 0])));
+    let generated = DynamicImage::ImageRgb8(RgbImage::from_pixel(64, 64, Rgb([0, 0, 255])));
+    let lines = ["dreamshaper".to_string(), "seed 1234".to_string()];
+
+    let canvas = render(&source, &generated, &lines).unwrap();
+    // Both cells take the size of the larger, the source scaled to 128x64
+    let label_row = text_height() + 2 * PADDING;
+    assert_eq!(canvas.width(), 3 * PADDING + 2 * 128);
+    assert_eq!(canvas.height(), label_row + 64 + PADDING + 2 * (text_height() + PADDING) + PADDING);
+    assert_eq!(canvas.get_pixel(PADDING + 64, label_row + 32), &Rgb([255, 0, 0]));
+    assert_eq!(canvas.get_pixel(2 * PADDING + 128 + 32, label_row + 32), &Rgb([0, 0, 255]));
+
+    // Long captions wrap to the width of the canvas
+    let long = vec!["kata ".repeat(40)];
+    let wrapped = render(&source, &generated, &long).unwrap();
+    assert!(wrapped.height() > canvas.height() - (text_height() + PADDING));
+}
+
+#[test]
+fn test_caption_counts_seeds_up_the_batch() {
+    let mut config = Config::load("nonexistent_config.yml").unwrap();
+    config.seed = 1234;
+    config.checkpoint_model = "dreamshaper_8".to_string();
+    config.controlnet_module = "canny".to_string();
+    config.sampler_name = "Euler a".to_string();
+    let metadata = ImageMetadata::from_config(&config, Path::new("kata.png"));
+
+    let lines = caption(&metadata, 2);
+    assert_eq!(lines[0], "dreamshaper_8");
+    assert!(lines[1].starts_with("seed 1236, steps"));
+    assert!(lines[2].starts_with("canny "));
+}
+
+#[test]
+fn test_composites_are_saved_next_to_the_images() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let input = temp_dir.path().join("kata.png");
+    RgbImage::from_pixel(4, 2, Rgb([10, 20, 30])).save(&input).unwrap();
+    let response = StableDiffusionResponse {
+        images: vec![GeneratedImage::from_base64(&generated_base64()).unwrap(); 2],
+        parameters: None,
+        info: None,
+        digest: None,
+    };
+    let mut config = Config::load("nonexistent_config.yml").unwrap();
+
+    let sink = MemorySink::new();
+    FileManager::save_to_sink(&sink, &response, &input, &config).unwrap();
+    assert!(sink.composites().is_empty());
+
+    config.composites = true;
+    FileManager::save_to_sink(&LabeledSink::new(&sink, "cfg-7"), &response, &input, &config).unwrap();
+    let composites = sink.composites();
+    assert_eq!(
+        composites.keys().collect::<Vec<_>>(),
+        [
+            &PathBuf::from("kata-cfg-7/kata-cfg-7-1-composite.png"),
+            &PathBuf::from("kata-cfg-7/kata-cfg-7-2-composite.png")
+        ]
+    );
+    let composite = image::load_from_memory(&composites[Path::new("kata-cfg-7/kata-cfg-7-1-composite.png")]).unwrap();
+    assert!(composite.width() > 2 * PADDING);
+
+    // An input that is not an image leaves the composites out without failing the save
+    let sink = MemorySink::new();
+    let saved = FileManager::save_to_sink(&sink, &response, Path::new("missing.png"), &config).unwrap();
+    assert_eq!(saved.paths.len(), 2);
+    assert!(sink.composites().is_empty());
+}
@@ -1,6 +1,8 @@
+use clap::Parser;
 use std::io::Write;
 use tempfile::NamedTempFile;
-use urasoe::config::{Args, Config, DEFAULT_CONFIG_PATH};
+use urasoe::config::{Args, Config, DEFAULT_CONFIG_PATH, OutputNaming, OverrideSetting, SeedStrategy};
+use urasoe::logging::LogLevel;
 
 /// Test that default configuration values match what we expect
 #[test]
@@ -25,6 +27,7 @@ fn test_default_config_values() {
         validate_options: None,
         validate_timeout: None,
         config: "nonexistent_file.yml".to_string(),
+        ..Default::default()
     };
 
     // Load config from a nonexistent file to get defaults
@@ -71,6 +74,7 @@ fn test_apply_args() {
         validate_options: Some(false),
         validate_timeout: Some(10000),
         config: "nonexistent_file.yml".to_string(),
+        ..Default::default()
     };
 
     // Start with default config
@@ -122,6 +126,7 @@ fn test_partial_args() {
         validate_options: None,
         validate_timeout: None,
         config: "nonexistent_file.yml".to_string(),
+        ..Default::default()
     };
 
     // Start with default config
@@ -192,6 +197,7 @@ fn test_load_config_from_file() {
         validate_options: None,
         validate_timeout: None,
         config: temp_file.path().to_string_lossy().to_string(),
+        ..Default::default()
     };
 
     // Load config from the file
@@ -249,6 +255,7 @@ fn test_args_override_config() {
         validate_options: Some(true), // Override
         validate_timeout: None, // Don't override
         config: temp_file.path().to_string_lossy().to_string(),
+        ..Default::default()
     };
 
     // Load config from the file then apply args
@@ -293,6 +300,7 @@ fn test_default_config_path() {
         validate_options: None,
         validate_timeout: None,
         config: DEFAULT_CONFIG_PATH.to_string(),
+        ..Default::default()
     };
     
     // This just verifies we can load a default config without crashing
@@ -300,62 +308,27 @@ fn test_default_config_path() {
     let _config = Config::load(&args.config);
 }
 
-/// Test config verbose flag setting
+/// Test that -v and -q shift the log level
 #[test]
-fn test_config_verbose() {
-    // Create a temporary YAML file with verbose setting
-    let mut temp_file = NamedTempFile::new().unwrap();
-    writeln!(temp_file, "verbose: true").unwrap();
-    
-    let args = Args {
-        input_dir: None,
-        output_dir: None,
-        batch_size: None,
-        width: None,
-        height: None,
-        model: None,
-        controlnet_module: None,
-        controlnet_weight: None,
-        sampler: None,
-        scheduler: None,
-        steps: None,
-        cfg: None,
-        max_retries: None,
-        retry_delay: None,
-        batch_break: None,
-        validate_options: None,
-        validate_timeout: None,
-        config: temp_file.path().to_string_lossy().to_string(),
-    };    let config = Config::load(&args.config).unwrap();
-    assert!(!config.verbose); // Default value should be false
-    
-    // Create another file with verbose: false
-    let mut temp_file2 = NamedTempFile::new().unwrap();
-    writeln!(temp_file2, "verbose: false").unwrap();
-    
-    let args2 = Args {
-        input_dir: None,
-        output_dir: None,
-        batch_size: None,
-        width: None,
-        height: None,
-        model: None,
-        controlnet_module: None,
-        controlnet_weight: None,
-        sampler: None,
-        scheduler: None,
-        steps: None,
-        cfg: None,
-        max_retries: None,
-        retry_delay: None,
-        batch_break: None,
-        validate_options: None,
-        validate_timeout: None,
-        config: temp_file2.path().to_string_lossy().to_string(),
+fn test_verbosity_flags() {
+    let defaults = Config::load("nonexistent_config.yml").unwrap();
+    let level_for = |flags: &[&str]| {
+        let args = Args::parse_from(["urasoe"].iter().chain(flags));
+        let mut config = defaults.clone();
+        config.apply_args(&args);
+        assert_eq!(args.log_level(), config.log_level);
+        config.log_level
     };
-    
-    let config2 = Config::load(&args2.config).unwrap();
-    assert!(!config2.verbose);
+
+    assert_eq!(level_for(&[]), LogLevel::Info);
+    assert_eq!(level_for(&["-v"]), LogLevel::Debug);
+    assert_eq!(level_for(&["-vv"]), LogLevel::Trace);
+    assert_eq!(level_for(&["-vvvv"]), LogLevel::Trace);
+    assert_eq!(level_for(&["-q"]), LogLevel::Warn);
+    assert_eq!(level_for(&["-qq"]), LogLevel::Error);
+    assert_eq!(level_for(&["--quiet", "--quiet", "--quiet"]), LogLevel::Error);
+    assert_eq!(level_for(&["-vv", "-q"]), LogLevel::Debug);
+    assert_eq!(level_for(&["--log-level", "warn", "-v"]), LogLevel::Info);
 }
 
 // Add tests for the new validation options
@@ -385,6 +358,7 @@ fn test_validation_options() {
         validate_options: None,
         validate_timeout: None,
         config: temp_file.path().to_string_lossy().to_string(),
+        ..Default::default()
     };
     
     let config = Config::load(&args.config).unwrap();
@@ -412,9 +386,193 @@ fn test_validation_options() {
         validate_options: Some(true),
         validate_timeout: Some(7000),
         config: temp_file.path().to_string_lossy().to_string(),
+        ..Default::default()
     };
     
     config2.apply_args(&override_args);
     assert!(config2.validate_options);
     assert_eq!(config2.validate_timeout_ms, 7000);
 }
+
+/// Test that the log level can be set from the config file and overridden by args
+#[test]
+fn test_log_level_config_and_args() {
+    let mut temp_file = NamedTempFile::new().unwrap();
+    writeln!(temp_file, "log_level: warn").unwrap();
+    let mut config = Config::load(temp_file.path().to_str().unwrap()).unwrap();
+    assert_eq!(config.log_level, urasoe::logging::LogLevel::Warn);
+
+    let args = Args {
+        log_level: Some(urasoe::logging::LogLevel::Debug),
+        ..Default::default()
+    };
+    config.apply_args(&args);
+    assert_eq!(config.log_level, urasoe::logging::LogLevel::Debug);
+}
+
+#[test]
+fn test_color_config_and_no_color_argument() {
+    let mut temp_file = NamedTempFile::new().unwrap();
+    writeln!(temp_file, "color: always").unwrap();
+    let mut config = Config::load(temp_file.path().to_str().unwrap()).unwrap();
+    assert_eq!(config.color, urasoe::logging::ColorMode::Always);
+
+    let args = Args::parse_from(["urasoe", "--no-color"]);
+    assert_eq!(args.color(), urasoe::logging::ColorMode::Never);
+    config.apply_args(&args);
+    assert_eq!(config.color, urasoe::logging::ColorMode::Never);
+}
+
+#[test]
+fn test_lang_config_and_argument() {
+    let mut temp_file = NamedTempFile::new().unwrap();
+    writeln!(temp_file, "lang: ja").unwrap();
+    let mut config = Config::load(temp_file.path().to_str().unwrap()).unwrap();
+    assert_eq!(config.lang, Some(urasoe::i18n::Lang::Ja));
+
+    config.apply_args(&Args::parse_from(["urasoe", "--lang", "fi"]));
+    assert_eq!(config.lang, Some(urasoe::i18n::Lang::Fi));
+    assert_eq!(Config::load("nonexistent_config.yml").unwrap().lang, None);
+}
+
+#[test]
+fn test_user_agent_and_run_id() {
+    let mut config = Config::load("nonexistent_config.yml").unwrap();
+    assert_eq!(config.user_agent, urasoe::http::DEFAULT_USER_AGENT);
+    assert_eq!(config.run_id, None);
+
+    config.apply_args(&Args::parse_from(["urasoe", "--user-agent", "dojo-bot/2", "--run-id", "nightly-42"]));
+    assert_eq!(config.user_agent, "dojo-bot/2");
+    assert_eq!(config.run_id.as_deref(), Some("nightly-42"));
+}
+
+#[test]
+fn test_shuffle_argument_with_and_without_seed() {
+    let mut config = Config::load("nonexistent_config.yml").unwrap();
+    config.apply_args(&Args::parse_from(["urasoe", "--shuffle"]));
+    assert!(config.shuffle);
+    assert_eq!(config.shuffle_seed, None);
+
+    config.apply_args(&Args::parse_from(["urasoe", "--shuffle", "7", "--stratified"]));
+    assert_eq!(config.shuffle_seed, Some(7));
+    assert!(config.stratified);
+}
+
+#[test]
+fn test_seed_derived_from_input_hash() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let kata = temp_dir.path().join("kata.png");
+    let kumite = temp_dir.path().join("kumite.png");
+    std::fs::write(&kata, "kata").unwrap();
+    std::fs::write(&kumite, "kumite").unwrap();
+
+    let mut config = Config::load("nonexistent_config.yml").unwrap();
+    assert_eq!(config.seed_strategy, SeedStrategy::Fixed);
+    assert_eq!(config.seed_strategy.seed_for(-1, &kata, "").unwrap(), -1);
+
+    config.apply_args(&Args::parse_from(["urasoe", "--seed-strategy", "from-input-hash", "--seed-salt", "dojo"]));
+    assert_eq!(config.seed_strategy, SeedStrategy::FromInputHash);
+    assert_eq!(config.seed_salt, "dojo");
+    let seed = config.seed_strategy.seed_for(-1, &kata, &config.seed_salt).unwrap();
+    assert!((0..=i64::from(u32::MAX)).contains(&seed));
+    assert_eq!(config.seed_strategy.seed_for(42, &kata, "dojo").unwrap(), seed);
+    assert_ne!(config.seed_strategy.seed_for(-1, &kumite, "dojo").unwrap(), seed);
+    assert_ne!(config.seed_strategy.seed_for(-1, &kata, "honbu").unwrap(), seed);
+    assert!(config.seed_strategy.seed_for(-1, &temp_dir.path().join("missing.png"), "").is_err());
+
+    let config: Config = serde_yaml::from_str("seed_strategy: from_input_hash\nseed_salt: v2\n").unwrap();
+    assert_eq!(config.seed_strategy, SeedStrategy::FromInputHash);
+}
+
+#[test]
+fn test_preset_overrides_config_and_names_outputs() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let preset_path = temp_dir.path().join("depth.yml");
+    std::fs::write(&preset_path, "model: depth\ncontrolnet_module: depth\nsteps: 40\n").unwrap();
+
+    let mut config = Config::load("nonexistent_config.yml").unwrap();
+    config.output_dir = "./results".to_string();
+    config.stats_out = Some("./reports/stats.json".to_string());
+    let args = Args::parse_from(["urasoe", "--steps", "20", "--preset", "depth.yml"]);
+    config.apply_args(&args);
+    assert_eq!(config.presets, vec!["depth.yml".to_string()]);
+
+    let preset = config.with_preset(&preset_path, &args).unwrap();
+    assert_eq!(preset.model, "depth");
+    assert_eq!(preset.controlnet_module, "depth");
+    // Command line arguments still win over the preset
+    assert_eq!(preset.steps, 20);
+    assert_eq!(preset.cfg, config.cfg);
+    assert!(preset.presets.is_empty());
+    assert_eq!(
+        std::path::Path::new(&preset.output_dir),
+        std::path::Path::new("./results").join("depth")
+    );
+    assert_eq!(
+        std::path::Path::new(preset.stats_out.as_deref().unwrap()),
+        std::path::Path::new("./reports/stats.depth.json")
+    );
+
+    std::fs::write(&preset_path, "output_dir: ./elsewhere\nsteps: many\n").unwrap();
+    assert!(config.with_preset(&preset_path, &args).is_err());
+}
+
+#[test]
+fn test_preset_applies_arguments_once() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let preset_path = temp_dir.path().join("depth.yml");
+    std::fs::write(&preset_path, "model: depth\n").unwrap();
+
+    let mut config = Config::load("nonexistent_config.yml").unwrap();
+    let args = Args::parse_from(["urasoe", "--captions", "append", "-v", "--preset", "depth.yml"]);
+    config.apply_args(&args);
+    let preset = config.with_preset(&preset_path, &args).unwrap();
+    assert_eq!(preset.prompt_sources, config.prompt_sources);
+    assert_eq!(preset.log_level, LogLevel::Debug);
+
+    // A level set by the preset is still adjusted by the arguments
+    std::fs::write(&preset_path, "log_level: warn\n").unwrap();
+    let preset = config.with_preset(&preset_path, &args).unwrap();
+    assert_eq!(preset.log_level, LogLevel::Info);
+}
+
+#[test]
+fn test_override_settings_from_args_and_per_backend() {
+    let setting: OverrideSetting = "eta_noise_seed_delta=1".parse().unwrap();
+    assert_eq!(setting.value, serde_json::json!(1));
+    let setting: OverrideSetting = "sd_vae=vae-ft-mse.safetensors".parse().unwrap();
+    assert_eq!(setting.value, serde_json::json!("vae-ft-mse.safetensors"));
+    assert!("eta_noise_seed_delta".parse::<OverrideSetting>().is_err());
+    assert!("=1".parse::<OverrideSetting>().is_err());
+
+    let mut config: Config = serde_yaml::from_str(
+        "backend_override_settings:\n  \"http://gpu-box:7860/\":\n    eta_noise_seed_delta: 0\n  \"http://gpu-box:7861\":\n    eta_noise_seed_delta: 1\n",
+    )
+    .unwrap();
+    let args = Args::parse_from(["urasoe", "--override-setting", "CLIP_stop_at_last_layers=2"]);
+    config.apply_args(&args);
+    assert_eq!(config.override_settings["CLIP_stop_at_last_layers"], serde_json::json!(2));
+    assert_eq!(config.backend_settings("http://gpu-box:7861/")["eta_noise_seed_delta"], serde_json::json!(1));
+    assert_eq!(config.backend_settings("http://gpu-box:7860")["eta_noise_seed_delta"], serde_json::json!(0));
+    assert!(config.backend_settings("http://127.0.0.1:7860/").is_empty());
+}
+
+#[test]
+fn test_output_naming_keeps_inputs_of_the_same_name_apart() {
+    let input_dir = std::path::Path::new("./photos");
+    let first = input_dir.join("2023").join("photo.png");
+    let second = input_dir.join("2024").join("photo.png");
+    assert_eq!(OutputNaming::Stem.name_for(&second, input_dir), "photo");
+    assert_eq!(OutputNaming::RelativePath.name_for(&second, input_dir), "2024_photo");
+    assert_eq!(OutputNaming::RelativePath.name_for(std::path::Path::new("/elsewhere/photo.png"), input_dir), "elsewhere_photo");
+    let hashed = OutputNaming::Hash.name_for(&first, input_dir);
+    assert!(hashed.starts_with("photo-") && hashed.len() == "photo-".len() + 8);
+    assert_ne!(hashed, OutputNaming::Hash.name_for(&second, input_dir));
+    assert_ne!(hashed, OutputNaming::Hash.name_for(&first.with_extension("jpg"), input_dir));
+
+    let mut config = Config::load("nonexistent_config.yml").unwrap();
+    config.input_dir = "./photos".to_string();
+    assert_eq!(config.named_input(&first), first);
+    config.apply_args(&Args::parse_from(["urasoe", "--output-naming", "relative-path"]));
+    assert_eq!(config.named_input(&first), input_dir.join("2023").join("2023_photo.png"));
+}
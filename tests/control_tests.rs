@@ -0,0 +1,162 @@
+//! Run control and dashboard tests for urasoe
+
+use std::path::Path;
+use std::time::{Duration, Instant};
+use urasoe::api_types::{ProgressResponse, ProgressState};
+use urasoe::control::{RECENT_FAILURES, RunControl};
+use urasoe::status_line;
+use urasoe::dashboard::{handle_key, key_feedback, render};
+use urasoe::processing::{ImageTiming, ProcessingStats};
+
+/// Timing with the given generation duration in seconds
+fn generation_secs(seconds: u64) -> ImageTiming {
+    ImageTiming {
+        generation: Duration::from_secs(seconds),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_control_tracks_progress_and_failures() {
+    let control = RunControl::default();
+    control.begin(10, 2, 1);
+
+    let mut stats = ProcessingStats::new();
+    stats.record_success(Path::new("a.png"), 4, 1.0, generation_secs(3), 1);
+    for index in 0..=RECENT_FAILURES {
+        let path = format!("failed-{}.png", index);
+        stats.record_failure(Path::new(&path), generation_secs(1), 1, "CUDA out of memory");
+    }
+
+    control.input_started(Path::new("a.png"), "http://127.0.0.1:7860");
+    assert_eq!(control.status().active.len(), 1);
+    assert_eq!(control.status().pending(), 6);
+
+    for result in &stats.images {
+        control.input_finished(Path::new(&result.path), result);
+    }
+
+    let status = control.status();
+    assert!(status.active.is_empty());
+    assert_eq!(status.done, 3);
+    assert_eq!(status.failed, 1 + RECENT_FAILURES + 1);
+    assert_eq!(status.generated, 4);
+    assert_eq!(status.recent_failures.len(), RECENT_FAILURES);
+    assert_eq!(status.recent_failures.front().unwrap().path, "failed-1.png");
+    assert_eq!(status.average_generation(), Some(Duration::from_secs(9) / 7));
+
+    assert!(!control.is_finished());
+    control.end();
+    assert!(control.is_finished());
+}
+
+#[tokio::test]
+async fn test_control_pause_skip_and_abort() {
+    let control = RunControl::new();
+    assert!(handle_key(&control, b'p'));
+    assert!(control.is_paused());
+    assert!(handle_key(&control, b'P'));
+    assert!(!control.is_paused());
+    assert!(!handle_key(&control, b'x'));
+
+    let waiter = {
+        let control = control.clone();
+        tokio::spawn(async move { control.skipped().await })
+    };
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    handle_key(&control, b's');
+    tokio::time::timeout(Duration::from_secs(1), waiter)
+        .await
+        .expect("skip should wake the waiting input")
+        .unwrap();
+
+    control.pause();
+    handle_key(&control, b'q');
+    assert!(control.is_aborted());
+    // An aborted run does not stay paused
+    tokio::time::timeout(Duration::from_secs(1), control.wait_while_paused())
+        .await
+        .expect("abort should end the pause");
+}
+
+#[test]
+fn test_resume_key_and_feedback() {
+    let control = RunControl::new();
+    assert!(handle_key(&control, b'p'));
+    assert_eq!(key_feedback(&control, b'p'), Some("Pausing after the current image, press r to resume"));
+    assert!(handle_key(&control, b'r'));
+    assert!(!control.is_paused());
+    assert_eq!(key_feedback(&control, b'r'), Some("Resuming"));
+    // Resuming a run that is not paused keeps it running
+    assert!(handle_key(&control, b'R'));
+    assert!(!control.is_paused());
+    assert_eq!(key_feedback(&control, b'x'), None);
+}
+
+#[test]
+fn test_processing_starts_after_begin() {
+    let control = RunControl::new();
+    control.begin(3, 0, 0);
+    assert!(!control.is_processing());
+    control.start_processing();
+    assert!(control.is_processing());
+    // A new run, e.g. of the next preset, asks its questions before taking inputs again
+    control.begin(3, 0, 0);
+    assert!(!control.is_processing());
+    control.start_processing();
+    control.end();
+    assert!(!control.is_processing());
+}
+
+#[test]
+fn test_dashboard_render() {
+    colored::control::set_override(false);
+    let control = RunControl::default();
+    control.begin(4, 1, 0);
+    control.input_started(Path::new("inputs/b.png"), "http://gpu-1:7860");
+
+    let mut stats = ProcessingStats::new();
+    stats.record_failure(Path::new("inputs/c.png"), generation_secs(2), 1, "Request timed out");
+    control.input_finished(Path::new("inputs/c.png"), &stats.images[0]);
+
+    let lines = render(&control.status(), true, false, Instant::now());
+    assert_eq!(lines[0], "urasoe  2/4 inputs (50%) [PAUSED]");
+    assert_eq!(lines[1], format!("[{}{}]", "#".repeat(20), "-".repeat(20)));
+    assert_eq!(lines[2], "Done 1   Failed 1   Pending 1   Generated 0 images");
+    assert!(lines[3].starts_with("Elapsed 00:00:00   Average 2.0s per input   ETA 00:00:04"));
+    assert!(lines.contains(&"  b.png on http://gpu-1:7860 (0s)".to_string()));
+    assert!(lines.iter().any(|line| line.starts_with("  c.png  ")));
+    assert_eq!(lines.last().unwrap(), "p pause/resume   s skip current   q abort");
+}
+
+#[test]
+fn test_sampling_progress_is_shown() {
+    colored::control::set_override(false);
+    let control = RunControl::default();
+    control.begin(4, 1, 0);
+    control.input_started(Path::new("inputs/kata.png"), "http://gpu-1:7860");
+    let progress = ProgressResponse {
+        progress: 0.4,
+        eta_relative: 8.4,
+        state: ProgressState {
+            sampling_step: 12,
+            sampling_steps: 30,
+        },
+        current_image: None,
+    };
+    control.input_progress(Path::new("inputs/kata.png"), &progress);
+
+    let status = control.status();
+    let sampling = status.active[0].sampling.unwrap();
+    assert_eq!((sampling.step, sampling.steps), (12, 30));
+    assert_eq!(
+        status_line::render(&status, Instant::now()),
+        format!("[{}{}] 1/4 inputs | kata.png [####------] 12/30 steps, 8s left", "#".repeat(5), "-".repeat(15))
+    );
+    let lines = render(&status, false, false, Instant::now());
+    assert!(lines.contains(&"  kata.png on http://gpu-1:7860 (0s)  [####------] 12/30 steps, 8s left".to_string()));
+
+    // An idle server reports no sampling steps
+    control.input_progress(Path::new("inputs/kata.png"), &ProgressResponse::default());
+    assert_eq!(control.status().active[0].sampling, None);
+}
@@ -0,0 +1,178 @@
+//! Daemon module tests for urasoe
+
+use std::collections::HashSet;
+use std::fs;
+use urasoe::config::{Args, Config};
+use urasoe::daemon::{
+    ActiveJob, DONE_DIR, FAILED_DIR, JobSpec, JobState, cancel_job, job_state, next_job, next_job_except, run_job,
+    submit_job,
+};
+use urasoe::metrics::Metrics;
+
+#[test]
+fn test_next_job_prefers_higher_priority() {
+    let spool = tempfile::tempdir().unwrap();
+    fs::write(spool.path().join("a.yml"), "input_dir: a\n").unwrap();
+    fs::write(spool.path().join("b.yaml"), "input_dir: b\npriority: 5\n").unwrap();
+    fs::write(spool.path().join("c.yml"), "input_dir: c\n").unwrap();
+    fs::write(spool.path().join("notes.txt"), "not a job").unwrap();
+
+    let (path, spec) = next_job(spool.path()).unwrap().unwrap();
+    assert_eq!(path, spool.path().join("b.yaml"));
+    assert_eq!(spec.input_dir, "b");
+
+    fs::remove_file(&path).unwrap();
+    let (path, _) = next_job(spool.path()).unwrap().unwrap();
+    assert_eq!(path, spool.path().join("a.yml"));
+}
+
+#[test]
+fn test_next_job_except_passes_over_skipped_jobs() {
+    let spool = tempfile::tempdir().unwrap();
+    fs::write(spool.path().join("a.yml"), "input_dir: a\npriority: 5\n").unwrap();
+    fs::write(spool.path().join("b.yml"), "input_dir: b\n").unwrap();
+
+    let skipped = HashSet::from([spool.path().join("a.yml")]);
+    let (path, _) = next_job_except(spool.path(), &skipped).unwrap().unwrap();
+    assert_eq!(path, spool.path().join("b.yml"));
+
+    let skipped = HashSet::from([spool.path().join("a.yml"), spool.path().join("b.yml")]);
+    assert!(next_job_except(spool.path(), &skipped).unwrap().is_none());
+}
+
+#[test]
+fn test_next_job_moves_invalid_files_to_failed() {
+    let spool = tempfile::tempdir().unwrap();
+    fs::write(spool.path().join("broken.yml"), "priority: [").unwrap();
+
+    assert!(next_job(spool.path()).unwrap().is_none());
+    assert!(spool.path().join(FAILED_DIR).join("broken.yml").exists());
+    assert!(spool.path().join(FAILED_DIR).join("broken.error.log").exists());
+}
+
+#[test]
+fn test_job_spec_to_config_with_preset() {
+    let spool = tempfile::tempdir().unwrap();
+    fs::write(spool.path().join("depth.yml"), "model: depth\nsteps: 12\n").unwrap();
+    let job_path = spool.path().join("job.yml");
+    let spec: JobSpec =
+        serde_yaml::from_str("input_dir: in\noutput_dir: out\npreset: depth.yml\n").unwrap();
+
+    let mut base = Config::load("nonexistent_config.yml").unwrap();
+    base.cfg = 4.5;
+    let args = Args {
+        steps: Some(40),
+        ..Default::default()
+    };
+    let config = spec.to_config(&job_path, &base, &args).unwrap();
+    assert_eq!(config.model, "depth");
+    assert_eq!(config.steps, 40);
+    // Settings the preset leaves out come from the daemon configuration
+    assert_eq!(config.cfg, 4.5);
+    assert_eq!(config.input_dir, "in");
+    assert_eq!(config.output_dir, "out");
+
+    let missing: JobSpec = serde_yaml::from_str("input_dir: in\npreset: missing.yml\n").unwrap();
+    assert!(missing.to_config(&job_path, &base, &args).is_err());
+}
+
+#[tokio::test]
+async fn test_run_job_archives_with_stats() {
+    let spool = tempfile::tempdir().unwrap();
+    let input_dir = tempfile::tempdir().unwrap();
+    let output_dir = tempfile::tempdir().unwrap();
+    let job_path = spool.path().join("empty.yml");
+    let spec = JobSpec {
+        input_dir: input_dir.path().to_string_lossy().into_owned(),
+        output_dir: Some(output_dir.path().to_string_lossy().into_owned()),
+        preset: None,
+        priority: 0,
+    };
+    fs::write(&job_path, serde_yaml::to_string(&spec).unwrap()).unwrap();
+
+    let base = Config::load("nonexistent_config.yml").unwrap();
+    let archived = run_job(spool.path(), &job_path, &spec, &base, &Args::default(), &Metrics::new(), &ActiveJob::new())
+        .await
+        .unwrap();
+
+    assert_eq!(archived, spool.path().join(DONE_DIR).join("empty.yml"));
+    assert!(!job_path.exists());
+    assert!(spool.path().join(DONE_DIR).join("empty.stats.json").exists());
+}
+
+#[tokio::test]
+async fn test_interrupted_job_stays_in_spool() {
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/sdapi/v1/txt2img"))
+        .respond_with(ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(5)))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({})))
+        .mount(&server)
+        .await;
+    let spool = tempfile::tempdir().unwrap();
+    let input_dir = tempfile::tempdir().unwrap();
+    fs::write(input_dir.path().join("kata.png"), "png").unwrap();
+    let output_dir = tempfile::tempdir().unwrap();
+    let job_path = spool.path().join("slow.yml");
+    let spec = JobSpec {
+        input_dir: input_dir.path().to_string_lossy().into_owned(),
+        output_dir: Some(output_dir.path().to_string_lossy().into_owned()),
+        preset: None,
+        priority: 0,
+    };
+    fs::write(&job_path, serde_yaml::to_string(&spec).unwrap()).unwrap();
+
+    let mut base = Config::load("nonexistent_config.yml").unwrap();
+    base.sd_api_url = format!("{}/", server.uri());
+    base.validate_options = false;
+    base.assume_yes = true;
+    let active = ActiveJob::new();
+    let interrupt = async {
+        while active.current().is_none() {
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+        active.current().unwrap().1.interrupt();
+    };
+    let metrics = Metrics::new();
+    let args = Args::default();
+    let (archived, _) = tokio::join!(
+        run_job(spool.path(), &job_path, &spec, &base, &args, &metrics, &active),
+        interrupt
+    );
+
+    assert_eq!(archived.unwrap(), job_path);
+    assert!(job_path.exists());
+    assert!(!spool.path().join(FAILED_DIR).join("slow.yml").exists());
+}
+
+#[test]
+fn test_submit_and_cancel_job() {
+    let spool = tempfile::tempdir().unwrap();
+    let active = ActiveJob::new();
+    let spec = JobSpec {
+        input_dir: "./incoming".to_string(),
+        output_dir: None,
+        preset: None,
+        priority: 3,
+    };
+    let id = submit_job(spool.path(), &spec).unwrap();
+    assert_eq!(job_state(spool.path(), &id, &active).0, JobState::Pending);
+    let (_, queued) = next_job(spool.path()).unwrap().unwrap();
+    assert_eq!(queued.priority, 3);
+
+    assert_eq!(cancel_job(spool.path(), &id, &active).unwrap(), JobState::Cancelled);
+    let (state, report) = job_state(spool.path(), &id, &active);
+    assert_eq!(state, JobState::Cancelled);
+    assert!(report.unwrap().starts_with("Cancelled"));
+    assert!(next_job(spool.path()).unwrap().is_none());
+
+    assert_eq!(job_state(spool.path(), "missing", &active).0, JobState::Unknown);
+    assert_eq!(job_state(spool.path(), "../escape", &active).0, JobState::Unknown);
+}
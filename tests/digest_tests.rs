@@ -0,0 +1,35 @@
+//! Request digest tests for urasoe
+
+use serde_json::json;
+
+use urasoe::digest::{RequestDigest, sha256_hex};
+
+#[test]
+fn test_sha256_known_values() {
+    assert_eq!(sha256_hex(b""), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+    assert_eq!(sha256_hex(b"abc"), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+    // Long enough for the padding to need a second block
+    assert_eq!(
+        sha256_hex(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+        "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+    );
+}
+
+#[test]
+fn test_request_digest_leaves_out_control_images() {
+    let payload = |image: &str, seed: i64| {
+        json!({
+            "prompt": "kata",
+            "seed": seed,
+            "alwayson_scripts": {"controlnet": {"args": [{"input_image": image, "weight": 1.0}, {"image": image}]}}
+        })
+    };
+    let digest = RequestDigest::new(&payload("aW1hZ2U=", 42), b"image", None);
+    assert_eq!(digest, RequestDigest::new(&payload("b3RoZXI=", 42), b"image", None));
+    assert_eq!(digest.image_sha256, sha256_hex(b"image"));
+    assert_ne!(digest.image_sha256, RequestDigest::new(&payload("aW1hZ2U=", 42), b"other", None).image_sha256);
+
+    // A random seed counts as the seed the server picked
+    assert_eq!(digest, RequestDigest::new(&payload("aW1hZ2U=", -1), b"image", Some(42)));
+    assert_ne!(digest.payload_sha256, RequestDigest::new(&payload("aW1hZ2U=", 7), b"image", Some(42)).payload_sha256);
+}
@@ -0,0 +1,117 @@
+//! Diagnostics tests for urasoe
+
+use serde_json::json;
+use tempfile::tempdir;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use urasoe::config::Config;
+use urasoe::doctor::{CheckStatus, check_output_writable, render, run_checks};
+
+/// Server answering every endpoint the checks use
+async fn healthy_server() -> MockServer {
+    let server = MockServer::start().await;
+    let responses = [
+        ("/sdapi/v1/progress", json!({"progress": 0.0})),
+        ("/internal/sysinfo", json!({"Version": "v1.10.1"})),
+        ("/controlnet/version", json!({"version": 2})),
+        ("/sdapi/v1/sd-models", json!([{"title": "realisticVisionV51_v51VAE"}])),
+        ("/controlnet/model_list", json!({"model_list": [{"model_name": "control_canny_sd15.pth"}]})),
+        ("/controlnet/module_list", json!({"module_list": ["canny"]})),
+        ("/sdapi/v1/samplers", json!([{"name": "DPM++ 2M"}])),
+    ];
+    for (endpoint, body) in responses {
+        Mock::given(method("GET"))
+            .and(path(endpoint))
+            .respond_with(ResponseTemplate::new(200).set_body_json(body))
+            .mount(&server)
+            .await;
+    }
+    server
+}
+
+/// Configuration pointing at the given server, writing into a temporary directory
+fn config_for(api_url: &str, output_dir: &std::path::Path) -> Config {
+    let mut config = Config::load("nonexistent_config.yml").unwrap();
+    config.sd_api_url = api_url.to_string();
+    config.output_dir = output_dir.to_string_lossy().to_string();
+    config
+}
+
+#[tokio::test]
+async fn test_doctor_healthy_server_passes() {
+    let server = healthy_server().await;
+    let temp_dir = tempdir().unwrap();
+    let config = config_for(&format!("{}/", server.uri()), temp_dir.path());
+
+    let checks = run_checks(&config).await;
+    let names: Vec<&str> = checks.iter().map(|check| check.name.as_str()).collect();
+    assert_eq!(
+        names,
+        [
+            "API reachable",
+            "API authentication",
+            "Web UI version",
+            "ControlNet extension",
+            "Configured models",
+            "Output directory writable",
+            "Free disk space",
+        ]
+    );
+    for check in &checks[..6] {
+        assert_eq!(check.status, CheckStatus::Pass, "{:?}", check);
+    }
+    assert_eq!(checks[2].detail, "v1.10.1");
+    // The output directory is left as it was
+    assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 0);
+}
+
+#[tokio::test]
+async fn test_doctor_reports_rejected_credentials() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(401))
+        .mount(&server)
+        .await;
+    let temp_dir = tempdir().unwrap();
+    let config = config_for(&format!("{}/", server.uri()), temp_dir.path());
+
+    let checks = run_checks(&config).await;
+    assert_eq!(checks[0].status, CheckStatus::Pass);
+    assert_eq!(checks[1].name, "API authentication");
+    assert_eq!(checks[1].status, CheckStatus::Fail);
+    assert!(checks[1].hint.is_some());
+    assert_eq!(checks[2].name, "Output directory writable");
+}
+
+#[tokio::test]
+async fn test_doctor_reports_missing_models_and_extension() {
+    let server = healthy_server().await;
+    let temp_dir = tempdir().unwrap();
+    let mut config = config_for(&format!("{}/", server.uri()), temp_dir.path());
+    config.checkpoint_model = "missing".to_string();
+
+    let checks = run_checks(&config).await;
+    let models = checks.iter().find(|check| check.name == "Configured models").unwrap();
+    assert_eq!(models.status, CheckStatus::Fail);
+    assert!(models.detail.contains("missing"));
+
+    let unreachable = config_for("http://127.0.0.1:9/", temp_dir.path());
+    let checks = run_checks(&unreachable).await;
+    assert_eq!(checks[0].name, "API reachable");
+    assert_eq!(checks[0].status, CheckStatus::Fail);
+}
+
+#[test]
+fn test_doctor_output_not_writable_and_render() {
+    let temp_dir = tempdir().unwrap();
+    let file = temp_dir.path().join("file");
+    std::fs::write(&file, "").unwrap();
+    let check = check_output_writable(&file.join("output").to_string_lossy());
+    assert_eq!(check.status, CheckStatus::Fail);
+
+    colored::control::set_override(false);
+    let output = render(&[check]);
+    assert!(output.starts_with("✗ Output directory writable: "));
+    assert!(output.ends_with("    Choose another output_dir or fix the permissions of this one\n"));
+}
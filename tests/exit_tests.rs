@@ -0,0 +1,136 @@
+//! Exit code tests for urasoe
+
+use std::path::Path;
+use std::process::Command;
+
+use urasoe::exit::{ConfigInvalid, ExitStatus};
+use urasoe::processing::{ImageTiming, ProcessingStats};
+
+fn stats(successes: usize, failures: &[&str]) -> ProcessingStats {
+    let mut stats = ProcessingStats::new();
+    for index in 0..successes {
+        stats.record_success(Path::new(&format!("ok-{}.png", index)), 1, 0.5, ImageTiming::default(), 1);
+    }
+    for (index, message) in failures.iter().enumerate() {
+        stats.record_failure(Path::new(&format!("bad-{}.png", index)), ImageTiming::default(), 3, message);
+    }
+    stats
+}
+
+#[test]
+fn test_exit_status_from_stats() {
+    assert_eq!(ExitStatus::from_stats(&stats(2, &[])), ExitStatus::Success);
+    assert_eq!(ExitStatus::from_stats(&stats(1, &["CUDA out of memory"])), ExitStatus::PartialFailure);
+    assert_eq!(ExitStatus::from_stats(&stats(0, &["CUDA out of memory"])), ExitStatus::AllFailed);
+    assert_eq!(
+        ExitStatus::from_stats(&stats(0, &["error sending request: connection refused"])),
+        ExitStatus::ApiUnreachable
+    );
+    assert_eq!(ExitStatus::PartialFailure.code(), 2);
+    assert_eq!(ExitStatus::ApiUnreachable.code(), 5);
+}
+
+#[test]
+fn test_exit_status_combine() {
+    let ok = stats(1, &[]);
+    let failed = stats(0, &["CUDA out of memory"]);
+    assert_eq!(ExitStatus::combine([&ok, &ok]), ExitStatus::Success);
+    assert_eq!(ExitStatus::combine([&ok, &failed]), ExitStatus::PartialFailure);
+    assert_eq!(ExitStatus::combine([&failed, &failed]), ExitStatus::AllFailed);
+    assert_eq!(ExitStatus::combine(None::<&ProcessingStats>), ExitStatus::Success);
+}
+
+#[test]
+fn test_exit_status_from_error() {
+    let invalid = anyhow::Error::from(ConfigInvalid("2 configuration issues found".to_string()));
+    assert_eq!(ExitStatus::from_error(&invalid.context("Validation failed")), ExitStatus::ConfigInvalid);
+    let yaml = serde_yaml::from_str::<u32>("[").unwrap_err();
+    assert_eq!(ExitStatus::from_error(&anyhow::Error::from(yaml)), ExitStatus::ConfigInvalid);
+    assert_eq!(ExitStatus::from_error(&anyhow::anyhow!("disk full")), ExitStatus::Error);
+}
+
+#[tokio::test]
+async fn test_exit_status_from_unreachable_server() {
+    let error = reqwest::get("http://127.0.0.1:1/").await.unwrap_err();
+    let error = anyhow::Error::from(error).context("Failed to load model");
+    assert_eq!(ExitStatus::from_error(&error), ExitStatus::ApiUnreachable);
+}
+
+#[test]
+fn test_binary_exits_with_config_invalid() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = dir.path().join("broken.yml");
+    std::fs::write(&config, "width: [not a number").unwrap();
+    let status = Command::new(env!("CARGO_BIN_EXE_urasoe"))
+        .args(["--config", config.to_str().unwrap(), "validate"])
+        .status()
+        .unwrap();
+    assert_eq!(status.code(), Some(4));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_binary_stops_gracefully_on_ctrl_c() {
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    // The server keeps generating until it is interrupted
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/sdapi/v1/txt2img"))
+        .respond_with(ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(30)))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/sdapi/v1/interrupt"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({})))
+        .mount(&server)
+        .await;
+
+    let dir = tempfile::tempdir().unwrap();
+    let input_dir = dir.path().join("input");
+    std::fs::create_dir_all(&input_dir).unwrap();
+    std::fs::write(input_dir.join("kata.png"), "png").unwrap();
+    std::fs::write(input_dir.join("tsuki.png"), "png").unwrap();
+    let output_dir = dir.path().join("output");
+    let config = dir.path().join("config.yml");
+    std::fs::write(&config, format!("sd_api_url: \"{}/\"\nvalidate_options: false\n", server.uri())).unwrap();
+    let mut child = tokio::process::Command::new(env!("CARGO_BIN_EXE_urasoe"))
+        .args(["--config", config.to_str().unwrap(), "--yes"])
+        .args(["--input-dir", input_dir.to_str().unwrap(), "--output-dir", output_dir.to_str().unwrap()])
+        .args(["--progress-file", "progress.json"])
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .unwrap();
+
+    // Interrupt once the first input is being generated
+    for _ in 0..200 {
+        let requests = server.received_requests().await.unwrap_or_default();
+        if requests.iter().any(|request| request.url.path() == "/sdapi/v1/txt2img") {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+    // SAFETY: sends SIGINT to the child process spawned above
+    unsafe {
+        libc::kill(child.id().unwrap() as libc::pid_t, libc::SIGINT);
+    }
+    let status = tokio::time::timeout(std::time::Duration::from_secs(10), child.wait())
+        .await
+        .expect("the run should stop well before the generation finishes")
+        .unwrap();
+    assert_eq!(status.code(), Some(ExitStatus::Interrupted.code().into()));
+    server.verify().await;
+
+    // The progress is written and both the interrupted and the other input stay queued for the next run
+    let progress = urasoe::progress::Progress::read(&output_dir.join("progress.json")).unwrap();
+    assert_eq!(progress.state, urasoe::progress::ProgressState::Aborted);
+    assert_eq!(progress.pending, 2);
+    assert_eq!(progress.failed, 0);
+}
@@ -10,6 +10,7 @@ fn test_save_generated_images_empty() {
         images: vec![],
         parameters: None,
         info: None,
+        digest: None,
     }, &fake_path, &config);
     assert!(result.is_ok());
 }
@@ -20,9 +21,10 @@ fn test_save_generated_images_invalid_base64() {
     let config = urasoe::config::Config::load("nonexistent_file.yml").unwrap();
     let fake_path = temp_dir.path().join("input.png");
     let result = urasoe::file_utils::FileManager::save_generated_images(&urasoe::api::StableDiffusionResponse {
-        images: vec!["not_base64".to_string()],
+        images: vec![urasoe::api::GeneratedImage::from_base64("not_base64").unwrap()],
         parameters: None,
         info: None,
+        digest: None,
     }, &fake_path, &config);
     assert!(result.is_err());
 }
@@ -36,9 +38,10 @@ fn test_save_generated_images_valid_base64() {
     // Create a valid 1x1 PNG image in base64
     let png_base64 = "iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAQAAAC1HAwCAAAAC0lEQVR42mP8/w8AAgMBApUAAAAASUVORK5CYII=";
     let result = urasoe::file_utils::FileManager::save_generated_images(&urasoe::api::StableDiffusionResponse {
-        images: vec![png_base64.to_string()],
+        images: vec![urasoe::api::GeneratedImage::from_base64(png_base64).unwrap()],
         parameters: None,
         info: None,
+        digest: None,
     }, &fake_path, &config);
     assert!(result.is_ok());
     // Check that the image file was created
@@ -48,6 +51,27 @@ fn test_save_generated_images_valid_base64() {
     assert!(image_path.exists());
 }
 
+#[test]
+fn test_save_generated_images_skips_undecodable() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let mut config = urasoe::config::Config::load("nonexistent_file.yml").unwrap();
+    config.output_dir = temp_dir.path().to_string_lossy().to_string();
+    config.batch_size = 3;
+    let fake_path = temp_dir.path().join("input.png");
+    let png_base64 = "iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAQAAAC1HAwCAAAAC0lEQVR42mP8/w8AAgMBApUAAAAASUVORK5CYII=";
+    let saved = urasoe::file_utils::FileManager::save_generated_images(&urasoe::api::StableDiffusionResponse {
+        images: vec![urasoe::api::GeneratedImage::from_base64("not_base64").unwrap(), urasoe::api::GeneratedImage::from_base64(png_base64).unwrap()],
+        parameters: None,
+        info: None,
+        digest: None,
+    }, &fake_path, &config).unwrap();
+    assert_eq!(saved.skipped, 1);
+    // One image of the three requested was not returned at all, one was undecodable
+    assert_eq!(saved.missing, 2);
+    assert_eq!(saved.paths, [temp_dir.path().join("input").join("input-1.png")]);
+    assert_eq!(saved.bytes, 65);
+}
+
 #[test]
 fn test_save_generated_images_metadata_created() {
     let temp_dir = tempfile::tempdir().unwrap();
@@ -56,9 +80,10 @@ fn test_save_generated_images_metadata_created() {
     let fake_path = temp_dir.path().join("input.png");
     let png_base64 = "iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAQAAAC1HAwCAAAAC0lEQVR42mP8/w8AAgMBApUAAAAASUVORK5CYII=";
     let result = urasoe::file_utils::FileManager::save_generated_images(&urasoe::api::StableDiffusionResponse {
-        images: vec![png_base64.to_string()],
+        images: vec![urasoe::api::GeneratedImage::from_base64(png_base64).unwrap()],
         parameters: None,
         info: None,
+        digest: None,
     }, &fake_path, &config);
     assert!(result.is_ok());
     let base_name = fake_path.file_stem().unwrap().to_string_lossy();
@@ -92,10 +117,11 @@ fn test_save_generated_images_unwritable_dir() {
         fs::set_permissions(&unwritable, perms).unwrap();
         config.output_dir = unwritable.to_string_lossy().to_string();
         let result = urasoe::file_utils::FileManager::save_generated_images(&urasoe::api::StableDiffusionResponse {
-            images: vec![png_base64.to_string()],
+            images: vec![urasoe::api::GeneratedImage::from_base64(png_base64).unwrap()],
             parameters: None,
             info: None,
-        }, &fake_path, &config);
+            digest: None,
+        }, fake_path, &config);
         assert!(result.is_err());
     }
     #[cfg(windows)]
@@ -109,10 +135,183 @@ fn test_save_generated_images_unwritable_dir() {
         fs::set_permissions(&unwritable_file, perms).unwrap();
         config.output_dir = unwritable_file.to_string_lossy().to_string();
         let result = urasoe::file_utils::FileManager::save_generated_images(&urasoe::api::StableDiffusionResponse {
-            images: vec![png_base64.to_string()],
+            images: vec![urasoe::api::GeneratedImage::from_base64(png_base64).unwrap()],
             parameters: None,
             info: None,
+            digest: None,
         }, fake_path, &config);
         assert!(result.is_err());
     }
 }
+
+#[test]
+fn test_dead_letter_off_leaves_input() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let mut config = urasoe::config::Config::load("nonexistent_file.yml").unwrap();
+    config.output_dir = temp_dir.path().join("out").to_string_lossy().to_string();
+    let input = temp_dir.path().join("input.png");
+    std::fs::write(&input, [1u8, 2, 3]).unwrap();
+
+    let result = urasoe::file_utils::FileManager::dead_letter(&input, "boom", 3, &config).unwrap();
+    assert!(result.is_none());
+    assert!(input.exists());
+    assert!(!temp_dir.path().join("out").join("_failed").exists());
+}
+
+#[test]
+fn test_dead_letter_copy_and_move() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let mut config = urasoe::config::Config::load("nonexistent_file.yml").unwrap();
+    config.output_dir = temp_dir.path().join("out").to_string_lossy().to_string();
+    let failed_dir = temp_dir.path().join("out").join("_failed");
+
+    let copied = temp_dir.path().join("copied.png");
+    std::fs::write(&copied, [1u8, 2, 3]).unwrap();
+    config.dead_letter = urasoe::config::DeadLetterMode::Copy;
+    let target = urasoe::file_utils::FileManager::dead_letter(&copied, "CUDA out of memory", 3, &config)
+        .unwrap()
+        .unwrap();
+    assert_eq!(target, failed_dir.join("copied.png"));
+    assert!(copied.exists());
+    assert!(target.exists());
+    let log = std::fs::read_to_string(failed_dir.join("copied.png.error.log")).unwrap();
+    assert!(log.contains("attempts: 3"));
+    assert!(log.contains("error: CUDA out of memory"));
+
+    let moved = temp_dir.path().join("moved.png");
+    std::fs::write(&moved, [4u8, 5, 6]).unwrap();
+    config.dead_letter = urasoe::config::DeadLetterMode::Move;
+    urasoe::file_utils::FileManager::dead_letter(&moved, "timeout", 1, &config).unwrap();
+    assert!(!moved.exists());
+    assert_eq!(std::fs::read(failed_dir.join("moved.png")).unwrap(), vec![4u8, 5, 6]);
+
+    // A later failure of the moved input keeps the original reason
+    urasoe::file_utils::FileManager::dead_letter(&moved, "No such file", 1, &config).unwrap();
+    let log = std::fs::read_to_string(failed_dir.join("moved.png.error.log")).unwrap();
+    assert!(log.starts_with("timestamp: "));
+    assert!(log.find("error: timeout").unwrap() < log.find("error: No such file").unwrap());
+    assert_eq!(std::fs::read(failed_dir.join("moved.png")).unwrap(), vec![4u8, 5, 6]);
+}
+
+#[test]
+fn test_dead_letter_keeps_same_named_inputs_apart() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let input_dir = temp_dir.path().join("input");
+    let mut config = urasoe::config::Config::load("nonexistent_file.yml").unwrap();
+    config.input_dir = input_dir.to_string_lossy().to_string();
+    config.output_dir = temp_dir.path().join("out").to_string_lossy().to_string();
+    config.recursive = true;
+    config.dead_letter = urasoe::config::DeadLetterMode::Move;
+    let failed_dir = temp_dir.path().join("out").join("_failed");
+
+    for (folder, content) in [("a", 1u8), ("b", 2u8)] {
+        let input = input_dir.join(folder).join("kata.png");
+        std::fs::create_dir_all(input.parent().unwrap()).unwrap();
+        std::fs::write(&input, [content]).unwrap();
+        let target = urasoe::file_utils::FileManager::dead_letter(&input, folder, 1, &config)
+            .unwrap()
+            .unwrap();
+        assert_eq!(target, failed_dir.join(folder).join("kata.png"));
+    }
+    for (folder, content) in [("a", 1u8), ("b", 2u8)] {
+        let folder_dir = failed_dir.join(folder);
+        assert_eq!(std::fs::read(folder_dir.join("kata.png")).unwrap(), vec![content]);
+        let log = std::fs::read_to_string(folder_dir.join("kata.png.error.log")).unwrap();
+        assert!(log.contains(&format!("error: {}\n", folder)));
+        assert!(folder_dir.join("kata.png.metadata.json").exists());
+    }
+}
+
+#[test]
+fn test_read_and_find_metadata() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let mut config = urasoe::config::Config::load("nonexistent_file.yml").unwrap();
+    config.prompt = "mawashi geri".to_string();
+    config.steps = 12;
+    let png_base64 = "iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAQAAAC1HAwCAAAAC0lEQVR42mP8/w8AAgMBApUAAAAASUVORK5CYII=";
+    let response = urasoe::api::StableDiffusionResponse {
+        images: vec![urasoe::api::GeneratedImage::from_base64(png_base64).unwrap()],
+        parameters: None,
+        info: None,
+        digest: None,
+    };
+    for (output_dir, input) in [("", "b.png"), ("", "a.png"), ("preset", "c.png")] {
+        config.output_dir = temp_dir.path().join(output_dir).to_string_lossy().to_string();
+        urasoe::file_utils::FileManager::save_generated_images(&response, std::path::Path::new(input), &config)
+            .unwrap();
+    }
+    let failed_dir = temp_dir.path().join("_failed");
+    std::fs::create_dir_all(&failed_dir).unwrap();
+    std::fs::write(failed_dir.join("d-metadata.json"), "{}").unwrap();
+    std::fs::write(temp_dir.path().join("a").join("broken-metadata.json"), "{").unwrap();
+
+    let metadata_path = temp_dir.path().join("a").join("a-metadata.json");
+    let metadata = urasoe::file_utils::ImageMetadata::read(&metadata_path).unwrap();
+    assert_eq!(metadata.prompt, "mawashi geri");
+    assert_eq!(metadata.steps, 12);
+    assert_eq!(metadata.source_image, "a.png");
+
+    let found = urasoe::file_utils::find_metadata(temp_dir.path()).unwrap();
+    let sources: Vec<&str> = found.iter().map(|(_, metadata)| metadata.source_image.as_str()).collect();
+    assert_eq!(sources, ["a.png", "b.png", "c.png"]);
+    assert_eq!(found[0].0, metadata_path);
+
+    assert!(urasoe::file_utils::ImageMetadata::read(&temp_dir.path().join("missing.json")).is_err());
+    assert!(urasoe::file_utils::find_metadata(&temp_dir.path().join("missing")).unwrap().is_empty());
+}
+
+#[test]
+fn test_infotext_matches_web_ui_format() {
+    let mut config = urasoe::config::Config::load("nonexistent_file.yml").unwrap();
+    config.prompt = "karateka, dojo".to_string();
+    config.negative_prompt = "blurry".to_string();
+    config.steps = 20;
+    config.sampler_name = "Euler a".to_string();
+    config.scheduler = "Karras".to_string();
+    config.cfg = 7.0;
+    config.seed = 1234;
+    config.width = 512;
+    config.height = 768;
+    config.checkpoint_model = "dreamshaper_8".to_string();
+    config.controlnet_module = "canny".to_string();
+    config.model = "control_v11p_sd15_canny".to_string();
+    config.controlnet_weight = 0.8;
+    let metadata = urasoe::file_utils::ImageMetadata::from_config(&config, std::path::Path::new("kata.png"));
+
+    assert_eq!(
+        metadata.infotext(1),
+        "karateka, dojo\nNegative prompt: blurry\nSteps: 20, Sampler: Euler a, Schedule type: Karras, CFG scale: 7.0, \
+         Seed: 1235, Size: 512x768, Model: dreamshaper_8, \
+         ControlNet 0: \"Module: canny, Model: control_v11p_sd15_canny, Weight: 0.8\""
+    );
+}
+
+#[test]
+fn test_encode_png_with_settings() {
+    use urasoe::file_utils::{PngCompression, PngConfig, encode_png};
+
+    let image = image::DynamicImage::ImageRgb16(image::ImageBuffer::from_pixel(4, 4, image::Rgb([1000u16, 2000, 3000])));
+    let mut original = Vec::new();
+    image.write_to(&mut std::io::Cursor::new(&mut original), image::ImageFormat::Png).unwrap();
+
+    // Nothing to change, the bytes of the server are kept
+    let kept = encode_png(original.clone(), &PngConfig::default()).unwrap();
+    assert_eq!(kept, original);
+
+    let png = PngConfig {
+        compression: Some(PngCompression::Best),
+        sixteen_bit: true,
+    };
+    let encoded = image::load_from_memory(&encode_png(original.clone(), &png).unwrap()).unwrap();
+    assert_eq!(encoded.color(), image::ColorType::Rgb16);
+
+    let png = PngConfig {
+        compression: None,
+        sixteen_bit: false,
+    };
+    let reduced = image::load_from_memory(&encode_png(original, &png).unwrap()).unwrap();
+    assert_eq!(reduced.color(), image::ColorType::Rgb8);
+
+    let jpeg = vec![0xFF, 0xD8, 0xFF, 0xE0];
+    assert_eq!(encode_png(jpeg.clone(), &png).unwrap(), jpeg);
+}
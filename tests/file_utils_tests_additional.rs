@@ -3,7 +3,7 @@
 use std::fs;
 use std::io::Write;
 use tempfile::tempdir;
-use urasoe::api::StableDiffusionResponse;
+use urasoe::api::{GeneratedImage, StableDiffusionResponse};
 use urasoe::config::Config;
 use urasoe::file_utils::FileManager;
 
@@ -22,13 +22,14 @@ fn test_save_multiple_generated_images() {
     let png_base64_2 = "iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAQAAAC1HAwCAAAAC0lEQVR42mNk+A8AAQUBAScY42YAAAAASUVORK5CYII=";
       // Create a response with multiple images
     let response = StableDiffusionResponse {
-        images: vec![png_base64_1.to_string(), png_base64_2.to_string()],
+        images: vec![GeneratedImage::from_base64(png_base64_1).unwrap(), GeneratedImage::from_base64(png_base64_2).unwrap()],
         parameters: Some(serde_json::json!({
             "prompt": "test prompt", 
             "negative_prompt": "test negative",
             "sd_model_checkpoint": "test_model"
         })),
         info: Some("Test generation info".to_string()),
+        digest: None,
     };
     
     // Save the generated images
@@ -79,9 +80,10 @@ fn test_save_images_with_nested_output_dir() {
     
     // Create a response with one image
     let response = StableDiffusionResponse {
-        images: vec![png_base64.to_string()],
+        images: vec![GeneratedImage::from_base64(png_base64).unwrap()],
         parameters: None,
         info: None,
+        digest: None,
     };
     
     // This should create all required directories
@@ -111,7 +113,7 @@ fn test_metadata_contents() {
     let input_path = temp_dir.path().join("input.png");
       // Create a valid response
     let response = StableDiffusionResponse {
-        images: vec!["iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAQAAAC1HAwCAAAAC0lEQVR42mP8/w8AAgMBApUAAAAASUVORK5CYII=".to_string()],
+        images: vec![GeneratedImage::from_base64("iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAQAAAC1HAwCAAAAC0lEQVR42mP8/w8AAgMBApUAAAAASUVORK5CYII=").unwrap()],
         parameters: Some(serde_json::json!({
             "prompt": "test prompt",
             "cfg_scale": 7.5,
@@ -119,6 +121,7 @@ fn test_metadata_contents() {
             "sd_model_checkpoint": "test_model"
         })),
         info: Some("Generation info".to_string()),
+        digest: None,
     };
     
     // Save the generated images
@@ -162,9 +165,10 @@ fn test_filenames_with_special_chars() {
         
         // Create a simple response
         let response = StableDiffusionResponse {
-            images: vec!["iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAQAAAC1HAwCAAAAC0lEQVR42mP8/w8AAgMBApUAAAAASUVORK5CYII=".to_string()],
+            images: vec![GeneratedImage::from_base64("iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAQAAAC1HAwCAAAAC0lEQVR42mP8/w8AAgMBApUAAAAASUVORK5CYII=").unwrap()],
             parameters: None,
             info: None,
+            digest: None,
         };
         
         // Save the generated image
@@ -2,7 +2,7 @@
 
 use std::fs;
 use tempfile::tempdir;
-use urasoe::api::StableDiffusionResponse;
+use urasoe::api::{GeneratedImage, StableDiffusionResponse};
 use urasoe::config::Config;
 use urasoe::file_utils::FileManager;
 
@@ -26,7 +26,7 @@ fn test_metadata_uses_config_values() {
     
     // Create a response with different values than config
     let response = StableDiffusionResponse {
-        images: vec!["iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAQAAAC1HAwCAAAAC0lEQVR42mP8/w8AAgMBApUAAAAASUVORK5CYII=".to_string()],
+        images: vec![GeneratedImage::from_base64("iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAQAAAC1HAwCAAAAC0lEQVR42mP8/w8AAgMBApUAAAAASUVORK5CYII=").unwrap()],
         parameters: Some(serde_json::json!({
             "prompt": "api response prompt", 
             "negative_prompt": "api response negative",
@@ -35,6 +35,7 @@ fn test_metadata_uses_config_values() {
             "sd_model_checkpoint": "api-checkpoint"
         })),
         info: Some("Generation info".to_string()),
+        digest: None,
     };
     
     // Save the generated images
@@ -74,13 +75,14 @@ fn test_response_values_not_in_metadata() {
     
     // Create a response with values that aren't in the default config
     let response = StableDiffusionResponse {
-        images: vec!["iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAQAAAC1HAwCAAAAC0lEQVR42mP8/w8AAgMBApUAAAAASUVORK5CYII=".to_string()],
+        images: vec![GeneratedImage::from_base64("iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAQAAAC1HAwCAAAAC0lEQVR42mP8/w8AAgMBApUAAAAASUVORK5CYII=").unwrap()],
         parameters: Some(serde_json::json!({
             "custom_field": "custom value",
             "another_custom": 123,
             "sd_model_checkpoint": "api-checkpoint"
         })),
         info: Some("Generation info".to_string()),
+        digest: None,
     };
     
     // Save the generated images
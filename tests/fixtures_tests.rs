@@ -0,0 +1,120 @@
+//! Fixture recording and replay tests for urasoe
+
+use std::fs;
+use std::path::Path;
+use tempfile::tempdir;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use urasoe::config::Config;
+use urasoe::control::RunControl;
+use urasoe::fixtures::{FixtureConfig, FixtureMode, Fixtures};
+use urasoe::metrics::Metrics;
+use urasoe::runner::run_batch;
+
+const PNG_DATA: [u8; 67] = [
+    137, 80, 78, 71, 13, 10, 26, 10, 0, 0, 0, 13, 73, 72, 68, 82, 0, 0, 0, 1, 0, 0, 0, 1, 8, 6, 0,
+    0, 0, 31, 21, 196, 137, 0, 0, 0, 10, 73, 68, 65, 84, 120, 156, 99, 0, 1, 0, 0, 5, 0, 1, 13, 10,
+    45, 180, 0, 0, 0, 0, 73, 69, 78, 68, 174, 66, 96, 130,
+];
+
+fn fixture_config(mode: FixtureMode, dir: &Path) -> FixtureConfig {
+    FixtureConfig {
+        mode,
+        dir: dir.to_string_lossy().to_string(),
+    }
+}
+
+#[test]
+fn test_fixtures_off_by_default() {
+    assert!(Fixtures::from_config(&FixtureConfig::default(), Path::new("in")).is_none());
+}
+
+#[test]
+fn test_fixtures_numbered_per_input() {
+    let temp_dir = tempdir().unwrap();
+    let recorder = Fixtures::from_config(&fixture_config(FixtureMode::Record, temp_dir.path()), Path::new("in")).unwrap();
+    recorder.record(Path::new("in/a.png"), "first").unwrap();
+    recorder.record(Path::new("in/a.png"), "second").unwrap();
+    recorder.record(Path::new("in/b.png"), "other").unwrap();
+
+    assert!(temp_dir.path().join("a.png.1.json").exists());
+    assert!(temp_dir.path().join("a.png.2.json").exists());
+    assert!(temp_dir.path().join("b.png.1.json").exists());
+
+    let player = Fixtures::from_config(&fixture_config(FixtureMode::Replay, temp_dir.path()), Path::new("in")).unwrap();
+    assert!(player.is_replay());
+    assert_eq!(player.replay(Path::new("in/a.png")).unwrap(), "first");
+    assert_eq!(player.replay(Path::new("in/a.png")).unwrap(), "second");
+    assert!(player.replay(Path::new("in/a.png")).is_err());
+}
+
+#[test]
+fn test_fixtures_of_same_named_inputs_kept_apart() {
+    let temp_dir = tempdir().unwrap();
+    let recorder = Fixtures::from_config(&fixture_config(FixtureMode::Record, temp_dir.path()), Path::new("in")).unwrap();
+    recorder.record(Path::new("in/day/a.png"), "day").unwrap();
+    recorder.record(Path::new("in/night/a.png"), "night").unwrap();
+
+    assert!(temp_dir.path().join("day").join("a.png.1.json").exists());
+    assert!(temp_dir.path().join("night").join("a.png.1.json").exists());
+
+    let player = Fixtures::from_config(&fixture_config(FixtureMode::Replay, temp_dir.path()), Path::new("in")).unwrap();
+    assert_eq!(player.replay(Path::new("in/night/a.png")).unwrap(), "night");
+    assert_eq!(player.replay(Path::new("in/day/a.png")).unwrap(), "day");
+}
+
+#[tokio::test]
+async fn test_replay_reproduces_recorded_run() {
+    let temp_dir = tempdir().unwrap();
+    let input_dir = temp_dir.path().join("input");
+    fs::create_dir_all(&input_dir).unwrap();
+    for index in 0..2 {
+        fs::write(input_dir.join(format!("image_{}.png", index)), PNG_DATA).unwrap();
+    }
+    let fixture_dir = temp_dir.path().join("fixtures");
+
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/options"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({})))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/sdapi/v1/txt2img"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "images": ["iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAQAAAC1HAwCAAAAC0lEQVR42mP8/w8AAgMBApUAAAAASUVORK5CYII="],
+            "parameters": {},
+            "info": "{}"
+        })))
+        .mount(&server)
+        .await;
+
+    let mut config = Config::load("nonexistent_file.yml").unwrap();
+    config.sd_api_url = format!("{}/", server.uri());
+    config.input_dir = input_dir.to_string_lossy().to_string();
+    config.output_dir = temp_dir.path().join("recorded").to_string_lossy().to_string();
+    config.batch_size = 1;
+    config.batch_break_ms = 0;
+    config.assume_yes = true;
+    config.estimate = false;
+    config.fixtures = fixture_config(FixtureMode::Record, &fixture_dir);
+
+    let recorded = run_batch(&config, &Metrics::new(), &RunControl::default()).await.unwrap().unwrap();
+    assert_eq!(recorded.success_count, 2);
+    assert!(fixture_dir.join("image_0.png.1.json").exists());
+    assert!(fixture_dir.join("image_1.png.1.json").exists());
+
+    // Nothing listens here, so every response has to come from the fixtures
+    config.sd_api_url = "http://127.0.0.1:9/".to_string();
+    config.output_dir = temp_dir.path().join("replayed").to_string_lossy().to_string();
+    config.fixtures = fixture_config(FixtureMode::Replay, &fixture_dir);
+
+    let replayed = run_batch(&config, &Metrics::new(), &RunControl::default()).await.unwrap().unwrap();
+    assert_eq!(replayed.success_count, 2);
+    assert_eq!(replayed.generated_count, recorded.generated_count);
+    assert_eq!(
+        fs::read(temp_dir.path().join("replayed/image_0/image_0-1.png")).unwrap(),
+        fs::read(temp_dir.path().join("recorded/image_0/image_0-1.png")).unwrap()
+    );
+}
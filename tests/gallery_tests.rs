@@ -0,0 +1,129 @@
+//! Gallery tests for urasoe
+
+use std::fs;
+use tempfile::tempdir;
+
+use urasoe::gallery::{Filter, percent_decode, render, resolve_file, scan, serve};
+use urasoe::queue::{DEFAULT_QUEUE_FILE, JobQueue};
+
+/// Output directory with two generated inputs, a failed one and a job queue
+fn sample_output() -> tempfile::TempDir {
+    let dir = tempdir().unwrap();
+    let cat = dir.path().join("cat");
+    fs::create_dir_all(&cat).unwrap();
+    for index in [1, 2, 10] {
+        fs::write(cat.join(format!("cat-{}.png", index)), b"png").unwrap();
+    }
+    fs::write(cat.join("cat-metadata.json"), r#"{"prompt": "a <cat>"}"#).unwrap();
+    let dog = dir.path().join("dog");
+    fs::create_dir_all(&dog).unwrap();
+    fs::write(dog.join("dog-1.png"), b"png").unwrap();
+    fs::create_dir_all(dir.path().join("empty")).unwrap();
+
+    let failed = dir.path().join("_failed");
+    fs::create_dir_all(&failed).unwrap();
+    fs::write(failed.join("bird.png"), b"png").unwrap();
+    fs::write(failed.join("bird.png.error.log"), "CUDA out of memory").unwrap();
+
+    let mut queue = JobQueue::create(dir.path().join(DEFAULT_QUEUE_FILE)).unwrap();
+    queue.enqueue_all(&["cat.png", "dog.png", "bird.png", "fish.png"]).unwrap();
+    queue.mark_done("cat.png").unwrap();
+    queue.mark_done("dog.png").unwrap();
+    queue.mark_failed("bird.png").unwrap();
+    dir
+}
+
+#[test]
+fn test_scan_output_directory() {
+    let dir = sample_output();
+    let gallery = scan(dir.path()).unwrap();
+
+    let run = gallery.run.unwrap();
+    assert_eq!((run.done, run.failed, run.pending), (2, 1, 1));
+
+    let mut names: Vec<&str> = gallery.inputs.iter().map(|input| input.name.as_str()).collect();
+    names.sort();
+    assert_eq!(names, vec!["cat", "dog"]);
+    let cat = gallery.inputs.iter().find(|input| input.name == "cat").unwrap();
+    assert_eq!(cat.images, vec!["cat/cat-1.png", "cat/cat-2.png", "cat/cat-10.png"]);
+    assert_eq!(cat.metadata.as_ref().unwrap()["prompt"], "a <cat>");
+
+    assert_eq!(gallery.failures.len(), 1);
+    assert_eq!(gallery.failures[0].image, "_failed/bird.png");
+    assert_eq!(gallery.failures[0].log.as_deref(), Some("CUDA out of memory"));
+
+    // Failed inputs of recursive runs keep their input folders
+    let nested = dir.path().join("_failed").join("birds");
+    fs::create_dir_all(&nested).unwrap();
+    fs::write(nested.join("bird.png"), b"png").unwrap();
+    fs::write(nested.join("bird.png.error.log"), "timeout").unwrap();
+    let failures = scan(dir.path()).unwrap().failures;
+    assert_eq!(failures.len(), 2);
+    assert_eq!(failures[1].name, "birds/bird.png");
+    assert_eq!(failures[1].image, "_failed/birds/bird.png");
+    assert_eq!(failures[1].log.as_deref(), Some("timeout"));
+
+    assert!(scan(&dir.path().join("missing")).unwrap().inputs.is_empty());
+}
+
+#[test]
+fn test_filter_and_render() {
+    assert_eq!(percent_decode("big+cat%21%2"), "big cat!%2");
+    let filter = Filter::from_query("q=CA&show=generated");
+    assert_eq!(filter.query, "CA");
+    assert!(filter.generated_only && !filter.failed_only);
+    assert!(filter.matches("cat"));
+    assert!(!filter.matches("dog"));
+
+    let dir = sample_output();
+    let gallery = scan(dir.path()).unwrap();
+    let page = render(&gallery, &filter);
+    assert!(page.contains("/files/cat/cat-10.png"));
+    assert!(!page.contains("dog-1.png"));
+    assert!(!page.contains("bird.png"));
+    assert!(page.contains("a &lt;cat&gt;"));
+
+    let page = render(&gallery, &Filter::from_query("show=failed"));
+    assert!(page.contains("CUDA out of memory"));
+    assert!(!page.contains("cat-1.png"));
+    assert!(page.contains("2 done, 1 failed, 1 pending"));
+}
+
+#[test]
+fn test_resolve_file_stays_inside_output_directory() {
+    let dir = sample_output();
+    assert!(resolve_file(dir.path(), "cat/cat-1.png").is_some());
+    assert!(resolve_file(dir.path(), "cat/missing.png").is_none());
+    assert!(resolve_file(dir.path(), "../etc/passwd").is_none());
+    assert!(resolve_file(dir.path(), "/etc/passwd").is_none());
+}
+
+#[tokio::test]
+async fn test_gallery_server() {
+    let dir = sample_output();
+    let (address, server) = serve("127.0.0.1:0", dir.path()).await.unwrap();
+
+    let page = reqwest::get(format!("http://{}/?q=dog", address)).await.unwrap();
+    assert_eq!(page.status(), 200);
+    let body = page.text().await.unwrap();
+    assert!(body.contains("dog-1.png"));
+    assert!(!body.contains("cat-1.png"));
+
+    let image = reqwest::get(format!("http://{}/files/cat/cat-2.png", address)).await.unwrap();
+    assert_eq!(image.status(), 200);
+    assert_eq!(image.headers()["content-type"], "image/png");
+    assert_eq!(image.bytes().await.unwrap().as_ref(), b"png");
+
+    let json: serde_json::Value = reqwest::get(format!("http://{}/api/gallery", address))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(json["failures"][0]["name"], "bird.png");
+
+    let escaped = reqwest::get(format!("http://{}/files/..%2F..%2Fetc%2Fpasswd", address)).await.unwrap();
+    assert_eq!(escaped.status(), 404);
+
+    server.abort();
+}
@@ -0,0 +1,113 @@
+//! gRPC job control tests for urasoe, run with `cargo test --features grpc`
+#![cfg(feature = "grpc")]
+
+use bytes::Bytes;
+use http::{HeaderMap, Request};
+use std::net::SocketAddr;
+use tokio::net::TcpStream;
+
+use urasoe::daemon::{ActiveJob, JobState, next_job};
+use urasoe::grpc::{JobRequest, JobStatus, SubmitRequest, frame, serve, unframe};
+
+/// Call a method, returning the reply messages and the trailers
+async fn call(address: SocketAddr, method: &str, message: Vec<u8>) -> (Vec<Vec<u8>>, HeaderMap) {
+    let socket = TcpStream::connect(address).await.unwrap();
+    let (client, connection) = h2::client::handshake(socket).await.unwrap();
+    tokio::spawn(connection);
+    let mut client = client.ready().await.unwrap();
+
+    let request = Request::post(format!("http://{}/urasoe.v1.JobControl/{}", address, method))
+        .header("content-type", "application/grpc")
+        .header("te", "trailers")
+        .body(())
+        .unwrap();
+    let (response, mut send) = client.send_request(request, false).unwrap();
+    send.send_data(frame(&message), true).unwrap();
+
+    let response = response.await.unwrap();
+    let headers = response.headers().clone();
+    let mut body = response.into_body();
+    let mut data = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk: Bytes = chunk.unwrap();
+        let _ = body.flow_control().release_capacity(chunk.len());
+        data.extend_from_slice(&chunk);
+    }
+    let trailers = body.trailers().await.unwrap().unwrap_or(headers);
+
+    let mut messages = Vec::new();
+    let mut rest = &data[..];
+    while !rest.is_empty() {
+        let message = unframe(rest).unwrap();
+        messages.push(message.to_vec());
+        rest = &rest[5 + message.len()..];
+    }
+    (messages, trailers)
+}
+
+#[test]
+fn test_messages_round_trip() {
+    let request = SubmitRequest {
+        input_dir: "./incoming".to_string(),
+        output_dir: String::new(),
+        preset: "depth.yml".to_string(),
+        priority: -2,
+    };
+    assert_eq!(SubmitRequest::decode(&request.encode()).unwrap(), request);
+
+    let status = JobStatus {
+        id: "job-1".to_string(),
+        state: JobState::Running,
+        total: 300,
+        done: 12,
+        failed: 0,
+        generated: 48,
+        report: String::new(),
+    };
+    assert_eq!(JobStatus::decode(&status.encode()).unwrap(), status);
+    assert!(!status.is_final());
+    assert!(JobRequest::decode(&[0x0A, 0x05, b'a']).is_err());
+}
+
+#[tokio::test]
+async fn test_submit_status_cancel_and_stream() {
+    let spool = tempfile::tempdir().unwrap();
+    let (address, server) = serve("127.0.0.1:0", spool.path(), ActiveJob::new()).await.unwrap();
+
+    let submit = SubmitRequest {
+        input_dir: "./incoming".to_string(),
+        priority: 4,
+        ..Default::default()
+    };
+    let (messages, trailers) = call(address, "Submit", submit.encode()).await;
+    assert_eq!(trailers["grpc-status"], "0");
+    let submitted = JobStatus::decode(&messages[0]).unwrap();
+    assert_eq!(submitted.state, JobState::Pending);
+    let (_, spec) = next_job(spool.path()).unwrap().unwrap();
+    assert_eq!((spec.input_dir.as_str(), spec.priority), ("./incoming", 4));
+
+    let job = JobRequest { id: submitted.id.clone() };
+    let (messages, _) = call(address, "Status", job.encode()).await;
+    assert_eq!(JobStatus::decode(&messages[0]).unwrap().state, JobState::Pending);
+
+    let (messages, _) = call(address, "Cancel", job.encode()).await;
+    assert_eq!(JobStatus::decode(&messages[0]).unwrap().state, JobState::Cancelled);
+
+    // An archived job streams its final state once and ends
+    let (messages, trailers) = call(address, "StreamProgress", job.encode()).await;
+    assert_eq!(messages.len(), 1);
+    assert_eq!(JobStatus::decode(&messages[0]).unwrap().state, JobState::Cancelled);
+    assert_eq!(trailers["grpc-status"], "0");
+
+    let missing = JobRequest { id: "missing".to_string() };
+    let (messages, trailers) = call(address, "Status", missing.encode()).await;
+    assert!(messages.is_empty());
+    assert_eq!(trailers["grpc-status"], "5");
+
+    let (_, trailers) = call(address, "Submit", SubmitRequest::default().encode()).await;
+    assert_eq!(trailers["grpc-status"], "3");
+    let (_, trailers) = call(address, "Restart", job.encode()).await;
+    assert_eq!(trailers["grpc-status"], "12");
+
+    server.abort();
+}
@@ -0,0 +1,112 @@
+//! History module tests for urasoe
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use urasoe::api::{GeneratedImage, StableDiffusionResponse};
+use urasoe::config::{Config, DeadLetterMode};
+use urasoe::file_utils::FileManager;
+use urasoe::history::{HistoryQuery, row, search};
+use urasoe::queue::JobQueue;
+
+const PNG_BASE64: &str = "iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAQAAAC1HAwCAAAAC0lEQVR42mP8/w8AAgMBApUAAAAASUVORK5CYII=";
+
+/// Output directory with two generated inputs, one dead-lettered and one failed input
+fn recorded_runs(dir: &Path) -> Config {
+    let mut config = Config::load("nonexistent_config.yml").unwrap();
+    config.output_dir = dir.join("output").to_string_lossy().to_string();
+    let response = StableDiffusionResponse {
+        images: vec![GeneratedImage::from_base64(PNG_BASE64).unwrap(), GeneratedImage::from_base64(PNG_BASE64).unwrap()],
+        parameters: None,
+        info: None,
+        digest: None,
+    };
+    config.prompt = "karateka in the Dojo".to_string();
+    config.model = "control_v11p_sd15_canny".to_string();
+    FileManager::save_generated_images(&response, Path::new("input/kata.png"), &config).unwrap();
+    config.prompt = "beach at sunset".to_string();
+    config.model = "control_v11f1p_sd15_depth".to_string();
+    config.controlnet_module = "depth_midas".to_string();
+    FileManager::save_generated_images(&response, Path::new("input/beach.png"), &config).unwrap();
+
+    config.prompt = "dojo at night".to_string();
+    config.model = "control_v11p_sd15_canny".to_string();
+    config.controlnet_module = "canny".to_string();
+    config.dead_letter = DeadLetterMode::Copy;
+    let input = dir.join("night.png");
+    fs::write(&input, "png").unwrap();
+    FileManager::dead_letter(&input, "CUDA out of memory", 3, &config).unwrap();
+
+    let mut queue = JobQueue::create(config.queue_path()).unwrap();
+    queue.enqueue(&input).unwrap();
+    queue.mark_failed(&input).unwrap();
+    queue.enqueue("input/lost.png").unwrap();
+    queue.mark_failed("input/lost.png").unwrap();
+    config
+}
+
+fn sources(config: &Config, query: &HistoryQuery) -> Vec<String> {
+    let mut sources: Vec<String> = search(Path::new(&config.output_dir), &config.queue_path(), query)
+        .unwrap()
+        .into_iter()
+        .map(|entry| entry.source_image)
+        .collect();
+    sources.sort();
+    sources
+}
+
+#[test]
+fn test_history_lists_generations_and_failures() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let config = recorded_runs(temp_dir.path());
+    let night = temp_dir.path().join("night.png").to_string_lossy().to_string();
+
+    let entries = search(Path::new(&config.output_dir), &config.queue_path(), &HistoryQuery::default()).unwrap();
+    assert_eq!(entries.len(), 4);
+    let kata = entries.iter().find(|entry| entry.source_image == "input/kata.png").unwrap();
+    let output_dir = PathBuf::from(&config.output_dir);
+    assert_eq!(kata.images, [output_dir.join("kata").join("kata-1.png"), output_dir.join("kata").join("kata-2.png")]);
+    assert_eq!(kata.record, Some(output_dir.join("kata").join("kata-metadata.json")));
+    assert!(!kata.failed);
+    assert_eq!(row(kata)[1], "generated");
+
+    let failure = entries.iter().find(|entry| entry.source_image == night).unwrap();
+    assert!(failure.failed);
+    assert_eq!(failure.error.as_deref(), Some("CUDA out of memory"));
+    assert_eq!(failure.metadata.as_ref().unwrap().prompt, "dojo at night");
+    // Failures that were not dead-lettered come from the job queue
+    let lost = entries.iter().find(|entry| entry.source_image == "input/lost.png").unwrap();
+    assert!(lost.failed && lost.metadata.is_none());
+}
+
+#[test]
+fn test_history_query_narrows_the_listing() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let config = recorded_runs(temp_dir.path());
+    let night = temp_dir.path().join("night.png").to_string_lossy().to_string();
+
+    let query = HistoryQuery {
+        prompt: Some("dojo".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(sources(&config, &query), [night.clone(), "input/kata.png".to_string()]);
+
+    let query = HistoryQuery {
+        model: Some("DEPTH".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(sources(&config, &query), ["input/beach.png"]);
+
+    let query = HistoryQuery {
+        failed: true,
+        ..Default::default()
+    };
+    assert_eq!(sources(&config, &query), [night.clone(), "input/lost.png".to_string()]);
+
+    let query = HistoryQuery {
+        prompt: Some("dojo".to_string()),
+        model: Some("canny".to_string()),
+        failed: true,
+    };
+    assert_eq!(sources(&config, &query), [night]);
+}
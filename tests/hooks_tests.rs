@@ -0,0 +1,75 @@
+//! Hooks module tests for urasoe
+
+use std::fs;
+use std::path::Path;
+use urasoe::config::Config;
+use urasoe::hooks::{self, HookEvent, HooksConfig};
+use urasoe::processing::{ImageTiming, ProcessingStats};
+
+fn env_value<'a>(env: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    env.iter()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.as_str())
+}
+
+#[test]
+fn test_image_env_contains_paths_and_outcome() {
+    let mut config = Config::load("nonexistent_config.yml").unwrap();
+    config.output_dir = "out".to_string();
+
+    let mut stats = ProcessingStats::new();
+    stats.record_failure(Path::new("in/cat.png"), ImageTiming::default(), 2, "CUDA out of memory");
+    let env = hooks::finished_image_env(&config, Path::new("in/cat.png"), "http://gpu/", &stats.images[0]);
+
+    assert_eq!(env_value(&env, "URASOE_IMAGE"), Some("in/cat.png"));
+    assert_eq!(
+        env_value(&env, "URASOE_IMAGE_OUTPUT_DIR"),
+        Some(Path::new("out").join("cat").to_str().unwrap())
+    );
+    assert_eq!(env_value(&env, "URASOE_BACKEND"), Some("http://gpu/"));
+    assert_eq!(env_value(&env, "URASOE_STATUS"), Some("failed"));
+    assert_eq!(env_value(&env, "URASOE_ATTEMPTS"), Some("2"));
+    assert_eq!(env_value(&env, "URASOE_ERROR"), Some("CUDA out of memory"));
+    assert_eq!(env_value(&env, "URASOE_CHECKPOINT"), Some(config.checkpoint_model.as_str()));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_fire_runs_command_with_environment() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let marker = temp_dir.path().join("marker.txt");
+    let hooks_config = HooksConfig {
+        after_run: Some(format!("echo \"$URASOE_SUCCESS_COUNT\" > '{}'", marker.display())),
+        ..Default::default()
+    };
+
+    let config = Config::load("nonexistent_config.yml").unwrap();
+    let mut stats = ProcessingStats::new();
+    stats.record_success(Path::new("a.png"), 4, 1.0, ImageTiming::default(), 1);
+
+    hooks_config
+        .fire(HookEvent::AfterRun, &hooks::finished_run_env(&config, &stats))
+        .await
+        .unwrap();
+    assert_eq!(fs::read_to_string(&marker).unwrap().trim(), "1");
+
+    // Events without a command do nothing
+    hooks_config.fire(HookEvent::BeforeRun, &Vec::new()).await.unwrap();
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_fire_fails_on_non_zero_exit() {
+    let hooks_config = HooksConfig {
+        before_image: Some("echo nope >&2; exit 3".to_string()),
+        ..Default::default()
+    };
+
+    let error = hooks_config
+        .fire(HookEvent::BeforeImage, &Vec::new())
+        .await
+        .unwrap_err();
+    let message = format!("{:#}", error);
+    assert!(message.contains("before_image"));
+    assert!(message.contains("nope"));
+}
@@ -0,0 +1,66 @@
+//! Localization tests for urasoe
+
+use urasoe::i18n::{self, Lang, Msg, fill, tr, tr_args};
+
+/// Placeholders of a template, as the index of the value each one takes
+fn placeholders(template: &str) -> Vec<usize> {
+    let mut indices = Vec::new();
+    let mut next = 0;
+    for part in template.split('{').skip(1) {
+        let Some((placeholder, _)) = part.split_once('}') else {
+            continue;
+        };
+        if placeholder.is_empty() {
+            indices.push(next);
+            next += 1;
+        } else {
+            indices.push(placeholder.parse().unwrap());
+        }
+    }
+    indices.sort();
+    indices
+}
+
+#[test]
+fn test_lang_from_locale() {
+    assert_eq!(Lang::from_locale("fi_FI.UTF-8"), Some(Lang::Fi));
+    assert_eq!(Lang::from_locale("ja_JP.eucJP"), Some(Lang::Ja));
+    assert_eq!(Lang::from_locale("en-GB"), Some(Lang::En));
+    assert_eq!(Lang::from_locale("ja"), Some(Lang::Ja));
+    assert_eq!(Lang::from_locale("de_DE.UTF-8"), None);
+    assert_eq!(Lang::from_locale("C"), None);
+}
+
+#[test]
+fn test_fill_sequential_and_positional() {
+    assert_eq!(fill("Progress: {}/{} ({} failed)", &[&3, &10, &1]), "Progress: 3/10 (1 failed)");
+    assert_eq!(fill("{2}ms 待機後に再試行 {0}/{1}", &[&1, &3, &500]), "500ms 待機後に再試行 1/3");
+    // Placeholders without a value stay visible instead of panicking
+    assert_eq!(fill("{} and {}", &[&"one"]), "one and {}");
+    assert_eq!(fill("unclosed {", &[&1]), "unclosed {");
+}
+
+#[test]
+fn test_translations_use_the_same_values() {
+    for msg in Msg::ALL {
+        let english = placeholders(msg.template(Lang::En));
+        for lang in [Lang::Fi, Lang::Ja] {
+            let translated = msg.template(lang);
+            assert!(!translated.is_empty(), "{:?} has no {:?} translation", msg, lang);
+            assert_eq!(placeholders(translated), english, "{:?} in {:?}", msg, lang);
+        }
+    }
+}
+
+#[test]
+fn test_selected_language() {
+    i18n::set_lang(Lang::Fi);
+    assert_eq!(i18n::lang(), Lang::Fi);
+    assert_eq!(tr_args(Msg::FoundImages, &[&12]), "Löytyi 12 käsiteltävää kuvaa");
+
+    i18n::set_lang(Lang::Ja);
+    assert_eq!(tr(Msg::GenerationComplete), "✓ 画像の生成が完了しました!");
+
+    i18n::set_lang(Lang::En);
+    assert_eq!(tr_args(Msg::Processing, &[&"input/a.png"]), "Processing: input/a.png");
+}
@@ -1,7 +1,7 @@
 //! Image module tests for urasoe
 
 use std::io::Write;
-use urasoe::image::{ImageProcessor, image_to_base64};
+use urasoe::image::{ImageProcessor, InputFilter, image_to_base64};
 
 #[test]
 fn test_get_image_list_empty_dir() {
@@ -19,9 +19,62 @@ fn test_get_image_list_with_images() {
     assert_eq!(images.len(), 1);
 }
 
+#[test]
+fn test_find_images_recursively_with_patterns() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    for file in ["a.png", "notes.txt", "2024/b.PNG", "2024/c.jpg", "2024/thumbs/d.png", "thumbs/e.png"] {
+        let path = temp_dir.path().join(file);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, b"").unwrap();
+    }
+    let relative = |filter: &InputFilter, recursive: bool| -> Vec<String> {
+        ImageProcessor::find_images(temp_dir.path(), recursive, filter)
+            .unwrap()
+            .iter()
+            .map(|path| path.strip_prefix(temp_dir.path()).unwrap().to_string_lossy().replace('\\', "/"))
+            .collect()
+    };
+
+    let all = InputFilter::default();
+    assert_eq!(relative(&all, false), ["a.png"]);
+    assert_eq!(relative(&all, true), ["2024/b.PNG", "2024/c.jpg", "2024/thumbs/d.png", "a.png", "thumbs/e.png"]);
+
+    let filter = InputFilter::new(&["**/*.png".to_string()], &["thumbs/**".to_string()]).unwrap();
+    assert_eq!(relative(&filter, true), ["2024/thumbs/d.png", "a.png"]);
+
+    // Patterns without a folder match the file name at any depth
+    let filter = InputFilter::new(&[], &["[de].png".to_string(), "*.jpg".to_string()]).unwrap();
+    assert_eq!(relative(&filter, true), ["2024/b.PNG", "a.png"]);
+
+    assert!(InputFilter::new(&["[a".to_string()], &[]).is_err());
+}
+
 #[test]
 fn test_image_to_base64_invalid_path() {
     let path = std::path::Path::new("not_a_real_image.png");
     let result = image_to_base64(path);
     assert!(result.is_err());
 }
+
+#[test]
+fn test_letterbox_pads_to_the_aspect_ratio() {
+    let mut encoded = Vec::new();
+    image::RgbImage::from_pixel(4, 2, image::Rgb([255, 255, 255]))
+        .write_to(&mut std::io::Cursor::new(&mut encoded), image::ImageFormat::Png)
+        .unwrap();
+
+    // Landscape control image for a portrait generation grows in height
+    let padded = image::load_from_memory(&ImageProcessor::letterbox(&encoded, 512, 768).unwrap()).unwrap().to_rgba8();
+    assert_eq!(padded.dimensions(), (4, 6));
+    assert_eq!(padded.get_pixel(0, 0).0, [0, 0, 0, 255]);
+    assert_eq!(padded.get_pixel(0, 2).0, [255, 255, 255, 255]);
+    assert_eq!(padded.get_pixel(3, 3).0, [255, 255, 255, 255]);
+    assert_eq!(padded.get_pixel(3, 5).0, [0, 0, 0, 255]);
+
+    // Widening keeps the image centered, and matching ratios keep the size
+    let padded = image::load_from_memory(&ImageProcessor::letterbox(&encoded, 4, 1).unwrap()).unwrap();
+    assert_eq!((padded.width(), padded.height()), (8, 2));
+    let padded = image::load_from_memory(&ImageProcessor::letterbox(&encoded, 1024, 512).unwrap()).unwrap();
+    assert_eq!((padded.width(), padded.height()), (4, 2));
+    assert!(ImageProcessor::letterbox(b"not an image", 512, 512).is_err());
+}
@@ -0,0 +1,67 @@
+//! Input triage module tests for urasoe
+
+use image::{Rgb, RgbImage, Rgba, RgbaImage};
+use std::fs;
+
+use urasoe::config::{Config, ResizeMode};
+use urasoe::inspect::{Orientation, inspect, inspect_dir, row};
+
+#[test]
+fn test_inputs_are_reported_with_their_output_size() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let input = temp_dir.path().join("kata.png");
+    RgbImage::from_pixel(800, 600, Rgb([10, 20, 30])).save(&input).unwrap();
+    let mut config = Config::load("nonexistent_config.yml").unwrap();
+    config.width = 768;
+    config.height = 512;
+
+    let report = inspect(&input, &config);
+    assert_eq!(report.size, Some((800, 600)));
+    assert_eq!(report.format.as_deref(), Some("png"));
+    assert_eq!(report.orientation, Some(Orientation::Landscape));
+    assert_eq!(report.output_size, (768, 512));
+    assert_eq!(report.payload_bytes, Some(fs::read(&input).unwrap().len().div_ceil(3) * 4));
+    assert!(!report.has_problems(), "{:?}", report.problems);
+    assert_eq!(row(&report)[..4], ["kata.png", "800x600", "png", "landscape"]);
+
+    // A portrait input loses much of itself to the crop of a landscape size
+    let portrait = temp_dir.path().join("kumite.png");
+    RgbImage::from_pixel(400, 800, Rgb([10, 20, 30])).save(&portrait).unwrap();
+    let report = inspect(&portrait, &config);
+    assert_eq!(report.problems, ["crops 67% to fit 768x512"]);
+    config.resize_mode = ResizeMode::JustResize;
+    assert_eq!(inspect(&portrait, &config).problems, ["stretches 67% to fit 768x512"]);
+    config.letterbox = true;
+    assert!(!inspect(&portrait, &config).has_problems());
+}
+
+#[test]
+fn test_problematic_inputs_are_flagged() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let config = Config::load("nonexistent_config.yml").unwrap();
+    fs::write(temp_dir.path().join("broken.png"), "not an image").unwrap();
+    RgbImage::from_pixel(config.width, config.height, Rgb([0, 0, 0]))
+        .save_with_format(temp_dir.path().join("kata.png"), image::ImageFormat::Jpeg)
+        .unwrap();
+    RgbImage::from_pixel(config.width, config.height, Rgb([0, 0, 0]))
+        .save(temp_dir.path().join("kata.webp"))
+        .unwrap();
+    let mut transparent = RgbaImage::from_pixel(100, 100, Rgba([0, 0, 0, 255]));
+    transparent.put_pixel(0, 0, Rgba([0, 0, 0, 0]));
+    transparent.save(temp_dir.path().join("kumite.png")).unwrap();
+
+    let reports = inspect_dir(temp_dir.path(), &config).unwrap();
+    let problems: Vec<(String, Vec<String>)> = reports
+        .iter()
+        .map(|report| (report.path.file_name().unwrap().to_string_lossy().to_string(), report.problems.clone()))
+        .collect();
+    assert_eq!(problems[0].0, "broken.png");
+    assert!(problems[0].1[0].starts_with("cannot be decoded"));
+    assert_eq!(
+        problems[1].1,
+        ["the extension does not match its jpeg contents", "shares its output folder with kata.webp"]
+    );
+    assert_eq!(problems[2].1, ["shares its output folder with kata.png"]);
+    assert_eq!(problems[3].1[0], "has transparent areas");
+    assert!(problems[3].1[1].starts_with("is scaled up 7.7x"));
+}
@@ -0,0 +1,93 @@
+//! Server list cache module tests for urasoe
+
+use serde_json::json;
+use tempfile::tempdir;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use urasoe::api::StableDiffusionClient;
+use urasoe::config::Config;
+use urasoe::list_cache::{DEFAULT_LIST_CACHE_FILE, ListCache};
+
+/// Server listing the default options the given number of times, failing afterwards
+async fn listing_server(times: u64) -> MockServer {
+    let server = MockServer::start().await;
+    let responses = [
+        ("/sdapi/v1/sd-models", json!([{"title": "realisticVisionV51_v51VAE"}])),
+        ("/controlnet/model_list", json!({"model_list": [{"model_name": "control_canny_sd15.pth"}]})),
+        ("/controlnet/module_list", json!({"module_list": ["canny"]})),
+        ("/sdapi/v1/samplers", json!([{"name": "DPM++ 2M"}])),
+    ];
+    for (endpoint, body) in responses {
+        Mock::given(method("GET"))
+            .and(path(endpoint))
+            .respond_with(ResponseTemplate::new(200).set_body_json(body))
+            .up_to_n_times(times)
+            .expect(times)
+            .mount(&server)
+            .await;
+    }
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(503))
+        .mount(&server)
+        .await;
+    server
+}
+
+/// Configuration validated against the given server, caching into a temporary directory
+fn config_for(api_url: &str, output_dir: &std::path::Path) -> Config {
+    let mut config = Config::load("nonexistent_config.yml").unwrap();
+    config.sd_api_url = api_url.to_string();
+    config.output_dir = output_dir.to_string_lossy().to_string();
+    config.validate_options = true;
+    config
+}
+
+#[tokio::test]
+async fn test_fresh_lists_are_not_fetched_again() {
+    let server = listing_server(1).await;
+    let temp_dir = tempdir().unwrap();
+    let config = config_for(&format!("{}/", server.uri()), temp_dir.path());
+    let client = StableDiffusionClient::new(&config.sd_api_url);
+
+    assert!(client.validate_config_options(&config).await.unwrap().is_empty());
+    assert!(temp_dir.path().join(DEFAULT_LIST_CACHE_FILE).is_file());
+    let cache = ListCache::from_config(&config);
+    assert_eq!(cache.fresh("samplers").unwrap().names, ["DPM++ 2M"]);
+
+    // The second validation is answered from the cache, the server listing only once
+    let mut typo = config.clone();
+    typo.sampler_name = "DPM++ 2X".to_string();
+    let issues = client.validate_config_options(&typo).await.unwrap();
+    assert_eq!(issues, ["Sampler 'DPM++ 2X' not found. Available samplers: DPM++ 2M"]);
+    server.verify().await;
+
+    // Without a time to live the server is asked every time
+    let mut uncached = config.clone();
+    uncached.list_cache.ttl_secs = 0;
+    assert!(ListCache::from_config(&uncached).fresh("samplers").is_none());
+}
+
+#[tokio::test]
+async fn test_stale_lists_are_used_while_offline() {
+    let server = listing_server(1).await;
+    let api_url = format!("{}/", server.uri());
+    let temp_dir = tempdir().unwrap();
+    let mut config = config_for(&api_url, temp_dir.path());
+    config.list_cache.ttl_secs = 0;
+    let client = StableDiffusionClient::new(&api_url);
+
+    assert!(client.validate_config_options(&config).await.unwrap().is_empty());
+
+    // The server fails from now on, the lists fetched before still catch an unknown checkpoint
+    config.checkpoint_model = "missing.safetensors".to_string();
+    let issues = client.validate_config_options(&config).await.unwrap();
+    assert_eq!(
+        issues,
+        ["Checkpoint model 'missing.safetensors' not found. Available models: realisticVisionV51_v51VAE"]
+    );
+
+    // With the cache off, nothing can be validated while offline
+    config.list_cache.enabled = false;
+    assert!(client.validate_config_options(&config).await.unwrap().is_empty());
+}
@@ -0,0 +1,63 @@
+//! Logging module tests for urasoe
+
+use colored::Colorize;
+use urasoe::logging::{self, ColorMode, LogFormat, LogLevel};
+
+#[test]
+fn test_log_level_ordering_and_parsing() {
+    assert!(LogLevel::Error < LogLevel::Warn);
+    assert!(LogLevel::Debug > LogLevel::Info);
+    assert_eq!(LogLevel::default(), LogLevel::Info);
+
+    let level: LogLevel = serde_yaml::from_str("debug").unwrap();
+    assert_eq!(level, LogLevel::Debug);
+    assert_eq!(LogLevel::Warn.as_level(), tracing::Level::WARN);
+}
+
+#[test]
+fn test_console_subscriber_levels() {
+    logging::init(LogLevel::Warn);
+    assert_eq!(logging::current_level(), LogLevel::Warn);
+    assert!(!tracing::enabled!(tracing::Level::INFO));
+    assert!(tracing::enabled!(tracing::Level::ERROR));
+
+    logging::set_level(LogLevel::Debug);
+    assert!(tracing::enabled!(tracing::Level::DEBUG));
+
+    // Events inside spans must render without panicking
+    let span = tracing::info_span!("image", path = "a.png");
+    span.in_scope(|| tracing::debug!(attempt = 1, "debug message"));
+    drop(span);
+    logging::set_level(LogLevel::Info);
+}
+
+#[test]
+fn test_log_format_selection() {
+    let format: LogFormat = serde_yaml::from_str("json").unwrap();
+    assert_eq!(format, LogFormat::Json);
+    assert_eq!(LogFormat::default(), LogFormat::Text);
+
+    logging::set_format(LogFormat::Json);
+    assert_eq!(logging::current_format(), LogFormat::Json);
+    tracing::warn!(event = "attempt_failed", attempt = 2, retryable = true, "json message");
+    logging::set_format(LogFormat::Text);
+    assert_eq!(logging::current_format(), LogFormat::Text);
+}
+
+#[test]
+fn test_color_mode_selection() {
+    let mode: ColorMode = serde_yaml::from_str("never").unwrap();
+    assert_eq!(mode, ColorMode::Never);
+    assert_eq!(ColorMode::default(), ColorMode::Auto);
+
+    logging::set_color(ColorMode::Never);
+    assert!(logging::is_plain());
+    assert_eq!("plain".red().to_string(), "plain");
+
+    logging::set_color(ColorMode::Always);
+    assert!(!logging::is_plain());
+
+    // Test output is captured, so it is not a terminal
+    logging::set_color(ColorMode::Auto);
+    assert!(logging::is_plain());
+}
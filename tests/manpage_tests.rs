@@ -0,0 +1,49 @@
+//! Manual page and help tests for urasoe
+
+use clap::CommandFactory;
+use urasoe::config::{Args, CONFIG_KEYS, config_key};
+use urasoe::manpage::{escape, render};
+
+#[test]
+fn test_config_keys_name_existing_options() {
+    let command = Args::command();
+    for (id, _) in CONFIG_KEYS {
+        assert!(
+            command.get_arguments().any(|arg| arg.get_id() == *id),
+            "no option with id {}",
+            id
+        );
+    }
+    assert_eq!(config_key("sampler"), Some("sampler_name"));
+    assert_eq!(config_key("yes"), None);
+}
+
+#[test]
+fn test_help_shows_config_keys() {
+    let mut command = Args::command_with_config_keys();
+    let help = command.render_long_help().to_string();
+    assert!(help.contains("[config: sampler_name]"));
+    assert!(help.contains("[config: notifications.slack_webhook]"));
+}
+
+#[test]
+fn test_man_page_sections() {
+    let page = render();
+    assert!(page.starts_with(".TH URASOE 1 "));
+    for section in [".SH NAME", ".SH SYNOPSIS", ".SH COMMANDS", ".SH OPTIONS", ".SH FILES"] {
+        assert!(page.contains(section), "missing {}", section);
+    }
+    assert!(page.contains(".TP\n\\fBdoctor\\fR\n"));
+    assert!(page.contains("\\fB\\-y\\fR, \\fB\\-\\-yes\\fR\n"));
+    assert!(page.contains("\\fB\\-\\-retry\\-delay\\fR \\fIRETRY_DELAY\\fR\n"));
+    assert!(page.contains("[config: retry_delay_ms]"));
+    assert!(page.contains("[possible values: error, warn, info, debug, trace]"));
+    assert!(!page.contains(".TP\n\\fB\\-h\\fR"));
+}
+
+#[test]
+fn test_roff_escape() {
+    assert_eq!(escape("0.0-1.0"), "0.0\\-1.0");
+    assert_eq!(escape(".hidden"), "\\&.hidden");
+    assert_eq!(escape("a\\b"), "a\\eb");
+}
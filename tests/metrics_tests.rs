@@ -0,0 +1,55 @@
+//! Metrics module tests for urasoe
+
+use std::path::Path;
+use std::time::Duration;
+use urasoe::metrics::{Metrics, serve};
+use urasoe::processing::{ImageTiming, ProcessingStats};
+
+/// Timing with the given generation duration in seconds
+fn generation_secs(seconds: u64) -> ImageTiming {
+    ImageTiming {
+        generation: Duration::from_secs(seconds),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_metrics_render_counters_and_histogram() {
+    let mut stats = ProcessingStats::new();
+    stats.record_success(Path::new("a.png"), 4, 1.0, generation_secs(3), 2);
+    stats.record_failure(Path::new("b.png"), generation_secs(700), 3, "CUDA out of memory");
+
+    let metrics = Metrics::new();
+    for result in &stats.images {
+        metrics.observe(result);
+    }
+    let output = metrics.render();
+
+    assert!(output.contains("urasoe_images_processed_total 1\n"));
+    assert!(output.contains("urasoe_images_generated_total 4\n"));
+    assert!(output.contains("urasoe_retries_total 3\n"));
+    assert!(output.contains("urasoe_images_failed_total{reason=\"cuda_oom\"} 1\n"));
+    assert!(output.contains("# TYPE urasoe_generation_duration_seconds histogram"));
+    assert!(output.contains("urasoe_generation_duration_seconds_bucket{le=\"2.5\"} 0\n"));
+    assert!(output.contains("urasoe_generation_duration_seconds_bucket{le=\"5\"} 1\n"));
+    assert!(output.contains("urasoe_generation_duration_seconds_bucket{le=\"600\"} 1\n"));
+    assert!(output.contains("urasoe_generation_duration_seconds_bucket{le=\"+Inf\"} 2\n"));
+    assert!(output.contains("urasoe_generation_duration_seconds_sum 703\n"));
+    assert!(output.contains("urasoe_generation_duration_seconds_count 2\n"));
+}
+
+#[tokio::test]
+async fn test_metrics_endpoint_serves_text_format() {
+    let metrics = Metrics::new();
+    let (address, server) = serve("127.0.0.1:0", metrics).await.unwrap();
+
+    let response = reqwest::get(format!("http://{}/metrics", address)).await.unwrap();
+    assert_eq!(response.status(), 200);
+    let body = response.text().await.unwrap();
+    assert!(body.contains("urasoe_images_processed_total 0"));
+
+    let missing = reqwest::get(format!("http://{}/other", address)).await.unwrap();
+    assert_eq!(missing.status(), 404);
+
+    server.abort();
+}
@@ -0,0 +1,135 @@
+//! MQTT module tests for urasoe
+
+use std::path::Path;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+
+use urasoe::daemon::next_job;
+use urasoe::mqtt::{Message, MqttClient, MqttConfig, packet, parse_publish, put_string, read_packet, serve_job_intake};
+use urasoe::processing::{ImageTiming, ProcessingStats};
+
+/// Accept one client on a fake broker and answer its CONNECT
+async fn accept_client(listener: &TcpListener) -> (TcpStream, Vec<u8>) {
+    let (mut stream, _) = listener.accept().await.unwrap();
+    let (first_byte, connect) = read_packet(&mut stream).await.unwrap();
+    assert_eq!(first_byte, 0x10);
+    stream.write_all(&[0x20, 2, 0, 0]).await.unwrap();
+    (stream, connect)
+}
+
+/// Read packets until a PUBLISH arrives
+async fn next_publish(stream: &mut TcpStream) -> Message {
+    loop {
+        let (first_byte, body) = read_packet(stream).await.unwrap();
+        if first_byte & 0xF0 == 0x30 {
+            return parse_publish(first_byte, &body).unwrap();
+        }
+    }
+}
+
+fn broker_config(address: std::net::SocketAddr) -> MqttConfig {
+    MqttConfig {
+        broker: Some(address.to_string()),
+        username: Some("user".to_string()),
+        password: Some("secret".to_string()),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_packet_encoding() {
+    let mut body = Vec::new();
+    put_string(&mut body, "urasoe/run");
+    body.extend_from_slice(b"{}");
+    let publish = packet(0x30, &body);
+    assert_eq!(&publish[..4], &[0x30, 14, 0, 10]);
+
+    let long = packet(0x30, &[0; 200]);
+    assert_eq!(&long[..3], &[0x30, 0xC8, 0x01]);
+
+    let message = parse_publish(0x30, &body).unwrap();
+    assert_eq!(message.topic, "urasoe/run");
+    assert_eq!(message.payload, b"{}");
+    assert_eq!(MqttConfig::default().topic("image"), "urasoe/image");
+}
+
+#[tokio::test]
+async fn test_publish_image_and_run_summary() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let config = broker_config(listener.local_addr().unwrap());
+    let broker = tokio::spawn(async move {
+        let (mut stream, connect) = accept_client(&listener).await;
+        let image = next_publish(&mut stream).await;
+        let run = next_publish(&mut stream).await;
+        (connect, image, run)
+    });
+
+    let (client, _) = MqttClient::connect(&config, "urasoe").await.unwrap();
+    let mut stats = ProcessingStats::new();
+    stats.record_success(Path::new("cat.png"), 4, 1.0, ImageTiming::default(), 1);
+    client.publish_image(&stats.images[0]).await;
+    client.publish_run_summary(&stats, 1).await;
+
+    let (connect, image, run) = broker.await.unwrap();
+    // Clean session with user name and password
+    assert_eq!(connect[7], 0xC2);
+    assert!(String::from_utf8_lossy(&connect).contains("secret"));
+    assert_eq!(image.topic, "urasoe/image");
+    let image: serde_json::Value = serde_json::from_slice(&image.payload).unwrap();
+    assert_eq!(image["path"], "cat.png");
+    assert_eq!(image["generated"], 4);
+    assert_eq!(run.topic, "urasoe/run");
+    let run: serde_json::Value = serde_json::from_slice(&run.payload).unwrap();
+    assert_eq!(run["total_images"], 1);
+    client.disconnect().await;
+}
+
+#[tokio::test]
+async fn test_refused_connection() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let config = broker_config(listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        read_packet(&mut stream).await.unwrap();
+        stream.write_all(&[0x20, 2, 0, 4]).await.unwrap();
+    });
+    let error = MqttClient::connect(&config, "urasoe").await.err().unwrap();
+    assert!(format!("{:#}", error).contains("bad user name or password"));
+}
+
+#[tokio::test]
+async fn test_job_intake_submits_to_spool() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let config = broker_config(listener.local_addr().unwrap());
+    let spool = tempfile::tempdir().unwrap();
+
+    let broker = tokio::spawn(async move {
+        let (mut stream, connect) = accept_client(&listener).await;
+        assert!(String::from_utf8_lossy(&connect).contains("urasoe-intake"));
+        let (first_byte, subscribe) = read_packet(&mut stream).await.unwrap();
+        assert_eq!(first_byte, 0x82);
+        assert!(String::from_utf8_lossy(&subscribe).contains("urasoe/jobs"));
+        stream.write_all(&[0x90, 3, 0, 1, 0]).await.unwrap();
+
+        for payload in ["input_dir: ./incoming\npriority: 2\n", "not: [a job"] {
+            let mut body = Vec::new();
+            put_string(&mut body, "urasoe/jobs");
+            body.extend_from_slice(payload.as_bytes());
+            stream.write_all(&packet(0x30, &body)).await.unwrap();
+        }
+        (next_publish(&mut stream).await, next_publish(&mut stream).await)
+    });
+
+    let intake = serve_job_intake(&config, spool.path()).await.unwrap();
+    let (submitted, rejected) = tokio::time::timeout(Duration::from_secs(5), broker)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(submitted.topic, "urasoe/jobs/submitted");
+    assert_eq!(rejected.topic, "urasoe/jobs/rejected");
+
+    let (_, spec) = next_job(spool.path()).unwrap().unwrap();
+    assert_eq!((spec.input_dir.as_str(), spec.priority), ("./incoming", 2));
+    intake.abort();
+}
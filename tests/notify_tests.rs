@@ -0,0 +1,68 @@
+//! Notification module tests for urasoe
+
+use std::path::Path;
+use urasoe::notify::{NotificationConfig, format_summary, send_run_summary};
+use urasoe::processing::{ImageTiming, ProcessingStats};
+use wiremock::matchers::{body_string_contains, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+/// Build statistics with one success and two failures
+fn sample_stats() -> ProcessingStats {
+    let mut stats = ProcessingStats::new();
+    stats.record_success(Path::new("a.png"), 4, 1.0, ImageTiming::default(), 1);
+    stats.record_failure(Path::new("b.png"), ImageTiming::default(), 3, "CUDA out of memory");
+    stats.record_failure(Path::new("c.png"), ImageTiming::default(), 3, "CUDA error");
+    stats.elapsed_ms = 90_000;
+    stats
+}
+
+#[test]
+fn test_format_summary() {
+    let summary = format_summary(&sample_stats(), 3, "out/stats.json");
+    assert!(summary.contains("Processed successfully: 1/3 images, generated 4 new images, 2 failed"));
+    assert!(summary.contains("Duration: 1.5 min"));
+    assert!(summary.contains("Failure reasons: CUDA/GPU out of memory (2)"));
+    assert!(summary.ends_with("Report: out/stats.json"));
+}
+
+#[test]
+fn test_notification_config_enabled() {
+    assert!(!NotificationConfig::default().is_enabled());
+    let config = NotificationConfig {
+        discord_webhook: Some("http://localhost/hook".to_string()),
+        ..Default::default()
+    };
+    assert!(config.is_enabled());
+
+    let desktop_only = NotificationConfig {
+        desktop: true,
+        ..Default::default()
+    };
+    assert!(desktop_only.is_enabled());
+}
+
+#[tokio::test]
+async fn test_send_run_summary_posts_to_webhooks() {
+    let mock_server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/slack"))
+        .and(body_string_contains("\"text\":\"urasoe run finished"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/discord"))
+        .and(body_string_contains("\"content\":\"urasoe run finished"))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let config = NotificationConfig {
+        slack_webhook: Some(format!("{}/slack", mock_server.uri())),
+        discord_webhook: Some(format!("{}/discord", mock_server.uri())),
+        ..Default::default()
+    };
+    send_run_summary(&config, &sample_stats(), 3, "out").await;
+}
@@ -0,0 +1,125 @@
+//! Pipeline tests for urasoe
+
+use futures::StreamExt;
+use std::fs;
+use tempfile::tempdir;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use urasoe::config::Config;
+use urasoe::pipeline::{Pipeline, RunEvent, RunEvents, event_stream};
+
+const PNG_BASE64: &str = "iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAQAAAC1HAwCAAAAC0lEQVR42mP8/w8AAgMBApUAAAAASUVORK5CYII=";
+
+/// Backend failing the first generation request and answering the rest
+async fn flaky_backend() -> MockServer {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/options"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({})))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/sdapi/v1/txt2img"))
+        .respond_with(ResponseTemplate::new(500).set_body_string("busy"))
+        .up_to_n_times(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/sdapi/v1/txt2img"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "images": [PNG_BASE64, PNG_BASE64],
+            "parameters": {},
+            "info": "{}"
+        })))
+        .mount(&server)
+        .await;
+    server
+}
+
+#[tokio::test]
+async fn test_pipeline_reports_events_in_order() {
+    let temp_dir = tempdir().unwrap();
+    let input_dir = temp_dir.path().join("input");
+    fs::create_dir_all(&input_dir).unwrap();
+    fs::write(input_dir.join("kata.png"), base64_png()).unwrap();
+    let server = flaky_backend().await;
+
+    let mut config = Config::load("nonexistent_config.yml").unwrap();
+    config.sd_api_url = format!("{}/", server.uri());
+    config.input_dir = input_dir.to_string_lossy().to_string();
+    config.output_dir = temp_dir.path().join("output").to_string_lossy().to_string();
+    config.batch_break_ms = 0;
+    config.retry_delay_ms = 0;
+    config.retry_on = vec!["busy".to_string()];
+    config.assume_yes = true;
+
+    let (events, handle) = Pipeline::new(config).start();
+    let events: Vec<RunEvent> = event_stream(events).collect().await;
+    let stats = handle.await.unwrap().unwrap().unwrap();
+    assert_eq!(stats.success_count, 1);
+
+    let names: Vec<&str> = events
+        .iter()
+        .map(|event| match event {
+            RunEvent::ImageStarted { .. } => "started",
+            RunEvent::AttemptFailed { .. } => "attempt_failed",
+            RunEvent::ImageSaved { .. } => "saved",
+            RunEvent::ImageFinished(_) => "finished",
+            RunEvent::RunFinished(_) => "run_finished",
+        })
+        .collect();
+    assert_eq!(names, ["started", "attempt_failed", "saved", "saved", "finished", "run_finished"]);
+
+    match &events[1] {
+        RunEvent::AttemptFailed { input, attempt, will_retry, error } => {
+            assert!(input.ends_with("kata.png"));
+            assert_eq!(*attempt, 1);
+            assert!(*will_retry);
+            assert!(error.contains("busy"), "{}", error);
+        }
+        other => panic!("unexpected event {:?}", other),
+    }
+    match &events[2] {
+        RunEvent::ImageSaved { output, .. } => assert!(output.is_file()),
+        other => panic!("unexpected event {:?}", other),
+    }
+    match &events[5] {
+        RunEvent::RunFinished(Some(stats)) => assert_eq!(stats.generated_count, 2),
+        other => panic!("unexpected event {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_pipeline_without_inputs_finishes_without_stats() {
+    let temp_dir = tempdir().unwrap();
+    let mut config = Config::load("nonexistent_config.yml").unwrap();
+    config.input_dir = temp_dir.path().to_string_lossy().to_string();
+    config.output_dir = temp_dir.path().join("output").to_string_lossy().to_string();
+
+    let (mut events, handle) = Pipeline::new(config).start();
+    assert!(matches!(events.recv().await, Some(RunEvent::RunFinished(None))));
+    assert!(events.recv().await.is_none());
+    assert!(handle.await.unwrap().unwrap().is_none());
+}
+
+#[test]
+fn test_run_events_forget_closed_subscribers() {
+    let events = RunEvents::default();
+    assert!(!events.has_subscribers());
+    let mut listening = events.subscribe();
+    drop(events.subscribe());
+    events.emit(RunEvent::RunFinished(None));
+    assert!(matches!(listening.try_recv(), Ok(RunEvent::RunFinished(None))));
+    assert!(events.has_subscribers());
+
+    events.close();
+    assert!(!events.has_subscribers());
+    assert!(listening.try_recv().is_err());
+}
+
+/// Bytes of a 1x1 PNG
+fn base64_png() -> Vec<u8> {
+    use base64::Engine;
+    base64::prelude::BASE64_STANDARD.decode(PNG_BASE64).unwrap()
+}
@@ -0,0 +1,103 @@
+//! Plugins module tests for urasoe
+#![cfg(feature = "scripting")]
+
+use serde_json::json;
+use std::fs;
+use std::path::{Path, PathBuf};
+use urasoe::file_utils::SavedImages;
+use urasoe::plugins::{PluginStage, Plugins};
+
+/// Write plugin scripts to a directory and load them in order
+fn load(dir: &Path, scripts: &[&str]) -> Plugins {
+    let paths: Vec<String> = scripts
+        .iter()
+        .enumerate()
+        .map(|(index, script)| {
+            let path = dir.join(format!("plugin-{}.rhai", index));
+            fs::write(&path, script).unwrap();
+            path.to_string_lossy().to_string()
+        })
+        .collect();
+    Plugins::load(&paths).unwrap()
+}
+
+#[test]
+fn test_filter_inputs_skips_rejected_images() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let plugins = load(
+        temp_dir.path(),
+        &[r#"fn filter_input(image) { !image.ends_with("b.png") }"#, "fn unrelated() {}"],
+    );
+    assert!(plugins.handles(PluginStage::InputFilter));
+    assert!(!plugins.handles(PluginStage::PayloadMutator));
+
+    let kept = plugins
+        .filter_inputs(vec![PathBuf::from("a.png"), PathBuf::from("b.png"), PathBuf::from("c.png")])
+        .unwrap();
+    assert_eq!(kept, vec![PathBuf::from("a.png"), PathBuf::from("c.png")]);
+}
+
+#[test]
+fn test_mutate_payload_chains_scripts() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let plugins = load(
+        temp_dir.path(),
+        &[
+            "fn mutate_payload(image, payload) { payload.steps = 50; payload }",
+            "fn mutate_payload(image, payload) { }",
+            r#"fn mutate_payload(image, payload) { payload.prompt += ", " + image; payload }"#,
+        ],
+    );
+
+    let payload = plugins
+        .mutate_payload(Path::new("kata.png"), json!({"steps": 30, "prompt": "dojo", "cfg": 7.5}))
+        .unwrap();
+    assert_eq!(payload, json!({"steps": 50, "prompt": "dojo, kata.png", "cfg": 7.5}));
+}
+
+#[test]
+fn test_post_process_transforms_outputs() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let output = temp_dir.path().join("kata-1.png");
+    image::RgbImage::new(8, 8).save(&output).unwrap();
+    let plugins = load(
+        temp_dir.path(),
+        &[r#"
+            fn post_process(image, outputs) {
+                let moved = [];
+                for output in outputs {
+                    resize_image(output, 4, 2);
+                    let target = output;
+                    target.replace(".png", "-small.png");
+                    rename_file(output, target);
+                    moved.push(target);
+                }
+                moved
+            }
+        "#],
+    );
+
+    let saved = SavedImages {
+        paths: vec![output.clone()],
+        ..Default::default()
+    };
+    let saved = plugins.post_process(Path::new("kata.png"), saved).unwrap();
+    let small = temp_dir.path().join("kata-1-small.png");
+    assert_eq!(saved.paths, vec![small.clone()]);
+    assert!(!output.exists());
+    assert_eq!(image::image_dimensions(&small).unwrap(), (4, 2));
+}
+
+#[test]
+fn test_script_errors_name_the_plugin() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let plugins = load(temp_dir.path(), &[r#"fn post_process(image, outputs) { remove_file("missing.png") }"#]);
+    let error = plugins.post_process(Path::new("kata.png"), SavedImages::default()).unwrap_err();
+    let message = format!("{:#}", error);
+    assert!(message.contains("plugin-0.rhai"));
+    assert!(message.contains("missing.png"));
+
+    let broken = temp_dir.path().join("broken.rhai");
+    fs::write(&broken, "fn filter_input(image) {").unwrap();
+    assert!(Plugins::load(&[broken.to_string_lossy().to_string()]).is_err());
+}
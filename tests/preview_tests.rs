@@ -0,0 +1,70 @@
+//! Live preview module tests for urasoe
+
+use serde_json::json;
+use std::path::Path;
+use std::time::Duration;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use urasoe::api::StableDiffusionClient;
+use urasoe::preview::{PREVIEWS_DIR, PreviewConfig, PreviewSaver};
+
+const PNG_BASE64: &str = "iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAQAAAC1HAwCAAAAC0lEQVR42mP8/w8AAgMBApUAAAAASUVORK5CYII=";
+
+#[test]
+fn test_previews_are_saved_every_few_steps() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let mut saver = PreviewSaver::new(temp_dir.path().to_path_buf(), "kata", 5);
+
+    assert_eq!(saver.save(3, Some(PNG_BASE64)).unwrap(), None);
+    let saved = saver.save(6, Some(PNG_BASE64)).unwrap().unwrap();
+    assert_eq!(saved, temp_dir.path().join("kata-step-006.png"));
+    assert_eq!(saver.save(9, Some(PNG_BASE64)).unwrap(), None);
+    assert_eq!(saver.save(10, None).unwrap(), None);
+    let data_url = format!("data:image/png;base64,{}", PNG_BASE64);
+    assert!(saver.save(11, Some(&data_url)).unwrap().is_some());
+
+    // The next image starts over
+    assert!(saver.save(5, Some(PNG_BASE64)).unwrap().is_some());
+    assert!(temp_dir.path().join("kata-step-005.png").is_file());
+}
+
+#[tokio::test]
+async fn test_previews_are_polled_while_generating() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/sdapi/v1/progress"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "progress": 0.4,
+            "state": {"sampling_step": 8, "sampling_steps": 20},
+            "current_image": PNG_BASE64
+        })))
+        .mount(&server)
+        .await;
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let client = StableDiffusionClient::new(&format!("{}/", server.uri()));
+    let config = PreviewConfig {
+        every_steps: 4,
+        poll_interval_ms: 20,
+    };
+    let generation = async {
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        "generated"
+    };
+    let output = config
+        .while_generating(&client, temp_dir.path(), Path::new("input/kata.png"), generation)
+        .await;
+
+    assert_eq!(output, "generated");
+    let previews: Vec<_> = std::fs::read_dir(temp_dir.path().join(PREVIEWS_DIR)).unwrap().flatten().collect();
+    assert_eq!(previews.len(), 1);
+    assert_eq!(previews[0].file_name(), "kata-step-008.png");
+
+    // Without previews the server is not polled
+    let client = StableDiffusionClient::new("http://127.0.0.1:9/");
+    let output = PreviewConfig::default()
+        .while_generating(&client, temp_dir.path(), Path::new("kata.png"), async { 1 })
+        .await;
+    assert_eq!(output, 1);
+}
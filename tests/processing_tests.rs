@@ -1,7 +1,14 @@
 /**
  * Tests for the processing module
  */
-use urasoe::processing::{BatchManager, ProcessingStats, RetryManager};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use urasoe::processing::{
+    AdaptiveBreaks, BatchManager, FailureReason, ImageTiming, ProcessingStats, RateLimiter, Retryability,
+    Retryable, RetryManager, StatsCollector, is_cuda_message, split_batch,
+};
+use urasoe::file_utils::SavedImages;
 
 #[test]
 fn test_processing_stats_display() {
@@ -38,3 +45,295 @@ fn test_retry_manager_cuda_error_detection() {
     let normal_error = anyhow::anyhow!("File not found");
     assert!(!retry_manager.is_cuda_error(&normal_error));
 }
+
+#[test]
+fn test_processing_stats_record_outcomes() {
+    let mut stats = ProcessingStats::new();
+    stats.record_success(Path::new("in/a.png"), 4, 1.0, timing(0, 1400, 1500), 1);
+    stats.record_failure(Path::new("in/b.png"), timing(1500, 300, 300), 3, "CUDA out of memory");
+
+    assert_eq!(stats.success_count, 1);
+    assert_eq!(stats.generated_count, 4);
+    assert_eq!(stats.failed_paths, vec!["in/b.png".to_string()]);
+    assert_eq!(stats.images.len(), 2);
+    assert_eq!(stats.total_retries(), 2);
+}
+
+#[test]
+fn test_processing_stats_export_json_and_csv() {
+    let mut stats = ProcessingStats::new();
+    stats.record_success(Path::new("a.png"), 2, 0.5, timing(0, 8, 10), 1);
+    stats.record_failure(Path::new("b.png"), timing(10, 20, 20), 2, "API error: 500, \"boom\"");
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let json_path = temp_dir.path().join("stats.json");
+    stats.write_to_file(&json_path).unwrap();
+    let json: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&json_path).unwrap()).unwrap();
+    assert_eq!(json["success_count"], 1);
+    assert_eq!(json["failed_count"], 1);
+    assert_eq!(json["total_retries"], 1);
+    assert_eq!(json["images"][1]["error"], "API error: 500, \"boom\"");
+    assert_eq!(json["failures_by_reason"]["http_server_error"], 1);
+
+    let csv_path = temp_dir.path().join("stats.csv");
+    stats.write_to_file(&csv_path).unwrap();
+    let csv = std::fs::read_to_string(&csv_path).unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(
+        lines[0],
+        "path,success,generated,megapixels,duration_ms,queue_wait_ms,generation_ms,attempts,reason,error"
+    );
+    assert_eq!(lines[1], "a.png,true,2,0.50,10,0,8,1,,");
+    assert_eq!(lines[2], "b.png,false,0,0.00,20,10,20,2,HTTP 5xx,\"API error: 500, \"\"boom\"\"\"");
+}
+
+#[test]
+fn test_processing_stats_throughput() {
+    let mut stats = ProcessingStats::new();
+    stats.record_success(Path::new("a.png"), 4, 2.0, timing(0, 20_000, 21_000), 1);
+    stats.record_success(Path::new("b.png"), 2, 1.0, timing(21_000, 10_000, 11_000), 2);
+    stats.elapsed_ms = 30_000;
+
+    assert_eq!(stats.average_generation_ms(), 15_000);
+    assert_eq!(stats.total_megapixels(), 3.0);
+    assert_eq!(stats.images_per_minute(), 12.0);
+    assert_eq!(stats.megapixels_per_minute(), 6.0);
+}
+
+#[test]
+fn test_stats_collector_records_from_many_threads() {
+    let collector = StatsCollector::new();
+    let saved = SavedImages {
+        bytes: 100,
+        ..Default::default()
+    };
+    let workers: Vec<_> = (0..4)
+        .map(|worker| {
+            let collector = Arc::clone(&collector);
+            let saved = saved.clone();
+            std::thread::spawn(move || {
+                for i in 0..10 {
+                    let path = format!("in/{}-{}.png", worker, i);
+                    if i % 5 == 0 {
+                        collector.record_failure(Path::new(&path), timing(0, 5, 5), 2, "CUDA out of memory");
+                    } else {
+                        collector.record_success(Path::new(&path), 2, 0.5, timing(0, 10, 12), 1, &saved);
+                    }
+                }
+            })
+        })
+        .collect();
+    for worker in workers {
+        worker.join().unwrap();
+    }
+
+    assert_eq!(collector.success_count(), 32);
+    assert_eq!(collector.generated_count(), 64);
+    assert_eq!(collector.failed_count(), 8);
+
+    let stats = collector.snapshot();
+    assert_eq!(stats.success_count, 32);
+    assert_eq!(stats.generated_count, 64);
+    assert_eq!(stats.failed_paths.len(), 8);
+    assert_eq!(stats.images.len(), 40);
+    assert_eq!(stats.bytes_written(), 3200);
+    assert_eq!(stats.total_retries(), 8);
+}
+
+#[test]
+fn test_stats_collector_returns_recorded_result() {
+    let collector = StatsCollector::new();
+    let result = collector.record_failure(Path::new("in/b.png"), timing(0, 300, 300), 3, "CUDA out of memory");
+    assert!(!result.success);
+    assert_eq!(result.attempts, 3);
+    assert_eq!(result.reason, Some(FailureReason::CudaOom));
+
+    // Snapshots are copies, later results do not change them
+    let before = collector.snapshot();
+    collector.record_success(Path::new("in/a.png"), 1, 1.0, timing(0, 10, 10), 1, &SavedImages::default());
+    assert_eq!(before.images.len(), 1);
+    assert_eq!(collector.snapshot().images.len(), 2);
+}
+
+/// Build an ImageTiming from millisecond values
+fn timing(queue_wait_ms: u64, generation_ms: u64, total_ms: u64) -> ImageTiming {
+    ImageTiming {
+        queue_wait: Duration::from_millis(queue_wait_ms),
+        generation: Duration::from_millis(generation_ms),
+        total: Duration::from_millis(total_ms),
+    }
+}
+
+#[test]
+fn test_failure_reason_classification() {
+    let cases = [
+        ("API error: 500 Internal Server Error - CUDA out of memory", FailureReason::CudaOom),
+        (
+            "API error: 500 Internal Server Error - NansException: A tensor with all NaNs was produced in Unet.",
+            FailureReason::NanTensors,
+        ),
+        ("API request failed: operation timed out", FailureReason::Timeout),
+        ("API error: 422 Unprocessable Entity - bad sampler", FailureReason::HttpClientError),
+        ("API error: 502 Bad Gateway - ", FailureReason::HttpServerError),
+        ("API request failed: error sending request for url", FailureReason::Connection),
+        ("Failed to decode base64 image: Invalid byte 33", FailureReason::DecodeError),
+        ("Error opening image: missing.png: No such file or directory", FailureReason::InvalidInput),
+        ("Something unexpected", FailureReason::Other),
+    ];
+    for (message, expected) in cases {
+        assert_eq!(FailureReason::from_message(message), expected, "{}", message);
+    }
+}
+
+#[test]
+fn test_processing_stats_groups_failures_by_reason() {
+    let mut stats = ProcessingStats::new();
+    stats.record_failure(Path::new("a.png"), timing(0, 1, 1), 3, "CUDA out of memory");
+    stats.record_failure(Path::new("b.png"), timing(0, 1, 1), 3, "CUDA error: device-side assert");
+    stats.record_failure(Path::new("c.png"), timing(0, 1, 1), 1, "Error reading image: c.png");
+
+    let groups = stats.failures_by_reason();
+    assert_eq!(groups[&FailureReason::CudaOom], vec!["a.png", "b.png"]);
+    assert_eq!(groups[&FailureReason::InvalidInput], vec!["c.png"]);
+    stats.display(3);
+}
+
+#[test]
+fn test_retry_manager_custom_retry_patterns() {
+    let retry_manager = RetryManager::with_config(3, 100)
+        .with_retry_patterns(&["NansException".to_string(), r"API error: 50[23]".to_string()])
+        .unwrap();
+
+    assert!(retry_manager.is_retryable(&anyhow::anyhow!("nansexception: A tensor with all NaNs")));
+    assert!(retry_manager.is_retryable(&anyhow::anyhow!("API error: 503 Service Unavailable")));
+    assert!(retry_manager.is_retryable(&anyhow::anyhow!("CUDA out of memory")));
+    assert!(!retry_manager.is_retryable(&anyhow::anyhow!("API error: 422 Unprocessable")));
+
+    let wrapped = anyhow::anyhow!("NansException").context("API request failed");
+    assert!(retry_manager.is_retryable(&wrapped));
+}
+
+#[test]
+fn test_retryability_tells_why() {
+    let retry_manager = RetryManager::with_config(3, 100)
+        .with_retry_patterns(&["NansException".to_string()])
+        .unwrap();
+
+    let gpu = anyhow::anyhow!("CUDA out of memory");
+    assert_eq!(gpu.retryability(), Retryability::Gpu);
+    assert_eq!(retry_manager.retryability(&gpu), Retryability::Gpu);
+
+    let nan = anyhow::anyhow!("NansException: A tensor with all NaNs");
+    assert!(!nan.is_retryable());
+    assert_eq!(retry_manager.retryability(&nan), Retryability::Pattern("NansException".to_string()));
+    assert_eq!(retry_manager.retryability(&nan).to_string(), "matches retry_on pattern NansException");
+
+    let rejected = anyhow::anyhow!("API error: 422 Unprocessable");
+    assert_eq!(retry_manager.retryability(&rejected), Retryability::Permanent);
+    assert!(!Retryability::Permanent.is_retryable());
+
+    // Server errors and CUDA errors wrapped in context are found in the chain
+    let unavailable = anyhow::anyhow!("API error: 503 Service Unavailable").context("Failed to generate");
+    assert_eq!(unavailable.retryability(), Retryability::Transient);
+    let wrapped_gpu = anyhow::anyhow!("CUDA out of memory").context("API error: 500 Internal Server Error");
+    assert_eq!(wrapped_gpu.retryability(), Retryability::Gpu);
+}
+
+#[test]
+fn test_cuda_message() {
+    assert!(is_cuda_message("RuntimeError: CUDA error: device-side assert triggered"));
+    assert!(is_cuda_message("Not enough memory to allocate"));
+    assert!(!is_cuda_message("System out of memory"));
+    assert!(!is_cuda_message("API error: 404 Not Found"));
+}
+
+#[test]
+fn test_retry_manager_invalid_retry_pattern() {
+    let result = RetryManager::with_config(3, 100).with_retry_patterns(&["(unclosed".to_string()]);
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn test_rate_limiter_spaces_requests() {
+    // 600 requests per minute is one every 100ms
+    let limiter = Arc::new(RateLimiter::per_minute(600));
+    let started = Instant::now();
+    let workers: Vec<_> = (0..2)
+        .map(|_| {
+            let limiter = Arc::clone(&limiter);
+            tokio::spawn(async move {
+                limiter.acquire().await;
+                limiter.acquire().await;
+            })
+        })
+        .collect();
+    for worker in workers {
+        worker.await.unwrap();
+    }
+
+    // The first request goes out at once, the other three wait their turn
+    assert!(started.elapsed() >= Duration::from_millis(290));
+}
+
+#[tokio::test]
+async fn test_rate_limiter_allows_burst_up_to_capacity() {
+    let limiter = RateLimiter::with_capacity(1.0, 3);
+    let started = Instant::now();
+    for _ in 0..3 {
+        limiter.acquire().await;
+    }
+    assert!(started.elapsed() < Duration::from_millis(100));
+}
+
+#[test]
+fn test_split_batch() {
+    assert_eq!(split_batch(8, None), vec![8]);
+    assert_eq!(split_batch(8, Some(8)), vec![8]);
+    assert_eq!(split_batch(8, Some(16)), vec![8]);
+    assert_eq!(split_batch(8, Some(2)), vec![2, 2, 2, 2]);
+    assert_eq!(split_batch(5, Some(2)), vec![2, 2, 1]);
+    assert_eq!(split_batch(5, Some(0)), vec![5]);
+}
+
+#[tokio::test]
+async fn test_generation_break_only_after_full_batch_with_work_left() {
+    let batch_manager = BatchManager::with_config(2, 200);
+
+    let started = Instant::now();
+    batch_manager.manage_generation_break(0, true).await;
+    batch_manager.manage_generation_break(1, true).await;
+    batch_manager.manage_generation_break(2, false).await;
+    assert!(started.elapsed() < Duration::from_millis(200));
+
+    let started = Instant::now();
+    batch_manager.manage_generation_break(2, true).await;
+    assert!(started.elapsed() >= Duration::from_millis(200));
+}
+
+#[test]
+fn test_adaptive_breaks_only_when_generation_degrades() {
+    let mut adaptive = AdaptiveBreaks::new(1000);
+    let seconds = Duration::from_secs;
+
+    // Healthy, steady generation times never cause a break
+    for _ in 0..10 {
+        assert_eq!(adaptive.record(seconds(10)), None);
+    }
+
+    // Slowing down pauses, doubling the break while the slowdown lasts
+    assert_eq!(adaptive.record(seconds(11)), None);
+    assert_eq!(adaptive.record(seconds(30)), Some(Duration::from_millis(1000)));
+    assert_eq!(adaptive.record(seconds(30)), Some(Duration::from_millis(2000)));
+    assert_eq!(adaptive.record(seconds(30)), Some(Duration::from_millis(4000)));
+    assert_eq!(adaptive.record(seconds(30)), Some(Duration::from_millis(8000)));
+    assert_eq!(adaptive.record(seconds(30)), Some(Duration::from_millis(16000)));
+    assert_eq!(adaptive.record(seconds(30)), Some(Duration::from_millis(16000)));
+
+    // Recovering resets the break duration
+    for _ in 0..5 {
+        adaptive.record(seconds(10));
+    }
+    assert_eq!(adaptive.record(seconds(10)), None);
+    assert_eq!(adaptive.record(seconds(40)), Some(Duration::from_millis(1000)));
+}
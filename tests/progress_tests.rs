@@ -0,0 +1,90 @@
+//! Progress file module tests for urasoe
+
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tempfile::tempdir;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use urasoe::config::Config;
+use urasoe::control::{ActiveInput, RunControl, RunStatus};
+use urasoe::metrics::Metrics;
+use urasoe::progress::{LastError, Progress, ProgressState};
+use urasoe::runner::run_batch;
+
+#[test]
+fn test_progress_reports_counts_current_inputs_and_eta() {
+    let status = RunStatus {
+        total: 10,
+        done: 3,
+        failed: 1,
+        generated: 6,
+        generation_time: Duration::from_secs(40),
+        finished_in_run: 4,
+        active: vec![ActiveInput {
+            path: "input/kata.png".into(),
+            backend: "http://127.0.0.1:7860/".to_string(),
+            started: Instant::now(),
+            sampling: None,
+        }],
+        started: Some(Instant::now()),
+        ..Default::default()
+    };
+    let last_error = LastError {
+        input: "input/kumite.png".to_string(),
+        error: "CUDA out of memory".to_string(),
+    };
+
+    let progress = Progress::from_status(&status, ProgressState::Running, Some("run-1".to_string()), Some(last_error));
+    assert_eq!((progress.done, progress.failed, progress.pending), (3, 1, 5));
+    assert_eq!(progress.current[0].input, Path::new("input/kata.png").display().to_string());
+    // Six inputs left at ten seconds each on the one busy backend
+    assert_eq!(progress.eta_seconds, Some(60));
+    assert!(progress.eta.is_some());
+    assert_eq!(progress.last_error.unwrap().error, "CUDA out of memory");
+
+    let finished = Progress::from_status(&status, ProgressState::Finished, None, None);
+    assert_eq!(finished.eta_seconds, None);
+    let json = serde_json::to_value(&finished).unwrap();
+    assert_eq!(json["state"], "finished");
+}
+
+#[tokio::test]
+async fn test_progress_file_is_kept_up_to_date_by_the_run() {
+    let temp_dir = tempdir().unwrap();
+    let input_dir = temp_dir.path().join("input");
+    fs::create_dir_all(&input_dir).unwrap();
+    for name in ["kata.png", "kumite.png"] {
+        fs::write(input_dir.join(name), "input").unwrap();
+    }
+    let backend = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/options"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({})))
+        .mount(&backend)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/sdapi/v1/txt2img"))
+        .respond_with(ResponseTemplate::new(400).set_body_string("Bad request"))
+        .mount(&backend)
+        .await;
+
+    let mut config = Config::load("nonexistent_file.yml").unwrap();
+    config.sd_api_url = format!("{}/", backend.uri());
+    config.input_dir = input_dir.to_string_lossy().to_string();
+    config.output_dir = temp_dir.path().join("output").to_string_lossy().to_string();
+    config.batch_break_ms = 0;
+    config.max_retries = 1;
+    config.assume_yes = true;
+    config.progress_file = Some("status/progress.json".to_string());
+
+    run_batch(&config, &Metrics::new(), &RunControl::default()).await.unwrap();
+    let progress = Progress::read(&temp_dir.path().join("output/status/progress.json")).unwrap();
+    assert_eq!(progress.state, ProgressState::Finished);
+    assert_eq!((progress.total, progress.failed, progress.pending), (2, 2, 0));
+    assert!(progress.current.is_empty());
+    assert!(progress.run_id.is_some());
+    assert!(progress.last_error.unwrap().input.ends_with("kumite.png"));
+    assert!(!temp_dir.path().join("output/status/progress.json.tmp").exists());
+}
@@ -0,0 +1,227 @@
+//! Prompt source module tests for urasoe
+
+use serde_json::json;
+use std::fs;
+use std::path::Path;
+use wiremock::matchers::{body_partial_json, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use urasoe::api::StableDiffusionClient;
+use urasoe::config::Config;
+use urasoe::prompt_source::{
+    CaptionMode, Emphasis, Prompt, PromptContext, PromptSourceConfig, emphasize, render, resolve,
+};
+
+fn config_with_sources(sources: &str) -> Config {
+    let mut config = Config::load("nonexistent_config.yml").unwrap();
+    config.prompt = "karate master".to_string();
+    config.negative_prompt = "blurry".to_string();
+    config.prompt_sources = serde_yaml::from_str(sources).unwrap();
+    config
+}
+
+#[test]
+fn test_render_replaces_known_placeholders() {
+    assert_eq!(
+        render("{prompt} in {dir}, {unknown}", &[("prompt", "kata"), ("dir", "dojo")]),
+        "kata in dojo, {unknown}"
+    );
+}
+
+#[test]
+fn test_prompt_source_config_parsing() {
+    let config = config_with_sources(
+        "- type: sidecar\n- type: interrogate\n- type: template\n  prompt: \"{prompt}, {dir}\"\n",
+    );
+    assert_eq!(
+        config.prompt_sources,
+        [
+            PromptSourceConfig::Sidecar,
+            PromptSourceConfig::Interrogate {
+                model: "clip".to_string(),
+                template: "{prompt}, {caption}".to_string(),
+            },
+            PromptSourceConfig::Template {
+                prompt: Some("{prompt}, {dir}".to_string()),
+                negative_prompt: None,
+            },
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_without_sources_the_configured_prompts_are_used() {
+    let config = config_with_sources("[]");
+    let client = StableDiffusionClient::new("http://127.0.0.1:9/");
+    let context = PromptContext {
+        image_path: Path::new("input/kata.png"),
+        client: &client,
+    };
+    assert_eq!(
+        resolve(&config, context).await.unwrap(),
+        Prompt {
+            prompt: "karate master".to_string(),
+            negative_prompt: "blurry".to_string(),
+        }
+    );
+}
+
+#[tokio::test]
+async fn test_sidecar_and_template_sources_compose() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let dojo = temp_dir.path().join("dojo");
+    fs::create_dir_all(&dojo).unwrap();
+    let image = dojo.join("kata.png");
+    fs::write(dojo.join("kata.yml"), "prompt: \"black belt\"\n").unwrap();
+
+    let config = config_with_sources(
+        "- type: sidecar\n- type: template\n  prompt: \"{prompt} doing {file_stem} in the {dir}\"\n  negative_prompt: \"{negative_prompt}, text\"\n",
+    );
+    let client = StableDiffusionClient::new("http://127.0.0.1:9/");
+    let context = PromptContext {
+        image_path: &image,
+        client: &client,
+    };
+    let prompt = resolve(&config, context).await.unwrap();
+    assert_eq!(prompt.prompt, "black belt doing kata in the dojo");
+    assert_eq!(prompt.negative_prompt, "blurry, text");
+
+    // A static source later in the list starts over from the configuration
+    let config = config_with_sources("- type: sidecar\n- type: static\n");
+    assert_eq!(resolve(&config, context).await.unwrap().prompt, "karate master");
+}
+
+#[tokio::test]
+async fn test_interrogate_source_adds_caption() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/sdapi/v1/interrogate"))
+        .and(body_partial_json(json!({"model": "deepdanbooru"})))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({"caption": " 1boy, white gi \n"})))
+        .mount(&server)
+        .await;
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let image = temp_dir.path().join("kata.png");
+    fs::write(&image, "png").unwrap();
+    let config = config_with_sources("- type: interrogate\n  model: deepdanbooru\n");
+    let client = StableDiffusionClient::new(&format!("{}/", server.uri()));
+    let context = PromptContext {
+        image_path: &image,
+        client: &client,
+    };
+    assert_eq!(resolve(&config, context).await.unwrap().prompt, "karate master, 1boy, white gi");
+
+    let client = StableDiffusionClient::new("http://127.0.0.1:9/");
+    let context = PromptContext {
+        image_path: &image,
+        client: &client,
+    };
+    assert!(resolve(&config, context).await.is_err());
+}
+
+#[tokio::test]
+async fn test_caption_files_become_prompts() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let image = temp_dir.path().join("kata.png");
+    fs::write(temp_dir.path().join("kata.txt"), "a man in a white gi\n").unwrap();
+
+    let config = config_with_sources("- type: caption\n");
+    let client = StableDiffusionClient::new("http://127.0.0.1:9/");
+    let context = PromptContext {
+        image_path: &image,
+        client: &client,
+    };
+    assert_eq!(resolve(&config, context).await.unwrap().prompt, "a man in a white gi");
+
+    let config = config_with_sources("- type: caption\n  template: \"{caption}, {prompt}\"\n");
+    assert_eq!(resolve(&config, context).await.unwrap().prompt, "a man in a white gi, karate master");
+
+    // Inputs without a caption file keep the prompt
+    let other = temp_dir.path().join("kumite.png");
+    let context = PromptContext {
+        image_path: &other,
+        client: &client,
+    };
+    assert_eq!(resolve(&config, context).await.unwrap().prompt, "karate master");
+}
+
+#[tokio::test]
+async fn test_caption_mode_reads_caption_files() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let image = temp_dir.path().join("kata.png");
+    fs::write(temp_dir.path().join("kata.caption"), "a man in a white gi\n").unwrap();
+    let client = StableDiffusionClient::new("http://127.0.0.1:9/");
+    let context = PromptContext {
+        image_path: &image,
+        client: &client,
+    };
+
+    let mut config = config_with_sources("[]");
+    config.prompt_sources.push(CaptionMode::Append.source());
+    assert_eq!(resolve(&config, context).await.unwrap().prompt, "karate master, a man in a white gi");
+
+    // A .txt caption is looked for first
+    fs::write(temp_dir.path().join("kata.txt"), "a man bowing").unwrap();
+    config.prompt_sources = vec![CaptionMode::Replace.source()];
+    assert_eq!(resolve(&config, context).await.unwrap().prompt, "a man bowing");
+}
+
+#[tokio::test]
+async fn test_filename_words_join_the_prompt() {
+    let config = config_with_sources("- type: filename\n");
+    let client = StableDiffusionClient::new("http://127.0.0.1:9/");
+    let context = PromptContext {
+        image_path: Path::new("input/sunset_beach-03.jpg"),
+        client: &client,
+    };
+    assert_eq!(resolve(&config, context).await.unwrap().prompt, "karate master, sunset beach");
+
+    let config = config_with_sources("- type: filename\n  separators: \".\"\n  strip_numbers: false\n  template: \"{words}\"\n");
+    let context = PromptContext {
+        image_path: Path::new("input/dojo.at.night 2.png"),
+        client: &client,
+    };
+    assert_eq!(resolve(&config, context).await.unwrap().prompt, "dojo at night 2");
+}
+
+#[test]
+fn test_emphasize_weights_terms_of_the_prompt() {
+    let weights = [("black belt".to_string(), 1.2), ("dojo".to_string(), 0.8), ("kiai".to_string(), 1.5)]
+        .into_iter()
+        .collect();
+    assert_eq!(
+        emphasize("Black belt karateka in a dojo, dojos", &weights),
+        "(Black belt:1.2) karateka in a (dojo:0.8), dojos, (kiai:1.5)"
+    );
+    assert_eq!(emphasize("", &[("kata".to_string(), 1.1)].into_iter().collect()), "(kata:1.1)");
+
+    assert_eq!(
+        "black belt = 1.2".parse::<Emphasis>().unwrap(),
+        Emphasis {
+            term: "black belt".to_string(),
+            weight: 1.2,
+        }
+    );
+    assert!("kata".parse::<Emphasis>().is_err());
+    assert!("=1.2".parse::<Emphasis>().is_err());
+    assert!("kata=strong".parse::<Emphasis>().is_err());
+}
+
+#[tokio::test]
+async fn test_emphasis_applies_to_the_resolved_prompt() {
+    let mut config = config_with_sources("- type: template\n  prompt: \"{prompt}, {file_stem}\"\n");
+    config.emphasize.insert("kata".to_string(), 1.3);
+    let client = StableDiffusionClient::new("http://127.0.0.1:9/");
+    let context = PromptContext {
+        image_path: Path::new("input/kata.png"),
+        client: &client,
+    };
+    assert_eq!(resolve(&config, context).await.unwrap().prompt, "karate master, (kata:1.3)");
+
+    let metadata = urasoe::file_utils::ImageMetadata::from_config(&config, Path::new("input/kata.png"));
+    assert_eq!(metadata.prompt, "karate master");
+    assert!(metadata.infotext(0).starts_with("karate master, (kata:1.3)\n"));
+    let replayed = metadata.apply_to(&Config::load("nonexistent_config.yml").unwrap());
+    assert_eq!(replayed.emphasize, config.emphasize);
+}
@@ -0,0 +1,39 @@
+//! Confirmation prompt tests for urasoe
+
+use clap::Parser;
+use urasoe::config::{Args, Config};
+use urasoe::prompt::{PromptPolicy, confirm, is_non_interactive};
+
+#[test]
+fn test_non_interactive_args() {
+    let args = Args::parse_from(["urasoe", "--non-interactive", "--prompt-policy", "abort"]);
+    let mut config = Config::load("nonexistent_config.yml").unwrap();
+    assert!(!config.non_interactive);
+    assert_eq!(config.prompt_policy, PromptPolicy::Continue);
+
+    config.apply_args(&args);
+    assert!(config.non_interactive);
+    assert!(is_non_interactive(&config));
+    assert_eq!(config.prompt_policy, PromptPolicy::Abort);
+}
+
+#[test]
+fn test_confirm_follows_prompt_policy() {
+    let mut config = Config::load("nonexistent_config.yml").unwrap();
+    config.non_interactive = true;
+
+    config.prompt_policy = PromptPolicy::Continue;
+    assert!(confirm(&config, "Continue anyway?").unwrap());
+
+    config.prompt_policy = PromptPolicy::Abort;
+    assert!(!confirm(&config, "Continue anyway?").unwrap());
+}
+
+#[test]
+fn test_confirm_yes_overrides_abort_policy() {
+    let mut config = Config::load("nonexistent_config.yml").unwrap();
+    config.non_interactive = true;
+    config.prompt_policy = PromptPolicy::Abort;
+    config.assume_yes = true;
+    assert!(confirm(&config, "Continue anyway?").unwrap());
+}
@@ -0,0 +1,205 @@
+//! Queue module tests for urasoe
+
+use std::path::PathBuf;
+use urasoe::queue::{JobQueue, JobStatus, order_inputs};
+
+#[test]
+fn test_queue_processes_in_enqueue_order() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let mut queue = JobQueue::create(temp_dir.path().join("queue.jsonl")).unwrap();
+
+    let added = queue.enqueue_all(&["a.png", "b.png", "a.png"]).unwrap();
+    assert_eq!(added, 2);
+    assert_eq!(queue.len(), 2);
+
+    assert_eq!(queue.next_pending().unwrap(), Some(PathBuf::from("a.png")));
+    assert_eq!(queue.status("a.png"), Some(JobStatus::InProgress));
+    queue.mark_done("a.png").unwrap();
+
+    assert_eq!(queue.next_pending().unwrap(), Some(PathBuf::from("b.png")));
+    queue.mark_failed("b.png").unwrap();
+
+    assert_eq!(queue.next_pending().unwrap(), None);
+    assert_eq!(queue.count(JobStatus::Done), 1);
+    assert_eq!(queue.count(JobStatus::Failed), 1);
+}
+
+#[test]
+fn test_queue_unfinished_while_inputs_are_left() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let queue_path = temp_dir.path().join("queue.jsonl");
+    assert!(!JobQueue::is_unfinished(&queue_path).unwrap());
+    let (a, b) = (temp_dir.path().join("a.png"), temp_dir.path().join("b.png"));
+    std::fs::write(&a, [1u8]).unwrap();
+    std::fs::write(&b, [1u8]).unwrap();
+
+    let mut queue = JobQueue::create(&queue_path).unwrap();
+    queue.enqueue_all(&[&a, &b]).unwrap();
+    queue.next_pending().unwrap();
+    queue.mark_failed(&a).unwrap();
+    queue.next_pending().unwrap();
+    // b.png was being generated when the run stopped
+    assert!(JobQueue::is_unfinished(&queue_path).unwrap());
+
+    // a.png failed, so the run is not finished yet
+    queue.mark_done(&b).unwrap();
+    assert!(JobQueue::is_unfinished(&queue_path).unwrap());
+
+    assert_eq!(queue.retry_failed().unwrap(), 1);
+    assert_eq!(queue.status(&a), Some(JobStatus::Pending));
+    assert!(JobQueue::is_unfinished(&queue_path).unwrap());
+
+    queue.next_pending().unwrap();
+    queue.mark_done(&a).unwrap();
+    assert!(!JobQueue::is_unfinished(&queue_path).unwrap());
+}
+
+#[test]
+fn test_queue_does_not_retry_failed_inputs_that_are_gone() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let queue_path = temp_dir.path().join("queue.jsonl");
+    let kept = temp_dir.path().join("kept.png");
+    let moved = temp_dir.path().join("moved.png");
+    std::fs::write(&kept, [1u8]).unwrap();
+
+    let mut queue = JobQueue::create(&queue_path).unwrap();
+    queue.enqueue_all(&[&kept, &moved]).unwrap();
+    queue.mark_failed(&moved).unwrap();
+    queue.mark_done(&kept).unwrap();
+    assert!(!JobQueue::is_unfinished(&queue_path).unwrap());
+    assert_eq!(queue.retry_failed().unwrap(), 0);
+    assert_eq!(queue.status(&moved), Some(JobStatus::Failed));
+
+    queue.mark_failed(&kept).unwrap();
+    assert!(JobQueue::is_unfinished(&queue_path).unwrap());
+    assert_eq!(queue.retry_failed().unwrap(), 1);
+    assert_eq!(queue.status(&kept), Some(JobStatus::Pending));
+}
+
+#[test]
+fn test_queue_reopen_restores_state() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let queue_path = temp_dir.path().join("queue.jsonl");
+
+    {
+        let mut queue = JobQueue::create(&queue_path).unwrap();
+        queue.enqueue_all(&["a.png", "b.png", "c.png"]).unwrap();
+        queue.next_pending().unwrap();
+        queue.mark_done("a.png").unwrap();
+        // Interrupted while b.png was in progress
+        queue.next_pending().unwrap();
+    }
+
+    let mut queue = JobQueue::open(&queue_path).unwrap();
+    assert_eq!(queue.len(), 3);
+    assert_eq!(queue.status("a.png"), Some(JobStatus::Done));
+    assert_eq!(queue.status("b.png"), Some(JobStatus::Pending));
+    assert_eq!(queue.next_pending().unwrap(), Some(PathBuf::from("b.png")));
+}
+
+#[test]
+fn test_queue_picks_up_external_appends() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let queue_path = temp_dir.path().join("queue.jsonl");
+    let mut queue = JobQueue::create(&queue_path).unwrap();
+    queue.enqueue("a.png").unwrap();
+    queue.next_pending().unwrap();
+
+    JobQueue::append_to(&queue_path, "late.png", 0).unwrap();
+
+    assert_eq!(queue.next_pending().unwrap(), Some(PathBuf::from("late.png")));
+    assert_eq!(queue.len(), 2);
+}
+
+#[test]
+fn test_queues_sharing_a_file_see_each_others_records() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let queue_path = temp_dir.path().join("queue.jsonl");
+    let mut first = JobQueue::create(&queue_path).unwrap();
+    let mut second = JobQueue::open(&queue_path).unwrap();
+
+    first.enqueue("a.png").unwrap();
+    second.enqueue("b.png").unwrap();
+    first.enqueue("c.png").unwrap();
+    assert_eq!(second.next_pending().unwrap(), Some(PathBuf::from("a.png")));
+    second.mark_done("a.png").unwrap();
+
+    // Every record is read, including those appended in between by the other queue
+    assert_eq!(first.next_pending().unwrap(), Some(PathBuf::from("b.png")));
+    assert_eq!(first.status("a.png"), Some(JobStatus::Done));
+    assert_eq!(second.len(), 3);
+}
+
+#[test]
+fn test_queue_create_truncates_previous_file() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let queue_path = temp_dir.path().join("queue.jsonl");
+    JobQueue::append_to(&queue_path, "old.png", 0).unwrap();
+
+    let queue = JobQueue::create(&queue_path).unwrap();
+    assert!(queue.is_empty());
+}
+
+#[test]
+fn test_queue_takes_highest_priority_first() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let queue_path = temp_dir.path().join("queue.jsonl");
+    let mut queue = JobQueue::create(&queue_path).unwrap();
+    queue.enqueue("backlog-1.png").unwrap();
+    queue.enqueue("backlog-2.png").unwrap();
+    queue.enqueue_with_priority("urgent-1.png", 10).unwrap();
+    queue.enqueue_with_priority("urgent-2.png", 10).unwrap();
+    queue.enqueue_with_priority("later.png", -1).unwrap();
+
+    assert_eq!(queue.next_pending().unwrap(), Some(PathBuf::from("urgent-1.png")));
+    queue.mark_done("urgent-1.png").unwrap();
+
+    // Priorities survive status changes and reopening the queue
+    let mut queue = JobQueue::open(&queue_path).unwrap();
+    assert_eq!(queue.entries()[2].priority, 10);
+    assert_eq!(queue.next_pending().unwrap(), Some(PathBuf::from("urgent-2.png")));
+    assert_eq!(queue.next_pending().unwrap(), Some(PathBuf::from("backlog-1.png")));
+
+    // An urgent input appended by another process jumps ahead of the backlog
+    JobQueue::append_to(&queue_path, "hot-folder.png", 5).unwrap();
+    assert_eq!(queue.next_pending().unwrap(), Some(PathBuf::from("hot-folder.png")));
+    assert_eq!(queue.next_pending().unwrap(), Some(PathBuf::from("backlog-2.png")));
+    assert_eq!(queue.next_pending().unwrap(), Some(PathBuf::from("later.png")));
+}
+
+fn paths(names: &[&str]) -> Vec<PathBuf> {
+    names.iter().map(PathBuf::from).collect()
+}
+
+#[test]
+fn test_order_inputs_sorted_by_default() {
+    let ordered = order_inputs(paths(&["b.png", "c.png", "a.png"]), None, false);
+    assert_eq!(ordered, paths(&["a.png", "b.png", "c.png"]));
+}
+
+#[test]
+fn test_order_inputs_shuffle_is_reproducible() {
+    let inputs: Vec<PathBuf> = (0..20).map(|i| PathBuf::from(format!("{:02}.png", i))).collect();
+
+    let first = order_inputs(inputs.clone(), Some(42), false);
+    let second = order_inputs(inputs.iter().rev().cloned().collect(), Some(42), false);
+    assert_eq!(first, second);
+    assert_ne!(first, inputs);
+
+    let mut sorted = first.clone();
+    sorted.sort();
+    assert_eq!(sorted, inputs);
+}
+
+#[test]
+fn test_order_inputs_stratified_interleaves_directories() {
+    let ordered = order_inputs(
+        paths(&["cats/1.png", "cats/2.png", "cats/3.png", "dogs/1.png", "owls/1.png", "owls/2.png"]),
+        None,
+        true,
+    );
+    assert_eq!(
+        ordered,
+        paths(&["cats/1.png", "dogs/1.png", "owls/1.png", "cats/2.png", "owls/2.png", "cats/3.png"])
+    );
+}
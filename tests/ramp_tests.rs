@@ -0,0 +1,53 @@
+//! Batch size ramp module tests for urasoe
+
+use urasoe::ramp::{BatchRamp, RampConfig};
+
+fn enabled() -> RampConfig {
+    RampConfig {
+        enabled: true,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_batch_size_doubles_up_to_the_target() {
+    assert_eq!(BatchRamp::new(&RampConfig::default(), 6).batch_size(), 6);
+
+    let mut ramp = BatchRamp::new(&enabled(), 6);
+    let mut sizes = Vec::new();
+    while !ramp.is_settled() {
+        let batch_size = ramp.batch_size();
+        sizes.push(batch_size);
+        ramp.record(true, batch_size as usize, 1000 * u64::from(batch_size));
+    }
+    assert_eq!(sizes, [1, 1, 2, 2, 4, 4]);
+    assert_eq!(ramp.batch_size(), 6);
+
+    // Failures before any step up are not blamed on the batch size
+    let mut ramp = BatchRamp::new(&enabled(), 4);
+    ramp.record(false, 0, 500);
+    assert_eq!(ramp.batch_size(), 1);
+    assert!(!ramp.is_settled());
+}
+
+#[test]
+fn test_failure_or_slowdown_holds_the_last_working_size() {
+    let mut ramp = BatchRamp::new(&enabled(), 8);
+    for _ in 0..2 {
+        ramp.record(true, 1, 1000);
+    }
+    assert_eq!(ramp.batch_size(), 2);
+    ramp.record(false, 0, 200);
+    assert_eq!(ramp.batch_size(), 1);
+    assert!(ramp.is_settled());
+    ramp.record(true, 1, 1000);
+    assert_eq!(ramp.batch_size(), 1);
+
+    let mut ramp = BatchRamp::new(&enabled(), 8);
+    for (generated, generation_ms) in [(1, 1000), (1, 1000), (2, 1800), (2, 1800), (4, 8000), (4, 8000)] {
+        ramp.record(true, generated, generation_ms);
+    }
+    // 2000 ms per image at batch size 4 against 900 ms at 2
+    assert_eq!(ramp.batch_size(), 2);
+    assert!(ramp.is_settled());
+}
@@ -3,11 +3,22 @@
 use std::fs;
 use tempfile::tempdir;
 use wiremock::{MockServer, Mock, ResponseTemplate};
-use wiremock::matchers::{method, path};
+use wiremock::matchers::{body_partial_json, method, path};
 
-use urasoe::api::StableDiffusionClient;
+use urasoe::api::{GeneratedImage, StableDiffusionClient};
 use urasoe::config::Config;
-use urasoe::processing::RetryManager;
+use urasoe::processing::{NanFallbackConfig, RetryManager, RetryOverrides};
+
+/// Image the mock server sends, the base64 of a label to recognise it by
+fn labelled(label: &str) -> String {
+    use base64::{Engine, prelude::BASE64_STANDARD};
+    BASE64_STANDARD.encode(label)
+}
+
+/// Labels of the images of a response
+fn labels(images: &[GeneratedImage]) -> Vec<String> {
+    images.iter().map(|image| String::from_utf8(image.bytes().unwrap()).unwrap()).collect()
+}
 
 #[cfg(test)]
 
@@ -158,3 +169,473 @@ async fn test_all_retries_fail() {
     // Should be an error since the response is invalid JSON
     assert!(result.is_err(), "Should be an error when JSON is invalid");
 }
+
+/// Test that errors not matching any retry pattern fail without further attempts
+#[tokio::test]
+async fn test_non_retryable_error_stops_immediately() {
+    let temp_dir = tempdir().unwrap();
+    let test_image = temp_dir.path().join("test_image.png");
+    fs::write(&test_image, [137, 80, 78, 71, 13, 10, 26, 10]).unwrap();
+
+    let mock_server = MockServer::start().await;
+    let uri = format!("{}/", mock_server.uri().trim_end_matches('/'));
+    let mut config = Config::load("nonexistent_file.yml").unwrap();
+    config.sd_api_url = uri.clone();
+
+    Mock::given(method("POST"))
+        .and(path("/sdapi/v1/txt2img"))
+        .respond_with(ResponseTemplate::new(422).set_body_string("Invalid sampler"))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let client = StableDiffusionClient::new(&uri);
+    let retry_manager = RetryManager::with_config(3, 10);
+    let (result, attempts) = retry_manager
+        .process_with_attempts(&client, &test_image, &config)
+        .await;
+
+    assert!(result.is_err());
+    assert_eq!(attempts, 1);
+}
+
+/// Test that errors matching a user-defined pattern are retried
+#[tokio::test]
+async fn test_retry_on_pattern_is_retried() {
+    let temp_dir = tempdir().unwrap();
+    let test_image = temp_dir.path().join("test_image.png");
+    fs::write(&test_image, [137, 80, 78, 71, 13, 10, 26, 10]).unwrap();
+
+    let mock_server = MockServer::start().await;
+    let uri = format!("{}/", mock_server.uri().trim_end_matches('/'));
+    let mut config = Config::load("nonexistent_file.yml").unwrap();
+    config.sd_api_url = uri.clone();
+
+    Mock::given(method("POST"))
+        .and(path("/sdapi/v1/txt2img"))
+        .respond_with(ResponseTemplate::new(503).set_body_string("Server busy"))
+        .expect(2)
+        .mount(&mock_server)
+        .await;
+
+    let client = StableDiffusionClient::new(&uri);
+    let retry_manager = RetryManager::with_config(2, 10)
+        .with_retry_patterns(&["server busy".to_string()])
+        .unwrap();
+    let (result, attempts) = retry_manager
+        .process_with_attempts(&client, &test_image, &config)
+        .await;
+
+    assert!(result.is_err());
+    assert_eq!(attempts, 2);
+}
+
+/// Test that large batches are split into sequential requests and merged
+#[tokio::test]
+async fn test_large_batch_is_split_into_requests() {
+    let temp_dir = tempdir().unwrap();
+    let test_image = temp_dir.path().join("test_image.png");
+    fs::write(&test_image, [137, 80, 78, 71, 13, 10, 26, 10]).unwrap();
+
+    let mock_server = MockServer::start().await;
+    let uri = format!("{}/", mock_server.uri().trim_end_matches('/'));
+    let mut config = Config::load("nonexistent_file.yml").unwrap();
+    config.sd_api_url = uri.clone();
+    config.batch_size = 5;
+    config.max_batch_per_request = Some(2);
+    config.seed = 100;
+
+    // Each request continues the seeds of the one before
+    for seed in [100, 102] {
+        Mock::given(method("POST"))
+            .and(path("/sdapi/v1/txt2img"))
+            .and(body_partial_json(serde_json::json!({"batch_size": 2, "seed": seed})))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "images": [labelled("pair-1"), labelled("pair-2")]
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+    }
+    Mock::given(method("POST"))
+        .and(path("/sdapi/v1/txt2img"))
+        .and(body_partial_json(serde_json::json!({"batch_size": 1, "seed": 104})))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "images": [labelled("single")]
+        })))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let client = StableDiffusionClient::new(&uri);
+    let retry_manager = RetryManager::with_config(2, 10);
+    let (result, attempts) = retry_manager
+        .process_with_attempts(&client, &test_image, &config)
+        .await;
+
+    let response = result.unwrap().unwrap();
+    assert_eq!(labels(&response.images), ["pair-1", "pair-2", "pair-1", "pair-2", "single"]);
+    assert_eq!(attempts, 1);
+}
+
+/// Test that the checkpoint is reloaded before the final attempt when CUDA errors persist
+#[tokio::test]
+async fn test_checkpoint_reloaded_after_repeated_cuda_errors() {
+    let temp_dir = tempdir().unwrap();
+    let test_image = temp_dir.path().join("test_image.png");
+    fs::write(&test_image, [137, 80, 78, 71, 13, 10, 26, 10]).unwrap();
+
+    let mock_server = MockServer::start().await;
+    let uri = format!("{}/", mock_server.uri().trim_end_matches('/'));
+    let mut config = Config::load("nonexistent_file.yml").unwrap();
+    config.sd_api_url = uri.clone();
+
+    Mock::given(method("POST"))
+        .and(path("/sdapi/v1/txt2img"))
+        .respond_with(ResponseTemplate::new(500).set_body_string("CUDA out of memory"))
+        .expect(3)
+        .mount(&mock_server)
+        .await;
+    for endpoint in ["/sdapi/v1/interrupt", "/sdapi/v1/unload-checkpoint", "/sdapi/v1/reload-checkpoint"] {
+        Mock::given(method("POST"))
+            .and(path(endpoint))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+    }
+
+    let client = StableDiffusionClient::new(&uri);
+    let retry_manager = RetryManager::with_config(3, 10).with_cuda_recovery(true, true);
+    let (result, attempts) = retry_manager
+        .process_with_attempts(&client, &test_image, &config)
+        .await;
+
+    assert!(result.is_err());
+    assert_eq!(attempts, 3);
+}
+
+/// Test that NaN tensors are retried once with the fallback sampler, even without retries left
+#[tokio::test]
+async fn test_nan_error_retried_with_fallback_sampler() {
+    let temp_dir = tempdir().unwrap();
+    let test_image = temp_dir.path().join("test_image.png");
+    fs::write(&test_image, [137, 80, 78, 71, 13, 10, 26, 10]).unwrap();
+
+    let mock_server = MockServer::start().await;
+    let uri = format!("{}/", mock_server.uri().trim_end_matches('/'));
+    let mut config = Config::load("nonexistent_file.yml").unwrap();
+    config.sd_api_url = uri.clone();
+
+    Mock::given(method("POST"))
+        .and(path("/sdapi/v1/txt2img"))
+        .and(body_partial_json(serde_json::json!({
+            "sampler_name": "Euler Karras",
+            "override_settings": {"upcast_attn": true}
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "images": [labelled("fallback")],
+            "info": "{\"sampler_name\": \"Euler Karras\"}"
+        })))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/sdapi/v1/txt2img"))
+        .respond_with(
+            ResponseTemplate::new(500)
+                .set_body_string("NansException: A tensor with all NaNs was produced in Unet."),
+        )
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let client = StableDiffusionClient::new(&uri);
+    let retry_manager = RetryManager::with_config(1, 10).with_nan_fallback(&NanFallbackConfig::default());
+    let (result, attempts) = retry_manager
+        .process_with_attempts(&client, &test_image, &config)
+        .await;
+
+    let response = result.unwrap().unwrap();
+    assert_eq!(labels(&response.images), ["fallback"]);
+    assert_eq!(response.sampler_name().as_deref(), Some("Euler Karras"));
+    assert_eq!(attempts, 2);
+}
+
+/// Test that the images missing from a short response are requested once more
+#[tokio::test]
+async fn test_missing_images_are_topped_up() {
+    let temp_dir = tempdir().unwrap();
+    let test_image = temp_dir.path().join("test_image.png");
+    fs::write(&test_image, [137, 80, 78, 71, 13, 10, 26, 10]).unwrap();
+
+    let mock_server = MockServer::start().await;
+    let uri = format!("{}/", mock_server.uri().trim_end_matches('/'));
+    let mut config = Config::load("nonexistent_file.yml").unwrap();
+    config.sd_api_url = uri.clone();
+    config.batch_size = 3;
+    config.seed = 100;
+    config.top_up_missing = true;
+
+    let base64_image = "iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAQAAAC1HAwCAAAAC0lEQVR42mP8/w8AAgMBApUAAAAASUVORK5CYII=";
+    Mock::given(method("POST"))
+        .and(path("/sdapi/v1/txt2img"))
+        .and(body_partial_json(serde_json::json!({"batch_size": 2, "seed": 101})))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "images": [base64_image, base64_image]
+        })))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/sdapi/v1/txt2img"))
+        .and(body_partial_json(serde_json::json!({"batch_size": 3})))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "images": [base64_image]
+        })))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let client = StableDiffusionClient::new(&uri);
+    let retry_manager = RetryManager::with_config(2, 10);
+    let (result, attempts) = retry_manager
+        .process_with_attempts(&client, &test_image, &config)
+        .await;
+
+    assert_eq!(result.unwrap().unwrap().images.len(), 3);
+    assert_eq!(attempts, 2);
+}
+
+/// Test that per-image overrides change the number of attempts
+#[tokio::test]
+async fn test_retry_overrides_raise_attempts() {
+    let temp_dir = tempdir().unwrap();
+    let test_image = temp_dir.path().join("test_image.png");
+    fs::write(&test_image, [137, 80, 78, 71, 13, 10, 26, 10]).unwrap();
+
+    let mock_server = MockServer::start().await;
+    let uri = format!("{}/", mock_server.uri().trim_end_matches('/'));
+    let mut config = Config::load("nonexistent_file.yml").unwrap();
+    config.sd_api_url = uri.clone();
+
+    Mock::given(method("POST"))
+        .and(path("/sdapi/v1/txt2img"))
+        .respond_with(ResponseTemplate::new(500).set_body_string("CUDA out of memory"))
+        .expect(4)
+        .mount(&mock_server)
+        .await;
+
+    let client = StableDiffusionClient::new(&uri);
+    let retry_manager = RetryManager::with_config(2, 1000);
+    let overrides = RetryOverrides {
+        max_retries: Some(4),
+        retry_delay_ms: Some(5),
+    };
+    let (result, attempts) = retry_manager
+        .process_with_overrides(&client, &test_image, &config, overrides)
+        .await;
+
+    assert!(result.is_err());
+    assert_eq!(attempts, 4);
+}
+
+/// Test that zero retries from a sidecar still makes a single attempt
+#[tokio::test]
+async fn test_zero_retries_makes_single_attempt() {
+    let temp_dir = tempdir().unwrap();
+    let test_image = temp_dir.path().join("test_image.png");
+    fs::write(&test_image, "test image data").unwrap();
+
+    let mock_server = MockServer::start().await;
+    let uri = format!("{}/", mock_server.uri().trim_end_matches('/'));
+    let mut config = Config::load("nonexistent_file.yml").unwrap();
+    config.sd_api_url = uri.clone();
+
+    Mock::given(method("POST"))
+        .and(path("/sdapi/v1/txt2img"))
+        .respond_with(ResponseTemplate::new(500).set_body_string("CUDA out of memory"))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let client = StableDiffusionClient::new(&uri);
+    let retry_manager = RetryManager::with_config(3, 5);
+    let overrides = RetryOverrides {
+        max_retries: Some(0),
+        retry_delay_ms: None,
+    };
+    let (result, attempts) = retry_manager
+        .process_with_overrides(&client, &test_image, &config, overrides)
+        .await;
+
+    assert!(result.is_err());
+    assert_eq!(attempts, 1);
+}
+
+/// Test that inputs whose images all score too low are re-rolled with a new seed
+#[tokio::test]
+async fn test_low_scores_are_rerolled() {
+    use base64::{Engine, prelude::BASE64_STANDARD};
+    use urasoe::selection::ScorerConfig;
+
+    let png = |sharp: bool| {
+        let image = image::GrayImage::from_fn(8, 8, |x, y| image::Luma([if sharp && (x + y) % 2 == 1 { 255 } else { 0 }]));
+        let mut png = Vec::new();
+        image.write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png).unwrap();
+        png
+    };
+    let temp_dir = tempdir().unwrap();
+    let test_image = temp_dir.path().join("test_image.png");
+    fs::write(&test_image, [137, 80, 78, 71, 13, 10, 26, 10]).unwrap();
+
+    let mock_server = MockServer::start().await;
+    let uri = format!("{}/", mock_server.uri().trim_end_matches('/'));
+    let mut config = Config::load("nonexistent_file.yml").unwrap();
+    config.sd_api_url = uri.clone();
+    config.seed = 42;
+    config.selection.scorer = Some(ScorerConfig::Laplacian);
+    config.selection.min_score = Some(100.0);
+
+    Mock::given(method("POST"))
+        .and(path("/sdapi/v1/txt2img"))
+        .and(body_partial_json(serde_json::json!({"seed": 42})))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "images": [BASE64_STANDARD.encode(png(false))],
+            "info": r#"{"seed": 42, "all_seeds": [42]}"#
+        })))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/sdapi/v1/txt2img"))
+        .and(body_partial_json(serde_json::json!({"seed": -1})))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "images": [BASE64_STANDARD.encode(png(true))],
+            "info": r#"{"seed": 1234, "all_seeds": [1234]}"#
+        })))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let client = StableDiffusionClient::new(&uri);
+    let retry_manager = RetryManager::with_config(2, 10);
+    let (result, attempts) = retry_manager
+        .process_with_attempts(&client, &test_image, &config)
+        .await;
+
+    // The sharp image of the re-roll reaches the minimum, so there is no second re-roll
+    let response = result.unwrap().unwrap();
+    let images: Vec<Vec<u8>> = response.images.iter().map(|image| image.bytes().unwrap()).collect();
+    assert_eq!(images, [png(false), png(true)]);
+    assert_eq!(attempts, 2);
+
+    // Each image records the seed of the roll that generated it
+    config.output_dir = temp_dir.path().join("output").to_string_lossy().to_string();
+    config.parameters_files = true;
+    let saved = urasoe::file_utils::FileManager::save_generated_images(&response, &test_image, &config).unwrap();
+    let metadata = urasoe::file_utils::ImageMetadata::read(saved.metadata_path.as_ref().unwrap()).unwrap();
+    assert_eq!(metadata.seed, 42);
+    assert_eq!(metadata.seeds, [42, 1234]);
+    let parameters = fs::read_to_string(saved.paths[1].with_file_name("test_image-2-parameters.txt")).unwrap();
+    assert!(parameters.contains("Seed: 1234,"));
+}
+
+/// Test that the best images so far are kept once the re-rolls run out
+#[tokio::test]
+async fn test_rerolls_are_limited() {
+    let temp_dir = tempdir().unwrap();
+    let test_image = temp_dir.path().join("test_image.png");
+    fs::write(&test_image, [137, 80, 78, 71, 13, 10, 26, 10]).unwrap();
+
+    let mock_server = MockServer::start().await;
+    let uri = format!("{}/", mock_server.uri().trim_end_matches('/'));
+    let mut config = Config::load("nonexistent_file.yml").unwrap();
+    config.sd_api_url = uri.clone();
+    config.selection = serde_yaml::from_str(&format!(
+        "scorer:\n  type: endpoint\n  url: \"{}score\"\nmin_score: 0.5\nmax_rerolls: 2\n",
+        uri
+    ))
+    .unwrap();
+
+    Mock::given(method("POST"))
+        .and(path("/score"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({"score": 0.1})))
+        .expect(3)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/sdapi/v1/txt2img"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({"images": ["cG5n"]})))
+        .expect(3)
+        .mount(&mock_server)
+        .await;
+
+    let client = StableDiffusionClient::new(&uri);
+    let retry_manager = RetryManager::with_config(2, 10);
+    let (result, attempts) = retry_manager
+        .process_with_attempts(&client, &test_image, &config)
+        .await;
+
+    assert_eq!(result.unwrap().unwrap().images.len(), 3);
+    assert_eq!(attempts, 3);
+}
+
+/// Test that a server restarting mid-run is waited for and the checkpoint loaded again
+#[tokio::test]
+async fn test_server_restart_waited_for_and_checkpoint_reloaded() {
+    let temp_dir = tempdir().unwrap();
+    let test_image = temp_dir.path().join("test_image.png");
+    fs::write(&test_image, [137, 80, 78, 71, 13, 10, 26, 10]).unwrap();
+
+    // Nothing listens on the port until the server comes back
+    let address = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let uri = format!("http://{}/", address);
+    let mut config = Config::load("nonexistent_file.yml").unwrap();
+    config.sd_api_url = uri.clone();
+
+    let restarted = tokio::spawn(async move {
+        tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+        let listener = std::net::TcpListener::bind(address).unwrap();
+        let mock_server = MockServer::builder().listener(listener).start().await;
+        Mock::given(method("GET"))
+            .and(path("/sdapi/v1/progress"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({})))
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/options"))
+            .and(body_partial_json(serde_json::json!({"sd_model_checkpoint": "realisticVisionV51_v51VAE"})))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/sdapi/v1/txt2img"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({"images": [labelled("restarted")]})))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        mock_server
+    });
+
+    let client = StableDiffusionClient::new(&uri);
+    let retry_manager = RetryManager::with_config(1, 10).with_restart_recovery(30);
+    let (result, attempts) = retry_manager
+        .process_with_attempts(&client, &test_image, &config)
+        .await;
+
+    assert_eq!(labels(&result.unwrap().unwrap().images), ["restarted"]);
+    assert_eq!(attempts, 2);
+    restarted.await.unwrap().verify().await;
+
+    // Without waiting, the refused connection is retried like any transient error
+    let unreachable = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+    let client = StableDiffusionClient::new(&format!("http://{}/", unreachable));
+    let (result, attempts) = RetryManager::with_config(3, 10)
+        .with_restart_recovery(0)
+        .process_with_attempts(&client, &test_image, &config)
+        .await;
+    assert!(result.is_err());
+    assert_eq!(attempts, 3);
+}
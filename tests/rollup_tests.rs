@@ -0,0 +1,55 @@
+//! Rollup module tests for urasoe
+
+use std::path::Path;
+
+use urasoe::api::{GeneratedImage, StableDiffusionResponse};
+use urasoe::config::Config;
+use urasoe::file_utils::FileManager;
+use urasoe::rollup::Rollup;
+
+const PNG_BASE64: &str = "iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAQAAAC1HAwCAAAAC0lEQVR42mP8/w8AAgMBApUAAAAASUVORK5CYII=";
+
+fn response(images: usize) -> StableDiffusionResponse {
+    StableDiffusionResponse {
+        images: vec![GeneratedImage::from_base64(PNG_BASE64).unwrap(); images],
+        parameters: None,
+        info: None,
+        digest: None,
+    }
+}
+
+#[test]
+fn test_rollup_counts_generations() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let mut config = Config::load("nonexistent_config.yml").unwrap();
+    config.output_dir = temp_dir.path().to_string_lossy().to_string();
+    config.checkpoint_model = "first".to_string();
+    config.prompt = "karate, dojo".to_string();
+    config.batch_size = 2;
+    FileManager::save_generated_images(&response(2), Path::new("kata.png"), &config).unwrap();
+    FileManager::save_generated_images(&response(2), Path::new("kihon.png"), &config).unwrap();
+    config.checkpoint_model = "second".to_string();
+    config.batch_size = 1;
+    FileManager::save_generated_images(&response(1), Path::new("kumite.png"), &config).unwrap();
+
+    let rollup = Rollup::scan(temp_dir.path()).unwrap();
+    assert_eq!(rollup.total.inputs, 3);
+    assert_eq!(rollup.total.images, 5);
+    assert_eq!(rollup.checkpoints["first"].images, 4);
+    assert_eq!(rollup.checkpoints["second"].inputs, 1);
+    assert_eq!(rollup.prompts["karate, dojo"].inputs, 3);
+    assert_eq!(rollup.dates.values().map(|count| count.inputs).sum::<usize>(), 3);
+    assert!(rollup.disk_usage > rollup.total.bytes);
+
+    let csv_path = temp_dir.path().join("rollup.csv");
+    rollup.write(&csv_path).unwrap();
+    let csv = std::fs::read_to_string(&csv_path).unwrap();
+    assert!(csv.starts_with("group,name,inputs,images,bytes\ntotal,,3,5,"));
+    assert!(csv.contains("\nprompt,\"karate, dojo\",3,5,"));
+    assert!(csv.contains("\ncheckpoint,second,1,1,"));
+}
+
+#[test]
+fn test_rollup_of_missing_directory_fails() {
+    assert!(Rollup::scan(Path::new("nonexistent_output_dir")).is_err());
+}
@@ -0,0 +1,109 @@
+//! Run directory module tests for urasoe
+
+use std::fs;
+use std::path::Path;
+use tempfile::tempdir;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use urasoe::config::Config;
+use urasoe::control::RunControl;
+use urasoe::file_utils::find_metadata;
+use urasoe::metrics::Metrics;
+use urasoe::run_dir::{self, CONFIG_SNAPSHOT_FILE, LOG_FILE, RunManifest};
+use urasoe::runner::run_batch;
+
+const PNG_BASE64: &str = "iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAQAAAC1HAwCAAAAC0lEQVR42mP8/w8AAgMBApUAAAAASUVORK5CYII=";
+
+#[test]
+fn test_prepare_decides_the_run_id_and_directory() {
+    let mut config = Config::load("nonexistent_config.yml").unwrap();
+    config.output_dir = "output".to_string();
+
+    let prepared = run_dir::prepare(&config);
+    let run_id = prepared.run_id.clone().unwrap();
+    assert_eq!(run_id.len(), "20261015T214500Z-3f9a2c1b".len());
+    assert_eq!(prepared.output_dir, "output");
+
+    config.run_dirs = true;
+    config.run_id = Some("kata-1".to_string());
+    let prepared = run_dir::prepare(&config);
+    assert_eq!(prepared.run_id.as_deref(), Some("kata-1"));
+    assert_eq!(Path::new(&prepared.output_dir), Path::new("output/kata-1"));
+    assert!(run_dir::list(Path::new("nonexistent_output")).unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_run_writes_into_its_own_directory() {
+    let temp_dir = tempdir().unwrap();
+    let input_dir = temp_dir.path().join("input");
+    fs::create_dir_all(&input_dir).unwrap();
+    fs::write(input_dir.join("kata.png"), "png").unwrap();
+
+    let backend = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/options"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({})))
+        .mount(&backend)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/sdapi/v1/txt2img"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({"images": [PNG_BASE64]})))
+        .mount(&backend)
+        .await;
+
+    let output_dir = temp_dir.path().join("output");
+    let mut config = Config::load("nonexistent_config.yml").unwrap();
+    config.sd_api_url = format!("{}/", backend.uri());
+    config.input_dir = input_dir.to_string_lossy().to_string();
+    config.output_dir = output_dir.to_string_lossy().to_string();
+    config.batch_break_ms = 0;
+    config.assume_yes = true;
+    config.run_dirs = true;
+
+    for _ in 0..2 {
+        let stats = run_batch(&config, &Metrics::new(), &RunControl::default()).await.unwrap().unwrap();
+        assert_eq!(stats.success_count, 1);
+    }
+
+    let runs = run_dir::list(&output_dir).unwrap();
+    assert_eq!(runs.len(), 2);
+    let (dir, manifest) = &runs[0];
+    assert_eq!(dir.file_name().unwrap().to_string_lossy(), manifest.run_id);
+    assert!(manifest.finished.is_some());
+    assert_eq!((manifest.inputs, manifest.succeeded, manifest.failed, manifest.generated), (1, 1, 0, 1));
+    assert_eq!(&RunManifest::read(dir).unwrap(), manifest);
+
+    let snapshot: Config = serde_yaml::from_str(&fs::read_to_string(dir.join(CONFIG_SNAPSHOT_FILE)).unwrap()).unwrap();
+    assert_eq!(snapshot.run_id.as_ref(), Some(&manifest.run_id));
+    assert!(dir.join(LOG_FILE).is_file());
+
+    let metadata = find_metadata(dir).unwrap();
+    assert_eq!(metadata.len(), 1);
+    assert_eq!(metadata[0].1.run_id.as_ref(), Some(&manifest.run_id));
+    assert_ne!(runs[1].1.run_id, manifest.run_id);
+}
+
+#[test]
+fn test_config_snapshot_leaves_out_credentials() {
+    let temp_dir = tempdir().unwrap();
+    let mut config = Config::load("nonexistent_config.yml").unwrap();
+    config.output_dir = temp_dir.path().join("kata-2").to_string_lossy().to_string();
+    config.run_id = Some("kata-2".to_string());
+    config.api_username = Some("sensei".to_string());
+    config.api_password = Some("api-secret".to_string());
+    config.api_token = Some("token-secret".to_string());
+    config.mqtt.password = Some("broker-secret".to_string());
+
+    RunManifest::start(&config, 1).unwrap();
+    urasoe::logging::set_log_file(None).unwrap();
+
+    let dir = Path::new(&config.output_dir);
+    let content = fs::read_to_string(dir.join(CONFIG_SNAPSHOT_FILE)).unwrap();
+    for secret in ["api-secret", "token-secret", "broker-secret"] {
+        assert!(!content.contains(secret));
+    }
+    let snapshot: Config = serde_yaml::from_str(&content).unwrap();
+    assert_eq!(snapshot.api_username.as_deref(), Some("sensei"));
+    assert_eq!((snapshot.api_password, snapshot.api_token, snapshot.mqtt.password), (None, None, None));
+}
@@ -0,0 +1,412 @@
+//! Runner module tests for urasoe
+
+use std::fs;
+use std::time::Duration;
+use tempfile::tempdir;
+use wiremock::matchers::{body_partial_json, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use urasoe::config::{Args, Config, DeadLetterMode, SeedStrategy};
+use urasoe::control::RunControl;
+use urasoe::file_utils::FileManager;
+use urasoe::metrics::Metrics;
+use urasoe::queue::{JobQueue, JobStatus};
+use urasoe::runner::{RunEstimate, run_batch, run_presets};
+
+const PNG_DATA: [u8; 67] = [
+    137, 80, 78, 71, 13, 10, 26, 10, 0, 0, 0, 13, 73, 72, 68, 82, 0, 0, 0, 1, 0, 0, 0, 1, 8, 6, 0,
+    0, 0, 31, 21, 196, 137, 0, 0, 0, 10, 73, 68, 65, 84, 120, 156, 99, 0, 1, 0, 0, 5, 0, 1, 13, 10,
+    45, 180, 0, 0, 0, 0, 73, 69, 78, 68, 174, 66, 96, 130,
+];
+
+/// Start a mock Stable Diffusion backend answering after the given delay
+async fn mock_backend(delay: Duration) -> MockServer {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/options"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({})))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/sdapi/v1/txt2img"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_delay(delay)
+                .set_body_json(serde_json::json!({
+                    "images": ["iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAQAAAC1HAwCAAAAC0lEQVR42mP8/w8AAgMBApUAAAAASUVORK5CYII="],
+                    "parameters": {},
+                    "info": "{}"
+                })),
+        )
+        .mount(&server)
+        .await;
+    server
+}
+
+#[tokio::test]
+async fn test_run_batch_shares_queue_between_backends() {
+    let temp_dir = tempdir().unwrap();
+    let input_dir = temp_dir.path().join("input");
+    fs::create_dir_all(&input_dir).unwrap();
+    for index in 0..6 {
+        fs::write(input_dir.join(format!("image_{}.png", index)), PNG_DATA).unwrap();
+    }
+
+    let fast = mock_backend(Duration::from_millis(10)).await;
+    let slow = mock_backend(Duration::from_millis(300)).await;
+
+    let mut config = Config::load("nonexistent_file.yml").unwrap();
+    config.sd_api_url = format!("{}/", fast.uri());
+    config.extra_api_urls = vec![format!("{}/", slow.uri())];
+    config.input_dir = input_dir.to_string_lossy().to_string();
+    config.output_dir = temp_dir.path().join("output").to_string_lossy().to_string();
+    config.batch_size = 1;
+    config.batch_break_ms = 0;
+    config.assume_yes = true;
+
+    let stats = run_batch(&config, &Metrics::new(), &RunControl::default()).await.unwrap().unwrap();
+    assert_eq!(stats.success_count, 6);
+    for image in &stats.images {
+        assert_eq!(image.outputs.len(), 1);
+        assert!(std::path::Path::new(&image.outputs[0]).is_file());
+    }
+
+    let generation_requests = |requests: Vec<wiremock::Request>| {
+        requests
+            .iter()
+            .filter(|request| request.url.path() == "/sdapi/v1/txt2img")
+            .count()
+    };
+    let fast_count = generation_requests(fast.received_requests().await.unwrap());
+    let slow_count = generation_requests(slow.received_requests().await.unwrap());
+    assert_eq!(fast_count + slow_count, 6);
+    assert!(slow_count >= 1);
+    assert!(fast_count > slow_count);
+}
+
+#[test]
+fn test_api_urls_without_duplicates() {
+    let mut config = Config::load("nonexistent_file.yml").unwrap();
+    config.sd_api_url = "http://a/".to_string();
+    config.extra_api_urls = vec!["http://b/".to_string(), "http://a/".to_string()];
+    assert_eq!(config.api_urls(), vec!["http://a/", "http://b/"]);
+}
+
+#[test]
+fn test_run_estimate_from_sample() {
+    // 9 inputs left after the sample, split over two backends: 5 rounds of 30s + 10s break
+    let estimate = RunEstimate::from_sample(30_000, 2_000_000, 9, 10, 2, 10_000);
+    assert_eq!(estimate.duration, Duration::from_secs(200));
+    assert_eq!(estimate.output_bytes, 20_000_000);
+    assert!(estimate.finish_at() > chrono::Local::now());
+}
+
+#[tokio::test]
+async fn test_run_presets_groups_by_checkpoint() {
+    let temp_dir = tempdir().unwrap();
+    let input_dir = temp_dir.path().join("input");
+    fs::create_dir_all(&input_dir).unwrap();
+    fs::write(input_dir.join("image.png"), PNG_DATA).unwrap();
+
+    let presets = [("a.yml", "first"), ("b.yml", "second"), ("c.yml", "first")];
+    for (file, checkpoint) in presets {
+        fs::write(temp_dir.path().join(file), format!("checkpoint_model: {}\n", checkpoint)).unwrap();
+    }
+
+    let backend = mock_backend(Duration::from_millis(0)).await;
+    let mut config = Config::load("nonexistent_file.yml").unwrap();
+    config.sd_api_url = format!("{}/", backend.uri());
+    config.input_dir = input_dir.to_string_lossy().to_string();
+    config.output_dir = temp_dir.path().join("output").to_string_lossy().to_string();
+    config.batch_size = 1;
+    config.batch_break_ms = 0;
+    config.assume_yes = true;
+    config.presets = presets
+        .iter()
+        .map(|(file, _)| temp_dir.path().join(file).to_string_lossy().to_string())
+        .collect();
+
+    let runs = run_presets(&config, &Args::default(), &Metrics::new(), &RunControl::default()).await.unwrap();
+    let order: Vec<&str> = runs.iter().map(|run| run.name.as_str()).collect();
+    assert_eq!(order, ["a", "c", "b"]);
+    for run in &runs {
+        assert_eq!(run.stats.as_ref().unwrap().success_count, 1);
+        assert!(temp_dir.path().join("output").join(&run.name).join("image").is_dir());
+    }
+}
+
+#[tokio::test]
+async fn test_resume_skips_inputs_finished_earlier() {
+    let temp_dir = tempdir().unwrap();
+    let input_dir = temp_dir.path().join("input");
+    fs::create_dir_all(&input_dir).unwrap();
+    for name in ["a.png", "b.png"] {
+        fs::write(input_dir.join(name), PNG_DATA).unwrap();
+    }
+
+    let backend = mock_backend(Duration::from_millis(0)).await;
+    let mut config = Config::load("nonexistent_file.yml").unwrap();
+    config.sd_api_url = format!("{}/", backend.uri());
+    config.input_dir = input_dir.to_string_lossy().to_string();
+    config.output_dir = temp_dir.path().join("output").to_string_lossy().to_string();
+    config.batch_size = 1;
+    config.batch_break_ms = 0;
+    config.assume_yes = true;
+    config.resume = true;
+    {
+        let mut queue = JobQueue::create(config.queue_path()).unwrap();
+        queue.enqueue(input_dir.join("a.png")).unwrap();
+        queue.mark_done(input_dir.join("a.png")).unwrap();
+    }
+
+    let stats = run_batch(&config, &Metrics::new(), &RunControl::default()).await.unwrap().unwrap();
+    assert_eq!(stats.success_count, 1);
+    assert!(stats.images[0].path.ends_with("b.png"));
+}
+
+#[tokio::test]
+async fn test_interrupted_run_resumes_unless_forced() {
+    let temp_dir = tempdir().unwrap();
+    let input_dir = temp_dir.path().join("input");
+    fs::create_dir_all(&input_dir).unwrap();
+    for name in ["a.png", "b.png", "c.png"] {
+        fs::write(input_dir.join(name), PNG_DATA).unwrap();
+    }
+
+    let backend = mock_backend(Duration::from_millis(0)).await;
+    let mut config = Config::load("nonexistent_file.yml").unwrap();
+    config.sd_api_url = format!("{}/", backend.uri());
+    config.input_dir = input_dir.to_string_lossy().to_string();
+    config.output_dir = temp_dir.path().join("output").to_string_lossy().to_string();
+    config.batch_size = 1;
+    config.batch_break_ms = 0;
+    config.assume_yes = true;
+    // The previous run finished a.png, failed b.png and stopped while generating c.png
+    let interrupt = || {
+        let mut queue = JobQueue::create(config.queue_path()).unwrap();
+        queue.enqueue_all(&[input_dir.join("a.png"), input_dir.join("b.png"), input_dir.join("c.png")]).unwrap();
+        queue.mark_done(input_dir.join("a.png")).unwrap();
+        queue.mark_failed(input_dir.join("b.png")).unwrap();
+        queue.next_pending().unwrap();
+    };
+
+    interrupt();
+    let stats = run_batch(&config, &Metrics::new(), &RunControl::default()).await.unwrap().unwrap();
+    let mut processed: Vec<_> = stats.images.iter().map(|image| image.path.clone()).collect();
+    processed.sort();
+    assert_eq!(processed.len(), 2);
+    assert!(processed[0].ends_with("b.png"));
+    assert!(processed[1].ends_with("c.png"));
+
+    interrupt();
+    config.force = true;
+    let stats = run_batch(&config, &Metrics::new(), &RunControl::default()).await.unwrap().unwrap();
+    assert_eq!(stats.success_count, 3);
+}
+
+#[tokio::test]
+async fn test_run_with_failures_retries_only_the_failed_inputs() {
+    let temp_dir = tempdir().unwrap();
+    let input_dir = temp_dir.path().join("input");
+    fs::create_dir_all(&input_dir).unwrap();
+    for name in ["a.png", "b.png"] {
+        fs::write(input_dir.join(name), PNG_DATA).unwrap();
+    }
+
+    let backend = mock_backend(Duration::from_millis(0)).await;
+    let mut config = Config::load("nonexistent_file.yml").unwrap();
+    config.sd_api_url = format!("{}/", backend.uri());
+    config.input_dir = input_dir.to_string_lossy().to_string();
+    config.output_dir = temp_dir.path().join("output").to_string_lossy().to_string();
+    config.batch_size = 1;
+    config.batch_break_ms = 0;
+    config.assume_yes = true;
+    // The previous run went through every input and failed b.png
+    let finish_with_failure = || {
+        let mut queue = JobQueue::create(config.queue_path()).unwrap();
+        queue.enqueue_all(&[input_dir.join("a.png"), input_dir.join("b.png")]).unwrap();
+        queue.mark_done(input_dir.join("a.png")).unwrap();
+        queue.mark_failed(input_dir.join("b.png")).unwrap();
+    };
+
+    finish_with_failure();
+    let stats = run_batch(&config, &Metrics::new(), &RunControl::default()).await.unwrap().unwrap();
+    assert_eq!(stats.success_count, 1);
+    assert!(stats.images[0].path.ends_with("b.png"));
+    assert_eq!(JobQueue::open(config.queue_path()).unwrap().count(JobStatus::Done), 2);
+
+    finish_with_failure();
+    config.force = true;
+    let stats = run_batch(&config, &Metrics::new(), &RunControl::default()).await.unwrap().unwrap();
+    assert_eq!(stats.success_count, 2);
+}
+
+#[tokio::test]
+async fn test_run_after_dead_letter_move_does_not_retry_the_moved_input() {
+    let temp_dir = tempdir().unwrap();
+    let input_dir = temp_dir.path().join("input");
+    fs::create_dir_all(&input_dir).unwrap();
+    for name in ["a.png", "b.png"] {
+        fs::write(input_dir.join(name), PNG_DATA).unwrap();
+    }
+
+    let backend = mock_backend(Duration::from_millis(0)).await;
+    let mut config = Config::load("nonexistent_file.yml").unwrap();
+    config.sd_api_url = format!("{}/", backend.uri());
+    config.input_dir = input_dir.to_string_lossy().to_string();
+    config.output_dir = temp_dir.path().join("output").to_string_lossy().to_string();
+    config.batch_size = 1;
+    config.batch_break_ms = 0;
+    config.assume_yes = true;
+    config.dead_letter = DeadLetterMode::Move;
+    // The previous run finished a.png and moved the failed b.png into the dead-letter folder
+    {
+        let mut queue = JobQueue::create(config.queue_path()).unwrap();
+        queue.enqueue_all(&[input_dir.join("a.png"), input_dir.join("b.png")]).unwrap();
+        queue.mark_done(input_dir.join("a.png")).unwrap();
+        queue.mark_failed(input_dir.join("b.png")).unwrap();
+        FileManager::dead_letter(&input_dir.join("b.png"), "CUDA out of memory", 3, &config).unwrap();
+    }
+    assert!(!JobQueue::is_unfinished(config.queue_path()).unwrap());
+
+    let stats = run_batch(&config, &Metrics::new(), &RunControl::default()).await.unwrap().unwrap();
+    assert_eq!(stats.success_count, 1);
+    assert!(stats.failed_paths.is_empty());
+    let failed_dir = temp_dir.path().join("output").join("_failed");
+    let log = fs::read_to_string(failed_dir.join("b.png.error.log")).unwrap();
+    assert!(log.contains("error: CUDA out of memory"));
+    assert_eq!(fs::read(failed_dir.join("b.png")).unwrap(), PNG_DATA);
+}
+
+#[tokio::test]
+async fn test_abort_leaves_the_input_being_generated_pending() {
+    let temp_dir = tempdir().unwrap();
+    let input_dir = temp_dir.path().join("input");
+    fs::create_dir_all(&input_dir).unwrap();
+    fs::write(input_dir.join("a.png"), PNG_DATA).unwrap();
+
+    let backend = mock_backend(Duration::from_secs(5)).await;
+    let mut config = Config::load("nonexistent_file.yml").unwrap();
+    config.sd_api_url = format!("{}/", backend.uri());
+    config.input_dir = input_dir.to_string_lossy().to_string();
+    config.output_dir = temp_dir.path().join("output").to_string_lossy().to_string();
+    config.batch_size = 1;
+    config.batch_break_ms = 0;
+    config.assume_yes = true;
+
+    let control = RunControl::default();
+    let abort = async {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        control.abort();
+    };
+    let metrics = Metrics::new();
+    let (stats, _) = tokio::join!(run_batch(&config, &metrics, &control), abort);
+
+    let stats = stats.unwrap().unwrap();
+    assert!(stats.failed_paths.is_empty());
+    let queue = JobQueue::open(config.queue_path()).unwrap();
+    assert_eq!(queue.status(input_dir.join("a.png")), Some(JobStatus::Pending));
+}
+
+#[tokio::test]
+async fn test_seed_derived_from_input_is_sent() {
+    let temp_dir = tempdir().unwrap();
+    let input_dir = temp_dir.path().join("input");
+    fs::create_dir_all(&input_dir).unwrap();
+    let input = input_dir.join("kata.png");
+    fs::write(&input, PNG_DATA).unwrap();
+    let seed = SeedStrategy::FromInputHash.seed_for(-1, &input, "dojo").unwrap();
+
+    let backend = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/options"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({})))
+        .mount(&backend)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/sdapi/v1/txt2img"))
+        .and(body_partial_json(serde_json::json!({"seed": seed})))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "images": ["iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAQAAAC1HAwCAAAAC0lEQVR42mP8/w8AAgMBApUAAAAASUVORK5CYII="]
+        })))
+        .expect(1)
+        .mount(&backend)
+        .await;
+
+    let mut config = Config::load("nonexistent_file.yml").unwrap();
+    config.sd_api_url = format!("{}/", backend.uri());
+    config.input_dir = input_dir.to_string_lossy().to_string();
+    config.output_dir = temp_dir.path().join("output").to_string_lossy().to_string();
+    config.batch_break_ms = 0;
+    config.assume_yes = true;
+    config.seed_strategy = SeedStrategy::FromInputHash;
+    config.seed_salt = "dojo".to_string();
+
+    let stats = run_batch(&config, &Metrics::new(), &RunControl::default()).await.unwrap().unwrap();
+    assert_eq!(stats.success_count, 1);
+}
+
+#[tokio::test]
+async fn test_batch_size_ramps_up_on_each_backend() {
+    let temp_dir = tempdir().unwrap();
+    let input_dir = temp_dir.path().join("input");
+    fs::create_dir_all(&input_dir).unwrap();
+    for index in 0..4 {
+        fs::write(input_dir.join(format!("image_{}.png", index)), PNG_DATA).unwrap();
+    }
+    let backend = mock_backend(Duration::from_millis(0)).await;
+
+    let mut config = Config::load("nonexistent_file.yml").unwrap();
+    config.sd_api_url = format!("{}/", backend.uri());
+    config.input_dir = input_dir.to_string_lossy().to_string();
+    config.output_dir = temp_dir.path().join("output").to_string_lossy().to_string();
+    config.batch_size = 4;
+    config.batch_break_ms = 0;
+    config.assume_yes = true;
+    config.ramp.enabled = true;
+    config.ramp.images_per_step = 1;
+    // Instant responses make the time per image noise, which must not stop the ramp
+    config.ramp.max_latency_factor = 1000.0;
+
+    let stats = run_batch(&config, &Metrics::new(), &RunControl::default()).await.unwrap().unwrap();
+    assert_eq!(stats.success_count, 4);
+    let batch_sizes: Vec<u64> = backend
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .filter(|request| request.url.path() == "/sdapi/v1/txt2img")
+        .map(|request| request.body_json::<serde_json::Value>().unwrap()["batch_size"].as_u64().unwrap())
+        .collect();
+    assert_eq!(batch_sizes, [1, 2, 4, 4]);
+}
+
+#[tokio::test]
+async fn test_concurrency_keeps_several_inputs_in_flight() {
+    let temp_dir = tempdir().unwrap();
+    let input_dir = temp_dir.path().join("input");
+    fs::create_dir_all(&input_dir).unwrap();
+    for index in 0..6 {
+        fs::write(input_dir.join(format!("image_{}.png", index)), PNG_DATA).unwrap();
+    }
+    let backend = mock_backend(Duration::from_millis(400)).await;
+
+    let mut config = Config::load("nonexistent_file.yml").unwrap();
+    config.sd_api_url = format!("{}/", backend.uri());
+    config.input_dir = input_dir.to_string_lossy().to_string();
+    config.output_dir = temp_dir.path().join("output").to_string_lossy().to_string();
+    config.batch_size = 1;
+    config.batch_break_ms = 0;
+    config.estimate = false;
+    config.concurrency = 3;
+
+    let started = std::time::Instant::now();
+    let stats = run_batch(&config, &Metrics::new(), &RunControl::default()).await.unwrap().unwrap();
+    assert_eq!(stats.success_count, 6);
+    // Six inputs of 400 ms each take 2.4 s one after another
+    assert!(started.elapsed() < Duration::from_millis(1800), "{:?}", started.elapsed());
+
+    // The workers of a backend share its loaded checkpoint
+    let requests = backend.received_requests().await.unwrap();
+    assert_eq!(requests.iter().filter(|request| request.url.path() == "/options").count(), 1);
+}
@@ -0,0 +1,54 @@
+//! Schedule module tests for urasoe
+
+use chrono::NaiveTime;
+use std::time::Duration;
+use urasoe::schedule::{ScheduleConfig, TimeWindow};
+
+fn time(hour: u32, minute: u32) -> NaiveTime {
+    NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+}
+
+#[test]
+fn test_time_window_parse_and_display() {
+    let window: TimeWindow = "22:00-07:00".parse().unwrap();
+    assert_eq!(window.start, time(22, 0));
+    assert_eq!(window.end, time(7, 0));
+    assert_eq!(window.to_string(), "22:00-07:00");
+
+    assert!("22:00".parse::<TimeWindow>().is_err());
+    assert!("25:00-07:00".parse::<TimeWindow>().is_err());
+}
+
+#[test]
+fn test_time_window_wrapping_midnight() {
+    let window: TimeWindow = "22:00-07:00".parse().unwrap();
+    assert!(window.contains(time(23, 30)));
+    assert!(window.contains(time(3, 0)));
+    assert!(!window.contains(time(7, 0)));
+    assert!(!window.contains(time(12, 0)));
+
+    assert_eq!(window.time_until_open(time(2, 0)), None);
+    assert_eq!(
+        window.time_until_open(time(20, 30)),
+        Some(Duration::from_secs(90 * 60))
+    );
+}
+
+#[test]
+fn test_time_window_same_day() {
+    let window: TimeWindow = "09:00-17:00".parse().unwrap();
+    assert!(window.contains(time(9, 0)));
+    assert!(!window.contains(time(17, 0)));
+    assert_eq!(
+        window.time_until_open(time(18, 0)),
+        Some(Duration::from_secs(15 * 60 * 60))
+    );
+}
+
+#[test]
+fn test_schedule_config_from_yaml() {
+    let config: ScheduleConfig = serde_yaml::from_str("allowed_hours: \"22:00-07:00\"").unwrap();
+    assert_eq!(config.allowed_hours.unwrap().to_string(), "22:00-07:00");
+
+    assert!(serde_yaml::from_str::<ScheduleConfig>("allowed_hours: \"later\"").is_err());
+}
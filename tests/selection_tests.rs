@@ -0,0 +1,88 @@
+//! Best-of-N selection tests for urasoe
+
+use image::{GrayImage, Luma};
+use serde_json::json;
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use urasoe::file_utils::SavedImages;
+use urasoe::selection::{ScorerConfig, SelectionConfig, laplacian_variance, select};
+
+/// PNG of a checkerboard with squares of the given size, smaller squares having more edges
+fn checkerboard(square: u32) -> Vec<u8> {
+    let image = GrayImage::from_fn(16, 16, |x, y| Luma([if (x / square + y / square).is_multiple_of(2) { 0 } else { 255 }]));
+    let mut png = Vec::new();
+    image.write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png).unwrap();
+    png
+}
+
+fn save(dir: &Path, images: &[Vec<u8>]) -> SavedImages {
+    let paths: Vec<PathBuf> = images
+        .iter()
+        .enumerate()
+        .map(|(index, image)| {
+            let path = dir.join(format!("kata-{}.png", index + 1));
+            fs::write(&path, image).unwrap();
+            path
+        })
+        .collect();
+    SavedImages {
+        paths,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_laplacian_variance_prefers_sharp_images() {
+    let flat = checkerboard(16);
+    assert_eq!(laplacian_variance(&flat).unwrap(), 0.0);
+    assert!(laplacian_variance(&checkerboard(1)).unwrap() > laplacian_variance(&checkerboard(4)).unwrap());
+    assert!(laplacian_variance(b"not an image").is_err());
+}
+
+#[tokio::test]
+async fn test_select_keeps_the_sharpest_images() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let saved = save(temp_dir.path(), &[checkerboard(8), checkerboard(1), checkerboard(4)]);
+    let config: SelectionConfig = serde_yaml::from_str("scorer:\n  type: laplacian\nkeep: 2\n").unwrap();
+
+    let selected = select(&config, saved).await.unwrap();
+    assert_eq!(selected.paths, [temp_dir.path().join("kata-2.png"), temp_dir.path().join("kata-3.png")]);
+    assert_eq!(selected.rejected, [temp_dir.path().join("rejected").join("kata-1.png")]);
+    assert!(selected.rejected[0].is_file());
+    assert!(!temp_dir.path().join("kata-1.png").exists());
+
+    // Without a scorer every image is kept
+    let saved = save(temp_dir.path(), &[checkerboard(8), checkerboard(1)]);
+    assert_eq!(select(&SelectionConfig::default(), saved.clone()).await.unwrap(), saved);
+}
+
+#[tokio::test]
+async fn test_select_with_endpoint_where_lower_is_better() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/score"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({"score": 42.5})))
+        .mount(&server)
+        .await;
+
+    let scorer = ScorerConfig::Endpoint {
+        url: format!("{}/score", server.uri()),
+        lower_is_better: true,
+    };
+    assert_eq!(scorer.score(b"png").await.unwrap(), -42.5);
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let saved = save(temp_dir.path(), &[b"one".to_vec(), b"two".to_vec()]);
+    let config = SelectionConfig {
+        scorer: Some(scorer),
+        keep: 1,
+        ..Default::default()
+    };
+    // Equal scores keep the first generated image
+    let selected = select(&config, saved).await.unwrap();
+    assert_eq!(selected.paths, [temp_dir.path().join("kata-1.png")]);
+}
@@ -0,0 +1,53 @@
+//! Video sequence tests for urasoe
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use urasoe::config::Config;
+use urasoe::sequence::{FRAMES_DIR, SequenceConfig, frame_number, order_frames};
+
+#[test]
+fn test_frame_numbers_order_the_frames() {
+    assert_eq!(frame_number(Path::new("clip/frame_0012.png")), Some(12));
+    assert_eq!(frame_number(Path::new("clip/7.png")), Some(7));
+    assert_eq!(frame_number(Path::new("clip/poster.png")), None);
+
+    let frames = ["frame_10.png", "poster.png", "frame_2.png", "frame_1.png"].map(PathBuf::from).to_vec();
+    assert_eq!(
+        order_frames(frames),
+        ["frame_1.png", "frame_2.png", "frame_10.png", "poster.png"].map(PathBuf::from)
+    );
+}
+
+#[test]
+fn test_prepare_fixes_seed_and_order() {
+    let mut config = Config::load("nonexistent_config.yml").unwrap();
+    config.seed = -1;
+    config.shuffle = true;
+    let prepared = SequenceConfig::default().prepare(&config);
+    assert!(prepared.seed >= 0);
+    assert!(!prepared.shuffle);
+
+    config.seed = 1234;
+    assert_eq!(SequenceConfig::default().prepare(&config).seed, 1234);
+}
+
+#[tokio::test]
+async fn test_assemble_gathers_first_image_of_each_frame() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let output_dir = temp_dir.path();
+    for (stem, images) in [("frame_1", vec![2, 3]), ("frame_2", vec![1])] {
+        fs::create_dir_all(output_dir.join(stem)).unwrap();
+        for index in images {
+            fs::write(output_dir.join(stem).join(format!("{}-{}.png", stem, index)), format!("{}-{}", stem, index)).unwrap();
+        }
+    }
+    let frames = ["in/frame_1.png", "in/frame_2.png", "in/frame_3.png"].map(PathBuf::from);
+
+    let video = SequenceConfig::default().assemble(&frames, output_dir).await.unwrap();
+    assert_eq!(video, None);
+    let frames_dir = output_dir.join(FRAMES_DIR);
+    assert_eq!(fs::read_to_string(frames_dir.join("frame_1.png")).unwrap(), "frame_1-2");
+    assert_eq!(fs::read_to_string(frames_dir.join("frame_2.png")).unwrap(), "frame_2-1");
+    assert!(!frames_dir.join("frame_3.png").exists());
+}
@@ -0,0 +1,108 @@
+//! Server-side saved images module tests for urasoe
+
+use serde_json::json;
+use std::fs;
+use wiremock::matchers::{body_partial_json, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use urasoe::api::{StableDiffusionClient, StableDiffusionResponse};
+use urasoe::config::Config;
+use urasoe::digest::sha256_hex;
+use urasoe::server_files::{ServerFile, ServerFilesConfig};
+
+#[test]
+fn test_saved_images_are_read_from_the_generation_info() {
+    let config = ServerFilesConfig {
+        enabled: true,
+        ..Default::default()
+    };
+    let mut payload = json!({"prompt": "kata"});
+    config.apply_to_payload(&mut payload);
+    assert_eq!(payload, json!({"prompt": "kata", "save_images": true, "send_images": false}));
+
+    let response = StableDiffusionResponse {
+        images: Vec::new(),
+        parameters: None,
+        info: Some(json!({"image_paths": ["out/a.png", "out/b.png"], "image_sha256": ["ABC"]}).to_string()),
+        digest: None,
+    };
+    assert_eq!(
+        config.files(&response).unwrap(),
+        [
+            ServerFile {
+                path: "out/a.png".to_string(),
+                sha256: Some("abc".to_string()),
+            },
+            ServerFile {
+                path: "out/b.png".to_string(),
+                sha256: None,
+            }
+        ]
+    );
+    let unreported = StableDiffusionResponse {
+        info: Some("{}".to_string()),
+        ..response
+    };
+    assert!(config.files(&unreported).is_err());
+
+    let file = ServerFile {
+        path: "out/a.png".to_string(),
+        sha256: Some(sha256_hex(b"kata")),
+    };
+    assert!(file.verify(b"kata").is_ok());
+    assert!(file.verify(b"kumite").is_err());
+}
+
+#[tokio::test]
+async fn test_saved_images_are_downloaded_and_verified() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let input = temp_dir.path().join("kata.png");
+    fs::write(&input, "input").unwrap();
+
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/sdapi/v1/txt2img"))
+        .and(body_partial_json(json!({"save_images": true, "send_images": false})))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "images": [],
+            "info": json!({
+                "seed": 7,
+                "image_paths": ["outputs/kata-1.png", "outputs/kata-2.png"],
+                "image_sha256": [sha256_hex(b"first"), sha256_hex(b"second")]
+            }).to_string()
+        })))
+        .mount(&server)
+        .await;
+    for (name, contents) in [("kata-1", "first"), ("kata-2", "corrupt")] {
+        Mock::given(method("GET"))
+            .and(path(format!("/file=outputs/{}.png", name)))
+            .respond_with(ResponseTemplate::new(200).set_body_string(contents))
+            .mount(&server)
+            .await;
+    }
+
+    let mut config = Config::load("nonexistent_config.yml").unwrap();
+    config.server_files.enabled = true;
+    let client = StableDiffusionClient::new(&format!("{}/", server.uri()));
+    let error = client.generate_with_controlnet(&input, &config).await.unwrap_err();
+    assert!(format!("{:#}", error).contains("Checksum mismatch for outputs/kata-2.png"));
+
+    server.reset().await;
+    Mock::given(method("POST"))
+        .and(path("/sdapi/v1/txt2img"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "images": [],
+            "info": json!({"image_paths": ["outputs/kata-1.png"]}).to_string()
+        })))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/file=outputs/kata-1.png"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("first"))
+        .expect(1)
+        .mount(&server)
+        .await;
+    let result = client.generate_with_controlnet(&input, &config).await.unwrap().unwrap();
+    assert_eq!(result.images.len(), 1);
+    assert_eq!(result.images[0].bytes().unwrap(), b"first");
+}
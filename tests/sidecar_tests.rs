@@ -0,0 +1,71 @@
+//! Sidecar module tests for urasoe
+
+use std::fs;
+use urasoe::processing::RetryOverrides;
+use urasoe::sidecar::Sidecar;
+
+#[test]
+fn test_sidecar_missing_gives_defaults() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let image = temp_dir.path().join("photo.png");
+
+    assert_eq!(Sidecar::path_for(&image), None);
+    assert_eq!(Sidecar::load_for(&image).unwrap(), Sidecar::default());
+}
+
+#[test]
+fn test_sidecar_retry_overrides() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let image = temp_dir.path().join("photo.png");
+    fs::write(temp_dir.path().join("photo.yaml"), "max_retries: 6\nretry_delay_ms: 30000\n").unwrap();
+
+    let sidecar = Sidecar::load_for(&image).unwrap();
+    assert_eq!(
+        sidecar.retry,
+        RetryOverrides {
+            max_retries: Some(6),
+            retry_delay_ms: Some(30000),
+        }
+    );
+}
+
+#[test]
+fn test_sidecar_empty_and_invalid_files() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let image = temp_dir.path().join("photo.png");
+
+    fs::write(temp_dir.path().join("photo.yml"), "").unwrap();
+    assert_eq!(Sidecar::load_for(&image).unwrap(), Sidecar::default());
+
+    fs::write(temp_dir.path().join("photo.yml"), "max_retries: many\n").unwrap();
+    assert!(Sidecar::load_for(&image).is_err());
+}
+
+#[test]
+fn test_sidecar_directory_defaults_and_priority() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let image = temp_dir.path().join("photo.png");
+    let other = temp_dir.path().join("other.png");
+    fs::write(temp_dir.path().join(".urasoe.yml"), "priority: 5\nmax_retries: 2\n").unwrap();
+    fs::write(temp_dir.path().join("photo.yml"), "priority: 9\n").unwrap();
+
+    let sidecar = Sidecar::load_for(&image).unwrap();
+    assert_eq!(sidecar.priority, Some(9));
+    assert_eq!(sidecar.retry.max_retries, Some(2));
+    assert_eq!(Sidecar::priority_for(&other).unwrap(), 5);
+
+    fs::remove_file(temp_dir.path().join(".urasoe.yml")).unwrap();
+    assert_eq!(Sidecar::priority_for(&other).unwrap(), 0);
+}
+
+#[test]
+fn test_sidecar_prompts_override_directory() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let image = temp_dir.path().join("photo.png");
+    fs::write(temp_dir.path().join(".urasoe.yml"), "prompt: dojo\nnegative_prompt: text\n").unwrap();
+    fs::write(temp_dir.path().join("photo.yml"), "prompt: kumite\n").unwrap();
+
+    let sidecar = Sidecar::load_for(&image).unwrap();
+    assert_eq!(sidecar.prompt.as_deref(), Some("kumite"));
+    assert_eq!(sidecar.negative_prompt.as_deref(), Some("text"));
+}
@@ -0,0 +1,165 @@
+//! Output sink tests for urasoe
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use urasoe::api::{GeneratedImage, StableDiffusionResponse};
+use urasoe::config::{Config, OutputNaming};
+use urasoe::file_utils::FileManager;
+use urasoe::pipeline::Pipeline;
+use urasoe::processing::ProcessingStats;
+use urasoe::sink::{FileSystemSink, MemorySink, OutputSink};
+
+const PNG_BASE64: &str = "iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAQAAAC1HAwCAAAAC0lEQVR42mP8/w8AAgMBApUAAAAASUVORK5CYII=";
+
+fn response(images: usize) -> StableDiffusionResponse {
+    StableDiffusionResponse {
+        images: vec![GeneratedImage::from_base64(PNG_BASE64).unwrap(); images],
+        parameters: None,
+        info: None,
+        digest: None,
+    }
+}
+
+#[test]
+fn test_save_to_memory_sink() {
+    let sink = MemorySink::new();
+    let mut config = Config::load("nonexistent_config.yml").unwrap();
+    config.prompt = "kihon".to_string();
+
+    let saved = FileManager::save_to_sink(&sink, &response(2), Path::new("input/kata.png"), &config).unwrap();
+    assert_eq!(saved.paths, [PathBuf::from("kata/kata-1.png"), PathBuf::from("kata/kata-2.png")]);
+    assert_eq!(saved.metadata_path, Some(PathBuf::from("input/kata.png")));
+
+    let images = sink.images();
+    assert_eq!(images.len(), 2);
+    assert_eq!(&images[Path::new("kata/kata-1.png")][1..4], b"PNG");
+
+    let metadata = sink.metadata();
+    let metadata = serde_json::to_value(&metadata[Path::new("input/kata.png")]).unwrap();
+    assert_eq!(metadata["prompt"], "kihon");
+    assert_eq!(metadata["source_image"], "input/kata.png");
+
+    let parameters = sink.parameters();
+    assert_eq!(parameters.len(), 2);
+    assert!(parameters[Path::new("kata/kata-2-parameters.txt")].starts_with("kihon\n"));
+}
+
+#[test]
+fn test_inputs_of_the_same_name_are_saved_apart() {
+    let sink = MemorySink::new();
+    let mut config = Config::load("nonexistent_config.yml").unwrap();
+    config.input_dir = "input".to_string();
+    config.output_naming = OutputNaming::RelativePath;
+
+    for folder in ["2023", "2024"] {
+        FileManager::save_to_sink(&sink, &response(1), &Path::new("input").join(folder).join("kata.png"), &config)
+            .unwrap();
+    }
+    assert_eq!(
+        sink.images().keys().collect::<Vec<_>>(),
+        [&PathBuf::from("2023_kata/2023_kata-1.png"), &PathBuf::from("2024_kata/2024_kata-1.png")]
+    );
+    // The metadata still names the input itself
+    let metadata = sink.metadata();
+    let metadata = serde_json::to_value(&metadata[&Path::new("input").join("2024").join("2024_kata.png")]).unwrap();
+    assert_eq!(metadata["source_image"], Path::new("input").join("2024").join("kata.png").to_string_lossy().as_ref());
+}
+
+#[test]
+fn test_parameters_reported_by_the_server_are_used() {
+    let sink = MemorySink::new();
+    let mut config = Config::load("nonexistent_config.yml").unwrap();
+    let mut result = response(2);
+    result.info = Some(r#"{"seed": 5, "infotexts": ["kihon\nSteps: 20, Seed: 5", "kihon\nSteps: 20, Seed: 6"]}"#.to_string());
+
+    FileManager::save_to_sink(&sink, &result, Path::new("kata.png"), &config).unwrap();
+    assert_eq!(sink.parameters()[Path::new("kata/kata-2-parameters.txt")], "kihon\nSteps: 20, Seed: 6");
+
+    let sink = MemorySink::new();
+    config.parameters_files = false;
+    FileManager::save_to_sink(&sink, &result, Path::new("kata.png"), &config).unwrap();
+    assert!(sink.parameters().is_empty());
+}
+
+#[test]
+fn test_save_nothing_stores_nothing() {
+    let sink = MemorySink::new();
+    let config = Config::load("nonexistent_config.yml").unwrap();
+    let saved = FileManager::save_to_sink(&sink, &response(0), Path::new("kata.png"), &config).unwrap();
+    assert!(saved.paths.is_empty());
+    assert_eq!(saved.metadata_path, None);
+    assert!(sink.metadata().is_empty());
+}
+
+#[test]
+fn test_file_system_sink_layout() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let sink = FileSystemSink::new(temp_dir.path());
+    let config = Config::load("nonexistent_config.yml").unwrap();
+
+    let saved = FileManager::save_to_sink(&sink, &response(1), Path::new("kata.png"), &config).unwrap();
+    assert_eq!(saved.paths, [temp_dir.path().join("kata").join("kata-1.png")]);
+    assert!(saved.paths[0].is_file());
+    assert_eq!(saved.bytes, std::fs::metadata(&saved.paths[0]).unwrap().len());
+    assert_eq!(saved.metadata_path, Some(temp_dir.path().join("kata").join("kata-metadata.json")));
+    assert!(temp_dir.path().join("kata").join("kata-1-parameters.txt").is_file());
+    assert!(saved.metadata_path.unwrap().is_file());
+    // Finalizing is optional for sinks
+    sink.finalize_run(&ProcessingStats::new()).unwrap();
+}
+
+#[test]
+fn test_recursive_run_mirrors_input_folders() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let mut config = Config::load("nonexistent_config.yml").unwrap();
+    config.input_dir = "input".to_string();
+    config.output_dir = temp_dir.path().to_string_lossy().to_string();
+    config.recursive = true;
+    let input = Path::new("input").join("2024").join("dojo").join("kata.png");
+
+    let saved = FileManager::save_generated_images(&response(1), &input, &config).unwrap();
+    let folder = temp_dir.path().join("2024").join("dojo").join("kata");
+    assert_eq!(saved.paths, [folder.join("kata-1.png")]);
+    assert_eq!(config.output_folder(&input), folder);
+    assert_eq!(config.output_folder(Path::new("input/kata.png")), temp_dir.path().join("kata"));
+}
+
+#[tokio::test]
+async fn test_pipeline_stores_in_sink() {
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/options"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({})))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/sdapi/v1/txt2img"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({"images": [PNG_BASE64]})))
+        .mount(&server)
+        .await;
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let input_dir = temp_dir.path().join("input");
+    std::fs::create_dir_all(&input_dir).unwrap();
+    std::fs::write(input_dir.join("kata.png"), "png").unwrap();
+    let mut config = Config::load("nonexistent_config.yml").unwrap();
+    config.sd_api_url = format!("{}/", server.uri());
+    config.input_dir = input_dir.to_string_lossy().to_string();
+    config.output_dir = temp_dir.path().join("output").to_string_lossy().to_string();
+    config.batch_break_ms = 0;
+    config.assume_yes = true;
+
+    let sink = Arc::new(MemorySink::new());
+    let (_events, handle) = Pipeline::new(config).with_sink(sink.clone()).start();
+    let stats = handle.await.unwrap().unwrap().unwrap();
+
+    assert_eq!(stats.success_count, 1);
+    assert_eq!(stats.images[0].outputs, ["kata/kata-1.png"]);
+    assert!(sink.images().contains_key(Path::new("kata/kata-1.png")));
+    assert!(!temp_dir.path().join("output").join("kata").exists());
+    assert_eq!(sink.finished_runs().len(), 1);
+}
@@ -0,0 +1,69 @@
+//! Style reference module tests for urasoe
+
+use base64::{Engine, prelude::BASE64_STANDARD};
+use serde_json::json;
+use std::fs;
+use std::path::PathBuf;
+use wiremock::matchers::{body_partial_json, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use urasoe::api::StableDiffusionClient;
+use urasoe::config::Config;
+use urasoe::style_reference::StyleReferenceConfig;
+
+#[test]
+fn test_style_image_lookup_order() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let inputs = temp_dir.path().join("input");
+    let styles = temp_dir.path().join("styles");
+    fs::create_dir_all(&inputs).unwrap();
+    fs::create_dir_all(&styles).unwrap();
+    fs::write(styles.join("kata.jpg"), "style").unwrap();
+    fs::write(inputs.join("kumite.yml"), "style: ../styles/ink.png\n").unwrap();
+
+    let config = StyleReferenceConfig {
+        dir: Some(styles.to_string_lossy().to_string()),
+        default: Some("styles/ukiyo-e.png".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(config.find(&inputs.join("kata.png")).unwrap(), Some(styles.join("kata.jpg")));
+    assert_eq!(config.find(&inputs.join("kumite.png")).unwrap(), Some(inputs.join("../styles/ink.png")));
+    assert_eq!(config.find(&inputs.join("bunkai.png")).unwrap(), Some(PathBuf::from("styles/ukiyo-e.png")));
+    assert_eq!(StyleReferenceConfig::default().find(&inputs.join("kata.png")).unwrap(), None);
+    assert!(StyleReferenceConfig::default().unit_for(&inputs.join("kata.png")).unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_structure_and_style_units_share_a_request() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let input = temp_dir.path().join("kata.png");
+    let styles = temp_dir.path().join("styles");
+    fs::create_dir_all(&styles).unwrap();
+    fs::write(&input, "structure").unwrap();
+    fs::write(styles.join("kata.png"), "style").unwrap();
+
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/sdapi/v1/txt2img"))
+        .and(body_partial_json(json!({"alwayson_scripts": {"controlnet": {"args": [
+            {"module": "canny", "input_image": BASE64_STANDARD.encode("structure")},
+            {"module": "reference_only", "model": "None", "weight": 0.5, "input_image": BASE64_STANDARD.encode("style")}
+        ]}}})))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({"images": []})))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let mut config = Config::load("nonexistent_config.yml").unwrap();
+    config.controlnet_module = "canny".to_string();
+    config.style_reference = serde_yaml::from_str(&format!(
+        "dir: {}\nmodule: reference_only\nmodel: \"None\"\nweight: 0.5\n",
+        styles.display()
+    ))
+    .unwrap();
+    let client = StableDiffusionClient::new(&format!("{}/", server.uri()));
+    assert!(client.generate_with_controlnet(&input, &config).await.unwrap().is_some());
+
+    let metadata = urasoe::file_utils::ImageMetadata::from_config(&config, &input);
+    assert_eq!(metadata.style_image, Some(styles.join("kata.png").to_string_lossy().to_string()));
+}
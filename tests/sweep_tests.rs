@@ -0,0 +1,105 @@
+//! Parameter sweep tests for urasoe
+
+use serde_json::json;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use wiremock::matchers::{body_partial_json, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use urasoe::config::Config;
+use urasoe::pipeline::Pipeline;
+use urasoe::sink::{LabeledSink, MemorySink, OutputSink};
+use urasoe::sweep::{SweepConfig, seed_label, weight_label};
+
+const PNG_BASE64: &str = "iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAQAAAC1HAwCAAAAC0lEQVR42mP8/w8AAgMBApUAAAAASUVORK5CYII=";
+
+#[test]
+fn test_sweep_variants_share_the_seed() {
+    let mut config = Config::load("nonexistent_config.yml").unwrap();
+    config.seed = -1;
+    let sweep: SweepConfig = serde_yaml::from_str("controlnet_weight: [0.3, 0.6, 1.2]").unwrap();
+
+    let variants = sweep.variants(&config);
+    let labels: Vec<_> = variants.iter().map(|variant| variant.label.clone().unwrap()).collect();
+    assert_eq!(labels, ["weight-0.3", "weight-0.6", "weight-1.2"]);
+    assert_eq!(variants[1].config.controlnet_weight, 0.6);
+    assert!(variants[0].config.seed >= 0);
+    assert!(variants.iter().all(|variant| variant.config.seed == variants[0].config.seed));
+
+    // Without a sweep the configuration is used as it is
+    let variants = SweepConfig::default().variants(&config);
+    assert_eq!(variants.len(), 1);
+    assert_eq!(variants[0].label, None);
+    assert_eq!(variants[0].config.seed, -1);
+}
+
+#[test]
+fn test_labeled_sink_names_outputs_after_the_label() {
+    let memory = MemorySink::new();
+    let label = weight_label(0.9);
+    let sink = LabeledSink::new(&memory, &label);
+    let paths = sink.save_images(Path::new("input/kata.png"), &[b"png".to_vec()]).unwrap();
+    assert_eq!(paths, [PathBuf::from("kata-weight-0.9/kata-weight-0.9-1.png")]);
+}
+
+#[tokio::test]
+async fn test_run_generates_every_weight() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/options"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .mount(&server)
+        .await;
+    for weight in [0.5, 1.0] {
+        Mock::given(method("POST"))
+            .and(path("/sdapi/v1/txt2img"))
+            .and(body_partial_json(json!({"seed": 77, "alwayson_scripts": {"controlnet": {"args": [{"weight": weight}]}}})))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"images": [PNG_BASE64]})))
+            .expect(1)
+            .mount(&server)
+            .await;
+    }
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let input_dir = temp_dir.path().join("input");
+    std::fs::create_dir_all(&input_dir).unwrap();
+    std::fs::write(input_dir.join("kata.png"), "png").unwrap();
+    let mut config = Config::load("nonexistent_config.yml").unwrap();
+    config.sd_api_url = format!("{}/", server.uri());
+    config.input_dir = input_dir.to_string_lossy().to_string();
+    config.output_dir = temp_dir.path().join("output").to_string_lossy().to_string();
+    config.batch_break_ms = 0;
+    config.assume_yes = true;
+    config.seed = 77;
+    config.sweep.controlnet_weight = vec![0.5, 1.0];
+
+    let sink = Arc::new(MemorySink::new());
+    let (_events, handle) = Pipeline::new(config).with_sink(sink.clone()).start();
+    let stats = handle.await.unwrap().unwrap().unwrap();
+
+    assert_eq!(stats.success_count, 1);
+    assert_eq!(stats.images[0].outputs, ["kata-weight-0.5/kata-weight-0.5-1.png", "kata-weight-1/kata-weight-1-1.png"]);
+    let metadata = sink.metadata();
+    assert_eq!(metadata[Path::new(&input_dir.join("kata-weight-0.5.png"))].controlnet_weight, 0.5);
+    assert_eq!(metadata[Path::new(&input_dir.join("kata-weight-1.png"))].source_image, input_dir.join("kata.png").to_string_lossy());
+}
+
+#[test]
+fn test_seed_sweep_uses_consecutive_seeds() {
+    let mut config = Config::load("nonexistent_config.yml").unwrap();
+    config.seed = 1000;
+    let sweep: SweepConfig = serde_yaml::from_str("seeds: 3").unwrap();
+
+    let variants = sweep.variants(&config);
+    let labels: Vec<_> = variants.iter().map(|variant| variant.label.clone().unwrap()).collect();
+    assert_eq!(labels, [seed_label(1000), seed_label(1001), seed_label(1002)]);
+    assert_eq!(variants[2].config.seed, 1002);
+
+    // Every weight is generated with each seed
+    let sweep = SweepConfig {
+        controlnet_weight: vec![0.5, 1.0],
+        seeds: 2,
+    };
+    let labels: Vec<_> = sweep.variants(&config).into_iter().map(|variant| variant.label.unwrap()).collect();
+    assert_eq!(labels, ["weight-0.5-seed-1000", "weight-0.5-seed-1001", "weight-1-seed-1000", "weight-1-seed-1001"]);
+}
@@ -0,0 +1,57 @@
+//! Run timelapse module tests for urasoe
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use urasoe::file_utils::SavedImages;
+use urasoe::processing::{ImageTiming, ProcessingStats};
+use urasoe::timelapse::{TimelapseConfig, completed_images};
+
+/// Statistics of a run that completed `kumite` before `kata`, with an output that is not a file
+fn run_stats(output_dir: &Path) -> ProcessingStats {
+    let mut stats = ProcessingStats::new();
+    for (input, outputs) in [("kumite", vec!["kumite-1.png", "kumite-2.png"]), ("kata", vec!["kata-1.png", "gone.png"])] {
+        fs::create_dir_all(output_dir.join(input)).unwrap();
+        let paths: Vec<PathBuf> = outputs.iter().map(|output| output_dir.join(input).join(output)).collect();
+        for path in paths.iter().filter(|path| !path.ends_with("gone.png")) {
+            fs::write(path, "png").unwrap();
+        }
+        stats.record_success(Path::new(input), paths.len(), 0.0, ImageTiming::default(), 1);
+        stats.record_saved(&SavedImages {
+            paths,
+            ..Default::default()
+        });
+    }
+    stats
+}
+
+#[tokio::test]
+async fn test_timelapse_follows_completion_order() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let stats = run_stats(temp_dir.path());
+
+    let images = completed_images(&stats);
+    let names: Vec<_> = images.iter().map(|image| image.file_name().unwrap().to_string_lossy()).collect();
+    assert_eq!(names, ["kumite-1.png", "kumite-2.png", "kata-1.png"]);
+
+    // Nothing is assembled without a video, nor when ffmpeg is missing
+    let size = (512, 768);
+    assert_eq!(TimelapseConfig::default().assemble(&stats, temp_dir.path(), size).await.unwrap(), None);
+    let config: TimelapseConfig = serde_yaml::from_str("video: timelapse.webm\nfps: 2\n").unwrap();
+    assert_eq!(config.fps, 2);
+    if which_ffmpeg().is_none() {
+        assert_eq!(config.assemble(&stats, temp_dir.path(), size).await.unwrap(), None);
+        assert!(!temp_dir.path().join("timelapse.webm").exists());
+    }
+    let empty = ProcessingStats::new();
+    assert_eq!(config.assemble(&empty, temp_dir.path(), size).await.unwrap(), None);
+}
+
+/// Path of ffmpeg when it is installed
+fn which_ffmpeg() -> Option<PathBuf> {
+    std::env::var_os("PATH")
+        .map(|paths| std::env::split_paths(&paths).map(|dir| dir.join("ffmpeg")).collect::<Vec<_>>())
+        .unwrap_or_default()
+        .into_iter()
+        .find(|path| path.is_file())
+}
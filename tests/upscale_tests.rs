@@ -0,0 +1,81 @@
+//! Upscaling pass tests for urasoe
+
+use serde_json::json;
+use std::fs;
+use std::path::Path;
+use wiremock::matchers::{body_partial_json, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use urasoe::api::StableDiffusionClient;
+use urasoe::upscale::{UpscaleConfig, find_images, upscaled_path};
+
+const PNG_BASE64: &str = "iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAQAAAC1HAwCAAAAC0lEQVR42mP8/w8AAgMBApUAAAAASUVORK5CYII=";
+
+/// Run directory with generated images next to files that are not upscaled
+fn run_dir(dir: &Path) {
+    for folder in ["kata", "kata/rejected", "previews", "_failed", "depth/kumite"] {
+        fs::create_dir_all(dir.join(folder)).unwrap();
+    }
+    for file in [
+        "kata/kata-1.png",
+        "kata/kata-2.png",
+        "kata/kata-1-parameters.txt",
+        "kata/kata-metadata.json",
+        "kata/rejected/kata-3.png",
+        "previews/kata-step-005.png",
+        "_failed/bird.png",
+        "depth/kumite/kumite-1.png",
+        "kata-compare-cfg.png",
+    ] {
+        fs::write(dir.join(file), "png").unwrap();
+    }
+}
+
+#[test]
+fn test_find_images_of_a_run() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    run_dir(temp_dir.path());
+    fs::write(temp_dir.path().join("kata/kata-2-up.png"), "png").unwrap();
+
+    let images = find_images(temp_dir.path()).unwrap();
+    let names: Vec<_> = images.iter().map(|image| image.strip_prefix(temp_dir.path()).unwrap()).collect();
+    assert_eq!(
+        names,
+        [Path::new("depth/kumite/kumite-1.png"), Path::new("kata/kata-1.png"), Path::new("kata/kata-2.png")]
+    );
+    assert_eq!(upscaled_path(Path::new("out/kata/kata-1.png")), Path::new("out/kata/kata-1-up.png"));
+}
+
+#[tokio::test]
+async fn test_upscale_writes_companions_once() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/sdapi/v1/extra-single-image"))
+        .and(body_partial_json(json!({"upscaler_1": "4x-UltraSharp", "upscaling_resize": 1.5})))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({"image": PNG_BASE64, "html_info": ""})))
+        .expect(4)
+        .mount(&server)
+        .await;
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    run_dir(temp_dir.path());
+    let config: UpscaleConfig = serde_yaml::from_str("upscaler: 4x-UltraSharp\nscale: 1.5\n").unwrap();
+    let client = StableDiffusionClient::new(&format!("{}/", server.uri()));
+
+    let summary = config.run(&client, temp_dir.path(), false).await.unwrap();
+    assert_eq!(summary.upscaled.len(), 3);
+    assert!(summary.failed.is_empty());
+    let upscaled = fs::read(temp_dir.path().join("kata/kata-1-up.png")).unwrap();
+    assert_eq!(&upscaled[1..4], b"PNG");
+
+    // Images with a companion are skipped unless forced
+    let summary = config.run(&client, temp_dir.path(), false).await.unwrap();
+    assert_eq!((summary.upscaled.len(), summary.skipped), (0, 3));
+    fs::remove_file(temp_dir.path().join("kata/kata-2-up.png")).unwrap();
+    let summary = config.run(&client, temp_dir.path(), false).await.unwrap();
+    assert_eq!(summary.upscaled, [temp_dir.path().join("kata/kata-2-up.png")]);
+
+    let client = StableDiffusionClient::new("http://127.0.0.1:9/");
+    let summary = config.run(&client, temp_dir.path(), true).await.unwrap();
+    assert_eq!(summary.failed.len(), 3);
+}
@@ -0,0 +1,103 @@
+//! Version information tests for urasoe
+
+use serde_json::json;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use urasoe::config::{Args, Config};
+use urasoe::exit::ExitStatus;
+use urasoe::version::{self, Verdict, check, parse_webui_version, render, verdict};
+
+/// Server reporting the given Web UI version and ControlNet API version
+async fn server_with(webui: &str, controlnet: Option<u64>) -> MockServer {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/sdapi/v1/progress"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({"progress": 0.0})))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/internal/sysinfo"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({"Version": webui})))
+        .mount(&server)
+        .await;
+    if let Some(controlnet) = controlnet {
+        Mock::given(method("GET"))
+            .and(path("/controlnet/version"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({"version": controlnet})))
+            .mount(&server)
+            .await;
+    }
+    server
+}
+
+fn config_for(server: &MockServer) -> Config {
+    let mut config = Config::load("nonexistent_config.yml").unwrap();
+    config.sd_api_url = format!("{}/", server.uri());
+    config
+}
+
+#[test]
+fn test_render_includes_build_metadata() {
+    let text = render();
+    assert!(text.starts_with(&format!("urasoe {}\n", env!("CARGO_PKG_VERSION"))));
+    assert!(text.contains(&format!("commit:   {}\n", version::GIT_HASH)));
+    assert!(text.contains(&format!("features: {}\n", version::FEATURES)));
+    // YYYY-MM-DD
+    assert_eq!(version::BUILD_DATE.len(), 10);
+    assert_eq!(version::BUILD_DATE.matches('-').count(), 2);
+}
+
+#[test]
+fn test_parse_webui_version() {
+    assert_eq!(parse_webui_version("v1.10.1"), Some((1, 10, 1)));
+    assert_eq!(parse_webui_version("1.9.0-RC"), Some((1, 9, 0)));
+    assert_eq!(parse_webui_version("v1.6"), Some((1, 6, 0)));
+    assert_eq!(parse_webui_version("f2.0.1v1.10.1-previous-665-gae278f79"), Some((1, 10, 1)));
+    assert_eq!(parse_webui_version("dev"), None);
+}
+
+#[test]
+fn test_verdict() {
+    assert_eq!(verdict(Some("v1.10.1"), Some(2)).0, Verdict::Compatible);
+    assert_eq!(verdict(Some("v1.9.0"), Some(1)).0, Verdict::Compatible);
+    assert_eq!(verdict(Some("v1.6.0"), Some(2)).0, Verdict::Incompatible);
+    assert_eq!(verdict(Some("v1.10.1"), None).0, Verdict::Incompatible);
+    assert_eq!(verdict(None, Some(2)).0, Verdict::Untested);
+    assert_eq!(verdict(Some("nightly"), Some(2)).0, Verdict::Untested);
+
+    let (_, reason) = verdict(Some("v1.6.0"), Some(2));
+    assert_eq!(reason, "Web UI v1.6.0 is older than v1.9.0");
+}
+
+#[tokio::test]
+async fn test_check_supported_server() {
+    let server = server_with("v1.10.1", Some(2)).await;
+    assert_eq!(check(&config_for(&server)).await.unwrap(), ExitStatus::Success);
+}
+
+#[tokio::test]
+async fn test_check_incompatible_server_is_invalid_config() {
+    let server = server_with("v1.10.1", None).await;
+    assert_eq!(check(&config_for(&server)).await.unwrap(), ExitStatus::ConfigInvalid);
+}
+
+#[tokio::test]
+async fn test_check_unreachable_server_fails() {
+    let mut config = Config::load("nonexistent_config.yml").unwrap();
+    config.sd_api_url = "http://127.0.0.1:1/".to_string();
+    config.validate_timeout_ms = 1000;
+
+    let error = check(&config).await.unwrap_err();
+    assert_eq!(ExitStatus::from_error(&error), ExitStatus::ApiUnreachable);
+}
+
+#[test]
+fn test_check_requires_version_flag() {
+    use clap::Parser;
+
+    let args = Args::try_parse_from(["urasoe", "--version", "--check"]).unwrap();
+    assert!(args.version && args.check);
+    assert!(Args::try_parse_from(["urasoe", "--check"]).is_err());
+    assert!(Args::try_parse_from(["urasoe", "-V"]).unwrap().version);
+}